@@ -0,0 +1,43 @@
+use md5::{Digest, Md5};
+
+// RetroAchievements addresses are offsets into these regions laid end to end, the same as rcheevos' DS memory map
+pub struct MemoryRegion {
+    pub start: u32,
+    pub end: u32,
+    // Where the region is in the ARM9's address space
+    pub real_addr: u32,
+    pub description: &'static str,
+}
+
+pub const MEMORY_REGIONS: [MemoryRegion; 2] = [
+    MemoryRegion { start: 0x00_0000, end: 0x3F_FFFF, real_addr: 0x0200_0000, description: "System RAM" },
+    MemoryRegion { start: 0x40_0000, end: 0x40_3FFF, real_addr: 0x027E_0000, description: "Data TCM" },
+];
+
+pub const MEMORY_SIZE: u32 = 0x40_4000;
+
+// Identifies the game the way rcheevos does: the MD5 of the header, both CPUs' code and the banner.
+// Returns None if the sizes in the header can't belong to a DS ROM.
+pub fn game_hash(rom: &[u8]) -> Option<String> {
+    // Some flash cartridges put a 512 byte header of their own in front of the ROM
+    let supercard = rom.len() >= 0x200 && rom[..4] == [0x2E, 0x00, 0x00, 0xEA] && rom[0xB0..0xB4] == [0x44, 0x46, 0x96, 0x00];
+    let rom = if supercard { &rom[0x200..] } else { rom };
+    if rom.len() < 0x160 { return None }
+    let word = |addr: usize| u32::from_le_bytes([rom[addr], rom[addr + 1], rom[addr + 2], rom[addr + 3]]) as usize;
+    let (arm9_offset, arm9_size) = (word(0x20), word(0x2C));
+    let (arm7_offset, arm7_size) = (word(0x30), word(0x3C));
+    let icon_offset = word(0x68);
+    if arm9_size + arm7_size > 16 * 1024 * 1024 { return None }
+
+    // Blocks past the end of the ROM are hashed as far as they go
+    let block = |offset: usize, size: usize| {
+        let start = offset.min(rom.len());
+        &rom[start..(start + size).min(rom.len())]
+    };
+    let mut md5 = Md5::new();
+    md5.update(&rom[..0x160]);
+    md5.update(block(arm9_offset, arm9_size));
+    md5.update(block(arm7_offset, arm7_size));
+    md5.update(block(icon_offset, 0xA00));
+    Some(md5.finalize().iter().map(|byte| format!("{:02x}", byte)).collect())
+}
@@ -5,9 +5,12 @@ use super::{
 };
 
 use crate::hw::AccessType;
+use crate::fault::Fault;
+use crate::nds::Cpu;
 
 impl ARM7 {
     pub(super) fn fill_arm_instr_buffer(&mut self, hw: &mut HW) {
+        self.call_stack.jump(self.regs.pc);
         self.regs.pc &= !0x3;
         self.instr_buffer[0] = self.read::<u32>(hw, AccessType::S, self.regs.pc & !0x3);
         self.regs.pc = self.regs.pc.wrapping_add(4);
@@ -56,6 +59,10 @@ impl ARM7 {
         self.instruction_prefetch::<u32>(hw, AccessType::N);
         if L { self.regs.set_reg(Reg::R14, self.regs.pc.wrapping_sub(4)) } // Branch with Link
         self.regs.pc = self.regs.pc.wrapping_add(offset << 2);
+        if L {
+            let return_addr = self.regs.get_reg(Reg::R14);
+            self.call_stack.call(return_addr.wrapping_sub(4), self.regs.pc, return_addr);
+        }
         self.fill_arm_instr_buffer(hw);
     }
 
@@ -442,13 +449,14 @@ impl ARM7 {
     // ARM.14: Coprocessor Data Operations (CDP)
     // ARM.15: Coprocessor Data Transfers (LDC,STC)
     // ARM.16: Coprocessor Register Transfers (MRC, MCR)
-    fn coprocessor(&mut self, _hw: &mut HW, _instr: u32) {
-        unimplemented!("Coprocessor not implemented!");
+    // The ARM7 has no coprocessors, so these are all undefined
+    fn coprocessor(&mut self, hw: &mut HW, instr: u32) {
+        self.undefined_instr_arm(hw, instr)
     }
 
     // ARM.17: Undefined Instruction
-    fn undefined_instr_arm(&mut self, _hw: &mut HW, _instr: u32) {
-        unimplemented!("ARM.17: Undefined Instruction not implemented!");
+    fn undefined_instr_arm(&mut self, hw: &mut HW, instr: u32) {
+        hw.faults.raise(Fault::UndefinedInstruction { cpu: Cpu::ARM7, addr: self.regs.pc.wrapping_sub(8), instr });
     }
 }
 
@@ -473,6 +481,8 @@ pub(super) fn gen_lut() -> [InstructionHandler<u32>; 4096] {
             compose_instr_handler!(psr_transfer, skeleton, 25, 22, 21)
         } else if skeleton & 0b1100_0000_0000_0000_0000_0000_0000 == 0b0000_0000_0000_0000_0000_0000_0000 {
             compose_instr_handler!(data_proc, skeleton, 25, 20)
+        } else if skeleton & 0b1110_0000_0000_0000_0000_0001_0000 == 0b0110_0000_0000_0000_0000_0001_0000 {
+            ARM7::undefined_instr_arm
         } else if skeleton & 0b1100_0000_0000_0000_0000_0000_0000 == 0b0100_0000_0000_0000_0000_0000_0000 {
             compose_instr_handler!(single_data_transfer, skeleton, 25, 24, 23, 22, 21, 20)
         } else if skeleton & 0b1110_0000_0000_0000_0000_0000_0000 == 0b1000_0000_0000_0000_0000_0000_0000 {
@@ -485,10 +495,7 @@ pub(super) fn gen_lut() -> [InstructionHandler<u32>; 4096] {
             ARM7::coprocessor
         } else if skeleton & 0b1111_0000_0000_0000_0000_0000_0000 == 0b1110_0000_0000_0000_0000_0000_0000 {
             ARM7::coprocessor
-        } else {
-            assert_eq!(skeleton & 0b1110_0000_0000_0000_0000_0001_0000, 0b0110_0000_0000_0000_0000_0001_0000);
-            ARM7::undefined_instr_arm
-        };
+        } else { unreachable!() };
     }
 
     lut
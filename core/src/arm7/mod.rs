@@ -4,6 +4,7 @@ mod arm;
 mod thumb;
 mod registers;
 
+use crate::call_stack::CallStack;
 use crate::num;
 use crate::hw::{AccessType, HW, MemoryValue};
 use registers::{Mode, Reg, RegValues};
@@ -14,20 +15,38 @@ pub struct ARM7 {
     instr_buffer: [u32; 2],
     next_access_type: AccessType,
     do_internal: bool,
+    pub call_stack: CallStack,
 
     condition_lut: [bool; 256],
     arm_lut: [instructions::InstructionHandler<u32>; 4096],
     thumb_lut: [instructions::InstructionHandler<u16>; 256],
 }
 
+savestate!(ARM7 { cycles_spent, regs, instr_buffer, next_access_type, do_internal });
+
 impl ARM7 {
     pub fn new(hw: &mut HW, direct_boot: bool) -> ARM7 {
+        let regs = if direct_boot { RegValues::direct_boot(hw.init_arm7()) } else { RegValues::new() };
+        ARM7::with_regs(hw, regs)
+    }
+
+    // Without a GBA BIOS, the cartridge is started directly with the state the BIOS leaves behind
+    pub fn new_gba(hw: &mut HW) -> ARM7 {
+        let regs = if hw.has_gba_bios() { RegValues::new() } else {
+            warn!("No GBA BIOS - Booting GBA Cartridge Directly");
+            RegValues::gba_direct_boot()
+        };
+        ARM7::with_regs(hw, regs)
+    }
+
+    fn with_regs(hw: &mut HW, regs: RegValues) -> ARM7 {
         let mut cpu = ARM7 {
             cycles_spent: 0,
-            regs: if direct_boot { RegValues::direct_boot(hw.init_arm7()) } else { RegValues::new() },
+            regs,
             instr_buffer: [0; 2],
             next_access_type: AccessType::N,
             do_internal: false,
+            call_stack: CallStack::new(),
 
             condition_lut: instructions::gen_condition_table(),
             arm_lut: arm::gen_lut(),
@@ -37,6 +56,21 @@ impl ARM7 {
         cpu
     }
 
+    // R15 reads as the address of the current instruction + 2 instructions
+    pub fn reg(&self, reg: u32) -> u32 {
+        if reg == 16 { self.regs.get_reg(registers::Reg::CPSR) } else { self.regs.get_reg_i(reg) }
+    }
+
+    // Address of the next instruction to execute, since R15 is a fetch ahead
+    pub fn pc(&self) -> u32 {
+        self.regs.get_reg_i(15).wrapping_sub(if self.regs.get_t() { 2 } else { 4 })
+    }
+
+    pub fn set_reg(&mut self, reg: u32, value: u32) {
+        assert!(reg < 15);
+        self.regs.set_reg_i(reg, value);
+    }
+
     pub fn emulate_instr(&mut self, hw: &mut HW) -> usize {
         self.cycles_spent = 0;
         if self.regs.get_t() { self.emulate_thumb_instr(hw) }
@@ -45,7 +79,7 @@ impl ARM7 {
     }
 
     pub fn read<T: MemoryValue>(&mut self, hw: &mut HW, access_type: AccessType, addr: u32) -> T {
-        let value = hw.arm7_read::<T>(addr);
+        let value = if hw.arm7_bios_protected(self.regs.pc, addr) { T::max_value() } else { hw.arm7_read::<T>(addr) };
         self.cycles_spent += hw.arm7_get_access_time::<T>(self.next_access_type, addr);
         self.next_access_type = access_type;
         value
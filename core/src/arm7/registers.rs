@@ -85,6 +85,9 @@ pub struct RegValues {
     spsr: [StatusReg; 5],
 }
 
+savestate_bitflags!(StatusReg);
+savestate!(RegValues { usr, fiq, svc, abt, irq, und, pc, cpsr, spsr });
+
 impl RegValues {
     pub fn new() -> RegValues {
         RegValues {
@@ -103,12 +106,23 @@ impl RegValues {
     pub fn direct_boot(pc: u32) -> RegValues {
         let mut reg_values = RegValues::new();
         reg_values.usr[12] = pc;
-        reg_values.usr[13] = 0x0380FFC0;
+        reg_values.usr[13] = 0x0380FD80;
+        reg_values.usr[14] = pc;
         reg_values.irq[0] = 0x0380FF80; // R13
-        reg_values.svc[0] = 0x0380FD80; // R13
-        reg_values.svc[1] = pc; // R14
+        reg_values.svc[0] = 0x0380FFC0; // R13
         reg_values.pc = pc;
-        reg_values.cpsr.bits = 0xD3;
+        // System mode with IRQs and FIQs disabled
+        reg_values.cpsr.bits = 0xDF;
+        reg_values
+    }
+
+    pub fn gba_direct_boot() -> RegValues {
+        let mut reg_values = RegValues::new();
+        reg_values.usr[13] = 0x03007F00;
+        reg_values.irq[0] = 0x03007FA0; // R13
+        reg_values.svc[0] = 0x03007FE0; // R13
+        reg_values.pc = 0x08000000;
+        reg_values.cpsr.bits = 0x1F;
         reg_values
     }
 
@@ -5,9 +5,12 @@ use super::{
 };
 
 use crate::hw::AccessType;
+use crate::fault::Fault;
+use crate::nds::Cpu;
 
 impl ARM7 {
     pub(super) fn fill_thumb_instr_buffer(&mut self, hw: &mut HW) {
+        self.call_stack.jump(self.regs.pc);
         self.regs.pc &= !0x1;
         self.instr_buffer[0] = self.read::<u16>(hw, AccessType::S, self.regs.pc & !0x1) as u32;
         self.regs.pc = self.regs.pc.wrapping_add(2);
@@ -476,6 +479,7 @@ impl ARM7 {
             let next_instr_pc = self.regs.pc.wrapping_sub(2);
             self.regs.pc = self.regs.get_reg(Reg::R14).wrapping_add(offset << 1);
             self.regs.set_reg(Reg::R14, next_instr_pc | 0x1);
+            self.call_stack.call(next_instr_pc.wrapping_sub(4), self.regs.pc, next_instr_pc);
             self.fill_thumb_instr_buffer(hw);
         } else { // First Instruction
             let offset = if offset >> 10 & 0x1 != 0 { 0xFFFF_F800 | offset } else { offset };
@@ -485,8 +489,8 @@ impl ARM7 {
         }
     }
 
-    fn undefined_instr_thumb(&mut self, _hw: &mut HW, _instr: u16) {
-        panic!("Undefined Thumb Instruction!")
+    fn undefined_instr_thumb(&mut self, hw: &mut HW, instr: u16) {
+        hw.faults.raise(Fault::UndefinedInstruction { cpu: Cpu::ARM7, addr: self.regs.pc.wrapping_sub(4), instr: instr as u32 });
     }
 }
 
@@ -6,9 +6,12 @@ use super::{
 
 
 use crate::hw::AccessType;
+use crate::fault::Fault;
+use crate::nds::Cpu;
 
 impl ARM9 {
     pub(super) fn fill_arm_instr_buffer(&mut self, hw: &mut HW) {
+        self.call_stack.jump(self.regs[15]);
         self.regs[15] &= !0x3;
         self.instr_buffer[0] = self.read::<u32>(hw, AccessType::S, self.regs[15] & !0x3);
         self.regs[15] = self.regs[15].wrapping_add(4);
@@ -43,6 +46,7 @@ impl ARM9 {
             self.regs.set_lr(self.regs[15].wrapping_sub(4));
         } else { assert_eq!(instr >> 4 & 0xF, 0b0001) } // BX
         self.regs[15] = self.regs[instr & 0xF];
+        if L { self.call_stack.call(self.regs.lr().wrapping_sub(4), self.regs[15], self.regs.lr()) }
         if self.regs[15] & 0x1 != 0 {
             self.regs[15] -= 1;
             self.regs.set_t(true);
@@ -59,11 +63,13 @@ impl ARM9 {
         if instr >> 28 == 0xF { // BLX
             self.regs.set_lr(self.regs[15].wrapping_sub(4));
             self.regs[15] = self.regs[15].wrapping_add(offset << 2).wrapping_add((L as u32) * 2); // L acts as H
+            self.call_stack.call(self.regs.lr().wrapping_sub(4), self.regs[15], self.regs.lr());
             self.regs.set_t(true);
             self.fill_thumb_instr_buffer(hw);
         } else {
             if L { self.regs.set_lr(self.regs[15].wrapping_sub(4)) } // Branch with Link
             self.regs[15] = self.regs[15].wrapping_add(offset << 2);
+            if L { self.call_stack.call(self.regs.lr().wrapping_sub(4), self.regs[15], self.regs.lr()) }
             self.fill_arm_instr_buffer(hw);
         }
     }
@@ -537,8 +543,9 @@ impl ARM9 {
 
     // ARM.14: Coprocessor Data Operations (CDP)
     // ARM.15: Coprocessor Data Transfers (LDC,STC)
-    fn coprocessor(&mut self, _hw: &mut HW, _instr: u32) {
-        unimplemented!("Coprocessor not implemented!");
+    // CP15 only supports register transfers, so these are undefined
+    fn coprocessor(&mut self, hw: &mut HW, instr: u32) {
+        self.undefined_instr_arm(hw, instr)
     }
 
     // ARM.16: Coprocessor Register Transfers (MRC, MCR)
@@ -560,13 +567,13 @@ impl ARM9 {
         if OP { // MRC
             self.regs[arm_src_dest_reg] = hw.cp15.read(cp_src_dest_reg, cp_operand_reg, cp_info);
         } else { // MCR
-            hw.cp15.write(cp_src_dest_reg, cp_operand_reg, cp_info, self.regs[arm_src_dest_reg]);
+            hw.arm9_write_cp15(cp_src_dest_reg, cp_operand_reg, cp_info, self.regs[arm_src_dest_reg]);
         }
     }
 
     // ARM.17: Undefined Instruction
-    fn undefined_instr_arm(&mut self, _hw: &mut HW, _instr: u32) {
-        unimplemented!("ARM.17: Undefined Instruction not implemented!");
+    fn undefined_instr_arm(&mut self, hw: &mut HW, instr: u32) {
+        hw.faults.raise(Fault::UndefinedInstruction { cpu: Cpu::ARM9, addr: self.regs[15].wrapping_sub(8), instr });
     }
 
     // ARM.X: Count Leading Zeros
@@ -628,6 +635,8 @@ pub(super) fn gen_lut() -> [InstructionHandler<u32>; 4096] {
             compose_instr_handler!(psr_transfer, skeleton, 25, 22, 21)
         } else if skeleton & 0b1100_0000_0000_0000_0000_0000_0000 == 0b0000_0000_0000_0000_0000_0000_0000 {
             compose_instr_handler!(data_proc, skeleton, 25, 20)
+        } else if skeleton & 0b1110_0000_0000_0000_0000_0001_0000 == 0b0110_0000_0000_0000_0000_0001_0000 {
+            ARM9::undefined_instr_arm
         } else if skeleton & 0b1100_0000_0000_0000_0000_0000_0000 == 0b0100_0000_0000_0000_0000_0000_0000 {
             compose_instr_handler!(single_data_transfer, skeleton, 25, 24, 23, 22, 21, 20)
         } else if skeleton & 0b1110_0000_0000_0000_0000_0000_0000 == 0b1000_0000_0000_0000_0000_0000_0000 {
@@ -642,10 +651,7 @@ pub(super) fn gen_lut() -> [InstructionHandler<u32>; 4096] {
             ARM9::coprocessor
         } else if skeleton & 0b1111_0000_0000_0000_0000_0001_0000 == 0b1110_0000_0000_0000_0000_0001_0000 {
             compose_instr_handler!(coprocessor_register_transfers, skeleton, 23, 22, 21, 20, 7, 6, 5)
-        } else {
-            assert_eq!(skeleton & 0b1110_0000_0000_0000_0000_0001_0000, 0b0110_0000_0000_0000_0000_0001_0000);
-            ARM9::undefined_instr_arm
-        };
+        } else { unreachable!() };
     }
 
     lut
@@ -4,6 +4,7 @@ mod arm;
 mod thumb;
 mod registers;
 
+use crate::call_stack::CallStack;
 use crate::num;
 use crate::hw::{AccessType, HW, MemoryValue};
 use registers::{Mode, RegValues};
@@ -14,12 +15,15 @@ pub struct ARM9 {
     instr_buffer: [u32; 2],
     next_access_type: AccessType,
     do_internal: bool,
+    pub call_stack: CallStack,
 
     condition_lut: [bool; 256],
     arm_lut: [instructions::InstructionHandler<u32>; 4096],
     thumb_lut: [instructions::InstructionHandler<u16>; 256],
 }
 
+savestate!(ARM9 { cycles_spent, regs, instr_buffer, next_access_type, do_internal });
+
 impl ARM9 {
     pub fn new(hw: &mut HW, direct_boot: bool) -> ARM9 {
         let mut cpu = ARM9 {
@@ -28,6 +32,7 @@ impl ARM9 {
             instr_buffer: [0; 2],
             next_access_type: AccessType::N,
             do_internal: false,
+            call_stack: CallStack::new(),
 
             condition_lut: instructions::gen_condition_table(),
             arm_lut: arm::gen_lut(),
@@ -37,6 +42,21 @@ impl ARM9 {
         cpu
     }
 
+    // R15 reads as the address of the current instruction + 2 instructions
+    pub fn reg(&self, reg: u32) -> u32 {
+        if reg == 16 { self.regs.cpsr() } else { self.regs[reg] }
+    }
+
+    // Address of the next instruction to execute, since R15 is a fetch ahead
+    pub fn pc(&self) -> u32 {
+        self.regs[15].wrapping_sub(if self.regs.get_t() { 2 } else { 4 })
+    }
+
+    pub fn set_reg(&mut self, reg: u32, value: u32) {
+        assert!(reg < 15);
+        self.regs[reg] = value;
+    }
+
     pub fn emulate_instr(&mut self, hw: &mut HW) -> usize {
         self.cycles_spent = 0;
         if self.regs.get_t() { self.emulate_thumb_instr(hw) }
@@ -62,6 +62,9 @@ pub struct RegValues {
     spsr: [StatusReg; 2], // SVC and IRQ
 }
 
+savestate_bitflags!(StatusReg);
+savestate!(RegValues { regs, usr, svc, irq, cpsr, spsr });
+
 impl RegValues {
     pub fn new() -> RegValues {
         let mut regs = RegValues {
@@ -79,12 +82,14 @@ impl RegValues {
     pub fn direct_boot(pc: u32) -> RegValues {
         let mut reg_values = RegValues::new();
         reg_values.regs[12] = pc;
-        reg_values.regs[13] = 0x03003FC0;
+        reg_values.regs[13] = 0x03002F7C;
+        reg_values.regs[14] = pc;
         reg_values.regs[15] = pc;
+        // The stacks are in DTCM
         reg_values.irq[0] = 0x03003F80; // R13
-        reg_values.svc[0] = 0x03002F7C; // R13
-        reg_values.svc[1] = pc; // R14
-        reg_values.cpsr.bits = 0xD3;
+        reg_values.svc[0] = 0x03003FC0; // R13
+        // System mode with IRQs and FIQs disabled
+        reg_values.cpsr.bits = 0xDF;
         reg_values
     }
 
@@ -5,9 +5,12 @@ use super::{
 };
 
 use crate::hw::AccessType;
+use crate::fault::Fault;
+use crate::nds::Cpu;
 
 impl ARM9 {
     pub(super) fn fill_thumb_instr_buffer(&mut self, hw: &mut HW) {
+        self.call_stack.jump(self.regs[15]);
         self.regs[15] &= !0x1;
         self.instr_buffer[0] = self.read::<u16>(hw, AccessType::S, self.regs[15] & !0x1) as u32;
         self.regs[15] = self.regs[15].wrapping_add(2);
@@ -145,6 +148,10 @@ impl ARM9 {
                     self.regs.set_lr(self.regs[15].wrapping_sub(1));
                 }
                 self.regs[15] = src;
+                if dest_reg_msb != 0 {
+                    let return_addr = self.regs.lr() & !0x1;
+                    self.call_stack.call(return_addr.wrapping_sub(2), src, return_addr);
+                }
                 if src & 0x1 != 0 {
                     self.regs[15] = self.regs[15] & !0x1;
                     self.fill_thumb_instr_buffer(hw);
@@ -484,6 +491,7 @@ impl ARM9 {
             let next_instr_pc = self.regs[15].wrapping_sub(2);
             self.regs[15] = self.regs.lr().wrapping_add(offset << 1);
             self.regs.set_lr(next_instr_pc | 0x1);
+            self.call_stack.call(next_instr_pc.wrapping_sub(4), self.regs[15], next_instr_pc);
             if X { // BL
                 self.fill_thumb_instr_buffer(hw);
             } else { // BLX
@@ -504,8 +512,8 @@ impl ARM9 {
         }
     }
 
-    fn undefined_instr_thumb(&mut self, _hw: &mut HW, _instr: u16) {
-        panic!("Undefined Thumb Instruction!")
+    fn undefined_instr_thumb(&mut self, hw: &mut HW, instr: u16) {
+        hw.faults.raise(Fault::UndefinedInstruction { cpu: Cpu::ARM9, addr: self.regs[15].wrapping_sub(4), instr: instr as u32 });
     }
 }
 
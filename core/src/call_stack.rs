@@ -0,0 +1,59 @@
+// Best effort call stack built from BL/BLX and branches back to their return addresses.
+// Code that returns some other way, like tail calls or longjmp, leaves frames that are only dropped
+// once a frame below them returns.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StackFrame {
+    // Address of the BL/BLX
+    pub call_addr: u32,
+    pub target: u32,
+    pub return_addr: u32,
+}
+
+pub(crate) struct CallStack {
+    enabled: bool,
+    frames: Vec<StackFrame>,
+}
+
+impl CallStack {
+    // The oldest frames are dropped past this, since code that never returns would grow it forever
+    const MAX_DEPTH: usize = 1024;
+
+    pub fn new() -> Self {
+        CallStack {
+            enabled: false,
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled { self.frames.clear() }
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    pub fn call(&mut self, call_addr: u32, target: u32, return_addr: u32) {
+        if !self.enabled { return }
+        if self.frames.len() == CallStack::MAX_DEPTH { self.frames.remove(0); }
+        self.frames.push(StackFrame {
+            call_addr,
+            target: target & !0x1,
+            return_addr: return_addr & !0x1,
+        });
+    }
+
+    // Called for every change of PC outside of sequential execution
+    pub fn jump(&mut self, addr: u32) {
+        if self.frames.is_empty() { return }
+        if let Some(i) = self.frames.iter().rposition(|frame| frame.return_addr == addr & !0x1) {
+            self.frames.truncate(i);
+        }
+    }
+
+    // Innermost call first
+    pub fn frames(&self) -> Vec<StackFrame> {
+        self.frames.iter().rev().copied().collect()
+    }
+}
@@ -0,0 +1,43 @@
+mod usrcheat;
+mod ram_search;
+
+pub use ram_search::{RamSearch, SearchFilter, SearchResult, SearchSize, SearchValue};
+
+#[derive(Clone)]
+pub struct Cheat {
+    pub name: String,
+    pub note: String,
+    // Action Replay code lines
+    pub codes: Vec<[u32; 2]>,
+    pub enabled: bool,
+    pub folder: Option<usize>,
+}
+
+#[derive(Clone)]
+pub struct CheatFolder {
+    pub name: String,
+    pub note: String,
+    // Only one cheat in the folder can be enabled at a time
+    pub one_choice: bool,
+}
+
+#[derive(Clone)]
+pub struct GameCheats {
+    pub title: String,
+    pub folders: Vec<CheatFolder>,
+    pub cheats: Vec<Cheat>,
+}
+
+impl GameCheats {
+    pub fn set_enabled(&mut self, cheat_i: usize, enabled: bool) {
+        let folder = self.cheats[cheat_i].folder;
+        if enabled && folder.is_some_and(|folder| self.folders[folder].one_choice) {
+            for cheat in self.cheats.iter_mut().filter(|cheat| cheat.folder == folder) { cheat.enabled = false }
+        }
+        self.cheats[cheat_i].enabled = enabled;
+    }
+
+    pub fn enabled_codes(&self) -> impl Iterator<Item = &[u32; 2]> {
+        self.cheats.iter().filter(|cheat| cheat.enabled).flat_map(|cheat| cheat.codes.iter())
+    }
+}
@@ -0,0 +1,119 @@
+use std::convert::TryInto;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum SearchSize {
+    Byte = 1,
+    Halfword = 2,
+    Word = 4,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum SearchValue {
+    Previous,
+    Constant(u32),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum SearchFilter {
+    Equal(SearchValue),
+    NotEqual(SearchValue),
+    Greater(SearchValue),
+    Less(SearchValue),
+    // Difference from the previous value, wrapping at the search size
+    ChangedBy(i64),
+}
+
+#[derive(Clone, Copy)]
+pub struct SearchResult {
+    pub addr: u32,
+    pub value: u32,
+    pub previous: u32,
+}
+
+// Narrows down the addresses in main memory holding a value by comparing it with earlier snapshots
+pub struct RamSearch {
+    size: SearchSize,
+    aligned: bool,
+    snapshot: Vec<u8>,
+    // Every address is a candidate before the first filter
+    candidates: Option<Vec<u32>>,
+}
+
+impl RamSearch {
+    pub const MAIN_MEM_ADDR: u32 = 0x0200_0000;
+
+    pub fn new(mem: &[u8], size: SearchSize, aligned: bool) -> Self {
+        RamSearch {
+            size,
+            aligned,
+            snapshot: mem.to_vec(),
+            candidates: None,
+        }
+    }
+
+    fn read(&self, mem: &[u8], offset: u32) -> u32 {
+        let offset = offset as usize;
+        match self.size {
+            SearchSize::Byte => mem[offset] as u32,
+            SearchSize::Halfword => u16::from_le_bytes(mem[offset..offset + 2].try_into().unwrap()) as u32,
+            SearchSize::Word => u32::from_le_bytes(mem[offset..offset + 4].try_into().unwrap()),
+        }
+    }
+
+    fn offsets(&self) -> Box<dyn Iterator<Item = u32> + '_> {
+        match &self.candidates {
+            Some(candidates) => Box::new(candidates.iter().copied()),
+            None => {
+                let step = if self.aligned { self.size as usize } else { 1 };
+                let end = (self.snapshot.len() + 1).saturating_sub(self.size as usize);
+                Box::new((0..end).step_by(step).map(|offset| offset as u32))
+            },
+        }
+    }
+
+    // Keeps the candidates that pass the filter and takes a new snapshot
+    // Main memory changes size when switching between a DS and a DSi, which starts the search over
+    pub fn filter(&mut self, mem: &[u8], filter: SearchFilter) {
+        if mem.len() != self.snapshot.len() {
+            self.snapshot = mem.to_vec();
+            self.candidates = None;
+            return
+        }
+        let mask = (u64::MAX >> (64 - 8 * self.size as u64)) as u32;
+        let matches = |value: u32, previous: u32| {
+            let other = |search_value| match search_value {
+                SearchValue::Previous => previous,
+                SearchValue::Constant(constant) => constant & mask,
+            };
+            match filter {
+                SearchFilter::Equal(search_value) => value == other(search_value),
+                SearchFilter::NotEqual(search_value) => value != other(search_value),
+                SearchFilter::Greater(search_value) => value > other(search_value),
+                SearchFilter::Less(search_value) => value < other(search_value),
+                SearchFilter::ChangedBy(delta) => value.wrapping_sub(previous) & mask == delta as u32 & mask,
+            }
+        };
+        let candidates = self.offsets()
+            .filter(|offset| matches(self.read(mem, *offset), self.read(&self.snapshot, *offset)))
+            .collect();
+        self.candidates = Some(candidates);
+        self.snapshot.copy_from_slice(mem);
+    }
+
+    pub fn result_count(&self) -> usize {
+        match &self.candidates {
+            Some(candidates) => candidates.len(),
+            None => self.offsets().count(),
+        }
+    }
+
+    pub fn results<'a>(&'a self, mem: &'a [u8]) -> impl Iterator<Item = SearchResult> + 'a {
+        let end = mem.len().min(self.snapshot.len());
+        let in_mem = move |offset: &u32| *offset as usize + self.size as usize <= end;
+        self.offsets().filter(in_mem).map(move |offset| SearchResult {
+            addr: RamSearch::MAIN_MEM_ADDR + offset,
+            value: self.read(mem, offset),
+            previous: self.read(&self.snapshot, offset),
+        })
+    }
+}
@@ -0,0 +1,120 @@
+use std::convert::TryInto;
+
+use flate2::Crc;
+
+use super::{Cheat, CheatFolder, GameCheats};
+
+// Parser for the usrcheat.dat database used by R4 flashcarts
+impl GameCheats {
+    const MAGIC: &'static [u8] = b"R4 CheatCode";
+    const INDEX_ADDR: usize = 0x100;
+    const INDEX_ENTRY_LEN: usize = 0x10;
+    const HEADER_CRC_LEN: usize = 0x200;
+
+    // Entries are matched by game code and CRC32 of the ROM header. Games missing from the database have no cheats.
+    pub fn from_usrcheat(db: &[u8], rom: &[u8]) -> Result<Option<GameCheats>, String> {
+        if !db.starts_with(GameCheats::MAGIC) || db.len() < GameCheats::INDEX_ADDR {
+            return Err("Invalid Cheat Database".to_string())
+        }
+        if rom.len() < GameCheats::HEADER_CRC_LEN { return Err("ROM is Missing its Header".to_string()) }
+        let mut crc = Crc::new();
+        crc.update(&rom[..GameCheats::HEADER_CRC_LEN]);
+        let header_crc = !crc.sum();
+        let game_code = &rom[0xC..0x10];
+
+        let entries: Vec<(&[u8], u32, usize)> = db[GameCheats::INDEX_ADDR..].chunks_exact(GameCheats::INDEX_ENTRY_LEN)
+            .map(|entry| (
+                &entry[0..4],
+                u32::from_le_bytes(entry[4..8].try_into().unwrap()),
+                u64::from_le_bytes(entry[8..16].try_into().unwrap()) as usize,
+            )).take_while(|(_, _, addr)| *addr != 0).collect();
+        // Fall back to the game code for revisions missing from the database
+        let (_, _, addr) = match entries.iter().find(|(code, crc, _)| *code == game_code && *crc == header_crc)
+            .or_else(|| entries.iter().find(|(code, _, _)| *code == game_code)) {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        GameCheats::parse_game(db, *addr).map(Some).ok_or_else(|| "Corrupt Cheat Database".to_string())
+    }
+
+    fn parse_game(db: &[u8], addr: usize) -> Option<GameCheats> {
+        let mut reader = Reader { db, pos: addr };
+        let title = reader.read_str()?;
+        reader.align();
+        let mut entries_left = reader.read_u32()? & 0x0FFF_FFFF;
+        // Skip the master codes
+        reader.pos += 8 * 4;
+
+        let mut folders = Vec::new();
+        let mut cheats = Vec::new();
+        while entries_left > 0 {
+            let value = reader.read_u32()?;
+            let (folder, cheats_in_folder) = if value & 0xF000_0000 == 0x1000_0000 {
+                folders.push(CheatFolder {
+                    name: reader.read_str()?,
+                    note: reader.read_str()?,
+                    one_choice: value & 0x0100_0000 != 0,
+                });
+                reader.align();
+                entries_left -= 1;
+                (Some(folders.len() - 1), value & 0xFF_FFFF)
+            } else {
+                reader.pos -= 4;
+                (None, 1)
+            };
+
+            for _ in 0..cheats_in_folder {
+                let start = reader.pos;
+                let value = reader.read_u32()?;
+                let name = reader.read_str()?;
+                let note = reader.read_str()?;
+                reader.align();
+                let len = reader.read_u32()? as usize;
+                let codes = (0..len / 2).map(|_| Some([reader.read_u32()?, reader.read_u32()?]))
+                    .collect::<Option<Vec<_>>>()?;
+                if len > 0 {
+                    cheats.push(Cheat {
+                        name,
+                        note,
+                        codes,
+                        enabled: value & 0xFF00_0000 != 0,
+                        folder,
+                    });
+                }
+                // The lower bits are the number of words in the entry
+                reader.pos = start + 4 * ((value & 0xFF_FFFF) as usize + 1);
+                entries_left = entries_left.checked_sub(1)?;
+            }
+        }
+
+        Some(GameCheats {
+            title,
+            folders,
+            cheats,
+        })
+    }
+}
+
+struct Reader<'a> {
+    db: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn read_u32(&mut self) -> Option<u32> {
+        let value = u32::from_le_bytes(self.db.get(self.pos..self.pos + 4)?.try_into().unwrap());
+        self.pos += 4;
+        Some(value)
+    }
+
+    fn read_str(&mut self) -> Option<String> {
+        let len = self.db.get(self.pos..)?.iter().position(|byte| *byte == 0)?;
+        let string = String::from_utf8_lossy(&self.db[self.pos..self.pos + len]).into_owned();
+        self.pos += len + 1;
+        Some(string)
+    }
+
+    fn align(&mut self) {
+        self.pos = (self.pos + 3) & !0x3;
+    }
+}
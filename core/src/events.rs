@@ -0,0 +1,47 @@
+use std::cell::RefCell;
+
+use crate::nds::Cpu;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Event {
+    // The frame has been drawn and the GPU entered VBlank
+    VBlank,
+    // Everything for the frame, including saves and recordings, has been handled
+    FrameCompleted,
+    StateSaved,
+    // The game wrote to its save memory during the frame
+    BackupModified,
+    // Emulation stops before executing the instruction at the address
+    Breakpoint(Cpu, u32),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct HookId(usize);
+
+pub type Hook = Box<dyn FnMut(Event) + Send>;
+
+// Hooks are kept in a RefCell so events can be emitted from methods that only borrow the NDS immutably
+pub(crate) struct Hooks {
+    hooks: RefCell<Vec<Option<Hook>>>,
+}
+
+impl Hooks {
+    pub fn new() -> Self {
+        Hooks { hooks: RefCell::new(Vec::new()) }
+    }
+
+    pub fn add(&mut self, hook: Hook) -> HookId {
+        let hooks = self.hooks.get_mut();
+        let id = hooks.iter().position(Option::is_none).unwrap_or(hooks.len());
+        if id == hooks.len() { hooks.push(Some(hook)) } else { hooks[id] = Some(hook) }
+        HookId(id)
+    }
+
+    pub fn remove(&mut self, id: HookId) {
+        if let Some(hook) = self.hooks.get_mut().get_mut(id.0) { *hook = None }
+    }
+
+    pub fn emit(&self, event: Event) {
+        for hook in self.hooks.borrow_mut().iter_mut().flatten() { hook(event) }
+    }
+}
@@ -0,0 +1,56 @@
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use crate::nds::Cpu;
+
+// Problems the emulator can't continue from, such as a bad ROM or a game using hardware that isn't emulated yet
+#[derive(Clone, Debug, PartialEq)]
+pub enum Fault {
+    InvalidRom(String),
+    UndefinedInstruction { cpu: Cpu, addr: u32, instr: u32 },
+    Unimplemented(String),
+    InvalidValue(String),
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Fault::InvalidRom(reason) => write!(f, "Invalid ROM: {}", reason),
+            Fault::UndefinedInstruction { cpu, addr, instr } =>
+                write!(f, "Undefined {:?} Instruction 0x{:08X} at 0x{:08X}", cpu, instr, addr),
+            Fault::Unimplemented(feature) => write!(f, "{} is Not Implemented", feature),
+            Fault::InvalidValue(reason) => write!(f, "Invalid {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for Fault {}
+
+// Only the first fault is kept, and emulation stops after the instruction that raised it.
+// Clones share the same fault like Notifier, so devices can raise one without access to the rest of the system.
+#[derive(Clone, Default)]
+pub(crate) struct Faults {
+    first: Arc<Mutex<Option<Fault>>>,
+}
+
+impl Faults {
+    pub fn raise(&self, fault: Fault) {
+        let mut first = self.first.lock().unwrap();
+        if first.is_none() {
+            error!("{}!", fault);
+            *first = Some(fault);
+        }
+    }
+
+    pub fn raised(&self) -> bool {
+        self.first.lock().unwrap().is_some()
+    }
+
+    pub fn get(&self) -> Option<Fault> {
+        self.first.lock().unwrap().clone()
+    }
+
+    pub fn clear(&self) {
+        self.first.lock().unwrap().take();
+    }
+}
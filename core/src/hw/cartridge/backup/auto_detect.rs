@@ -0,0 +1,109 @@
+use std::cell::Cell;
+
+use crate::savestate::{Savestate, StateReader, StateWriter};
+use super::Backup;
+
+// Used for games missing from the DB without an existing save.
+// The chip is picked from the number of address bytes the game sends before reading data back,
+// 1 for 0.5KB EEPROM, 2 for 8KB and 64KB EEPROM and 3 for flash, and from the length of the first write,
+// which is typically a full page: 32 bytes for 8KB EEPROM and 128 bytes for 64KB EEPROM.
+// Games that write before ever reading get a chip guessed from the write's length alone.
+pub struct AutoDetect {
+    backup: Option<Box<dyn Backup>>,
+    transfer: Vec<u8>,
+    // Some(0) if the game reads back every byte it sends, which says nothing about the address
+    addr_len: Cell<Option<usize>>,
+}
+
+impl AutoDetect {
+    pub fn new() -> Self {
+        AutoDetect {
+            backup: None,
+            transfer: Vec::new(),
+            addr_len: Cell::new(None),
+        }
+    }
+
+    fn detect(&self) -> Option<usize> {
+        let cmd = *self.transfer.first()?;
+        let len = self.transfer.len();
+        let addr = self.transfer.get(1..3).map_or(0, |addr| u16::from_be_bytes([addr[0], addr[1]]) as usize);
+        match (cmd, self.addr_len.get()) {
+            // Flash only commands
+            (0x08 | 0x9F | 0xDB | 0xD8, _) => Some(0x4_0000),
+            // Read from the upper half of the small EEPROM or flash Fast Read, which sends a dummy byte
+            (0x03 | 0x0B | 0x02 | 0x0A, Some(1)) => Some(0x200),
+            (0x03 | 0x0B | 0x02 | 0x0A, Some(3 | 4)) => Some(0x4_0000),
+            (0x02, Some(2)) if len <= 1 + 2 + 0x20 && addr < 0x2000 => Some(0x2000),
+            (0x02, Some(2)) => Some(0x1_0000),
+            // Write to the upper half of the small EEPROM or flash Page Write
+            (0x0A, _) => Some(if len <= 1 + 1 + 0x10 { 0x200 } else { 0x4_0000 }),
+            (0x02, _) if len <= 1 + 1 + 0x10 => Some(0x200),
+            (0x02, _) if len <= 1 + 2 + 0x20 => Some(0x2000),
+            (0x02, _) if len <= 1 + 2 + 0x80 => Some(0x1_0000),
+            (0x02, _) => Some(0x4_0000),
+            _ => None,
+        }
+    }
+}
+
+// The detected chip is recreated from its size if it hasn't been detected in this session yet.
+// Until then the size holds the number of address bytes plus 1, which no chip size can be mistaken for.
+impl Savestate for AutoDetect {
+    fn save(&self, state: &mut StateWriter) {
+        self.transfer.save(state);
+        match &self.backup {
+            Some(backup) => backup.mem().map_or(0, |mem| mem.len()).save(state),
+            None => self.addr_len.get().map_or(0, |addr_len| addr_len + 1).save(state),
+        }
+        if let Some(backup) = &self.backup { backup.save(state) }
+    }
+
+    fn load(&mut self, state: &mut StateReader) {
+        self.transfer.load(state);
+        let mut size = 0usize;
+        size.load(state);
+        if size < 0x200 {
+            self.backup = None;
+            self.addr_len.set(size.checked_sub(1));
+            return
+        }
+        if self.backup.as_ref().and_then(|backup| backup.mem()).is_none_or(|mem| mem.len() != size) {
+            self.backup = <dyn Backup>::from_size(None, size);
+        }
+        match &mut self.backup {
+            Some(backup) => backup.load(state),
+            None => state.invalidate(),
+        }
+    }
+}
+
+impl Backup for AutoDetect {
+    fn read(&self) -> u8 {
+        if let Some(backup) = &self.backup { return backup.read() }
+        // The first byte read back comes after the command, the address and the byte sent to clock it out
+        if self.addr_len.get().is_none() && matches!(self.transfer.first(), Some(0x03 | 0x0B)) {
+            self.addr_len.set(Some(self.transfer.len().saturating_sub(2)));
+        }
+        0xFF // Reads as an empty save until detected
+    }
+
+    fn write(&mut self, hold: bool, value: u8) {
+        if let Some(backup) = &mut self.backup { backup.write(hold, value); return }
+        self.transfer.push(value);
+        if hold { return }
+        if let Some(size) = self.detect() {
+            info!("Detected Save Size: 0x{:X}", size);
+            let mut backup = <dyn Backup>::from_size(None, size).unwrap();
+            // Replay the transfer that identified the chip, and the write enable sent before it was dropped
+            if !matches!(self.transfer[0], 0x03 | 0x0B) { backup.write(false, 0x06) }
+            let len = self.transfer.len();
+            for (i, value) in self.transfer.iter().enumerate() { backup.write(i != len - 1, *value) }
+            self.backup = Some(backup);
+        }
+        self.transfer.clear();
+    }
+
+    fn mem(&self) -> Option<&Vec<u8>> { self.backup.as_ref()?.mem() }
+    fn dirty(&mut self) -> bool { self.backup.as_mut().is_some_and(|backup| backup.dirty()) }
+}
@@ -1,12 +1,11 @@
 use std::marker::PhantomData;
-use std::path::PathBuf;
 
+use crate::savestate::{Savestate, StateReader, StateWriter};
 use super::Backup;
 
 pub struct EEPROM<T: EEPROMType> {
     eeprom_type: PhantomData<T>,
     mem: Vec<u8>,
-    save_file: PathBuf,
     dirty: bool,
 
     mode: Mode,
@@ -17,12 +16,13 @@ pub struct EEPROM<T: EEPROMType> {
     
 }
 
+savestate!([T: EEPROMType] EEPROM<T> { mem, mode, value, write_enable, write_protect });
+
 impl<T: EEPROMType> EEPROM<T> {
-    pub fn new(save_file: PathBuf, size: usize) -> EEPROM<T> {
+    pub fn new(saved: Option<Vec<u8>>, size: usize) -> EEPROM<T> {
         EEPROM {
             eeprom_type: PhantomData,
-            mem: Backup::get_initial_mem(&save_file, 0, size),
-            save_file,
+            mem: Backup::get_initial_mem(saved, 0, size),
             dirty: false,
 
             mode: Mode::ReadCommand,
@@ -38,16 +38,40 @@ impl<T: EEPROMType> EEPROM<T> {
             Command::WREN => {
                 self.write_enable = true;
                 Mode::ReadCommand
-            }
+            },
+            Command::WRDI => {
+                self.write_enable = false;
+                Mode::ReadCommand
+            },
+            Command::Unknown(value) => {
+                warn!("Unknown {} EEPROM Command: 0x{:X}", T::debug_str(), value);
+                Mode::ReadCommand
+            },
             _ => Mode::HandleCommand(command),
         }
     }
 
+    fn write_protected(&self, addr: usize) -> bool {
+        let len = self.mem.len();
+        match self.write_protect {
+            WriteProtect::None => false,
+            WriteProtect::UpperQuarter => addr >= len / 4 * 3,
+            WriteProtect::UpperHalf => addr >= len / 2,
+            WriteProtect::All => true,
+        }
+    }
+
+    // Writes stay within the current page
+    fn next_page_addr(&self, addr: usize) -> usize {
+        let page_len = T::page_len(self.mem.len());
+        addr & !(page_len - 1) | (addr + 1) & (page_len - 1)
+    }
+
     fn handle_command(&mut self, command: Command, value: u8) -> Mode {
+        let mem_mask = self.mem.len() - 1;
         match command {
             Command::RD(0, addr) => {
-                assert_eq!(value, 0);
-                self.value = self.mem[addr];
+                self.value = self.mem[addr & mem_mask];
                 Mode::HandleCommand(Command::RD(0, addr + 1))
             },
             Command::RD(addr_bytes_left, addr) => {
@@ -55,26 +79,37 @@ impl<T: EEPROMType> EEPROM<T> {
             },
 
             Command::WR(0, addr) => {
-                if self.write_enable { self.dirty = true; self.mem[addr] = value }
-                Mode::HandleCommand(Command::WR(0, addr + 1))
+                let addr = addr & mem_mask;
+                if self.write_enable && !self.write_protected(addr) { self.dirty = true; self.mem[addr] = value }
+                Mode::HandleCommand(Command::WR(0, self.next_page_addr(addr)))
             },
             Command::WR(addr_bytes_left, addr) => {
                 Mode::HandleCommand(Command::WR(addr_bytes_left - 1, addr << 8 | value as usize))
             },
 
             Command::RDSR => {
-                assert_eq!(value, 0);
                 // TODO: Figure out Write in Progress needs to be emulated
                 let low_nibble = (self.write_protect as u8) << 2 | (self.write_enable as u8) << 1;
-                // TODO: Figure out what SWRD Status Register is
+                // Unused bits read as set on the small EEPROM
                 let high_nibble = if T::is_small() { 0xF } else { 0 };
                 self.value = high_nibble << 4 | low_nibble;
+                Mode::HandleCommand(Command::RDSR)
+            },
+            Command::WRSR => {
+                if self.write_enable { self.write_protect = WriteProtect::from(value >> 2 & 0x3) }
+                self.write_enable = false;
                 Mode::ReadCommand
             },
 
-            Command::WREN => unreachable!(),
+            Command::WREN | Command::WRDI | Command::Unknown(_) => unreachable!(),
         }
     }
+
+    fn deselect(&mut self) {
+        // Write enable is cleared once a write completes
+        if let Mode::HandleCommand(Command::WR(..)) = self.mode { self.write_enable = false }
+        self.mode = Mode::ReadCommand;
+    }
 }
 
 impl<T: EEPROMType> Backup for EEPROM<T> {
@@ -87,11 +122,10 @@ impl<T: EEPROMType> Backup for EEPROM<T> {
             Mode::ReadCommand => self.set_command(Command::get::<T>(value)),
             Mode::HandleCommand(command) => self.handle_command(command, value),
         };
-        if !hold { self.mode = Mode::ReadCommand }
+        if !hold { self.deselect() }
     }
 
-    fn mem(&self) -> &Vec<u8> { &self.mem }
-    fn save_file(&self) -> &PathBuf { &self.save_file }
+    fn mem(&self) -> Option<&Vec<u8>> { Some(&self.mem) }
     fn dirty(&mut self) -> bool { let old = self.dirty; self.dirty = false; old }
 }
 
@@ -101,12 +135,68 @@ enum Mode {
     HandleCommand(Command),
 }
 
+impl Savestate for Mode {
+    fn save(&self, state: &mut StateWriter) {
+        match self {
+            Mode::ReadCommand => false.save(state),
+            Mode::HandleCommand(command) => { true.save(state); command.save(state) },
+        }
+    }
+
+    fn load(&mut self, state: &mut StateReader) {
+        let mut handle_command = false;
+        handle_command.load(state);
+        *self = if handle_command {
+            let mut command = Command::RDSR;
+            command.load(state);
+            Mode::HandleCommand(command)
+        } else { Mode::ReadCommand };
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 enum Command {
     WR(usize, usize), // Write
     RD(usize, usize), // Read
     RDSR, // Read Status Register
+    WRSR, // Write Status Register
     WREN, // Write Enable
+    WRDI, // Write Disable
+    Unknown(u8),
+}
+
+impl Savestate for Command {
+    fn save(&self, state: &mut StateWriter) {
+        let (tag, bytes_left, addr) = match *self {
+            Command::WR(bytes_left, addr) => (0u8, bytes_left, addr),
+            Command::RD(bytes_left, addr) => (1, bytes_left, addr),
+            Command::RDSR => (2, 0, 0),
+            Command::WRSR => (3, 0, 0),
+            Command::WREN => (4, 0, 0),
+            Command::WRDI => (5, 0, 0),
+            Command::Unknown(value) => (6, value as usize, 0),
+        };
+        tag.save(state);
+        bytes_left.save(state);
+        addr.save(state);
+    }
+
+    fn load(&mut self, state: &mut StateReader) {
+        let (mut tag, mut bytes_left, mut addr) = (0u8, 0usize, 0usize);
+        tag.load(state);
+        bytes_left.load(state);
+        addr.load(state);
+        *self = match tag {
+            0 => Command::WR(bytes_left, addr),
+            1 => Command::RD(bytes_left, addr),
+            2 => Command::RDSR,
+            3 => Command::WRSR,
+            4 => Command::WREN,
+            5 => Command::WRDI,
+            6 => Command::Unknown(bytes_left as u8),
+            _ => { state.invalidate(); Command::RDSR },
+        };
+    }
 }
 
 impl Command {
@@ -116,11 +206,13 @@ impl Command {
             0x03 if T::is_small() => Command::RD(1, 0), // RDLO
             0x02 => Command::WR(2, 0),
             0x03 => Command::RD(2, 0),
+            0x01 => Command::WRSR,
+            0x04 => Command::WRDI,
             0x05 => Command::RDSR,
             0x06 => Command::WREN,
             0x0A if T::is_small() => Command::WR(1, 1), // WRHI
             0x0B if T::is_small() => Command::RD(1, 1), // RDHI
-            _ => unimplemented!("{} EEPROM Command: 0x{:X}", T::debug_str(), value),
+            _ => Command::Unknown(value),
         }
     }
 }
@@ -128,25 +220,49 @@ impl Command {
 #[derive(Clone, Copy)]
 enum WriteProtect {
     None = 0,
-    _UpperQuarter = 1,
-    _UpperHalf = 2,
-    _All = 3,
+    UpperQuarter = 1,
+    UpperHalf = 2,
+    All = 3,
 }
 
-pub trait EEPROMType {
+savestate_enum!(WriteProtect { None, UpperQuarter, UpperHalf, All });
+
+impl WriteProtect {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => WriteProtect::None,
+            1 => WriteProtect::UpperQuarter,
+            2 => WriteProtect::UpperHalf,
+            3 => WriteProtect::All,
+            _ => unreachable!(),
+        }
+    }
+}
+
+pub trait EEPROMType: Send {
     fn is_small() -> bool;
+    fn page_len(size: usize) -> usize;
     fn debug_str() -> &'static str;
 }
 
 pub struct EEPROMSmall {}
 pub struct EEPROMNormal {}
+pub struct FRAM {}
 
 impl EEPROMType for EEPROMSmall {
     fn is_small() -> bool { true }
+    fn page_len(_size: usize) -> usize { 0x10 }
     fn debug_str() -> &'static str { "Small" }
 }
 impl EEPROMType for EEPROMNormal {
     fn is_small() -> bool { false }
+    fn page_len(size: usize) -> usize { if size <= 8 * 0x400 { 0x20 } else if size <= 64 * 0x400 { 0x80 } else { 0x100 } }
     fn debug_str() -> &'static str { "Normal" }
 }
+// FRAM has the EEPROM command set but no page boundaries
+impl EEPROMType for FRAM {
+    fn is_small() -> bool { false }
+    fn page_len(size: usize) -> usize { size }
+    fn debug_str() -> &'static str { "FRAM" }
+}
 
@@ -1,12 +1,11 @@
-use std::path::PathBuf;
-
+use crate::savestate::{Savestate, StateReader, StateWriter};
 use super::Backup;
 
 pub struct Flash {
-    save_file: PathBuf,
     mem: Vec<u8>,
     dirty: bool,
 
+    id: [u8; 3],
     mode: Mode,
     value: u8,
     // Status Reg
@@ -14,13 +13,15 @@ pub struct Flash {
     
 }
 
+savestate!(Flash { mem, mode, value, write_enable });
+
 impl Flash {
-    pub fn new_backup(save_file: PathBuf, size: usize) -> Self {
+    pub fn new_backup(saved: Option<Vec<u8>>, size: usize) -> Self {
         Flash {
-            mem: Backup::get_initial_mem(&save_file, 0xFF, size),
-            save_file,
+            mem: Backup::get_initial_mem(saved, 0xFF, size),
             dirty: false,
 
+            id: Flash::id(size),
             mode: Mode::ReadInstr,
             value: 0,
             // Status Reg
@@ -30,8 +31,8 @@ impl Flash {
 
     pub fn new_firmware(firmware: Vec<u8>) -> Self {
         Flash {
+            id: Flash::id(firmware.len()),
             mem: firmware,
-            save_file: PathBuf::new(),
             dirty: false,
 
             mode: Mode::ReadInstr,
@@ -41,6 +42,14 @@ impl Flash {
         }
     }
 
+    const PAGE_LEN: usize = 0x100;
+    const SECTOR_LEN: usize = 0x1_0000;
+
+    // ST M45PExx family, the last byte encodes the capacity (0x12 for the 256KB firmware)
+    fn id(size: usize) -> [u8; 3] {
+        [0x20, 0x40, 0x11 + (size.trailing_zeros() as u8).saturating_sub(17)]
+    }
+
     fn set_instr(&mut self, instr: Instr) -> Mode {
         match instr {
             Instr::IR => Mode::ReadInstr, // TODO: Actually implement IR
@@ -48,45 +57,97 @@ impl Flash {
                 self.write_enable = true;
                 Mode::ReadInstr
             },
+            Instr::WRDI => {
+                self.write_enable = false;
+                Mode::ReadInstr
+            },
+            Instr::DP | Instr::RDP => Mode::ReadInstr,
+            Instr::Unknown(value) => {
+                warn!("Unknown Flash Instr: 0x{:X}", value);
+                Mode::ReadInstr
+            },
             _ => Mode::HandleInstr(instr),
         }
     }
 
+    // Writes stay within the current page
+    fn next_page_addr(addr: usize) -> usize {
+        addr & !(Flash::PAGE_LEN - 1) | (addr + 1) & (Flash::PAGE_LEN - 1)
+    }
+
     fn handle_instr(&mut self, instr: Instr, value: u8) -> Mode {
+        let mem_mask = self.mem.len() - 1;
         match instr {
-            Instr::IR => unreachable!(),
+            Instr::IR | Instr::WREN | Instr::WRDI | Instr::DP | Instr::RDP | Instr::Unknown(_) => unreachable!(),
 
             Instr::READ(0, addr) => {
-                assert_eq!(value, 0);
-                self.value = self.mem[addr];
+                self.value = self.mem[addr & mem_mask];
                 Mode::HandleInstr(Instr::READ(0, addr + 1))
             },
             Instr::READ(addr_bytes_left, addr) => {
                 Mode::HandleInstr(Instr::READ(addr_bytes_left - 1, addr << 8 | value as usize))
             },
+            // Fast Read has a dummy byte after the address
+            Instr::FastRead(0, addr) => Mode::HandleInstr(Instr::READ(0, addr)),
+            Instr::FastRead(addr_bytes_left, addr) => {
+                Mode::HandleInstr(Instr::FastRead(addr_bytes_left - 1, addr << 8 | value as usize))
+            },
 
             Instr::RDSR => {
-                assert_eq!(value, 0);
                 // TODO: Figure out if in Progress needs to be emulated
                 self.value = (self.write_enable as u8) << 1;
-                Mode::ReadInstr
+                Mode::HandleInstr(Instr::RDSR)
+            },
+            Instr::RDID(index) => {
+                self.value = *self.id.get(index).unwrap_or(&0);
+                Mode::HandleInstr(Instr::RDID(index + 1))
             },
 
-            Instr::WREN => unreachable!(),
-
-            Instr::PW(0, addr) => {
-                self.dirty = true;
+            Instr::PW(0, addr) | Instr::PP(0, addr) => {
+                let addr = addr & mem_mask;
                 self.value = self.mem[addr];
-                self.mem[addr] = value;
-                Mode::HandleInstr(Instr::PW(0, addr + 1))
+                if self.write_enable {
+                    self.dirty = true;
+                    // Page Program can only clear bits
+                    self.mem[addr] = if let Instr::PW(..) = instr { value } else { self.mem[addr] & value };
+                }
+                let next_addr = Flash::next_page_addr(addr);
+                Mode::HandleInstr(if let Instr::PW(..) = instr { Instr::PW(0, next_addr) } else { Instr::PP(0, next_addr) })
             },
             Instr::PW(addr_bytes_left, addr) => {
                 Mode::HandleInstr(Instr::PW(addr_bytes_left - 1, addr << 8 | value as usize))
             },
+            Instr::PP(addr_bytes_left, addr) => {
+                Mode::HandleInstr(Instr::PP(addr_bytes_left - 1, addr << 8 | value as usize))
+            },
+
+            Instr::PE(1, addr) | Instr::SE(1, addr) => {
+                let len = if let Instr::PE(..) = instr { Flash::PAGE_LEN } else { Flash::SECTOR_LEN };
+                let start = (addr << 8 | value as usize) & mem_mask & !(len - 1);
+                if self.write_enable {
+                    self.dirty = true;
+                    self.mem[start..start + len].iter_mut().for_each(|byte| *byte = 0xFF);
+                }
+                self.write_enable = false;
+                Mode::ReadInstr
+            },
+            Instr::PE(addr_bytes_left, addr) => {
+                Mode::HandleInstr(Instr::PE(addr_bytes_left - 1, addr << 8 | value as usize))
+            },
+            Instr::SE(addr_bytes_left, addr) => {
+                Mode::HandleInstr(Instr::SE(addr_bytes_left - 1, addr << 8 | value as usize))
+            },
         }
     }
 
+    // Flash always has its memory, unlike some other backups
+    pub fn data(&self) -> &[u8] { &self.mem }
+
     pub fn deselect(&mut self) {
+        // Write enable is cleared once a write or erase completes
+        if let Mode::HandleInstr(Instr::PW(..)) | Mode::HandleInstr(Instr::PP(..)) = self.mode {
+            self.write_enable = false;
+        }
         self.mode = Mode::ReadInstr;
     }
 }
@@ -101,11 +162,10 @@ impl Backup for Flash {
             Mode::ReadInstr => self.set_instr(Instr::get(value)),
             Mode::HandleInstr(instr) => self.handle_instr(instr, value),
         };
-        if !hold { self.mode = Mode::ReadInstr }
+        if !hold { self.deselect() }
     }
 
-    fn mem(&self) -> &Vec<u8> { &self.mem }
-    fn save_file(&self) -> &PathBuf { &self.save_file }
+    fn mem(&self) -> Option<&Vec<u8>> { Some(&self.mem) }
     fn dirty(&mut self) -> bool { let old = self.dirty; self.dirty = false; old }
 }
 
@@ -115,13 +175,89 @@ enum Mode {
     HandleInstr(Instr),
 }
 
+impl Savestate for Mode {
+    fn save(&self, state: &mut StateWriter) {
+        match self {
+            Mode::ReadInstr => false.save(state),
+            Mode::HandleInstr(instr) => { true.save(state); instr.save(state) },
+        }
+    }
+
+    fn load(&mut self, state: &mut StateReader) {
+        let mut handle_instr = false;
+        handle_instr.load(state);
+        *self = if handle_instr {
+            let mut instr = Instr::IR;
+            instr.load(state);
+            Mode::HandleInstr(instr)
+        } else { Mode::ReadInstr };
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 enum Instr {
     IR,
     READ(usize, usize),
+    FastRead(usize, usize),
     RDSR, // Read Status Register
+    RDID(usize), // Read Identification
     WREN, // Write Enable
+    WRDI, // Write Disable
     PW(usize, usize), // Page Write
+    PP(usize, usize), // Page Program
+    PE(usize, usize), // Page Erase
+    SE(usize, usize), // Sector Erase
+    DP, // Deep Power-down
+    RDP, // Release from Deep Power-down
+    Unknown(u8),
+}
+
+impl Savestate for Instr {
+    fn save(&self, state: &mut StateWriter) {
+        let (tag, bytes_left, addr) = match *self {
+            Instr::IR => (0u8, 0, 0),
+            Instr::READ(bytes_left, addr) => (1, bytes_left, addr),
+            Instr::FastRead(bytes_left, addr) => (2, bytes_left, addr),
+            Instr::RDSR => (3, 0, 0),
+            Instr::RDID(index) => (4, index, 0),
+            Instr::WREN => (5, 0, 0),
+            Instr::WRDI => (6, 0, 0),
+            Instr::PW(bytes_left, addr) => (7, bytes_left, addr),
+            Instr::PP(bytes_left, addr) => (8, bytes_left, addr),
+            Instr::PE(bytes_left, addr) => (9, bytes_left, addr),
+            Instr::SE(bytes_left, addr) => (10, bytes_left, addr),
+            Instr::DP => (11, 0, 0),
+            Instr::RDP => (12, 0, 0),
+            Instr::Unknown(value) => (13, value as usize, 0),
+        };
+        tag.save(state);
+        bytes_left.save(state);
+        addr.save(state);
+    }
+
+    fn load(&mut self, state: &mut StateReader) {
+        let (mut tag, mut bytes_left, mut addr) = (0u8, 0usize, 0usize);
+        tag.load(state);
+        bytes_left.load(state);
+        addr.load(state);
+        *self = match tag {
+            0 => Instr::IR,
+            1 => Instr::READ(bytes_left, addr),
+            2 => Instr::FastRead(bytes_left, addr),
+            3 => Instr::RDSR,
+            4 => Instr::RDID(bytes_left),
+            5 => Instr::WREN,
+            6 => Instr::WRDI,
+            7 => Instr::PW(bytes_left, addr),
+            8 => Instr::PP(bytes_left, addr),
+            9 => Instr::PE(bytes_left, addr),
+            10 => Instr::SE(bytes_left, addr),
+            11 => Instr::DP,
+            12 => Instr::RDP,
+            13 => Instr::Unknown(bytes_left as u8),
+            _ => { state.invalidate(); Instr::IR },
+        };
+    }
 }
 
 impl Instr {
@@ -130,10 +266,18 @@ impl Instr {
             0x00 => Instr::IR,
             0x08 => Instr::IR,
             0x03 => Instr::READ(3, 0),
+            0x0B => Instr::FastRead(3, 0),
             0x05 => Instr::RDSR,
+            0x9F => Instr::RDID(0),
             0x06 => Instr::WREN,
+            0x04 => Instr::WRDI,
             0x0A => Instr::PW(3, 0),
-            _ => unimplemented!("Flash Instr: 0x{:X}", value),
+            0x02 => Instr::PP(3, 0),
+            0xDB => Instr::PE(3, 0),
+            0xD8 => Instr::SE(3, 0),
+            0xB9 => Instr::DP,
+            0xAB => Instr::RDP,
+            _ => Instr::Unknown(value),
         }
     }
 }
@@ -0,0 +1,48 @@
+use super::Backup;
+
+// Cartridges with an infrared transceiver put it in front of the save chip.
+// The first byte of every transfer is an IR command, with 0x00 passing the rest through to the save chip.
+pub struct IR {
+    backup: Box<dyn Backup>,
+    command: Option<u8>,
+    value: u8,
+}
+
+savestate!(IR { backup, command, value });
+
+impl IR {
+    const ID: u8 = 0xAA;
+
+    pub fn new(backup: Box<dyn Backup>) -> Self {
+        IR {
+            backup,
+            command: None,
+            value: 0,
+        }
+    }
+}
+
+impl Backup for IR {
+    fn read(&self) -> u8 {
+        match self.command {
+            Some(0x00) => self.backup.read(),
+            _ => self.value,
+        }
+    }
+
+    fn write(&mut self, hold: bool, value: u8) {
+        match self.command {
+            None => { self.command = Some(value); self.value = 0 },
+            Some(0x00) => self.backup.write(hold, value),
+            // No peer is ever in range, so nothing is received
+            Some(0x01) => self.value = 0,
+            Some(0x02) => (),
+            Some(0x08) => self.value = IR::ID,
+            Some(command) => { warn!("Unknown IR Command: 0x{:X}", command); self.value = 0 },
+        }
+        if !hold { self.command = None }
+    }
+
+    fn mem(&self) -> Option<&Vec<u8>> { self.backup.mem() }
+    fn dirty(&mut self) -> bool { self.backup.dirty() }
+}
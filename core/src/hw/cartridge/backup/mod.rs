@@ -2,54 +2,84 @@ mod game_db;
 mod no_backup;
 mod eeprom;
 mod flash;
+mod auto_detect;
+mod nand;
+mod ir;
+mod storage;
 
-use std::fs;
-use std::path::PathBuf;
-
+use crate::savestate::Savestate;
 use super::Header;
 
 use no_backup::NoBackup;
-use eeprom::{EEPROM, EEPROMSmall, EEPROMNormal};
+use eeprom::{EEPROM, EEPROMSmall, EEPROMNormal, FRAM};
 pub use flash::Flash;
+use auto_detect::AutoDetect;
+use nand::NAND;
+use ir::IR;
+pub use storage::{MemoryStorage, SaveStorage};
+#[cfg(feature = "host")]
+pub use storage::FileStorage;
 
 
-pub trait Backup {
+// Save memory is part of save states so it stays consistent with the game's view of it
+pub trait Backup: Savestate + Send {
     fn read(&self) -> u8;
     fn write(&mut self, hold: bool, value: u8);
     
-    fn mem(&self) -> &Vec<u8>;
-    fn save_file(&self) -> &PathBuf;
+    // Save memory on the ROM bus handles main data mode commands before the ROM does
+    fn rom_command(&mut self, _command: &[u8; 8], _len: usize) -> Option<Vec<u32>> { None }
+    fn rom_write(&mut self, _word: u32) {}
+
+    // None when there's no chip to save, like before a chip has been detected
+    fn mem(&self) -> Option<&Vec<u8>>;
     fn dirty(&mut self) -> bool;
 }
 
 impl dyn Backup {
-    pub fn detect_type(header: &Header, save_file: PathBuf) -> Box<dyn Backup> {
+    pub fn detect_type(header: &Header, saved: Option<Vec<u8>>) -> Box<dyn Backup> {
+        let backup = <dyn Backup>::detect_chip(header, saved);
+        // Game codes starting with I are used by the Pokemon games with IR cartridges
+        if header.game_code[0] == b'I' { Box::new(IR::new(backup)) } else { backup }
+    }
+
+    fn detect_chip(header: &Header, saved: Option<Vec<u8>>) -> Box<dyn Backup> {
         let game_code = u32::from_le_bytes(header.game_code);
         if let Some(pos) = Backup::GAME_DB.iter().position(|game_info| game_info.game_code == game_code) {
             let game_info = &Backup::GAME_DB[pos];
             let sram_size = Backup::SRAM_SIZES[game_info.sram_type];
             match game_info.sram_type {
-                1 => Box::new(EEPROM::<EEPROMSmall>::new(save_file, sram_size)),
-                2 ..= 4 => Box::new(EEPROM::<EEPROMNormal>::new(save_file, sram_size)),
-                5 ..= 8 => Box::new(Flash::new_backup(save_file, sram_size)),
-                _ => todo!(),
+                0 => Box::new(NoBackup::new()),
+                1 => Box::new(EEPROM::<EEPROMSmall>::new(saved, sram_size)),
+                2 ..= 4 => Box::new(EEPROM::<EEPROMNormal>::new(saved, sram_size)),
+                5 ..= 8 => Box::new(Flash::new_backup(saved, sram_size)),
+                // Stored after the ROM
+                9 => Box::new(NAND::new(saved, sram_size, 0x2_0000 << header.device_capacity)),
+                _ => unreachable!(),
             }
         } else {
-            warn!("Game not found in DB!");
-            Box::new(NoBackup::new())
+            // Fall back to the size of an existing save and then to watching how the game accesses it
+            let save_size = saved.as_ref().map_or(0, |saved| saved.len());
+            if let Some(backup) = <dyn Backup>::from_size(saved, save_size) { return backup }
+            warn!("Game not found in DB! Detecting Save Type");
+            Box::new(AutoDetect::new())
         }
     }
 
-    fn get_initial_mem(save_file: &PathBuf, default_val: u8, size: usize) -> Vec<u8> {
-        if let Ok(mem) = fs::read(save_file) {
-            if mem.len() == size { mem } else { vec![default_val; size] }
-        } else { vec![default_val; size] }
+    fn from_size(saved: Option<Vec<u8>>, size: usize) -> Option<Box<dyn Backup>> {
+        Some(match size {
+            0x200 => Box::new(EEPROM::<EEPROMSmall>::new(saved, size)),
+            0x2000 | 0x1_0000 | 0x2_0000 => Box::new(EEPROM::<EEPROMNormal>::new(saved, size)),
+            // Only FRAM comes in this size
+            0x8000 => Box::new(EEPROM::<FRAM>::new(saved, size)),
+            0x4_0000 | 0x8_0000 | 0x10_0000 | 0x80_0000 => Box::new(Flash::new_backup(saved, size)),
+            _ => return None,
+        })
     }
 
-    pub fn save(&mut self) {
-        if self.dirty() {
-            fs::write(self.save_file(), self.mem())
-            .unwrap_or_else(|err| warn!("Unable to Save to File: {}!", err))
+    fn get_initial_mem(saved: Option<Vec<u8>>, default_val: u8, size: usize) -> Vec<u8> {
+        match saved {
+            Some(mem) if mem.len() == size => mem,
+            _ => vec![default_val; size],
         }
     }
 }
@@ -0,0 +1,113 @@
+use std::convert::TryInto;
+
+use super::Backup;
+
+// NAND saves are accessed through cartridge commands on the ROM bus instead of AUXSPI.
+// The save is mapped after the ROM, and a 128KB window of it replaces ROM reads once selected.
+pub struct NAND {
+    mem: Vec<u8>,
+    dirty: bool,
+
+    base_addr: usize,
+    window: Option<usize>,
+    write_enable: bool,
+    write_addr: usize,
+    write_buffer: Vec<u8>,
+}
+
+savestate!(NAND { mem, window, write_enable, write_addr, write_buffer });
+
+impl NAND {
+    const WINDOW_LEN: usize = 0x2_0000;
+    const PAGE_LEN: usize = 0x800;
+    // Ready for the next command
+    const STATUS_READY: u8 = 0x20;
+
+    pub fn new(saved: Option<Vec<u8>>, size: usize, base_addr: usize) -> Self {
+        NAND {
+            mem: <dyn Backup>::get_initial_mem(saved, 0xFF, size),
+            dirty: false,
+
+            base_addr,
+            window: None,
+            write_enable: false,
+            write_addr: 0,
+            write_buffer: Vec::with_capacity(NAND::PAGE_LEN),
+        }
+    }
+
+    fn mem_addr(&self, addr: usize) -> Option<usize> {
+        addr.checked_sub(self.base_addr).filter(|addr| *addr < self.mem.len())
+    }
+}
+
+impl Backup for NAND {
+    fn read(&self) -> u8 { 0 }
+    fn write(&mut self, _hold: bool, _value: u8) {}
+
+    fn rom_command(&mut self, command: &[u8; 8], len: usize) -> Option<Vec<u32>> {
+        let addr = u32::from_be_bytes(command[1..5].try_into().unwrap()) as usize;
+        let words = len / 4;
+        match command[0] {
+            // Enter save mode
+            0x85 => {
+                self.write_enable = true;
+                self.write_buffer.clear();
+                Some(vec![0; words])
+            },
+            // Leave save mode
+            0x8B => {
+                self.window = None;
+                self.write_enable = false;
+                Some(vec![0; words])
+            },
+            // Write a page to the buffer
+            0x81 => {
+                self.write_addr = addr;
+                self.write_buffer.clear();
+                Some(vec![0; words])
+            },
+            // Program the buffered page
+            0x82 => {
+                if let (true, Some(mem_addr)) = (self.write_enable, self.mem_addr(self.write_addr)) {
+                    let start = mem_addr & !(NAND::PAGE_LEN - 1);
+                    let len = self.write_buffer.len().min(self.mem.len() - start);
+                    self.mem[start..start + len].copy_from_slice(&self.write_buffer[..len]);
+                    self.dirty = true;
+                }
+                self.write_buffer.clear();
+                Some(vec![0; words])
+            },
+            // Discard the buffered page
+            0x84 => {
+                self.write_buffer.clear();
+                Some(vec![0; words])
+            },
+            0x94 => Some(vec![0; words]), // ID
+            0xB2 => {
+                let window = addr & !(NAND::WINDOW_LEN - 1);
+                if self.mem_addr(window).is_some() { self.window = Some(window) }
+                else { warn!("Invalid NAND Save Window: 0x{:X}", addr) }
+                Some(vec![0; words])
+            },
+            0xB7 => {
+                let window = self.window?;
+                if !(window .. window + NAND::WINDOW_LEN).contains(&addr) { return None }
+                let mem_addr = self.mem_addr(addr)?;
+                Some((0..words).map(|i| {
+                    let addr = (mem_addr + 4 * i) % self.mem.len();
+                    u32::from_le_bytes(self.mem[addr..addr + 4].try_into().unwrap())
+                }).collect())
+            },
+            0xD6 => Some(vec![u32::from_le_bytes([NAND::STATUS_READY; 4]); words]), // Status
+            _ => None,
+        }
+    }
+
+    fn rom_write(&mut self, word: u32) {
+        if self.write_buffer.len() < NAND::PAGE_LEN { self.write_buffer.extend_from_slice(&word.to_le_bytes()) }
+    }
+
+    fn mem(&self) -> Option<&Vec<u8>> { Some(&self.mem) }
+    fn dirty(&mut self) -> bool { let old = self.dirty; self.dirty = false; old }
+}
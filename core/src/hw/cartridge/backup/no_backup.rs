@@ -1,5 +1,3 @@
-use std::path::PathBuf;
-
 use super::Backup;
 
 pub struct NoBackup {}
@@ -8,11 +6,12 @@ impl Backup for NoBackup {
     fn read(&self) -> u8 { 0 }
     fn write(&mut self, _hold: bool, _value: u8) {}
 
-    fn mem(&self) -> &Vec<u8> { unreachable!() }
-    fn save_file(&self) -> &PathBuf { unreachable!() }
+    fn mem(&self) -> Option<&Vec<u8>> { None }
     fn dirty(&mut self) -> bool { false }
 }
 
+savestate!(NoBackup {});
+
 impl NoBackup {
     pub fn new() -> Self {
         NoBackup {}
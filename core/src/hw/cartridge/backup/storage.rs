@@ -0,0 +1,60 @@
+use std::io;
+#[cfg(feature = "host")]
+use std::fs;
+#[cfg(feature = "host")]
+use std::path::PathBuf;
+
+pub trait SaveStorage: Send {
+    // Contents of the save, if one exists
+    fn load(&mut self) -> Option<Vec<u8>>;
+    fn flush(&mut self, mem: &[u8]) -> io::Result<()>;
+}
+
+// Keeps the save in memory, so runs that shouldn't touch the real save can still start from it
+pub struct MemoryStorage {
+    mem: Option<Vec<u8>>,
+}
+
+impl MemoryStorage {
+    pub fn new(mem: Option<Vec<u8>>) -> Self {
+        MemoryStorage {
+            mem,
+        }
+    }
+}
+
+impl SaveStorage for MemoryStorage {
+    fn load(&mut self) -> Option<Vec<u8>> {
+        self.mem.clone()
+    }
+
+    fn flush(&mut self, mem: &[u8]) -> io::Result<()> {
+        self.mem = Some(mem.to_vec());
+        Ok(())
+    }
+}
+
+#[cfg(feature = "host")]
+pub struct FileStorage {
+    path: PathBuf,
+}
+
+#[cfg(feature = "host")]
+impl FileStorage {
+    pub fn new(path: PathBuf) -> Self {
+        FileStorage {
+            path,
+        }
+    }
+}
+
+#[cfg(feature = "host")]
+impl SaveStorage for FileStorage {
+    fn load(&mut self) -> Option<Vec<u8>> {
+        fs::read(&self.path).ok()
+    }
+
+    fn flush(&mut self, mem: &[u8]) -> io::Result<()> {
+        fs::write(&self.path, mem)
+    }
+}
@@ -0,0 +1,153 @@
+@ DLDI driver that services sector reads and writes with emulator-specific cartridge commands:
+@ C0 reads a 0x200 byte sector, C1 writes one and C2 returns whether an SD card image is inserted.
+@ Only PC-relative addressing is used so no sections need fixing when patched.
+@ Calls avoid bl so that the driver can be built without a linker:
+@   llvm-mc --triple=armv4t-none-eabi -filetype=obj driver.s -o driver.o
+@   llvm-objcopy -O binary --only-section=.text driver.o driver.bin
+    .arm
+    .text
+    .global _start
+_start:
+    .word 0xBF8DA5ED
+    .ascii " Chishm\0"
+    .byte 1                 @ Version
+    .byte 10                @ Driver size (1KB)
+    .byte 0                 @ Sections to fix
+    .byte 10                @ Allocated space
+    .ascii "NDS-Emulator SD Card"
+    .space 48 - 20
+    .word 0xBF800000        @ Text start
+    .word 0xBF800000 + (driver_end - _start)
+    .word 0xBF800000 + (driver_end - _start)
+    .word 0xBF800000 + (driver_end - _start)
+    .word 0xBF800000 + (driver_end - _start)
+    .word 0xBF800000 + (driver_end - _start)
+    .word 0xBF800000 + (driver_end - _start)
+    .word 0xBF800000 + (driver_end - _start)
+    .ascii "EMSD"
+    .word 0x23              @ Can read, can write, Slot-1
+    .word 0xBF800000 + (is_inserted - _start)   @ Startup
+    .word 0xBF800000 + (is_inserted - _start)
+    .word 0xBF800000 + (read_sectors - _start)
+    .word 0xBF800000 + (write_sectors - _start)
+    .word 0xBF800000 + (clear_status - _start)
+    .word 0xBF800000 + (clear_status - _start)  @ Shutdown
+
+is_inserted:
+    push {r4, lr}
+    mov r0, #0xC2
+    mov r1, #0
+    ldr r2, =0xA7000000     @ Start, release reset, 4 bytes
+    mov lr, pc
+    b send_command
+    ldr r3, =0x040001A4
+    ldr r4, =0x04100010
+    mov r0, #0
+1:  ldr r12, [r3]
+    tst r12, #0x80000000
+    beq 2f
+    tst r12, #0x00800000
+    ldrne r0, [r4]
+    b 1b
+2:  pop {r4, lr}
+    bx lr
+
+@ r0 = sector, r1 = number of sectors, r2 = buffer
+read_sectors:
+    push {r4-r7, lr}
+    mov r4, r0
+    mov r5, r1
+    mov r6, r2
+    ldr r7, =0x04100010
+1:  cmp r5, #0
+    beq 4f
+    mov r0, #0xC0
+    mov r1, r4
+    ldr r2, =0xA1000000     @ Start, release reset, 0x200 bytes
+    mov lr, pc
+    b send_command
+    ldr r3, =0x040001A4
+2:  ldr r12, [r3]
+    tst r12, #0x80000000
+    beq 3f
+    tst r12, #0x00800000
+    beq 2b
+    @ The buffer may not be word aligned
+    ldr r12, [r7]
+    strb r12, [r6], #1
+    mov r12, r12, lsr #8
+    strb r12, [r6], #1
+    mov r12, r12, lsr #8
+    strb r12, [r6], #1
+    mov r12, r12, lsr #8
+    strb r12, [r6], #1
+    b 2b
+3:  add r4, r4, #1
+    sub r5, r5, #1
+    b 1b
+4:  mov r0, #1
+    pop {r4-r7, lr}
+    bx lr
+
+@ r0 = sector, r1 = number of sectors, r2 = buffer
+write_sectors:
+    push {r4-r8, lr}
+    mov r4, r0
+    mov r5, r1
+    mov r6, r2
+    ldr r7, =0x04100010
+1:  cmp r5, #0
+    beq 4f
+    mov r0, #0xC1
+    mov r1, r4
+    ldr r2, =0xE1000000     @ Start, write, release reset, 0x200 bytes
+    mov lr, pc
+    b send_command
+    ldr r3, =0x040001A4
+2:  ldr r12, [r3]
+    tst r12, #0x80000000
+    beq 3f
+    tst r12, #0x00800000
+    beq 2b
+    ldrb r12, [r6], #1
+    ldrb r8, [r6], #1
+    orr r12, r12, r8, lsl #8
+    ldrb r8, [r6], #1
+    orr r12, r12, r8, lsl #16
+    ldrb r8, [r6], #1
+    orr r12, r12, r8, lsl #24
+    str r12, [r7]
+    b 2b
+3:  add r4, r4, #1
+    sub r5, r5, #1
+    b 1b
+4:  mov r0, #1
+    pop {r4-r8, lr}
+    bx lr
+
+clear_status:
+    mov r0, #1
+    bx lr
+
+@ r0 = command, r1 = sector, r2 = ROMCTRL
+send_command:
+    ldr r3, =0x040001A0
+    mov r12, #0x80          @ Enable the slot in ROM mode
+    strb r12, [r3, #1]
+    strb r0, [r3, #8]
+    mov r12, r1, lsr #24
+    strb r12, [r3, #9]
+    mov r12, r1, lsr #16
+    strb r12, [r3, #10]
+    mov r12, r1, lsr #8
+    strb r12, [r3, #11]
+    strb r1, [r3, #12]
+    mov r12, #0
+    strb r12, [r3, #13]
+    strb r12, [r3, #14]
+    strb r12, [r3, #15]
+    str r2, [r3, #4]
+    bx lr
+
+    .ltorg
+driver_end:
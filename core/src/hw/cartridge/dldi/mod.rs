@@ -0,0 +1,112 @@
+use std::convert::TryInto;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+
+use crate::notifications::Notifier;
+
+// Homebrew accesses the SD card through the DLDI driver linked into it. The stub driver is replaced
+// with one (see driver.s) that reads and writes sectors of an image file through cartridge commands.
+pub trait SdImage: Read + Write + Seek + Send {}
+impl<T: Read + Write + Seek + Send> SdImage for T {}
+
+pub struct SDCard {
+    image: Option<Box<dyn SdImage>>,
+    write_sector: Option<u64>,
+    write_buffer: Vec<u8>,
+}
+
+impl SDCard {
+    const DRIVER: &'static [u8] = include_bytes!("driver.bin");
+    const MAGIC: [u8; 12] = [0xED, 0xA5, 0x8D, 0xBF, b' ', b'C', b'h', b'i', b's', b'h', b'm', 0x00];
+    const SECTOR_LEN: usize = 0x200;
+
+    // Header offsets
+    const DRIVER_SIZE: usize = 0x0D;
+    const ALLOCATED_SPACE: usize = 0x0F;
+    const TEXT_START: usize = 0x40;
+    const SECTION_ADDRS: Range<usize> = 0x40..0x60;
+    const FUNCTION_ADDRS: Range<usize> = 0x68..0x80;
+    const STARTUP: usize = 0x68;
+    const CODE: usize = 0x80;
+
+    pub fn new() -> Self {
+        SDCard {
+            image: None,
+            write_sector: None,
+            write_buffer: Vec::with_capacity(SDCard::SECTOR_LEN),
+        }
+    }
+
+    // Returns whether a DLDI stub was found and replaced
+    pub fn patch_driver(binary: &mut [u8], notifier: &Notifier) -> bool {
+        let pos = match binary.windows(SDCard::MAGIC.len()).position(|window| window == SDCard::MAGIC) {
+            Some(pos) => pos,
+            None => return false,
+        };
+        let stub = &mut binary[pos..];
+        let driver_size = SDCard::DRIVER[SDCard::DRIVER_SIZE];
+        if stub[SDCard::ALLOCATED_SPACE] < driver_size || stub.len() < SDCard::DRIVER.len() {
+            notify!(notifier, "Not Enough Space for DLDI Driver");
+            return false
+        }
+
+        let read_addr = |data: &[u8], offset: usize| u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+        let mut stub_addr = read_addr(stub, SDCard::TEXT_START);
+        if stub_addr == 0 { stub_addr = read_addr(stub, SDCard::STARTUP).wrapping_sub(SDCard::CODE as u32) }
+        let relocation = stub_addr.wrapping_sub(read_addr(SDCard::DRIVER, SDCard::TEXT_START));
+
+        let allocated_space = stub[SDCard::ALLOCATED_SPACE];
+        stub[..SDCard::DRIVER.len()].copy_from_slice(SDCard::DRIVER);
+        stub[SDCard::ALLOCATED_SPACE] = allocated_space;
+        // The driver is position independent so only the addresses in its header need to be relocated
+        for offset in SDCard::SECTION_ADDRS.chain(SDCard::FUNCTION_ADDRS).step_by(4) {
+            let addr = read_addr(stub, offset).wrapping_add(relocation);
+            stub[offset..offset + 4].copy_from_slice(&addr.to_le_bytes());
+        }
+        true
+    }
+
+    pub fn set_image(&mut self, image: Option<Box<dyn SdImage>>) {
+        self.image = image;
+        self.write_sector = None;
+    }
+
+    pub fn rom_command(&mut self, command: &[u8; 8], len: usize) -> Option<Vec<u32>> {
+        let sector = u32::from_be_bytes(command[1..5].try_into().unwrap()) as u64;
+        let words = len / 4;
+        match command[0] {
+            0xC0 => {
+                let mut data = vec![0; words * 4];
+                if let Some(image) = &mut self.image {
+                    let result = image.seek(SeekFrom::Start(sector * SDCard::SECTOR_LEN as u64))
+                        .and_then(|_| image.read_exact(&mut data));
+                    if let Err(err) = result { warn!("Unable to Read SD Sector 0x{:X}: {}", sector, err) }
+                }
+                Some(data.chunks_exact(4).map(|word| u32::from_le_bytes(word.try_into().unwrap())).collect())
+            },
+            0xC1 => {
+                self.write_sector = Some(sector);
+                self.write_buffer.clear();
+                Some(vec![0; words])
+            },
+            0xC2 => Some(vec![self.image.is_some() as u32; words]),
+            _ => None,
+        }
+    }
+
+    pub fn rom_write(&mut self, word: u32) {
+        let sector = match self.write_sector {
+            Some(sector) => sector,
+            None => return,
+        };
+        self.write_buffer.extend_from_slice(&word.to_le_bytes());
+        if self.write_buffer.len() < SDCard::SECTOR_LEN { return }
+        self.write_sector = None;
+        let write_buffer = &self.write_buffer;
+        if let Some(image) = &mut self.image {
+            let result = image.seek(SeekFrom::Start(sector * SDCard::SECTOR_LEN as u64))
+                .and_then(|_| image.write_all(write_buffer));
+            if let Err(err) = result { warn!("Unable to Write SD Sector 0x{:X}: {}", sector, err) }
+        }
+    }
+}
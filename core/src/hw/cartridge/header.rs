@@ -51,7 +51,13 @@ pub struct Header {
 }
 
 impl Header {
-    pub fn new(rom: &Vec<u8>) -> Header {
+    const LEN: usize = 0x160;
+
+    pub fn parse(rom: &[u8]) -> Option<Header> {
+        if rom.len() < Header::LEN { None } else { Some(Header::new(rom)) }
+    }
+
+    pub fn new(rom: &[u8]) -> Header {
         Header {
             game_title: rom[0x000..0x00C].try_into().unwrap(),
             game_code: rom[0x00C..0x010].try_into().unwrap(),
@@ -102,8 +108,17 @@ impl Header {
             // reserved5: rom[0x170..0x200].try_into().unwrap(),
         }
     }
+
+    pub fn title(&self) -> String { Header::ascii(&self.game_title) }
+    pub fn game_code_str(&self) -> String { Header::ascii(&self.game_code) }
+    pub fn maker_code_str(&self) -> String { Header::ascii(&self.maker_code) }
+
+    fn ascii(bytes: &[u8]) -> String {
+        bytes.iter().take_while(|byte| **byte != 0).map(|byte| *byte as char).collect()
+    }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum UnitCode {
     NDS,
     Both,
@@ -116,11 +131,12 @@ impl UnitCode {
             0b00 => UnitCode::NDS,
             0b10 => UnitCode::Both,
             0b11 => UnitCode::DSi,
-            _ => unreachable!(),
+            _ => { warn!("Unknown Unit Code: 0x{:X}", value); UnitCode::NDS },
         }
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Region {
     Normal,
     China,
@@ -133,7 +149,7 @@ impl Region {
             0x00 => Region::Normal,
             0x80 => Region::China,
             0x40 => Region::Korea,
-            _ => unreachable!(),
+            _ => { warn!("Unknown Region: 0x{:X}", value); Region::Normal },
         }
     }
 }
@@ -0,0 +1,133 @@
+use std::convert::TryInto;
+
+// Blowfish variant used for cartridge commands and the secure area
+#[derive(Clone, Default)]
+pub struct Key1 {
+    keybuf: Vec<u32>,
+}
+
+savestate!(Key1 { keybuf });
+
+impl Key1 {
+    // Offset and length of the initial key table in the ARM7 BIOS
+    pub const KEY_TABLE_ADDR: usize = 0x30;
+    pub const KEY_TABLE_LEN: usize = 0x1048;
+
+    pub fn new(key_table: &[u8], id_code: u32, level: usize, modulo: usize) -> Self {
+        let mut key1 = Key1 {
+            keybuf: key_table.chunks_exact(4).map(|word| u32::from_le_bytes(word.try_into().unwrap())).collect(),
+        };
+        let mut keycode = [id_code, id_code / 2, id_code.wrapping_mul(2)];
+        if level >= 1 { key1.apply_keycode(&mut keycode, modulo) }
+        if level >= 2 { key1.apply_keycode(&mut keycode, modulo) }
+        keycode[1] = keycode[1].wrapping_mul(2);
+        keycode[2] /= 2;
+        if level >= 3 { key1.apply_keycode(&mut keycode, modulo) }
+        key1
+    }
+
+    fn apply_keycode(&mut self, keycode: &mut [u32; 3], modulo: usize) {
+        let mut words = [keycode[1], keycode[2]];
+        self.encrypt(&mut words);
+        keycode[1] = words[0];
+        keycode[2] = words[1];
+        let mut words = [keycode[0], keycode[1]];
+        self.encrypt(&mut words);
+        keycode[0] = words[0];
+        keycode[1] = words[1];
+        for i in 0..0x12 {
+            self.keybuf[i] ^= keycode[i % (modulo / 4)].swap_bytes();
+        }
+        let mut scratch = [0; 2];
+        for i in (0..0x412).step_by(2) {
+            self.encrypt(&mut scratch);
+            self.keybuf[i] = scratch[1];
+            self.keybuf[i + 1] = scratch[0];
+        }
+    }
+
+    fn round(&self, x: u32) -> u32 {
+        let keybuf = &self.keybuf;
+        let mut value = keybuf[0x012 + (x >> 24) as usize];
+        value = value.wrapping_add(keybuf[0x112 + (x >> 16 & 0xFF) as usize]);
+        value ^= keybuf[0x212 + (x >> 8 & 0xFF) as usize];
+        value.wrapping_add(keybuf[0x312 + (x & 0xFF) as usize])
+    }
+
+    pub fn encrypt(&self, words: &mut [u32; 2]) {
+        let (mut x, mut y) = (words[1], words[0]);
+        for i in 0..0x10 {
+            let z = self.keybuf[i] ^ x;
+            x = self.round(z) ^ y;
+            y = z;
+        }
+        words[0] = x ^ self.keybuf[0x10];
+        words[1] = y ^ self.keybuf[0x11];
+    }
+
+    pub fn decrypt(&self, words: &mut [u32; 2]) {
+        let (mut x, mut y) = (words[1], words[0]);
+        for i in (0x02..0x12).rev() {
+            let z = self.keybuf[i] ^ x;
+            x = self.round(z) ^ y;
+            y = z;
+        }
+        words[0] = x ^ self.keybuf[0x1];
+        words[1] = y ^ self.keybuf[0x0];
+    }
+
+    // Commands are sent as big endian 64-bit values
+    pub fn decrypt_command(&self, command: &mut [u8; 8]) {
+        let mut words = [
+            u32::from_be_bytes(command[4..8].try_into().unwrap()),
+            u32::from_be_bytes(command[0..4].try_into().unwrap()),
+        ];
+        self.decrypt(&mut words);
+        command[0..4].copy_from_slice(&words[1].to_be_bytes());
+        command[4..8].copy_from_slice(&words[0].to_be_bytes());
+    }
+
+    fn read_words(data: &[u8]) -> [u32; 2] {
+        [u32::from_le_bytes(data[0..4].try_into().unwrap()), u32::from_le_bytes(data[4..8].try_into().unwrap())]
+    }
+
+    fn write_words(data: &mut [u8], words: [u32; 2]) {
+        data[0..4].copy_from_slice(&words[0].to_le_bytes());
+        data[4..8].copy_from_slice(&words[1].to_le_bytes());
+    }
+
+    // The first 2KB of the secure area are encrypted in dumps of retail cartridges.
+    // Returns whether the secure area was encrypted.
+    pub fn decrypt_secure_area(key_table: &[u8], id_code: u32, secure_area: &mut [u8]) -> bool {
+        let level2 = Key1::new(key_table, id_code, 2, 8);
+        let level3 = Key1::new(key_table, id_code, 3, 8);
+        let mut id = Key1::read_words(secure_area);
+        level2.decrypt(&mut id);
+        level3.decrypt(&mut id);
+        if &[id[0].to_le_bytes(), id[1].to_le_bytes()].concat() != b"encryObj" { return false }
+
+        Key1::write_words(secure_area, id);
+        for block in secure_area[8..0x800].chunks_exact_mut(8) {
+            let mut words = Key1::read_words(block);
+            level3.decrypt(&mut words);
+            Key1::write_words(block, words);
+        }
+        // The ID is replaced with an undefined instruction once decrypted
+        Key1::write_words(secure_area, [0xE7FF_DEFF, 0xE7FF_DEFF]);
+        true
+    }
+
+    pub fn encrypt_secure_area(key_table: &[u8], id_code: u32, secure_area: &mut [u8]) {
+        let level2 = Key1::new(key_table, id_code, 2, 8);
+        let level3 = Key1::new(key_table, id_code, 3, 8);
+        secure_area[0..8].copy_from_slice(b"encryObj");
+        for block in secure_area[0..0x800].chunks_exact_mut(8) {
+            let mut words = Key1::read_words(block);
+            level3.encrypt(&mut words);
+            Key1::write_words(block, words);
+        }
+        let mut id = Key1::read_words(secure_area);
+        level2.encrypt(&mut id);
+        Key1::write_words(secure_area, id);
+    }
+}
@@ -0,0 +1,42 @@
+// Stream cipher applied to commands and data once the cartridge is in its main data mode
+#[derive(Clone, Copy)]
+pub struct Key2 {
+    x: u64,
+    y: u64,
+}
+
+savestate!(Key2 { x, y });
+
+impl Key2 {
+    const MASK: u64 = (1 << 39) - 1;
+    // Seeds used by the BIOS, the first is combined with a value sent using KEY1 command 4
+    pub const SEED_BYTES: [u8; 8] = [0xE8, 0x4D, 0x5A, 0xB1, 0x17, 0x8F, 0x99, 0xD5];
+    pub const SEED1: u64 = 0x5C_879B_9B05;
+
+    pub fn new(seed0: u64, seed1: u64) -> Self {
+        Key2 {
+            x: Key2::reverse(seed0),
+            y: Key2::reverse(seed1),
+        }
+    }
+
+    pub fn seed0(value: u32, seed_byte: u8) -> u64 {
+        ((value as u64) << 15) + 0x6000 + seed_byte as u64
+    }
+
+    fn reverse(seed: u64) -> u64 { (seed & Key2::MASK).reverse_bits() >> (64 - 39) }
+
+    pub fn apply(&mut self, byte: u8) -> u8 {
+        let x = self.x;
+        let y = self.y;
+        self.x = ((x >> 5 ^ x >> 17 ^ x >> 18 ^ x >> 31) & 0xFF | x << 8) & Key2::MASK;
+        self.y = ((y >> 5 ^ y >> 23 ^ y >> 18 ^ y >> 31) & 0xFF | y << 8) & Key2::MASK;
+        byte ^ self.x as u8 ^ self.y as u8
+    }
+
+    pub fn apply_word(&mut self, word: u32) -> u32 {
+        let mut bytes = word.to_le_bytes();
+        for byte in bytes.iter_mut() { *byte = self.apply(*byte) }
+        u32::from_le_bytes(bytes)
+    }
+}
@@ -1,10 +1,12 @@
 mod header;
 mod backup;
+mod key1;
+mod key2;
+mod dldi;
 
 use std::convert::TryInto;
 use std::collections::VecDeque;
 use std::ops::Range;
-use std::path::PathBuf;
 
 use super::{
     HW,
@@ -13,14 +15,32 @@ use super::{
     interrupt_controller::InterruptRequest,
 };
 
-use header::Header;
+pub use header::{Header, Region, UnitCode};
+use key1::Key1;
+use key2::Key2;
+use dldi::SDCard;
+use crate::fault::Fault;
+use crate::nds::Cpu;
+use crate::notifications::Notifier;
+pub use dldi::SdImage;
 
 pub(super) use backup::{Backup, Flash}; // For Firmware
+pub use backup::{MemoryStorage, SaveStorage};
+#[cfg(feature = "host")]
+pub use backup::FileStorage;
 
 pub struct Cartridge {
     chip_id: u32,
     header: Header,
     rom: Vec<u8>,
+    // Encryption
+    key_table: Vec<u8>,
+    key1: Option<Key1>,
+    command_mode: CommandMode,
+    encrypted_secure_area: Vec<u8>,
+    rom_seeds: [u64; 2],
+    key2: Key2,
+    card_key2: Key2,
     // Registers
     pub spicnt: SPICNT,
     romctrl: ROMCTRL,
@@ -28,19 +48,63 @@ pub struct Cartridge {
     cur_game_card_word: u32,
     // Data Transfer
     rom_bytes_left: usize,
+    rom_block_len: usize,
     game_card_words: VecDeque<u32>,
     // Backup
-    backup: Box<dyn Backup>
+    spi_value: u8,
+    backup: Box<dyn Backup>,
+    storage: Box<dyn SaveStorage>,
+    frames_until_flush: Option<usize>,
+    // Homebrew
+    sd_card: Option<SDCard>,
+    notifier: Notifier,
 }
 
+// The ROM, save file and SD card image stay with the running instance
+savestate!(Cartridge { key1, command_mode, rom_seeds, key2, card_key2, spicnt, romctrl, command, cur_game_card_word, rom_bytes_left,
+    rom_block_len, game_card_words, spi_value, backup });
+
 impl Cartridge {
-    pub fn new(rom: Vec<u8>, save_file: PathBuf) -> Self {
-        let header = Header::new(&rom);
-        let backup = Backup::detect_type(&header, save_file);
-        Cartridge {
-            chip_id: 0x000_01FC2u32, // TODO: Actually Calculate
+    // Cycles per bit for each baudrate: 4MHz, 2MHz, 1MHz, 512KHz
+    const SPI_CYCLES_PER_BIT: [usize; 4] = [8, 16, 32, 64];
+
+    // Saves are written once they have been left untouched for a second
+    const FLUSH_DELAY_FRAMES: usize = 60;
+
+    const SECURE_AREA: Range<usize> = 0x4000..0x8000;
+
+    pub fn new(mut rom: Vec<u8>, mut storage: Box<dyn SaveStorage>, bios7: &[u8], direct_boot: bool,
+        notifier: Notifier) -> Result<Self, Fault> {
+        let header = Header::parse(&rom).ok_or_else(|| Fault::InvalidRom(format!("Only {} Bytes Long", rom.len())))?;
+        if direct_boot {
+            let binaries = [(Cpu::ARM9, header.arm9_rom_offset, header.arm9_size),
+                (Cpu::ARM7, header.arm7_rom_offset, header.arm7_size)];
+            for (cpu, offset, size) in binaries.iter() {
+                if *offset as usize + *size as usize > rom.len() {
+                    return Err(Fault::InvalidRom(format!("{:?} Binary is Past the End of the ROM", cpu)))
+                }
+            }
+        }
+        let backup = Backup::detect_type(&header, storage.load());
+        let key_table = bios7.get(Key1::KEY_TABLE_ADDR..Key1::KEY_TABLE_ADDR + Key1::KEY_TABLE_LEN)
+            .map_or_else(|| { notify!(notifier, "ARM7 BIOS is Missing the KEY1 Table"); Vec::new() }, |table| table.to_vec());
+        let encrypted_secure_area = Cartridge::init_secure_area(&header, &key_table, &mut rom);
+        let sd_card = Cartridge::patch_dldi(&header, &mut rom, &notifier);
+        // Both sides start with matching streams when the boot protocol is skipped
+        let rom_seeds = [Key2::seed0(0, Key2::SEED_BYTES[header.encryption_seed as usize & 0x7]), Key2::SEED1];
+        Ok(Cartridge {
+            chip_id: Cartridge::calc_chip_id(rom.len()),
             header,
             rom,
+            // Encryption
+            key_table,
+            key1: None,
+            // Direct boot skips the cartridge boot protocol
+            command_mode: if direct_boot { CommandMode::Main } else { CommandMode::Unencrypted },
+            encrypted_secure_area,
+            rom_seeds,
+            key2: Key2::new(rom_seeds[0], rom_seeds[1]),
+            card_key2: Key2::new(rom_seeds[0], rom_seeds[1]),
             // Registers
             spicnt: SPICNT::new(),
             romctrl: ROMCTRL::new(),
@@ -48,9 +112,45 @@ impl Cartridge {
             cur_game_card_word: 0,
             // Data Transfer
             rom_bytes_left: 0,
+            rom_block_len: 0,
             game_card_words: VecDeque::new(),
+            spi_value: 0,
             backup,
+            storage,
+            frames_until_flush: None,
+            // Homebrew
+            sd_card,
+            notifier,
+        })
+    }
+
+    fn calc_chip_id(rom_len: usize) -> u32 {
+        let size = rom_len.next_power_of_two();
+        // Manufacturer, size in MB - 1 and a flag for 1T-ROMs
+        let size_id = if size >= 0x10_0000 { (size >> 20) - 1 } else { 0x100 - (size >> 17) } & 0xFF;
+        let flags = if size >= 0x800_0000 { 0x80 } else { 0x00 };
+        flags << 24 | (size_id as u32) << 8 | 0xC2
+    }
+
+    // Decrypts the secure area so it can be loaded directly and returns the encrypted copy
+    // that the BIOS reads during the cartridge boot protocol
+    fn init_secure_area(header: &Header, key_table: &[u8], rom: &mut [u8]) -> Vec<u8> {
+        let id_code = u32::from_le_bytes(header.game_code);
+        if key_table.is_empty() || header.arm9_rom_offset as usize != Cartridge::SECURE_AREA.start ||
+            rom.len() < Cartridge::SECURE_AREA.end { return Vec::new() }
+        let secure_area = &mut rom[Cartridge::SECURE_AREA];
+        if Key1::decrypt_secure_area(key_table, id_code, secure_area) { info!("Decrypted Secure Area") }
+        let mut encrypted = secure_area.to_vec();
+        if encrypted[0..8] == [0xFF, 0xDE, 0xFF, 0xE7, 0xFF, 0xDE, 0xFF, 0xE7] {
+            Key1::encrypt_secure_area(key_table, id_code, &mut encrypted);
         }
+        encrypted
+    }
+
+    fn patch_dldi(header: &Header, rom: &mut [u8], notifier: &Notifier) -> Option<SDCard> {
+        let start = header.arm9_rom_offset as usize;
+        let arm9_binary = rom.get_mut(start..start + header.arm9_size as usize)?;
+        if SDCard::patch_driver(arm9_binary, notifier) { info!("Patched DLDI Driver"); Some(SDCard::new()) } else { None }
     }
 
     pub fn run_command(&mut self, scheduler: &mut Scheduler, is_arm9: bool) {
@@ -61,105 +161,176 @@ impl Cartridge {
         //self.romctrl.block_busy = false;
         //self.romctrl.data_block_size = 0x4;
         //self.romctrl.resb_release_reset = false;
-        assert_eq!(self.rom_bytes_left % 4, 0);
         self.rom_bytes_left = match self.romctrl.data_block_size {
             0 => 0,
             7 => 4,
-            _ => { assert!(self.romctrl.data_block_size < 7); 0x100 << self.romctrl.data_block_size },
+            _ => 0x100 << self.romctrl.data_block_size,
         };
+        self.rom_block_len = self.rom_bytes_left;
         self.romctrl.block_busy = true;
         self.romctrl.data_word_ready = false;
+        let mut command = self.command;
+        match (self.command_mode, &self.key1) {
+            (CommandMode::Key1, Some(key1)) => key1.decrypt_command(&mut command),
+            // Encrypted by the reader and decrypted by the cartridge
+            (CommandMode::Main, _) if self.romctrl.key2_encrypt_cmd => for byte in command.iter_mut() {
+                *byte = self.card_key2.apply(self.key2.apply(*byte));
+            },
+            _ => (),
+        }
+        let first_word = self.game_card_words.len();
         let out_words = &mut self.game_card_words;
         let rom = &self.rom;
+        // Trimmed ROMs read as unused space past their end
         let mut copy_rom = |range: Range<usize>| for addr in range.step_by(4) {
-            out_words.push_back(u32::from_le_bytes(rom[addr..addr + 4].try_into().unwrap()));
+            out_words.push_back(rom.get(addr..addr + 4).map_or(0xFFFF_FFFF, |word| u32::from_le_bytes(word.try_into().unwrap())));
         };
-        match self.command[0] {
-            0x00 => {
-                for byte in self.command[1..].iter() { assert_eq!(*byte, 0) }
-                assert!(self.rom_bytes_left < 0x10000); // TODO: Support
-                copy_rom(0..self.rom_bytes_left);
-            },
-            0xB7 => {
-                for byte in self.command[5..].iter() { assert_eq!(*byte, 0) }
-                let addr = u32::from_be_bytes(self.command[1..=4].try_into().unwrap()) as usize;
-                assert!(addr + self.rom_bytes_left < self.rom.len()); // TODO: Handle mirroring later
-                let addr = if addr < 0x8000 { 0x8000 + (addr & 0x1FFF) } else { addr };
-                let transfer_len = self.rom_bytes_left;
-                if addr & 0x1000 != (addr + transfer_len) & 0x1000 { // Crosess 4K boundary
-                    let block4k_start = addr & !0xFFF;
-                    let block4k_end = block4k_start + 0x1000;
-                    let extra_len = transfer_len - (block4k_end - addr);
-                    copy_rom(addr..block4k_end);
-                    copy_rom(block4k_start..block4k_start + extra_len);
-                } else {
-                    copy_rom(addr..addr + transfer_len);
-                }
-            },
-            0xB8 => {
-                for byte in self.command[1..].iter() { assert_eq!(*byte, 0) }
-                // Chip ID is repeated
-                for _ in 0..self.rom_bytes_left / 4 {
-                    self.game_card_words.push_back(self.chip_id);
-                }
-            },
-            0x90 => {
+        let rom_bytes_left = self.rom_bytes_left;
+        let sd_card = &mut self.sd_card;
+        let backup_words = match self.command_mode {
+            CommandMode::Main => self.backup.rom_command(&command, rom_bytes_left)
+                .or_else(|| sd_card.as_mut()?.rom_command(&command, rom_bytes_left)),
+            _ => None,
+        };
+        match (self.command_mode, backup_words) {
+            (_, Some(words)) => self.game_card_words.extend(words),
+            (CommandMode::Unencrypted, None) => match command[0] {
+                0x00 => copy_rom(0..self.rom_bytes_left),
                 // Chip ID is repeated
-                for _ in 0..self.rom_bytes_left / 4 {
-                    self.game_card_words.push_back(self.chip_id);
-                }
-            }
-            0x9F => {
+                0x90 => self.push_words(self.chip_id),
                 // Endless stream of HIGH-Z bytes
-                for byte in self.command[1..].iter() { assert_eq!(*byte, 0) }
-                for _ in 0..self.rom_bytes_left / 4 {
-                    self.game_card_words.push_back(0xFFFF_FFFF);
-                }
+                0x9F => self.push_words(0xFFFF_FFFF),
+                0x3C => {
+                    if self.key_table.is_empty() { warn!("Unable to Activate KEY1 without the ARM7 BIOS Key Table") }
+                    else {
+                        let id_code = u32::from_le_bytes(self.header.game_code);
+                        self.key1 = Some(Key1::new(&self.key_table, id_code, 2, 8));
+                        self.command_mode = CommandMode::Key1;
+                    }
+                    self.push_words(0);
+                },
+                _ => {
+                    warn!("Unimplemented Unencrypted Cartridge Command: {:X}", command[0]);
+                    self.push_words(0);
+                },
             },
-            _ => {
-                warn!("Unimplemented Cartridge Command: {:X}", self.command[0]);
-                for _ in 0..self.rom_bytes_left / 4 {
-                    self.game_card_words.push_back(0);
-                }
+            (CommandMode::Key1, None) => match command[0] >> 4 {
+                0x1 => self.push_words(self.chip_id),
+                0x2 => {
+                    let start = (((command[2] & 0xF0) as usize) << 8).wrapping_sub(Cartridge::SECURE_AREA.start);
+                    let secure_area = &self.encrypted_secure_area;
+                    for offset in (start..start.wrapping_add(self.rom_bytes_left)).step_by(4) {
+                        self.game_card_words.push_back(secure_area.get(offset..offset + 4)
+                            .map_or(0, |word| u32::from_le_bytes(word.try_into().unwrap())));
+                    }
+                },
+                0x4 => {
+                    let value = (u64::from_be_bytes(command) >> 20) as u32 & 0xFF_FFFF;
+                    let seed_byte = Key2::SEED_BYTES[self.header.encryption_seed as usize & 0x7];
+                    self.card_key2 = Key2::new(Key2::seed0(value, seed_byte), Key2::SEED1);
+                    self.push_words(0);
+                },
+                0xA => {
+                    self.command_mode = CommandMode::Main;
+                    self.push_words(0);
+                },
+                _ => {
+                    warn!("Unimplemented KEY1 Cartridge Command: {:X}", command[0]);
+                    self.push_words(0);
+                },
+            },
+            (CommandMode::Main, None) => match command[0] {
+                0xB7 => {
+                    // The ROM chip is mirrored across the address space
+                    let addr = u32::from_be_bytes(command[1..=4].try_into().unwrap()) as usize
+                        & (self.rom.len().next_power_of_two() - 1);
+                    let addr = if addr < 0x8000 { 0x8000 + (addr & 0x1FFF) } else { addr };
+                    let transfer_len = self.rom_bytes_left;
+                    if addr & 0x1000 != (addr + transfer_len) & 0x1000 { // Crosess 4K boundary
+                        let block4k_start = addr & !0xFFF;
+                        let block4k_end = block4k_start + 0x1000;
+                        let extra_len = transfer_len - (block4k_end - addr);
+                        copy_rom(addr..block4k_end);
+                        copy_rom(block4k_start..block4k_start + extra_len);
+                    } else {
+                        copy_rom(addr..addr + transfer_len);
+                    }
+                },
+                // Chip ID is repeated
+                0xB8 => self.push_words(self.chip_id),
+                _ => {
+                    warn!("Unimplemented Cartridge Command: {:X}", command[0]);
+                    self.push_words(0);
+                },
             },
         };
 
+        if self.romctrl.key2_encrypt_data {
+            // Encrypted by the cartridge and decrypted by the reader
+            for word in self.game_card_words.iter_mut().skip(first_word) {
+                *word = self.key2.apply_word(self.card_key2.apply_word(*word));
+            }
+        }
+
         // TODO: Take into account WR bit
+        // 8 command bytes followed by the leading gap and the gap before the first 0x200 byte block
+        let command_clks = 8 + self.romctrl.key1_gap1_len as usize;
         if self.rom_bytes_left == 0 {
-            // 8 command bytes transferred
             scheduler.schedule(
                 Event::ROMBlockEnded(is_arm9),
                 HW::on_rom_block_ended,
-                self.transfer_byte_time() * 8
+                self.transfer_byte_time() * command_clks
             );
         } else {
-            // 8 command bytes + 4 bytes for word
+            // 4 bytes for word
             scheduler.schedule(
                 Event::ROMWordTransfered,
                 HW::on_rom_word_transfered,
-                self.transfer_byte_time() * (8 + 4)
+                self.transfer_byte_time() * (command_clks + self.romctrl.key1_gap2_len as usize + 4)
             );
         }
     }
 
+    fn push_words(&mut self, word: u32) {
+        for _ in 0..self.rom_bytes_left / 4 {
+            self.game_card_words.push_back(word);
+        }
+    }
+
     pub fn read_gamecard(&mut self, scheduler: &mut Scheduler, is_arm9: bool, has_access: bool) -> u32 {
         if !has_access { warn!("No Read Access from Game Card Command"); return 0 }
+        self.next_word(scheduler, is_arm9);
+        self.cur_game_card_word
+    }
+
+    // Used by the save memory of NAND cartridges and the SD card of homebrew
+    pub fn write_gamecard(&mut self, scheduler: &mut Scheduler, is_arm9: bool, has_access: bool, value: u32) {
+        if !has_access { warn!("No Write Access to Game Card Command"); return }
+        if !self.romctrl.wr || !self.romctrl.data_word_ready { return }
+        self.backup.rom_write(value);
+        if let Some(sd_card) = &mut self.sd_card { sd_card.rom_write(value) }
+        self.next_word(scheduler, is_arm9);
+    }
+
+    fn next_word(&mut self, scheduler: &mut Scheduler, is_arm9: bool) {
         if self.romctrl.data_word_ready {
             self.romctrl.data_word_ready = false;
             self.rom_bytes_left -= 4;
 
             if self.rom_bytes_left > 0 {
-                // 1 word (4 bytes) transferred
+                // 1 word (4 bytes) transferred with a gap before every 0x200 byte block
+                let gap_clks = if (self.rom_block_len - self.rom_bytes_left) & 0x1FF == 0 {
+                    self.romctrl.key1_gap2_len as usize
+                } else { 0 };
                 scheduler.schedule(
                     Event::ROMWordTransfered,
                     HW::on_rom_word_transfered,
-                    self.transfer_byte_time() * 4
+                    self.transfer_byte_time() * (gap_clks + 4)
                 );
             } else {
                 scheduler.run_now(Event::ROMBlockEnded(is_arm9), HW::on_rom_block_ended);
             }
         }
-        self.cur_game_card_word
     }
 
     pub fn read_spi_data(&self, has_access: bool) -> u8 {
@@ -169,9 +340,14 @@ impl Cartridge {
 
     pub fn read_romctrl(&self, has_access: bool, byte: usize) -> u8 { self.romctrl.read(has_access, byte) }
 
-    pub fn write_spi_data(&mut self, has_access: bool, value: u8) {
+    pub fn write_spi_data(&mut self, scheduler: &mut Scheduler, has_access: bool, value: u8) {
         if !has_access { warn!("No Write Access to SPI DATA"); return }
-        self.backup.write(self.spicnt.hold, value);
+        if !self.spicnt.slot_enable || !self.spicnt.slot_mode { return }
+        if self.spicnt.busy { warn!("AUX SPI Transfer Started while Busy") }
+        self.spicnt.busy = true;
+        self.spi_value = value;
+        scheduler.schedule(Event::AUXSPITransferFinished, HW::on_aux_spi_transfer_finished,
+            8 * Cartridge::SPI_CYCLES_PER_BIT[self.spicnt.baudrate as usize]);
     }
 
     pub fn write_command(&mut self, has_access: bool, byte: usize, value: u8) {
@@ -181,25 +357,88 @@ impl Cartridge {
     }
 
     pub fn write_romctrl(&mut self, scheduler: &mut Scheduler, is_arm9: bool, has_access: bool, byte: usize, value: u8) {
-        if self.romctrl.write(has_access, byte, value) { self.run_command(scheduler, is_arm9) }
+        let start = self.romctrl.write(has_access, byte, value);
+        if self.romctrl.key2_apply_seed {
+            self.romctrl.key2_apply_seed = false;
+            self.key2 = Key2::new(self.rom_seeds[0], self.rom_seeds[1]);
+        }
+        if start { self.run_command(scheduler, is_arm9) }
+    }
+
+    pub fn write_rom_seed(&mut self, has_access: bool, byte: usize, value: u8) {
+        if !has_access { warn!("No Write Access to ROM SEED"); return }
+        let (seed, shift) = match byte {
+            0 ..= 3 => (0, 8 * byte),
+            4 ..= 7 => (1, 8 * (byte - 4)),
+            8 => (0, 32),
+            10 => (1, 32),
+            9 | 11 => return,
+            _ => unreachable!(),
+        };
+        // Only the lower 7 bits of the upper halves are used
+        let value = if shift == 32 { value & 0x7F } else { value } as u64;
+        self.rom_seeds[seed] = self.rom_seeds[seed] & !(0xFF << shift) | value << shift;
+    }
+
+    pub fn set_sd_image(&mut self, image: Option<Box<dyn SdImage>>) {
+        match &mut self.sd_card {
+            Some(sd_card) => sd_card.set_image(image),
+            None => if image.is_some() { notify!(self.notifier, "ROM has no DLDI Driver to Access the SD Card") },
+        }
     }
 
     pub fn chip_id(&self) -> u32 { self.chip_id }
     pub fn rom(&self) -> &Vec<u8> { &self.rom }
     pub fn header(&self) -> &Header { &self.header }
-    pub fn save_backup(&mut self) { self.backup.save() }
 
+    // Returns whether the game wrote to the backup since the last update
+    pub fn update_backup(&mut self) -> bool {
+        if self.backup.dirty() {
+            self.frames_until_flush = Some(Cartridge::FLUSH_DELAY_FRAMES);
+            return true
+        } else if let Some(frames) = self.frames_until_flush {
+            if frames == 0 { self.flush_backup() } else { self.frames_until_flush = Some(frames - 1) }
+        }
+        false
+    }
+
+    pub fn flush_backup(&mut self) {
+        if self.backup.dirty() || self.frames_until_flush.is_some() {
+            self.frames_until_flush = None;
+            let mem = match self.backup.mem() {
+                Some(mem) => mem,
+                None => return,
+            };
+            if let Err(err) = self.storage.flush(mem) {
+                notify!(self.notifier, "Unable to Save to File: {}!", err);
+            }
+        }
+    }
+
+    // Cycles per byte at 4.2MHz or 6.7MHz
     fn transfer_byte_time(&self) -> usize { if self.romctrl.transfer_clk_rate { 8 } else { 5 } }
 }
 
+impl Drop for Cartridge {
+    fn drop(&mut self) {
+        self.flush_backup();
+    }
+}
+
 impl HW {
-    fn on_rom_word_transfered(&mut self, _event: Event) {
+    pub(super) fn on_aux_spi_transfer_finished(&mut self, _event: Event) {
+        let cartridge = &mut self.cartridge;
+        cartridge.spicnt.busy = false;
+        cartridge.backup.write(cartridge.spicnt.hold, cartridge.spi_value);
+    }
+
+    pub(super) fn on_rom_word_transfered(&mut self, _event: Event) {
         self.cartridge.cur_game_card_word = self.cartridge.game_card_words.pop_front().unwrap();
         self.cartridge.romctrl.data_word_ready = true;
         self.run_dmas(DMAOccasion::DSCartridge);
     }
 
-    fn on_rom_block_ended(&mut self, event: Event) {
+    pub(super) fn on_rom_block_ended(&mut self, event: Event) {
         let is_arm9 = match event {
             Event::ROMBlockEnded(is_arm9) => is_arm9,
             _ => unreachable!(),
@@ -211,6 +450,15 @@ impl HW {
     }
 }
 
+#[derive(Clone, Copy)]
+enum CommandMode {
+    Unencrypted,
+    Key1,
+    Main,
+}
+
+savestate_enum!(CommandMode { Unencrypted, Key1, Main });
+
 pub struct SPICNT {
     // Registers
     baudrate: u8,
@@ -221,6 +469,8 @@ pub struct SPICNT {
     slot_enable: bool,
 }
 
+savestate!(SPICNT { baudrate, hold, busy, slot_mode, transfer_ready_irq, slot_enable });
+
 impl SPICNT {
     pub fn new() -> Self {
         SPICNT {
@@ -250,7 +500,6 @@ impl SPICNT {
             0 => {
                 self.baudrate = value & 0x3;
                 self.hold = value >> 6 & 0x1 != 0;
-                self.busy = value >> 7 & 0x1 != 0;
             },
             1 => {
                 self.slot_mode = value >> 5 & 0x1 != 0;
@@ -277,6 +526,9 @@ pub struct ROMCTRL {
     block_busy: bool,
 }
 
+savestate!(ROMCTRL { key1_gap1_len, key2_encrypt_data, key2_apply_seed, key1_gap2_len, key2_encrypt_cmd, data_word_ready, data_block_size,
+    transfer_clk_rate, key1_gap_clks, resb_release_reset, wr, block_busy });
+
 impl ROMCTRL {
     pub fn new() -> Self {
         ROMCTRL {
@@ -315,7 +567,7 @@ impl ROMCTRL {
         match byte {
             0 => self.key1_gap1_len = self.key1_gap1_len & !0xFF | value as u16,
             1 => {
-                self.key1_gap1_len = self.key1_gap1_len & !0x1F00 | (value as u16 & 0x1F) << 4;
+                self.key1_gap1_len = self.key1_gap1_len & !0x1F00 | (value as u16 & 0x1F) << 8;
                 self.key2_encrypt_data = value >> 5 & 0x1 != 0;
                 self.key2_apply_seed = value >> 7 & 0x1 != 0;
             },
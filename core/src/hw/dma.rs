@@ -1,3 +1,5 @@
+use crate::fault::Fault;
+use crate::num;
 use super::{
     HW,
     mem::{AccessType, IORegister, MemoryValue},
@@ -10,6 +12,8 @@ pub struct DMAController {
     pub by_type: [Vec<usize>; DMAOccasion::num()],
 }
 
+savestate!(DMAController { channels, by_type });
+
 impl DMAController {
     pub fn new(is_nds9: bool) -> Self {
         let mut controller = DMAController {
@@ -46,6 +50,11 @@ impl DMAController {
                 self.by_type[new_start_timing as usize].push(channel);
             }
         }
+        if prev_enable && !new_enable {
+            // Stopping a channel mid transfer aborts it
+            self.channels[channel].active = false;
+            scheduler.remove(Event::DMA(self.channels[channel].is_nds9, channel));
+        }
         if !prev_enable && new_enable {
             let channel = &mut self.channels[channel];
             channel.latch();
@@ -62,6 +71,11 @@ impl DMAController {
         }
     }
 
+    // GBA start timings replace the DS ones on the ARM7
+    pub fn enter_gba_mode(&mut self) {
+        for channel in self.channels.iter_mut() { channel.cnt.gba_mode = true }
+    }
+
     pub fn disable(&mut self, channel: usize) {
         let vec = &mut self.by_type[self.channels[channel].cnt.start_timing as usize];
         let pos = vec.iter().position(|i| *i == channel);
@@ -84,52 +98,108 @@ impl std::ops::IndexMut<usize> for DMAController {
 }
 
 impl HW {
-    fn on_dma(&mut self, event: Event) {
+    const DMA_FILL_START: u32 = 0x0400_00E0;
+    // Minimum number of cycles a DMA runs for before other events are handled
+    const DMA_MIN_CHUNK_CYCLES: usize = 32;
+
+    pub(super) fn on_dma(&mut self, event: Event) {
         let (is_nds9, num) = match event {
             Event::DMA(is_nds9, num) => (is_nds9, num),
             _ => unreachable!(),
         };
-        if self.dmas[is_nds9 as usize][num].cnt.transfer_32 {
+        let i = is_nds9 as usize;
+        if !self.dmas[i][num].active { self.start_dma(is_nds9, num) }
+        // Lower numbered channels have priority and resume this one once they finish
+        if (0..num).any(|other| self.dmas[i][other].active) { return }
+        if self.dmas[i][num].words_left == 0 { self.finish_dma(is_nds9, num); return }
+
+        let budget = self.cycles_until_event().max(HW::DMA_MIN_CHUNK_CYCLES);
+        let cycles = self.transfer_dma(is_nds9, num, budget);
+        self.scheduler.schedule(Event::DMA(is_nds9, num), HW::on_dma, cycles);
+    }
+
+    fn transfer_dma(&mut self, is_nds9: bool, num: usize, budget: usize) -> usize {
+        if self.dmas[is_nds9 as usize][num].transfers_32() {
             if is_nds9 {
-                self.run_dma::<_, _, _, _, true>(num, &HW::arm9_get_access_time::<u32>,
-                    &HW::arm9_read::<u32>, &HW::arm9_write::<u32>);
+                self.run_dma::<_, _, _, _, true>(num, budget, &HW::arm9_get_access_time::<u32>,
+                    &HW::arm9_dma_read::<u32>, &HW::arm9_write::<u32>)
             } else {
-                self.run_dma::<_, _, _, _, false>(num, &HW::arm7_get_access_time::<u32>,
-                    &HW::arm7_read::<u32>, &HW::arm7_write::<u32>);
+                self.run_dma::<_, _, _, _, false>(num, budget, &HW::arm7_get_access_time::<u32>,
+                    &HW::arm7_read::<u32>, &HW::arm7_write::<u32>)
             }
         } else {
             if is_nds9 {
-                self.run_dma::<_, _, _, _, true>(num, &HW::arm9_get_access_time::<u16>,
-                    &HW::arm9_read::<u16>, &HW::arm9_write::<u16>);
+                self.run_dma::<_, _, _, _, true>(num, budget, &HW::arm9_get_access_time::<u16>,
+                    &HW::arm9_dma_read::<u16>, &HW::arm9_write::<u16>)
             } else {
-                self.run_dma::<_, _, _, _, false>(num, &HW::arm7_get_access_time::<u16>,
-                    &HW::arm7_read::<u16>, &HW::arm7_write::<u16>);
+                self.run_dma::<_, _, _, _, false>(num, budget, &HW::arm7_get_access_time::<u16>,
+                    &HW::arm7_read::<u16>, &HW::arm7_write::<u16>)
             }
         }
     }
 
-    fn run_dma<A, R, W, T: MemoryValue, const IS_NDS9: bool>(&mut self, num: usize, access_time_fn: A, read_fn: R, write_fn: W)
+    fn arm9_dma_read<T: MemoryValue>(&mut self, addr: u32) -> T {
+        if (HW::DMA_FILL_START..HW::DMA_FILL_START + 0x10).contains(&addr) {
+            // Fill data is read directly, which allows filling memory using a fixed source address
+            let value = self.dma_fill[(addr - HW::DMA_FILL_START) as usize / 4] >> (8 * (addr & 0x3));
+            num::cast::<u32, T>(value & (u32::MAX >> (32 - 8 * std::mem::size_of::<T>()))).unwrap()
+        } else { self.arm9_read(addr) }
+    }
+
+    fn start_dma(&mut self, is_nds9: bool, num: usize) {
+        let channel = &mut self.dmas[is_nds9 as usize][num];
+        // Geometry Command FIFO DMAs transfer in blocks of 112 words each time they're triggered
+        let count = if channel.cnt.start_timing == DMAOccasion::GeometryCommandFIFO {
+            channel.count_latch.min(DMAChannel::GXFIFO_BLOCK_LEN)
+        } else { channel.count_latch };
+        channel.active = true;
+        channel.first = true;
+        channel.words_left = count;
+        channel.block_len = count;
+        info!("Running {:?} ARM{} DMA{}: Writing {} values to {:08X} from {:08X}, size: {}", channel.cnt.start_timing,
+        if is_nds9 { 9 } else { 7 }, num, count, channel.dad_latch, channel.sad_latch,
+        if channel.cnt.transfer_32 { 32 } else { 16 });
+    }
+
+    fn run_dma<A, R, W, T: MemoryValue, const IS_NDS9: bool>(&mut self, num: usize, budget: usize,
+        access_time_fn: A, read_fn: R, write_fn: W) -> usize
         where A: Fn(&mut HW, AccessType, u32) -> usize, R: Fn(&mut HW, u32) -> T, W: Fn(&mut HW, u32, T) {
         let i = IS_NDS9 as usize;
-        let channel = &mut self.dmas[i][num];
-        let count = channel.count_latch;
-        let mut src_addr = channel.sad_latch;
-        let mut dest_addr = channel.dad_latch;
+        let channel = &self.dmas[i][num];
         let src_addr_ctrl = channel.cnt.src_addr_ctrl;
-        let dest_addr_ctrl = channel.cnt.dest_addr_ctrl;
-        let transfer_32 = channel.cnt.transfer_32;
-        let irq = channel.cnt.irq;
-        channel.cnt.enable = channel.cnt.start_timing != DMAOccasion::Immediate && channel.cnt.repeat;
-        info!("Running {:?} ARM{} DMA{}: Writing {} values to {:08X} from {:08X}, size: {}", channel.cnt.start_timing,
-        if IS_NDS9 { 9 } else { 7 }, num, count, dest_addr, src_addr, if transfer_32 { 32 } else { 16 });
-
-        let (addr_change, addr_mask) = if transfer_32 { (4, 0x3) } else { (2, 0x1) };
-        src_addr &= !addr_mask;
-        dest_addr &= !addr_mask;
-        let mut first = true;
-        let original_dest_addr = dest_addr;
-        let mut cycles_passed = 0;
-        for _ in 0..count {
+        if src_addr_ctrl == 3 {
+            let cpu = if IS_NDS9 { 9 } else { 7 };
+            self.faults.raise(Fault::InvalidValue(format!("ARM{} DMA{} Source Address Control 3", cpu, num)));
+            return budget
+        }
+        // Sound FIFO DMAs always write to the FIFO
+        let dest_addr_ctrl = if channel.cnt.start_timing == DMAOccasion::SoundFIFO { 2 } else { channel.cnt.dest_addr_ctrl };
+        let (addr_change, addr_mask) = if channel.transfers_32() { (4, 0x3) } else { (2, 0x1) };
+        let mut src_addr = channel.sad_latch & !addr_mask;
+        let mut dest_addr = channel.dad_latch & !addr_mask;
+        let mut words_left = channel.words_left;
+        let mut first = channel.first;
+        let mut cycles_passed = if first { 2 } else { 0 }; // 2 I cycles
+        let incrementing = src_addr_ctrl == 0 && (dest_addr_ctrl == 0 || dest_addr_ctrl == 3);
+        while words_left > 0 && cycles_passed < budget {
+            if incrementing {
+                let word_cycles = access_time_fn(self, AccessType::S, src_addr) +
+                    access_time_fn(self, AccessType::S, dest_addr);
+                let max_words = (((budget - cycles_passed) / word_cycles) as u32).clamp(1, words_left);
+                let words = self.copy_ram(IS_NDS9, src_addr, dest_addr, max_words * addr_change) / addr_change;
+                if words > 0 {
+                    if first {
+                        cycles_passed += access_time_fn(self, AccessType::N, src_addr) +
+                            access_time_fn(self, AccessType::N, dest_addr) - word_cycles;
+                    }
+                    cycles_passed += words as usize * word_cycles;
+                    src_addr = src_addr.wrapping_add(words * addr_change);
+                    dest_addr = dest_addr.wrapping_add(words * addr_change);
+                    words_left -= words;
+                    first = false;
+                    continue
+                }
+            }
             let cycle_type = if first { AccessType::N } else { AccessType::S };
             cycles_passed += access_time_fn(self, cycle_type, src_addr);
             cycles_passed += access_time_fn(self, cycle_type, dest_addr);
@@ -140,7 +210,7 @@ impl HW {
                 0 => src_addr.wrapping_add(addr_change),
                 1 => src_addr.wrapping_sub(addr_change),
                 2 => src_addr,
-                _ => panic!("Invalid DMA Source Address Control!"),
+                _ => unreachable!(),
             };
             dest_addr = match dest_addr_ctrl {
                 0 | 3 => dest_addr.wrapping_add(addr_change),
@@ -148,53 +218,115 @@ impl HW {
                 2 => dest_addr,
                 _ => unreachable!(),
             };
+            words_left -= 1;
             first = false;
         }
         let channel = &mut self.dmas[i][num];
         channel.sad_latch = src_addr;
         channel.dad_latch = dest_addr;
-        // if channel.cnt.enable { channel.count_latch = channel.count.count as u32 } // Only reload Count - TODO: Why?
-        if dest_addr_ctrl == 3 { channel.dad_latch = original_dest_addr }
-        cycles_passed += 2; // 2 I cycles
+        channel.words_left = words_left;
+        channel.first = first;
+        // TODO: Don't halt CPU if PC is in TCM
+        cycles_passed
+    }
+
+    // Copies directly between RAM regions, returning the number of bytes copied
+    fn copy_ram(&mut self, is_nds9: bool, src_addr: u32, dest_addr: u32, max_len: u32) -> u32 {
+        let region = |hw: &HW, addr| if is_nds9 { hw.arm9_ram_region(addr) } else { hw.arm7_ram_region(addr) };
+        let (src_region, src_offset, src_len) = match region(self, src_addr) { Some(region) => region, None => return 0 };
+        let (dest_region, dest_offset, dest_len) = match region(self, dest_addr) { Some(region) => region, None => return 0 };
+        let len = (max_len as usize).min(src_len).min(dest_len);
+        if src_region == dest_region {
+            // Copying forward one unit at a time repeats data when the destination overlaps after the source
+            if dest_offset > src_offset && dest_offset < src_offset + len { return 0 }
+            self.ram_region_mut(src_region).copy_within(src_offset..src_offset + len, dest_offset);
+        } else {
+            let src = std::mem::take(self.ram_region_mut(src_region));
+            self.ram_region_mut(dest_region)[dest_offset..dest_offset + len]
+                .copy_from_slice(&src[src_offset..src_offset + len]);
+            *self.ram_region_mut(src_region) = src;
+        }
+        len as u32
+    }
 
+    fn finish_dma(&mut self, is_nds9: bool, num: usize) {
+        let i = is_nds9 as usize;
+        let channel = &mut self.dmas[i][num];
+        channel.active = false;
+        let finished = channel.block_len == channel.count_latch;
+        if finished {
+            channel.cnt.enable = channel.cnt.start_timing != DMAOccasion::Immediate && channel.cnt.repeat;
+            if channel.cnt.enable && channel.cnt.start_timing == DMAOccasion::GeometryCommandFIFO { channel.latch_count() }
+        } else { channel.count_latch -= channel.block_len }
+        // if channel.cnt.enable { channel.count_latch = channel.count.count as u32 } // Only reload Count - TODO: Why?
+        if channel.cnt.dest_addr_ctrl == 3 { channel.reload_dest() }
+        let irq = channel.cnt.irq && finished;
+        let occasion = channel.cnt.start_timing;
         if !channel.cnt.enable { self.dmas[i].disable(num) }
 
-        // TODO: Don't halt CPU if PC is in TCM
-        self.clock(cycles_passed);
-        
-        if irq {
-            let interrupt = match num {
-                0 => InterruptRequest::DMA0,
-                1 => InterruptRequest::DMA1,
-                2 => InterruptRequest::DMA2,
-                3 => InterruptRequest::DMA3,
-                _ => unreachable!(),
-            };
-            self.interrupts[0].request |= interrupt;
-            self.interrupts[1].request |= interrupt;
+        // Only the CPU that owns the channel is interrupted
+        if irq { self.interrupts[i].request |= self.dmas[i][num].interrupt() }
+
+        // Resume channels that were interrupted by this one
+        for other in num + 1..4 {
+            if self.dmas[i][other].active { self.scheduler.run_now(Event::DMA(is_nds9, other), HW::on_dma) }
+        }
+        match occasion {
+            DMAOccasion::GeometryCommandFIFO => self.check_geometry_command_fifo(),
+            DMAOccasion::MainMemoryDisplay => self.check_main_memory_display_dmas(),
+            _ => (),
         }
     }
 
-    fn check_geometry_command_fifo_handler(&mut self, _event: Event) {
+    pub fn dma_active(&self, is_nds9: bool) -> bool {
+        (0..4).any(|num| self.dmas[is_nds9 as usize][num].active)
+    }
+
+    pub(super) fn flush_dmas(&mut self, occasion: DMAOccasion) {
+        for i in 0..2 {
+            for num in 0..4 {
+                let channel = &self.dmas[i][num];
+                if !channel.active || channel.cnt.start_timing != occasion { continue }
+                self.scheduler.remove(Event::DMA(i == 1, num));
+                self.transfer_dma(i == 1, num, usize::MAX);
+                self.finish_dma(i == 1, num);
+            }
+        }
+    }
+
+    pub(super) fn check_geometry_command_fifo_handler(&mut self, _event: Event) {
         self.check_geometry_command_fifo();
     }
 
     pub fn check_geometry_command_fifo(&mut self) {
-        if self.gpu.engine3d.should_run_fifo() {
-            self.run_dmas(DMAOccasion::GeometryCommandFIFO);
+        if self.gpu.engine3d.should_run_fifo() { self.run_dmas(DMAOccasion::GeometryCommandFIFO) }
+    }
+
+    pub fn check_main_memory_display_dmas(&mut self) {
+        if self.gpu.engine_a.request_main_mem_data() { self.run_dmas(DMAOccasion::MainMemoryDisplay) }
+    }
+
+    pub(super) fn run_sound_fifo_dmas(&mut self, fifo_addr: u32) {
+        for num in self.dmas[0].by_type[DMAOccasion::SoundFIFO as usize].iter() {
+            let channel = &self.dmas[0][*num];
+            if channel.dad_latch == fifo_addr && !channel.active {
+                self.scheduler.run_now(Event::DMA(false, *num), HW::on_dma);
+            }
         }
     }
 
     pub fn run_dmas(&mut self, occasion: DMAOccasion) {
-        self.in_dma = true;
-        let mut events = Vec::new();
-        for dma in self.dmas.iter() {
-            for num in dma.by_type[occasion as usize].iter() {
-                events.push(Event::DMA(true, *num));
+        // Only the CPU with access to the DS slot receives cartridge DMA requests
+        let controllers = match occasion {
+            DMAOccasion::DSCartridge => if self.exmem.nds_arm7_access() { 0..1 } else { 1..2 },
+            _ => 0..2,
+        };
+        for i in controllers {
+            for num in self.dmas[i].by_type[occasion as usize].iter() {
+                // Channels already running keep their current schedule
+                if !self.dmas[i][*num].active { self.scheduler.run_now(Event::DMA(i == 1, *num), HW::on_dma) }
             }
         }
-        for event in events.drain(..) { self.on_dma(event) }
-        self.in_dma = false;
     }
 }
 
@@ -204,13 +336,21 @@ pub struct DMAChannel {
     pub sad_latch: u32,
     pub dad_latch: u32,
     pub count_latch: u32,
+    active: bool,
+    first: bool,
+    words_left: u32,
+    block_len: u32,
 
     pub cnt: DMACNT,
     sad: Address,
     dad: Address,
 }
 
+savestate!(DMAChannel { sad_latch, dad_latch, count_latch, active, first, words_left, block_len, cnt, sad, dad });
+
 impl DMAChannel {
+    const GXFIFO_BLOCK_LEN: u32 = 112;
+
     pub fn new(is_nds9: bool, num: usize) -> Self {
         DMAChannel {
             num,
@@ -218,6 +358,10 @@ impl DMAChannel {
             sad_latch: 0,
             dad_latch: 0,
             count_latch: 0,
+            active: false,
+            first: false,
+            words_left: 0,
+            block_len: 0,
 
             cnt: DMACNT::new(is_nds9, num),
             sad: Address::new(if is_nds9 { 0x0FFF_FFFF } else { if num == 0 { 0x07FF_FFFF } else { 0x0FFF_FFFF} }),
@@ -227,9 +371,32 @@ impl DMAChannel {
 
     pub fn latch(&mut self) {
         self.sad_latch = self.sad.addr & self.sad.mask;
-        self.dad_latch = self.dad.addr & self.sad.mask;
+        self.dad_latch = self.dad.addr & self.dad.mask;
+        self.latch_count();
+    }
+
+    fn interrupt(&self) -> InterruptRequest {
+        match self.num {
+            0 => InterruptRequest::DMA0,
+            1 => InterruptRequest::DMA1,
+            2 => InterruptRequest::DMA2,
+            3 => InterruptRequest::DMA3,
+            _ => unreachable!(),
+        }
+    }
+
+    fn reload_dest(&mut self) {
+        self.dad_latch = self.dad.addr & self.dad.mask;
+    }
+
+    pub fn latch_count(&mut self) {
         let count = self.cnt.count & self.cnt.count_mask;
-        self.count_latch = if count == 0 { self.cnt.count_mask + 1 } else { count };
+        self.count_latch = if self.cnt.start_timing == DMAOccasion::SoundFIFO { 4 }
+        else if count == 0 { self.cnt.count_mask + 1 } else { count };
+    }
+
+    fn transfers_32(&self) -> bool {
+        self.cnt.transfer_32 || self.cnt.start_timing == DMAOccasion::SoundFIFO
     }
 }
 
@@ -282,19 +449,32 @@ pub enum DMAOccasion {
     GBACartridge = 6,
     GeometryCommandFIFO = 7,
     WirelessInterrupt = 8,
+    SoundFIFO = 9,
 }
 
+savestate_enum!(DMAOccasion { Immediate, VBlank, HBlank, StartOfDisplay, MainMemoryDisplay, DSCartridge, GBACartridge,
+    GeometryCommandFIFO, WirelessInterrupt, SoundFIFO });
+
 impl DMAOccasion {
-    const fn num() -> usize { 9 }
+    const fn num() -> usize { 10 }
 
-    fn get(is_nds9: bool, dma_num: usize, start_timing: u8) -> Self {
-        if is_nds9 {
+    fn get(is_nds9: bool, gba_mode: bool, dma_num: usize, start_timing: u8) -> Self {
+        if gba_mode {
+            match start_timing & 0x3 {
+                0 => DMAOccasion::Immediate,
+                1 => DMAOccasion::VBlank,
+                2 => DMAOccasion::HBlank,
+                3 if dma_num == 3 => { warn!("GBA Video Capture DMA not implemented!"); DMAOccasion::GBACartridge },
+                3 => DMAOccasion::SoundFIFO,
+                _ => unreachable!(),
+            }
+        } else if is_nds9 {
             match start_timing {
                 0 => DMAOccasion::Immediate,
                 1 => DMAOccasion::VBlank,
                 2 => DMAOccasion::HBlank,
-                3 => { warn!("ARM9 Start Of Display DMA not implemented!"); DMAOccasion::StartOfDisplay },
-                4 => { warn!("ARM9 Main Memory Display DMA not implemented!"); DMAOccasion::MainMemoryDisplay },
+                3 => DMAOccasion::StartOfDisplay,
+                4 => DMAOccasion::MainMemoryDisplay,
                 5 => DMAOccasion::DSCartridge,
                 6 => { warn!("ARM9 GBA Cartridge DMA not implemented!"); DMAOccasion::GBACartridge },
                 7 => DMAOccasion::GeometryCommandFIFO,
@@ -303,9 +483,9 @@ impl DMAOccasion {
         } else {
             match start_timing & 0x3 {
                 0 => DMAOccasion::Immediate,
-                1 => { warn!("ARM7 VBlank DMA not implemented!"); DMAOccasion::VBlank },
+                1 => DMAOccasion::VBlank,
                 2 => DMAOccasion::DSCartridge,
-                3 if dma_num % 2 == 0 => { warn!("ARM7 WirelessInterrupt DMA not implemented!"); DMAOccasion::WirelessInterrupt },
+                3 if dma_num.is_multiple_of(2) => DMAOccasion::WirelessInterrupt,
                 3 => { warn!("ARM7 GBA Cartridge DMA not implemented!"); DMAOccasion::GBACartridge },
                 _ => unreachable!(),
             }
@@ -322,14 +502,19 @@ pub struct DMACNT {
     pub repeat: bool,
     pub transfer_32: bool,
     pub start_timing: DMAOccasion,
+    start_timing_bits: u8,
     pub irq: bool,
     pub enable: bool,
 
     is_nds9: bool,
+    gba_mode: bool,
     num: usize,
     count_mask: u32,
 }
 
+savestate!(DMACNT { count, count_latch, dest_addr_ctrl, src_addr_ctrl, repeat, transfer_32, start_timing, start_timing_bits, irq,
+    enable, gba_mode, count_mask });
+
 impl DMACNT {
     pub fn new(is_nds9: bool, num: usize) -> Self {
         DMACNT {
@@ -340,10 +525,12 @@ impl DMACNT {
             repeat: false,
             transfer_32: false,
             start_timing: DMAOccasion::Immediate,
+            start_timing_bits: 0,
             irq: false,
             enable: false,
 
             is_nds9,
+            gba_mode: false,
             num,
             count_mask: if is_nds9 { 0x1F_FFFF } else { if num == 3 { 0xFFFF } else { 0x3FFF }},
         }
@@ -355,7 +542,7 @@ impl IORegister for DMACNT {
         match byte {
             0 | 1 => HW::read_byte_from_value(&self.count, byte),
             2 => (self.src_addr_ctrl & 0x1) << 7 | self.dest_addr_ctrl << 5 | (self.count >> 16) as u8,
-            3 => (self.enable as u8) << 7 | (self.irq as u8) << 6 | (self.start_timing as u8) << 3 |
+            3 => (self.enable as u8) << 7 | (self.irq as u8) << 6 | self.start_timing_bits << 3 |
                 (self.transfer_32 as u8) << 2 | (self.repeat as u8) << 1 | self.src_addr_ctrl >> 1,
             _ => unreachable!(),
         }
@@ -372,7 +559,8 @@ impl IORegister for DMACNT {
             3 => {
                 self.enable = value >> 7 & 0x1 != 0;
                 self.irq = value >> 6 & 0x1 != 0;
-                self.start_timing = DMAOccasion::get(self.is_nds9, self.num, value >> 3 & 0x7);
+                self.start_timing_bits = value >> 3 & if self.is_nds9 { 0x7 } else { 0x3 };
+                self.start_timing = DMAOccasion::get(self.is_nds9, self.gba_mode, self.num, self.start_timing_bits);
                 self.transfer_32 = value >> 2 & 0x1 != 0;
                 self.repeat = value >> 1 & 0x1 != 0;
                 self.src_addr_ctrl = self.src_addr_ctrl & !0x2 | value << 1 & 0x2;
@@ -388,6 +576,8 @@ pub struct Address {
     mask: u32,
 }
 
+savestate!(Address { addr, mask });
+
 impl Address {
     pub fn new(mask: u32) -> Address {
         Address {
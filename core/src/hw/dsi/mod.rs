@@ -0,0 +1,117 @@
+mod ndma;
+mod nwram;
+mod scfg;
+mod sdmmc;
+
+use std::mem::size_of;
+use crate::num;
+use crate::savestate::{Savestate, StateWriter};
+use super::{HW, MemoryValue, SdImage};
+use super::interrupt_controller::{DSiInterrupts, InterruptRequest2};
+pub use ndma::NDMA;
+pub use nwram::NWRAM;
+pub use scfg::SCFG;
+pub use sdmmc::SDMMC;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ConsoleModel {
+    DS,
+    DSi,
+}
+
+savestate_enum!(ConsoleModel { DS, DSi });
+
+// States from before DSi support are all from a DS
+pub(crate) fn migrate_mem_v1(data: &[u8]) -> Vec<u8> {
+    let mut state = StateWriter::new();
+    ConsoleModel::DS.save(&mut state);
+    SCFG::new(ConsoleModel::DS).save(&mut state);
+    NWRAM::new(ConsoleModel::DS).save(&mut state);
+    [data, &state.finish()].concat()
+}
+
+pub(crate) fn migrate_mem_v2(data: &[u8]) -> Vec<u8> {
+    let mut state = StateWriter::new();
+    DSiInterrupts::new().save(&mut state);
+    SDMMC::new().save(&mut state);
+    [data, &state.finish()].concat()
+}
+
+pub(crate) fn migrate_mem_v3(data: &[u8]) -> Vec<u8> {
+    let mut state = StateWriter::new();
+    NDMA::new().save(&mut state);
+    NDMA::new().save(&mut state);
+    [data, &state.finish()].concat()
+}
+
+impl HW {
+    // SCFG_EXT9 can limit main memory to the 4MB of a DS
+    pub(super) fn main_mem_mask(&self) -> u32 {
+        (self.main_mem.len().min(self.scfg.main_mem_size()) - 1) as u32
+    }
+
+    pub(super) fn read_dsi_io_register(&self, is_arm9: bool, addr: u32) -> u8 {
+        if self.model == ConsoleModel::DS || !self.scfg.enabled(is_arm9) { return 0 }
+        match addr {
+            0x0400_4000 ..= 0x0400_403F => self.scfg.read(is_arm9, addr),
+            _ => self.nwram.read_register(is_arm9, addr),
+        }
+    }
+
+    pub(super) fn write_dsi_io_register(&mut self, is_arm9: bool, addr: u32, value: u8) {
+        if self.model == ConsoleModel::DS || !self.scfg.enabled(is_arm9) { return }
+        let remap = match addr {
+            // SCFG_EXT9 sets how much main memory there is
+            0x0400_4000 ..= 0x0400_403F => {
+                let main_mem_size = self.scfg.main_mem_size();
+                self.scfg.write(is_arm9, addr, value);
+                self.scfg.main_mem_size() != main_mem_size
+            },
+            _ => self.nwram.write_register(is_arm9, addr, value),
+        };
+        if remap { self.remap_pages() }
+    }
+
+    // Port 0 is the SD card and port 1 the NAND
+    pub(super) fn set_sdmmc_image(&mut self, port: usize, image: Option<Box<dyn SdImage>>) {
+        self.sdmmc.set_image(port, image);
+    }
+
+    pub(super) fn read_sdmmc_register(&self, addr: u32) -> u8 {
+        if self.model == ConsoleModel::DS { return 0 }
+        self.sdmmc.read(addr)
+    }
+
+    pub(super) fn write_sdmmc_register(&mut self, addr: u32, value: u8) {
+        if self.model == ConsoleModel::DS { return }
+        self.sdmmc.write(addr, value);
+        self.check_sdmmc_interrupt();
+    }
+
+    // Each byte of a FIFO access moves it along by a byte
+    pub(super) fn read_sdmmc_fifo<T: MemoryValue>(&mut self) -> T {
+        if self.model == ConsoleModel::DS { return num::zero() }
+        let mut value: T = num::zero();
+        for i in 0..size_of::<T>() {
+            value |= num::cast::<u8, T>(self.sdmmc.read_fifo()).unwrap() << (8 * i);
+        }
+        self.check_sdmmc_interrupt();
+        value
+    }
+
+    pub(super) fn write_sdmmc_fifo<T: MemoryValue>(&mut self, value: T) {
+        if self.model == ConsoleModel::DS { return }
+        let value = num::cast::<T, u32>(value).unwrap();
+        for i in 0..size_of::<T>() { self.sdmmc.write_fifo((value >> (8 * i)) as u8) }
+        self.check_sdmmc_interrupt();
+    }
+
+    pub(super) fn is_sdmmc_fifo(addr: u32) -> bool {
+        (0x0400_4830 ..= 0x0400_4831).contains(&addr) || (0x0400_490C ..= 0x0400_490F).contains(&addr)
+    }
+
+    fn check_sdmmc_interrupt(&mut self) {
+        if self.sdmmc.take_interrupt() { self.dsi_interrupts.request |= InterruptRequest2::SD_MMC }
+        self.check_sdmmc_ndmas();
+    }
+}
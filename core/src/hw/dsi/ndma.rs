@@ -0,0 +1,215 @@
+use super::{ConsoleModel, HW};
+use crate::hw::interrupt_controller::InterruptRequest;
+use crate::hw::scheduler::Event;
+
+// One of the DSi's new DMA channels, which move a block of words every time they're started
+struct NDMAChannel {
+    sad: u32,
+    dad: u32,
+    // Words in the whole transfer and in each block
+    tcnt: u32,
+    wcnt: u32,
+    bcnt: u32,
+    fill: u32,
+    cnt: u32,
+    src_addr: u32,
+    dest_addr: u32,
+    words_left: u32,
+}
+
+savestate!(NDMAChannel { sad, dad, tcnt, wcnt, bcnt, fill, cnt, src_addr, dest_addr, words_left });
+
+impl NDMAChannel {
+    fn new() -> Self {
+        NDMAChannel {
+            sad: 0,
+            dad: 0,
+            tcnt: 0,
+            wcnt: 0,
+            bcnt: 0,
+            fill: 0,
+            cnt: 0,
+            src_addr: 0,
+            dest_addr: 0,
+            words_left: 0,
+        }
+    }
+
+    fn enabled(&self) -> bool { self.cnt & NDMA::ENABLE != 0 }
+    fn mode(&self) -> u32 { self.cnt >> 24 & 0x1F }
+    // Repeating channels run until they're stopped
+    fn repeating(&self) -> bool { self.cnt & 1 << 29 != 0 }
+    fn block_len(&self) -> u32 { if self.wcnt == 0 { 0x100_0000 } else { self.wcnt } }
+}
+
+// NDMA of one CPU. Blocks requested by the SD/MMC controller are moved all at once, without taking any time.
+// Immediate transfers are a single block of up to 16M words, so they're moved a chunk at a time instead.
+pub struct NDMA {
+    gcnt: u32,
+    channels: [NDMAChannel; 4],
+    // Set while a block is being moved, so the FIFO accesses it makes don't start another one
+    running: bool,
+}
+
+savestate!(NDMA { gcnt, channels });
+
+impl NDMA {
+    // Startup modes
+    pub const SD_MMC: u32 = 0x08;
+    pub const IMMEDIATE: u32 = 0x10;
+    const ENABLE: u32 = 1 << 31;
+    const CHUNK_WORDS: u32 = 0x400;
+
+    pub fn new() -> Self {
+        NDMA {
+            gcnt: 0,
+            channels: [NDMAChannel::new(), NDMAChannel::new(), NDMAChannel::new(), NDMAChannel::new()],
+            running: false,
+        }
+    }
+
+    fn register(&self, addr: u32) -> (usize, u32) {
+        let offset = addr - 0x0400_4104;
+        (offset as usize / 0x1C, (offset % 0x1C) & !0x3)
+    }
+
+    pub fn read(&self, addr: u32) -> u8 {
+        let byte = addr as usize & 0x3;
+        if addr < 0x0400_4104 { return HW::read_byte_from_value(&self.gcnt, byte) }
+        let (num, offset) = self.register(addr);
+        let channel = &self.channels[num];
+        let value = match offset {
+            0x00 => channel.sad,
+            0x04 => channel.dad,
+            0x08 => channel.tcnt,
+            0x0C => channel.wcnt,
+            0x10 => channel.bcnt,
+            0x14 => channel.fill,
+            0x18 => channel.cnt,
+            _ => unreachable!(),
+        };
+        HW::read_byte_from_value(&value, byte)
+    }
+
+    // Returns the channel that was just enabled, if any
+    pub fn write(&mut self, addr: u32, value: u8) -> Option<usize> {
+        let byte = addr as usize & 0x3;
+        if addr < 0x0400_4104 {
+            HW::write_byte_to_value(&mut self.gcnt, byte, value);
+            self.gcnt &= 0x800F_0000;
+            return None
+        }
+        let (num, offset) = self.register(addr);
+        let channel = &mut self.channels[num];
+        let was_enabled = channel.enabled();
+        let (register, mask) = match offset {
+            0x00 => (&mut channel.sad, 0xFFFF_FFFC),
+            0x04 => (&mut channel.dad, 0xFFFF_FFFC),
+            0x08 => (&mut channel.tcnt, 0x0FFF_FFFF),
+            0x0C => (&mut channel.wcnt, 0x00FF_FFFF),
+            0x10 => (&mut channel.bcnt, 0x0003_FFFF),
+            0x14 => (&mut channel.fill, 0xFFFF_FFFF),
+            0x18 => (&mut channel.cnt, 0xFF0F_FC00),
+            _ => unreachable!(),
+        };
+        HW::write_byte_to_value(register, byte, value);
+        *register &= mask;
+        if was_enabled || !channel.enabled() { return None }
+        channel.src_addr = channel.sad;
+        channel.dest_addr = channel.dad;
+        channel.words_left = if channel.mode() == NDMA::IMMEDIATE { channel.block_len() } else { channel.tcnt };
+        Some(num)
+    }
+}
+
+impl HW {
+    pub(crate) fn read_ndma_register(&self, is_arm9: bool, addr: u32) -> u8 {
+        if self.model == ConsoleModel::DS { return 0 }
+        self.ndmas[is_arm9 as usize].read(addr)
+    }
+
+    pub(crate) fn write_ndma_register(&mut self, is_arm9: bool, addr: u32, value: u8) {
+        if self.model == ConsoleModel::DS { return }
+        let num = match self.ndmas[is_arm9 as usize].write(addr, value) {
+            Some(num) => num,
+            None => return,
+        };
+        match (is_arm9, self.ndmas[is_arm9 as usize].channels[num].mode()) {
+            (_, NDMA::IMMEDIATE) => self.scheduler.run_now(Event::NDMA(is_arm9, num), HW::on_ndma),
+            (false, NDMA::SD_MMC) => self.check_sdmmc_ndmas(),
+            (_, mode) => warn!("Ignoring ARM{} NDMA{} Startup Mode 0x{:02X}", if is_arm9 { 9 } else { 7 }, num, mode),
+        }
+    }
+
+    // Moves a block for each request the SD/MMC controller makes while a channel is waiting for it
+    pub(super) fn check_sdmmc_ndmas(&mut self) {
+        if self.ndmas[0].running { return }
+        while self.sdmmc.data_requested() {
+            let progress = self.sdmmc.progress();
+            let num = match (0..4).find(|num| {
+                let channel = &self.ndmas[0].channels[*num];
+                channel.enabled() && channel.mode() == NDMA::SD_MMC
+            }) {
+                Some(num) => num,
+                None => return,
+            };
+            self.run_ndma(false, num);
+            // A channel going the wrong way for the transfer would otherwise repeat forever
+            if self.sdmmc.progress() == progress { return }
+        }
+    }
+
+    // Takes about a cycle per word
+    pub(crate) fn on_ndma(&mut self, event: Event) {
+        let (is_arm9, num) = match event {
+            Event::NDMA(is_arm9, num) => (is_arm9, num),
+            _ => unreachable!(),
+        };
+        // Stopped partway through
+        if !self.ndmas[is_arm9 as usize].channels[num].enabled() { return }
+        let words = self.run_ndma(is_arm9, num);
+        if self.ndmas[is_arm9 as usize].channels[num].enabled() {
+            self.scheduler.schedule(Event::NDMA(is_arm9, num), HW::on_ndma, words as usize);
+        }
+    }
+
+    // Returns the number of words moved
+    fn run_ndma(&mut self, is_arm9: bool, num: usize) -> u32 {
+        let i = is_arm9 as usize;
+        self.ndmas[i].running = true;
+        let channel = &self.ndmas[i].channels[num];
+        let immediate = channel.mode() == NDMA::IMMEDIATE;
+        let words = if immediate { channel.words_left.min(NDMA::CHUNK_WORDS) } else if channel.repeating() {
+            channel.block_len()
+        } else { channel.block_len().min(channel.words_left) };
+        let (src_ctrl, dest_ctrl) = (channel.cnt >> 13 & 0x3, channel.cnt >> 10 & 0x3);
+        let (mut src_addr, mut dest_addr, fill) = (channel.src_addr, channel.dest_addr, channel.fill);
+        for _ in 0..words {
+            // Source control 3 writes the fill data
+            let value = if src_ctrl == 3 { fill } else if is_arm9 { self.arm9_read::<u32>(src_addr) }
+                else { self.arm7_read::<u32>(src_addr) };
+            if is_arm9 { self.arm9_write(dest_addr, value) } else { self.arm7_write(dest_addr, value) }
+            let step = |addr: u32, ctrl| match ctrl {
+                0 => addr.wrapping_add(4),
+                1 => addr.wrapping_sub(4),
+                _ => addr,
+            };
+            src_addr = step(src_addr, src_ctrl);
+            dest_addr = step(dest_addr, dest_ctrl);
+        }
+        self.ndmas[i].running = false;
+
+        let channel = &mut self.ndmas[i].channels[num];
+        // Addresses can be reloaded after each block
+        channel.src_addr = if channel.cnt & 1 << 15 != 0 { channel.sad } else { src_addr };
+        channel.dest_addr = if channel.cnt & 1 << 12 != 0 { channel.dad } else { dest_addr };
+        channel.words_left -= words.min(channel.words_left);
+        if (immediate || !channel.repeating()) && channel.words_left == 0 {
+            channel.cnt &= !NDMA::ENABLE;
+            if channel.cnt & 1 << 30 != 0 {
+                self.interrupts[i].request |= InterruptRequest::from_bits_truncate(InterruptRequest::NDMA0.bits() << num);
+            }
+        }
+        words
+    }
+}
@@ -0,0 +1,124 @@
+use super::{ConsoleModel, HW, MemoryValue};
+
+// The DSi's extra WRAM: banks A, B and C of 256KB each, split into slots that are given to a CPU and then
+// mapped into its 0x03000000 region through a window of its own
+pub struct NWRAM {
+    mem: Vec<u8>,
+    // MBK1-5, a byte for each of WRAM A's four 64KB slots then WRAM B's and C's eight 32KB ones
+    slots: [u8; 20],
+    // MBK6-8, where WRAM A, B and C are for the ARM7 and ARM9
+    windows: [[u32; 3]; 2],
+    // MBK9, the slots only the ARM7 can give away
+    protect: u32,
+}
+
+impl NWRAM {
+    const BANK_SIZE: usize = 0x4_0000;
+
+    // On a DSi everything is left the way the launcher sets it up for DSi titles,
+    // with WRAM A and C for the ARM7 and WRAM B for the ARM9 at 0x03700000-0x037FFFFF
+    pub fn new(model: ConsoleModel) -> Self {
+        match model {
+            ConsoleModel::DS => NWRAM {
+                mem: Vec::new(),
+                slots: [0; 20],
+                windows: [[0; 3]; 2],
+                protect: 0,
+            },
+            ConsoleModel::DSi => NWRAM {
+                mem: vec![0; 3 * NWRAM::BANK_SIZE],
+                slots: [
+                    0x81, 0x85, 0x89, 0x8D,
+                    0x80, 0x84, 0x88, 0x8C, 0x90, 0x94, 0x98, 0x9C,
+                    0x81, 0x85, 0x89, 0x8D, 0x91, 0x95, 0x99, 0x9D,
+                ],
+                windows: [[0x0800_37C0, 0x07C0_3740, 0x0740_3700]; 2],
+                protect: 0x00FF_FF0F,
+            },
+        }
+    }
+
+    // Returns the offset into NWRAM the CPU sees at addr, if any slot is mapped there
+    pub fn mapping(&self, is_arm9: bool, addr: u32) -> Option<usize> {
+        if self.mem.is_empty() || addr >> 24 != 0x03 { return None }
+        (0..3).find_map(|bank| self.bank_mapping(is_arm9, bank, addr))
+    }
+
+    fn bank_mapping(&self, is_arm9: bool, bank: usize, addr: u32) -> Option<usize> {
+        let window = self.windows[is_arm9 as usize][bank];
+        // WRAM A has 64KB slots and only needs 1 bit for the CPU and 2 for the offset
+        let (slot_shift, slots, start, end, master_mask, offset_mask) = if bank == 0 {
+            (16, &self.slots[..4], window >> 4 & 0xFF, window >> 20 & 0x1FF, 0x1, 0x3)
+        } else {
+            (15, &self.slots[4 + 8 * (bank - 1)..][..8], window >> 3 & 0x1FF, window >> 19 & 0x3FF, 0x3, 0x7)
+        };
+        let addr_slot = (addr & 0x00FF_FFFF) >> slot_shift;
+        if addr_slot < start || addr_slot >= end { return None }
+        // The window mirrors an image of 1 to all of the bank's slots
+        let image_slots = match (bank, window >> 12 & 0x3) {
+            (0, size) => 1 << size.saturating_sub(1),
+            (_, size) => 1 << size,
+        };
+        let offset = addr_slot & (image_slots - 1);
+        let master = if is_arm9 { 0 } else { 1 };
+        let slot = slots.iter().position(|slot|
+            slot & 0x80 != 0 && (slot & master_mask) as u32 == master && (slot >> 2 & offset_mask) as u32 == offset
+        )?;
+        Some(bank * NWRAM::BANK_SIZE + (slot << slot_shift) + (addr as usize & ((1 << slot_shift) - 1)))
+    }
+
+    pub fn read<T: MemoryValue>(&self, offset: usize) -> T {
+        HW::read_mem(&self.mem, offset as u32)
+    }
+
+    pub fn write<T: MemoryValue>(&mut self, offset: usize, value: T) {
+        HW::write_mem(&mut self.mem, offset as u32, value)
+    }
+
+    pub fn byte_mut(&mut self, offset: usize) -> &mut u8 {
+        &mut self.mem[offset]
+    }
+
+    pub fn read_register(&self, is_arm9: bool, addr: u32) -> u8 {
+        let byte = addr as usize & 0x3;
+        match addr {
+            0x0400_4040 ..= 0x0400_4053 => self.slots[(addr - 0x0400_4040) as usize],
+            0x0400_4054 ..= 0x0400_405F =>
+                HW::read_byte_from_value(&self.windows[is_arm9 as usize][(addr - 0x0400_4054) as usize / 4], byte),
+            0x0400_4060 ..= 0x0400_4063 => HW::read_byte_from_value(&self.protect, byte),
+            _ => unreachable!(),
+        }
+    }
+
+    // Returns whether what's mapped where changed
+    pub fn write_register(&mut self, is_arm9: bool, addr: u32, value: u8) -> bool {
+        let byte = addr as usize & 0x3;
+        match addr {
+            0x0400_4040 ..= 0x0400_4053 => {
+                let slot = (addr - 0x0400_4040) as usize;
+                let protect_bit = if slot < 4 { slot } else { slot + 4 };
+                if is_arm9 && self.protect & 1 << protect_bit != 0 { return false }
+                let old_slot = self.slots[slot];
+                self.slots[slot] = value & if slot < 4 { 0x8D } else { 0x9F };
+                self.slots[slot] != old_slot
+            },
+            0x0400_4054 ..= 0x0400_405F => {
+                let bank = (addr - 0x0400_4054) as usize / 4;
+                let window = &mut self.windows[is_arm9 as usize][bank];
+                let old_window = *window;
+                HW::write_byte_to_value(window, byte, value);
+                *window &= if bank == 0 { 0x1FF0_3FF0 } else { 0x1FF8_3FF8 };
+                *window != old_window
+            },
+            0x0400_4060 ..= 0x0400_4063 if !is_arm9 => {
+                HW::write_byte_to_value(&mut self.protect, byte, value);
+                self.protect &= 0x00FF_FF0F;
+                false
+            },
+            0x0400_4060 ..= 0x0400_4063 => false,
+            _ => unreachable!(),
+        }
+    }
+}
+
+savestate!(NWRAM { mem, slots, windows, protect });
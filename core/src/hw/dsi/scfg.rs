@@ -0,0 +1,104 @@
+use super::{ConsoleModel, HW};
+
+// System configuration registers that turn the DSi's extra hardware on and off
+pub struct SCFG {
+    // BIOS settings for both CPUs, of which the ARM9 can only read the low byte
+    rom: u16,
+    clk9: u16,
+    clk7: u16,
+    rst: u16,
+    ext9: u32,
+    ext7: u32,
+    mc: u16,
+}
+
+impl SCFG {
+    // The registers don't exist on a DS. On a DSi they're left the way the launcher sets them up for DSi titles.
+    pub fn new(model: ConsoleModel) -> Self {
+        match model {
+            ConsoleModel::DS => SCFG {
+                rom: 0,
+                clk9: 0,
+                clk7: 0,
+                rst: 0,
+                ext9: 0,
+                ext7: 0,
+                mc: 0,
+            },
+            ConsoleModel::DSi => SCFG {
+                rom: 0x0101,
+                clk9: 0x0187,
+                clk7: 0x0187,
+                rst: 0x0001,
+                ext9: 0x8307_F100,
+                ext7: 0x93FF_FB06,
+                mc: 0x0010,
+            },
+        }
+    }
+
+    // Each CPU loses access to SCFG and the WRAM banking registers once it clears bit 31 of its SCFG_EXT
+    pub fn enabled(&self, is_arm9: bool) -> bool {
+        (if is_arm9 { self.ext9 } else { self.ext7 }) & 0x8000_0000 != 0
+    }
+
+    pub fn main_mem_size(&self) -> usize {
+        match self.ext9 >> 14 & 0x3 {
+            0 | 1 => 0x40_0000,
+            2 => 0x100_0000,
+            3 => 0x200_0000,
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn read(&self, is_arm9: bool, addr: u32) -> u8 {
+        let byte = addr as usize & 0x3;
+        match (is_arm9, addr) {
+            (true, 0x0400_4000) => self.rom as u8 & 0x03,
+            (false, 0x0400_4000 ..= 0x0400_4001) => HW::read_byte_from_value(&self.rom, byte),
+            (true, 0x0400_4004 ..= 0x0400_4005) => HW::read_byte_from_value(&self.clk9, byte & 0x1),
+            (false, 0x0400_4004 ..= 0x0400_4005) => HW::read_byte_from_value(&self.clk7, byte & 0x1),
+            (true, 0x0400_4006 ..= 0x0400_4007) => HW::read_byte_from_value(&self.rst, byte & 0x1),
+            (true, 0x0400_4008 ..= 0x0400_400B) => HW::read_byte_from_value(&self.ext9, byte),
+            (false, 0x0400_4008 ..= 0x0400_400B) => HW::read_byte_from_value(&self.ext7, byte),
+            (_, 0x0400_4010 ..= 0x0400_4011) => HW::read_byte_from_value(&self.mc, byte),
+            _ => 0,
+        }
+    }
+
+    pub fn write(&mut self, is_arm9: bool, addr: u32, value: u8) {
+        let byte = addr as usize & 0x3;
+        match (is_arm9, addr) {
+            (false, 0x0400_4000 ..= 0x0400_4001) => {
+                // The BIOSes can be locked but never unlocked again
+                let mut rom = 0;
+                HW::write_byte_to_value(&mut rom, byte, value);
+                self.rom |= rom & 0x0703;
+            },
+            (true, 0x0400_4004 ..= 0x0400_4005) => {
+                HW::write_byte_to_value(&mut self.clk9, byte & 0x1, value);
+                self.clk9 &= 0x0187;
+            },
+            (false, 0x0400_4004 ..= 0x0400_4005) => {
+                HW::write_byte_to_value(&mut self.clk7, byte & 0x1, value);
+                self.clk7 &= 0x0187;
+            },
+            (true, 0x0400_4006) => self.rst = value as u16 & 0x1,
+            (true, 0x0400_4008 ..= 0x0400_400B) => {
+                let mut ext9 = self.ext9;
+                HW::write_byte_to_value(&mut ext9, byte, value);
+                self.ext9 = ext9 & 0x8307_F19F;
+            },
+            (false, 0x0400_4008 ..= 0x0400_400B) => {
+                let mut ext7 = self.ext7;
+                HW::write_byte_to_value(&mut ext7, byte, value);
+                self.ext7 = ext7 & 0x93FF_FF87;
+            },
+            // Only the slot power bits can be changed, the rest reflect the cartridge
+            (false, 0x0400_4010) => self.mc = self.mc & !0x000C | value as u16 & 0x000C,
+            _ => warn!("Ignoring {} SCFG Write 0x{:08X} = {:02X}", if is_arm9 { "ARM9" } else { "ARM7" }, addr, value),
+        }
+    }
+}
+
+savestate!(SCFG { rom, clk9, clk7, rst, ext9, ext7, mc });
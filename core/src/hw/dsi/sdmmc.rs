@@ -0,0 +1,579 @@
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use crate::hw::SdImage;
+use super::HW;
+
+// The card on each port as far as the controller knows
+struct Card {
+    rca: u16,
+    state: u8,
+    app_cmd: bool,
+}
+
+savestate!(Card { rca, state, app_cmd });
+
+impl Card {
+    const IDLE: u8 = 0;
+    const READY: u8 = 1;
+    const IDENT: u8 = 2;
+    const STANDBY: u8 = 3;
+    const TRANSFER: u8 = 4;
+    const DATA: u8 = 5;
+    const RECEIVE: u8 = 6;
+
+    fn new() -> Self {
+        Card {
+            rca: 0,
+            state: Card::IDLE,
+            app_cmd: false,
+        }
+    }
+
+    // Card status returned in R1 responses
+    fn status(&self) -> u32 {
+        (self.state as u32) << 9 | 0x100 | (self.app_cmd as u32) << 5
+    }
+}
+
+// SD/MMC host controller with the SD card slot on port 0 and the eMMC NAND on port 1. Commands and blocks complete
+// as soon as they're started.
+// There's no AES engine to decrypt the NAND and the DSi BIOSes aren't booted, so DSiWare and the DSi menu don't start.
+// Only direct booted titles use the controller, and they see the NAND exactly as it's stored in the image.
+pub struct SDMMC {
+    // The images aren't part of save states
+    images: [Option<Box<dyn SdImage>>; 2],
+    sectors: [u64; 2],
+    cards: [Card; 2],
+    command: u16,
+    port: u16,
+    arg: u32,
+    stop: u16,
+    block_count16: u16,
+    response: [u32; 4],
+    irq_status: u32,
+    irq_mask: u32,
+    clk_ctl: u16,
+    block_len16: u16,
+    option: u16,
+    data_ctl: u16,
+    data32_irq: u16,
+    block_len32: u16,
+    block_count32: u16,
+    // Block the CPU is reading from or writing to the FIFO
+    buffer: Vec<u8>,
+    buffer_pos: usize,
+    reading: bool,
+    writing: bool,
+    // Sector of the next block and how many are left in the transfer
+    transfer_sector: u64,
+    blocks_left: u16,
+    interrupt: bool,
+}
+
+savestate!(SDMMC { cards, command, port, arg, stop, block_count16, response, irq_status, irq_mask, clk_ctl, block_len16,
+    option, data_ctl, data32_irq, block_len32, block_count32, buffer, buffer_pos, reading, writing, transfer_sector,
+    blocks_left, interrupt });
+
+impl SDMMC {
+    const SECTOR_LEN: usize = 0x200;
+
+    // SD_IRQ_STATUS
+    const CMD_RESPONSE_END: u32 = 1 << 0;
+    const DATA_END: u32 = 1 << 2;
+    const CARD_PRESENT: u32 = 1 << 5;
+    const WRITABLE: u32 = 1 << 7;
+    const CMD_TIMEOUT: u32 = 1 << 22;
+    const RX_READY: u32 = 1 << 24;
+    const TX_REQUEST: u32 = 1 << 25;
+    const CMD_READY: u32 = 1 << 29;
+
+    // SD_DATA32_IRQ
+    const RX32_READY: u16 = 1 << 8;
+    const TX32_REQUEST: u16 = 1 << 9;
+    const CLEAR_FIFO32: u16 = 1 << 10;
+    const RX32_IRQ: u16 = 1 << 11;
+    const TX32_IRQ: u16 = 1 << 12;
+
+    pub fn new() -> Self {
+        SDMMC {
+            images: [None, None],
+            sectors: [0; 2],
+            cards: [Card::new(), Card::new()],
+            command: 0,
+            port: 0,
+            arg: 0,
+            stop: 0,
+            block_count16: 0,
+            response: [0; 4],
+            irq_status: 0,
+            irq_mask: 0x8B7F_031D,
+            clk_ctl: 0,
+            block_len16: SDMMC::SECTOR_LEN as u16,
+            option: 0,
+            data_ctl: 0,
+            data32_irq: 0,
+            block_len32: SDMMC::SECTOR_LEN as u16,
+            block_count32: 0,
+            buffer: Vec::new(),
+            buffer_pos: 0,
+            reading: false,
+            writing: false,
+            transfer_sector: 0,
+            blocks_left: 0,
+            interrupt: false,
+        }
+    }
+
+    // Port 0 is the SD card and port 1 the NAND
+    pub fn set_image(&mut self, port: usize, mut image: Option<Box<dyn SdImage>>) {
+        self.sectors[port] = match image.as_mut().map(|image| image.seek(SeekFrom::End(0))) {
+            Some(Ok(len)) => len / SDMMC::SECTOR_LEN as u64,
+            Some(Err(err)) => { warn!("Unable to Get SD/MMC Image Size: {}!", err); 0 },
+            None => 0,
+        };
+        self.images[port] = image;
+        self.cards[port] = Card::new();
+    }
+
+    // Returns whether an enabled IRQ was raised since the last call
+    pub fn take_interrupt(&mut self) -> bool {
+        std::mem::replace(&mut self.interrupt, false)
+    }
+
+    // Whether the 32-bit FIFO has a block to read or needs one written, which starts NDMA
+    pub fn data_requested(&self) -> bool {
+        self.data32_irq & (SDMMC::RX32_READY | SDMMC::TX32_REQUEST) != 0
+    }
+
+    // Changes whenever data moves through the FIFO
+    pub fn progress(&self) -> (u64, u16, usize, usize) {
+        (self.transfer_sector, self.blocks_left, self.buffer_pos, self.buffer.len())
+    }
+
+    fn set_status(&mut self, bits: u32) {
+        self.irq_status |= bits;
+        if bits & !self.irq_mask != 0 { self.interrupt = true }
+    }
+
+    fn current_port(&self) -> usize {
+        self.port as usize & 0x1
+    }
+
+    pub fn read(&self, addr: u32) -> u8 {
+        let byte = addr as usize & 0x1;
+        let present = if self.images[self.current_port()].is_some() { SDMMC::CARD_PRESENT | SDMMC::WRITABLE } else { 0 };
+        let value = match addr & 0x1FE {
+            0x000 => self.command,
+            0x002 => self.port,
+            0x004 => self.arg as u16,
+            0x006 => (self.arg >> 16) as u16,
+            0x008 => self.stop,
+            0x00A => self.block_count16,
+            0x00C ..= 0x01A => (self.response[(addr as usize & 0x1F) / 4 - 3] >> (8 * (addr & 0x2))) as u16,
+            0x01C => (self.irq_status | present | SDMMC::CMD_READY) as u16,
+            0x01E => ((self.irq_status | present | SDMMC::CMD_READY) >> 16) as u16,
+            0x020 => self.irq_mask as u16,
+            0x022 => (self.irq_mask >> 16) as u16,
+            0x024 => self.clk_ctl,
+            0x026 => self.block_len16,
+            0x028 => self.option,
+            0x0D8 => self.data_ctl,
+            0x100 => self.data32_irq,
+            0x104 => self.block_len32,
+            0x108 => self.block_count32,
+            _ => 0,
+        };
+        HW::read_byte_from_value(&value, byte)
+    }
+
+    pub fn write(&mut self, addr: u32, value: u8) {
+        let byte = addr as usize & 0x1;
+        let write16 = |register: &mut u16| HW::write_byte_to_value(register, byte, value);
+        match addr & 0x1FE {
+            0x000 => {
+                write16(&mut self.command);
+                if byte == 1 { self.run_command() }
+            },
+            0x002 => write16(&mut self.port),
+            0x004 ..= 0x006 => HW::write_byte_to_value(&mut self.arg, addr as usize & 0x3, value),
+            0x008 => write16(&mut self.stop),
+            0x00A => write16(&mut self.block_count16),
+            // Writing 0 to a bit acknowledges it
+            0x01C ..= 0x01E => self.irq_status &= !((!value as u32 & 0xFF) << (8 * (addr & 0x3))),
+            0x020 ..= 0x022 => HW::write_byte_to_value(&mut self.irq_mask, addr as usize & 0x3, value),
+            0x024 => write16(&mut self.clk_ctl),
+            0x026 => {
+                write16(&mut self.block_len16);
+                self.block_len16 &= 0x3FF;
+            },
+            0x028 => write16(&mut self.option),
+            0x0D8 => write16(&mut self.data_ctl),
+            0x0E0 => if byte == 0 && value & 0x1 == 0 { self.reset() },
+            0x100 => {
+                let mut data32_irq = self.data32_irq;
+                HW::write_byte_to_value(&mut data32_irq, byte, value);
+                if data32_irq & SDMMC::CLEAR_FIFO32 != 0 { self.buffer.clear(); self.buffer_pos = 0 }
+                let status = SDMMC::RX32_READY | SDMMC::TX32_REQUEST;
+                self.data32_irq = self.data32_irq & status | data32_irq & !status & 0x1802;
+            },
+            0x104 => write16(&mut self.block_len32),
+            0x108 => write16(&mut self.block_count32),
+            _ => warn!("Ignoring SD/MMC Write 0x{:08X} = {:02X}", addr, value),
+        }
+    }
+
+    fn reset(&mut self) {
+        let images = std::mem::take(&mut self.images);
+        let sectors = self.sectors;
+        *self = SDMMC::new();
+        self.images = images;
+        self.sectors = sectors;
+    }
+
+    fn run_command(&mut self) {
+        let port = self.current_port();
+        let index = self.command & 0x3F;
+        let app_cmd = self.command & 0xC0 == 0x40 || self.cards[port].app_cmd;
+        self.cards[port].app_cmd = false;
+        if self.images[port].is_none() { return self.set_status(SDMMC::CMD_TIMEOUT) }
+        // The SD card is high capacity and addressed by sector, the NAND by byte
+        let sector = if port == 0 { self.arg as u64 } else { self.arg as u64 / SDMMC::SECTOR_LEN as u64 };
+        match (app_cmd, index) {
+            (false, 0) => {
+                self.cards[port] = Card::new();
+                self.set_status(SDMMC::CMD_RESPONSE_END);
+            },
+            (false, 1) if port == 1 => {
+                self.cards[port].state = Card::READY;
+                self.respond(0x80FF_8080);
+            },
+            (true, 41) if port == 0 => {
+                self.cards[port].state = Card::READY;
+                self.respond(0xC0FF_8000);
+            },
+            (false, 2) => {
+                self.cards[port].state = Card::IDENT;
+                self.respond_long(self.cid(port));
+            },
+            (false, 3) if port == 0 => {
+                self.cards[port].rca = 0x0001;
+                self.cards[port].state = Card::STANDBY;
+                self.respond((self.cards[port].rca as u32) << 16 | self.cards[port].status() & 0x1FFF);
+            },
+            (false, 3) => {
+                self.cards[port].rca = (self.arg >> 16) as u16;
+                self.cards[port].state = Card::STANDBY;
+                self.respond_status(port);
+            },
+            (false, 7) => {
+                self.cards[port].state = if (self.arg >> 16) as u16 == self.cards[port].rca { Card::TRANSFER }
+                    else { Card::STANDBY };
+                self.respond_status(port);
+            },
+            (false, 8) if port == 0 => self.respond(self.arg & 0xFFF),
+            (false, 8) => {
+                // Extended CSD, of which only the sector count matters
+                let mut ext_csd = vec![0; SDMMC::SECTOR_LEN];
+                ext_csd[212..216].copy_from_slice(&(self.sectors[port] as u32).to_le_bytes());
+                self.start_data_read(port, ext_csd);
+            },
+            (false, 9) => self.respond_long(self.csd(port)),
+            (false, 10) => self.respond_long(self.cid(port)),
+            (false, 12) => {
+                self.reading = false;
+                self.writing = false;
+                self.irq_status &= !(SDMMC::RX_READY | SDMMC::TX_REQUEST);
+                self.data32_irq &= !(SDMMC::RX32_READY | SDMMC::TX32_REQUEST);
+                self.cards[port].state = Card::TRANSFER;
+                self.respond_status(port);
+            },
+            (_, 6) | (_, 13) | (false, 16) => self.respond_status(port),
+            (false, 17) | (false, 18) => {
+                self.transfer_sector = sector;
+                self.blocks_left = if index == 18 { self.block_count16.max(1) } else { 1 };
+                self.reading = true;
+                self.cards[port].state = Card::DATA;
+                self.respond_status(port);
+                self.read_block(port);
+            },
+            (false, 24) | (false, 25) => {
+                self.transfer_sector = sector;
+                self.blocks_left = if index == 25 { self.block_count16.max(1) } else { 1 };
+                self.writing = true;
+                self.cards[port].state = Card::RECEIVE;
+                self.respond_status(port);
+                self.request_block();
+            },
+            (false, 55) => {
+                self.cards[port].app_cmd = true;
+                self.respond_status(port);
+            },
+            // SD configuration register, for a card with a 4-bit bus that supports SD 2.0
+            (true, 51) => self.start_data_read(port, vec![0x02, 0x35, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00]),
+            _ => {
+                warn!("Unhandled SD/MMC {}{} on Port {}", if app_cmd { "ACMD" } else { "CMD" }, index, port);
+                self.set_status(SDMMC::CMD_TIMEOUT);
+            },
+        }
+    }
+
+    fn respond(&mut self, response: u32) {
+        self.response = [response, 0, 0, 0];
+        self.set_status(SDMMC::CMD_RESPONSE_END);
+    }
+
+    fn respond_status(&mut self, port: usize) {
+        self.respond(self.cards[port].status());
+    }
+
+    // The response registers hold the 136-bit response without its CRC
+    fn respond_long(&mut self, register: u128) {
+        let value = register >> 8;
+        self.response = [value as u32, (value >> 32) as u32, (value >> 64) as u32, (value >> 96) as u32];
+        self.set_status(SDMMC::CMD_RESPONSE_END);
+    }
+
+    fn cid(&self, port: usize) -> u128 {
+        let product = if port == 0 { *b"NDSSD" } else { *b"NAND " };
+        let mut cid = 0x03u128 << 120 | (u16::from_be_bytes(*b"SD") as u128) << 104 | 0x0100_0000 << 24 | 1;
+        for (i, byte) in product.iter().enumerate() { cid |= (*byte as u128) << (96 - 8 * i) }
+        cid
+    }
+
+    fn csd(&self, port: usize) -> u128 {
+        let sectors = self.sectors[port] as u128;
+        if port == 0 {
+            // Version 2, which counts 512KB units
+            let c_size = (sectors / 1024).max(1) - 1;
+            1 << 126 | 0x0E << 112 | 0x32 << 96 | 0x5B5 << 84 | 9 << 80 | (c_size & 0x3F_FFFF) << 48 | 9 << 22 | 1
+        } else {
+            // Version 1, counting 256KB units
+            let c_size = (sectors / 512).max(1) - 1;
+            2 << 126 | 0x0E << 112 | 0x32 << 96 | 0x5B5 << 84 | 9 << 80 | (c_size & 0xFFF) << 62 | 7 << 47 | 9 << 22 | 1
+        }
+    }
+
+    fn start_data_read(&mut self, port: usize, data: Vec<u8>) {
+        self.respond_status(port);
+        self.buffer = data;
+        self.buffer_pos = 0;
+        self.blocks_left = 1;
+        self.reading = true;
+        self.data_ready();
+    }
+
+    // Blocks are at most a sector long, and a length of 0 would never finish one so it's taken as a whole sector
+    fn block_len(&self) -> usize {
+        match self.block_len16 as usize {
+            0 => SDMMC::SECTOR_LEN,
+            len => len.min(SDMMC::SECTOR_LEN),
+        }
+    }
+
+    fn read_block(&mut self, port: usize) {
+        let mut data = vec![0; self.block_len()];
+        if let Some(image) = &mut self.images[port] {
+            let result = image.seek(SeekFrom::Start(self.transfer_sector * SDMMC::SECTOR_LEN as u64))
+                .and_then(|_| image.read_exact(&mut data));
+            if let Err(err) = result { warn!("Unable to Read SD/MMC Sector 0x{:X}: {}!", self.transfer_sector, err) }
+        }
+        self.buffer = data;
+        self.buffer_pos = 0;
+        self.data_ready();
+    }
+
+    fn data_ready(&mut self) {
+        self.data32_irq |= SDMMC::RX32_READY;
+        if self.data32_irq & SDMMC::RX32_IRQ != 0 { self.interrupt = true }
+        self.set_status(SDMMC::RX_READY);
+    }
+
+    fn request_block(&mut self) {
+        self.buffer.clear();
+        self.data32_irq |= SDMMC::TX32_REQUEST;
+        if self.data32_irq & SDMMC::TX32_IRQ != 0 { self.interrupt = true }
+        self.set_status(SDMMC::TX_REQUEST);
+    }
+
+    fn finish_transfer(&mut self) {
+        self.reading = false;
+        self.writing = false;
+        let port = self.current_port();
+        self.cards[port].state = Card::TRANSFER;
+        self.set_status(SDMMC::DATA_END);
+    }
+
+    // Both FIFOs are read a byte at a time
+    pub fn read_fifo(&mut self) -> u8 {
+        if !self.reading || self.buffer_pos >= self.buffer.len() { return 0 }
+        let value = self.buffer[self.buffer_pos];
+        self.buffer_pos += 1;
+        if self.buffer_pos == self.buffer.len() {
+            self.irq_status &= !SDMMC::RX_READY;
+            self.data32_irq &= !SDMMC::RX32_READY;
+            self.transfer_sector += 1;
+            self.blocks_left -= 1;
+            if self.blocks_left == 0 { self.finish_transfer() } else { self.read_block(self.current_port()) }
+        }
+        value
+    }
+
+    pub fn write_fifo(&mut self, value: u8) {
+        if !self.writing { return }
+        self.buffer.push(value);
+        if self.buffer.len() < self.block_len() { return }
+        self.irq_status &= !SDMMC::TX_REQUEST;
+        self.data32_irq &= !SDMMC::TX32_REQUEST;
+        let port = self.current_port();
+        if let Some(image) = &mut self.images[port] {
+            let buffer = &self.buffer;
+            let result = image.seek(SeekFrom::Start(self.transfer_sector * SDMMC::SECTOR_LEN as u64))
+                .and_then(|_| image.write_all(buffer));
+            if let Err(err) = result { warn!("Unable to Write SD/MMC Sector 0x{:X}: {}!", self.transfer_sector, err) }
+        }
+        self.transfer_sector += 1;
+        self.blocks_left -= 1;
+        if self.blocks_left == 0 { self.finish_transfer() } else { self.request_block() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::hw::{ConsoleModel, MemoryStorage, SampleQueue, SystemFiles};
+    use crate::hw::dsi::NDMA;
+
+    const SECTORS: usize = 0x10;
+    const FIFO32: u32 = 0x0400_490C;
+
+    // Each sector has its own pattern, so a block from the wrong sector or offset can't pass for the right one
+    fn image() -> Vec<u8> {
+        (0..SECTORS * SDMMC::SECTOR_LEN).map(|i| (i / SDMMC::SECTOR_LEN * 0x1F + i) as u8 ^ (i >> 8) as u8).collect()
+    }
+
+    fn sectors(image: &[u8], start: usize, count: usize) -> Vec<u8> {
+        image[start * SDMMC::SECTOR_LEN..(start + count) * SDMMC::SECTOR_LEN].to_vec()
+    }
+
+    fn registers(arg: u32, blocks: u16, index: u16) -> [(u32, u16); 4] {
+        [(0x004, arg as u16), (0x006, (arg >> 16) as u16), (0x00A, blocks), (0x000, index)]
+    }
+
+    fn command(sdmmc: &mut SDMMC, index: u16, arg: u32, blocks: u16) {
+        for (offset, value) in registers(arg, blocks, index) {
+            for byte in 0..2 { sdmmc.write(0x0400_4800 + offset + byte, (value >> (8 * byte)) as u8) }
+        }
+    }
+
+    fn sdmmc() -> SDMMC {
+        let mut sdmmc = SDMMC::new();
+        sdmmc.set_image(0, Some(Box::new(Cursor::new(image()))));
+        sdmmc
+    }
+
+    fn data_ended(sdmmc: &mut SDMMC) -> bool {
+        let ended = sdmmc.read(0x0400_481C) as u32 & SDMMC::DATA_END != 0;
+        sdmmc.write(0x0400_481C, !SDMMC::DATA_END as u8);
+        ended
+    }
+
+    #[test]
+    fn fifo_reads() {
+        let mut sdmmc = sdmmc();
+        command(&mut sdmmc, 17, 2, 0);
+        let block: Vec<u8> = (0..SDMMC::SECTOR_LEN).map(|_| sdmmc.read_fifo()).collect();
+        assert_eq!(block, sectors(&image(), 2, 1));
+        assert!(data_ended(&mut sdmmc));
+
+        command(&mut sdmmc, 18, 5, 3);
+        let blocks: Vec<u8> = (0..3 * SDMMC::SECTOR_LEN).map(|_| sdmmc.read_fifo()).collect();
+        assert_eq!(blocks, sectors(&image(), 5, 3));
+        assert!(data_ended(&mut sdmmc));
+        assert!(!sdmmc.data_requested());
+    }
+
+    #[test]
+    fn fifo_writes() {
+        let mut sdmmc = sdmmc();
+        let data = sectors(&image(), 0, 3);
+        command(&mut sdmmc, 24, 9, 0);
+        assert!(sdmmc.data_requested());
+        for byte in data[..SDMMC::SECTOR_LEN].iter() { sdmmc.write_fifo(*byte) }
+        assert!(data_ended(&mut sdmmc));
+
+        command(&mut sdmmc, 25, 10, 2);
+        for byte in data[SDMMC::SECTOR_LEN..].iter() { sdmmc.write_fifo(*byte) }
+        assert!(data_ended(&mut sdmmc));
+        assert!(!sdmmc.data_requested());
+
+        command(&mut sdmmc, 18, 9, 3);
+        let blocks: Vec<u8> = (0..3 * SDMMC::SECTOR_LEN).map(|_| sdmmc.read_fifo()).collect();
+        assert_eq!(blocks, data);
+    }
+
+    // A DSi title, so the SD/MMC controller and NDMA are there
+    fn hw() -> HW {
+        let mut rom = vec![0; 0x8000];
+        rom[0x12] = 3;
+        // ARM9 and ARM7 ROM offsets, entry points, RAM addresses and sizes
+        let header = [0x4000, 0x0200_0000, 0x0200_0000, 0x4, 0x5000, 0x0238_0000, 0x0238_0000, 0x4];
+        for (i, word) in header.iter().enumerate() {
+            rom[0x20 + 4 * i..0x24 + 4 * i].copy_from_slice(&u32::to_le_bytes(*word));
+        }
+        let files = SystemFiles { bios7: vec![0; 0x4000], bios9: vec![0; 0x1000], firmware: None };
+        let mut hw = HW::new(files, rom, Box::new(MemoryStorage::new(None)), Box::new(SampleQueue::new(48000)), true,
+            ConsoleModel::DSi).unwrap();
+        hw.set_sd_image(Some(Box::new(Cursor::new(image()))));
+        hw
+    }
+
+    fn arm7_command(hw: &mut HW, index: u16, arg: u32, blocks: u16) {
+        for (offset, value) in registers(arg, blocks, index) { hw.arm7_write::<u16>(0x0400_4800 + offset, value) }
+    }
+
+    // Moves a sector per block between the FIFO and main memory when the controller asks for it
+    fn start_ndma(hw: &mut HW, num: u32, sad: u32, dad: u32, blocks: u32, cnt: u32) {
+        let base = 0x0400_4104 + 0x1C * num;
+        let words = (SDMMC::SECTOR_LEN / 4) as u32;
+        for (offset, value) in [(0x00, sad), (0x04, dad), (0x08, blocks * words), (0x0C, words), (0x18, cnt)] {
+            hw.arm7_write::<u32>(base + offset, value);
+        }
+    }
+
+    fn ndma_enabled(hw: &HW, num: u32) -> bool {
+        hw.read_ndma_register(false, 0x0400_411F + 0x1C * num) & 0x80 != 0
+    }
+
+    #[test]
+    fn ndma_blocks() {
+        const ENABLE_SD_MMC: u32 = 1 << 31 | NDMA::SD_MMC << 24;
+        const FIXED_SRC: u32 = 2 << 13;
+        const FIXED_DEST: u32 = 2 << 10;
+        let mut hw = hw();
+
+        // Sectors 4 - 7 into main memory
+        start_ndma(&mut hw, 0, FIFO32, 0x0210_0000, 4, ENABLE_SD_MMC | FIXED_SRC);
+        arm7_command(&mut hw, 18, 4, 4);
+        assert!(!ndma_enabled(&hw, 0));
+        let read: Vec<u8> = (0..4 * SDMMC::SECTOR_LEN as u32).map(|i| hw.arm7_read::<u8>(0x0210_0000 + i)).collect();
+        assert_eq!(read, sectors(&image(), 4, 4));
+
+        // And back out to sectors 12 - 15, a block for CMD24 and the rest for CMD25
+        start_ndma(&mut hw, 1, 0x0210_0000, FIFO32, 1, ENABLE_SD_MMC | FIXED_DEST);
+        arm7_command(&mut hw, 24, 12, 0);
+        assert!(!ndma_enabled(&hw, 1));
+        start_ndma(&mut hw, 1, 0x0210_0200, FIFO32, 3, ENABLE_SD_MMC | FIXED_DEST);
+        arm7_command(&mut hw, 25, 13, 3);
+        assert!(!ndma_enabled(&hw, 1));
+        assert!(!hw.sdmmc.data_requested());
+
+        // Read back a byte at a time through CMD17
+        for sector in 12..16 {
+            arm7_command(&mut hw, 17, sector, 0);
+            let block: Vec<u8> = (0..SDMMC::SECTOR_LEN).map(|_| hw.arm7_read::<u8>(FIFO32)).collect();
+            assert_eq!(block, sectors(&image(), sector as usize - 8, 1));
+        }
+    }
+}
@@ -0,0 +1,74 @@
+mod sound;
+
+use super::{HW, InterruptController};
+use sound::GBASound;
+
+// State of GBA mode, which the ARM7 runs alone at 16.78 MHz once HALTCNT switches into it
+pub struct GBA {
+    pub enabled: bool,
+    pub bios: Vec<u8>,
+    pub waitcnt: u16,
+    pub postflg: u8,
+    pub sound: GBASound,
+}
+
+savestate!(GBA { enabled, waitcnt, postflg, sound });
+
+impl GBA {
+    pub const BIOS_SIZE: usize = 0x4000;
+    pub const EWRAM_SIZE: usize = 0x4_0000;
+    pub const IWRAM_SIZE: usize = 0x8000;
+    const FIFO_ADDRS: [u32; 2] = [0x0400_00A0, 0x0400_00A4];
+
+    pub fn new() -> Self {
+        GBA {
+            enabled: false,
+            bios: Vec::new(),
+            waitcnt: 0,
+            postflg: 0,
+            sound: GBASound::new(),
+        }
+    }
+}
+
+impl HW {
+    pub fn set_gba_bios(&mut self, bios: Vec<u8>) {
+        self.gba.bios = bios;
+    }
+
+    pub fn gba_mode(&self) -> bool {
+        self.gba.enabled
+    }
+
+    pub fn has_gba_bios(&self) -> bool {
+        self.gba.bios.len() == GBA::BIOS_SIZE
+    }
+
+    // Done by HALTCNT after the firmware has set up the screens. GBA EWRAM and IWRAM are main memory and shared WRAM
+    pub fn enter_gba_mode(&mut self) {
+        if self.gba.enabled { return }
+        info!("Entering GBA Mode");
+        self.gba.enabled = true;
+        self.gba.postflg = if self.has_gba_bios() { 0 } else { 1 };
+        self.main_mem[..GBA::EWRAM_SIZE].iter_mut().for_each(|byte| *byte = 0);
+        self.shared_wram[..GBA::IWRAM_SIZE].iter_mut().for_each(|byte| *byte = 0);
+        // The ARM7 owns the GBA slot
+        self.exmem.write_arm9(0x80);
+        self.interrupts[0] = InterruptController::new(HW::interrupt_sources(self.model, true, false));
+        self.dmas[0].enter_gba_mode();
+        self.timers[0].enter_gba_mode();
+        self.gpu.enter_gba_mode(&mut self.scheduler);
+    }
+
+    pub(super) fn on_gba_timer_overflow(&mut self, timer: usize) {
+        let requests = self.gba.sound.timer_overflow(timer);
+        for (i, requested) in requests.iter().enumerate() {
+            if *requested { self.run_sound_fifo_dmas(GBA::FIFO_ADDRS[i]) }
+        }
+    }
+
+    pub(super) fn generate_gba_audio_sample(&mut self) {
+        let sample = self.gba.sound.generate_sample();
+        self.spu.push_sample(sample);
+    }
+}
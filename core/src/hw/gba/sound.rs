@@ -0,0 +1,367 @@
+use std::collections::VecDeque;
+
+use super::super::spu::SPU;
+
+// Legacy PSG channels and DirectSound FIFOs, which replace the DS sound channels in GBA mode
+pub struct GBASound {
+    regs: [u8; GBASound::REGS_LEN],
+    wave_ram: [[u8; 0x10]; 2],
+    fifos: [VecDeque<i8>; 2],
+    fifo_samples: [i8; 2],
+    squares: [Square; 2],
+    wave: Wave,
+    noise: Noise,
+    sequencer_cycles: usize,
+    sequencer_step: usize,
+}
+
+savestate!(GBASound { regs, wave_ram, fifos, fifo_samples, squares, wave, noise, sequencer_cycles, sequencer_step });
+
+impl GBASound {
+    // Registers from 0x04000060 to 0x0400008F
+    const REGS_LEN: usize = 0x30;
+    const FIFO_LEN: usize = 32;
+    // Everything is clocked in 16.78 MHz GBA cycles
+    const CYCLES_PER_SAMPLE: usize = SPU::CLOCKS_PER_SAMPLE / 2;
+    const SEQUENCER_PERIOD: usize = (1 << 24) / 512;
+    const DUTY_CYCLES: [u8; 4] = [0b0000_0001, 0b1000_0001, 0b1000_0111, 0b0111_1110];
+    // Duty and envelope register, then frequency and control register
+    const SQUARE_REGS: [(usize, usize); 2] = [(0x02, 0x04), (0x08, 0x0C)];
+
+    pub fn new() -> Self {
+        let mut regs = [0; GBASound::REGS_LEN];
+        // SOUNDBIAS is set by the BIOS on boot
+        regs[0x29] = 0x02;
+        GBASound {
+            regs,
+            wave_ram: [[0; 0x10]; 2],
+            fifos: [VecDeque::with_capacity(GBASound::FIFO_LEN), VecDeque::with_capacity(GBASound::FIFO_LEN)],
+            fifo_samples: [0; 2],
+            squares: [Square::new(), Square::new()],
+            wave: Wave::new(),
+            noise: Noise::new(),
+            sequencer_cycles: 0,
+            sequencer_step: 0,
+        }
+    }
+
+    fn reg(&self, offset: usize) -> u16 {
+        u16::from_le_bytes([self.regs[offset], self.regs[offset + 1]])
+    }
+
+    fn master_enabled(&self) -> bool { self.regs[0x24] & 0x80 != 0 }
+
+    fn wave_bank(&self) -> usize { (self.regs[0x10] >> 6 & 0x1) as usize }
+
+    pub fn read(&self, addr: usize) -> u8 {
+        match addr {
+            0x60 ..= 0x83 | 0x86 ..= 0x8F => self.regs[addr - 0x60],
+            0x84 => self.regs[0x24] & 0x80 | (self.squares[0].enabled as u8) | (self.squares[1].enabled as u8) << 1 |
+                (self.wave.enabled as u8) << 2 | (self.noise.enabled as u8) << 3,
+            0x85 => 0,
+            // The CPU accesses the bank that isn't being played
+            0x90 ..= 0x9F => self.wave_ram[self.wave_bank() ^ 1][addr - 0x90],
+            0xA0 ..= 0xA7 => 0, // FIFOs are write-only
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn write(&mut self, addr: usize, value: u8) {
+        if !self.master_enabled() && (0x60..=0x81).contains(&addr) { return }
+        match addr {
+            0x60 ..= 0x8F => {
+                self.regs[addr - 0x60] = value;
+                match addr {
+                    0x65 => self.write_square_control(0, value),
+                    0x6D => self.write_square_control(1, value),
+                    0x64 | 0x6C => self.squares[(addr - 0x64) / 8].freq = self.reg(addr - 0x60) & 0x7FF,
+                    0x75 if value & 0x80 != 0 => self.wave.restart(256 - self.regs[0x12] as usize),
+                    0x7D if value & 0x80 != 0 => self.restart_noise(),
+                    0x83 => for i in 0..2 {
+                        if value >> (3 + 4 * i) & 0x1 != 0 { self.fifos[i].clear() }
+                    },
+                    0x84 if value & 0x80 == 0 => {
+                        // Disabling sound resets all PSG registers
+                        self.regs[..0x22].iter_mut().for_each(|reg| *reg = 0);
+                        self.squares = [Square::new(), Square::new()];
+                        self.wave = Wave::new();
+                        self.noise = Noise::new();
+                    },
+                    _ => (),
+                }
+            },
+            0x90 ..= 0x9F => { let bank = self.wave_bank() ^ 1; self.wave_ram[bank][addr - 0x90] = value },
+            0xA0 ..= 0xA7 => {
+                let fifo = &mut self.fifos[(addr - 0xA0) / 4];
+                if fifo.len() < GBASound::FIFO_LEN { fifo.push_back(value as i8) }
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    fn write_square_control(&mut self, i: usize, value: u8) {
+        let (duty_reg, freq_reg) = GBASound::SQUARE_REGS[i];
+        self.squares[i].freq = self.reg(freq_reg) & 0x7FF;
+        if value & 0x80 == 0 { return }
+        let envelope = self.reg(duty_reg) >> 8;
+        let square = &mut self.squares[i];
+        square.enabled = true;
+        square.timer = 0;
+        square.length = 64 - (self.regs[duty_reg] & 0x3F) as usize;
+        square.envelope.restart(envelope as u8);
+        square.sweep_timer = self.regs[0x00] >> 4 & 0x7;
+    }
+
+    fn restart_noise(&mut self) {
+        let noise = &mut self.noise;
+        noise.enabled = true;
+        noise.timer = 0;
+        noise.length = 64 - (self.regs[0x18] & 0x3F) as usize;
+        noise.envelope.restart(self.regs[0x19]);
+        noise.lfsr = if self.regs[0x1C] & 0x8 != 0 { 0x40 } else { 0x4000 };
+    }
+
+    // Returns the FIFOs that need to be refilled
+    pub fn timer_overflow(&mut self, timer: usize) -> [bool; 2] {
+        let mut requests = [false; 2];
+        for (i, request) in requests.iter_mut().enumerate() {
+            if (self.regs[0x23] >> (2 + 4 * i) & 0x1) as usize != timer { continue }
+            if let Some(sample) = self.fifos[i].pop_front() { self.fifo_samples[i] = sample }
+            *request = self.fifos[i].len() <= GBASound::FIFO_LEN / 2;
+        }
+        requests
+    }
+
+    fn clock_sequencer(&mut self) {
+        self.sequencer_cycles += GBASound::CYCLES_PER_SAMPLE;
+        while self.sequencer_cycles >= GBASound::SEQUENCER_PERIOD {
+            self.sequencer_cycles -= GBASound::SEQUENCER_PERIOD;
+            let step = self.sequencer_step;
+            self.sequencer_step = (self.sequencer_step + 1) % 8;
+            if step.is_multiple_of(2) {
+                let length_enabled = |control: u8| control & 0x40 != 0;
+                for i in 0..2 {
+                    let control = self.regs[GBASound::SQUARE_REGS[i].1 + 1];
+                    Self::clock_length(&mut self.squares[i].enabled, &mut self.squares[i].length, length_enabled(control));
+                }
+                Self::clock_length(&mut self.wave.enabled, &mut self.wave.length, length_enabled(self.regs[0x15]));
+                Self::clock_length(&mut self.noise.enabled, &mut self.noise.length, length_enabled(self.regs[0x1D]));
+            }
+            if step == 2 || step == 6 { self.clock_sweep() }
+            if step == 7 {
+                for i in 0..2 { self.squares[i].envelope.clock(self.regs[GBASound::SQUARE_REGS[i].0 + 1]) }
+                self.noise.envelope.clock(self.regs[0x19]);
+            }
+        }
+    }
+
+    fn clock_length(enabled: &mut bool, length: &mut usize, length_enabled: bool) {
+        if !length_enabled || *length == 0 { return }
+        *length -= 1;
+        if *length == 0 { *enabled = false }
+    }
+
+    fn clock_sweep(&mut self) {
+        let sweep = self.regs[0x00];
+        let (shift, decrease, time) = (sweep & 0x7, sweep & 0x8 != 0, sweep >> 4 & 0x7);
+        let square = &mut self.squares[0];
+        if time == 0 || !square.enabled { return }
+        square.sweep_timer = square.sweep_timer.saturating_sub(1);
+        if square.sweep_timer != 0 { return }
+        square.sweep_timer = time;
+        let delta = square.freq >> shift;
+        let freq = if decrease { square.freq.saturating_sub(delta) } else { square.freq + delta };
+        if freq > 0x7FF { square.enabled = false }
+        else if shift != 0 {
+            square.freq = freq;
+            self.regs[0x04] = freq as u8;
+            self.regs[0x05] = self.regs[0x05] & !0x7 | (freq >> 8) as u8;
+        }
+    }
+
+    fn square_output(&mut self, i: usize) -> i32 {
+        let duty = self.regs[GBASound::SQUARE_REGS[i].0] >> 6;
+        let square = &mut self.squares[i];
+        if !square.enabled { return 0 }
+        let period = (0x800 - square.freq as usize) * 16;
+        square.timer += GBASound::CYCLES_PER_SAMPLE;
+        square.duty_step = (square.duty_step + square.timer / period) % 8;
+        square.timer %= period;
+        let volume = square.envelope.volume as i32;
+        if GBASound::DUTY_CYCLES[duty as usize] >> square.duty_step & 0x1 != 0 { volume } else { -volume }
+    }
+
+    fn wave_output(&mut self) -> i32 {
+        if !self.wave.enabled || self.regs[0x10] & 0x80 == 0 { return 0 }
+        let two_banks = self.regs[0x10] & 0x20 != 0;
+        let num_samples = if two_banks { 64 } else { 32 };
+        let period = (0x800 - (self.reg(0x14) & 0x7FF) as usize) * 8;
+        self.wave.timer += GBASound::CYCLES_PER_SAMPLE;
+        self.wave.pos = (self.wave.pos + self.wave.timer / period) % num_samples;
+        self.wave.timer %= period;
+        let bank = (self.wave_bank() + self.wave.pos / 32) % 2;
+        let byte = self.wave_ram[bank][self.wave.pos % 32 / 2];
+        let sample = (if self.wave.pos.is_multiple_of(2) { byte >> 4 } else { byte & 0xF }) as i32 * 2 - 15;
+        let volume = self.regs[0x13];
+        if volume & 0x80 != 0 { return sample * 3 / 4 }
+        match volume >> 5 & 0x3 {
+            0 => 0,
+            1 => sample,
+            2 => sample / 2,
+            3 => sample / 4,
+            _ => unreachable!(),
+        }
+    }
+
+    fn noise_output(&mut self) -> i32 {
+        let control = self.regs[0x1C];
+        let noise = &mut self.noise;
+        if !noise.enabled { return 0 }
+        let (ratio, shift) = ((control & 0x7) as usize, (control >> 4) as usize);
+        if shift < 14 {
+            let period = if ratio == 0 { 16 } else { 32 * ratio } << (shift + 1);
+            noise.timer += GBASound::CYCLES_PER_SAMPLE;
+            for _ in 0..noise.timer / period {
+                noise.output = noise.lfsr & 0x1 != 0;
+                noise.lfsr >>= 1;
+                if noise.output { noise.lfsr ^= if control & 0x8 != 0 { 0x60 } else { 0x6000 } }
+            }
+            noise.timer %= period;
+        }
+        let volume = noise.envelope.volume as i32;
+        if noise.output { volume } else { -volume }
+    }
+
+    pub fn generate_sample(&mut self) -> (i16, i16) {
+        if !self.master_enabled() { return (0, 0) }
+        self.clock_sequencer();
+        let outputs = [self.square_output(0), self.square_output(1), self.wave_output(), self.noise_output()];
+        let (cnt_l, cnt_h) = (self.reg(0x20), self.reg(0x22));
+        // Right then left
+        let mut psg = [0, 0];
+        for (i, output) in outputs.iter().enumerate() {
+            if cnt_l >> (8 + i) & 0x1 != 0 { psg[0] += output }
+            if cnt_l >> (12 + i) & 0x1 != 0 { psg[1] += output }
+        }
+        let psg_shift = match cnt_h & 0x3 { 0 => 2, 1 => 1, _ => 0 };
+        let mut mix = [
+            (psg[0] * ((cnt_l & 0x7) as i32 + 1)) >> psg_shift,
+            (psg[1] * ((cnt_l >> 4 & 0x7) as i32 + 1)) >> psg_shift,
+        ];
+        for i in 0..2 {
+            let sample = (self.fifo_samples[i] as i32) << (1 + (cnt_h >> (2 + i) & 0x1));
+            if cnt_h >> (8 + 4 * i) & 0x1 != 0 { mix[0] += sample }
+            if cnt_h >> (9 + 4 * i) & 0x1 != 0 { mix[1] += sample }
+        }
+        // Output is 10 bits
+        let output = |sample: i32| (sample.clamp(-0x200, 0x1FF) << 6) as i16;
+        (output(mix[1]), output(mix[0]))
+    }
+}
+
+struct Envelope {
+    volume: u8,
+    timer: u8,
+}
+
+savestate!(Envelope { volume, timer });
+
+impl Envelope {
+    fn new() -> Self {
+        Envelope {
+            volume: 0,
+            timer: 0,
+        }
+    }
+
+    fn restart(&mut self, value: u8) {
+        self.volume = value >> 4;
+        self.timer = value & 0x7;
+    }
+
+    fn clock(&mut self, value: u8) {
+        let step_time = value & 0x7;
+        if step_time == 0 { return }
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer != 0 { return }
+        self.timer = step_time;
+        if value & 0x8 != 0 { self.volume = (self.volume + 1).min(0xF) } else { self.volume = self.volume.saturating_sub(1) }
+    }
+}
+
+struct Square {
+    enabled: bool,
+    freq: u16,
+    timer: usize,
+    duty_step: usize,
+    length: usize,
+    envelope: Envelope,
+    sweep_timer: u8,
+}
+
+savestate!(Square { enabled, freq, timer, duty_step, length, envelope, sweep_timer });
+
+impl Square {
+    fn new() -> Self {
+        Square {
+            enabled: false,
+            freq: 0,
+            timer: 0,
+            duty_step: 0,
+            length: 0,
+            envelope: Envelope::new(),
+            sweep_timer: 0,
+        }
+    }
+}
+
+struct Wave {
+    enabled: bool,
+    timer: usize,
+    pos: usize,
+    length: usize,
+}
+
+savestate!(Wave { enabled, timer, pos, length });
+
+impl Wave {
+    fn new() -> Self {
+        Wave {
+            enabled: false,
+            timer: 0,
+            pos: 0,
+            length: 0,
+        }
+    }
+
+    fn restart(&mut self, length: usize) {
+        self.enabled = true;
+        self.timer = 0;
+        self.pos = 0;
+        self.length = length;
+    }
+}
+
+struct Noise {
+    enabled: bool,
+    timer: usize,
+    lfsr: u16,
+    output: bool,
+    length: usize,
+    envelope: Envelope,
+}
+
+savestate!(Noise { enabled, timer, lfsr, output, length, envelope });
+
+impl Noise {
+    fn new() -> Self {
+        Noise {
+            enabled: false,
+            timer: 0,
+            lfsr: 0x4000,
+            output: false,
+            length: 0,
+            envelope: Envelope::new(),
+        }
+    }
+}
@@ -1,4 +1,34 @@
-use super::{Engine2D, EngineType, GPU, VRAM, engine2d::BGMode};
+use super::{Engine2D, EngineType, GPU, VRAM, engine2d::{BGMode, DISPCNTFlags, MapLayout}};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OBJMode {
+    Normal,
+    SemiTransparent,
+    Window,
+    Bitmap,
+}
+
+pub struct OAMEntry {
+    pub index: usize,
+    pub x: i16,
+    pub y: u8,
+    pub width: usize,
+    pub height: usize,
+    pub tile: usize,
+    pub palette: usize,
+    pub priority: u8,
+    pub mode: OBJMode,
+    pub bpp8: bool,
+    pub mosaic: bool,
+    pub affine: bool,
+    pub affine_param: usize, // Only used if affine
+    pub double_size: bool,
+    pub disabled: bool,
+    pub flip_x: bool,
+    pub flip_y: bool,
+    // Untransformed sprite, width * height
+    pub pixels: Vec<u16>,
+}
 
 impl GPU {
     pub fn render_palettes<F: Fn(usize) -> u16>(get_color: F, palettes_size: usize) -> (Vec<u16>, usize, usize) {
@@ -18,135 +48,275 @@ impl GPU {
         (pixels, size, size)
     }
 
-    pub fn render_tiles<E: EngineType, V, C>(vram: &VRAM, get_vram_byte: &V, get_color: &C, offset: usize, palette: usize,
-        extended: bool, bitmap: bool, bpp8: bool) -> (Vec<u16>, usize, usize)
+    pub fn render_bitmap_tiles<V: Fn(&VRAM, usize) -> u8>(vram: &VRAM, get_vram_byte: &V, base: usize)
+        -> (Vec<u16>, usize, usize) {
+        let start_addr = base * 0x4000;
+        let (width, height) = (256, 256);
+        let mut pixels = vec![0; width * height];
+        for (i, pixel) in pixels.iter_mut().enumerate() {
+            // TODO: Use u16 version of get_vram
+            *pixel = u16::from_le_bytes([
+                get_vram_byte(vram, start_addr + 2 * i),
+                get_vram_byte(vram, start_addr + 2 * i + 1),
+            ]);
+        }
+        (pixels, width, height)
+    }
+
+    pub fn render_tiles<E: EngineType, V, C>(vram: &VRAM, get_vram_byte: &V, get_color: &C, base: usize, palette: usize,
+        extended: bool, bpp8: bool) -> (Vec<u16>, usize, usize)
         where V: Fn(&VRAM, usize) -> u8, C: Fn(usize) -> u16 {
-        let tile_start_addr = offset * 128 * 0x400;
-        if bitmap {
-            let (width, height) = (256, 256);
-            let mut pixels = vec![0; width * height];
-            for (i, pixel) in pixels.iter_mut().enumerate() {
-                // TODO: Use u16 version of get_vram
-                *pixel = u16::from_le_bytes([
-                    get_vram_byte(vram, tile_start_addr + 2 * i),
-                    get_vram_byte(vram, tile_start_addr + 2 * i + 1),
-                ]);
-            }
-            (pixels, width, height)
-        } else {
-            let bpp8 = bpp8 || extended;
-            let (tiles_width, tiles_height) = if bpp8 { (64, 32) } else { (64, 64) };
-            let (width, height) = (tiles_width * 8, tiles_height * 8);
-            let mut pixels = vec![0; width * height];
-            let bit_depth = if bpp8 { 8 } else { 4 };
-            for tile_y in 0..tiles_height {
-                for tile_x in 0..tiles_width {
-                    let start_i = (tile_y * width + tile_x) * 8;
-                    let tile_num = tile_y * tiles_width + tile_x;
-                    let addr = tile_start_addr + 8 * bit_depth * tile_num;
-                    for y in 0..8 {
-                        let colors = Engine2D::<E>::get_colors_from_tile(&vram,
-                            get_vram_byte, addr, false, false, bit_depth,
-                            y, palette);
-                        for (x, (palette_num, color_num)) in colors.iter().enumerate() {
-                            if *color_num == 0 { continue }
-                            pixels[start_i + y * width + x] = 0x8000 | if extended {
-                                get_color(palette * 16 + color_num)
-                            } else {
-                                get_color(palette_num * 16 + color_num)
-                            }
+        let tile_start_addr = base * 0x4000;
+        let bpp8 = bpp8 || extended;
+        let (tiles_width, tiles_height) = if bpp8 { (64, 32) } else { (64, 64) };
+        let (width, height) = (tiles_width * 8, tiles_height * 8);
+        let mut pixels = vec![0; width * height];
+        let bit_depth = if bpp8 { 8 } else { 4 };
+        for tile_y in 0..tiles_height {
+            for tile_x in 0..tiles_width {
+                let start_i = (tile_y * width + tile_x) * 8;
+                let tile_num = tile_y * tiles_width + tile_x;
+                let addr = tile_start_addr + 8 * bit_depth * tile_num;
+                for y in 0..8 {
+                    let colors = Engine2D::<E>::get_colors_from_tile(&vram,
+                        get_vram_byte, addr, false, false, bit_depth,
+                        y, palette);
+                    for (x, (palette_num, color_num)) in colors.iter().enumerate() {
+                        if *color_num == 0 { continue }
+                        pixels[start_i + y * width + x] = 0x8000 | if extended {
+                            get_color(palette * 16 + color_num)
+                        } else {
+                            get_color(palette_num * 16 + color_num)
                         }
                     }
                 }
             }
-            (pixels, width, height)
         }
+        (pixels, width, height)
     }
 }
 
 impl<E: EngineType> Engine2D<E> {
+    fn bg_vram_size() -> usize { if E::is_a() { 0x8_0000 } else { 0x2_0000 } }
+    fn obj_vram_size() -> usize { if E::is_a() { 0x4_0000 } else { 0x2_0000 } }
+
     pub fn render_map(&self, vram: &VRAM, bg_i: usize) -> (Vec<u16>, usize, usize) {
         let bgcnt = self.bgcnts[bg_i];
-        let affine = match self.dispcnt.bg_mode {
-            BGMode::Mode0 => false,
-            BGMode::Mode1 => bg_i == 3,
-            BGMode::Mode2 => bg_i >= 2,
-            BGMode::Mode4 => bg_i == 2,
-            _ => false,
-        };
-        let (width, height) = match bgcnt.screen_size {
-            0 => [(256, 256), (128, 128)][affine as usize],
-            1 => [(512, 256), (256, 256)][affine as usize],
-            2 => [(256, 512), (512, 512)][affine as usize],
-            3 => [(512, 512), (1024, 1024)][affine as usize],
-            _ => unreachable!(),
-        };
+        match (self.dispcnt.bg_mode, bg_i) {
+            (BGMode::Mode1, 3) | (BGMode::Mode2, 2..=3) | (BGMode::Mode4, 2) => self.render_affine_map(vram, bg_i, false),
+            (BGMode::Mode3, 3) | (BGMode::Mode4, 3) | (BGMode::Mode5, 2..=3) => {
+                if !bgcnt.bpp8 { return self.render_affine_map(vram, bg_i, true) }
+                let (width, height) = [(128, 128), (256, 256), (512, 256), (512, 512)][bgcnt.screen_size as usize];
+                self.render_bitmap_map(vram, bgcnt.map_block as usize * 0x4000, width, height,
+                    bgcnt.tile_block & 0x1 != 0)
+            },
+            (BGMode::Mode6, 2) => {
+                let (width, height) = if bgcnt.screen_size & 0x1 == 0 { (512, 1024) } else { (1024, 512) };
+                self.render_bitmap_map(vram, 0, width, height, false)
+            },
+            _ => self.render_text_map(vram, bg_i),
+        }
+    }
+
+    fn render_text_map(&self, vram: &VRAM, bg_i: usize) -> (Vec<u16>, usize, usize) {
+        let bgcnt = self.bgcnts[bg_i];
+        let (width, height) = [(256, 256), (512, 256), (256, 512), (512, 512)][bgcnt.screen_size as usize];
         let mut pixels = vec![0u16; width * height];
         let tile_start_addr = self.calc_tile_start_addr(&bgcnt);
         let map_start_addr = self.calc_map_start_addr(&bgcnt);
-        let bit_depth = if bgcnt.bpp8 || affine { 8 } else { 4 }; // Also bytes per row of tile
+        let bit_depth = if bgcnt.bpp8 { 8 } else { 4 }; // Also bytes per row of tile
 
         for y in 0..height {
             for x in 0..width {
-                if affine {
-                    // Get Screen Entry
-                    let map_x = x / 8;
-                    let map_y = y / 8;
-                    let addr = map_start_addr + map_y * width / 8 + map_x;
-                    let tile_num = vram.get_bg::<E, u8>(addr) as usize;
-                    
-                    // Convert from tile to pixels
-                    let (_, color_num) = Engine2D::<E>::get_color_from_tile(vram, VRAM::get_bg::<E, u8>,
-                        tile_start_addr + 8 * bit_depth * tile_num, false, false, bit_depth,
-                        x % 8, y % 8, 0);
-                    if color_num == 0 { continue }
-                    pixels[y * width + x] = self.bg_palettes()[color_num] | 0x8000;
+                // Get Screen Entry
+                let map_x = x / 8;
+                let map_y = y / 8;
+                let x_overflowed = (map_x / 32) % 2 == 1;
+                let y_overflowed = (map_y / 32) % 2 == 1;
+                let map_start_addr = map_start_addr + match bgcnt.screen_size {
+                    0 => 0,
+                    1 => if x_overflowed { 0x800 } else { 0 },
+                    2 => if y_overflowed { 0x800 } else { 0 },
+                    3 => if x_overflowed && y_overflowed { 0x800 * 3 }
+                        else if y_overflowed { 0x800 * 2 }
+                        else if x_overflowed { 0x800 }
+                        else { 0 },
+                    _ => unreachable!(),
+                };
+                let layout = MapLayout {
+                    map_start_addr: map_start_addr % Self::bg_vram_size(),
+                    tile_start_addr,
+                    bit_depth,
+                    map_size: 32 * 8,
+                };
+                pixels[y * width + x] = self.render_16bit_entry(vram, bg_i, layout, (map_x % 32, map_y % 32), (x, y));
+            }
+        }
+        (pixels, width, height)
+    }
+
+    fn render_affine_map(&self, vram: &VRAM, bg_i: usize, extended: bool) -> (Vec<u16>, usize, usize) {
+        let bgcnt = self.bgcnts[bg_i];
+        let size = 128 << bgcnt.screen_size;
+        let mut pixels = vec![0u16; size * size];
+        let layout = MapLayout {
+            map_start_addr: self.calc_map_start_addr(&bgcnt),
+            tile_start_addr: self.calc_tile_start_addr(&bgcnt),
+            bit_depth: 8,
+            map_size: size,
+        };
+
+        for y in 0..size {
+            for x in 0..size {
+                pixels[y * size + x] = if extended {
+                    self.render_16bit_entry(vram, bg_i, layout, (x / 8, y / 8), (x, y))
                 } else {
-                    // Get Screen Entry
-                    let map_x = x / 8;
-                    let map_y = y / 8;
-                    let map_start_addr = map_start_addr + match bgcnt.screen_size {
-                        0 => 0,
-                        1 => if (map_x / 32) % 2 == 1 { 0x800 } else { 0 },
-                        2 => if (map_y / 32) % 2 == 1 { 0x800 } else { 0 },
-                        3 => {
-                            let x_overflowed = (map_x / 32) % 2 == 1;
-                            let y_overflowed = (map_y / 32) % 2 == 1;
-                            if x_overflowed && y_overflowed { 0x800 * 3 }
-                            else if y_overflowed { 0x800 * 2 }
-                            else if x_overflowed { 0x800 * 1 }
-                            else { 0 }
-                        },
-                        _ => unreachable!(),
-                    };
-                    pixels[y * width + x] = self.render_16bit_entry(vram, bg_i, map_start_addr, tile_start_addr, bit_depth,
-                        32 * 8, map_x, map_y, x, y);
-                }
+                    self.render_8bit_entry(vram, bg_i, layout, (x / 8, y / 8), (x, y))
+                };
             }
         }
+        (pixels, size, size)
+    }
+
+    fn render_bitmap_map(&self, vram: &VRAM, start_addr: usize, width: usize, height: usize,
+        direct_color: bool) -> (Vec<u16>, usize, usize) {
+        let mut pixels = vec![0u16; width * height];
+        for (i, pixel) in pixels.iter_mut().enumerate() {
+            *pixel = if direct_color {
+                vram.get_bg::<E, u16>((start_addr + 2 * i) % Self::bg_vram_size())
+            } else {
+                let color_num = vram.get_bg::<E, u8>((start_addr + i) % Self::bg_vram_size()) as usize;
+                if color_num == 0 { continue }
+                self.bg_palettes()[color_num] | 0x8000
+            };
+        }
         (pixels, width, height)
     }
 
     pub fn render_tiles(&self, vram: &VRAM, is_bg: bool, extended: bool, bitmap: bool, bpp8: bool, slot: usize,
-        palette: usize, offset: usize) -> (Vec<u16>, usize, usize) {
+        palette: usize, base: usize) -> (Vec<u16>, usize, usize) {
         if is_bg {
-            if extended {
-                GPU::render_tiles::<E, _, _>(vram, &VRAM::get_bg::<E, u8>,
+            let get_byte = |vram: &VRAM, addr: usize| vram.get_bg::<E, u8>(addr % Self::bg_vram_size());
+            if bitmap {
+                GPU::render_bitmap_tiles(vram, &get_byte, base)
+            } else if extended {
+                GPU::render_tiles::<E, _, _>(vram, &get_byte,
                 &|i| vram.get_bg_ext_pal::<E>(slot, i),
-                offset, palette, extended, bitmap, bpp8)
+                base, palette, extended, bpp8)
             } else {
-                GPU::render_tiles::<E, _, _>(vram, &VRAM::get_bg::<E, u8>,
-                &|i| self.bg_palettes()[i], offset, palette, extended, bitmap, bpp8)
+                GPU::render_tiles::<E, _, _>(vram, &get_byte,
+                &|i| self.bg_palettes()[i], base, palette, extended, bpp8)
             }
         } else {
-            if extended {
-                GPU::render_tiles::<E, _, _>(vram, &VRAM::get_obj::<E, u8>,
+            let get_byte = |vram: &VRAM, addr: usize| vram.get_obj::<E, u8>(addr % Self::obj_vram_size());
+            if bitmap {
+                GPU::render_bitmap_tiles(vram, &get_byte, base)
+            } else if extended {
+                GPU::render_tiles::<E, _, _>(vram, &get_byte,
                 &|i| vram.get_obj_ext_pal::<E>(i),
-                offset, palette, extended, bitmap, bpp8)
+                base, palette, extended, bpp8)
+            } else {
+                GPU::render_tiles::<E, _, _>(vram, &get_byte,
+                &|i| self.obj_palettes()[i], base, palette, extended, bpp8)
+            }
+        }
+    }
+
+    pub fn oam_entries(&self, vram: &VRAM) -> Vec<OAMEntry> {
+        self.oam.chunks(8).enumerate().map(|(index, chunk)| {
+            let attrs = [
+                u16::from_le_bytes([chunk[0], chunk[1]]),
+                u16::from_le_bytes([chunk[2], chunk[3]]),
+                u16::from_le_bytes([chunk[4], chunk[5]]),
+            ];
+            let affine = attrs[0] >> 8 & 0x1 != 0;
+            let obj_shape = (attrs[0] >> 14 & 0x3) as usize;
+            let obj_size = (attrs[1] >> 14 & 0x3) as usize;
+            // Shape 3 is prohibited
+            let (width, height) = Engine2D::<E>::OBJ_SIZES[obj_size].get(obj_shape)
+                .map_or((0, 0), |(width, height)| (*width as usize, *height as usize));
+            let obj_x = attrs[1] & 0x1FF;
+            let mut entry = OAMEntry {
+                index,
+                x: if obj_x & 0x100 != 0 { 0xFE00 | obj_x } else { obj_x } as i16,
+                y: attrs[0] as u8,
+                width,
+                height,
+                tile: (attrs[2] & 0x3FF) as usize,
+                palette: (attrs[2] >> 12 & 0xF) as usize,
+                priority: (attrs[2] >> 10 & 0x3) as u8,
+                mode: match attrs[0] >> 10 & 0x3 {
+                    0 => OBJMode::Normal,
+                    1 => OBJMode::SemiTransparent,
+                    2 => OBJMode::Window,
+                    3 => OBJMode::Bitmap,
+                    _ => unreachable!(),
+                },
+                bpp8: attrs[0] >> 13 & 0x1 != 0,
+                mosaic: attrs[0] >> 12 & 0x1 != 0,
+                affine,
+                affine_param: (attrs[1] >> 9 & 0x1F) as usize,
+                double_size: affine && attrs[0] >> 9 & 0x1 != 0,
+                disabled: !affine && attrs[0] >> 9 & 0x1 != 0,
+                flip_x: !affine && attrs[1] >> 12 & 0x1 != 0,
+                flip_y: !affine && attrs[1] >> 13 & 0x1 != 0,
+                pixels: Vec::new(),
+            };
+            entry.pixels = self.render_obj(vram, &entry);
+            entry
+        }).collect()
+    }
+
+    fn render_obj(&self, vram: &VRAM, entry: &OAMEntry) -> Vec<u16> {
+        let get_byte = |vram: &VRAM, addr: usize| vram.get_obj::<E, u8>(addr % Self::obj_vram_size());
+        let mut pixels = vec![0; entry.width * entry.height];
+        if entry.mode == OBJMode::Bitmap {
+            let (tile_start_addr, row_width) = if self.dispcnt.contains(DISPCNTFlags::BITMAP_OBJ_1D) {
+                // Reserved, displays nothing
+                if self.dispcnt.contains(DISPCNTFlags::BITMAP_OBJ_SQUARE) { return pixels }
+                let boundary = if self.dispcnt.contains(DISPCNTFlags::BITMAP_OBJ_1D_BOUND) { 256 } else { 128 };
+                (entry.tile * boundary, entry.width)
             } else {
-                GPU::render_tiles::<E, _, _>(vram, &VRAM::get_obj::<E, u8>,
-                &|i| self.obj_palettes()[i], offset, palette, extended, bitmap, bpp8)
+                let (mask_x, row_width) = if self.dispcnt.contains(DISPCNTFlags::BITMAP_OBJ_SQUARE) {
+                    (0x1F, 256)
+                } else { (0x0F, 128) };
+                ((entry.tile & mask_x) * 0x10 + (entry.tile & !mask_x) * 0x80, row_width)
+            };
+            for y in 0..entry.height {
+                for x in 0..entry.width {
+                    let addr = tile_start_addr + 2 * (y * row_width + x);
+                    let color = u16::from_le_bytes([get_byte(vram, addr), get_byte(vram, addr + 1)]);
+                    if color & 0x8000 != 0 { pixels[y * entry.width + x] = color }
+                }
+            }
+            return pixels
+        }
+
+        let bit_depth = if entry.bpp8 { 8 } else { 4 };
+        for tile_y in 0..entry.height / 8 {
+            for tile_x in 0..entry.width / 8 {
+                let (boundary, tile_offset) = if self.dispcnt.contains(DISPCNTFlags::TILE_OBJ_1D) {
+                    (32 << self.dispcnt.tile_obj_1d_bound, tile_y * entry.width / 8 + tile_x)
+                } else { (32, tile_y * 0x80 / bit_depth + tile_x) };
+                let addr = boundary * entry.tile + tile_offset * bit_depth * 8;
+                for y in 0..8 {
+                    let colors = Engine2D::<E>::get_colors_from_tile(vram, get_byte, addr, false, false,
+                        bit_depth, y, entry.palette);
+                    for (x, (palette_num, color_num)) in colors.iter().enumerate() {
+                        if *color_num == 0 { continue }
+                        let color = if entry.bpp8 && self.dispcnt.contains(DISPCNTFlags::OBJ_EXTENDED_PALETTES) {
+                            vram.get_obj_ext_pal::<E>(entry.palette * 256 + color_num)
+                        } else { self.obj_palettes()[palette_num * 16 + color_num] };
+                        let (x, y) = (tile_x * 8 + x, tile_y * 8 + y);
+                        let (x, y) = (
+                            if entry.flip_x { entry.width - 1 - x } else { x },
+                            if entry.flip_y { entry.height - 1 - y } else { y },
+                        );
+                        pixels[y * entry.width + x] = color | 0x8000;
+                    }
+                }
             }
         }
+        pixels
     }
 }
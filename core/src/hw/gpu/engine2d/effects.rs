@@ -0,0 +1,79 @@
+#[cfg(feature = "simd")]
+use std::simd::{cmp::{SimdOrd, SimdPartialEq}, Select, u16x16};
+
+#[cfg(feature = "simd")]
+use super::registers::MasterBrightMode;
+use super::{registers::ColorSFX, GPU};
+
+const ALPHA_BLEND: u16 = ColorSFX::AlphaBlend as u16;
+const BRIGHTNESS_INC: u16 = ColorSFX::BrightnessInc as u16;
+const BRIGHTNESS_DEC: u16 = ColorSFX::BrightnessDec as u16;
+
+// Applies the ColorSFX of each pixel to the top colors of a line, which are alpha blended with the colors below them
+#[cfg(not(feature = "simd"))]
+pub fn color_effects(tops: &[u16; GPU::WIDTH], bottoms: &[u16; GPU::WIDTH], special_effects: &[u16; GPU::WIDTH],
+    eva: u16, evb: u16, evy: u16, line: &mut [u16; GPU::WIDTH]) {
+    for (dot_x, pixel) in line.iter_mut().enumerate() {
+        let (top, bottom) = (tops[dot_x], bottoms[dot_x]);
+        *pixel = match special_effects[dot_x] {
+            ALPHA_BLEND => 0x8000 | map_channels(top, bottom, |top, bottom|
+                std::cmp::min(0x1F, (top * eva + bottom * evb) >> 4)
+            ),
+            BRIGHTNESS_INC => 0x8000 | map_channels(top, bottom, |top, _| top + (((0x1F - top) * evy) >> 4)),
+            BRIGHTNESS_DEC => 0x8000 | map_channels(top, bottom, |top, _| top - ((top * evy) >> 4)),
+            _ => top,
+        };
+    }
+}
+
+#[cfg(not(feature = "simd"))]
+fn map_channels<F: Fn(u16, u16) -> u16>(a: u16, b: u16, f: F) -> u16 {
+    (0..3).rev().fold(0, |color, i| color << 5 | f(a >> (5 * i) & 0x1F, b >> (5 * i) & 0x1F) & 0x1F)
+}
+
+// Every effect is calculated for a group of pixels and then each pixel picks the one it uses
+#[cfg(feature = "simd")]
+type Lanes = u16x16;
+
+#[cfg(feature = "simd")]
+pub fn color_effects(tops: &[u16; GPU::WIDTH], bottoms: &[u16; GPU::WIDTH], special_effects: &[u16; GPU::WIDTH],
+    eva: u16, evb: u16, evy: u16, line: &mut [u16; GPU::WIDTH]) {
+    let (eva, evb, evy) = (Lanes::splat(eva), Lanes::splat(evb), Lanes::splat(evy));
+    let (max, opaque) = (Lanes::splat(0x1F), Lanes::splat(0x8000));
+    let chunks = line.chunks_exact_mut(Lanes::LEN).zip(tops.chunks_exact(Lanes::LEN))
+        .zip(bottoms.chunks_exact(Lanes::LEN)).zip(special_effects.chunks_exact(Lanes::LEN));
+    for (((line, tops), bottoms), special_effects) in chunks {
+        let (top, bottom) = (Lanes::from_slice(tops), Lanes::from_slice(bottoms));
+        let special_effect = Lanes::from_slice(special_effects);
+        let alpha_blended = map_channels(top, bottom, |top, bottom| ((top * eva + bottom * evb) >> 4).simd_min(max));
+        let brightened = map_channels(top, bottom, |top, _| top + (((max - top) * evy) >> 4));
+        let darkened = map_channels(top, bottom, |top, _| top - ((top * evy) >> 4));
+        let color = special_effect.simd_eq(Lanes::splat(ALPHA_BLEND)).select(opaque | alpha_blended,
+            special_effect.simd_eq(Lanes::splat(BRIGHTNESS_INC)).select(opaque | brightened,
+            special_effect.simd_eq(Lanes::splat(BRIGHTNESS_DEC)).select(opaque | darkened, top)));
+        color.copy_to_slice(line);
+    }
+}
+
+#[cfg(feature = "simd")]
+pub fn master_brightness(mode: MasterBrightMode, factor: u8, line: &mut [u16; GPU::WIDTH]) {
+    let (max, factor) = (Lanes::splat(0x1F), Lanes::splat(factor as u16));
+    for line in line.chunks_exact_mut(Lanes::LEN) {
+        let color = Lanes::from_slice(line);
+        let new_color = color & Lanes::splat(0x8000) | match mode {
+            MasterBrightMode::Disable => return,
+            MasterBrightMode::Up => map_channels(color, color, |channel, _| channel + (((max - channel) * factor) >> 4)),
+            MasterBrightMode::Down => map_channels(color, color, |channel, _| channel - ((channel * factor) >> 4)),
+        };
+        new_color.copy_to_slice(line);
+    }
+}
+
+#[cfg(feature = "simd")]
+fn map_channels<F: Fn(Lanes, Lanes) -> Lanes>(a: Lanes, b: Lanes, f: F) -> Lanes {
+    let mask = Lanes::splat(0x1F);
+    (0..3).rev().fold(Lanes::splat(0), |color, i| {
+        let shift = Lanes::splat(5 * i);
+        color << Lanes::splat(5) | f(a >> shift & mask, b >> shift & mask) & mask
+    })
+}
@@ -1,10 +1,14 @@
+mod effects;
 mod registers;
 
-pub use registers::{BGMode, DisplayMode};
+use std::collections::VecDeque;
+
+pub use registers::{BGMode, DISPCNTFlags, DisplayMode};
 
 use registers::*;
 use super::{EngineType, Engine3D, GPU, VRAM};
-use crate::hw::{mem::IORegister, Scheduler};
+use crate::fault::{Fault, Faults};
+use crate::hw::{mem::IORegister, MemoryValue, Scheduler, HW};
 
 pub struct Engine2D<E: EngineType> {
     // Registers
@@ -22,7 +26,7 @@ pub struct Engine2D<E: EngineType> {
     bgxs_latch: [ReferencePointCoord; 2],
     bgys_latch: [ReferencePointCoord; 2],
     mosaic: MOSAIC,
-    pub master_bright: MasterBright,
+    master_bright: MasterBright,
     // Windows
     winhs: [WindowDimensions; 2],
     winvs: [WindowDimensions; 2],
@@ -38,17 +42,34 @@ pub struct Engine2D<E: EngineType> {
     // Palettes
     bg_palettes: Vec<u16>,
     obj_palettes: Vec<u16>,
-    pub oam: Vec<u8>,
+    pub(super) oam: Vec<u8>,
 
     // Important Rendering Variables
     pixels: Vec<u16>,
+    // Writes that could change what's drawn, so a line is only redrawn if there was one since it was last drawn
+    writes: u64,
+    written_registers: [Option<u8>; 0x70],
+    drawn_lines: Vec<Option<DrawnLine>>,
     bg_lines: [[u16; GPU::WIDTH]; 4],
     objs_line: [OBJPixel; GPU::WIDTH],
     windows_lines: [[bool; GPU::WIDTH]; 3],
+    // Main Memory Display
+    main_mem_fifo: VecDeque<u16>,
+    main_mem_fifo_word: u32,
+    main_mem_fifo_requests: usize,
+    // GBA Mode
+    gba_mode: bool,
+    gba_dispcnt: u16,
+    faults: Faults,
 }
 
+// Line buffers are scratch space refilled for every line
+savestate!([E: EngineType] Engine2D<E> { dispcnt, bgcnts, hofs, vofs, dxs, dmxs, dys, dmys, bgxs, bgys, bgxs_latch, bgys_latch, mosaic,
+    master_bright, winhs, winvs, win_0_cnt, win_1_cnt, win_out_cnt, win_obj_cnt, bldcnt, bldalpha, bldy, bg_palettes, obj_palettes, oam,
+    pixels, main_mem_fifo, main_mem_fifo_word, main_mem_fifo_requests, gba_mode, gba_dispcnt });
+
 impl<E: EngineType> Engine2D<E> {
-    pub fn new() -> Self {
+    pub fn new(faults: Faults) -> Self {
         Engine2D {
             // Registers
             dispcnt: DISPCNT::new(),
@@ -85,13 +106,36 @@ impl<E: EngineType> Engine2D<E> {
 
             // Important Rendering Variables
             pixels: vec![0; GPU::WIDTH * GPU::HEIGHT],
+            writes: 0,
+            written_registers: [None; 0x70],
+            drawn_lines: vec![None; GPU::HEIGHT],
             bg_lines: [[0; GPU::WIDTH]; 4],
             objs_line: [OBJPixel::none(); GPU::WIDTH],
             windows_lines: [[false; GPU::WIDTH]; 3],
+            // Main Memory Display
+            main_mem_fifo: VecDeque::with_capacity(GPU::WIDTH),
+            main_mem_fifo_word: 0,
+            main_mem_fifo_requests: 0,
+            // GBA Mode
+            gba_mode: false,
+            gba_dispcnt: 0,
+            faults,
+        }
+    }
+
+    pub fn enter_gba_mode(&mut self, scheduler: &mut Scheduler) {
+        self.gba_mode = true;
+        self.dispcnt = DISPCNT::new();
+        self.dispcnt.write(scheduler, 2, DisplayMode::Mode1 as u8);
+        self.write_gba_dispcnt(scheduler, 0, 0x80);
+        self.write_gba_dispcnt(scheduler, 1, 0x00);
+        // BG2PA, BG2PD, BG3PA and BG3PD start at 1.0
+        for addr in [0x0400_0021, 0x0400_0027, 0x0400_0031, 0x0400_0037].iter() {
+            self.write_register(scheduler, *addr, 0x01);
         }
     }
 
-    const OBJ_SIZES: [[(i16, u16); 3]; 4] = [
+    pub(super) const OBJ_SIZES: [[(i16, u16); 3]; 4] = [
         [(8, 8), (16, 8), (8, 16)],
         [(16, 16), (32, 8), (8, 32)],
         [(32, 32), (32, 16), (16, 32)],
@@ -99,6 +143,32 @@ impl<E: EngineType> Engine2D<E> {
     ];
 
     pub fn render_line(&mut self, engine3d: &Engine3D, vram: &VRAM, vcount: u16) {
+        let line = vcount as usize;
+        let writes = (self.writes, vram.writes);
+        // The main memory FIFO and 3D are drawn from data that changes without any writes
+        let uses_3d = E::is_a() && self.dispcnt.contains(DISPCNTFlags::IS_3D | DISPCNTFlags::DISPLAY_BG0);
+        if self.dispcnt.display_mode != DisplayMode::Mode3 && !uses_3d {
+            if let Some(drawn_line) = self.drawn_lines[line].filter(|drawn_line| drawn_line.writes == writes) {
+                self.bgxs_latch = drawn_line.bgxs_latch;
+                self.bgys_latch = drawn_line.bgys_latch;
+                return
+            }
+        }
+        self.draw_line(engine3d, vram, vcount);
+        self.drawn_lines[line] = Some(DrawnLine {
+            writes,
+            bgxs_latch: self.bgxs_latch,
+            bgys_latch: self.bgys_latch,
+        });
+    }
+
+    // Lines are redrawn after a savestate is loaded since the writes aren't saved
+    pub fn invalidate_lines(&mut self) {
+        self.written_registers = [None; 0x70];
+        for drawn_line in self.drawn_lines.iter_mut() { *drawn_line = None }
+    }
+
+    fn draw_line(&mut self, engine3d: &Engine3D, vram: &VRAM, vcount: u16) {
         match self.dispcnt.display_mode {
             DisplayMode::Mode0 => for dot_x in 0..GPU::WIDTH {
                 self.set_pixel(vcount, dot_x, 0xFFFF);
@@ -111,7 +181,38 @@ impl<E: EngineType> Engine2D<E> {
                 } else { 0 };
                 self.set_pixel(vcount, dot_x, color);
             },
-            DisplayMode::Mode3 => todo!(),
+            DisplayMode::Mode3 => for dot_x in 0..GPU::WIDTH {
+                let color = self.main_mem_fifo.pop_front().unwrap_or(0);
+                self.set_pixel(vcount, dot_x, color);
+            },
+        }
+    }
+
+    pub fn displays_main_mem(&self) -> bool {
+        self.dispcnt.display_mode == DisplayMode::Mode3
+    }
+
+    pub fn request_main_mem_data(&mut self) -> bool {
+        // Each request moves 4 words (8 pixels) into the FIFO
+        if self.dispcnt.display_mode != DisplayMode::Mode3 || self.main_mem_fifo.len() >= GPU::WIDTH ||
+            self.main_mem_fifo_requests >= GPU::WIDTH / 8 { return false }
+        self.main_mem_fifo_requests += 1;
+        true
+    }
+
+    pub fn start_main_mem_line(&mut self) {
+        self.main_mem_fifo_requests = 0;
+    }
+
+    pub fn clear_main_mem_fifo(&mut self) {
+        self.main_mem_fifo.clear();
+    }
+
+    pub fn write_main_mem_fifo(&mut self, byte: usize, value: u8) {
+        HW::write_byte_to_value(&mut self.main_mem_fifo_word, byte, value);
+        if byte == 3 {
+            self.main_mem_fifo.push_back(self.main_mem_fifo_word as u16);
+            self.main_mem_fifo.push_back((self.main_mem_fifo_word >> 16) as u16);
         }
     }
 
@@ -120,6 +221,7 @@ impl<E: EngineType> Engine2D<E> {
         if self.dispcnt.contains(DISPCNTFlags::DISPLAY_WINDOW0) { self.render_window(vcount, 0) }
         if self.dispcnt.contains(DISPCNTFlags::DISPLAY_WINDOW1) { self.render_window(vcount, 1) }
         if self.dispcnt.contains(DISPCNTFlags::DISPLAY_OBJ) { self.render_objs_line(vram, vcount) }
+        if self.gba_mode { return self.render_gba_bgs(vram, vcount) }
 
         match self.dispcnt.bg_mode {
             BGMode::Mode0 => {
@@ -164,10 +266,59 @@ impl<E: EngineType> Engine2D<E> {
                 if self.dispcnt.contains(DISPCNTFlags::DISPLAY_BG3) { self.render_extended_line(vram, 3) }
                 self.process_lines(vcount, 0, 3);
             },
-            BGMode::Mode6 => todo!(),
+            BGMode::Mode6 => self.faults.raise(Fault::Unimplemented("BG Mode 6".to_string())),
         }
     }
     
+    fn render_gba_bgs(&mut self, vram: &VRAM, vcount: u16) {
+        let affine_render_fn = Engine2D::<E>::render_8bit_entry;
+        match self.dispcnt.bg_mode {
+            BGMode::Mode0 => {
+                if self.dispcnt.contains(DISPCNTFlags::DISPLAY_BG0) { self.render_text_line(vram, vcount, 0) }
+                if self.dispcnt.contains(DISPCNTFlags::DISPLAY_BG1) { self.render_text_line(vram, vcount, 1) }
+                if self.dispcnt.contains(DISPCNTFlags::DISPLAY_BG2) { self.render_text_line(vram, vcount, 2) }
+                if self.dispcnt.contains(DISPCNTFlags::DISPLAY_BG3) { self.render_text_line(vram, vcount, 3) }
+                self.process_lines(vcount, 0, 3);
+            },
+            BGMode::Mode1 => {
+                if self.dispcnt.contains(DISPCNTFlags::DISPLAY_BG0) { self.render_text_line(vram, vcount, 0) }
+                if self.dispcnt.contains(DISPCNTFlags::DISPLAY_BG1) { self.render_text_line(vram, vcount, 1) }
+                if self.dispcnt.contains(DISPCNTFlags::DISPLAY_BG2) { self.render_affine_line(vram, 2, affine_render_fn) }
+                self.process_lines(vcount, 0, 2);
+            },
+            BGMode::Mode2 => {
+                if self.dispcnt.contains(DISPCNTFlags::DISPLAY_BG2) { self.render_affine_line(vram, 2, affine_render_fn) }
+                if self.dispcnt.contains(DISPCNTFlags::DISPLAY_BG3) { self.render_affine_line(vram, 3, affine_render_fn) }
+                self.process_lines(vcount, 2, 3);
+            },
+            BGMode::Mode3 | BGMode::Mode4 | BGMode::Mode5 => {
+                if self.dispcnt.contains(DISPCNTFlags::DISPLAY_BG2) { self.render_gba_bitmap_line(vram) }
+                self.process_lines(vcount, 2, 2);
+            },
+            BGMode::Mode6 => unreachable!(),
+        }
+    }
+
+    fn render_gba_bitmap_line(&mut self, vram: &VRAM) {
+        let mut base_x = self.bgxs_latch[0];
+        let mut base_y = self.bgys_latch[0];
+        self.bgxs_latch[0] += self.dmxs[0];
+        self.bgys_latch[0] += self.dmys[0];
+        let bg_mode = self.dispcnt.bg_mode;
+        let frame_addr = if bg_mode != BGMode::Mode3 && self.gba_dispcnt & 0x10 != 0 { 0xA000 } else { 0 };
+        let (width, height) = if bg_mode == BGMode::Mode5 { (160, 128) } else { (240, 160) };
+        for dot_x in 0..GPU::WIDTH {
+            let (x, y) = (base_x.integer(), base_y.integer());
+            base_x += self.dxs[0];
+            base_y += self.dys[0];
+            self.bg_lines[2][dot_x] = if x < 0 || x >= width || y < 0 || y >= height { 0 } // Transparent Color
+            else if bg_mode == BGMode::Mode4 {
+                let color_num = vram.gba_read::<u8>(frame_addr + (y * width + x) as usize) as usize;
+                if color_num == 0 { 0 } else { self.bg_palettes[color_num] | 0x8000 }
+            } else { vram.gba_read::<u16>(frame_addr + 2 * (y * width + x) as usize) | 0x8000 };
+        }
+    }
+
     fn process_lines(&mut self, vcount: u16, start_line: usize, end_line: usize) {
         let mut bgs : Vec<(usize, u8)> = Vec::new();
         for bg_i in start_line..=end_line {
@@ -181,6 +332,9 @@ impl<E: EngineType> Engine2D<E> {
             self.dispcnt.contains(DISPCNTFlags::DISPLAY_BG3),
             self.dispcnt.contains(DISPCNTFlags::DISPLAY_OBJ),
         ];
+        let mut tops = [0; GPU::WIDTH];
+        let mut bottoms = [0; GPU::WIDTH];
+        let mut special_effects = [ColorSFX::None as u16; GPU::WIDTH];
         for dot_x in 0..GPU::WIDTH {
             let window_control = if self.windows_lines[0][dot_x] {
                 self.win_0_cnt
@@ -238,45 +392,24 @@ impl<E: EngineType> Engine2D<E> {
             let trans_obj = layers[0] == Layer::OBJ && self.objs_line[dot_x].semitransparent;
             let target1_enabled = self.bldcnt.target_pixel1.enabled[layers[0] as usize] || trans_obj;
             let target2_enabled = self.bldcnt.target_pixel2.enabled[layers[1] as usize];
-            let final_color = if window_control.color_special_enable && target1_enabled {
+            tops[dot_x] = colors[0];
+            bottoms[dot_x] = colors[1];
+            if window_control.color_special_enable && target1_enabled {
                 let effect = if trans_obj && target2_enabled { ColorSFX::AlphaBlend } else { self.bldcnt.effect };
-                match effect {
-                    ColorSFX::None => colors[0],
-                    ColorSFX::AlphaBlend => {
-                        if target2_enabled {
-                            let mut new_color = 0;
-                            for i in (0..3).rev() {
-                                let val1 = colors[0] >> (5 * i) & 0x1F;
-                                let val2 = colors[1] >> (5 * i) & 0x1F;
-                                let new_val = std::cmp::min(0x1F,
-                                    (val1 * self.bldalpha.eva + val2 * self.bldalpha.evb) >> 4);
-                                new_color = new_color << 5 | new_val;
-                            }
-                            0x8000 | new_color
-                        } else { colors[0] }
-                    },
-                    ColorSFX::BrightnessInc => {
-                        let mut new_color = 0;
-                        for i in (0..3).rev() {
-                            let val = colors[0] >> (5 * i) & 0x1F;
-                            let new_val = val + (((0x1F - val) * self.bldy.evy as u16) >> 4);
-                            new_color = new_color << 5 | new_val & 0x1F;
-                        }
-                        0x8000 | new_color
-                    },
-                    ColorSFX::BrightnessDec => {
-                        let mut new_color = 0;
-                        for i in (0..3).rev() {
-                            let val = colors[0] >> (5 * i) & 0x1F;
-                            let new_val = val - ((val * self.bldy.evy as u16) >> 4);
-                            new_color = new_color << 5 | new_val & 0x1F;
-                        }
-                        0x8000 | new_color
-                    },
-                }
-            } else { colors[0] };
-            self.set_pixel(vcount, dot_x, final_color);
+                special_effects[dot_x] = match effect {
+                    ColorSFX::AlphaBlend if !target2_enabled => ColorSFX::None,
+                    effect => effect,
+                } as u16;
+            }
         }
+
+        // Effects are applied to the whole line at once so they can be vectorized
+        let mut line = [0; GPU::WIDTH];
+        effects::color_effects(&tops, &bottoms, &special_effects, self.bldalpha.eva, self.bldalpha.evb,
+            self.bldy.evy as u16, &mut line);
+        self.master_bright.apply_line(&mut line);
+        let start = vcount as usize * GPU::WIDTH;
+        self.pixels[start..start + GPU::WIDTH].copy_from_slice(&line);
     }
 
     fn render_window(&mut self, vcount: u16, window_i: usize) {
@@ -458,12 +591,12 @@ impl<E: EngineType> Engine2D<E> {
         let bgcnt = self.bgcnts[bg_i];
         if bgcnt.bpp8 {
             if bgcnt.tile_block & 0x1 != 0 { // Direct Color
-                self.render_affine_line(vram, bg_i, |_, _, _, _, _, _, map_size, _, _, x, y|
-                    vram.get_bg::<E, u16>(2 * (y * map_size + x))
+                self.render_affine_line(vram, bg_i, |_, _, _, layout, _, (x, y)|
+                    vram.get_bg::<E, u16>(2 * (y * layout.map_size + x))
                 );
             } else {
                 self.render_affine_line(vram, bg_i,
-                    |engine, _, _, _, _, _, _, _, _, x, y| {
+                    |engine, _, _, _, _, (x, y)| {
                         let color_num = vram.get_bg::<E, u8>(y * GPU::WIDTH + x) as usize;
                         if color_num == 0 { 0 } // Transparent Color
                         else { engine.bg_palettes[color_num] | 0x8000 }
@@ -476,7 +609,7 @@ impl<E: EngineType> Engine2D<E> {
     }
 
     fn render_affine_line<F>(&mut self, vram: &VRAM, bg_i: usize, render_fn: F)
-        where F: Fn(&Engine2D<E>, &VRAM, usize, usize, usize, usize, usize, usize, usize, usize, usize) -> u16 {
+        where F: Fn(&Engine2D<E>, &VRAM, usize, MapLayout, (usize, usize), (usize, usize)) -> u16 {
         let mut base_x = self.bgxs_latch[bg_i - 2];
         let mut base_y = self.bgys_latch[bg_i - 2];
         self.bgxs_latch[bg_i - 2] += self.dmxs[bg_i - 2];
@@ -484,10 +617,13 @@ impl<E: EngineType> Engine2D<E> {
         let dx = self.dxs[bg_i - 2];
         let dy = self.dys[bg_i - 2];
         let bgcnt = self.bgcnts[bg_i];
-        let tile_start_addr = self.calc_tile_start_addr(&bgcnt);
-        let map_start_addr = self.calc_map_start_addr(&bgcnt);
-        let bit_depth = 8; // Always 8bpp - Also bytes per row of tileper row of tile
         let map_size = 128 << bgcnt.screen_size; // In Pixels
+        let layout = MapLayout {
+            map_start_addr: self.calc_map_start_addr(&bgcnt),
+            tile_start_addr: self.calc_tile_start_addr(&bgcnt),
+            bit_depth: 8, // Always 8bpp - Also bytes per row of tile
+            map_size,
+        };
         let (mosaic_x, mosaic_y) = if bgcnt.mosaic {
             (self.mosaic.bg_size.h_size as usize, self.mosaic.bg_size.v_size as usize)
         } else { (1, 1) };
@@ -507,8 +643,7 @@ impl<E: EngineType> Engine2D<E> {
             // Get Screen Entry
             let map_x = (x / mosaic_x * mosaic_x / 8) % (map_size / 8);
             let map_y = (y / mosaic_y * mosaic_y / 8) % (map_size / 8);
-            self.bg_lines[bg_i][dot_x] = render_fn(self, vram, bg_i, map_start_addr, tile_start_addr, bit_depth,
-                map_size, map_x, map_y, x, y);
+            self.bg_lines[bg_i][dot_x] = render_fn(self, vram, bg_i, layout, (map_x, map_y), (x, y));
         }
     }
 
@@ -593,14 +728,14 @@ impl<E: EngineType> Engine2D<E> {
         }
     }
 
-    fn render_8bit_entry(&self, vram: &VRAM, _bg_i: usize, map_start_addr: usize, tile_start_addr: usize, bit_depth: usize,
-        map_size: usize, map_x: usize, map_y: usize, x: usize, y: usize) -> u16 {
-        let addr = map_start_addr + map_y * map_size / 8 + map_x;
+    pub(super) fn render_8bit_entry(&self, vram: &VRAM, _bg_i: usize, layout: MapLayout, (map_x, map_y): (usize, usize),
+        (x, y): (usize, usize)) -> u16 {
+        let addr = layout.map_start_addr + map_y * layout.map_size / 8 + map_x;
         let tile_num = vram.get_bg::<E, u8>(addr) as usize;
         
         // Convert from tile to pixels
         let (_, color_num) = Engine2D::<E>::get_color_from_tile(vram, VRAM::get_bg::<E, u8>,
-            tile_start_addr + 8 * bit_depth * tile_num, false, false, bit_depth,
+            layout.tile_start_addr + 8 * layout.bit_depth * tile_num, false, false, layout.bit_depth,
             x % 8, y % 8, 0);
         if color_num == 0 { 0 } // Transparent Color
         else { self.bg_palettes[color_num] | 0x8000 }
@@ -633,11 +768,11 @@ impl<E: EngineType> Engine2D<E> {
         final_colors
     }
 
-    pub(super) fn render_16bit_entry(&self, vram: &VRAM, bg_i: usize, map_start_addr: usize, tile_start_addr: usize,
-        bit_depth: usize, map_size: usize, map_x: usize, map_y: usize, x: usize, y: usize) -> u16 {
+    pub(super) fn render_16bit_entry(&self, vram: &VRAM, bg_i: usize, layout: MapLayout, (map_x, map_y): (usize, usize),
+        (x, y): (usize, usize)) -> u16 {
         let bgcnt = self.bgcnts[bg_i];
 
-        let addr = map_start_addr + 2 * (map_y * map_size / 8 + map_x);
+        let addr = layout.map_start_addr + 2 * (map_y * layout.map_size / 8 + map_x);
         let screen_entry = vram.get_bg::<E, u16>(addr) as usize;
         let tile_num = screen_entry & 0x3FF;
         let flip_x = (screen_entry >> 10) & 0x1 != 0;
@@ -646,8 +781,8 @@ impl<E: EngineType> Engine2D<E> {
         
         // Convert from tile to pixels
         let (palette_num, color_num) = Engine2D::<E>::get_color_from_tile(vram,
-            VRAM::get_bg::<E, u8>, tile_start_addr + 8 * bit_depth * tile_num, flip_x, flip_y, bit_depth,
-            x % 8, y % 8, original_palette_num);
+            VRAM::get_bg::<E, u8>, layout.tile_start_addr + 8 * layout.bit_depth * tile_num, flip_x, flip_y,
+            layout.bit_depth, x % 8, y % 8, original_palette_num);
         if color_num == 0 { 0 } // Transparent Color
         else if bgcnt.bpp8 & self.dispcnt.contains(DISPCNTFlags::BG_EXTENDED_PALETTES) {
             // Wrap bit is Change Ext Palette Slot for BG0/BG1
@@ -702,6 +837,25 @@ impl<E: EngineType> Engine2D<E> {
     }
 }
 
+// Where a BG's screen entries and tiles are, for drawing one entry at a time
+#[derive(Clone, Copy)]
+pub(super) struct MapLayout {
+    pub map_start_addr: usize,
+    pub tile_start_addr: usize,
+    // Also bytes per row of tile
+    pub bit_depth: usize,
+    // In pixels
+    pub map_size: usize,
+}
+
+// The affine latches are restored when a line is skipped, since they're stepped as each line is drawn
+#[derive(Clone, Copy)]
+struct DrawnLine {
+    writes: (u64, u64),
+    bgxs_latch: [ReferencePointCoord; 2],
+    bgys_latch: [ReferencePointCoord; 2],
+}
+
 #[derive(Clone, Copy, PartialEq)]
 enum Layer {
     BG0 = 0,
@@ -831,13 +985,25 @@ impl<E: EngineType> Engine2D<E>{
             0x054 => self.bldy.read(0),
             0x055 => self.bldy.read(1),
             0x056 ..= 0x05F => 0,
+            0x06C => self.master_bright.read(0),
+            0x06D => self.master_bright.read(1),
+            0x06E => self.master_bright.read(2),
+            0x06F => self.master_bright.read(3),
             _ => { warn!("Ignoring Engine2D Read at 0x{:08X}", addr); 0 },
         }
     }
 
     pub fn write_register(&mut self, scheduler: &mut Scheduler, addr: u32, value: u8) {
         assert_eq!((addr >> 12) & !0x1, 0x04000);
+        let index = (addr & 0xFFF) as usize;
+        // Writing the same value again only has an effect on BG2X/Y and BG3X/Y, which reload the latches
+        if let Some(written) = self.written_registers.get_mut(index) {
+            let reloads_latch = (0x028 ..= 0x02F).contains(&index) || (0x038 ..= 0x03F).contains(&index);
+            if *written != Some(value) || reloads_latch { self.writes += 1 }
+            *written = Some(value);
+        } else { self.writes += 1 }
         match addr & 0xFFF {
+            0x000 if value & 0x7 == 7 => self.faults.raise(Fault::InvalidValue("BG Mode 7".to_string())),
             0x000 => self.dispcnt.write(scheduler, 0, value),
             0x001 => self.dispcnt.write(scheduler, 1, value),
             0x002 => self.dispcnt.write(scheduler, 2, value),
@@ -921,10 +1087,29 @@ impl<E: EngineType> Engine2D<E>{
             0x054 => self.bldy.write(scheduler, 0, value),
             0x055 => self.bldy.write(scheduler, 1, value),
             0x056 ..= 0x05F => (),
+            0x06C => self.master_bright.write(scheduler, 0, value),
+            0x06D => self.master_bright.write(scheduler, 1, value),
+            0x06E => self.master_bright.write(scheduler, 2, value),
+            0x06F => self.master_bright.write(scheduler, 3, value),
             _ => warn!("Ignoring Engine2D Write 0x{:08X} = {:02X}", addr, value),
         }
     }
 
+    pub fn read_gba_dispcnt(&self, byte: usize) -> u8 {
+        (self.gba_dispcnt >> (8 * byte)) as u8
+    }
+
+    pub fn write_gba_dispcnt(&mut self, scheduler: &mut Scheduler, byte: usize, value: u8) {
+        self.writes += 1;
+        HW::write_byte_to_value(&mut self.gba_dispcnt, byte, value);
+        if byte == 0 {
+            let bg_mode = if value & 0x7 > 5 { warn!("Invalid GBA BG Mode: {}", value & 0x7); 0 } else { value & 0x7 };
+            // The GBA has OBJ 1D mapping in bit 6 instead of bit 4
+            let value = value & 0x80 | bg_mode | (value >> 6 & 0x1) << 4;
+            self.dispcnt.write(scheduler, 0, value);
+        } else { self.dispcnt.write(scheduler, 1, value) }
+    }
+
     pub fn read_palette_ram(&self, addr: u32) -> u8 {
         let addr = addr as usize & (2 * GPU::PALETTE_SIZE - 1);
         let palettes = if addr < GPU::PALETTE_SIZE { &self.bg_palettes } else { &self.obj_palettes };
@@ -944,7 +1129,28 @@ impl<E: EngineType> Engine2D<E>{
         let addr = addr as usize & (2 * GPU::PALETTE_SIZE - 1);
         let palettes = if addr < GPU::PALETTE_SIZE { &mut self.bg_palettes } else { &mut self.obj_palettes };
         let index = (addr & GPU::PALETTE_SIZE - 1) / 2;
-        palettes[index] = value;
+        if palettes[index] != value {
+            palettes[index] = value;
+            self.writes += 1;
+        }
+    }
+
+    pub fn read_oam<T: MemoryValue>(&self, addr: u32) -> T {
+        HW::read_mem(&self.oam, addr & GPU::OAM_MASK as u32)
+    }
+
+    pub fn write_oam<T: MemoryValue>(&mut self, addr: u32, value: T) {
+        let addr = addr & GPU::OAM_MASK as u32;
+        if HW::read_mem::<T>(&self.oam, addr) != value {
+            HW::write_mem(&mut self.oam, addr, value);
+            self.writes += 1;
+        }
+    }
+
+    // For debugging tools, which may write through it
+    pub fn oam_mut(&mut self) -> &mut Vec<u8> {
+        self.writes += 1;
+        &mut self.oam
     }
 
     pub fn bg_palettes(&self) -> &Vec<u16> { &self.bg_palettes }
@@ -6,7 +6,7 @@ use crate::hw::{
     mem::IORegister,
     scheduler::Scheduler,
 };
-use super::EngineType;
+use super::{EngineType, GPU};
 
 #[derive(Clone, Copy, PartialEq)]
 pub enum BGMode {
@@ -19,6 +19,8 @@ pub enum BGMode {
     Mode6 = 6,
 }
 
+savestate_enum!(BGMode { Mode0, Mode1, Mode2, Mode3, Mode4, Mode5, Mode6 });
+
 impl BGMode {
     pub fn from_bits(bits: u8) -> Self {
         use BGMode::*;
@@ -30,7 +32,7 @@ impl BGMode {
             4 => Mode4,
             5 => Mode5,
             6 => Mode6,
-            _ => panic!("Invalid BG Mode!"),
+            _ => unreachable!(),
         }
     }
 }
@@ -43,6 +45,8 @@ pub enum DisplayMode {
     Mode3 = 3,
 }
 
+savestate_enum!(DisplayMode { Mode0, Mode1, Mode2, Mode3 });
+
 impl DisplayMode {
     pub fn from_bits(bits: u8) -> Self {
         use DisplayMode::*;
@@ -78,6 +82,8 @@ bitflags! {
     }
 }
 
+savestate_bitflags!(DISPCNTFlags);
+
 pub struct DISPCNT<E: EngineType> {
     pub flags: DISPCNTFlags,
     pub bg_mode: BGMode,
@@ -89,6 +95,8 @@ pub struct DISPCNT<E: EngineType> {
     engine_type: PhantomData<E>,
 }
 
+savestate!([E: EngineType] DISPCNT<E> { flags, bg_mode, display_mode, vram_block, tile_obj_1d_bound, char_base, screen_base });
+
 impl<E: EngineType> DISPCNT<E> {
     pub fn new() -> DISPCNT<E> {
         DISPCNT {
@@ -146,9 +154,8 @@ impl<E: EngineType> IORegister for DISPCNT<E> {
             1 => self.flags.bits = self.flags.bits & !0x0000_FF00 | (value as u32) << 8 & DISPCNTFlags::all().bits,
             2 => {
                 self.display_mode = DisplayMode::from_bits(value & 0x3);
-                if E::is_a() {
-                    self.vram_block = value >> 2 & 0x3;
-                } else { assert_eq!(self.vram_block, 0) }
+                // Engine B doesn't have these bits
+                if E::is_a() { self.vram_block = value >> 2 & 0x3 }
                 self.tile_obj_1d_bound = value >> 4 & 0x3;
                 self.flags.bits = self.flags.bits & !0x00FF_0000 | (value as u32) << 16 & DISPCNTFlags::all().bits;
                 // TODO: Use trait specialization instead of this
@@ -161,9 +168,6 @@ impl<E: EngineType> IORegister for DISPCNT<E> {
                 if E::is_a() {
                     self.screen_base = value >> 3 & 0x7;
                     self.char_base = value & 0x7;
-                } else {
-                    assert_eq!(self.screen_base, 0);
-                    assert_eq!(self.char_base, 0);
                 }
             },
             _ => unreachable!(),
@@ -182,6 +186,8 @@ pub struct BGCNT {
     pub screen_size: u8,
 }
 
+savestate!(BGCNT { priority, tile_block, mosaic, bpp8, map_block, wrap, screen_size });
+
 impl BGCNT {
     pub fn new() -> BGCNT {
         BGCNT {
@@ -228,6 +234,8 @@ pub struct OFS {
     pub offset: u16,
 }
 
+savestate!(OFS { offset });
+
 impl OFS {
     pub fn new() -> OFS {
         OFS {
@@ -259,6 +267,8 @@ pub struct RotationScalingParameter {
     value: i16,
 }
 
+savestate!(RotationScalingParameter { value });
+
 impl RotationScalingParameter {
     pub fn new() -> RotationScalingParameter {
         RotationScalingParameter {
@@ -288,6 +298,8 @@ pub struct ReferencePointCoord {
     value: i32,
 }
 
+savestate!(ReferencePointCoord { value });
+
 impl ReferencePointCoord {
     pub fn new() -> ReferencePointCoord {
         ReferencePointCoord {
@@ -330,6 +342,8 @@ pub struct WindowDimensions {
     pub coord1: u8,
 }
 
+savestate!(WindowDimensions { coord2, coord1 });
+
 impl WindowDimensions {
     pub fn new() -> WindowDimensions {
         WindowDimensions {
@@ -367,6 +381,8 @@ pub struct WindowControl {
     pub color_special_enable: bool,
 }
 
+savestate!(WindowControl { bg0_enable, bg1_enable, bg2_enable, bg3_enable, obj_enable, color_special_enable });
+
 impl WindowControl {
     pub fn new() -> WindowControl {
         WindowControl {
@@ -420,6 +436,8 @@ pub struct MosaicSize {
     pub v_size: u16,
 }
 
+savestate!(MosaicSize { h_size, v_size });
+
 impl MosaicSize {
     pub fn new() -> MosaicSize {
         MosaicSize {
@@ -443,6 +461,8 @@ pub struct MOSAIC {
     pub obj_size: MosaicSize,
 }
 
+savestate!(MOSAIC { bg_size, obj_size });
+
 impl MOSAIC {
     pub fn new() -> MOSAIC {
         MOSAIC {
@@ -474,6 +494,8 @@ pub struct BLDCNTTargetPixelSelection {
     pub enabled: [bool; 6]
 }
 
+savestate!(BLDCNTTargetPixelSelection { enabled });
+
 impl BLDCNTTargetPixelSelection {
     pub fn new() -> BLDCNTTargetPixelSelection {
         BLDCNTTargetPixelSelection {
@@ -508,6 +530,8 @@ pub enum ColorSFX {
     BrightnessDec = 3,
 }
 
+savestate_enum!(ColorSFX { None, AlphaBlend, BrightnessInc, BrightnessDec });
+
 impl ColorSFX {
     pub fn from(value: u8) -> ColorSFX {
         use ColorSFX::*;
@@ -527,6 +551,8 @@ pub struct BLDCNT {
     pub target_pixel2: BLDCNTTargetPixelSelection,
 }
 
+savestate!(BLDCNT { target_pixel1, effect, target_pixel2 });
+
 impl BLDCNT {
     pub fn new() -> BLDCNT {
         BLDCNT {
@@ -565,6 +591,8 @@ pub struct BLDALPHA {
     pub evb: u16,
 }
 
+savestate!(BLDALPHA { raw_eva, raw_evb, eva, evb });
+
 impl BLDALPHA {
     pub fn new() -> BLDALPHA {
         BLDALPHA {
@@ -604,6 +632,8 @@ pub struct BLDY {
     pub evy: u8,
 }
 
+savestate!(BLDY { evy });
+
 impl BLDY {
     pub fn new() -> BLDY {
         BLDY {
@@ -631,6 +661,8 @@ pub enum MasterBrightMode {
     Down = 2,
 }
 
+savestate_enum!(MasterBrightMode { Disable, Up, Down });
+
 impl MasterBrightMode {
     pub fn from_bits(value: u8) -> Self {
         match value {
@@ -648,6 +680,8 @@ pub struct MasterBright {
     mode: MasterBrightMode,
 }
 
+savestate!(MasterBright { factor_read, factor, mode });
+
 impl MasterBright {
     pub fn new() -> Self {
         MasterBright {
@@ -676,6 +710,16 @@ impl MasterBright {
             },
         }
     }
+
+    #[cfg(not(feature = "simd"))]
+    pub fn apply_line(&self, line: &mut [u16; GPU::WIDTH]) {
+        for pixel in line.iter_mut() { *pixel = self.apply(*pixel) }
+    }
+
+    #[cfg(feature = "simd")]
+    pub fn apply_line(&self, line: &mut [u16; GPU::WIDTH]) {
+        super::effects::master_brightness(self.mode, self.factor, line)
+    }
 }
 
 impl IORegister for MasterBright {
@@ -0,0 +1,101 @@
+use std::io::{self, Write};
+
+use super::{Engine3D, geometry::Polygon};
+pub use super::registers::{PolygonMode, TextureFormat};
+
+#[derive(Clone, Debug)]
+pub struct CapturedVertex {
+    pub clip_coords: [i32; 4], // 20.12 fixed point
+    pub screen_coords: [u32; 2],
+    pub depth: u32, // 24 bit depth
+    pub color: [u8; 3], // 6 bit components
+    pub tex_coord: [i16; 2], // 1 + 11 + 4 fixed point
+}
+
+#[derive(Clone, Debug)]
+pub struct CapturedPolygon {
+    pub vertices: Vec<CapturedVertex>,
+    pub mode: PolygonMode,
+    pub alpha: u8,
+    pub polygon_id: u8,
+    pub is_front: bool,
+    pub lights_enabled: [bool; 4],
+    pub fog_enable: bool,
+    pub tex_format: TextureFormat,
+    pub tex_addr: usize,
+    pub tex_size: [usize; 2],
+    pub tex_repeat: [bool; 2],
+    pub tex_flip: [bool; 2],
+    pub color0_transparent: bool,
+    pub palette_base: usize,
+}
+
+impl CapturedPolygon {
+    // Wavefront OBJ with vertex colors, using normalized device coordinates
+    pub fn write_obj<W: Write>(polygons: &[CapturedPolygon], mut writer: W) -> io::Result<()> {
+        let mut vert_num = 1;
+        for (i, polygon) in polygons.iter().enumerate() {
+            writeln!(writer, "o polygon{}", i)?;
+            for vert in polygon.vertices.iter() {
+                let w = if vert.clip_coords[3] == 0 { 1.0 } else { vert.clip_coords[3] as f32 };
+                writeln!(writer, "v {} {} {} {} {} {}", vert.clip_coords[0] as f32 / w, vert.clip_coords[1] as f32 / w,
+                    vert.clip_coords[2] as f32 / w, vert.color[0] as f32 / 63.0, vert.color[1] as f32 / 63.0,
+                    vert.color[2] as f32 / 63.0)?;
+            }
+            write!(writer, "f")?;
+            for _ in polygon.vertices.iter() {
+                write!(writer, " {}", vert_num)?;
+                vert_num += 1;
+            }
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+}
+
+impl Engine3D {
+    // The polygons are captured when the next frame is rendered
+    pub fn request_capture(&mut self) {
+        self.capture_requested = true;
+    }
+
+    pub fn take_capture(&mut self) -> Option<Vec<CapturedPolygon>> {
+        self.capture.take()
+    }
+
+    pub(super) fn capture_polygons(&mut self) {
+        self.capture_requested = false;
+        self.capture = Some(self.polygons.iter().map(|polygon| self.capture_polygon(polygon)).collect());
+    }
+
+    fn capture_polygon(&self, polygon: &Polygon) -> CapturedPolygon {
+        let tex_params = &polygon.tex_params;
+        CapturedPolygon {
+            vertices: self.vertices[polygon.start_vert..polygon.end_vert].iter().map(|vert| CapturedVertex {
+                clip_coords: [
+                    vert.clip_coords[0].raw(),
+                    vert.clip_coords[1].raw(),
+                    vert.clip_coords[2].raw(),
+                    vert.clip_coords[3].raw(),
+                ],
+                screen_coords: vert.screen_coords,
+                depth: vert.z_depth,
+                color: [vert.color.r6(), vert.color.g6(), vert.color.b6()],
+                tex_coord: vert.tex_coord,
+            }).collect(),
+            mode: polygon.attrs.mode,
+            alpha: polygon.attrs.alpha,
+            polygon_id: polygon.attrs.polygon_id,
+            is_front: polygon.is_front,
+            lights_enabled: polygon.attrs.lights_enabled,
+            fog_enable: polygon.attrs.fog_enable,
+            tex_format: tex_params.format,
+            tex_addr: tex_params.vram_offset,
+            tex_size: [tex_params.size_s, tex_params.size_t],
+            tex_repeat: [tex_params.repeat_s, tex_params.repeat_t],
+            tex_flip: [tex_params.flip_s, tex_params.flip_t],
+            color0_transparent: tex_params.color0_transparent,
+            palette_base: polygon.palette_base,
+        }
+    }
+}
@@ -44,86 +44,82 @@ impl Engine3D {
         match command_entry.command {
             NOP => (),
             MtxMode => self.mtx_mode = MatrixMode::from(param as u8 & 0x3),
+            // Overflowing or underflowing a stack sets the error bit in GXSTAT instead of accessing it
             MtxPush => match self.mtx_mode {
                 MatrixMode::Proj => {
-                    self.proj_stack[self.proj_stack_sp as usize] = self.cur_proj;
-                    self.proj_stack_sp += 1;
-                    assert!(self.proj_stack_sp <= 1);
+                    if self.proj_stack_sp == 1 { self.gxstat.mat_stack_error = true }
+                    self.proj_stack[0] = self.cur_proj;
+                    self.proj_stack_sp = 1;
                 },
                 MatrixMode::Pos | MatrixMode::PosVec => {
-                    self.pos_stack[self.pos_vec_stack_sp as usize] = self.cur_pos;
-                    self.vec_stack[self.pos_vec_stack_sp as usize] = self.cur_vec;
-                    self.pos_vec_stack_sp += 1;
-                    assert!(self.pos_vec_stack_sp <= 31);
+                    let sp = self.pos_vec_stack_sp as usize;
+                    if sp >= self.pos_stack.len() { self.gxstat.mat_stack_error = true } else {
+                        self.pos_stack[sp] = self.cur_pos;
+                        self.vec_stack[sp] = self.cur_vec;
+                    }
+                    self.pos_vec_stack_sp = (self.pos_vec_stack_sp + 1) & 0x3F;
                 },
                 MatrixMode::Texture => {
-                    self.tex_stack[self.tex_stack_sp as usize] = self.cur_tex;
-                    self.tex_stack_sp += 1;
-                    assert!(self.tex_stack_sp <= 31);
+                    if self.tex_stack_sp == 1 { self.gxstat.mat_stack_error = true }
+                    self.tex_stack[0] = self.cur_tex;
+                    self.tex_stack_sp = 1;
                 },
             },
             MtxPop => {
                 let offset = param & 0x3F;
                 let offset = if offset & 0x20 != 0 { 0xC0 | offset } else { offset } as i8;
                 match self.mtx_mode {
+                    // The offset is ignored by the single entry stacks
                     MatrixMode::Proj => {
-                        self.proj_stack_sp -= 1;
-                        assert!(self.proj_stack_sp < 1);
-                        self.cur_proj = self.proj_stack[self.proj_stack_sp as usize];
+                        if self.proj_stack_sp == 0 { self.gxstat.mat_stack_error = true }
+                        self.proj_stack_sp = 0;
+                        self.cur_proj = self.proj_stack[0];
                         self.calc_clip_mat();
                     },
                     MatrixMode::Pos | MatrixMode::PosVec => {
-                        self.pos_vec_stack_sp = (self.pos_vec_stack_sp as i8 - offset) as u8;
-                        assert!(self.pos_vec_stack_sp < 31);
-                        self.cur_pos = self.pos_stack[self.pos_vec_stack_sp as usize];
-                        self.calc_clip_mat();
-                        self.cur_vec = self.vec_stack[self.pos_vec_stack_sp as usize];
+                        self.pos_vec_stack_sp = (self.pos_vec_stack_sp as i8).wrapping_sub(offset) as u8 & 0x3F;
+                        let sp = self.pos_vec_stack_sp as usize;
+                        if sp >= self.pos_stack.len() { self.gxstat.mat_stack_error = true } else {
+                            self.cur_pos = self.pos_stack[sp];
+                            self.calc_clip_mat();
+                            self.cur_vec = self.vec_stack[sp];
+                        }
                     },
                     MatrixMode::Texture => {
-                        self.tex_stack_sp = (self.tex_stack_sp as i8 - offset) as u8;
-                        assert!(self.tex_stack_sp < 31);
-                        self.cur_tex = self.tex_stack[self.tex_stack_sp as usize];
+                        if self.tex_stack_sp == 0 { self.gxstat.mat_stack_error = true }
+                        self.tex_stack_sp = 0;
+                        self.cur_tex = self.tex_stack[0];
                     },
                 }
             },
             MtxStore => {
-                let index = param & 0x3F;
-                if index == 31 { self.gxstat.mat_stack_error = true }
+                let index = param as usize & 0x1F;
                 match self.mtx_mode {
-                    MatrixMode::Proj => {
-                        assert!(index <= 1);
-                        self.proj_stack[0] = self.cur_proj;
-                    },
-                    MatrixMode::Pos | MatrixMode::PosVec => {
-                        assert!(index <= 31);
-                        self.pos_stack[index as usize] = self.cur_pos;
-                        self.vec_stack[index as usize] = self.cur_vec;
-                    },
-                    MatrixMode::Texture => {
-                        assert!(index <= 31);
-                        self.tex_stack[index as usize] = self.cur_tex;
+                    MatrixMode::Proj => self.proj_stack[0] = self.cur_proj,
+                    MatrixMode::Pos | MatrixMode::PosVec => if index >= self.pos_stack.len() {
+                        self.gxstat.mat_stack_error = true;
+                    } else {
+                        self.pos_stack[index] = self.cur_pos;
+                        self.vec_stack[index] = self.cur_vec;
                     },
+                    MatrixMode::Texture => self.tex_stack[0] = self.cur_tex,
                 }
             },
             MtxRestore => {
-                let index = param & 0x3F;
-                if index == 31 { self.gxstat.mat_stack_error = true }
+                let index = param as usize & 0x1F;
                 match self.mtx_mode {
                     MatrixMode::Proj => {
-                        assert!(index <= 1);
                         self.cur_proj = self.proj_stack[0];
                         self.calc_clip_mat();
                     },
-                    MatrixMode::Pos | MatrixMode::PosVec => {
-                        assert!(index <= 31);
-                        self.cur_pos = self.pos_stack[index as usize];
+                    MatrixMode::Pos | MatrixMode::PosVec => if index >= self.pos_stack.len() {
+                        self.gxstat.mat_stack_error = true;
+                    } else {
+                        self.cur_pos = self.pos_stack[index];
                         self.calc_clip_mat();
-                        self.cur_vec = self.vec_stack[index as usize];
-                    },
-                    MatrixMode::Texture => {
-                        assert!(index <= 31);
-                        self.cur_tex = self.tex_stack[index as usize];
+                        self.cur_vec = self.vec_stack[index];
                     },
+                    MatrixMode::Texture => self.cur_tex = self.tex_stack[0],
                 }
             },
             MtxIdentity => self.apply_cur_mat(Matrix::set_identity, true),
@@ -488,8 +484,10 @@ impl Engine3D {
             tex_params: self.tex_params,
             palette_base: self.palette_base,
             is_front,
-            original_verts: self.original_verts.drain(..).collect(),
+            start_original_vert: self.polygon_original_verts.len(),
+            end_original_vert: self.polygon_original_verts.len() + self.original_verts.len(),
         };
+        self.polygon_original_verts.append(&mut self.original_verts);
         let mut w_size = 0;
         for vert in self.cur_poly_verts.iter() {
             let w = vert.clip_coords[3].raw() as u32;
@@ -627,7 +625,7 @@ impl Engine3D {
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
 pub enum GeometryCommand {
     NOP = 0x00,
     MtxMode = 0x10,
@@ -665,9 +663,15 @@ pub enum GeometryCommand {
     SwapBuffers = 0x50,
     Viewport = 0x60,
     BoxTest = 0x70,
+    #[default]
     Unimplemented = 0xFF,
 }
 
+savestate_enum!(GeometryCommand { NOP, MtxMode, MtxPush, MtxPop, MtxStore, MtxRestore, MtxIdentity, MtxLoad4x4, MtxLoad4x3,
+    MtxMult4x4, MtxMult4x3, MtxMult3x3, MtxScale, MtxTrans, Color, Normal, TexCoord, Vtx16, Vtx10, VtxXY, VtxXZ, VtxYZ, VtxDiff, PolygonAttr,
+    TexImageParam, PlttBase, DifAmb, SpeEmi, LightVector, LightColor, Shininess, BeginVtxs, EndVtxs, SwapBuffers, Viewport, BoxTest,
+    Unimplemented });
+
 impl GeometryCommand {
     fn from_addr(addr: u32) -> Self {
         use GeometryCommand::*;
@@ -797,12 +801,14 @@ impl GeometryCommand {
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
 pub struct GeometryCommandEntry {
     command: GeometryCommand,
     param: u32,
 }
 
+savestate!(GeometryCommandEntry { command, param });
+
 impl GeometryCommandEntry {
     pub fn new(command: GeometryCommand, param: u32) -> Self {
         GeometryCommandEntry {
@@ -812,6 +818,7 @@ impl GeometryCommandEntry {
     }
 }
 
+#[derive(Clone, Copy)]
 pub enum MatrixMode {
     Proj = 0,
     Pos = 1,
@@ -819,6 +826,8 @@ pub enum MatrixMode {
     Texture = 3,
 }
 
+savestate_enum!(MatrixMode { Proj, Pos, PosVec, Texture });
+
 impl From<u8> for MatrixMode {
     fn from(value: u8) -> Self {
         match value {
@@ -837,6 +846,8 @@ pub struct Light {
     color: [i32; 3],
 }
 
+savestate!(Light { direction, color });
+
 impl Light {
     pub fn new() -> Self {
         Light {
@@ -855,6 +866,8 @@ pub struct Material {
     use_shininess_table: bool,
 }
 
+savestate!(Material { diffuse, ambient, specular, emission, shininess, use_shininess_table });
+
 impl Material {
     pub fn new() -> Self {
         Material {
@@ -896,13 +909,15 @@ impl Material {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Default)]
 pub struct Color {
     r: u8,
     g: u8,
     b: u8,
 }
 
+savestate!(Color { r, g, b });
+
 impl From<u16> for Color {
     fn from(value: u16) -> Self {
         Color::new5(
@@ -963,7 +978,7 @@ impl Color {
     pub fn b8(&self) -> u8 { self.b >> 0 }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Default)]
 pub struct Vertex {
     pub clip_coords: Vec4,
     pub screen_coords: [u32; 2],
@@ -973,6 +988,8 @@ pub struct Vertex {
     pub tex_coord: [i16; 2], // 1 + 11 + 4 fixed point
 }
 
+savestate!(Vertex { clip_coords, screen_coords, z_depth, normalized_w, color, tex_coord });
+
 impl Vertex {
     pub fn new() -> Self {
         Vertex {
@@ -986,6 +1003,7 @@ impl Vertex {
     }
 }
 
+#[derive(Default)]
 pub struct Polygon {
     pub start_vert: usize,
     pub end_vert: usize,
@@ -994,5 +1012,9 @@ pub struct Polygon {
     pub tex_params: TextureParams,
     pub palette_base: usize,
     pub is_front: bool,
-    pub original_verts: Vec<(Matrix, [FixedPoint; 3])>,
+    pub start_original_vert: usize,
+    pub end_original_vert: usize,
 }
+
+savestate!(Polygon { start_vert, end_vert, y_bounds, attrs, tex_params, palette_base, is_front, start_original_vert,
+    end_original_vert });
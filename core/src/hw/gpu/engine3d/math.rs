@@ -1,10 +1,12 @@
 use std::ops::{AddAssign, Add, Sub, Mul, Neg, Index};
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Default)]
 pub struct Matrix {
     elems: [FixedPoint; 16],
 }
 
+savestate!(Matrix { elems });
+
 impl Matrix {
     pub fn identity() -> Self {
         Matrix {
@@ -176,9 +178,11 @@ impl Mul<[FixedPoint; 3]> for Matrix {
 }
 
 // 12 bit fraction
-#[derive(Clone, Copy, PartialEq, PartialOrd)]
+#[derive(Clone, Copy, PartialEq, PartialOrd, Default)]
 pub struct FixedPoint(i32);
 
+savestate!(FixedPoint { 0 });
+
 impl Mul for FixedPoint {
     type Output = i64;
 
@@ -255,11 +259,13 @@ impl std::fmt::Debug for FixedPoint {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Default)]
 pub struct Vec4 {
     elems: [FixedPoint; 4]
 }
 
+savestate!(Vec4 { elems });
+
 impl Vec4 {
     pub fn new(x: FixedPoint, y: FixedPoint, z: FixedPoint, w: FixedPoint) -> Self {
         Vec4 {
@@ -1,5 +1,6 @@
 use std::collections::VecDeque;
 
+use crate::fault::Faults;
 use crate::hw::mem::IORegister;
 use super::{GPU, Scheduler, InterruptRequest};
 
@@ -7,10 +8,18 @@ mod registers;
 mod math;
 mod geometry;
 mod rendering;
+mod debug;
+mod textures;
+
+pub use debug::{CapturedPolygon, CapturedVertex, PolygonMode, TextureFormat};
+pub use textures::{Texture, TexturePack};
+#[cfg(feature = "host")]
+pub use textures::TextureDir;
 
 use math::{FixedPoint, Matrix};
 use geometry::*;
 use rendering::FrameBufferPixel;
+use textures::TextureCache;
 use registers::*;
 
 pub struct Engine3D {
@@ -46,6 +55,8 @@ pub struct Engine3D {
     clear_color: ClearColor,
     clear_depth: ClearDepth,
     frame_buffer: Vec<FrameBufferPixel>,
+    // Rendered into and then swapped with the frame buffer so neither is reallocated
+    back_buffer: Vec<FrameBufferPixel>,
     polygons_submitted: bool,
     // Polygons
     polygon_attrs: PolygonAttributes,
@@ -58,6 +69,8 @@ pub struct Engine3D {
     vertices: Vec<Vertex>,
     polygons: Vec<Polygon>,
     original_verts: Vec<(Matrix, [FixedPoint; 3])>,
+    // Original vertices of every submitted polygon, which are only used to debug clipping
+    polygon_original_verts: Vec<(Matrix, [FixedPoint; 3])>,
     // Lighting
     lights: [Light; 4],
     material: Material,
@@ -69,12 +82,26 @@ pub struct Engine3D {
     tex_coord: [i16; 2], // 1 + 11 + 4 fixed point
     // Toon
     toon_table: [Color; 0x20],
+    // Debugging
+    pub wireframe: bool,
+    capture_requested: bool,
+    capture: Option<Vec<CapturedPolygon>>,
+    // Dumping and replacing textures
+    texture_cache: Option<TextureCache>,
+    faults: Faults,
 }
 
+savestate!(Engine3D { bus_stalled, disp3dcnt, gxstat, prev_command, packed_commands, cur_command, num_params, params_processed, params,
+    gxfifo, mtx_mode, cur_proj, cur_pos, cur_vec, cur_tex, proj_stack_sp, pos_vec_stack_sp, tex_stack_sp, proj_stack, pos_stack, vec_stack,
+    tex_stack, frame_params, next_frame_params, viewport, clear_color, clear_depth, frame_buffer, polygons_submitted, polygon_attrs,
+    polygon_attrs_latch, vertex_primitive, prev_pos, swap_verts, clip_mat, cur_poly_verts, vertices, polygons, original_verts,
+    polygon_original_verts, lights,
+    material, color, tex_params, palette_base, raw_tex_coord, tex_coord, toon_table });
+
 impl Engine3D {
     const FIFO_LEN: usize = 256;
 
-    pub fn new() -> Self {
+    pub fn new(faults: Faults) -> Self {
         Engine3D {
             bus_stalled: false,
             // Registers
@@ -108,6 +135,7 @@ impl Engine3D {
             clear_color: ClearColor::new(),
             clear_depth: ClearDepth::new(),
             frame_buffer: vec![FrameBufferPixel::new(); GPU::WIDTH * GPU::HEIGHT],
+            back_buffer: vec![FrameBufferPixel::new(); GPU::WIDTH * GPU::HEIGHT],
             polygons_submitted: false,
             // Polygons
             polygon_attrs: PolygonAttributes::new(),
@@ -120,6 +148,7 @@ impl Engine3D {
             vertices: Vec::new(),
             polygons: Vec::new(),
             original_verts: Vec::new(),
+            polygon_original_verts: Vec::new(),
             // Lighting
             lights: [Light::new(); 4],
             material: Material::new(),
@@ -131,6 +160,12 @@ impl Engine3D {
             tex_coord: [0; 2], // 1 + 11 + 4 fixed point
             // Toon
             toon_table: [Color::new5(0, 0, 0); 0x20],
+            // Debugging
+            wireframe: false,
+            capture_requested: false,
+            capture: None,
+            texture_cache: None,
+            faults,
         }
     }
 
@@ -1,4 +1,5 @@
-use super::{GPU, Engine3D, Color, math::Vec4, IORegister, Scheduler};
+use super::{Engine3D, Color, math::Vec4, IORegister, Scheduler};
+use crate::fault::Fault;
 
 pub struct DISP3DCNT {
     pub texture_mapping: bool,
@@ -15,6 +16,9 @@ pub struct DISP3DCNT {
     pub rear_plane_bitmap: bool,
 }
 
+savestate!(DISP3DCNT { texture_mapping, highlight_shading, alpha_test, alpha_blending, antia_aliasing, edge_marking, fog_alpha_only,
+    fog_master_enable, fog_depth_shift, color_buffer_underflow, poly_vert_ram_overflow, rear_plane_bitmap });
+
 impl DISP3DCNT {
     pub fn new() -> Self {
         DISP3DCNT {
@@ -80,6 +84,8 @@ pub struct GXSTAT {
     pub command_fifo_irq: CommandFifoIRQ,
 }
 
+savestate!(GXSTAT { test_busy, box_test_inside, mat_stack_busy, mat_stack_error, geometry_engine_busy, command_fifo_irq });
+
 #[derive(Clone, Copy)]
 pub enum CommandFifoIRQ {
     Never = 0,
@@ -87,13 +93,14 @@ pub enum CommandFifoIRQ {
     Empty = 2,
 }
 
+savestate_enum!(CommandFifoIRQ { Never, LessHalf, Empty });
+
 impl From<u8> for CommandFifoIRQ {
     fn from(value: u8) -> Self {
         match value {
             0 => CommandFifoIRQ::Never,
             1 => CommandFifoIRQ::LessHalf,
             2 => CommandFifoIRQ::Empty,
-            3 => panic!("Reserved Command FIFO IRQ"),
             _ => unreachable!(),
         }
     }
@@ -131,6 +138,7 @@ impl Engine3D {
         match byte {
             0 | 2 => (), // Read Only
             1 => self.gxstat.mat_stack_error = self.gxstat.mat_stack_error && value & 0x80 == 0,
+            3 if value >> 6 == 3 => self.faults.raise(Fault::InvalidValue("Command FIFO IRQ 3".to_string())),
             3 => self.gxstat.command_fifo_irq = CommandFifoIRQ::from(value >> 6 & 0x3),
             _ => unreachable!(),
         }
@@ -170,6 +178,8 @@ pub struct ClearColor {
     pub polygon_id: u8,
 }
 
+savestate!(ClearColor { r, g, b, fog, a, polygon_id });
+
 impl ClearColor {
     pub fn new() -> Self {
         ClearColor {
@@ -208,6 +218,8 @@ pub struct ClearDepth {
     depth: u16,
 }
 
+savestate!(ClearDepth { depth });
+
 impl ClearDepth {
     pub fn new() -> Self {
         ClearDepth {
@@ -232,7 +244,7 @@ impl IORegister for ClearDepth {
     }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Default)]
 pub struct TextureParams {
     pub vram_offset: usize,
     pub repeat_s: bool,
@@ -248,6 +260,9 @@ pub struct TextureParams {
     pub coord_transformation_mode: TexCoordTransformationMode, 
 }
 
+savestate!(TextureParams { vram_offset, repeat_s, repeat_t, size_s_shift, size_t_shift, flip_s, flip_t, size_s, size_t, format,
+    color0_transparent, coord_transformation_mode });
+
 impl TextureParams {
     pub fn new() -> Self {
         TextureParams {
@@ -282,8 +297,9 @@ impl TextureParams {
     }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, Default)]
 pub enum TextureFormat {
+    #[default]
     NoTexture = 0,
     A3I5 = 1,
     Palette4 = 2,
@@ -294,6 +310,8 @@ pub enum TextureFormat {
     DirectColor = 7,
 }
 
+savestate_enum!(TextureFormat { NoTexture, A3I5, Palette4, Palette16, Palette256, Compressed, A5I3, DirectColor });
+
 impl From<u32> for TextureFormat {
     fn from(value: u32) -> Self {
         match value {
@@ -310,14 +328,17 @@ impl From<u32> for TextureFormat {
     }
 }
 
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, PartialEq, Default)]
 pub enum TexCoordTransformationMode {
+    #[default]
     None = 0,
     TexCoord = 1,
     Normal = 2,
     Vertex = 3,
 }
 
+savestate_enum!(TexCoordTransformationMode { None, TexCoord, Normal, Vertex });
+
 impl From<u32> for TexCoordTransformationMode {
     fn from(value: u32) -> Self {
         match value {
@@ -325,12 +346,12 @@ impl From<u32> for TexCoordTransformationMode {
             2 => TexCoordTransformationMode::Normal,
             1 => TexCoordTransformationMode::TexCoord,
             3 => TexCoordTransformationMode::Vertex,
-            _ => todo!(),
+            _ => unreachable!(),
         }
     }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Default)]
 pub struct PolygonAttributes {
     pub lights_enabled: [bool; 4],
     pub mode: PolygonMode,
@@ -345,6 +366,9 @@ pub struct PolygonAttributes {
     pub polygon_id: u8,
 }
 
+savestate!(PolygonAttributes { lights_enabled, mode, render_back, render_front, set_depth_translucent, render_far_plane_intersecting,
+    render_1dot_behind_depth, depth_test_eq, fog_enable, alpha, polygon_id });
+
 impl PolygonAttributes {
     pub fn new() -> Self {
         PolygonAttributes {
@@ -380,14 +404,17 @@ impl PolygonAttributes {
     }
 }
 
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
 pub enum PolygonMode {
+    #[default]
     Modulation = 0,
     Decal = 1,
     ToonHighlight = 2,
     Shadow = 3,
 }
 
+savestate_enum!(PolygonMode { Modulation, Decal, ToonHighlight, Shadow });
+
 impl From<u32> for PolygonMode {
     fn from(value: u32) -> Self {
         match value {
@@ -406,6 +433,8 @@ pub struct FrameParams {
     pub w_buffer: bool,
 }
 
+savestate!(FrameParams { manual_sort_translucent, w_buffer });
+
 impl FrameParams {
     pub fn new() -> Self {
         FrameParams {
@@ -429,6 +458,8 @@ pub struct Viewport {
     height: i32,
 }
 
+savestate!(Viewport { x1, y1, x2, y2, width, height });
+
 impl Viewport {
     pub fn new() -> Self {
         Viewport {
@@ -446,12 +477,9 @@ impl Viewport {
         self.y1 = (value >> 8) as u8 as i32;
         self.x2 = (value >> 16) as u8 as i32;
         self.y2 = (value >> 24) as u8 as i32;
-        assert!((self.y1 as usize) < GPU::HEIGHT);
-        assert!((self.y2 as usize) < GPU::HEIGHT);
-        self.width = self.x2 - self.x1 + 1;
-        self.height = self.y2 - self.y1 + 1;
-        assert!(self.width as usize <= GPU::WIDTH);
-        assert!(self.height as usize <= GPU::HEIGHT);
+        // Wraps like the hardware when the corners are swapped, and anything past the screen isn't drawn
+        self.width = (self.x2 - self.x1 + 1) & 0x1FF;
+        self.height = (self.y2 - self.y1 + 1) & 0xFF;
     }
 
     pub fn screen_coords(&self, clip_coords: &Vec4) -> [u32; 2] {
@@ -474,6 +502,7 @@ impl Viewport {
     }
 }
 
+#[derive(Clone, Copy)]
 pub enum VertexPrimitive {
     Triangles = 0,
     Quad = 1,
@@ -481,6 +510,8 @@ pub enum VertexPrimitive {
     QuadStrips = 3,
 }
 
+savestate_enum!(VertexPrimitive { Triangles, Quad, TriangleStrips, QuadStrips });
+
 impl From<u32> for VertexPrimitive {
     fn from(value: u32) -> Self {
         match value {
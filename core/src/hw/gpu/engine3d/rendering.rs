@@ -2,8 +2,11 @@ use super::{
     super::VRAM,
     Color, Engine3D, GPU, TextureFormat,
     geometry::{Polygon, Vertex},
+    math::{FixedPoint, Matrix},
     registers::{DISP3DCNT, PolygonMode},
+    textures::Texture,
 };
+use crate::fault::Fault;
 
 impl Engine3D {
     pub fn pixel_color(&self, index: usize) -> u16 {
@@ -19,7 +22,7 @@ impl Engine3D {
     pub fn render(&mut self, vram: &VRAM) {
         if !self.polygons_submitted { return }
         // TODO: Optimize
-        for pixel in self.frame_buffer.iter_mut() {
+        for pixel in self.back_buffer.iter_mut() {
             pixel.color = FrameBufferColor::new5(
                 Color::new5(
                     self.clear_color.r,
@@ -31,13 +34,22 @@ impl Engine3D {
             pixel.depth = self.clear_depth.depth();
         }
 
-        assert!(!self.frame_params.w_buffer); // TODO: Implement W-Buffer
-        assert!(!self.disp3dcnt.alpha_test); // TODO: Implement alpha test
+        // TODO: Implement W-Buffer and alpha test
+        if self.frame_params.w_buffer { return self.faults.raise(Fault::Unimplemented("W-Buffering".to_string())) }
+        if self.disp3dcnt.alpha_test { return self.faults.raise(Fault::Unimplemented("Alpha Test".to_string())) }
+
+        if self.capture_requested { self.capture_polygons() }
+        let edges = if self.wireframe { self.wireframe_edges() } else { Vec::new() };
+        let replacements = self.texture_replacements(vram);
 
         let disp3dcnt = &self.disp3dcnt;
         let toon_table = &self.toon_table;
-        let blend = |polygon: &Polygon, vert_color, s: i32, t: i32| {
-            let tex_color = Self::get_tex_color(vram, polygon, s, t);
+        let faults = &self.faults;
+        let blend = |polygon: &Polygon, replacement: Option<&Texture>, vert_color, s: i32, t: i32| {
+            let tex_color = match replacement {
+                Some(texture) => Some(Self::get_replacement_color(polygon, texture, s, t)),
+                None => Self::get_tex_color(vram, polygon, s >> 4, t >> 4),
+            };
             let modulation_blend = |val1, val2| ((val1 + 1) * (val2 + 1) - 1) / 64;
             match polygon.attrs.mode {
                 PolygonMode::Modulation => Self::blend_tex(tex_color, vert_color,
@@ -53,40 +65,87 @@ impl Engine3D {
                 },
                 // TODO: Use decal blending
                 PolygonMode::Shadow => tex_color.unwrap_or_else(|| FrameBufferColor::new5(Color::new5(0, 0, 0), 0)),
-                _ => todo!(),
+                _ => { faults.raise(Fault::Unimplemented("Decal Polygons".to_string())); vert_color },
             }
         };
 
         let vertices = &self.vertices;
-        let frame_buffer = &mut self.frame_buffer;
-        let mut render = |polygon: Polygon| {
+        let original_verts = &self.polygon_original_verts;
+        let frame_buffer = &mut self.back_buffer;
+        let mut render = |i: usize, polygon: &Polygon| {
             let vertices = &vertices[polygon.start_vert..polygon.end_vert];
-            Self::render_polygon(disp3dcnt, blend, &polygon, vertices, frame_buffer);
+            let original_verts = &original_verts[polygon.start_original_vert..polygon.end_original_vert];
+            // Empty unless a texture pack is set
+            let replacement = replacements.get(i).and_then(Option::as_deref);
+            Self::render_polygon(disp3dcnt, |polygon, vert_color, s, t| blend(polygon, replacement, vert_color, s, t),
+                polygon, vertices, original_verts, frame_buffer);
         };
 
         if disp3dcnt.alpha_blending {
-            let (opaque, translucent): (Vec<Polygon>, Vec<Polygon>) = self.polygons.drain(..).partition(
-                |polygon| polygon.attrs.alpha == 0x1F
-            );
-    
-            for polygon in opaque {
-                render(polygon)
+            // Opaque polygons are drawn first, without splitting them off into another list
+            for (i, polygon) in self.polygons.iter().enumerate().filter(|(_, polygon)| polygon.attrs.alpha == 0x1F) {
+                render(i, polygon)
             }
-            for polygon in translucent {
-                render(polygon)
+            for (i, polygon) in self.polygons.iter().enumerate().filter(|(_, polygon)| polygon.attrs.alpha != 0x1F) {
+                render(i, polygon)
             }
         } else {
-            for polygon in self.polygons.drain(..) {
-                render(polygon)
+            for (i, polygon) in self.polygons.iter().enumerate() {
+                render(i, polygon)
             }
         }
 
+        for (start, end) in edges { self.draw_line(start, end) }
+
+        std::mem::swap(&mut self.frame_buffer, &mut self.back_buffer);
+        self.finish_render();
+    }
+
+    // Leaves the geometry engine as a render would without drawing anything, for frames that aren't shown
+    pub fn skip_render(&mut self) {
+        if !self.polygons_submitted { return }
+        self.finish_render();
+    }
+
+    fn finish_render(&mut self) {
+        self.polygons.clear();
         self.vertices.clear();
+        self.polygon_original_verts.clear();
         self.gxstat.geometry_engine_busy = false;
         self.polygons_submitted = false;
     }
 
-    fn render_polygon<B>(disp3dcnt: &DISP3DCNT, blend: B, polygon: &Polygon, vertices: &[Vertex], frame_buffer: &mut [FrameBufferPixel])
+    fn wireframe_edges(&self) -> Vec<([u32; 2], [u32; 2])> {
+        let mut edges = Vec::new();
+        for polygon in self.polygons.iter() {
+            let vertices = &self.vertices[polygon.start_vert..polygon.end_vert];
+            for (i, vert) in vertices.iter().enumerate() {
+                edges.push((vert.screen_coords, vertices[(i + 1) % vertices.len()].screen_coords));
+            }
+        }
+        edges
+    }
+
+    fn draw_line(&mut self, start: [u32; 2], end: [u32; 2]) {
+        let (mut x, mut y) = (start[0] as i32, start[1] as i32);
+        let (end_x, end_y) = (end[0] as i32, end[1] as i32);
+        let (dx, dy) = ((end_x - x).abs(), -(end_y - y).abs());
+        let (step_x, step_y) = (if x < end_x { 1 } else { -1 }, if y < end_y { 1 } else { -1 });
+        let mut err = dx + dy;
+        loop {
+            if (0..GPU::WIDTH as i32).contains(&x) && (0..GPU::HEIGHT as i32).contains(&y) {
+                self.back_buffer[y as usize * GPU::WIDTH + x as usize].color =
+                    FrameBufferColor::new5(Color::new5(0x1F, 0x1F, 0x1F), 0x1F);
+            }
+            if x == end_x && y == end_y { break }
+            let err2 = 2 * err;
+            if err2 >= dy { err += dy; x += step_x }
+            if err2 <= dx { err += dx; y += step_y }
+        }
+    }
+
+    fn render_polygon<B>(disp3dcnt: &DISP3DCNT, blend: B, polygon: &Polygon, vertices: &[Vertex],
+        original_verts: &[(Matrix, [FixedPoint; 3])], frame_buffer: &mut [FrameBufferPixel])
         where B: Fn(&Polygon, FrameBufferColor, i32, i32) -> FrameBufferColor {
         if polygon.attrs.mode == PolygonMode::Shadow { return }
         let depth_test = Self::get_depth_test(polygon);
@@ -136,6 +195,8 @@ impl Engine3D {
         right_vert = new_right_vert;
 
         for y in vertices[start_vert].screen_coords[1]..vertices[end_vert].screen_coords[1] {
+            // The viewport can extend past the bottom of the screen
+            if y as usize >= GPU::HEIGHT { break }
             // Find next vertex below current 
             while y >= left_end {
                 let new_left_vert = next_left(left_vert);
@@ -157,7 +218,7 @@ impl Engine3D {
             let w_start = left_slope.next_w() as i16;
             let w_end = right_slope.next_w() as i16;
             assert!(x_end >= x_start, "{}", (|| {
-                for vert in polygon.original_verts.iter() {
+                for vert in original_verts.iter() {
                     println!("Clip: {:?}", vert.0);
                     println!("OVert: {:?}", vert.1);
                     println!("Vert: {:?}", vert.0 * super::math::Vec4::new(vert.1[0], vert.1[1], vert.1[2], super::math::FixedPoint::one()));
@@ -198,13 +259,15 @@ impl Engine3D {
             );
 
             for x in x_start..x_end {
+                if x >= GPU::WIDTH { break }
                 let y = y as usize;
                 let depth_val = depth.next() as u32;
                 let pixel = &mut frame_buffer[y * GPU::WIDTH + x];
 
                 let vert_color = FrameBufferColor::new5(color.next(), polygon.attrs.alpha);
                 let fb_color = &pixel.color;
-                let poly_color = blend(polygon, vert_color, s.next() as i32 >> 4, t.next() as i32 >> 4);
+                // Texture coordinates are passed in 1/16ths of a texel for replacements that are sampled more finely
+                let poly_color = blend(polygon, vert_color, s.next() as i32, t.next() as i32);
                 if poly_color.a5() == 0 {
                     // Pixel is totally tranpsarent so not rendered
                 } else if disp3dcnt.alpha_blending && fb_color.a5() != 0 && poly_color.a5() != 0x1F {
@@ -227,7 +290,7 @@ impl Engine3D {
         }
     }
 
-    fn get_tex_color(vram: &VRAM, polygon: &Polygon, s: i32, t: i32) -> Option<FrameBufferColor> {
+    pub(super) fn get_tex_color(vram: &VRAM, polygon: &Polygon, s: i32, t: i32) -> Option<FrameBufferColor> {
         let vram_offset = polygon.tex_params.vram_offset;
         let pal_offset = polygon.palette_base;
         let size = (polygon.tex_params.size_s as u32, polygon.tex_params.size_t as u32);
@@ -582,12 +645,14 @@ impl<const N: u8, const M: u8> std::ops::SubAssign<Frac::<M>> for Frac<N> {
     }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Default)]
 pub struct FrameBufferPixel {
     color: FrameBufferColor,
     depth: u32,
 }
 
+savestate!(FrameBufferPixel { color, depth });
+
 impl FrameBufferPixel {
     pub fn new() -> Self {
         FrameBufferPixel {
@@ -597,12 +662,14 @@ impl FrameBufferPixel {
     }
 }
 
-#[derive(Clone, Copy)]
-struct FrameBufferColor {
+#[derive(Clone, Copy, Default)]
+pub struct FrameBufferColor {
     color: Color,
     a: u8,
 }
 
+savestate!(FrameBufferColor { color, a });
+
 impl FrameBufferColor {
     pub fn new5(color: Color, a: u8) -> Self {
         FrameBufferColor {
@@ -631,6 +698,10 @@ impl FrameBufferColor {
     pub fn g6(&self) -> u8 { self.color.g6() }
     pub fn b6(&self) -> u8 { self.color.b6() }
     pub fn a6(&self) -> u8 { self.a >> 2 }
+    pub fn r8(&self) -> u8 { self.color.r8() }
+    pub fn g8(&self) -> u8 { self.color.g8() }
+    pub fn b8(&self) -> u8 { self.color.b8() }
+    pub fn a8(&self) -> u8 { self.a }
 
     // TODO: Convert 2D engine to also use 8 bit color
     pub fn as_u16(&self) -> u16 {
@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+#[cfg(feature = "host")]
+use std::fs::{self, File};
+use std::io;
+#[cfg(feature = "host")]
+use std::io::{BufReader, BufWriter};
+#[cfg(feature = "host")]
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use super::{
+    super::VRAM,
+    Color, Engine3D,
+    geometry::Polygon,
+    rendering::FrameBufferColor,
+};
+
+// RGBA8 texels, row by row
+pub struct Texture {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+impl Texture {
+    // FNV-1a of the decoded texels, so a texture has the same hash wherever it's loaded in VRAM
+    pub fn hash(&self) -> u64 {
+        [self.width as u32, self.height as u32].iter().flat_map(|size| size.to_le_bytes()).chain(self.pixels.iter().copied())
+        .fold(0xCBF2_9CE4_8422_2325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x0100_0000_01B3))
+    }
+}
+
+pub trait TexturePack: Send {
+    // Called once for each texture the first time it's drawn
+    fn dump(&mut self, hash: u64, texture: &Texture) -> io::Result<()>;
+    // Replacements have to be the same shape as the original, but can be any multiple of its size
+    fn replacement(&mut self, hash: u64) -> Option<Texture>;
+}
+
+// Textures are PNGs named after their hash. Either directory can be left out to only dump or only replace.
+#[cfg(feature = "host")]
+pub struct TextureDir {
+    dump_dir: Option<PathBuf>,
+    replacement_dir: Option<PathBuf>,
+}
+
+#[cfg(feature = "host")]
+impl TextureDir {
+    pub fn new(dump_dir: Option<PathBuf>, replacement_dir: Option<PathBuf>) -> Self {
+        TextureDir {
+            dump_dir,
+            replacement_dir,
+        }
+    }
+
+    fn file_name(hash: u64) -> String {
+        format!("{:016X}.png", hash)
+    }
+
+    fn read_png(path: &std::path::Path) -> Result<Texture, png::DecodingError> {
+        let mut decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info()?;
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf)?;
+        let buf = &buf[..info.buffer_size()];
+        let pixels = match info.color_type {
+            png::ColorType::Rgba => buf.to_vec(),
+            png::ColorType::Rgb => buf.chunks_exact(3).flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 0xFF]).collect(),
+            png::ColorType::GrayscaleAlpha => buf.chunks_exact(2).flat_map(|ga| [ga[0], ga[0], ga[0], ga[1]]).collect(),
+            png::ColorType::Grayscale => buf.iter().flat_map(|g| [*g, *g, *g, 0xFF]).collect(),
+            png::ColorType::Indexed => unreachable!(),
+        };
+        Ok(Texture { width: info.width as usize, height: info.height as usize, pixels })
+    }
+}
+
+#[cfg(feature = "host")]
+impl TexturePack for TextureDir {
+    fn dump(&mut self, hash: u64, texture: &Texture) -> io::Result<()> {
+        let dir = match &self.dump_dir { Some(dir) => dir, None => return Ok(()) };
+        let path = dir.join(TextureDir::file_name(hash));
+        if path.exists() { return Ok(()) }
+        fs::create_dir_all(dir)?;
+        let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), texture.width as u32, texture.height as u32);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header()?.write_image_data(&texture.pixels)?;
+        Ok(())
+    }
+
+    fn replacement(&mut self, hash: u64) -> Option<Texture> {
+        let path = self.replacement_dir.as_ref()?.join(TextureDir::file_name(hash));
+        if !path.exists() { return None }
+        TextureDir::read_png(&path).map_err(|err| warn!("Unable to Load {}: {}!", path.display(), err)).ok()
+    }
+}
+
+// Textures from the pack are looked up by hash once and kept for the rest of the session
+pub(super) struct TextureCache {
+    pack: Box<dyn TexturePack>,
+    replacements: HashMap<u64, Option<Arc<Texture>>>,
+}
+
+impl TextureCache {
+    pub fn new(pack: Box<dyn TexturePack>) -> Self {
+        TextureCache {
+            pack,
+            replacements: HashMap::new(),
+        }
+    }
+}
+
+impl Engine3D {
+    pub fn set_texture_pack(&mut self, pack: Option<Box<dyn TexturePack>>) {
+        self.texture_cache = pack.map(TextureCache::new);
+    }
+
+    // Decodes every texture drawn this frame, dumping the new ones and finding their replacements
+    pub(super) fn texture_replacements(&mut self, vram: &VRAM) -> Vec<Option<Arc<Texture>>> {
+        let cache = match self.texture_cache.as_mut() { Some(cache) => cache, None => return Vec::new() };
+        // The same texture is usually drawn by many polygons, so each one is only decoded once a frame
+        let mut frame_textures = HashMap::new();
+        self.polygons.iter().map(|polygon| {
+            let params = &polygon.tex_params;
+            let key = (params.vram_offset, params.format as u8, params.size_s, params.size_t, params.color0_transparent,
+                polygon.palette_base);
+            frame_textures.entry(key).or_insert_with(|| {
+                let texture = Engine3D::decode_texture(vram, polygon)?;
+                let hash = texture.hash();
+                if !cache.replacements.contains_key(&hash) {
+                    cache.pack.dump(hash, &texture)
+                    .unwrap_or_else(|err| warn!("Unable to Dump Texture {:016X}: {}!", hash, err));
+                    let replacement = cache.pack.replacement(hash).filter(|replacement| {
+                        let scale = replacement.width / texture.width;
+                        let fits = scale > 0 && replacement.width == scale * texture.width &&
+                            replacement.height == scale * texture.height;
+                        if !fits {
+                            warn!("Unable to Replace Texture {:016X}: {}x{} isn't a multiple of {}x{}!", hash,
+                                replacement.width, replacement.height, texture.width, texture.height);
+                        }
+                        fits
+                    });
+                    cache.replacements.insert(hash, replacement.map(Arc::new));
+                }
+                cache.replacements[&hash].clone()
+            }).clone()
+        }).collect()
+    }
+
+    fn decode_texture(vram: &VRAM, polygon: &Polygon) -> Option<Texture> {
+        let (width, height) = (polygon.tex_params.size_s, polygon.tex_params.size_t);
+        let mut pixels = Vec::with_capacity(4 * width * height);
+        for t in 0..height as i32 {
+            for s in 0..width as i32 {
+                let color = Engine3D::get_tex_color(vram, polygon, s, t)?;
+                pixels.extend_from_slice(&[color.r8(), color.g8(), color.b8(), color.a8()]);
+            }
+        }
+        Some(Texture { width, height, pixels })
+    }
+
+    // Coordinates are in 1/16ths of a texel of the original texture
+    pub(super) fn get_replacement_color(polygon: &Polygon, texture: &Texture, s: i32, t: i32) -> FrameBufferColor {
+        let params = &polygon.tex_params;
+        let scale = (texture.width / params.size_s) as i32;
+        let s = Engine3D::wrap_tex_coord((s * scale) >> 4, texture.width, params.repeat_s, params.flip_s);
+        let t = Engine3D::wrap_tex_coord((t * scale) >> 4, texture.height, params.repeat_t, params.flip_t);
+        let rgba = &texture.pixels[4 * (t * texture.width + s)..][..4];
+        FrameBufferColor::new8(Color::new8(rgba[0], rgba[1], rgba[2]), rgba[3])
+    }
+
+    fn wrap_tex_coord(coord: i32, size: usize, repeat: bool, flip: bool) -> usize {
+        let size = size as i32;
+        if !repeat { return coord.clamp(0, size - 1) as usize }
+        let wrapped = coord.rem_euclid(size);
+        (if flip && coord.div_euclid(size) % 2 != 0 { size - 1 - wrapped } else { wrapped }) as usize
+    }
+}
@@ -4,6 +4,7 @@ mod engine3d;
 mod vram;
 pub mod debug;
 
+use crate::fault::{Fault, Faults};
 use crate::hw::{
     HW,
     scheduler::{Event, Scheduler},
@@ -12,7 +13,9 @@ use crate::hw::{
 };
 
 pub use engine2d::Engine2D;
-pub use engine3d::Engine3D;
+pub use engine3d::{Engine3D, CapturedPolygon, CapturedVertex, PolygonMode, Texture, TextureFormat, TexturePack};
+#[cfg(feature = "host")]
+pub use engine3d::TextureDir;
 pub use vram::VRAM;
 pub use registers::{DISPSTAT, DISPSTATFlags, DISPCAPCNT, POWCNT1};
 
@@ -23,6 +26,8 @@ pub struct GPU {
     // Registers and Values Shared between Engines
     pub dispstats: [DISPSTAT; 2],
     pub vcount: u16,
+    // Written to VCOUNT, replaces the next line number
+    next_vcount: Option<u16>,
     rendered_frame: bool,
 
     pub engine_a: Engine2D<EngineA>,
@@ -33,6 +38,28 @@ pub struct GPU {
     pub dispcapcnt: DISPCAPCNT,
     capturing: bool,
     pub powcnt1: POWCNT1,
+    // The GBA picture is centered on the top screen and the bottom screen is left blank
+    gba_screens: Option<[Vec<u16>; 2]>,
+    // Finished frames in RGBA8, updated at VBlank
+    frame_buffers: [Vec<u8>; 2],
+    frame_count: u64,
+    // Frames left undrawn after each drawn one, which still run every event that affects timing
+    pub frameskip: usize,
+    frames_to_skip: usize,
+    skipping: bool,
+    faults: Faults,
+}
+
+savestate!(GPU { dispstats, vcount, rendered_frame, engine_a, engine_b, engine3d, vram, dispcapcnt, capturing, powcnt1, gba_screens,
+    frame_buffers, frame_count, next_vcount });
+
+pub struct Frame<'a> {
+    pub top: &'a [u8],
+    pub bottom: &'a [u8],
+    pub width: usize,
+    pub height: usize,
+    // Number of frames finished since power on
+    pub count: u64,
 }
 
 impl GPU {
@@ -47,23 +74,71 @@ impl GPU {
     const HBLANK_DOT: usize = 256 + 8;
     const DOTS_PER_LINE: usize = 355;
     const NUM_LINES: usize = 263;
+    pub const CYCLES_PER_FRAME: usize = GPU::CYCLES_PER_DOT * GPU::DOTS_PER_LINE * GPU::NUM_LINES;
 
-    pub fn new(scheduler: &mut Scheduler) -> GPU {
+    const GBA_WIDTH: usize = 240;
+    const GBA_HEIGHT: usize = 160;
+    const GBA_CYCLES_PER_DOT: usize = 8;
+    const GBA_HBLANK_DOT: usize = 240;
+    const GBA_DOTS_PER_LINE: usize = 308;
+    const GBA_NUM_LINES: usize = 228;
+
+    pub fn new(scheduler: &mut Scheduler, faults: Faults) -> GPU {
         scheduler.schedule(Event::HBlank, HW::on_hblank, GPU::HBLANK_DOT * GPU::CYCLES_PER_DOT);
         GPU {
             // Registers and Values Shared between Engines
             dispstats: [DISPSTAT::new(), DISPSTAT::new()],
             vcount: 0,
+            next_vcount: None,
             rendered_frame: false,
 
-            engine_a: Engine2D::new(),
-            engine_b: Engine2D::new(),
-            engine3d: Engine3D::new(),
+            engine_a: Engine2D::new(faults.clone()),
+            engine_b: Engine2D::new(faults.clone()),
+            engine3d: Engine3D::new(faults.clone()),
             vram: VRAM::new(),
 
             dispcapcnt: DISPCAPCNT::new(),
             capturing: false,
             powcnt1: POWCNT1::ENABLE_LCDS,
+            gba_screens: None,
+            frame_buffers: [vec![0; 4 * GPU::WIDTH * GPU::HEIGHT], vec![0; 4 * GPU::WIDTH * GPU::HEIGHT]],
+            frame_count: 0,
+            frameskip: 0,
+            frames_to_skip: 0,
+            skipping: false,
+            faults,
+        }
+    }
+
+    pub fn enter_gba_mode(&mut self, scheduler: &mut Scheduler) {
+        self.gba_screens = Some([vec![0; GPU::WIDTH * GPU::HEIGHT], vec![0; GPU::WIDTH * GPU::HEIGHT]]);
+        self.powcnt1 = POWCNT1::ENABLE_LCDS | POWCNT1::ENABLE_ENGINE_A | POWCNT1::TOP_A;
+        self.capturing = false;
+        self.engine_a.enter_gba_mode(scheduler);
+        self.vram.enter_gba_mode();
+    }
+
+    pub fn height(&self) -> u16 {
+        (if self.gba_screens.is_some() { GPU::GBA_HEIGHT } else { GPU::HEIGHT }) as u16
+    }
+
+    fn num_lines(&self) -> u16 {
+        (if self.gba_screens.is_some() { GPU::GBA_NUM_LINES } else { GPU::NUM_LINES }) as u16
+    }
+
+    pub fn cycles_per_frame(&self) -> usize {
+        if self.gba_screens.is_some() {
+            GPU::GBA_CYCLES_PER_DOT * GPU::GBA_DOTS_PER_LINE * GPU::GBA_NUM_LINES
+        } else { GPU::CYCLES_PER_FRAME }
+    }
+
+    // Cycles from the start of a line until HBlank and from HBlank until the next line
+    fn line_timings(&self) -> (usize, usize) {
+        if self.gba_screens.is_some() {
+            (GPU::GBA_HBLANK_DOT * GPU::GBA_CYCLES_PER_DOT,
+                (GPU::GBA_DOTS_PER_LINE - GPU::GBA_HBLANK_DOT) * GPU::GBA_CYCLES_PER_DOT)
+        } else {
+            (GPU::HBLANK_DOT * GPU::CYCLES_PER_DOT, (GPU::DOTS_PER_LINE - GPU::HBLANK_DOT) * GPU::CYCLES_PER_DOT)
         }
     }
 
@@ -71,26 +146,58 @@ impl GPU {
     pub fn start_next_line(&mut self) {
         for dispstat in self.dispstats.iter_mut() { dispstat.remove(DISPSTATFlags::HBLANK) }
 
-        if self.vcount == 262 {
+        let vblank = self.height()..self.num_lines();
+        self.vcount = match self.next_vcount.take() {
+            Some(vcount) if vblank.contains(&vcount) => vcount,
+            Some(vcount) => {
+                warn!("Ignoring VCOUNT Write of {} Outside of VBlank", vcount);
+                (self.vcount + 1) % self.num_lines()
+            },
+            None => (self.vcount + 1) % self.num_lines(),
+        };
+        if self.vcount == 0 {
             self.engine_a.latch_affine();
             self.engine_b.latch_affine();
         }
-        self.vcount += 1;
-        if self.vcount == GPU::NUM_LINES as u16 {
-            self.vcount = 0;
+    }
+
+    // Used to sync consoles in local multiplayer, only lines in VBlank can be skipped or repeated.
+    // The line length doesn't change, so the line events don't have to be rescheduled.
+    pub fn write_vcount(&mut self, byte: usize, value: u8) {
+        if !(self.height()..self.num_lines()).contains(&self.vcount) {
+            warn!("Ignoring VCOUNT Write on Line {}", self.vcount);
+            return
         }
+        let vcount = self.next_vcount.unwrap_or(self.vcount);
+        self.next_vcount = Some(match byte {
+            0 => vcount & !0x00FF | value as u16,
+            1 => vcount & !0xFF00 | (value as u16 & 0x1) << 8,
+            _ => unreachable!(),
+        });
     }
 
     // Dot: HBLANK_DOT - TODO: Check for drift
     pub fn render_line(&mut self) {
+        if let Some(screens) = &mut self.gba_screens {
+            if self.skipping { return }
+            self.engine_a.render_line(&self.engine3d, &self.vram, self.vcount);
+            let line = self.vcount as usize;
+            let start = (line + (GPU::HEIGHT - GPU::GBA_HEIGHT) / 2) * GPU::WIDTH + (GPU::WIDTH - GPU::GBA_WIDTH) / 2;
+            screens[0][start..start + GPU::GBA_WIDTH]
+                .copy_from_slice(&self.engine_a.pixels()[line * GPU::WIDTH..line * GPU::WIDTH + GPU::GBA_WIDTH]);
+            return
+        }
         // TODO: Use POWCNT to selectively render engines
-        if self.powcnt1.contains(POWCNT1::ENABLE_ENGINE_A) {
+        // Captures write to VRAM and the main memory display FIFO has to be drained for its DMA to keep going,
+        // so Engine A is still drawn for them in skipped frames
+        let engine_a_needed = self.capturing || self.engine_a.displays_main_mem();
+        if self.powcnt1.contains(POWCNT1::ENABLE_ENGINE_A) && (!self.skipping || engine_a_needed) {
             self.engine_a.render_line(&self.engine3d, &self.vram, self.vcount);
             if self.capturing && (self.vcount as usize) < self.dispcapcnt.capture_size.height() {
                 self.capture();
             }
         }
-        if self.powcnt1.contains(POWCNT1::ENABLE_ENGINE_B) {
+        if self.powcnt1.contains(POWCNT1::ENABLE_ENGINE_B) && !self.skipping {
             self.engine_b.render_line(&self.engine3d, &self.vram, self.vcount)
         }
     }
@@ -107,7 +214,8 @@ impl GPU {
         let src_a_range = start_addr..start_addr + width;
         let mut src_b = [0; 2 * GPU::WIDTH];
         if self.dispcapcnt.src_b_fifo {
-            todo!()
+            self.faults.raise(Fault::Unimplemented("Capturing the Main Memory Display FIFO".to_string()));
+            return
         } else {
             let offset = 2 * start_addr + if self.engine_a.dispcnt.display_mode == DisplayMode::Mode2 {
                 0
@@ -118,6 +226,7 @@ impl GPU {
         }
 
         let offset = 2 * start_addr + self.dispcapcnt.vram_write_offset.offset();
+        self.vram.writes += 1;
         let bank = &mut self.vram.banks[self.dispcapcnt.vram_write_block];
         // TODO: Replace write_mem and read_mem with slice conversions
         match self.dispcapcnt.capture_src {
@@ -149,6 +258,11 @@ impl GPU {
         }
     }
 
+    pub fn invalidate_lines(&mut self) {
+        self.engine_a.invalidate_lines();
+        self.engine_b.invalidate_lines();
+    }
+
     pub fn bus_stalled(&self) -> bool {
         self.engine3d.bus_stalled
     }
@@ -159,7 +273,44 @@ impl GPU {
         rendered_frame
     }
 
-    pub fn get_screens(&self) -> [&Vec<u16>; 2] {
+    fn finish_frame(&mut self) {
+        if !self.skipping {
+            let mut frame_buffers = std::mem::take(&mut self.frame_buffers);
+            for (frame_buffer, screen) in frame_buffers.iter_mut().zip(self.get_screens().iter()) {
+                for (rgba, pixel) in frame_buffer.chunks_exact_mut(4).zip(screen.iter()) {
+                    let to_rgb8 = |shift: u16| { let value = (pixel >> shift & 0x1F) as u8; value << 3 | value >> 2 };
+                    rgba.copy_from_slice(&[to_rgb8(0), to_rgb8(5), to_rgb8(10), 0xFF]);
+                }
+            }
+            self.frame_buffers = frame_buffers;
+            self.frames_to_skip = self.frameskip;
+        }
+        self.frame_count += 1;
+        // Decided now so 3D is only rendered at VBlank when the next frame is drawn
+        self.frames_to_skip = self.frames_to_skip.min(self.frameskip);
+        self.skipping = self.frames_to_skip > 0;
+        if self.skipping { self.frames_to_skip -= 1 }
+    }
+
+    // Only as many frames are left to skip as the new frameskip allows, so lowering it to 0 draws the next frame
+    pub fn set_frameskip(&mut self, frameskip: usize) {
+        self.frameskip = frameskip;
+        self.frames_to_skip = self.frames_to_skip.min(frameskip);
+        if frameskip == 0 { self.skipping = false }
+    }
+
+    pub fn frame(&self) -> Frame<'_> {
+        Frame {
+            top: &self.frame_buffers[0],
+            bottom: &self.frame_buffers[1],
+            width: GPU::WIDTH,
+            height: GPU::HEIGHT,
+            count: self.frame_count,
+        }
+    }
+
+    fn get_screens(&self) -> [&Vec<u16>; 2] {
+        if let Some([top, bottom]) = &self.gba_screens { return [top, bottom] }
         if self.powcnt1.contains(POWCNT1::TOP_A) {
             [&self.engine_a.pixels(), &self.engine_b.pixels()]
         } else {
@@ -169,16 +320,19 @@ impl GPU {
 }
 
 impl HW {
-    fn start_next_line(&mut self, _event: Event) {
-        self.scheduler.schedule(Event::HBlank, HW::on_hblank, GPU::HBLANK_DOT * GPU::CYCLES_PER_DOT);
+    pub(super) fn start_next_line(&mut self, _event: Event) {
+        self.scheduler.schedule(Event::HBlank, HW::on_hblank, self.gpu.line_timings().0);
         self.gpu.start_next_line();
+        // The VBlank flag is cleared a line early, it isn't set during the last line of the frame
         if self.gpu.vcount == 0 {
             self.gpu.capturing = self.gpu.dispcapcnt.enable;
+        } else if self.gpu.vcount == self.gpu.num_lines() - 1 {
             for dispstat in self.gpu.dispstats.iter_mut() { dispstat.remove(DISPSTATFlags::VBLANK) }
-        } else if self.gpu.vcount == GPU::HEIGHT as u16 {
+        } else if self.gpu.vcount == self.gpu.height() {
             if self.gpu.capturing { self.gpu.dispcapcnt.enable = false }
             for dispstat in self.gpu.dispstats.iter_mut() { dispstat.insert(DISPSTATFlags::VBLANK) }
             self.gpu.rendered_frame = true;
+            self.gpu.finish_frame();
             
             self.on_vblank(Event::VBlank);
             self.check_dispstats(&mut |dispstat, interrupts|
@@ -188,22 +342,30 @@ impl HW {
             );
         }
 
+        if self.gpu.vcount == 0 { self.gpu.engine_a.clear_main_mem_fifo() }
+        if self.gpu.vcount < self.gpu.height() {
+            // HBlank DMAs that overran their HBlank period finish before the line is drawn
+            self.flush_dmas(DMAOccasion::HBlank);
+            self.gpu.engine_a.start_main_mem_line();
+            self.run_dmas(DMAOccasion::StartOfDisplay);
+            self.check_main_memory_display_dmas();
+        }
+
+        // Each CPU has its own VCount setting and IRQ enables
         let vcount = self.gpu.vcount;
-        self.check_dispstats(&mut |dispstat, interrupts|
-            if dispstat.contains(DISPSTATFlags::VBLANK_IRQ_ENABLE) && vcount == dispstat.vcount_setting {
+        self.check_dispstats(&mut |dispstat, interrupts| {
+            let matched = vcount == dispstat.vcount_setting;
+            dispstat.set(DISPSTATFlags::VCOUNTER, matched);
+            if matched && dispstat.contains(DISPSTATFlags::VCOUNTER_IRQ_ENABLE) {
                 interrupts.request |= InterruptRequest::VCOUNTER_MATCH;
             }
-        );
+        });
     }
 
-    fn on_hblank(&mut self, _event: Event) {
-        self.scheduler.schedule(
-            Event::StartNextLine,
-            HW::start_next_line,
-            (GPU::DOTS_PER_LINE - GPU::HBLANK_DOT) * GPU::CYCLES_PER_DOT
-        );
+    pub(super) fn on_hblank(&mut self, _event: Event) {
+        self.scheduler.schedule(Event::StartNextLine, HW::start_next_line, self.gpu.line_timings().1);
         for dispstat in self.gpu.dispstats.iter_mut() { dispstat.insert(DISPSTATFlags::HBLANK) }
-        if self.gpu.vcount < GPU::HEIGHT as u16 {
+        if self.gpu.vcount < self.gpu.height() {
             self.gpu.render_line();
             self.run_dmas(DMAOccasion::HBlank);
         }
@@ -218,7 +380,10 @@ impl HW {
         self.run_dmas(DMAOccasion::VBlank);
         // TODO: Render using multiple threads
         if self.gpu.powcnt1.contains(POWCNT1::ENABLE_3D_RENDERING) {
-            self.gpu.engine3d.render(&self.gpu.vram);
+            // Games that capture usually do so every frame, so their captures aren't left with a stale picture
+            if self.gpu.skipping && !self.gpu.capturing {
+                self.gpu.engine3d.skip_render();
+            } else { self.gpu.engine3d.render(&self.gpu.vram) }
             
             self.gpu.engine3d.exec_commands();
             self.check_geometry_command_fifo();
@@ -28,8 +28,8 @@ impl IORegister for POWCNT1 {
     fn write(&mut self, _scheduler: &mut Scheduler, byte: usize, value: u8) {
         assert!(byte < 4);
         HW::write_byte_to_value(&mut self.bits, byte, value);
+        // TODO: Blank the screens when ENABLE_LCDS is cleared
         self.bits &= POWCNT1::all().bits;
-        assert!(self.contains(POWCNT1::ENABLE_LCDS)); // TODO: Figure out what this does
     }
 }
 
@@ -40,15 +40,19 @@ bitflags! {
         const VCOUNTER = 1 << 2;
         const VBLANK_IRQ_ENABLE = 1 << 3;
         const HBLANK_IRQ_ENABLE = 1 << 4;
-        const VCOUNTER_IRQ_ENALBE = 1 << 5;
+        const VCOUNTER_IRQ_ENABLE = 1 << 5;
     }
 }
 
+savestate_bitflags!(POWCNT1, DISPSTATFlags);
+
 pub struct DISPSTAT {
     pub flags: DISPSTATFlags,
     pub vcount_setting: u16,
 }
 
+savestate!(DISPSTAT { flags, vcount_setting });
+
 impl DISPSTAT {
     pub fn new() -> DISPSTAT {
         DISPSTAT {
@@ -83,11 +87,10 @@ impl IORegister for DISPSTAT {
 
     fn write(&mut self, _scheduler: &mut Scheduler, byte: usize, value: u8) {
         match byte {
+            // The status flags are read only and bit 7 is bit 8 of the VCount setting, for lines 256 to 262
             0 => {
-                let old_bits = self.flags.bits;
                 self.flags.bits = self.flags.bits & 0x7 | ((value as u16) & !0x7 & DISPSTATFlags::all().bits);
-                assert_eq!(old_bits & 0x7, self.flags.bits & 0x7);
-                self.vcount_setting = self.vcount_setting & !0x100 | (value as u16 & 0x80) << 8;
+                self.vcount_setting = self.vcount_setting & !0x100 | (value as u16 & 0x80) << 1;
             },
             1 => self.vcount_setting = self.vcount_setting & !0xFF | value as u16,
             _ => unreachable!(),
@@ -108,6 +111,9 @@ pub struct DISPCAPCNT {
     pub enable: bool,
 }
 
+savestate!(DISPCAPCNT { eva, evb, vram_write_block, vram_write_offset, capture_size, src_a_is_3d_only, src_b_fifo, vram_read_offset,
+    capture_src, enable });
+
 impl DISPCAPCNT {
     pub fn new() -> DISPCAPCNT {
         DISPCAPCNT {
@@ -166,6 +172,8 @@ pub enum CaptureOffset {
     O18000 = 3,
 }
 
+savestate_enum!(CaptureOffset { O00000, O08000, O10000, O18000 });
+
 impl CaptureOffset {
     pub fn offset(&self) -> usize {
         match *self {
@@ -197,6 +205,8 @@ pub enum CaptureSize {
     S256x192 = 3,
 }
 
+savestate_enum!(CaptureSize { S128x128, S256x64, S256x128, S256x192 });
+
 impl CaptureSize {
     pub fn width(&self) -> usize {
         match *self {
@@ -236,6 +246,8 @@ pub enum CaptureSource {
     AB = 2,
 }
 
+savestate_enum!(CaptureSource { A, B, AB });
+
 impl From<u8> for CaptureSource {
     fn from(value: u8) -> Self {
         match value {
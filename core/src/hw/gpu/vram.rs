@@ -22,8 +22,13 @@ pub struct VRAM {
     engine_b_bg_ext_pal: Vec<Vec<Bank>>,
     engine_b_obj_ext_pal: Vec<Vec<Bank>>,
     arm7_wram: Vec<Vec<Bank>>,
+    // Writes that changed VRAM or its mapping, for skipping lines that don't need to be redrawn
+    pub(super) writes: u64,
 }
 
+savestate!(VRAM { cnts, banks, lcdc_enabled, lcdc, engine_a_bg, engine_a_obj, engine_a_bg_ext_pal, engine_a_obj_ext_pal, textures,
+    textures_pal, engine_b_bg, engine_b_obj, engine_b_bg_ext_pal, engine_b_obj_ext_pal, arm7_wram });
+
 impl VRAM {
     const BANKS_LEN: [usize; 9] = [128 * 0x400, 128 * 0x400, 128 * 0x400, 128 * 0x400,
         64 * 0x400, 16 * 0x400, 16 * 0x400, 32 * 0x400, 16 * 0x400];
@@ -83,6 +88,7 @@ impl VRAM {
             engine_b_bg_ext_pal: create_vecs(2),
             engine_b_obj_ext_pal: create_vecs(1),
             arm7_wram: create_vecs(2),
+            writes: 0,
         }
     }
 
@@ -93,11 +99,11 @@ impl VRAM {
     pub fn write_vram_cnt(&mut self, index: usize, value: u8) {
         let bank = Bank::from_index(index);
         let new_cnt = VRAMCNT::new(index, value);
+        if new_cnt.read() != self.cnts[index].read() { self.writes += 1 }
 
         if self.cnts[index].enabled {
             match (index, self.cnts[index].mst) {
                 (index, 0) => {
-                    assert!(self.lcdc_enabled[index]);
                     self.lcdc_enabled[index] = false;
                     VRAM::remove_mapping(&mut self.lcdc, bank, VRAM::LCDC_OFFSETS[index], None)
                 },
@@ -129,7 +135,8 @@ impl VRAM {
                 (VRAM::BANK_E, 3) => VRAM::remove_mapping(&mut self.textures_pal, bank, 0, None),
                 (VRAM::BANK_F ..= VRAM::BANK_G, 3) => VRAM::remove_mapping(&mut self.textures_pal,
                     bank, bank.get_textures_pal_offset(self.cnts[index].offset), None),
-                _ => unreachable!(),
+                // Invalid MSTs leave the bank unmapped
+                _ => (),
             }
         }
 
@@ -138,7 +145,6 @@ impl VRAM {
 
         match (index, new_cnt.mst) {
             (index, 0) => {
-                assert!(!self.lcdc_enabled[index]);
                 self.lcdc_enabled[index] = true;
                 VRAM::add_mapping(&mut self.lcdc, bank, VRAM::LCDC_OFFSETS[index], None)
             },
@@ -169,10 +175,31 @@ impl VRAM {
             (VRAM::BANK_E, 3) => VRAM::add_mapping(&mut self.textures_pal, bank, 0, None),
             (VRAM::BANK_F ..= VRAM::BANK_G, 3) => VRAM::add_mapping(&mut self.textures_pal,
                 bank, bank.get_textures_pal_offset(self.cnts[index].offset), None),
-            _ => unreachable!(),
+            _ => (),
         }
     }
 
+    // GBA VRAM is kept linearly in bank A, with the OBJ half also copied to bank B for Engine A's OBJs
+    pub fn enter_gba_mode(&mut self) {
+        for index in 0..self.cnts.len() { self.write_vram_cnt(index, 0) }
+        self.write_vram_cnt(VRAM::BANK_A, 0x81);
+        self.write_vram_cnt(VRAM::BANK_B, 0x82);
+    }
+
+    pub fn gba_read<T: MemoryValue>(&self, addr: usize) -> T {
+        let addr = addr & 0x1_FFFF;
+        let addr = if addr >= 0x1_8000 { addr - 0x8000 } else { addr };
+        HW::read_mem(&self.banks[VRAM::BANK_A], addr as u32)
+    }
+
+    pub fn gba_write<T: MemoryValue>(&mut self, addr: usize, value: T) {
+        let addr = addr & 0x1_FFFF;
+        let addr = if addr >= 0x1_8000 { addr - 0x8000 } else { addr };
+        if HW::read_mem::<T>(&self.banks[VRAM::BANK_A], addr as u32) != value { self.writes += 1 }
+        HW::write_mem(&mut self.banks[VRAM::BANK_A], addr as u32, value);
+        if addr >= 0x1_0000 { HW::write_mem(&mut self.banks[VRAM::BANK_B], (addr - 0x1_0000) as u32, value) }
+    }
+
     pub fn arm7_read<T: MemoryValue>(&self, addr: u32) -> T {
         let addr = (addr as usize) & (2 * VRAM::BANKS_LEN[VRAM::BANK_C] - 1);
         let index = (addr as usize) / VRAM::BANKS_LEN[VRAM::BANK_C];
@@ -208,7 +235,7 @@ impl VRAM {
         // TODO: Optimize - Slower than previous approach
         let index = addr as usize / VRAM::MAPPING_LEN;
         let addr = addr as usize;
-        match addr & 0x00E0_0000 {
+        let changed = match addr & 0x00E0_0000 {
             VRAM::ENGINE_A_BG_OFFSET => VRAM::write_mapping(&mut self.banks,
             &self.engine_a_bg[index % self.engine_a_bg.len()], addr, value),
             VRAM::ENGINE_B_BG_OFFSET => VRAM::write_mapping(&mut self.banks,
@@ -220,7 +247,8 @@ impl VRAM {
             VRAM::LCDC_OFFSET => VRAM::write_mapping(&mut self.banks,
             &self.lcdc[(addr & 0xF_C000) / VRAM::MAPPING_LEN], addr, value),
             _ => unreachable!(),
-        }
+        };
+        if changed { self.writes += 1 }
     }
 
     pub fn arm7_write<T: MemoryValue>(&mut self, addr: u32, value: T) {
@@ -228,10 +256,53 @@ impl VRAM {
         let index = (addr as usize) / VRAM::BANKS_LEN[VRAM::BANK_C];
         let addr = addr & (VRAM::BANKS_LEN[VRAM::BANK_C] - 1);
         for bank in self.arm7_wram[index].iter() {
+            if HW::read_mem::<T>(&self.banks[*bank as usize], addr as u32) != value { self.writes += 1 }
             HW::write_mem(&mut self.banks[*bank as usize], addr as u32, value);
         }
     }
 
+    fn arm9_mapping(&self, addr: usize) -> &Vec<Bank> {
+        let index = addr / VRAM::MAPPING_LEN;
+        match addr & 0x00E0_0000 {
+            VRAM::ENGINE_A_BG_OFFSET => &self.engine_a_bg[index & VRAM::ENGINE_A_BG_MASK],
+            VRAM::ENGINE_B_BG_OFFSET => &self.engine_b_bg[index & VRAM::ENGINE_B_BG_MASK],
+            VRAM::ENGINE_A_OBJ_OFFSET => &self.engine_a_obj[index & VRAM::ENGINE_A_OBJ_MASK],
+            VRAM::ENGINE_B_OBJ_OFFSET => &self.engine_b_obj[index & VRAM::ENGINE_B_OBJ_MASK],
+            VRAM::LCDC_OFFSET => &self.lcdc[(addr & 0xF_C000) / VRAM::MAPPING_LEN],
+            _ => unreachable!(),
+        }
+    }
+
+    // Returns the bank, offset into it, and number of bytes until the mapping may change
+    pub fn arm9_bank_region(&self, addr: u32) -> Option<(usize, usize, usize)> {
+        let addr = addr as usize;
+        match self.arm9_mapping(addr).as_slice() {
+            [bank] => Some((*bank as usize, addr & (VRAM::BANKS_LEN[*bank as usize] - 1),
+                VRAM::MAPPING_LEN - addr % VRAM::MAPPING_LEN)),
+            _ => None,
+        }
+    }
+
+    pub fn arm7_bank_region(&self, addr: u32) -> Option<(usize, usize, usize)> {
+        let addr = (addr as usize) & (2 * VRAM::BANKS_LEN[VRAM::BANK_C] - 1);
+        let index = addr / VRAM::BANKS_LEN[VRAM::BANK_C];
+        let addr = addr & (VRAM::BANKS_LEN[VRAM::BANK_C] - 1);
+        match self.arm7_wram[index].as_slice() {
+            [bank] => Some((*bank as usize, addr, VRAM::BANKS_LEN[VRAM::BANK_C] - addr)),
+            _ => None,
+        }
+    }
+
+    pub fn bank_mut(&mut self, bank: usize) -> &mut Vec<u8> {
+        self.writes += 1;
+        &mut self.banks[bank]
+    }
+
+    pub fn debug_bank_mem(&mut self, bank: usize, offset: usize) -> Option<&mut u8> {
+        self.writes += 1;
+        self.banks.get_mut(bank)?.get_mut(offset)
+    }
+
     pub fn get_lcdc_bank(&self, bank: u8) -> Option<&Vec<u8>> {
         if self.lcdc_enabled[bank as usize] { Some(&self.banks[bank as usize]) } else { None }
     }
@@ -287,18 +358,22 @@ impl VRAM {
         value
     }
 
-    fn write_mapping<T: MemoryValue>(banks: &mut [Vec<u8>], mapping: &Vec<Bank>, addr: usize, value: T) {
+    // Returns whether any of the banks changed
+    fn write_mapping<T: MemoryValue>(banks: &mut [Vec<u8>], mapping: &[Bank], addr: usize, value: T) -> bool {
+        let mut changed = false;
         for bank in mapping.iter() {
             let addr = addr & (VRAM::BANKS_LEN[*bank as usize] - 1);
+            changed |= HW::read_mem::<T>(&banks[*bank as usize], addr as u32) != value;
             HW::write_mem(&mut banks[*bank as usize], addr as u32, value);
         }
+        changed
     }
 
     fn add_mapping(arr: &mut [Vec<Bank>], bank: Bank, offset: usize, size: Option<usize>) {
         let size = size.unwrap_or_else(|| VRAM::BANKS_LEN[bank as usize]);
         for addr in (0..size).step_by(VRAM::MAPPING_LEN) {
-            assert!(arr[(addr + offset) / VRAM::MAPPING_LEN].iter().position(|b| *b == bank).is_none());
-            arr[(addr + offset) / VRAM::MAPPING_LEN].push(bank);
+            let vec = &mut arr[(addr + offset) / VRAM::MAPPING_LEN];
+            if !vec.contains(&bank) { vec.push(bank) }
         }
     }
 
@@ -312,14 +387,13 @@ impl VRAM {
         }
     }
 
+    // Only the low bit of the offset selects the ARM7 slot
     fn add_arm7_wram_mapping(&mut self, bank: Bank, offset: u8) {
-        assert!(offset < 2);
-        self.arm7_wram[offset as usize].push(bank);
+        self.arm7_wram[offset as usize & 0x1].push(bank);
     }
 
     fn remove_arm7_wram_mapping(&mut self, bank: Bank, offset: u8) {
-        assert!(offset < 2);
-        let vec = &mut self.arm7_wram[offset as usize];
+        let vec = &mut self.arm7_wram[offset as usize & 0x1];
         if let Some(pos) = vec.iter().position(|b| *b == bank) {
             vec.swap_remove(pos);
         }
@@ -338,6 +412,8 @@ struct VRAMCNT {
     byte: u8,
 }
 
+savestate!(VRAMCNT { mst, offset, enabled, byte });
+
 impl VRAMCNT {
     const MST_MASKS: [u8; 9] = [0x3, 0x3, 0x7, 0x7, 0x7, 0x7, 0x7, 0x3, 0x3];
     const OFS_MASKS: [u8; 9] = [0x3, 0x3, 0x3, 0x3, 0x0, 0x3, 0x3, 0x0, 0x0];
@@ -356,8 +432,9 @@ impl VRAMCNT {
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
 enum Bank {
+    #[default]
     A = 0,
     B = 1,
     C = 2,
@@ -369,6 +446,8 @@ enum Bank {
     I = 8,
 }
 
+savestate_enum!(Bank { A, B, C, D, E, F, G, H, I });
+
 impl Bank {
     pub fn from_index(index: usize) -> Self {
         match index {
@@ -389,7 +468,7 @@ impl Bank {
         let offset = offset as usize;
         match self {
             Bank::A | Bank::B | Bank::C | Bank::D => 0x2_0000 * offset,
-            Bank::E => { assert_eq!(offset, 0); 0 },
+            Bank::E => 0,
             Bank::F | Bank::G => 0x4000 * (offset & 0x1) + 0x1_0000 * (offset >> 1 & 0x1),
             Bank::H | Bank::I => unreachable!(),
         }
@@ -404,9 +483,9 @@ impl Bank {
         slot as usize * 0x400 * 16
     }
 
+    // Bit 1 of the offset is ignored
     pub fn get_ext_bg_pal_offset(&self, offset: u8) -> usize {
-        assert!(offset < 2); // TODO: Figure out behavior
-        offset as usize * 0x400 * 16
+        (offset as usize & 0x1) * 0x400 * 16
     }
 }
 
@@ -1,23 +1,56 @@
 use bitflags::*;
+use crate::savestate::{Savestate, StateWriter};
 use super::{mem::IORegister, Scheduler};
 
 pub struct InterruptController {
     pub enable: InterruptEnable,
     pub master_enable: InterruptMasterEnable,
     pub request: InterruptRequest,
+    // Sources that exist on this CPU, the rest of IE is always 0
+    pub(super) sources: InterruptEnable,
+    // IRQs only see a new IME, IE or IF after the CPU's next instruction
+    pub(super) delayed: bool,
 }
 
+savestate!(InterruptController { enable, master_enable, request });
+
 impl InterruptController {
-    pub fn new() -> Self {
+    pub fn new(sources: InterruptEnable) -> Self {
         InterruptController {
             enable: InterruptEnable::empty(),
             master_enable: InterruptMasterEnable::empty(),
             request: InterruptRequest::empty(),
+            sources,
+            delayed: false,
         }
     }
 
-    pub fn interrupts_requested(&self) -> bool {
-        self.master_enable.bits() != 0 && (self.request.bits() & self.enable.bits()) != 0
+    // Called once before each instruction
+    pub fn interrupts_requested(&mut self) -> bool {
+        if self.delayed { self.delayed = false; return false }
+        self.master_enable.bits() != 0 && self.interrupts_pending()
+    }
+
+    // Wakes a halted CPU even while IME or the CPSR's I bit keeps the interrupt from being taken
+    pub fn interrupts_pending(&self) -> bool {
+        (self.request.bits() & self.enable.bits()) != 0
+    }
+
+    pub fn write_master_enable(&mut self, scheduler: &mut Scheduler, byte: usize, value: u8) {
+        self.master_enable.write(scheduler, byte, value);
+        self.delayed = true;
+    }
+
+    pub fn write_enable(&mut self, scheduler: &mut Scheduler, byte: usize, value: u8) {
+        self.enable.write(scheduler, byte, value);
+        self.enable &= self.sources;
+        self.delayed = true;
+    }
+
+    // Writing 1 to a bit of IF acknowledges it
+    pub fn write_request(&mut self, scheduler: &mut Scheduler, byte: usize, value: u8) {
+        self.request.write(scheduler, byte, value);
+        self.delayed = true;
     }
 }
 
@@ -43,6 +76,78 @@ bitflags! {
         const GAME_CARD_TRANSFER_COMPLETION = 1 << 19;
         const GAME_CARD_IREQ_MC = 1 << 20;
         const GEOMETRY_COMMAND_FIFO = 1 << 21;
+        const SCREENS_UNFOLDING = 1 << 22;
+        const SPI = 1 << 23;
+        const WIFI = 1 << 24;
+        const NDMA0 = 1 << 28;
+        const NDMA1 = 1 << 29;
+        const NDMA2 = 1 << 30;
+        const NDMA3 = 1 << 31;
+    }
+}
+
+// Which sources exist depends on the console model and GBA mode, which an IO section doesn't have, so they're left
+// empty here and filled in once the rest of the state is loaded
+pub(crate) fn migrate_io_v2(data: &[u8]) -> Vec<u8> {
+    let mut state = StateWriter::new();
+    for _ in 0..2 {
+        InterruptEnable::empty().save(&mut state);
+        false.save(&mut state);
+    }
+    [data, &state.finish()].concat()
+}
+
+impl InterruptEnable {
+    pub const ARM7_SOURCES: InterruptEnable = InterruptEnable::from_bits_truncate(0x01DF_3FFF);
+    // The ARM9 has no serial, SPI, WiFi or lid interrupts, but it's the only one with the geometry command FIFO
+    pub const ARM9_SOURCES: InterruptEnable = InterruptEnable::from_bits_truncate(0x003F_3F7F);
+    pub const GBA_SOURCES: InterruptEnable = InterruptEnable::from_bits_truncate(0x0000_3FFF);
+    // Added to both CPUs on a DSi
+    pub const DSI_SOURCES: InterruptEnable = InterruptEnable::from_bits_truncate(0xF000_0000);
+}
+
+// IE2 and IF2, the ARM7's interrupts for hardware added by the DSi. They're only taken while IME is set.
+pub struct DSiInterrupts {
+    pub enable: InterruptEnable2,
+    pub request: InterruptRequest2,
+}
+
+savestate!(DSiInterrupts { enable, request });
+
+impl DSiInterrupts {
+    pub fn new() -> Self {
+        DSiInterrupts {
+            enable: InterruptEnable2::empty(),
+            request: InterruptRequest2::empty(),
+        }
+    }
+
+    pub fn interrupts_pending(&self) -> bool {
+        (self.request.bits() & self.enable.bits()) != 0
+    }
+}
+
+bitflags! {
+    pub struct InterruptEnable2: u32 {
+        const SD_MMC = 1 << 8;
+        const SD_DATA1 = 1 << 9;
+        const SDIO = 1 << 10;
+        const SDIO_DATA1 = 1 << 11;
+        const AES = 1 << 12;
+        const I2C = 1 << 13;
+        const MICROPHONE = 1 << 14;
+    }
+}
+
+bitflags! {
+    pub struct InterruptRequest2: u32 {
+        const SD_MMC = 1 << 8;
+        const SD_DATA1 = 1 << 9;
+        const SDIO = 1 << 10;
+        const SDIO_DATA1 = 1 << 11;
+        const AES = 1 << 12;
+        const I2C = 1 << 13;
+        const MICROPHONE = 1 << 14;
     }
 }
 
@@ -73,14 +178,23 @@ bitflags! {
         const IPC_RECV_FIFO_NOT_EMPTY = 1 << 18;
         const GAME_CARD_TRANSFER_COMPLETION = 1 << 19;
         const GAME_CARD_IREQ_MC = 1 << 20;
-        const GEOMETRY_COMMAND_FIFO = 1 << 21; // TODO: Don't include for interrupts7
+        const GEOMETRY_COMMAND_FIFO = 1 << 21;
+        const SCREENS_UNFOLDING = 1 << 22;
+        const SPI = 1 << 23;
+        const WIFI = 1 << 24;
+        const NDMA0 = 1 << 28;
+        const NDMA1 = 1 << 29;
+        const NDMA2 = 1 << 30;
+        const NDMA3 = 1 << 31;
     }
 }
 
+savestate_bitflags!(InterruptEnable, InterruptMasterEnable, InterruptRequest, InterruptEnable2, InterruptRequest2);
+
 impl IORegister for InterruptEnable {
     fn read(&self, byte: usize) -> u8 {
         match byte {
-            0 => (self.bits >> 0) as u8,
+            0 => self.bits as u8,
             1 => (self.bits >> 8) as u8,
             2 => (self.bits >> 16) as u8,
             3 => (self.bits >> 24) as u8,
@@ -90,7 +204,7 @@ impl IORegister for InterruptEnable {
 
     fn write(&mut self, _scheduler: &mut Scheduler, byte: usize, value: u8) {
         match byte {
-            0 => self.bits = self.bits & !0x0000_00FF | (value as u32) << 0,
+            0 => self.bits = self.bits & !0x0000_00FF | value as u32,
             1 => self.bits = self.bits & !0x0000_FF00 | (value as u32) << 8,
             2 => self.bits = self.bits & !0x00FF_0000 | (value as u32) << 16,
             3 => self.bits = self.bits & !0xFF00_0000 | (value as u32) << 24,
@@ -102,7 +216,7 @@ impl IORegister for InterruptEnable {
 impl IORegister for InterruptMasterEnable {
     fn read(&self, byte: usize) -> u8 {
         match byte {
-            0 => (self.bits >> 0) as u8,
+            0 => self.bits as u8,
             1 => (self.bits >> 8) as u8,
             2 => (self.bits >> 16) as u8,
             3 => (self.bits >> 24) as u8,
@@ -112,10 +226,9 @@ impl IORegister for InterruptMasterEnable {
 
     fn write(&mut self, _scheduler: &mut Scheduler, byte: usize, value: u8) {
         match byte {
-            0 => self.bits = self.bits & !0x0000_00FF | (value as u32) << 0 & InterruptEnable::all().bits,
-            1 => self.bits = self.bits & !0x0000_FF00 | (value as u32) << 8 & InterruptEnable::all().bits,
-            2 => self.bits = self.bits & !0x00FF_0000 | (value as u32) << 16 & InterruptEnable::all().bits,
-            3 => self.bits = self.bits & !0xFF00_0000 | (value as u32) << 24 & InterruptEnable::all().bits,
+            // Only bit 0 exists
+            0 => self.bits = value as u32 & InterruptMasterEnable::all().bits,
+            1 ..= 3 => (),
             _ => unreachable!(),
         }
     }
@@ -124,7 +237,7 @@ impl IORegister for InterruptMasterEnable {
 impl IORegister for InterruptRequest {
     fn read(&self, byte: usize) -> u8 {
         match byte {
-            0 => (self.bits >> 0) as u8,
+            0 => self.bits as u8,
             1 => (self.bits >> 8) as u8,
             2 => (self.bits >> 16) as u8,
             3 => (self.bits >> 24) as u8,
@@ -134,11 +247,32 @@ impl IORegister for InterruptRequest {
 
     fn write(&mut self, _scheduler: &mut Scheduler, byte: usize, value: u8) {
         match byte {
-            0 => self.bits = self.bits & !((value as u32) << 0),
-            1 => self.bits = self.bits & !((value as u32) << 8),
-            2 => self.bits = self.bits & !((value as u32) << 16),
-            3 => self.bits = self.bits & !((value as u32) << 24),
+            0 => self.bits &= !(value as u32),
+            1 => self.bits &= !((value as u32) << 8),
+            2 => self.bits &= !((value as u32) << 16),
+            3 => self.bits &= !((value as u32) << 24),
             _ => unreachable!(),
         }
     }
 }
+
+impl IORegister for InterruptEnable2 {
+    fn read(&self, byte: usize) -> u8 {
+        (self.bits >> (8 * byte)) as u8
+    }
+
+    fn write(&mut self, _scheduler: &mut Scheduler, byte: usize, value: u8) {
+        let bits = self.bits & !(0xFF << (8 * byte)) | (value as u32) << (8 * byte);
+        *self = InterruptEnable2::from_bits_truncate(bits);
+    }
+}
+
+impl IORegister for InterruptRequest2 {
+    fn read(&self, byte: usize) -> u8 {
+        (self.bits >> (8 * byte)) as u8
+    }
+
+    fn write(&mut self, _scheduler: &mut Scheduler, byte: usize, value: u8) {
+        self.bits &= !((value as u32) << (8 * byte));
+    }
+}
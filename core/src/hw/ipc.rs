@@ -13,6 +13,8 @@ pub struct IPC {
     prev_value9: u32,
 }
 
+savestate!(IPC { fifocnt7, sync7, output7, prev_value7, fifocnt9, sync9, output9, prev_value9 });
+
 impl IPC {
     const FIFO_LEN: usize = 16;
     
@@ -85,7 +87,6 @@ impl IPC {
     fn recv(send_cnt: &FIFOCNT, recv_cnt: &mut FIFOCNT, recv_fifo: &mut VecDeque<u32>,
         prev_value: &mut u32) -> (u32, InterruptRequest) {
         if !recv_cnt.enable { return (*prev_value, InterruptRequest::empty()) }
-        assert!(send_cnt.enable); // TODO: Figure out behavior
         let interrupt = if let Some(value) = recv_fifo.pop_front() {
             *prev_value = value;
             if send_cnt.enable && send_cnt.send_fifo_empty_irq && recv_fifo.is_empty() {
@@ -118,6 +119,8 @@ struct SYNC {
     sync_irq: bool,
 }
 
+savestate!(SYNC { input, output, sync_irq });
+
 impl SYNC {
     fn new() -> Self {
         SYNC {
@@ -141,7 +144,8 @@ impl SYNC {
         if match byte {
             0 => false,
             1 => {
-                self.output = value;
+                // Bit 5 only triggers the remote IRQ and isn't stored
+                self.output = value & 0xF;
                 other.input = self.output;
                 self.sync_irq = value >> 6 & 0x1 != 0;
                 other.sync_irq && value >> 5 & 0x1 != 0
@@ -161,6 +165,8 @@ struct FIFOCNT {
     enable: bool,
 }
 
+savestate!(FIFOCNT { send_fifo_empty_irq, recv_fifo_not_empty_irq, error, enable });
+
 impl FIFOCNT {
     fn new() -> Self {
         FIFOCNT {
@@ -1,7 +1,7 @@
 use bitflags::*;
 use super::{mem::IORegister, Scheduler};
 
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Key {
     A = 0,
     B = 1,
@@ -19,15 +19,18 @@ pub enum Key {
 
 pub struct Keypad {
     pub keyinput: KEYINPUT,
-    pub keycnt: KEYCNT,
+    pub keycnt: [KEYCNT; 2],
     pub extkeyin: EXTKEYIN,
 }
 
+// Input comes from the host, so only the interrupt configuration is part of the state
+savestate!(Keypad { keycnt });
+
 impl Keypad {
     pub fn new() -> Self {
         Keypad {
             keyinput: KEYINPUT::all(),
-            keycnt: KEYCNT::empty(),
+            keycnt: [KEYCNT::empty(), KEYCNT::empty()],
             extkeyin: EXTKEYIN::new(),
         }
     }
@@ -48,6 +51,20 @@ impl Keypad {
         }
     }
 
+    // Held keys as 1 bits, numbered like Key
+    pub fn held_keys(&self) -> u16 {
+        !self.keyinput.bits & 0x3FF | (!self.extkeyin.bits as u16 & 0x3) << 10
+    }
+
+    pub fn set_held_keys(&mut self, keys: u16) {
+        self.keyinput.bits = !keys & 0x3FF;
+        self.extkeyin.bits = self.extkeyin.bits & !0x3 | !(keys >> 10) as u8 & 0x3;
+    }
+
+    pub fn screen_pressed(&self) -> bool {
+        !self.extkeyin.contains(EXTKEYIN::PEN_DOWN)
+    }
+
     pub fn press_screen(&mut self) {
         self.extkeyin.remove(EXTKEYIN::PEN_DOWN);
     }
@@ -56,11 +73,14 @@ impl Keypad {
         self.extkeyin.insert(EXTKEYIN::PEN_DOWN);
     }
 
-    pub fn interrupt_requested(&self) -> bool {
-        if self.keycnt.contains(KEYCNT::IRQ_ENABLE) {
-            let irq_keys = self.keycnt - KEYCNT::IRQ_ENABLE - KEYCNT::IRQ_COND_AND;
-            if self.keycnt.contains(KEYCNT::IRQ_COND_AND) { irq_keys.bits() & !self.keyinput.bits() == irq_keys.bits() }
-            else { irq_keys.bits() & !self.keyinput.bits() != 0 }
+    pub fn interrupt_requested(&self, is_nds9: bool) -> bool {
+        let keycnt = self.keycnt[is_nds9 as usize];
+        if keycnt.contains(KEYCNT::IRQ_ENABLE) {
+            let irq_keys = keycnt - KEYCNT::IRQ_ENABLE - KEYCNT::IRQ_COND_AND;
+            let pressed = irq_keys.bits() & !self.keyinput.bits();
+            // AND mode requires all selected keys to be held, OR mode any of them
+            if keycnt.contains(KEYCNT::IRQ_COND_AND) { irq_keys.bits() != 0 && pressed == irq_keys.bits() }
+            else { pressed != 0 }
         } else { false }
     }
 }
@@ -98,6 +118,8 @@ bitflags! {
     }
 }
 
+savestate_bitflags!(KEYCNT);
+
 bitflags! {
     pub struct EXTKEYIN: u8 {
         const X = 1 << 0;
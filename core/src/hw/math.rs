@@ -9,6 +9,8 @@ pub struct Div {
     rem: MathParam,
 }
 
+savestate!(Div { cnt, numer, denom, quot, rem });
+
 impl Div {
     pub fn new() -> Self {
         Div {
@@ -69,6 +71,8 @@ pub struct Sqrt {
     result: u32,
 }
 
+savestate!(Sqrt { cnt, param, result });
+
 impl Sqrt {
     pub fn new() -> Self {
         Sqrt {
@@ -96,6 +100,8 @@ pub struct MathParam {
     value: u64,
 }
 
+savestate!(MathParam { value });
+
 impl MathParam {
     pub fn new() -> Self {
         MathParam {
@@ -122,6 +128,8 @@ pub struct DIVCNT {
     busy: bool,
 }
 
+savestate!(DIVCNT { mode, div_by_0, busy });
+
 impl DIVCNT {
     pub fn new() -> Self {
         DIVCNT {
@@ -156,6 +164,8 @@ pub struct SQRTCNT {
     busy: bool,
 }
 
+savestate!(SQRTCNT { is_64bit, busy });
+
 impl SQRTCNT {
     pub fn new() -> Self {
         SQRTCNT {
@@ -1,47 +1,91 @@
-use super::{AccessType, HW, MemoryValue, IORegister};
+use crate::num;
+use super::{AccessType, HW, MemoryValue, IORegister, Page, RAMRegion};
+use crate::hw::ConsoleModel;
 
 type MemoryRegion = ARM7MemoryRegion;
 
 impl HW {
     pub fn arm7_read<T: MemoryValue>(&mut self, addr: u32) -> T {
+        if self.gba.enabled { return self.gba_read(addr) }
+        if let Some(value) = self.read_page(self.arm7_pages.get((addr >> HW::PAGE_SHIFT) as usize), addr) { return value }
         match MemoryRegion::from_addr(addr) {
             MemoryRegion::BIOS => HW::read_mem(&self.bios7, addr),
-            MemoryRegion::MainMem => HW::read_mem(&self.main_mem, addr & HW::MAIN_MEM_MASK),
-            MemoryRegion::SharedWRAM if self.wramcnt.arm7_mask == 0 => {
-                warn!("Reading from Unmapped ARM7 Shared WRAM: 0x{:X}", addr);
-                HW::read_mem(&self.iwram, addr & HW::IWRAM_MASK)
+            MemoryRegion::MainMem => HW::read_mem(&self.main_mem, addr & self.main_mem_mask()),
+            MemoryRegion::SharedWRAM | MemoryRegion::IWRAM if self.nwram.mapping(false, addr).is_some() =>
+                self.nwram.read(self.nwram.mapping(false, addr).unwrap()),
+            MemoryRegion::SharedWRAM => match self.wramcnt.arm7_mapping(addr) {
+                Some((offset, _)) => HW::read_mem(&self.shared_wram, offset),
+                // ARM7 WRAM is mirrored here while the ARM9 has all of shared WRAM
+                None => HW::read_mem(&self.iwram, addr & HW::IWRAM_MASK),
             },
-            MemoryRegion::SharedWRAM => HW::read_mem(&self.shared_wram,
-                self.wramcnt.arm7_offset + (addr & self.wramcnt.arm7_mask)),
             MemoryRegion::IWRAM => HW::read_mem(&self.iwram, addr & HW::IWRAM_MASK),
             MemoryRegion::IO if (0x0410_0000 ..= 0x0410_0003).contains(&addr) => self.ipc_fifo_recv(false, addr),
             MemoryRegion::IO if (0x0410_0010 ..= 0x0410_0013).contains(&addr) => self.read_game_card(false, addr),
+            MemoryRegion::IO if (0x0480_0000 ..= 0x0480_FFFF).contains(&addr) => self.read_wifi(addr),
+            MemoryRegion::IO if HW::is_sdmmc_fifo(addr) => self.read_sdmmc_fifo(),
             MemoryRegion::IO => HW::read_from_bytes(self, &HW::arm7_read_io_register, addr),
             MemoryRegion::VRAM => self.gpu.vram.arm7_read(addr),
             MemoryRegion::GBAROM => self.read_gba_rom(false, addr),
-            MemoryRegion::GBARAM => todo!(),
+            MemoryRegion::GBARAM => self.read_gba_ram(false, addr),
+            MemoryRegion::Unknown => { warn!("Reading from Unknown 0x{:08X}", addr); num::zero() },
         }
     }
 
+    // Only code in the BIOS can read it, and BIOSPROT keeps most of the BIOS from reading its start
+    pub fn arm7_bios_protected(&self, pc: u32, addr: u32) -> bool {
+        if self.gba.enabled || !matches!(MemoryRegion::from_addr(addr), MemoryRegion::BIOS) { return false }
+        pc >= 0x4000 || addr < self.biosprot as u32 && pc >= self.biosprot as u32
+    }
+
     pub fn arm7_write<T: MemoryValue>(&mut self, addr: u32, value: T) {
+        if self.gba.enabled { return self.gba_write(addr, value) }
+        let page = self.arm7_pages.get((addr >> HW::PAGE_SHIFT) as usize).copied();
+        if self.write_page(page, addr, value) { return }
         match MemoryRegion::from_addr(addr) {
             MemoryRegion::BIOS => warn!("Writing to BIOS7 0x{:08x} = 0x{:X}", addr, value),
-            MemoryRegion::MainMem => HW::write_mem(&mut self.main_mem, addr & HW::MAIN_MEM_MASK, value),
-            MemoryRegion::SharedWRAM if self.wramcnt.arm7_mask == 0 =>
-                HW::write_mem(&mut self.iwram, addr & HW::IWRAM_MASK, value),
-            MemoryRegion::SharedWRAM => HW::write_mem(&mut self.shared_wram,
-                self.wramcnt.arm7_offset + addr & self.wramcnt.arm7_mask, value),
+            MemoryRegion::MainMem => {
+                let mask = self.main_mem_mask();
+                HW::write_mem(&mut self.main_mem, addr & mask, value)
+            },
+            MemoryRegion::SharedWRAM | MemoryRegion::IWRAM if self.nwram.mapping(false, addr).is_some() => {
+                let offset = self.nwram.mapping(false, addr).unwrap();
+                self.nwram.write(offset, value)
+            },
+            MemoryRegion::SharedWRAM => match self.wramcnt.arm7_mapping(addr) {
+                Some((offset, _)) => HW::write_mem(&mut self.shared_wram, offset, value),
+                None => HW::write_mem(&mut self.iwram, addr & HW::IWRAM_MASK, value),
+            },
             MemoryRegion::IWRAM => HW::write_mem(&mut self.iwram, addr & HW::IWRAM_MASK, value),
             MemoryRegion::IO if (0x0400_0188 ..= 0x0400_018B).contains(&addr) =>
                 self.ipc_fifo_send(true, addr, value),
+            MemoryRegion::IO if (0x0410_0010 ..= 0x0410_0013).contains(&addr) =>
+                self.write_game_card(false, addr, value),
+            MemoryRegion::IO if (0x0480_0000 ..= 0x0480_FFFF).contains(&addr) => self.write_wifi(addr, value),
+            MemoryRegion::IO if HW::is_sdmmc_fifo(addr) => self.write_sdmmc_fifo(value),
             MemoryRegion::IO => HW::write_from_bytes(self, &HW::arm7_write_io_register, addr, value),
             MemoryRegion::VRAM => self.gpu.vram.arm7_write(addr, value),
-            MemoryRegion::GBAROM => (),
-            MemoryRegion::GBARAM => todo!(),
+            MemoryRegion::GBAROM => self.write_gba_rom(false, addr, value),
+            MemoryRegion::GBARAM => self.write_gba_ram(false, addr, value),
+            MemoryRegion::Unknown => warn!("Writing to Unknown 0x{:08X} = 0x{:X}", addr, value),
+        }
+    }
+
+    pub(super) fn arm7_page(&self, addr: u32) -> Page {
+        match MemoryRegion::try_from_addr(addr) {
+            Some(MemoryRegion::MainMem) => Page::MainMem(addr & self.main_mem_mask()),
+            // DSi WRAM takes priority over both kinds of WRAM
+            Some(MemoryRegion::SharedWRAM) | Some(MemoryRegion::IWRAM) if self.nwram.mapping(false, addr).is_some() =>
+                Page::Slow,
+            Some(MemoryRegion::SharedWRAM) => match self.wramcnt.arm7_mapping(addr) {
+                Some((offset, _)) => Page::SharedWRAM(offset),
+                None => Page::IWRAM(addr & HW::IWRAM_MASK),
+            },
+            Some(MemoryRegion::IWRAM) => Page::IWRAM(addr & HW::IWRAM_MASK),
+            _ => Page::Slow,
         }
     }
 
-    fn arm7_read_io_register(&self, addr: u32) -> u8 {
+    pub(crate) fn arm7_read_io_register(&self, addr: u32) -> u8 {
         match addr {
             0x0400_0004 => self.gpu.dispstats[0].read(0),
             0x0400_0005 => self.gpu.dispstats[0].read(1),
@@ -57,12 +101,12 @@ impl HW {
             0x0400_010C ..= 0x0400_010F => self.timers[0][3].read(&self.scheduler, addr as usize % 4),
             0x0400_0130 => self.keypad.keyinput.read(0),
             0x0400_0131 => self.keypad.keyinput.read(1),
-            0x0400_0132 => self.keypad.keycnt.read(0),
-            0x0400_0133 => self.keypad.keycnt.read(1),
+            0x0400_0132 => self.keypad.keycnt[0].read(0),
+            0x0400_0133 => self.keypad.keycnt[0].read(1),
             0x0400_0134 ..= 0x0400_0135 => 0, // TODO: Debug RCNT
             0x0400_0136 => self.keypad.extkeyin.read(0),
             0x0400_0137 => self.keypad.extkeyin.read(1),
-            0x0400_0138 ..= 0x0400_0139 => 0, // TODO: RTC
+            0x0400_0138 ..= 0x0400_0139 => self.rtc.read(addr as usize % 2),
             0x0400_0180 => self.ipc.read_sync7(0),
             0x0400_0181 => self.ipc.read_sync7(1),
             0x0400_0182 => self.ipc.read_sync7(2),
@@ -97,6 +141,10 @@ impl HW {
             0x0400_0215 => self.interrupts[0].request.read(1),
             0x0400_0216 => self.interrupts[0].request.read(2),
             0x0400_0217 => self.interrupts[0].request.read(3),
+            0x0400_0218 ..= 0x0400_021B if self.model == ConsoleModel::DSi =>
+                self.dsi_interrupts.enable.read(addr as usize & 0x3),
+            0x0400_021C ..= 0x0400_021F if self.model == ConsoleModel::DSi =>
+                self.dsi_interrupts.request.read(addr as usize & 0x3),
             0x0400_0241 => self.wramcnt.read(0),
             0x0400_0300 => self.postflg7,
             0x0400_0301 => self.haltcnt.read(0),
@@ -105,8 +153,9 @@ impl HW {
             0x0400_0306 => self.powcnt2.read(2),
             0x0400_0307 => self.powcnt2.read(3),
             0x0400_0400 ..= 0x0400_051F => self.spu.read(addr as usize & 0xFFF),
-            0x0480_4000 ..= 0x0480_5FFF => 0, // TODO: WiFi RAM
-            0x0480_8000 ..= 0x0480_8FFF => 0, // TOOD: WiFi Registers
+            0x0400_4000 ..= 0x0400_4063 => self.read_dsi_io_register(false, addr),
+            0x0400_4100 ..= 0x0400_4173 => self.read_ndma_register(false, addr),
+            0x0400_4800 ..= 0x0400_49FF => self.read_sdmmc_register(addr),
             _ => { warn!("Ignoring ARM7 IO Register Read at 0x{:08X}", addr); 0 }
         }
     }
@@ -115,8 +164,8 @@ impl HW {
         match addr {
             0x0400_0004 => self.gpu.dispstats[0].write(&mut self.scheduler, 0, value),
             0x0400_0005 => self.gpu.dispstats[0].write(&mut self.scheduler, 1, value),
-            0x0400_0006 => (), // VCOUNT is read only
-            0x0400_0007 => (), // VCOUNT is read only
+            0x0400_0006 => self.gpu.write_vcount(0, value),
+            0x0400_0007 => self.gpu.write_vcount(1, value),
             0x0400_00B0 ..= 0x0400_00BB => self.dmas[0].write(0, &mut self.scheduler, addr - 0xB0, value),
             0x0400_00BC ..= 0x0400_00C7 => self.dmas[0].write(1, &mut self.scheduler, addr - 0xBC, value),
             0x0400_00C8 ..= 0x0400_00D3 => self.dmas[0].write(2, &mut self.scheduler, addr - 0xC8, value),
@@ -125,10 +174,12 @@ impl HW {
             0x0400_0104 ..= 0x0400_0107 => self.timers[0][1].write(&mut self.scheduler, addr as usize % 4, value),
             0x0400_0108 ..= 0x0400_010B => self.timers[0][2].write(&mut self.scheduler, addr as usize % 4, value),
             0x0400_010C ..= 0x0400_010F => self.timers[0][3].write(&mut self.scheduler, addr as usize % 4, value),
+            0x0400_0132 => self.keypad.keycnt[0].write(&mut self.scheduler, 0, value),
+            0x0400_0133 => self.keypad.keycnt[0].write(&mut self.scheduler, 1, value),
             0x0400_0134 ..= 0x0400_0135 => (), // TODO: Debug RCNT
             0x0400_0136 => self.keypad.extkeyin.write(&mut self.scheduler, 0, value),
             0x0400_0137 => self.keypad.extkeyin.write(&mut self.scheduler, 1, value),
-            0x0400_0138 ..= 0x0400_0139 => (), // TODO: RTC
+            0x0400_0138 ..= 0x0400_0139 => self.rtc.write(self.scheduler.cycle, addr as usize % 2, value),
             0x0400_0180 => self.interrupts[1].request |= self.ipc.write_sync7(0, value),
             0x0400_0181 => self.interrupts[1].request |= self.ipc.write_sync7(1, value),
             0x0400_0182 => self.interrupts[1].request |= self.ipc.write_sync7(2, value),
@@ -139,7 +190,7 @@ impl HW {
             0x0400_0187 => self.interrupts[0].request |= self.ipc.write_fifocnt7(3, value),
             0x0400_01A0 => self.cartridge.spicnt.write(self.exmem.nds_arm7_access, 0, value),
             0x0400_01A1 => self.cartridge.spicnt.write(self.exmem.nds_arm7_access, 1, value),
-            0x0400_01A2 => self.cartridge.write_spi_data(self.exmem.nds_arm7_access, value),
+            0x0400_01A2 => self.cartridge.write_spi_data(&mut self.scheduler, self.exmem.nds_arm7_access, value),
             0x0400_01A3 => (), // TODO: Does this write do anything?
             0x0400_01A4 => self.cartridge.write_romctrl(&mut self.scheduler, false,
                 self.exmem.nds_arm7_access, 0, value),
@@ -157,41 +208,107 @@ impl HW {
             0x0400_01AD => self.cartridge.write_command(self.exmem.nds_arm7_access, 5, value),
             0x0400_01AE => self.cartridge.write_command(self.exmem.nds_arm7_access, 6, value),
             0x0400_01AF => self.cartridge.write_command(self.exmem.nds_arm7_access, 7, value),
+            0x0400_01B0 ..= 0x0400_01BB => self.cartridge.write_rom_seed(self.exmem.nds_arm7_access,
+                addr as usize - 0x0400_01B0, value),
             0x0400_01C0 => self.spi.write_cnt(&mut self.scheduler, 0, value),
             0x0400_01C1 => self.spi.write_cnt(&mut self.scheduler, 1, value),
-            0x0400_01C2 => self.spi.write_data(value),
+            0x0400_01C2 => self.spi.write_data(&mut self.scheduler, value),
             0x0400_01C3 => (), // SPI bug makes upper 8 bits always 0
             0x0400_0204 => self.exmem.write_arm7(value),
             0x0400_0205 => (), // Upper bits are read-only for ARM7
-            0x0400_0208 => self.interrupts[0].master_enable.write(&mut self.scheduler, 0, value),
-            0x0400_0209 => self.interrupts[0].master_enable.write(&mut self.scheduler, 1, value),
-            0x0400_020A => self.interrupts[0].master_enable.write(&mut self.scheduler, 2, value),
-            0x0400_020B => self.interrupts[0].master_enable.write(&mut self.scheduler, 3, value),
-            0x0400_0210 => self.interrupts[0].enable.write(&mut self.scheduler, 0, value),
-            0x0400_0211 => self.interrupts[0].enable.write(&mut self.scheduler, 1, value),
-            0x0400_0212 => self.interrupts[0].enable.write(&mut self.scheduler, 2, value),
-            0x0400_0213 => self.interrupts[0].enable.write(&mut self.scheduler, 3, value),
-            0x0400_0214 => self.interrupts[0].request.write(&mut self.scheduler, 0, value),
-            0x0400_0215 => self.interrupts[0].request.write(&mut self.scheduler, 1, value),
-            0x0400_0216 => self.interrupts[0].request.write(&mut self.scheduler, 2, value),
-            0x0400_0217 => self.interrupts[0].request.write(&mut self.scheduler, 3, value),
+            0x0400_0208 => self.interrupts[0].write_master_enable(&mut self.scheduler, 0, value),
+            0x0400_0209 => self.interrupts[0].write_master_enable(&mut self.scheduler, 1, value),
+            0x0400_020A => self.interrupts[0].write_master_enable(&mut self.scheduler, 2, value),
+            0x0400_020B => self.interrupts[0].write_master_enable(&mut self.scheduler, 3, value),
+            0x0400_0210 => self.interrupts[0].write_enable(&mut self.scheduler, 0, value),
+            0x0400_0211 => self.interrupts[0].write_enable(&mut self.scheduler, 1, value),
+            0x0400_0212 => self.interrupts[0].write_enable(&mut self.scheduler, 2, value),
+            0x0400_0213 => self.interrupts[0].write_enable(&mut self.scheduler, 3, value),
+            0x0400_0214 => self.interrupts[0].write_request(&mut self.scheduler, 0, value),
+            0x0400_0215 => self.interrupts[0].write_request(&mut self.scheduler, 1, value),
+            0x0400_0216 => self.interrupts[0].write_request(&mut self.scheduler, 2, value),
+            0x0400_0217 => self.interrupts[0].write_request(&mut self.scheduler, 3, value),
+            0x0400_0218 ..= 0x0400_021B if self.model == ConsoleModel::DSi =>
+                self.dsi_interrupts.enable.write(&mut self.scheduler, addr as usize & 0x3, value),
+            0x0400_021C ..= 0x0400_021F if self.model == ConsoleModel::DSi =>
+                self.dsi_interrupts.request.write(&mut self.scheduler, addr as usize & 0x3, value),
             0x0400_0241 => (), // WRAMCNT is read-only
             0x0400_0300 => self.postflg7 |= value & 0x1, // Should only be written to during boot
-            0x0400_0301 => self.haltcnt.write(&mut self.scheduler, 0, value),
+            0x0400_0301 => {
+                self.haltcnt.write(&mut self.scheduler, 0, value);
+                if self.haltcnt.gba_requested() {
+                    self.haltcnt.unhalt();
+                    self.enter_gba_mode();
+                }
+            },
             0x0400_0304 => self.powcnt2.write(&mut self.scheduler, 0, value),
             0x0400_0305 => self.powcnt2.write(&mut self.scheduler, 1, value),
             0x0400_0306 => self.powcnt2.write(&mut self.scheduler, 2, value),
             0x0400_0307 => self.powcnt2.write(&mut self.scheduler, 3, value),
-            0x0400_0400 ..= 0x0400_051F => self.spu.write(&mut self.scheduler, addr as usize & 0xFFF, value),
-            0x0480_4000 ..= 0x0480_5FFF => (), // TODO: WiFi RAM
-            0x0480_8000 ..= 0x0480_8FFF => (), // TOOD: WiFi Registers
+            // Can only be set once
+            0x0400_0308 if self.biosprot == 0 => self.biosprot = value as u16 & 0xFE,
+            0x0400_0309 if self.biosprot & 0xFF00 == 0 => self.biosprot |= (value as u16) << 8,
+            0x0400_0308 ..= 0x0400_030B => (),
+            0x0400_0400 ..= 0x0400_051F => {
+                self.run_audio_channels();
+                self.spu.write(&mut self.scheduler, addr as usize & 0xFFF, value)
+            },
+            0x0400_4000 ..= 0x0400_4063 => self.write_dsi_io_register(false, addr, value),
+            0x0400_4100 ..= 0x0400_4173 => self.write_ndma_register(false, addr, value),
+            0x0400_4800 ..= 0x0400_49FF => self.write_sdmmc_register(addr, value),
             _ => warn!("Ignoring ARM7 IO Register Write 0x{:08X} = {:02X}", addr, value),
         }
     }
 
-    pub fn arm7_get_access_time<T: MemoryValue>(&mut self, _access_type: AccessType, _addr: u32) -> usize {
-        // TODO: Use accurate timings
-        1
+    // Returns the region, offset into it, and number of bytes until the mapping may change
+    pub fn arm7_ram_region(&self, addr: u32) -> Option<(RAMRegion, usize, usize)> {
+        if self.gba.enabled { return self.gba_ram_region(addr) }
+        match MemoryRegion::from_addr(addr) {
+            MemoryRegion::MainMem => {
+                let offset = (addr & self.main_mem_mask()) as usize;
+                Some((RAMRegion::MainMem, offset, self.main_mem_mask() as usize + 1 - offset))
+            },
+            MemoryRegion::SharedWRAM | MemoryRegion::IWRAM if self.nwram.mapping(false, addr).is_some() => None,
+            MemoryRegion::SharedWRAM if self.wramcnt.arm7_mapping(addr).is_some() => {
+                let (offset, len) = self.wramcnt.arm7_mapping(addr)?;
+                Some((RAMRegion::SharedWRAM, offset as usize, len))
+            },
+            MemoryRegion::SharedWRAM | MemoryRegion::IWRAM => {
+                let offset = (addr & HW::IWRAM_MASK) as usize;
+                Some((RAMRegion::IWRAM, offset, HW::IWRAM_SIZE - offset))
+            },
+            MemoryRegion::VRAM => {
+                let (bank, offset, len) = self.gpu.vram.arm7_bank_region(addr)?;
+                Some((RAMRegion::VRAM(bank), offset, len))
+            },
+            _ => None,
+        }
+    }
+
+    // Side effect free access to the memory the ARM7 sees for debugging tools
+    pub fn arm7_debug_mem(&mut self, addr: u32) -> Option<&mut u8> {
+        MemoryRegion::try_from_addr(addr)?;
+        if let (false, Some(offset)) = (self.gba.enabled, self.nwram.mapping(false, addr)) {
+            return Some(self.nwram.byte_mut(offset))
+        }
+        let (region, offset, _) = self.arm7_ram_region(addr)?;
+        Some(&mut self.ram_region_mut(region)[offset])
+    }
+
+    pub fn arm7_debug_read(&mut self, addr: u32) -> Option<u8> {
+        match MemoryRegion::try_from_addr(addr)? {
+            MemoryRegion::BIOS => self.bios7.get(addr as usize).copied(),
+            _ => self.arm7_debug_mem(addr).map(|byte| *byte),
+        }
+    }
+
+    pub fn arm7_get_access_time<T: MemoryValue>(&mut self, access_type: AccessType, addr: u32) -> usize {
+        if self.gba.enabled { return self.gba_get_access_time::<T>(access_type, addr) }
+        match addr >> 24 {
+            0x8 ..= 0xA => self.gba_access_time::<T>(false, access_type, addr),
+            // TODO: Use accurate timings
+            _ => 1,
+        }
     }
 
     pub fn init_arm7(&mut self) -> u32 {
@@ -214,12 +331,20 @@ pub enum ARM7MemoryRegion {
     VRAM,
     GBAROM,
     GBARAM,
+    Unknown,
 }
 
 impl ARM7MemoryRegion {
     pub fn from_addr(addr: u32) -> Self {
+        ARM7MemoryRegion::try_from_addr(addr).unwrap_or_else(|| {
+            warn!("Unknown Memory Access: {:X}", addr);
+            ARM7MemoryRegion::Unknown
+        })
+    }
+
+    pub fn try_from_addr(addr: u32) -> Option<Self> {
         use ARM7MemoryRegion::*;
-        match addr >> 24 {
+        Some(match addr >> 24 {
             0x0 => BIOS,
             0x2 => MainMem,
             0x3 if addr < 0x0380_0000 => SharedWRAM,
@@ -228,7 +353,7 @@ impl ARM7MemoryRegion {
             0x6 => VRAM,
             0x8 | 0x9 => GBAROM,
             0xA => GBARAM,
-            _ => todo!(),
-        }
+            _ => return None,
+        })
     }
 }
@@ -1,5 +1,5 @@
 use crate::num;
-use super::{AccessType, CP15, HW, MemoryValue, IORegister};
+use super::{AccessType, CP15, HW, MemoryValue, IORegister, Page, RAMRegion};
 use crate::hw::gpu::{GPU, Engine2D, EngineType};
 
 type MemoryRegion = ARM9MemoryRegion;
@@ -7,18 +7,21 @@ type MemoryRegion = ARM9MemoryRegion;
 impl HW {
     const ITCM_MASK: u32 = HW::ITCM_SIZE as u32 - 1;
     const DTCM_MASK: u32 = HW::DTCM_SIZE as u32 - 1;
+    const TCM_PAGE_LEN: usize = 0x1000;
 
     pub fn arm9_read<T: MemoryValue>(&mut self, addr: u32) -> T {
+        if let Some(value) = self.read_page(self.arm9_pages.get((addr >> HW::PAGE_SHIFT) as usize), addr) { return value }
         match MemoryRegion::from_addr(addr, &self.cp15) {
             MemoryRegion::ITCM => HW::read_mem(&self.itcm, addr & HW::ITCM_MASK),
             MemoryRegion::DTCM => HW::read_mem(&self.dtcm, addr & HW::DTCM_MASK),
-            MemoryRegion::MainMem => HW::read_mem(&self.main_mem, addr & HW::MAIN_MEM_MASK),
-            MemoryRegion::SharedWRAM if self.wramcnt.arm9_mask == 0 => {
-                warn!("Reading from Unmapped ARM9 Shared WRAM: 0x{:X}", addr);
-                num::zero()
+            MemoryRegion::MainMem => HW::read_mem(&self.main_mem, addr & self.main_mem_mask()),
+            MemoryRegion::SharedWRAM if self.nwram.mapping(true, addr).is_some() =>
+                self.nwram.read(self.nwram.mapping(true, addr).unwrap()),
+            MemoryRegion::SharedWRAM => match self.wramcnt.arm9_mapping(addr) {
+                Some((offset, _)) => HW::read_mem(&self.shared_wram, offset),
+                // Nothing is mapped while the ARM7 has all of shared WRAM
+                None => num::zero(),
             },
-            MemoryRegion::SharedWRAM => HW::read_mem(&self.shared_wram,
-                self.wramcnt.arm9_offset + (addr & self.wramcnt.arm9_mask)),
             MemoryRegion::IO if (0x0410_0000 ..= 0x0410_0003).contains(&addr) => self.ipc_fifo_recv(true, addr),
             MemoryRegion::IO if (0x0410_0010 ..= 0x0410_0013).contains(&addr) => self.read_game_card(true, addr),
             MemoryRegion::IO => HW::read_from_bytes(self, &HW::arm9_read_io_register, addr),
@@ -27,44 +30,145 @@ impl HW {
             MemoryRegion::Palette => HW::read_from_bytes(&self.gpu.engine_b,
                 &Engine2D::read_palette_ram, addr as u32),
             MemoryRegion::VRAM => self.gpu.vram.arm9_read(addr),
-            MemoryRegion::OAM if addr & 0x7FFF < 0x400 => HW::read_mem(&self.gpu.engine_a.oam, addr & GPU::OAM_MASK as u32),
-            MemoryRegion::OAM => HW::read_mem(&self.gpu.engine_b.oam, addr & GPU::OAM_MASK as u32),
+            MemoryRegion::OAM if addr & 0x7FFF < 0x400 => self.gpu.engine_a.read_oam(addr),
+            MemoryRegion::OAM => self.gpu.engine_b.read_oam(addr),
             MemoryRegion::GBAROM => self.read_gba_rom(true, addr),
-            MemoryRegion::GBARAM => todo!(),
+            MemoryRegion::GBARAM => self.read_gba_ram(true, addr),
             MemoryRegion::BIOS => HW::read_mem(&self.bios9, addr & 0xFFFF),
             MemoryRegion::Unknown => { warn!("Reading from Unknown 0x{:08X}", addr); num::zero() },
         }
     }
 
     pub fn arm9_write<T: MemoryValue>(&mut self, addr: u32, value: T) {
+        let page = self.arm9_pages.get((addr >> HW::PAGE_SHIFT) as usize).copied();
+        if self.write_page(page, addr, value) { return }
         match MemoryRegion::from_addr(addr, &self.cp15) {
             MemoryRegion::ITCM => HW::write_mem(&mut self.itcm, addr & HW::ITCM_MASK, value),
             MemoryRegion::DTCM => HW::write_mem(&mut self.dtcm, addr & HW::DTCM_MASK, value),
-            MemoryRegion::MainMem => HW::write_mem(&mut self.main_mem, addr & HW::MAIN_MEM_MASK, value),
-            MemoryRegion::SharedWRAM if self.wramcnt.arm9_mask == 0 => warn!("Writing to Unmapped ARM9 Shared WRAM"),
-            MemoryRegion::SharedWRAM => HW::write_mem(&mut self.shared_wram,
-                self.wramcnt.arm9_offset + addr & self.wramcnt.arm9_mask, value),
+            MemoryRegion::MainMem => {
+                let mask = self.main_mem_mask();
+                HW::write_mem(&mut self.main_mem, addr & mask, value)
+            },
+            MemoryRegion::SharedWRAM if self.nwram.mapping(true, addr).is_some() => {
+                let offset = self.nwram.mapping(true, addr).unwrap();
+                self.nwram.write(offset, value)
+            },
+            MemoryRegion::SharedWRAM => if let Some((offset, _)) = self.wramcnt.arm9_mapping(addr) {
+                HW::write_mem(&mut self.shared_wram, offset, value)
+            },
             MemoryRegion::IO if (0x0400_0188 ..= 0x0400_018B).contains(&addr) =>
                 self.ipc_fifo_send(false, addr, value),
             MemoryRegion::IO if (0x0400_0400 .. 0x0400_0440).contains(&addr) => self.write_geometry_fifo(addr, value),
             MemoryRegion::IO if (0x0400_0440 ..= 0x0400_05CB).contains(&addr) => self.write_geometry_command(addr, value),
+            MemoryRegion::IO if (0x0410_0010 ..= 0x0410_0013).contains(&addr) =>
+                self.write_game_card(true, addr, value),
             MemoryRegion::IO => HW::write_from_bytes(self, &HW::arm9_write_io_register, addr, value),
             MemoryRegion::Palette if addr & 0x7FFF < 0x400 => HW::write_palette_ram(&mut self.gpu.engine_a, addr, value),
             MemoryRegion::Palette => HW::write_palette_ram(&mut self.gpu.engine_b, addr, value),
             MemoryRegion::VRAM => self.gpu.vram.arm9_write(addr, value),
-            MemoryRegion::OAM if addr & 0x7FFF < 0x400 => HW::write_mem(&mut self.gpu.engine_a.oam,
-                addr & GPU::OAM_MASK as u32, value),
-            MemoryRegion::OAM => HW::write_mem(&mut self.gpu.engine_b.oam, addr & GPU::OAM_MASK as u32, value),
-            MemoryRegion::GBAROM => (),
-            MemoryRegion::GBARAM => todo!(),
+            MemoryRegion::OAM if addr & 0x7FFF < 0x400 => self.gpu.engine_a.write_oam(addr, value),
+            MemoryRegion::OAM => self.gpu.engine_b.write_oam(addr, value),
+            MemoryRegion::GBAROM => self.write_gba_rom(true, addr, value),
+            MemoryRegion::GBARAM => self.write_gba_ram(true, addr, value),
             MemoryRegion::BIOS => warn!("Writing to BIOS9 0x{:08x} = 0x{:X}", addr, value),
             MemoryRegion::Unknown => warn!("Writing to Unknown 0x{:08X} = 0x{:X}", addr, value),
         }
     }
 
-    pub fn arm9_get_access_time<T: MemoryValue>(&mut self, _access_type: AccessType, _addr: u32) -> usize {
-        // TODO: Use accurate timings
-        1
+    pub(super) fn arm9_page(&self, addr: u32) -> Page {
+        match MemoryRegion::try_from_addr(addr, &self.cp15) {
+            Some(MemoryRegion::ITCM) => Page::ITCM(addr & HW::ITCM_MASK),
+            Some(MemoryRegion::DTCM) => Page::DTCM(addr & HW::DTCM_MASK),
+            Some(MemoryRegion::MainMem) => Page::MainMem(addr & self.main_mem_mask()),
+            // DSi WRAM takes priority over shared WRAM
+            Some(MemoryRegion::SharedWRAM) if self.nwram.mapping(true, addr).is_some() => Page::Slow,
+            Some(MemoryRegion::SharedWRAM) => match self.wramcnt.arm9_mapping(addr) {
+                Some((offset, _)) => Page::SharedWRAM(offset),
+                None => Page::Slow,
+            },
+            _ => Page::Slow,
+        }
+    }
+
+    // TCM mappings are changed through CP15
+    pub fn arm9_write_cp15(&mut self, n: u32, m: u32, p: u32, value: u32) {
+        self.cp15.write(n, m, p, value);
+        if n == 9 && m == 1 { self.remap_pages() }
+    }
+
+    // Returns the region, offset into it, and number of bytes until the mapping may change
+    pub fn arm9_ram_region(&self, addr: u32) -> Option<(RAMRegion, usize, usize)> {
+        // TCM is mapped in 4KB units so a page is either all TCM or none of it
+        let page_len = HW::TCM_PAGE_LEN - addr as usize % HW::TCM_PAGE_LEN;
+        let (region, offset, len) = match MemoryRegion::from_addr(addr, &self.cp15) {
+            MemoryRegion::MainMem => {
+                let offset = (addr & self.main_mem_mask()) as usize;
+                (RAMRegion::MainMem, offset, self.main_mem_mask() as usize + 1 - offset)
+            },
+            MemoryRegion::SharedWRAM if self.nwram.mapping(true, addr).is_some() => return None,
+            MemoryRegion::SharedWRAM => {
+                let (offset, len) = self.wramcnt.arm9_mapping(addr)?;
+                (RAMRegion::SharedWRAM, offset as usize, len)
+            },
+            MemoryRegion::VRAM => {
+                let (bank, offset, len) = self.gpu.vram.arm9_bank_region(addr)?;
+                (RAMRegion::VRAM(bank), offset, len)
+            },
+            _ => return None,
+        };
+        Some((region, offset, len.min(page_len)))
+    }
+
+    // Side effect free access to the memory the ARM9 sees for debugging tools
+    pub fn arm9_debug_mem(&mut self, addr: u32) -> Option<&mut u8> {
+        match MemoryRegion::try_from_addr(addr, &self.cp15)? {
+            MemoryRegion::ITCM => Some(&mut self.itcm[(addr & HW::ITCM_MASK) as usize]),
+            MemoryRegion::DTCM => Some(&mut self.dtcm[(addr & HW::DTCM_MASK) as usize]),
+            MemoryRegion::OAM if addr & 0x7FFF < 0x400 =>
+                Some(&mut self.gpu.engine_a.oam_mut()[(addr & GPU::OAM_MASK as u32) as usize]),
+            MemoryRegion::OAM => Some(&mut self.gpu.engine_b.oam_mut()[(addr & GPU::OAM_MASK as u32) as usize]),
+            MemoryRegion::SharedWRAM if self.nwram.mapping(true, addr).is_some() => {
+                let offset = self.nwram.mapping(true, addr).unwrap();
+                Some(self.nwram.byte_mut(offset))
+            },
+            _ => {
+                let (region, offset, _) = self.arm9_ram_region(addr)?;
+                Some(&mut self.ram_region_mut(region)[offset])
+            },
+        }
+    }
+
+    pub fn arm9_debug_read(&mut self, addr: u32) -> Option<u8> {
+        match MemoryRegion::try_from_addr(addr, &self.cp15)? {
+            MemoryRegion::Palette if addr & 0x7FFF < 0x400 => Some(self.gpu.engine_a.read_palette_ram(addr)),
+            MemoryRegion::Palette => Some(self.gpu.engine_b.read_palette_ram(addr)),
+            MemoryRegion::BIOS => self.bios9.get((addr & 0xFFFF) as usize).copied(),
+            _ => self.arm9_debug_mem(addr).map(|byte| *byte),
+        }
+    }
+
+    // Palette RAM is stored as halfwords, so the other byte of the halfword is kept
+    pub fn arm9_debug_write(&mut self, addr: u32, value: u8) -> Option<()> {
+        match MemoryRegion::try_from_addr(addr, &self.cp15)? {
+            MemoryRegion::Palette => {
+                let mut bytes = [self.arm9_debug_read(addr & !0x1)?, self.arm9_debug_read(addr | 0x1)?];
+                bytes[addr as usize % 2] = value;
+                let value = u16::from_le_bytes(bytes);
+                if addr & 0x7FFF < 0x400 {
+                    HW::write_palette_ram(&mut self.gpu.engine_a, addr & !0x1, value);
+                } else { HW::write_palette_ram(&mut self.gpu.engine_b, addr & !0x1, value) }
+            },
+            _ => *self.arm9_debug_mem(addr)? = value,
+        }
+        Some(())
+    }
+
+    pub fn arm9_get_access_time<T: MemoryValue>(&mut self, access_type: AccessType, addr: u32) -> usize {
+        match addr >> 24 {
+            0x8 ..= 0xA => self.gba_access_time::<T>(true, access_type, addr),
+            // TODO: Use accurate timings
+            _ => 1,
+        }
     }
 
     pub fn init_arm9(&mut self) -> u32 {
@@ -78,7 +182,7 @@ impl HW {
         self.cartridge.header().arm9_entry_addr
     }
 
-    fn arm9_read_io_register(&self, addr: u32) -> u8 {
+    pub(crate) fn arm9_read_io_register(&self, addr: u32) -> u8 {
         match addr {
             0x0400_0000 ..= 0x0400_0003 => self.gpu.engine_a.read_register(addr),
             0x0400_0004 => self.gpu.dispstats[1].read(0),
@@ -88,10 +192,7 @@ impl HW {
             0x0400_0008 ..= 0x0400_005F => self.gpu.engine_a.read_register(addr),
             0x0400_0060 ..= 0x0400_0063 => self.gpu.engine3d.disp3dcnt.read(addr as usize % 4),
             0x0400_0064 ..= 0x0400_0067 => self.gpu.dispcapcnt.read(addr as usize % 4),
-            0x0400_006C => self.gpu.engine_a.master_bright.read(0),
-            0x0400_006D => self.gpu.engine_a.master_bright.read(1),
-            0x0400_006E => self.gpu.engine_a.master_bright.read(2),
-            0x0400_006F => self.gpu.engine_a.master_bright.read(3),
+            0x0400_006C ..= 0x0400_006F => self.gpu.engine_a.read_register(addr),
             0x0400_00B0 ..= 0x0400_00BB => self.dmas[1].read(0, addr - 0xB0),
             0x0400_00BC ..= 0x0400_00C7 => self.dmas[1].read(1, addr - 0xBC),
             0x0400_00C8 ..= 0x0400_00D3 => self.dmas[1].read(2, addr - 0xC8),
@@ -106,10 +207,8 @@ impl HW {
             0x0400_010C ..= 0x0400_010F => self.timers[1][3].read(&self.scheduler, addr as usize % 4),
             0x0400_0130 => self.keypad.keyinput.read(0),
             0x0400_0131 => self.keypad.keyinput.read(1),
-            0x0400_0132 => self.keypad.keycnt.read(0),
-            0x0400_0133 => self.keypad.keycnt.read(1),
-            0x0400_0136 => self.keypad.extkeyin.read(0),
-            0x0400_0137 => self.keypad.extkeyin.read(1),
+            0x0400_0132 => self.keypad.keycnt[1].read(0),
+            0x0400_0133 => self.keypad.keycnt[1].read(1),
             0x0400_0180 => self.ipc.read_sync9(0),
             0x0400_0181 => self.ipc.read_sync9(1),
             0x0400_0182 => self.ipc.read_sync9(2),
@@ -162,11 +261,9 @@ impl HW {
             0x0400_1004 ..= 0x0400_1007 => 0,
             0x0400_1008 ..= 0x0400_105F => self.gpu.engine_b.read_register(addr),
             0x0400_1060 ..= 0x0400_106B => 0,
-            0x0400_106C => self.gpu.engine_b.master_bright.read(0),
-            0x0400_106D => self.gpu.engine_b.master_bright.read(1),
-            0x0400_106E => self.gpu.engine_b.master_bright.read(2),
-            0x0400_106F => self.gpu.engine_b.master_bright.read(3),
-            0x0400_4010 ..= 0x0400_4011 => 0, // DSi register that's unused for NDS
+            0x0400_106C ..= 0x0400_106F => self.gpu.engine_b.read_register(addr),
+            0x0400_4000 ..= 0x0400_4063 => self.read_dsi_io_register(true, addr),
+            0x0400_4100 ..= 0x0400_4173 => self.read_ndma_register(true, addr),
             _ => { warn!("Ignoring ARM9 IO Register Read at 0x{:08X}", addr); 0 }
         }
     }
@@ -176,15 +273,13 @@ impl HW {
             0x0400_0000 ..= 0x0400_0003 => self.gpu.engine_a.write_register(&mut self.scheduler, addr, value),
             0x0400_0004 => self.gpu.dispstats[1].write(&mut self.scheduler, 0, value),
             0x0400_0005 => self.gpu.dispstats[1].write(&mut self.scheduler, 1, value),
-            0x0400_0006 => (), // VCOUNT is read only
-            0x0400_0007 => (), // VCOUNT is read only
+            0x0400_0006 => self.gpu.write_vcount(0, value),
+            0x0400_0007 => self.gpu.write_vcount(1, value),
             0x0400_0008 ..= 0x0400_005F => self.gpu.engine_a.write_register(&mut self.scheduler, addr, value),
             0x0400_0060 ..= 0x0400_0063 => self.gpu.engine3d.disp3dcnt.write(&mut self.scheduler, addr as usize % 4, value),
             0x0400_0064 ..= 0x0400_0067 => self.gpu.dispcapcnt.write(&mut self.scheduler, addr as usize % 4, value),
-            0x0400_006C => self.gpu.engine_a.master_bright.write(&mut self.scheduler, 0, value),
-            0x0400_006D => self.gpu.engine_a.master_bright.write(&mut self.scheduler, 1, value),
-            0x0400_006E => self.gpu.engine_a.master_bright.write(&mut self.scheduler, 2, value),
-            0x0400_006F => self.gpu.engine_a.master_bright.write(&mut self.scheduler, 3, value),
+            0x0400_0068 ..= 0x0400_006B => self.gpu.engine_a.write_main_mem_fifo(addr as usize % 4, value),
+            0x0400_006C ..= 0x0400_006F => self.gpu.engine_a.write_register(&mut self.scheduler, addr, value),
             0x0400_00B0 ..= 0x0400_00BB => self.dmas[1].write(0, &mut self.scheduler, addr - 0xB0, value),
             0x0400_00BC ..= 0x0400_00C7 => self.dmas[1].write(1, &mut self.scheduler, addr - 0xBC, value),
             0x0400_00C8 ..= 0x0400_00D3 => self.dmas[1].write(2, &mut self.scheduler, addr - 0xC8, value),
@@ -203,10 +298,8 @@ impl HW {
             0x0400_010C ..= 0x0400_010F => self.timers[1][3].write(&mut self.scheduler, addr as usize % 4, value),
             0x0400_0130 => self.keypad.keyinput.write(&mut self.scheduler, 0, value),
             0x0400_0131 => self.keypad.keyinput.write(&mut self.scheduler, 1, value),
-            0x0400_0132 => self.keypad.keycnt.write(&mut self.scheduler, 0, value),
-            0x0400_0133 => self.keypad.keycnt.write(&mut self.scheduler, 1, value),
-            0x0400_0136 => self.keypad.extkeyin.write(&mut self.scheduler, 0, value),
-            0x0400_0137 => self.keypad.extkeyin.write(&mut self.scheduler, 1, value),
+            0x0400_0132 => self.keypad.keycnt[1].write(&mut self.scheduler, 0, value),
+            0x0400_0133 => self.keypad.keycnt[1].write(&mut self.scheduler, 1, value),
             0x0400_0180 => self.interrupts[0].request |= self.ipc.write_sync9(0, value),
             0x0400_0181 => self.interrupts[0].request |= self.ipc.write_sync9(1, value),
             0x0400_0182 => self.interrupts[0].request |= self.ipc.write_sync9(2, value),
@@ -217,15 +310,15 @@ impl HW {
             0x0400_0187 => self.interrupts[1].request |= self.ipc.write_fifocnt9(3, value),
             0x0400_01A0 => self.cartridge.spicnt.write(!self.exmem.nds_arm7_access, 0, value),
             0x0400_01A1 => self.cartridge.spicnt.write(!self.exmem.nds_arm7_access, 1, value),
-            0x0400_01A2 => self.cartridge.write_spi_data(!self.exmem.nds_arm7_access, value),
+            0x0400_01A2 => self.cartridge.write_spi_data(&mut self.scheduler, !self.exmem.nds_arm7_access, value),
             0x0400_01A3 => (), // TODO: Does this write do anything?
-            0x0400_01A4 => self.cartridge.write_romctrl(&mut self.scheduler, false,
+            0x0400_01A4 => self.cartridge.write_romctrl(&mut self.scheduler, true,
                 !self.exmem.nds_arm7_access, 0, value),
-            0x0400_01A5 => self.cartridge.write_romctrl(&mut self.scheduler, false,
+            0x0400_01A5 => self.cartridge.write_romctrl(&mut self.scheduler, true,
                 !self.exmem.nds_arm7_access, 1, value),
-            0x0400_01A6 => self.cartridge.write_romctrl(&mut self.scheduler, false,
+            0x0400_01A6 => self.cartridge.write_romctrl(&mut self.scheduler, true,
                 !self.exmem.nds_arm7_access, 2, value),
-            0x0400_01A7 => self.cartridge.write_romctrl(&mut self.scheduler, false,
+            0x0400_01A7 => self.cartridge.write_romctrl(&mut self.scheduler, true,
                 !self.exmem.nds_arm7_access, 3, value),
             0x0400_01A8 => self.cartridge.write_command(!self.exmem.nds_arm7_access, 0, value),
             0x0400_01A9 => self.cartridge.write_command(!self.exmem.nds_arm7_access, 1, value),
@@ -235,22 +328,24 @@ impl HW {
             0x0400_01AD => self.cartridge.write_command(!self.exmem.nds_arm7_access, 5, value),
             0x0400_01AE => self.cartridge.write_command(!self.exmem.nds_arm7_access, 6, value),
             0x0400_01AF => self.cartridge.write_command(!self.exmem.nds_arm7_access, 7, value),
+            0x0400_01B0 ..= 0x0400_01BB => self.cartridge.write_rom_seed(!self.exmem.nds_arm7_access,
+                addr as usize - 0x0400_01B0, value),
             0x0400_0204 => self.exmem.write_arm9(value),
             0x0400_0205 => self.exmem.write_common(value),
-            0x0400_0208 => self.interrupts[1].master_enable.write(&mut self.scheduler, 0, value),
-            0x0400_0209 => self.interrupts[1].master_enable.write(&mut self.scheduler, 1, value),
-            0x0400_020A => self.interrupts[1].master_enable.write(&mut self.scheduler, 2, value),
-            0x0400_020B => self.interrupts[1].master_enable.write(&mut self.scheduler, 3, value),
-            0x0400_0210 => self.interrupts[1].enable.write(&mut self.scheduler, 0, value),
-            0x0400_0211 => self.interrupts[1].enable.write(&mut self.scheduler, 1, value),
-            0x0400_0212 => self.interrupts[1].enable.write(&mut self.scheduler, 2, value),
-            0x0400_0213 => self.interrupts[1].enable.write(&mut self.scheduler, 3, value),
-            0x0400_0214 => self.interrupts[1].request.write(&mut self.scheduler, 0, value),
-            0x0400_0215 => self.interrupts[1].request.write(&mut self.scheduler, 1, value),
-            0x0400_0216 => self.interrupts[1].request.write(&mut self.scheduler, 2, value),
-            0x0400_0217 => self.interrupts[1].request.write(&mut self.scheduler, 3, value),
+            0x0400_0208 => self.interrupts[1].write_master_enable(&mut self.scheduler, 0, value),
+            0x0400_0209 => self.interrupts[1].write_master_enable(&mut self.scheduler, 1, value),
+            0x0400_020A => self.interrupts[1].write_master_enable(&mut self.scheduler, 2, value),
+            0x0400_020B => self.interrupts[1].write_master_enable(&mut self.scheduler, 3, value),
+            0x0400_0210 => self.interrupts[1].write_enable(&mut self.scheduler, 0, value),
+            0x0400_0211 => self.interrupts[1].write_enable(&mut self.scheduler, 1, value),
+            0x0400_0212 => self.interrupts[1].write_enable(&mut self.scheduler, 2, value),
+            0x0400_0213 => self.interrupts[1].write_enable(&mut self.scheduler, 3, value),
+            0x0400_0214 => self.interrupts[1].write_request(&mut self.scheduler, 0, value),
+            0x0400_0215 => self.interrupts[1].write_request(&mut self.scheduler, 1, value),
+            0x0400_0216 => self.interrupts[1].write_request(&mut self.scheduler, 2, value),
+            0x0400_0217 => self.interrupts[1].write_request(&mut self.scheduler, 3, value),
             0x0400_0240 ..= 0x0400_0246 => self.gpu.vram.write_vram_cnt(addr as usize & 0xF, value),
-            0x0400_0247 => self.wramcnt.write(&mut self.scheduler, 0, value),
+            0x0400_0247 => { self.wramcnt.write(&mut self.scheduler, 0, value); self.remap_pages() },
             0x0400_0248 ..= 0x0400_0249 => self.gpu.vram.write_vram_cnt((addr as usize & 0xF) - 1, value),
             0x0400_0280 ..= 0x0400_0283 => self.div.cnt.write(&mut self.scheduler, addr as usize & 0xF, value),
             0x0400_0290 ..= 0x0400_0297 => self.div.write_numer(&mut self.scheduler, addr as usize & 0x7, value),
@@ -271,10 +366,9 @@ impl HW {
             0x0400_1004 ..= 0x0400_1007 => (),
             0x0400_1008 ..= 0x0400_105F => self.gpu.engine_b.write_register(&mut self.scheduler, addr, value),
             0x0400_1060 ..= 0x0400_106B => (),
-            0x0400_106C => self.gpu.engine_b.master_bright.write(&mut self.scheduler, 0, value),
-            0x0400_106D => self.gpu.engine_b.master_bright.write(&mut self.scheduler, 1, value),
-            0x0400_106E => self.gpu.engine_b.master_bright.write(&mut self.scheduler, 2, value),
-            0x0400_106F => self.gpu.engine_b.master_bright.write(&mut self.scheduler, 3, value),
+            0x0400_106C ..= 0x0400_106F => self.gpu.engine_b.write_register(&mut self.scheduler, addr, value),
+            0x0400_4000 ..= 0x0400_4063 => self.write_dsi_io_register(true, addr, value),
+            0x0400_4100 ..= 0x0400_4173 => self.write_ndma_register(true, addr, value),
             _ => warn!("Ignoring ARM9 IO Register Write 0x{:08X} = {:02X}", addr, value),
         }
     }
@@ -323,10 +417,17 @@ pub enum ARM9MemoryRegion {
 
 impl ARM9MemoryRegion {
     pub fn from_addr(addr: u32, cp15: &CP15) -> Self {
+        ARM9MemoryRegion::try_from_addr(addr, cp15).unwrap_or_else(|| {
+            warn!("Unknown Memory Access: {:X}", addr);
+            ARM9MemoryRegion::Unknown
+        })
+    }
+
+    pub fn try_from_addr(addr: u32, cp15: &CP15) -> Option<Self> {
         use ARM9MemoryRegion::*;
-        if cp15.addr_in_itcm(addr) { return ITCM }
-        if cp15.addr_in_dtcm(addr) { return DTCM }
-        match addr >> 24 {
+        if cp15.addr_in_itcm(addr) { return Some(ITCM) }
+        if cp15.addr_in_dtcm(addr) { return Some(DTCM) }
+        Some(match addr >> 24 {
             0x2 => MainMem,
             0x3 => SharedWRAM,
             0x4 => IO,
@@ -336,7 +437,7 @@ impl ARM9MemoryRegion {
             0x8 | 0x9 => GBAROM,
             0xA => GBARAM,
             0xFF if addr >> 16 == 0xFFFF => BIOS,
-            _ => { warn!("Uknown Memory Access: {:X}", addr); Unknown },
-        }
+            _ => return None,
+        })
     }
 }
@@ -1,6 +1,7 @@
 use bitflags::*;
 
 use super::HW;
+use crate::fault::{Fault, Faults};
 
 pub struct CP15 {
     control: Control,
@@ -16,10 +17,14 @@ pub struct CP15 {
     // PU Regions
     pu_data_regions: [u32; 8],
     pu_instr_regions: [u32; 8],
+    faults: Faults,
 }
 
+savestate!(CP15 { control, interrupt_base, itcm_control, dtcm_control, arm9_halted, ap_data_region, ap_instr_region,
+    ext_ap_data_region, ext_ap_instr_region, pu_data_regions, pu_instr_regions });
+
 impl CP15 {
-    pub fn new() -> Self {
+    pub fn new(faults: Faults) -> Self {
         CP15 {
             control: Control::new(),
             interrupt_base: 0xFFFF_0000,
@@ -34,6 +39,22 @@ impl CP15 {
             // PU Regions
             pu_data_regions: [0; 8],
             pu_instr_regions: [0; 8],
+            faults,
+        }
+    }
+
+    // The firmware leaves DTCM at 0x03000000 with the protection unit set up, though it's turned off
+    pub fn direct_boot(&mut self) {
+        self.write_control_reg(0, 0, 0x0001_2078);
+        self.dtcm_control.write(0x0300_000A);
+        self.itcm_control.write(0x0000_0020);
+        self.write_ap_regions(0, 2, 0x1511_1011);
+        self.write_ap_regions(0, 3, 0x0510_0011);
+        let regions = [0x0400_0033, 0x0200_002B, 0x0000_0000, 0x0800_0035, 0x0300_001B, 0x0000_0000, 0xFFFF_001D,
+            0x027F_F017];
+        for (region, value) in regions.iter().enumerate() {
+            self.write_pu_regions(region as u32, 0, *value);
+            self.write_pu_regions(region as u32, 1, *value);
         }
     }
 
@@ -45,7 +66,7 @@ impl CP15 {
             5 => self.read_ap_regions(m, p),
             6 => self.read_pu_regions(m, p),
             9 => self.read_cache_control(m, p),
-            _ => todo!(),
+            _ => self.unimplemented(n, m, p),
         }
     }
 
@@ -59,10 +80,15 @@ impl CP15 {
             6 => self.write_pu_regions(m, p, value),
             7 => self.write_cache_command(m, p, value),
             9 => self.write_cache_control(m, p, value),
-            _ => todo!(),
+            _ => { self.unimplemented(n, m, p); },
         }
     }
 
+    fn unimplemented(&self, n: u32, m: u32, p: u32) -> u32 {
+        self.faults.raise(Fault::Unimplemented(format!("CP15 Register C{}, C{}, {}", n, m, p)));
+        0
+    }
+
     pub fn addr_in_itcm(&self, addr: u32) -> bool {
         addr < self.itcm_control.virtual_size
     }
@@ -82,15 +108,15 @@ impl CP15 {
             (0, 1) => self.ap_instr_region,
             (0, 2) => self.ext_ap_data_region,
             (0, 3) => self.ext_ap_instr_region,
-            _ => todo!(),
+            _ => self.unimplemented(5, m, p),
         }
     }
 
     fn read_pu_regions(&self, m: u32, p: u32) -> u32 {
         match (m, p) {
-            (region, 0) => self.pu_data_regions[region as usize],
-            (region, 1) => self.pu_instr_regions[region as usize],
-            _ => todo!(),
+            (region, 0) if region < 8 => self.pu_data_regions[region as usize],
+            (region, 1) if region < 8 => self.pu_instr_regions[region as usize],
+            _ => self.unimplemented(6, m, p),
         }
     }
 
@@ -105,7 +131,7 @@ impl CP15 {
         match (m, p) {
             (0, 0) => warn!("Cachability Bits for Data/Unified Region: 0x{:X}", value),
             (0, 1) => warn!("Cachability Bits for Instruction Region: 0x{:X}", value),
-            _ => todo!(),
+            _ => { self.unimplemented(2, m, p); },
         }
     }
 
@@ -120,15 +146,15 @@ impl CP15 {
             (0, 1) => self.ap_instr_region = value & 0xFFFF,
             (0, 2) => self.ext_ap_data_region = value,
             (0, 3) => self.ext_ap_instr_region = value,
-            _ => todo!(),
+            _ => { self.unimplemented(5, m, p); },
         }
     }
 
     fn write_pu_regions(&mut self, m: u32, p: u32, value: u32) {
         match (m, p) {
-            (region, 0) => self.pu_data_regions[region as usize] = value & !(0x3F << 6),
-            (region, 1) => self.pu_instr_regions[region as usize] = value & !(0x3F << 6),
-            _ => todo!(),
+            (region, 0) if region < 8 => self.pu_data_regions[region as usize] = value & !(0x3F << 6),
+            (region, 1) if region < 8 => self.pu_instr_regions[region as usize] = value & !(0x3F << 6),
+            _ => { self.unimplemented(6, m, p); },
         }
     }
 
@@ -144,7 +170,7 @@ impl CP15 {
             (10, 4) if value == 0 => info!("Drain Write Buffer"), // TODO: Drain Write Buffer
             (14, 1) => info!("Clean and Invalidate Data Cache Line 0x{:X}", value), // TODO: Clean and Invalidate Data Cache Line
             (14, 2) => info!("Clean and Invalidate Data Cache Index 0x{:X}", value), // TODO: Clean and Invalidate Data Cache Line
-            _ => todo!(),
+            _ => { self.unimplemented(7, m, p); },
         }
     }
 
@@ -152,7 +178,7 @@ impl CP15 {
         match (m, p) {
             (1, 0) => self.dtcm_control.read(),
             (1, 1) => self.itcm_control.read(),
-            _ => todo!(),
+            _ => self.unimplemented(9, m, p),
         }
     }
 
@@ -161,8 +187,9 @@ impl CP15 {
             (0, 0) => warn!("Data Cache Lockdown: 0x{:X}", value), // TODO: Data Cache Lockdown
             (0, 1) => warn!("Instruction Cache Lockdown: 0x{:X}", value), // TODO: Instruction Cache Lockdown
             (1, 0) => self.dtcm_control.write(value),
-            (1, 1) => { self.itcm_control.write(value); assert_eq!(self.itcm_control.base, 0) },
-            _ => todo!(),
+            // The ITCM is always at address 0
+            (1, 1) => { self.itcm_control.write(value); self.itcm_control.base = 0 },
+            _ => { self.unimplemented(9, m, p); },
         }
     }
 }
@@ -173,6 +200,8 @@ struct TCMControl {
     virtual_size_shift: u32,
 }
 
+savestate!(TCMControl { base, virtual_size, virtual_size_shift });
+
 impl TCMControl {
     pub fn new(base: u32, virtual_size: u32) -> Self {
         let mut v_size_copy = virtual_size;
@@ -195,8 +224,8 @@ impl TCMControl {
 
     pub fn write(&mut self, value: u32) {
         self.base = value & !0xFFF;
-        self.virtual_size_shift = value >> 1 & 0x1F;
-        assert!((3..=23).contains(&self.virtual_size_shift));
+        // Sizes are limited to 4KB to 4GB
+        self.virtual_size_shift = (value >> 1 & 0x1F).clamp(3, 23);
         self.virtual_size = 0x200 << self.virtual_size_shift;
     }
 }
@@ -223,6 +252,8 @@ bitflags! {
     }
 }
 
+savestate_bitflags!(Control);
+
 impl Control {
     const MASK: u32 = (1 << 19) | (1 << 18) | (1 << 17) | (1 << 16) | (1 << 15) | (1 << 14) | (1 << 13) | (1 << 12) |
         (1 << 7) | (1 << 2) | (1 << 0);
@@ -0,0 +1,201 @@
+use std::mem::size_of;
+
+use super::{AccessType, HW, MemoryValue, IORegister, RAMRegion};
+use crate::num;
+
+type MemoryRegion = GBAMemoryRegion;
+
+impl HW {
+    const GBA_EWRAM_MASK: u32 = 0x3_FFFF;
+    const GBA_IWRAM_MASK: u32 = 0x7FFF;
+
+    pub(super) fn gba_read<T: MemoryValue>(&mut self, addr: u32) -> T {
+        match MemoryRegion::from_addr(addr) {
+            MemoryRegion::BIOS if (addr as usize) < self.gba.bios.len() => HW::read_mem(&self.gba.bios, addr),
+            MemoryRegion::BIOS => { warn!("Reading from Unmapped GBA BIOS 0x{:08X}", addr); num::zero() },
+            MemoryRegion::EWRAM => HW::read_mem(&self.main_mem, addr & HW::GBA_EWRAM_MASK),
+            MemoryRegion::IWRAM => HW::read_mem(&self.shared_wram, addr & HW::GBA_IWRAM_MASK),
+            MemoryRegion::IO => HW::read_from_bytes(self, &HW::gba_read_io_register, addr),
+            MemoryRegion::Palette => HW::read_from_bytes(&self.gpu.engine_a, &|engine, addr| engine.read_palette_ram(addr),
+                addr),
+            MemoryRegion::VRAM => self.gpu.vram.gba_read(addr as usize),
+            MemoryRegion::OAM => self.gpu.engine_a.read_oam(addr),
+            MemoryRegion::ROM => self.read_gba_rom(false, addr),
+            MemoryRegion::SRAM => self.read_gba_ram(false, addr),
+            MemoryRegion::Unused => { warn!("Reading from Unmapped GBA Memory 0x{:08X}", addr); num::zero() },
+        }
+    }
+
+    pub(super) fn gba_write<T: MemoryValue>(&mut self, addr: u32, value: T) {
+        match MemoryRegion::from_addr(addr) {
+            MemoryRegion::BIOS => warn!("Writing to GBA BIOS 0x{:08x} = 0x{:X}", addr, value),
+            MemoryRegion::EWRAM => HW::write_mem(&mut self.main_mem, addr & HW::GBA_EWRAM_MASK, value),
+            MemoryRegion::IWRAM => HW::write_mem(&mut self.shared_wram, addr & HW::GBA_IWRAM_MASK, value),
+            MemoryRegion::IO => HW::write_from_bytes(self, &HW::gba_write_io_register, addr, value),
+            // 8-bit writes to palette and BG VRAM are written to both bytes
+            MemoryRegion::Palette => {
+                let addr = addr & 0x3FE;
+                let value = num::cast::<T, u32>(value).unwrap();
+                match size_of::<T>() {
+                    1 => self.gpu.engine_a.write_palette_ram(addr as usize, (value as u16) << 8 | value as u16),
+                    2 => self.gpu.engine_a.write_palette_ram(addr as usize, value as u16),
+                    4 => {
+                        self.gpu.engine_a.write_palette_ram(addr as usize, value as u16);
+                        self.gpu.engine_a.write_palette_ram(addr as usize + 2, (value >> 16) as u16);
+                    },
+                    _ => unreachable!(),
+                }
+            },
+            MemoryRegion::VRAM if size_of::<T>() == 1 => {
+                let value = num::cast::<T, u16>(value).unwrap();
+                // OBJ VRAM ignores 8-bit writes
+                if addr & 0x1_FFFF < 0x1_0000 { self.gpu.vram.gba_write(addr as usize & !0x1, value << 8 | value) }
+            },
+            MemoryRegion::VRAM => self.gpu.vram.gba_write(addr as usize, value),
+            MemoryRegion::OAM if size_of::<T>() == 1 => (),
+            MemoryRegion::OAM => self.gpu.engine_a.write_oam(addr, value),
+            MemoryRegion::ROM => self.write_gba_rom(false, addr, value),
+            MemoryRegion::SRAM => self.write_gba_ram(false, addr, value),
+            MemoryRegion::Unused => warn!("Writing to Unmapped GBA Memory 0x{:08X} = 0x{:X}", addr, value),
+        }
+    }
+
+    fn gba_read_io_register(&self, addr: u32) -> u8 {
+        match addr {
+            0x0400_0000 ..= 0x0400_0001 => self.gpu.engine_a.read_gba_dispcnt(addr as usize % 2),
+            0x0400_0002 ..= 0x0400_0003 => 0, // TODO: Green Swap
+            0x0400_0004 => self.gpu.dispstats[0].read(0),
+            0x0400_0005 => self.gpu.dispstats[0].read(1),
+            0x0400_0006 => self.gpu.vcount as u8,
+            0x0400_0007 => 0,
+            0x0400_0008 ..= 0x0400_005F => self.gpu.engine_a.read_register(addr),
+            0x0400_0060 ..= 0x0400_00A7 => self.gba.sound.read(addr as usize & 0xFF),
+            0x0400_00A8 ..= 0x0400_00AF => 0,
+            0x0400_00B0 ..= 0x0400_00BB => self.dmas[0].read(0, addr - 0xB0),
+            0x0400_00BC ..= 0x0400_00C7 => self.dmas[0].read(1, addr - 0xBC),
+            0x0400_00C8 ..= 0x0400_00D3 => self.dmas[0].read(2, addr - 0xC8),
+            0x0400_00D4 ..= 0x0400_00DF => self.dmas[0].read(3, addr - 0xD4),
+            0x0400_0100 ..= 0x0400_0103 => self.timers[0][0].read(&self.scheduler, addr as usize % 4),
+            0x0400_0104 ..= 0x0400_0107 => self.timers[0][1].read(&self.scheduler, addr as usize % 4),
+            0x0400_0108 ..= 0x0400_010B => self.timers[0][2].read(&self.scheduler, addr as usize % 4),
+            0x0400_010C ..= 0x0400_010F => self.timers[0][3].read(&self.scheduler, addr as usize % 4),
+            0x0400_0130 => self.keypad.keyinput.read(0),
+            0x0400_0131 => self.keypad.keyinput.read(1),
+            0x0400_0132 => self.keypad.keycnt[0].read(0),
+            0x0400_0133 => self.keypad.keycnt[0].read(1),
+            0x0400_0200 => self.interrupts[0].enable.read(0),
+            0x0400_0201 => self.interrupts[0].enable.read(1),
+            0x0400_0202 => self.interrupts[0].request.read(0),
+            0x0400_0203 => self.interrupts[0].request.read(1),
+            0x0400_0204 ..= 0x0400_0205 => HW::read_byte_from_value(&self.gba.waitcnt, addr as usize % 2),
+            0x0400_0208 => self.interrupts[0].master_enable.read(0),
+            0x0400_0209 ..= 0x0400_020B => 0,
+            0x0400_0300 => self.gba.postflg,
+            0x0400_0301 => 0,
+            _ => { warn!("Ignoring GBA IO Register Read at 0x{:08X}", addr); 0 }
+        }
+    }
+
+    fn gba_write_io_register(&mut self, addr: u32, value: u8) {
+        match addr {
+            0x0400_0000 ..= 0x0400_0001 =>
+                self.gpu.engine_a.write_gba_dispcnt(&mut self.scheduler, addr as usize % 2, value),
+            0x0400_0002 ..= 0x0400_0003 => (), // TODO: Green Swap
+            0x0400_0004 => self.gpu.dispstats[0].write(&mut self.scheduler, 0, value & 0x7F), // No lines past 255
+            0x0400_0005 => self.gpu.dispstats[0].write(&mut self.scheduler, 1, value),
+            0x0400_0006 ..= 0x0400_0007 => (), // VCOUNT is read only
+            0x0400_0008 ..= 0x0400_005F => self.gpu.engine_a.write_register(&mut self.scheduler, addr, value),
+            0x0400_0060 ..= 0x0400_00A7 => self.gba.sound.write(addr as usize & 0xFF, value),
+            0x0400_00A8 ..= 0x0400_00AF => (),
+            0x0400_00B0 ..= 0x0400_00BB => self.dmas[0].write(0, &mut self.scheduler, addr - 0xB0, value),
+            0x0400_00BC ..= 0x0400_00C7 => self.dmas[0].write(1, &mut self.scheduler, addr - 0xBC, value),
+            0x0400_00C8 ..= 0x0400_00D3 => self.dmas[0].write(2, &mut self.scheduler, addr - 0xC8, value),
+            0x0400_00D4 ..= 0x0400_00DF => self.dmas[0].write(3, &mut self.scheduler, addr - 0xD4, value),
+            0x0400_0100 ..= 0x0400_0103 => self.timers[0][0].write(&mut self.scheduler, addr as usize % 4, value),
+            0x0400_0104 ..= 0x0400_0107 => self.timers[0][1].write(&mut self.scheduler, addr as usize % 4, value),
+            0x0400_0108 ..= 0x0400_010B => self.timers[0][2].write(&mut self.scheduler, addr as usize % 4, value),
+            0x0400_010C ..= 0x0400_010F => self.timers[0][3].write(&mut self.scheduler, addr as usize % 4, value),
+            0x0400_0132 => self.keypad.keycnt[0].write(&mut self.scheduler, 0, value),
+            0x0400_0133 => self.keypad.keycnt[0].write(&mut self.scheduler, 1, value),
+            0x0400_0200 => self.interrupts[0].write_enable(&mut self.scheduler, 0, value),
+            0x0400_0201 => self.interrupts[0].write_enable(&mut self.scheduler, 1, value),
+            0x0400_0202 => self.interrupts[0].write_request(&mut self.scheduler, 0, value),
+            0x0400_0203 => self.interrupts[0].write_request(&mut self.scheduler, 1, value),
+            0x0400_0204 ..= 0x0400_0205 => HW::write_byte_to_value(&mut self.gba.waitcnt, addr as usize % 2, value),
+            0x0400_0208 => self.interrupts[0].write_master_enable(&mut self.scheduler, 0, value),
+            0x0400_0209 ..= 0x0400_020B => (),
+            0x0400_0300 => self.gba.postflg |= value & 0x1,
+            // Only Halt is supported, Stop is treated the same way
+            0x0400_0301 => self.haltcnt.write(&mut self.scheduler, 0, 0x80),
+            _ => warn!("Ignoring GBA IO Register Write 0x{:08X} = {:02X}", addr, value),
+        }
+    }
+
+    pub(super) fn gba_ram_region(&self, addr: u32) -> Option<(RAMRegion, usize, usize)> {
+        match MemoryRegion::from_addr(addr) {
+            MemoryRegion::EWRAM => {
+                let offset = (addr & HW::GBA_EWRAM_MASK) as usize;
+                Some((RAMRegion::MainMem, offset, HW::GBA_EWRAM_MASK as usize + 1 - offset))
+            },
+            MemoryRegion::IWRAM => {
+                let offset = (addr & HW::GBA_IWRAM_MASK) as usize;
+                Some((RAMRegion::SharedWRAM, offset, HW::GBA_IWRAM_MASK as usize + 1 - offset))
+            },
+            _ => None,
+        }
+    }
+
+    // In cycles of the 16.78 MHz GBA clock
+    pub(super) fn gba_get_access_time<T: MemoryValue>(&self, access_type: AccessType, addr: u32) -> usize {
+        const N_CYCLES: [usize; 4] = [4, 3, 2, 8];
+        const S_CYCLES: [[usize; 2]; 3] = [[2, 1], [4, 1], [8, 1]];
+        let waitcnt = self.gba.waitcnt as usize;
+        let wide = size_of::<T>() == 4;
+        // 32-bit accesses take two accesses on the 16-bit buses
+        let accesses = 1 + wide as usize;
+        match MemoryRegion::from_addr(addr) {
+            MemoryRegion::EWRAM => 3 * accesses,
+            MemoryRegion::Palette | MemoryRegion::VRAM => accesses,
+            MemoryRegion::ROM => {
+                let wait_state = ((addr >> 25) - 4) as usize;
+                let n_cycles = N_CYCLES[waitcnt >> (2 + 3 * wait_state) & 0x3] + 1;
+                let s_cycles = S_CYCLES[wait_state][waitcnt >> (4 + 3 * wait_state) & 0x1] + 1;
+                let first = match access_type { AccessType::N => n_cycles, AccessType::S => s_cycles };
+                if wide { first + s_cycles } else { first }
+            },
+            MemoryRegion::SRAM => N_CYCLES[waitcnt & 0x3] + 1,
+            _ => 1,
+        }
+    }
+}
+
+pub enum GBAMemoryRegion {
+    BIOS,
+    EWRAM,
+    IWRAM,
+    IO,
+    Palette,
+    VRAM,
+    OAM,
+    ROM,
+    SRAM,
+    Unused,
+}
+
+impl GBAMemoryRegion {
+    pub fn from_addr(addr: u32) -> Self {
+        use GBAMemoryRegion::*;
+        match addr >> 24 {
+            0x0 if addr < 0x4000 => BIOS,
+            0x2 => EWRAM,
+            0x3 => IWRAM,
+            0x4 => IO,
+            0x5 => Palette,
+            0x6 => VRAM,
+            0x7 => OAM,
+            0x8 ..= 0xD => ROM,
+            0xE ..= 0xF => SRAM,
+            _ => Unused,
+        }
+    }
+}
@@ -1,20 +1,31 @@
 pub mod arm7;
 pub mod arm9;
 pub mod cp15;
+mod gba;
 
+use std::convert::TryInto;
 use std::mem::size_of;
 use std::ops::BitOrAssign;
 pub use cp15::CP15;
+use crate::fault::Fault;
 use crate::num::{self, cast::FromPrimitive, NumCast, PrimInt, Unsigned};
-use super::{HW, Scheduler};
+use super::{HW, OpenBus, Scheduler};
 
 impl HW {
-    const MAIN_MEM_MASK: u32 = HW::MAIN_MEM_SIZE as u32 - 1;
     const IWRAM_MASK: u32 = HW::IWRAM_SIZE as u32 - 1;
+    // TCM can be mapped in units this small
+    const PAGE_SHIFT: u32 = 12;
+    const PAGE_MASK: u32 = (1 << HW::PAGE_SHIFT) - 1;
+    // All RAM is below 0x0800_0000 apart from DTCM moved above it, which takes the slow path
+    pub(super) const PAGE_COUNT: usize = 0x0800_0000 >> HW::PAGE_SHIFT;
+
+    fn unimplemented_access<T: MemoryValue>(&self, name: &str, addr: u32) {
+        self.faults.raise(Fault::Unimplemented(format!("{} Bit {} Access at 0x{:08X}", 8 * size_of::<T>(), name, addr)));
+    }
 
     // TODO: Replace with const generic
     fn ipc_fifo_recv<T: MemoryValue>(&mut self, is_arm9: bool, addr: u32) -> T {
-        if addr != 0x0410_0000 || size_of::<T>() != 4 { todo!() }
+        if addr != 0x0410_0000 || size_of::<T>() != 4 { self.unimplemented_access::<T>("IPC FIFO", addr); return num::zero() }
         if is_arm9 {
             let (value, interrupt) = self.ipc.arm9_recv();
             self.interrupts[0].request |= interrupt;
@@ -27,7 +38,7 @@ impl HW {
     }
 
     fn ipc_fifo_send<T: MemoryValue>(&mut self, is_arm9: bool, addr: u32, value: T) {
-        if addr != 0x0400_0188 || size_of::<T>() != 4 { todo!() }
+        if addr != 0x0400_0188 || size_of::<T>() != 4 { return self.unimplemented_access::<T>("IPC FIFO", addr) }
         let value = num::cast::<T, u32>(value).unwrap();
         if is_arm9 {
             self.interrupts[1].request |= self.ipc.arm7_send(value);
@@ -37,43 +48,134 @@ impl HW {
     }
 
     fn read_game_card<T: MemoryValue>(&mut self, is_arm9: bool, addr: u32) -> T {
-        if addr != 0x0410_0010 || size_of::<T>() != 4 { todo!() }
+        if addr != 0x0410_0010 || size_of::<T>() != 4 { self.unimplemented_access::<T>("Game Card", addr); return num::zero() }
         let value = self.cartridge.read_gamecard(&mut self.scheduler, is_arm9,
             self.exmem.nds_arm7_access != is_arm9);
         num::cast::<u32, T>(value).unwrap()
     }
 
+    fn write_game_card<T: MemoryValue>(&mut self, is_arm9: bool, addr: u32, value: T) {
+        if addr != 0x0410_0010 || size_of::<T>() != 4 { return self.unimplemented_access::<T>("Game Card", addr) }
+        let value = num::cast::<T, u32>(value).unwrap();
+        self.cartridge.write_gamecard(&mut self.scheduler, is_arm9, self.exmem.nds_arm7_access != is_arm9, value);
+    }
+
     // TODO: Replace with const generic
     fn read_gba_rom<T: MemoryValue>(&self, is_arm9: bool, addr: u32) -> T {
         if self.exmem.gba_arm7_access != is_arm9 {
-            let cnt = &self.exmem.gba[is_arm9 as usize];
-            let value = match cnt.rom_n_access_time {
-                0 => addr / 2 | 0xFE08,
-                1 | 2 => addr / 2,
-                3 => 0xFFFF,
-                _ => unreachable!(),
-            } & 0xFFFF;
+            let value = match (&self.slot2, self.slot2_open_bus) {
+                (Some(slot2), _) => slot2.read_rom(addr) as u32,
+                (None, OpenBus::Pattern) => {
+                    let cnt = &self.exmem.gba[is_arm9 as usize];
+                    let value = match cnt.rom_n_access_time {
+                        0 => (addr / 2) | 0xFE08,
+                        1 | 2 => addr / 2,
+                        3 => 0xFFFF,
+                        _ => unreachable!(),
+                    };
+                    value & 0xFFFF
+                },
+                (None, OpenBus::Zeros) => 0x0000,
+                (None, OpenBus::Ones) => 0xFFFF,
+            };
             num::cast::<u32, T>(match size_of::<T>() {
-                1 => value & 0xFF,
+                1 => value >> (8 * (addr & 0x1)) & 0xFF,
                 2 => value,
-                4 => (self.read_gba_rom::<u16>(is_arm9, addr + 1) as u32) << 16 | value,
+                4 => (self.read_gba_rom::<u16>(is_arm9, addr + 2) as u32) << 16 | value,
                 _ => unreachable!(),
             }).unwrap()
         } else {
+            // The slot belongs to the other CPU
             num::zero()
         }
     }
 
-    pub(super) fn read_mem<T: MemoryValue>(mem: &[u8], addr: u32) -> T {
-        unsafe {
-            *(&mem[addr as usize] as *const u8 as *const T)
+    fn write_gba_rom<T: MemoryValue>(&mut self, is_arm9: bool, addr: u32, value: T) {
+        if self.exmem.gba_arm7_access != is_arm9 {
+            let value = num::cast::<T, u32>(value).unwrap();
+            if let Some(slot2) = &mut self.slot2 {
+                match size_of::<T>() {
+                    // Bytes are written to both halves of the 16-bit bus
+                    1 => slot2.write_rom(addr, (value as u16) << 8 | value as u16),
+                    2 => slot2.write_rom(addr, value as u16),
+                    4 => {
+                        slot2.write_rom(addr, value as u16);
+                        slot2.write_rom(addr + 2, (value >> 16) as u16);
+                    },
+                    _ => unreachable!(),
+                }
+            }
         }
     }
 
-    pub(super) fn write_mem<T: MemoryValue>(mem: &mut [u8], addr: u32, value: T) {
-        unsafe {
-            *(&mut mem[addr as usize] as *mut u8 as *mut T) = value;
+    fn read_gba_ram<T: MemoryValue>(&self, is_arm9: bool, addr: u32) -> T {
+        if self.exmem.gba_arm7_access != is_arm9 {
+            // Nothing drives the 8-bit SRAM bus without a cartridge
+            let empty = if self.slot2_open_bus == OpenBus::Zeros { 0x00 } else { 0xFF };
+            let value = self.slot2.as_ref().map_or(empty, |slot2| slot2.read_ram(addr)) as u32;
+            // The byte is repeated across wider reads
+            num::cast::<u32, T>(value * 0x0101_0101 & (0xFFFF_FFFF >> (32 - 8 * size_of::<T>()))).unwrap()
+        } else { num::zero() }
+    }
+
+    fn write_gba_ram<T: MemoryValue>(&mut self, is_arm9: bool, addr: u32, value: T) {
+        if self.exmem.gba_arm7_access != is_arm9 {
+            // Only one byte of wider writes makes it onto the 8-bit bus
+            let value = num::cast::<T, u32>(value).unwrap() >> (8 * (addr as usize & (size_of::<T>() - 1)));
+            if let Some(slot2) = &mut self.slot2 { slot2.write_ram(addr, value as u8) }
+        }
+    }
+
+    // Wi-Fi is on a 16-bit bus
+    fn read_wifi<T: MemoryValue>(&mut self, addr: u32) -> T {
+        let value = self.wifi.read(&self.scheduler, addr) as u32;
+        let value = match size_of::<T>() {
+            1 => value >> (8 * (addr & 0x1)) & 0xFF,
+            2 => value,
+            4 => (self.wifi.read(&self.scheduler, addr + 2) as u32) << 16 | value,
+            _ => unreachable!(),
+        };
+        self.check_wifi_interrupt();
+        num::cast::<u32, T>(value).unwrap()
+    }
+
+    fn write_wifi<T: MemoryValue>(&mut self, addr: u32, value: T) {
+        let value = num::cast::<T, u32>(value).unwrap();
+        match size_of::<T>() {
+            1 => warn!("Ignoring 8-bit Wi-Fi Write 0x{:08X} = 0x{:X}", addr, value),
+            2 => self.wifi.write(&mut self.scheduler, addr, value as u16),
+            4 => {
+                self.wifi.write(&mut self.scheduler, addr, value as u16);
+                self.wifi.write(&mut self.scheduler, addr + 2, (value >> 16) as u16);
+            },
+            _ => unreachable!(),
         }
+        self.check_wifi_interrupt();
+    }
+
+    fn gba_access_time<T: MemoryValue>(&self, is_arm9: bool, access_type: AccessType, addr: u32) -> usize {
+        const FIRST_ACCESS_TIMES: [usize; 4] = [10, 8, 6, 18];
+        const SECOND_ACCESS_TIMES: [usize; 2] = [6, 4];
+        let cnt = &self.exmem.gba[is_arm9 as usize];
+        let cycles = if addr >> 24 == 0xA {
+            FIRST_ACCESS_TIMES[cnt.sram_access_time as usize] * size_of::<T>()
+        } else {
+            let n_cycles = FIRST_ACCESS_TIMES[cnt.rom_n_access_time as usize];
+            let s_cycles = SECOND_ACCESS_TIMES[cnt.rom_s_access_time as usize];
+            let first = match access_type { AccessType::N => n_cycles, AccessType::S => s_cycles };
+            if size_of::<T>() == 4 { first + s_cycles } else { first }
+        };
+        // The ARM9 bus runs at twice the speed
+        if is_arm9 { 2 * cycles } else { cycles }
+    }
+
+    // Memory is stored little endian like on the DS, whatever the host is
+    pub(super) fn read_mem<T: MemoryValue>(mem: &[u8], addr: u32) -> T {
+        T::from_le_slice(&mem[addr as usize..addr as usize + size_of::<T>()])
+    }
+
+    pub(super) fn write_mem<T: MemoryValue>(mem: &mut [u8], addr: u32, value: T) {
+        value.write_le_slice(&mut mem[addr as usize..addr as usize + size_of::<T>()]);
     }
 
     fn read_from_bytes<T: MemoryValue, F: Fn(&D, u32) -> u8, D>(device: &D, read_fn: &F, addr: u32) -> T {
@@ -91,6 +193,50 @@ impl HW {
         }
     }
 
+    // Rebuilt whenever TCM, WRAMCNT or a loaded state changes what's mapped
+    pub fn remap_pages(&mut self) {
+        for page in 0..HW::PAGE_COUNT {
+            let addr = (page << HW::PAGE_SHIFT) as u32;
+            self.arm7_pages[page] = self.arm7_page(addr);
+            self.arm9_pages[page] = self.arm9_page(addr);
+        }
+    }
+
+    fn read_page<T: MemoryValue>(&self, page: Option<&Page>, addr: u32) -> Option<T> {
+        let offset = addr & HW::PAGE_MASK;
+        Some(match *page? {
+            Page::Slow => return None,
+            Page::ITCM(base) => HW::read_mem(&self.itcm, base | offset),
+            Page::DTCM(base) => HW::read_mem(&self.dtcm, base | offset),
+            Page::MainMem(base) => HW::read_mem(&self.main_mem, base | offset),
+            Page::SharedWRAM(base) => HW::read_mem(&self.shared_wram, base | offset),
+            Page::IWRAM(base) => HW::read_mem(&self.iwram, base | offset),
+        })
+    }
+
+    // Returns false if the access has to go through the slow path
+    fn write_page<T: MemoryValue>(&mut self, page: Option<Page>, addr: u32, value: T) -> bool {
+        let offset = addr & HW::PAGE_MASK;
+        match page {
+            None | Some(Page::Slow) => return false,
+            Some(Page::ITCM(base)) => HW::write_mem(&mut self.itcm, base | offset, value),
+            Some(Page::DTCM(base)) => HW::write_mem(&mut self.dtcm, base | offset, value),
+            Some(Page::MainMem(base)) => HW::write_mem(&mut self.main_mem, base | offset, value),
+            Some(Page::SharedWRAM(base)) => HW::write_mem(&mut self.shared_wram, base | offset, value),
+            Some(Page::IWRAM(base)) => HW::write_mem(&mut self.iwram, base | offset, value),
+        }
+        true
+    }
+
+    pub(super) fn ram_region_mut(&mut self, region: RAMRegion) -> &mut Vec<u8> {
+        match region {
+            RAMRegion::MainMem => &mut self.main_mem,
+            RAMRegion::SharedWRAM => &mut self.shared_wram,
+            RAMRegion::IWRAM => &mut self.iwram,
+            RAMRegion::VRAM(bank) => self.gpu.vram.bank_mut(bank),
+        }
+    }
+
     pub fn read_byte_from_value<T: MemoryValue>(value: &T, byte: usize) -> u8 {
         let mask = FromPrimitive::from_u8(0xFF).unwrap();
         num::cast::<T, u8>((*value >> (byte * 8)) & mask).unwrap()
@@ -103,12 +249,48 @@ impl HW {
     }
 }
 
-pub trait MemoryValue: Unsigned + PrimInt + NumCast + FromPrimitive + std::fmt::UpperHex + BitOrAssign {}
+pub trait MemoryValue: Unsigned + PrimInt + NumCast + FromPrimitive + std::fmt::UpperHex + BitOrAssign {
+    // The slice must be exactly the size of the value
+    fn from_le_slice(bytes: &[u8]) -> Self;
+    fn write_le_slice(self, bytes: &mut [u8]);
+}
+
+macro_rules! memory_value {
+    ($($ty:ty),*) => {
+        $(impl MemoryValue for $ty {
+            fn from_le_slice(bytes: &[u8]) -> Self {
+                <$ty>::from_le_bytes(bytes.try_into().unwrap())
+            }
+
+            fn write_le_slice(self, bytes: &mut [u8]) {
+                bytes.copy_from_slice(&self.to_le_bytes());
+            }
+        })*
+    };
+}
+
+memory_value!(u8, u16, u32, u64);
+
+// RAM backing a page and the offset of the page in it, so most accesses skip matching on the address.
+// Slow pages go through the region handlers, which covers IO and anything else with side effects.
+#[derive(Clone, Copy)]
+pub enum Page {
+    Slow,
+    ITCM(u32),
+    DTCM(u32),
+    MainMem(u32),
+    SharedWRAM(u32),
+    IWRAM(u32),
+}
 
-impl MemoryValue for u8 {}
-impl MemoryValue for u16 {}
-impl MemoryValue for u32 {}
-impl MemoryValue for u64 {}
+// Memory that can be accessed directly without side effects
+#[derive(Clone, Copy, PartialEq)]
+pub enum RAMRegion {
+    MainMem,
+    SharedWRAM,
+    IWRAM,
+    VRAM(usize),
+}
 
 #[derive(Clone, Copy)]
 pub enum AccessType {
@@ -116,6 +298,8 @@ pub enum AccessType {
     S,
 }
 
+savestate_enum!(AccessType { N, S });
+
 pub trait IORegister {
     fn read(&self, byte: usize) -> u8;
     fn write(&mut self, scheduler: &mut Scheduler, byte: usize, value: u8);
@@ -129,6 +313,8 @@ pub struct EXMEM {
     main_mem_arm7_priority: bool,
 }
 
+savestate!(EXMEM { gba, gba_arm7_access, nds_arm7_access, main_mem_interface_mode, main_mem_arm7_priority });
+
 impl EXMEM {
     pub fn new() -> Self {
         EXMEM {
@@ -143,10 +329,11 @@ impl EXMEM {
     pub fn read_arm7(&self) -> u8 { (self.gba_arm7_access as u8) << 7 | self.gba[0].read() }
     pub fn read_arm9(&self) -> u8 { (self.gba_arm7_access as u8) << 7 | self.gba[1].read() }
     pub fn read_common(&self) -> u8 {
-        // TODO: Is bit 5 set or clear?
-        (self.main_mem_arm7_priority as u8) << 7 | (self.main_mem_interface_mode as u8) << 6 |
+        // Bit 13 is always set
+        (self.main_mem_arm7_priority as u8) << 7 | (self.main_mem_interface_mode as u8) << 6 | 1 << 5 |
         (self.nds_arm7_access as u8) << 3
     }
+    pub fn nds_arm7_access(&self) -> bool { self.nds_arm7_access }
     pub fn write_arm7(&mut self, value: u8) { self.gba[0].write(value) }
     pub fn write_arm9(&mut self, value: u8) { self.gba_arm7_access = value >> 7 & 0x1 != 0; self.gba[1].write(value) }
     pub fn write_common(&mut self, value: u8) {
@@ -163,6 +350,8 @@ pub struct ExMemGBA {
     phi: u8,
 }
 
+savestate!(ExMemGBA { sram_access_time, rom_n_access_time, rom_s_access_time, phi });
+
 impl ExMemGBA {
     pub fn new() -> Self {
         ExMemGBA {
@@ -194,6 +383,8 @@ pub struct WRAMCNT {
     arm9_mask: u32,
 }
 
+savestate!(WRAMCNT { value, arm7_offset, arm7_mask, arm9_offset, arm9_mask });
+
 impl WRAMCNT {
     pub fn new(value: u8) -> Self {
         let mut wramcnt = WRAMCNT {
@@ -211,6 +402,7 @@ impl WRAMCNT {
     fn changed(&mut self) {
         match self.value {
             0 => {
+                self.arm7_offset = 0;
                 self.arm7_mask = 0;
                 self.arm9_offset = 0;
                 self.arm9_mask = HW::SHARED_WRAM_SIZE as u32 - 1;
@@ -230,11 +422,27 @@ impl WRAMCNT {
             3 => {
                 self.arm7_offset = 0;
                 self.arm7_mask = HW::SHARED_WRAM_SIZE as u32 - 1;
+                self.arm9_offset = 0;
                 self.arm9_mask = 0;
             },
             _ => unreachable!(),
         }
     }
+
+    // Offset into shared WRAM and bytes until the next mirror, or None if the CPU has none of it
+    pub fn arm7_mapping(&self, addr: u32) -> Option<(u32, usize)> {
+        WRAMCNT::mapping(self.arm7_offset, self.arm7_mask, addr)
+    }
+
+    pub fn arm9_mapping(&self, addr: u32) -> Option<(u32, usize)> {
+        WRAMCNT::mapping(self.arm9_offset, self.arm9_mask, addr)
+    }
+
+    fn mapping(offset: u32, mask: u32, addr: u32) -> Option<(u32, usize)> {
+        if mask == 0 { return None }
+        let addr = addr & mask;
+        Some((offset + addr, (mask - addr) as usize + 1))
+    }
 }
 
 impl IORegister for WRAMCNT {
@@ -255,6 +463,8 @@ pub struct POWCNT2 {
     enable_wifi: bool,
 }
 
+savestate!(POWCNT2 { enable_sound, enable_wifi });
+
 impl POWCNT2 {
     pub fn new() -> Self {
         POWCNT2 {
@@ -294,6 +504,8 @@ enum HaltMode {
     Sleep = 3,
 }
 
+savestate_enum!(HaltMode { None, GBA, Halt, Sleep });
+
 impl HaltMode {
     fn from_bits(value: u8) -> Self {
         match value {
@@ -310,6 +522,8 @@ pub struct HALTCNT {
     mode: HaltMode,
 }
 
+savestate!(HALTCNT { mode });
+
 impl HALTCNT {
     pub fn new() -> Self {
         HALTCNT {
@@ -318,7 +532,9 @@ impl HALTCNT {
     }
 
     pub fn unhalt(&mut self) { self.mode = HaltMode::None; }
-    pub fn halted(&self) -> bool { self.mode == HaltMode::Halt }
+    // Sleep also stops most of the hardware, which isn't emulated, so it wakes up on interrupts the same as a halt
+    pub fn halted(&self) -> bool { self.mode == HaltMode::Halt || self.mode == HaltMode::Sleep }
+    pub fn gba_requested(&self) -> bool { self.mode == HaltMode::GBA }
 }
 
 impl IORegister for HALTCNT {
@@ -327,7 +543,6 @@ impl IORegister for HALTCNT {
     fn write(&mut self, _scheduler: &mut Scheduler, byte: usize, value: u8) {
         assert_eq!(byte, 0);
         self.mode = HaltMode::from_bits(value >> 6);
-        assert!(self.mode != HaltMode::GBA && self.mode != HaltMode::Sleep); // TODO: Implement
     }
     
 }
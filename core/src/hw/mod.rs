@@ -10,106 +10,207 @@ mod ipc;
 mod math;
 mod spi;
 mod cartridge;
+mod rtc;
+mod slot2;
+mod wifi;
+mod gba;
+mod registers;
+mod dsi;
 
 use std::convert::TryInto;
-use std::path::PathBuf;
 
 pub use mem::{AccessType, MemoryValue};
-use mem::{CP15, EXMEM, HALTCNT, POWCNT2, WRAMCNT};
+use mem::{CP15, EXMEM, HALTCNT, Page, POWCNT2, WRAMCNT};
 use scheduler::Scheduler;
-pub use gpu::{GPU, EngineA, EngineB};
+pub use scheduler::{EventKind, EventStats, PendingEvent};
+use crate::fault::{Fault, Faults};
+use crate::notifications::Notifier;
+pub use gpu::{GPU, EngineA, EngineB, Frame, CapturedPolygon, CapturedVertex, PolygonMode, Texture, TextureFormat,
+    TexturePack, debug::{OAMEntry, OBJMode}};
+#[cfg(feature = "host")]
+pub use gpu::TextureDir;
 use spu::SPU;
+pub use spu::{AudioSink, ChannelFormat, ChannelState, SampleQueue};
 use keypad::Keypad;
 pub use keypad::Key;
-use interrupt_controller::{InterruptController, InterruptRequest};
+use interrupt_controller::{DSiInterrupts, InterruptController, InterruptEnable, InterruptRequest};
 use dma::DMAController;
 use timers::Timers;
 use ipc::IPC;
 use math::{Div, Sqrt};
 use spi::SPI;
 use cartridge::Cartridge;
+pub use cartridge::{MemoryStorage, SaveStorage, SdImage, Header, Region, UnitCode};
+#[cfg(feature = "host")]
+pub use cartridge::FileStorage;
+use rtc::RTC;
+pub use rtc::RtcMode;
+use slot2::Slot2Device;
+pub use slot2::{Slot2, GuitarKey, OpenBus};
+use wifi::WiFi;
+pub use wifi::{LocalLink, NoLink, WiFiFrame, WiFiLink};
+#[cfg(feature = "host")]
+pub use wifi::UdpLink;
+#[cfg(feature = "bridge")]
+pub use wifi::BridgeLink;
+use gba::GBA;
+pub use registers::{Register, RegisterField};
+use dsi::{NDMA, NWRAM, SCFG, SDMMC};
+pub use dsi::ConsoleModel;
+pub(crate) use dsi::{migrate_mem_v1, migrate_mem_v2, migrate_mem_v3};
+pub(crate) use interrupt_controller::migrate_io_v2;
+
+// The BIOSes and firmware dumped from a console
+pub struct SystemFiles {
+    pub bios7: Vec<u8>,
+    pub bios9: Vec<u8>,
+    pub firmware: Option<Vec<u8>>,
+}
 
 pub struct HW {
+    model: ConsoleModel,
     // Memory
     pub cp15: CP15,
     bios7: Vec<u8>,
     bios9: Vec<u8>,
     cartridge: Cartridge,
+    slot2: Option<Box<dyn Slot2Device>>,
+    slot2_open_bus: OpenBus,
     itcm: Vec<u8>,
     dtcm: Vec<u8>,
     main_mem: Vec<u8>,
     iwram: Vec<u8>,
     shared_wram: Vec<u8>,
+    nwram: NWRAM,
+    arm7_pages: Vec<Page>,
+    arm9_pages: Vec<Page>,
     // Devices
     pub gpu: GPU,
     spu: SPU,
     keypad: Keypad,
     interrupts: [InterruptController; 2],
-    in_dma: bool,
+    dsi_interrupts: DSiInterrupts,
     dmas: [DMAController; 2],
     dma_fill: [u32; 4],
     timers: [Timers; 2],
     ipc: IPC,
     spi: SPI,
+    rtc: RTC,
+    wifi: WiFi,
+    gba: GBA,
+    sdmmc: SDMMC,
+    ndmas: [NDMA; 2],
     // Registers
     wramcnt: WRAMCNT,
+    scfg: SCFG,
     powcnt2: POWCNT2,
     pub haltcnt: HALTCNT,
     postflg7: u8,
     postflg9: u8,
+    // ARM7 BIOS code past this address can't read the BIOS before it
+    biosprot: u16,
     exmem: EXMEM,
     // Math
     div: Div,
     sqrt: Sqrt,
     // Misc
     scheduler: Scheduler,
+    pub notifier: Notifier,
+    pub faults: Faults,
 }
 
+// The BIOSes and the ROM aren't part of the state
+savestate_sections!(HW {
+    b"MEM " 4 => { cp15, itcm, dtcm, main_mem, iwram, shared_wram, wramcnt, exmem, model, scfg, nwram, dsi_interrupts,
+        sdmmc, ndmas },
+    b"CART" 1 => { cartridge, slot2 },
+    b"GPU " 2 => { gpu },
+    b"SPU " 1 => { spu },
+    b"DMA " 1 => { dmas, dma_fill },
+    b"IO  " 3 => { keypad, interrupts, timers, ipc, powcnt2, haltcnt, postflg7, postflg9, div, sqrt, biosprot,
+        interrupts[0].sources, interrupts[0].delayed, interrupts[1].sources, interrupts[1].delayed },
+    b"SPI " 1 => { spi },
+    b"RTC " 1 => { rtc },
+    b"WIFI" 1 => { wifi },
+    b"GBA " 1 => { gba },
+    b"SCHD" 1 => { scheduler },
+});
+
 impl HW {
     const ITCM_SIZE: usize = 0x8000;
     const DTCM_SIZE: usize = 0x4000;
     const MAIN_MEM_SIZE: usize = 0x40_0000;
+    const DSI_MAIN_MEM_SIZE: usize = 0x100_0000;
     const IWRAM_SIZE: usize = 0x1_0000;
     const SHARED_WRAM_SIZE: usize = 0x8000;
+    pub const AUDIO_CHANNELS: usize = SPU::NUM_CHANNELS;
 
-    pub fn new(bios7: Vec<u8>, bios9: Vec<u8>, firmware: Vec<u8>, rom: Vec<u8>, save_file: PathBuf, direct_boot: bool) -> Self {
+    pub fn new(files: SystemFiles, rom: Vec<u8>, save_storage: Box<dyn SaveStorage>, audio_sink: Box<dyn AudioSink>,
+        direct_boot: bool, model: ConsoleModel) -> Result<Self, Fault> {
+        let SystemFiles { bios7, bios9, firmware } = files;
         let mut scheduler = Scheduler::new();
-        let hw = HW {
+        let notifier = Notifier::default();
+        let faults = Faults::default();
+        let cartridge = Cartridge::new(rom, save_storage, &bios7, direct_boot, notifier.clone())?;
+        // DS titles run with SCFG locked, 4MB of main memory and no DSi WRAM, like the DSi menu starts them
+        let dsi_mode = model == ConsoleModel::DSi && cartridge.header().unit_code != UnitCode::NDS;
+        let mode = if dsi_mode { ConsoleModel::DSi } else { ConsoleModel::DS };
+        let mut hw = HW {
+            model,
             // Memory
-            cp15: CP15::new(),
+            cp15: CP15::new(faults.clone()),
             bios7,
             bios9,
-            cartridge: Cartridge::new(rom, save_file),
+            cartridge,
+            slot2: None,
+            slot2_open_bus: OpenBus::Pattern,
             itcm: vec![0; HW::ITCM_SIZE],
             dtcm: vec![0; HW::DTCM_SIZE],
-            main_mem: vec![0; HW::MAIN_MEM_SIZE],
+            main_mem: vec![0; if model == ConsoleModel::DSi { HW::DSI_MAIN_MEM_SIZE } else { HW::MAIN_MEM_SIZE }],
             iwram: vec![0; HW::IWRAM_SIZE],
             shared_wram: vec![0; HW::SHARED_WRAM_SIZE],
+            nwram: NWRAM::new(mode),
+            arm7_pages: vec![Page::Slow; HW::PAGE_COUNT],
+            arm9_pages: vec![Page::Slow; HW::PAGE_COUNT],
             // Devices
-            gpu: GPU::new(&mut scheduler),
-            spu: SPU::new(&mut scheduler),
+            gpu: GPU::new(&mut scheduler, faults.clone()),
+            spu: SPU::new(&mut scheduler, audio_sink, faults.clone()),
             keypad: Keypad::new(),
-            interrupts: [InterruptController::new(), InterruptController::new()],
-            in_dma: false,
+            interrupts: [
+                InterruptController::new(HW::interrupt_sources(model, false, false)),
+                InterruptController::new(HW::interrupt_sources(model, false, true)),
+            ],
+            dsi_interrupts: DSiInterrupts::new(),
             dmas: [DMAController::new(false), DMAController::new(true)],
             dma_fill: [0; 4],
             timers: [Timers::new(false), Timers::new(true)],
             ipc: IPC::new(),
-            spi: SPI::new(firmware),
+            spi: SPI::new(firmware, faults.clone()),
+            rtc: RTC::new(&mut scheduler),
+            wifi: WiFi::new(),
+            gba: GBA::new(),
+            sdmmc: SDMMC::new(),
+            ndmas: [NDMA::new(), NDMA::new()],
             // Registesr
             wramcnt: WRAMCNT::new(3),
+            scfg: SCFG::new(mode),
             powcnt2: POWCNT2::new(),
             haltcnt: HALTCNT::new(),
             postflg7: if direct_boot { 0x1 } else { 0x0 },
             postflg9: if direct_boot { 0x1 } else { 0x0 },
+            // Set by the firmware
+            biosprot: if direct_boot { 0x1204 } else { 0x0000 },
             exmem: EXMEM::new(),
             // Math
             div: Div::new(),
             sqrt: Sqrt::new(),
             // Misc
             scheduler,
+            notifier,
+            faults,
         };
-        if direct_boot { hw.init_mem() } else { hw }
+        hw.remap_pages();
+        Ok(if direct_boot { hw.init_mem() } else { hw })
     }
 
     pub fn clock(&mut self, arm7_cycles: usize) {
@@ -117,22 +218,141 @@ impl HW {
         self.gpu.engine3d.check_interrupts(&mut self.interrupts[1].request);
     }
 
+    fn interrupt_sources(model: ConsoleModel, gba_mode: bool, is_arm9: bool) -> InterruptEnable {
+        let dsi_sources = if model == ConsoleModel::DSi { InterruptEnable::DSI_SOURCES } else { InterruptEnable::empty() };
+        match (gba_mode, is_arm9) {
+            (true, false) => InterruptEnable::GBA_SOURCES,
+            (_, false) => InterruptEnable::ARM7_SOURCES | dsi_sources,
+            (_, true) => InterruptEnable::ARM9_SOURCES | dsi_sources,
+        }
+    }
+
+    // States from before the sources were saved leave them empty
+    pub(crate) fn restore_interrupt_sources(&mut self) {
+        for (i, interrupts) in self.interrupts.iter_mut().enumerate() {
+            if interrupts.sources.is_empty() { interrupts.sources = HW::interrupt_sources(self.model, self.gba.enabled, i == 1) }
+        }
+    }
+
     pub fn arm7_interrupts_requested(&mut self) -> bool {
-        if self.keypad.interrupt_requested() { self.interrupts[0].request |= InterruptRequest::KEYPAD }
-        self.interrupts[0].interrupts_requested()
+        if self.keypad.interrupt_requested(false) { self.interrupts[0].request |= InterruptRequest::KEYPAD }
+        // IE2 and IF2 share IME with the rest of the ARM7's interrupts
+        let dsi_requested = self.interrupts[0].master_enable.bits() != 0 && self.dsi_interrupts.interrupts_pending();
+        self.interrupts[0].interrupts_requested() || dsi_requested
     }
 
     pub fn arm9_interrupts_requested(&mut self) -> bool {
-        if self.keypad.interrupt_requested() { self.interrupts[1].request |= InterruptRequest::KEYPAD }
+        if self.keypad.interrupt_requested(true) { self.interrupts[1].request |= InterruptRequest::KEYPAD }
         self.interrupts[1].interrupts_requested()
     }
 
+    // Halted CPUs stay halted until an enabled interrupt is requested, so these also wake them up
+    pub fn arm7_halted(&mut self) -> bool {
+        if !self.haltcnt.halted() { return false }
+        if self.keypad.interrupt_requested(false) { self.interrupts[0].request |= InterruptRequest::KEYPAD }
+        if self.interrupts[0].interrupts_pending() || self.dsi_interrupts.interrupts_pending() { self.haltcnt.unhalt() }
+        self.haltcnt.halted()
+    }
+
+    pub fn arm9_halted(&mut self) -> bool {
+        if !self.cp15.arm9_halted { return false }
+        if self.keypad.interrupt_requested(true) { self.interrupts[1].request |= InterruptRequest::KEYPAD }
+        if self.interrupts[1].interrupts_pending() { self.cp15.arm9_halted = false }
+        self.cp15.arm9_halted
+    }
+
     pub fn rendered_frame(&mut self) -> bool {
         self.gpu.rendered_frame()
     }
 
-    pub fn save_backup(&mut self) {
-        self.cartridge.save_backup();
+    pub fn feed_mic_samples(&mut self, samples: &[i16], sample_rate: usize) {
+        self.spi.feed_mic_samples(samples, sample_rate);
+    }
+
+    pub fn set_mic_blowing(&mut self, blowing: bool) {
+        self.spi.set_mic_blowing(blowing);
+    }
+
+    pub fn mic_blowing(&self) -> bool {
+        self.spi.mic_blowing()
+    }
+
+    pub fn set_rtc_mode(&mut self, mode: RtcMode) {
+        self.rtc.set_mode(mode);
+    }
+
+    pub fn header(&self) -> &Header {
+        self.cartridge.header()
+    }
+
+    pub fn rom(&self) -> &[u8] {
+        self.cartridge.rom()
+    }
+
+    pub fn main_mem(&self) -> &[u8] {
+        &self.main_mem
+    }
+
+    pub fn dtcm(&self) -> &[u8] {
+        &self.dtcm
+    }
+
+    // A DSi has an SD card slot of its own, a DS can only get to one through the cartridge
+    pub fn set_sd_image(&mut self, image: Option<Box<dyn SdImage>>) {
+        if self.model == ConsoleModel::DSi { self.set_sdmmc_image(0, image) } else { self.cartridge.set_sd_image(image) }
+    }
+
+    pub fn set_nand_image(&mut self, image: Option<Box<dyn SdImage>>) {
+        self.set_sdmmc_image(1, image);
+    }
+
+    pub fn powered_off(&self) -> bool {
+        self.spi.powered_off()
+    }
+
+    pub fn set_battery_low(&mut self, low: bool) {
+        self.spi.set_battery_low(low);
+    }
+
+    pub fn set_external_power(&mut self, connected: bool) {
+        self.spi.set_external_power(connected);
+    }
+
+    pub fn battery_low(&self) -> bool {
+        self.spi.battery_low()
+    }
+
+    pub fn external_power(&self) -> bool {
+        self.spi.external_power()
+    }
+
+    pub fn set_backlight_level(&mut self, level: u8) {
+        self.spi.set_backlight_level(level);
+    }
+
+    pub fn backlight(&self) -> [Option<u8>; 2] {
+        self.spi.backlight()
+    }
+
+    pub fn update_backup(&mut self) -> bool {
+        let cartridge_modified = self.cartridge.update_backup();
+        let slot2_modified = self.slot2.as_mut().is_some_and(|slot2| slot2.update_save());
+        cartridge_modified || slot2_modified
+    }
+
+    pub fn flush_backup(&mut self) {
+        self.cartridge.flush_backup();
+        if let Some(slot2) = &mut self.slot2 { slot2.flush_save() }
+    }
+
+    pub fn set_slot2(&mut self, slot2: Slot2) {
+        // Flush the old device's save first in case the new one loads the same file
+        self.slot2 = None;
+        self.slot2 = <dyn Slot2Device>::new(slot2, self.notifier.clone());
+    }
+
+    pub fn set_slot2_open_bus(&mut self, open_bus: OpenBus) {
+        self.slot2_open_bus = open_bus;
     }
 
     pub fn press_key(&mut self, key: Key) {
@@ -143,6 +363,18 @@ impl HW {
         self.keypad.release_key(key);
     }
 
+    pub fn set_guitar_key(&mut self, key: GuitarKey, pressed: bool) {
+        if let Some(slot2) = &mut self.slot2 { slot2.set_guitar_key(key, pressed) }
+    }
+
+    pub fn guitar_keys(&self) -> u8 {
+        self.slot2.as_ref().map_or(0, |slot2| slot2.guitar_keys())
+    }
+
+    pub fn set_guitar_keys(&mut self, keys: u8) {
+        if let Some(slot2) = &mut self.slot2 { slot2.set_guitar_keys(keys) }
+    }
+
     pub fn press_screen(&mut self, x: usize, y: usize) {
         self.keypad.press_screen();
         self.spi.press_screen(x, y)
@@ -153,6 +385,18 @@ impl HW {
         self.spi.release_screen();
     }
 
+    pub fn held_keys(&self) -> u16 {
+        self.keypad.held_keys()
+    }
+
+    pub fn set_held_keys(&mut self, keys: u16) {
+        self.keypad.set_held_keys(keys);
+    }
+
+    pub fn touch(&self) -> Option<(usize, usize)> {
+        if self.keypad.screen_pressed() { Some(self.spi.touch_position()) } else { None }
+    }
+
     pub fn render_palettes(&self, extended: bool, slot: usize, palette: usize,
         engine: Engine, graphics_type: GraphicsType) -> (Vec<u16>, usize, usize) {
         if extended {
@@ -188,33 +432,66 @@ impl HW {
     }
 
     pub fn render_tiles(&self, engine: Engine, graphics_type: GraphicsType, extended: bool, bitmap: bool, bpp8: bool,
-        slot: usize, palette: usize, offset: usize) -> (Vec<u16>, usize, usize) {
+        slot: usize, palette: usize, base: usize) -> (Vec<u16>, usize, usize) {
         let is_bg = graphics_type == GraphicsType::BG;
         match engine {
-            Engine::A => self.gpu.engine_a.render_tiles(&self.gpu.vram, is_bg, extended, bitmap, bpp8, slot, palette, offset),
-            Engine::B => self.gpu.engine_b.render_tiles(&self.gpu.vram, is_bg, extended, bitmap, bpp8, slot, palette, offset),
+            Engine::A => self.gpu.engine_a.render_tiles(&self.gpu.vram, is_bg, extended, bitmap, bpp8, slot, palette, base),
+            Engine::B => self.gpu.engine_b.render_tiles(&self.gpu.vram, is_bg, extended, bitmap, bpp8, slot, palette, base),
+        }
+    }
+
+    pub fn oam_entries(&self, engine: Engine) -> Vec<OAMEntry> {
+        match engine {
+            Engine::A => self.gpu.engine_a.oam_entries(&self.gpu.vram),
+            Engine::B => self.gpu.engine_b.oam_entries(&self.gpu.vram),
         }
     }
 
+    pub fn pending_events(&self) -> Vec<PendingEvent> {
+        self.scheduler.pending_events()
+    }
+
+    pub fn event_stats(&self) -> Vec<(EventKind, EventStats)> {
+        self.scheduler.event_stats()
+    }
+
+    pub fn reset_event_stats(&mut self) {
+        self.scheduler.reset_event_stats()
+    }
+
+    pub fn set_profiling(&mut self, profiling: bool) {
+        self.scheduler.profiling = profiling;
+    }
+
     pub fn render_bank(&self, ignore_alpha: bool, bank: usize) -> (Vec<u16>, usize, usize) {
         self.gpu.vram.render_bank(ignore_alpha, bank)
     }
 
+    // Sets up everything the firmware would have by the time it starts the game
     pub fn init_mem(mut self) -> Self {
-        let addr = 0x027F_FE00 & (HW::MAIN_MEM_SIZE - 1);
+        self.cp15.direct_boot();
+        // The TCMs just moved
+        self.remap_pages();
+        // Boot information goes at the end of main memory, so DS titles find it mirrored at 0x027FFxxx
+        let addr = (0x02FF_FE00 & self.main_mem_mask()) as usize;
         self.main_mem[addr..addr + 0x170].copy_from_slice(&self.cartridge.rom()[..0x170]);
         
-        for addr in [0x027FF800, 0x027FFC00].iter() {
+        for addr in [0x02FFF800, 0x02FFFC00].iter() {
             self.arm9_write(addr + 0x0, self.cartridge.chip_id());
             self.arm9_write(addr + 0x4, self.cartridge.chip_id());
             self.arm9_write(addr + 0x8, u16::from_le_bytes(self.cartridge.rom()[0x15E..=0x15F].try_into().unwrap()));
             self.arm9_write(addr + 0xA, u16::from_le_bytes(self.cartridge.rom()[0x6C..=0x6D].try_into().unwrap()));
         }
 
-        self.arm9_write(0x027FF850, 0x5835u16);
-        self.arm9_write(0x027FFC10, 0x5835u16);
-        self.arm9_write(0x027FFC30, 0xFFFFu16);
-        self.arm9_write(0x027FFC40, 0x0001u16);
+        self.arm9_write(0x02FFF850, 0x5835u16);
+        self.arm9_write(0x02FFFC10, 0x5835u16);
+        self.arm9_write(0x02FFFC30, 0xFFFFu16);
+        self.arm9_write(0x02FFFC40, 0x0001u16); // Booted from a cartridge
+        self.arm9_write(0x02FFF868, (self.spi.user_settings_addr() / 8) as u16);
+
+        let addr = (0x02FF_FC80 & self.main_mem_mask()) as usize;
+        let user_settings = self.spi.user_settings();
+        self.main_mem[addr..addr + user_settings.len()].copy_from_slice(user_settings);
         self
     }
 }
@@ -0,0 +1,334 @@
+use super::HW;
+
+// Register descriptions for debugging tools. Only registers that can be read without side effects are listed.
+pub struct Register {
+    pub name: String,
+    pub addr: u32,
+    pub size: usize,
+    pub value: u64,
+    pub fields: Vec<RegisterField>,
+}
+
+pub struct RegisterField {
+    pub name: &'static str,
+    pub shift: u32,
+    pub len: u32,
+    pub value: u64,
+    // Name of the value for fields that select between options
+    pub meaning: Option<&'static str>,
+}
+
+struct FieldDef {
+    name: &'static str,
+    shift: u32,
+    len: u32,
+    values: &'static [&'static str],
+}
+
+const fn bit(name: &'static str, shift: u32) -> FieldDef {
+    FieldDef { name, shift, len: 1, values: &[] }
+}
+
+const fn bits(name: &'static str, shift: u32, len: u32) -> FieldDef {
+    FieldDef { name, shift, len, values: &[] }
+}
+
+const fn named(name: &'static str, shift: u32, len: u32, values: &'static [&'static str]) -> FieldDef {
+    FieldDef { name, shift, len, values }
+}
+
+const DISPCNT: &[FieldDef] = &[
+    bits("BG Mode", 0, 3), named("BG0", 3, 1, &["2D", "3D"]), named("Tile OBJ Mapping", 4, 1, &["2D", "1D"]),
+    named("Bitmap OBJ 2D Width", 5, 1, &["128", "256"]), named("Bitmap OBJ Mapping", 6, 1, &["2D", "1D"]),
+    bit("Forced Blank", 7), bit("BG0 Enable", 8), bit("BG1 Enable", 9), bit("BG2 Enable", 10), bit("BG3 Enable", 11),
+    bit("OBJ Enable", 12), bit("Window 0 Enable", 13), bit("Window 1 Enable", 14), bit("OBJ Window Enable", 15),
+    named("Display Mode", 16, 2, &["Off", "Graphics", "VRAM", "Main Memory FIFO"]),
+    named("VRAM Block", 18, 2, &["A", "B", "C", "D"]), bits("Tile OBJ 1D Boundary", 20, 2),
+    bit("Bitmap OBJ 1D Boundary", 22), bit("OBJ During HBlank", 23), bits("Char Base", 24, 3),
+    bits("Screen Base", 27, 3), bit("BG Extended Palettes", 30), bit("OBJ Extended Palettes", 31),
+];
+
+const DISPSTAT: &[FieldDef] = &[
+    bit("VBlank", 0), bit("HBlank", 1), bit("VCount Match", 2), bit("VBlank IRQ", 3), bit("HBlank IRQ", 4),
+    bit("VCount Match IRQ", 5), bit("VCount Setting Bit 8", 7), bits("VCount Setting", 8, 8),
+];
+
+const VCOUNT: &[FieldDef] = &[bits("Line", 0, 9)];
+
+const BGCNT: &[FieldDef] = &[
+    bits("Priority", 0, 2), bits("Char Base", 2, 4), bit("Mosaic", 6), named("Colors", 7, 1, &["16/16", "256/1"]),
+    bits("Screen Base", 8, 5), bit("Ext Palette Slot / Wraparound", 13), bits("Screen Size", 14, 2),
+];
+
+const BGOFS: &[FieldDef] = &[bits("Offset", 0, 9)];
+
+const DISP3DCNT: &[FieldDef] = &[
+    bit("Texture Mapping", 0), named("Shading", 1, 1, &["Toon", "Highlight"]), bit("Alpha Test", 2),
+    bit("Alpha Blending", 3), bit("Anti-Aliasing", 4), bit("Edge Marking", 5),
+    named("Fog Mode", 6, 1, &["Color and Alpha", "Alpha"]), bit("Fog", 7), bits("Fog Shift", 8, 4),
+    bit("Color Buffer Underflow", 12), bit("RAM Overflow", 13), named("Rear Plane", 14, 1, &["Blank", "Bitmap"]),
+];
+
+const DISPCAPCNT: &[FieldDef] = &[
+    bits("EVA", 0, 5), bits("EVB", 8, 5), named("VRAM Write Block", 16, 2, &["A", "B", "C", "D"]),
+    named("VRAM Write Offset", 18, 2, &["0x00000", "0x08000", "0x10000", "0x18000"]),
+    named("Capture Size", 20, 2, &["128x128", "256x64", "256x128", "256x192"]),
+    named("Source A", 24, 1, &["Graphics", "3D"]), named("Source B", 25, 1, &["VRAM", "Main Memory FIFO"]),
+    named("VRAM Read Offset", 26, 2, &["0x00000", "0x08000", "0x10000", "0x18000"]),
+    named("Capture Source", 29, 2, &["A", "B", "A + B", "A + B"]), bit("Capture Enable", 31),
+];
+
+const MASTER_BRIGHT: &[FieldDef] = &[bits("Factor", 0, 5), named("Mode", 14, 2, &["Off", "Up", "Down", "Reserved"])];
+
+const GXSTAT: &[FieldDef] = &[
+    bit("Box/Pos/Vec Test Busy", 0), bit("Box Test Result", 1), bits("Position Stack Level", 8, 5),
+    bit("Projection Stack Level", 13), bit("Matrix Stack Busy", 14), bit("Matrix Stack Error", 15),
+    bits("FIFO Entries", 16, 9), bit("FIFO Less Than Half", 25), bit("FIFO Empty", 26), bit("Geometry Busy", 27),
+    named("FIFO IRQ", 30, 2, &["Never", "Less Than Half", "Empty", "Reserved"]),
+];
+
+const DMACNT_ARM9: &[FieldDef] = &[
+    bits("Word Count", 0, 21),
+    named("Dest Control", 21, 2, &["Increment", "Decrement", "Fixed", "Increment/Reload"]),
+    named("Source Control", 23, 2, &["Increment", "Decrement", "Fixed", "Prohibited"]), bit("Repeat", 25),
+    named("Transfer Type", 26, 1, &["16-bit", "32-bit"]),
+    named("Start Timing", 27, 3, &["Immediately", "VBlank", "HBlank", "Display Start", "Main Memory Display",
+        "Game Card", "GBA Cartridge", "Geometry FIFO"]),
+    bit("IRQ", 30), bit("Enable", 31),
+];
+
+const DMACNT_ARM7: &[FieldDef] = &[
+    bits("Word Count", 0, 16),
+    named("Dest Control", 21, 2, &["Increment", "Decrement", "Fixed", "Increment/Reload"]),
+    named("Source Control", 23, 2, &["Increment", "Decrement", "Fixed", "Prohibited"]), bit("Repeat", 25),
+    named("Transfer Type", 26, 1, &["16-bit", "32-bit"]),
+    named("Start Timing", 28, 2, &["Immediately", "VBlank", "Game Card", "Wi-Fi/GBA Cartridge"]),
+    bit("IRQ", 30), bit("Enable", 31),
+];
+
+const TMCNT: &[FieldDef] = &[
+    named("Prescaler", 0, 2, &["1", "64", "256", "1024"]), bit("Count-Up", 2), bit("IRQ", 6), bit("Enable", 7),
+];
+
+// Keys are 0 while pressed
+const KEYINPUT: &[FieldDef] = &[
+    bit("A", 0), bit("B", 1), bit("Select", 2), bit("Start", 3), bit("Right", 4), bit("Left", 5), bit("Up", 6),
+    bit("Down", 7), bit("R", 8), bit("L", 9),
+];
+
+const KEYCNT: &[FieldDef] = &[bits("Keys", 0, 10), bit("IRQ", 14), named("Condition", 15, 1, &["OR", "AND"])];
+
+const EXTKEYIN: &[FieldDef] = &[bit("X", 0), bit("Y", 1), bit("Debug", 3), bit("Pen Up", 6), bit("Hinge Closed", 7)];
+
+const RTC: &[FieldDef] = &[
+    bit("Data", 0), bit("Clock", 1), bit("Select", 2), named("Data Direction", 4, 1, &["Read", "Write"]),
+];
+
+const IPCSYNC: &[FieldDef] = &[bits("Data In", 0, 4), bits("Data Out", 8, 4), bit("IRQ Enable", 14)];
+
+const IPCFIFOCNT: &[FieldDef] = &[
+    bit("Send FIFO Empty", 0), bit("Send FIFO Full", 1), bit("Send FIFO Empty IRQ", 2), bit("Receive FIFO Empty", 8),
+    bit("Receive FIFO Full", 9), bit("Receive FIFO Not Empty IRQ", 10), bit("Error", 14), bit("Enable", 15),
+];
+
+const AUXSPICNT: &[FieldDef] = &[
+    named("Baudrate", 0, 2, &["4 MHz", "2 MHz", "1 MHz", "512 kHz"]), bit("Hold Chip Select", 6), bit("Busy", 7),
+    named("Mode", 13, 1, &["ROM", "Backup"]), bit("Transfer IRQ", 14), bit("Enable", 15),
+];
+
+const ROMCTRL: &[FieldDef] = &[
+    bits("KEY1 Gap 1", 0, 13), bit("KEY2 Data", 13), bit("KEY2 Apply Seed", 15), bits("KEY1 Gap 2", 16, 6),
+    bit("KEY2 Command", 22), bit("Data Ready", 23),
+    named("Block Size", 24, 3, &["0", "0x200", "0x400", "0x800", "0x1000", "0x2000", "0x4000", "4"]),
+    named("Clock Rate", 27, 1, &["6.7 MHz", "4.2 MHz"]), bit("KEY1 Gap Clocks", 28), bit("Reset Released", 29),
+    bit("Write", 30), bit("Busy", 31),
+];
+
+const SPICNT: &[FieldDef] = &[
+    named("Baudrate", 0, 2, &["4 MHz", "2 MHz", "1 MHz", "512 kHz"]), bit("Busy", 7),
+    named("Device", 8, 2, &["Power Management", "Firmware", "Touchscreen", "Reserved"]),
+    named("Transfer Size", 10, 1, &["8-bit", "16-bit"]), bit("Hold Chip Select", 11), bit("IRQ", 14),
+    bit("Enable", 15),
+];
+
+const EXMEMCNT: &[FieldDef] = &[
+    bits("GBA RAM Access Time", 0, 2), bits("GBA ROM 1st Access Time", 2, 2), bit("GBA ROM 2nd Access Time", 4),
+    bits("PHI Output", 5, 2), named("GBA Slot Access", 7, 1, &["ARM9", "ARM7"]),
+    named("NDS Slot Access", 11, 1, &["ARM9", "ARM7"]), bit("Main Memory Interface", 14),
+    named("Main Memory Priority", 15, 1, &["ARM9", "ARM7"]),
+];
+
+const IME: &[FieldDef] = &[bit("Enable", 0)];
+
+const INTERRUPTS: &[FieldDef] = &[
+    bit("VBlank", 0), bit("HBlank", 1), bit("VCount Match", 2), bit("Timer 0", 3), bit("Timer 1", 4),
+    bit("Timer 2", 5), bit("Timer 3", 6), bit("RTC", 7), bit("DMA 0", 8), bit("DMA 1", 9), bit("DMA 2", 10),
+    bit("DMA 3", 11), bit("Keypad", 12), bit("GBA Slot", 13), bit("IPC Sync", 16), bit("IPC Send FIFO Empty", 17),
+    bit("IPC Receive FIFO Not Empty", 18), bit("Game Card Transfer", 19), bit("Game Card IREQ", 20),
+    bit("Geometry FIFO", 21), bit("Screens Unfolding", 22), bit("SPI", 23), bit("Wi-Fi", 24),
+];
+
+const VRAMCNT: &[FieldDef] = &[bits("MST", 0, 3), bits("Offset", 3, 2), bit("Enable", 7)];
+
+const WRAMCNT: &[FieldDef] = &[
+    named("Mode", 0, 2, &["ARM9 32K", "ARM9 Upper 16K, ARM7 Lower 16K", "ARM9 Lower 16K, ARM7 Upper 16K", "ARM7 32K"]),
+];
+
+const DIVCNT: &[FieldDef] = &[
+    named("Mode", 0, 2, &["32/32", "64/32", "64/64", "64/64"]), bit("Division by Zero", 14), bit("Busy", 15),
+];
+
+const SQRTCNT: &[FieldDef] = &[named("Mode", 0, 1, &["32-bit", "64-bit"]), bit("Busy", 15)];
+
+const POSTFLG: &[FieldDef] = &[bit("Booted", 0)];
+
+const HALTCNT: &[FieldDef] = &[named("Mode", 6, 2, &["None", "GBA", "Halt", "Sleep"])];
+
+const POWCNT1: &[FieldDef] = &[
+    bit("LCDs", 0), bit("2D Engine A", 1), bit("3D Rendering", 2), bit("3D Geometry", 3), bit("2D Engine B", 9),
+    named("Top Screen", 15, 1, &["Engine B", "Engine A"]),
+];
+
+const POWCNT2: &[FieldDef] = &[bit("Sound", 0), bit("Wi-Fi", 1)];
+
+const SOUNDCNT: &[FieldDef] = &[
+    bits("Master Volume", 0, 7), named("Left Output", 8, 2, &["Mixer", "Channel 1", "Channel 3", "Channel 1 + 3"]),
+    named("Right Output", 10, 2, &["Mixer", "Channel 1", "Channel 3", "Channel 1 + 3"]),
+    bit("Skip Channel 1 Mixing", 12), bit("Skip Channel 3 Mixing", 13), bit("Enable", 15),
+];
+
+const SOUNDBIAS: &[FieldDef] = &[bits("Bias", 0, 10)];
+
+const SOUNDXCNT: &[FieldDef] = &[
+    bits("Volume", 0, 7), named("Volume Divider", 8, 2, &["1", "2", "4", "16"]), bit("Hold", 15),
+    bits("Panning", 16, 7), bits("Wave Duty", 24, 3), named("Repeat", 27, 2, &["Manual", "Loop", "One-Shot", "Reserved"]),
+    named("Format", 29, 2, &["PCM8", "PCM16", "IMA-ADPCM", "PSG/Noise"]), bit("Start", 31),
+];
+
+struct RegisterDef {
+    name: String,
+    addr: u32,
+    size: usize,
+    fields: &'static [FieldDef],
+}
+
+fn reg(name: &str, addr: u32, size: usize, fields: &'static [FieldDef]) -> RegisterDef {
+    RegisterDef { name: name.to_string(), addr, size, fields }
+}
+
+// Slot-1 registers can only be read by the CPU that EXMEMCNT gives access to
+fn common_registers(arm9: bool, slot1_access: bool) -> Vec<RegisterDef> {
+    let mut regs = vec![reg("DISPSTAT", 0x0400_0004, 2, DISPSTAT), reg("VCOUNT", 0x0400_0006, 2, VCOUNT)];
+    for i in 0..4 {
+        let addr = 0x0400_00B0 + 0xC * i;
+        regs.push(reg(&format!("DMA{}SAD", i), addr, 4, &[]));
+        regs.push(reg(&format!("DMA{}DAD", i), addr + 4, 4, &[]));
+        regs.push(reg(&format!("DMA{}CNT", i), addr + 8, 4, if arm9 { DMACNT_ARM9 } else { DMACNT_ARM7 }));
+    }
+    if arm9 {
+        for i in 0..4 { regs.push(reg(&format!("DMA{}FILL", i), 0x0400_00E0 + 4 * i, 4, &[])) }
+    }
+    for i in 0..4 {
+        regs.push(reg(&format!("TM{}CNT_L", i), 0x0400_0100 + 4 * i, 2, &[]));
+        regs.push(reg(&format!("TM{}CNT_H", i), 0x0400_0102 + 4 * i, 2, TMCNT));
+    }
+    regs.extend(vec![
+        reg("KEYINPUT", 0x0400_0130, 2, KEYINPUT),
+        reg("KEYCNT", 0x0400_0132, 2, KEYCNT),
+        reg("IPCSYNC", 0x0400_0180, 4, IPCSYNC),
+        reg("IPCFIFOCNT", 0x0400_0184, 4, IPCFIFOCNT),
+        reg(if arm9 { "EXMEMCNT" } else { "EXMEMSTAT" }, 0x0400_0204, 2, EXMEMCNT),
+        reg("IME", 0x0400_0208, 4, IME),
+        reg("IE", 0x0400_0210, 4, INTERRUPTS),
+        reg("IF", 0x0400_0214, 4, INTERRUPTS),
+    ]);
+    if slot1_access {
+        regs.push(reg("AUXSPICNT", 0x0400_01A0, 2, AUXSPICNT));
+        regs.push(reg("ROMCTRL", 0x0400_01A4, 4, ROMCTRL));
+    }
+    regs
+}
+
+fn arm9_registers(slot1_access: bool) -> Vec<RegisterDef> {
+    let mut regs = common_registers(true, slot1_access);
+    // Engine B's registers are prefixed with DB_ like in GBATEK
+    for (engine, base) in [("", 0x0400_0000), ("DB_", 0x0400_1000)].iter() {
+        regs.push(reg(&format!("{}DISPCNT", engine), *base, 4, DISPCNT));
+        for i in 0..4 {
+            regs.push(reg(&format!("{}BG{}CNT", engine, i), base + 0x08 + 2 * i, 2, BGCNT));
+            regs.push(reg(&format!("{}BG{}HOFS", engine, i), base + 0x10 + 4 * i, 2, BGOFS));
+            regs.push(reg(&format!("{}BG{}VOFS", engine, i), base + 0x12 + 4 * i, 2, BGOFS));
+        }
+        regs.push(reg(&format!("{}MASTER_BRIGHT", engine), base + 0x6C, 2, MASTER_BRIGHT));
+    }
+    regs.extend(vec![
+        reg("DISP3DCNT", 0x0400_0060, 4, DISP3DCNT),
+        reg("DISPCAPCNT", 0x0400_0064, 4, DISPCAPCNT),
+        reg("GXSTAT", 0x0400_0600, 4, GXSTAT),
+    ]);
+    for (i, bank) in "ABCDEFG".chars().enumerate() {
+        regs.push(reg(&format!("VRAMCNT_{}", bank), 0x0400_0240 + i as u32, 1, VRAMCNT));
+    }
+    regs.extend(vec![
+        reg("WRAMCNT", 0x0400_0247, 1, WRAMCNT),
+        reg("VRAMCNT_H", 0x0400_0248, 1, VRAMCNT),
+        reg("VRAMCNT_I", 0x0400_0249, 1, VRAMCNT),
+        reg("DIVCNT", 0x0400_0280, 2, DIVCNT),
+        reg("DIV_NUMER", 0x0400_0290, 8, &[]),
+        reg("DIV_DENOM", 0x0400_0298, 8, &[]),
+        reg("DIV_RESULT", 0x0400_02A0, 8, &[]),
+        reg("DIVREM_RESULT", 0x0400_02A8, 8, &[]),
+        reg("SQRTCNT", 0x0400_02B0, 2, SQRTCNT),
+        reg("SQRT_RESULT", 0x0400_02B4, 4, &[]),
+        reg("SQRT_PARAM", 0x0400_02B8, 8, &[]),
+        reg("POSTFLG", 0x0400_0300, 1, POSTFLG),
+        reg("POWCNT1", 0x0400_0304, 4, POWCNT1),
+    ]);
+    regs
+}
+
+fn arm7_registers(slot1_access: bool) -> Vec<RegisterDef> {
+    let mut regs = common_registers(false, slot1_access);
+    regs.extend(vec![
+        reg("EXTKEYIN", 0x0400_0136, 2, EXTKEYIN),
+        reg("RTC", 0x0400_0138, 2, RTC),
+        reg("SPICNT", 0x0400_01C0, 2, SPICNT),
+        reg("WRAMSTAT", 0x0400_0241, 1, WRAMCNT),
+        reg("POSTFLG", 0x0400_0300, 1, POSTFLG),
+        reg("HALTCNT", 0x0400_0301, 1, HALTCNT),
+        reg("POWCNT2", 0x0400_0304, 4, POWCNT2),
+    ]);
+    // The rest of the channel registers are write only
+    for i in 0..16 { regs.push(reg(&format!("SOUND{}CNT", i), 0x0400_0400 + 0x10 * i, 4, SOUNDXCNT)) }
+    regs.extend(vec![
+        reg("SOUNDCNT", 0x0400_0500, 2, SOUNDCNT),
+        reg("SOUNDBIAS", 0x0400_0504, 2, SOUNDBIAS),
+    ]);
+    regs
+}
+
+impl HW {
+    pub fn io_registers(&self, arm9: bool) -> Vec<Register> {
+        let slot1_access = self.exmem.nds_arm7_access() != arm9;
+        let mut defs = if arm9 { arm9_registers(slot1_access) } else { arm7_registers(slot1_access) };
+        defs.sort_by_key(|def| def.addr);
+        defs.into_iter().map(|def| {
+            let value = (0..def.size as u32).fold(0, |value, i| {
+                let byte = if arm9 { self.arm9_read_io_register(def.addr + i) }
+                    else { self.arm7_read_io_register(def.addr + i) };
+                value | (byte as u64) << (8 * i)
+            });
+            let fields = def.fields.iter().map(|field| {
+                let field_value = value >> field.shift & ((1 << field.len) - 1);
+                RegisterField {
+                    name: field.name,
+                    shift: field.shift,
+                    len: field.len,
+                    value: field_value,
+                    meaning: field.values.get(field_value as usize).copied(),
+                }
+            }).collect();
+            Register { name: def.name, addr: def.addr, size: def.size, value, fields }
+        }).collect()
+    }
+}
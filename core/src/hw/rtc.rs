@@ -0,0 +1,332 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::{
+    HW,
+    interrupt_controller::InterruptRequest,
+    scheduler::{Event, Scheduler},
+};
+use crate::nds::NDS;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RtcMode {
+    // Follows the host clock (UTC)
+    Host,
+    // Starts at the given Unix timestamp and advances with emulated time
+    Fixed(u64),
+}
+
+pub struct RTC {
+    mode: RtcMode,
+    // Seconds added by the game setting the time
+    offset: i64,
+    // Serial Interface
+    data: bool,
+    clock: bool,
+    select: bool,
+    data_out: bool,
+    clock_out: bool,
+    select_out: bool,
+    byte: u8,
+    bit: usize,
+    cmd: Option<u8>,
+    params: Vec<u8>,
+    read_bytes: Vec<u8>,
+    // Registers
+    status1: u8,
+    status2: u8,
+    alarm1: [u8; 3],
+    alarm2: [u8; 3],
+    clock_adjust: u8,
+    free: u8,
+    prev_minute: Option<u8>,
+}
+
+// The clock source is a host setting
+savestate!(RTC { offset, data, clock, select, data_out, clock_out, select_out, byte, bit, cmd, params, read_bytes, status1, status2,
+    alarm1, alarm2, clock_adjust, free, prev_minute });
+
+impl RTC {
+    const STATUS1_RESET: u8 = 1 << 0;
+    const STATUS1_24_HOUR: u8 = 1 << 1;
+    const STATUS1_INT1: u8 = 1 << 4;
+    const STATUS1_INT2: u8 = 1 << 5;
+    const STATUS2_INT2_ENABLE: u8 = 1 << 6;
+    // 2000-01-01
+    const RESET_TIMESTAMP: i64 = 946_684_800;
+
+    pub fn new(scheduler: &mut Scheduler) -> Self {
+        scheduler.schedule(Event::RTCTick, HW::on_rtc_tick, NDS::CLOCK_RATE);
+        RTC {
+            mode: RtcMode::Host,
+            offset: 0,
+            data: false,
+            clock: false,
+            select: false,
+            data_out: false,
+            clock_out: false,
+            select_out: false,
+            byte: 0,
+            bit: 0,
+            cmd: None,
+            params: Vec::new(),
+            read_bytes: Vec::new(),
+            status1: RTC::STATUS1_24_HOUR,
+            status2: 0,
+            alarm1: [0; 3],
+            alarm2: [0; 3],
+            clock_adjust: 0,
+            free: 0,
+            prev_minute: None,
+        }
+    }
+
+    pub fn set_mode(&mut self, mode: RtcMode) {
+        self.mode = mode;
+        self.offset = 0;
+    }
+
+    fn timestamp(&self, cycle: usize) -> i64 {
+        let base = match self.mode {
+            RtcMode::Host => SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+            RtcMode::Fixed(timestamp) => timestamp + (cycle / NDS::CLOCK_RATE) as u64,
+        };
+        base as i64 + self.offset
+    }
+
+    fn date_time(&self, cycle: usize) -> DateTime {
+        DateTime::from_timestamp(self.timestamp(cycle))
+    }
+
+    fn bcd(value: u8) -> u8 { ((value / 10) << 4) | (value % 10) }
+    fn from_bcd(value: u8) -> u8 { (value >> 4) * 10 + (value & 0xF) }
+
+    fn hour_bcd(&self, hour: u8) -> u8 {
+        // AM/PM flag is set in both modes
+        let pm = (hour >= 12) as u8;
+        let hour = if self.status1 & RTC::STATUS1_24_HOUR != 0 { hour } else { hour % 12 };
+        pm << 6 | RTC::bcd(hour)
+    }
+
+    fn time_bytes(&self, date_time: &DateTime) -> [u8; 3] {
+        [self.hour_bcd(date_time.hour), RTC::bcd(date_time.minute), RTC::bcd(date_time.second)]
+    }
+
+    pub fn read(&self, byte: usize) -> u8 {
+        match byte {
+            0 => (self.select_out as u8) << 6 | (self.clock_out as u8) << 5 | (self.data_out as u8) << 4 |
+                (self.select as u8) << 2 | (self.clock as u8) << 1 | self.data as u8,
+            1 => 0,
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn write(&mut self, cycle: usize, byte: usize, value: u8) {
+        if byte != 0 { return }
+        self.data_out = value >> 4 & 0x1 != 0;
+        self.clock_out = value >> 5 & 0x1 != 0;
+        self.select_out = value >> 6 & 0x1 != 0;
+        let reading = self.cmd.is_some_and(|cmd| cmd & 0x80 != 0);
+        if self.data_out && !reading { self.data = value & 0x1 != 0 }
+        let clock = if self.clock_out { value >> 1 & 0x1 != 0 } else { self.clock };
+        let select = if self.select_out { value >> 2 & 0x1 != 0 } else { self.select };
+
+        if !select || !self.select {
+            self.cmd = None;
+            self.byte = 0;
+            self.bit = 0;
+        } else if !self.clock && clock {
+            // Bits are transferred LSB first on the rising edge of the clock
+            if reading {
+                let index = self.bit / 8;
+                self.data = self.read_bytes.get(index).is_some_and(|byte| byte >> (self.bit % 8) & 0x1 != 0);
+                self.bit += 1;
+            } else {
+                self.byte |= (self.data as u8) << self.bit;
+                self.bit += 1;
+                if self.bit == 8 {
+                    let byte = self.byte;
+                    self.byte = 0;
+                    self.bit = 0;
+                    self.receive_byte(cycle, byte);
+                }
+            }
+        }
+        self.clock = clock;
+        self.select = select;
+    }
+
+    fn receive_byte(&mut self, cycle: usize, byte: u8) {
+        match self.cmd {
+            None => {
+                // Command may be sent in either bit order
+                let cmd = if byte & 0x0F == 0x06 { byte } else if byte & 0xF0 == 0x60 { byte.reverse_bits() } else {
+                    warn!("Invalid RTC Command: 0x{:X}", byte);
+                    return
+                };
+                self.cmd = Some(cmd);
+                self.params.clear();
+                if cmd & 0x80 != 0 { self.read_bytes = self.read_register(cycle, cmd >> 4 & 0x7) }
+            },
+            Some(cmd) => {
+                self.params.push(byte);
+                self.write_register(cycle, cmd >> 4 & 0x7);
+            },
+        }
+    }
+
+    fn read_register(&mut self, cycle: usize, register: u8) -> Vec<u8> {
+        match register {
+            0 => {
+                let value = self.status1;
+                // Interrupt and power flags are cleared on read
+                self.status1 &= 0x0F;
+                vec![value]
+            },
+            4 => vec![self.status2],
+            2 => {
+                let date_time = self.date_time(cycle);
+                let time = self.time_bytes(&date_time);
+                vec![RTC::bcd((date_time.year % 100) as u8), RTC::bcd(date_time.month), RTC::bcd(date_time.day),
+                date_time.day_of_week, time[0], time[1], time[2]]
+            },
+            6 => self.time_bytes(&self.date_time(cycle)).to_vec(),
+            1 => if self.status2 & 0xF == 0x4 { self.alarm1.to_vec() } else { vec![self.alarm1[0]] },
+            5 => self.alarm2.to_vec(),
+            3 => vec![self.clock_adjust],
+            7 => vec![self.free],
+            _ => unreachable!(),
+        }
+    }
+
+    fn write_register(&mut self, cycle: usize, register: u8) {
+        let index = self.params.len() - 1;
+        let value = self.params[index];
+        match register {
+            0 => {
+                if value & RTC::STATUS1_RESET != 0 { self.reset(cycle) }
+                // Only the 12/24 hour mode and general purpose bits are writable
+                self.status1 = self.status1 & !0x0E | value & 0x0E;
+            },
+            4 => self.status2 = value,
+            2 if self.params.len() == 7 => self.set_date_time(cycle, true),
+            6 if self.params.len() == 3 => self.set_date_time(cycle, false),
+            2 | 6 => (),
+            1 if index < 3 => self.alarm1[index] = value,
+            5 if index < 3 => self.alarm2[index] = value,
+            1 | 5 => (),
+            3 => self.clock_adjust = value,
+            7 => self.free = value,
+            _ => unreachable!(),
+        }
+    }
+
+    fn reset(&mut self, cycle: usize) {
+        self.status1 = 0;
+        self.status2 = 0;
+        self.alarm1 = [0; 3];
+        self.alarm2 = [0; 3];
+        self.clock_adjust = 0;
+        self.free = 0;
+        self.offset = 0;
+        self.offset = RTC::RESET_TIMESTAMP - self.timestamp(cycle);
+    }
+
+    fn set_date_time(&mut self, cycle: usize, has_date: bool) {
+        let current = self.date_time(cycle);
+        let (date, time) = if has_date { self.params.split_at(4) } else { self.params.split_at(0) };
+        let hour = RTC::from_bcd(time[0] & 0x3F);
+        let hour = if self.status1 & RTC::STATUS1_24_HOUR == 0 && time[0] & 0x40 != 0 { hour % 12 + 12 } else { hour };
+        let new = DateTime {
+            year: if has_date { 2000 + RTC::from_bcd(date[0]) as i64 } else { current.year },
+            month: if has_date { RTC::from_bcd(date[1] & 0x1F) } else { current.month },
+            day: if has_date { RTC::from_bcd(date[2] & 0x3F) } else { current.day },
+            day_of_week: 0,
+            hour,
+            minute: RTC::from_bcd(time[1] & 0x7F),
+            second: RTC::from_bcd(time[2] & 0x7F),
+        };
+        self.offset += new.timestamp() - self.timestamp(cycle);
+    }
+
+    fn alarm_matches(alarm: &[u8; 3], date_time: &DateTime, hour_bcd: u8) -> bool {
+        // Bit 7 of each byte enables comparing that field
+        let fields = [date_time.day_of_week, hour_bcd & 0x3F, RTC::bcd(date_time.minute)];
+        let masks = [0x07, 0x3F, 0x7F];
+        alarm.iter().zip(fields.iter()).zip(masks.iter())
+            .all(|((alarm, field), mask)| alarm & 0x80 == 0 || alarm & mask == *field)
+    }
+
+    // Checked once per second, returns whether an interrupt was raised
+    fn tick(&mut self, cycle: usize) -> bool {
+        let date_time = self.date_time(cycle);
+        let new_minute = self.prev_minute.is_some_and(|minute| minute != date_time.minute);
+        self.prev_minute = Some(date_time.minute);
+        if !new_minute { return false }
+
+        let hour_bcd = self.hour_bcd(date_time.hour);
+        let int1 = match self.status2 & 0xF {
+            0x2 | 0x3 | 0x6 => true, // Per-minute interrupts
+            0x4 => RTC::alarm_matches(&self.alarm1, &date_time, hour_bcd),
+            _ => false,
+        };
+        let int2 = self.status2 & RTC::STATUS2_INT2_ENABLE != 0 &&
+            RTC::alarm_matches(&self.alarm2, &date_time, hour_bcd);
+        if int1 { self.status1 |= RTC::STATUS1_INT1 }
+        if int2 { self.status1 |= RTC::STATUS1_INT2 }
+        int1 || int2
+    }
+}
+
+impl HW {
+    pub(super) fn on_rtc_tick(&mut self, _event: Event) {
+        self.scheduler.schedule(Event::RTCTick, HW::on_rtc_tick, NDS::CLOCK_RATE);
+        if self.rtc.tick(self.scheduler.cycle) { self.interrupts[0].request |= InterruptRequest::SERIAL }
+    }
+}
+
+struct DateTime {
+    year: i64,
+    month: u8,
+    day: u8,
+    day_of_week: u8, // 0 is Sunday
+    hour: u8,
+    minute: u8,
+    second: u8,
+}
+
+impl DateTime {
+    // Civil date conversion from days since the Unix epoch
+    fn from_timestamp(timestamp: i64) -> Self {
+        let days = timestamp.div_euclid(86400);
+        let secs = timestamp.rem_euclid(86400);
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z.rem_euclid(146_097);
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u8;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u8;
+        let year = yoe + era * 400 + (month <= 2) as i64;
+        DateTime {
+            year,
+            month,
+            day,
+            day_of_week: (days + 4).rem_euclid(7) as u8,
+            hour: (secs / 3600) as u8,
+            minute: (secs / 60 % 60) as u8,
+            second: (secs % 60) as u8,
+        }
+    }
+
+    fn timestamp(&self) -> i64 {
+        let year = self.year - (self.month <= 2) as i64;
+        let era = year.div_euclid(400);
+        let yoe = year.rem_euclid(400);
+        let month = self.month as i64;
+        let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + self.day as i64 - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        let days = era * 146_097 + doe - 719_468;
+        days * 86400 + self.hour as i64 * 3600 + self.minute as i64 * 60 + self.second as i64
+    }
+}
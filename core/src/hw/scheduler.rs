@@ -1,8 +1,6 @@
-use std::cmp::{PartialEq, Eq, Reverse};
-use std::hash::Hash;
-
-use priority_queue::PriorityQueue;
+use std::time::Instant;
 
+use crate::savestate::{Savestate, StateReader, StateWriter};
 use super::{HW, spu};
 
 type EventHandler = fn(&mut HW, Event);
@@ -10,22 +8,28 @@ type EventHandler = fn(&mut HW, Event);
 impl HW {
     pub fn handle_events(&mut self, arm7_cycles: usize) {
         self.scheduler.cycle += arm7_cycles;
-        while let Some(wrapper) = self.scheduler.get_next_event() {
-            (wrapper.handler)(self, wrapper.event);
-        }
+        while let Some(queued) = self.scheduler.get_next_event() { self.handle_event(queued) }
     }
 
     pub fn clock_until_event(&mut self) {
-        let (_, Reverse(cycle)) = self.scheduler.event_queue.peek().unwrap();
-        if self.scheduler.cycle > *cycle { return }
-        let (wrapper, Reverse(cycle)) = self.scheduler.event_queue.pop().unwrap();
-        self.scheduler.cycle = cycle;
-        (wrapper.handler)(self, wrapper.event);
+        if self.scheduler.cycle > self.scheduler.next_cycle() { return }
+        let queued = self.scheduler.pop();
+        self.scheduler.cycle = queued.cycle;
+        self.handle_event(queued);
+    }
+
+    fn handle_event(&mut self, queued: QueuedEvent) {
+        let kind = queued.event.kind() as usize;
+        self.scheduler.stats[kind].handled += 1;
+        if self.scheduler.profiling {
+            let start = Instant::now();
+            (queued.handler)(self, queued.event);
+            self.scheduler.stats[kind].nanos += start.elapsed().as_nanos() as u64;
+        } else { (queued.handler)(self, queued.event) }
     }
 
     pub fn cycles_until_event(&self) -> usize {
-        let (_wrapper, Reverse(cycle)) = self.scheduler.event_queue.peek().unwrap();
-        if self.scheduler.cycle > *cycle { 0 } else { cycle - self.scheduler.cycle }
+        self.scheduler.next_cycle().saturating_sub(self.scheduler.cycle)
     }
 
     fn dummy_handler(&mut self, _event: Event) { unreachable!() }
@@ -33,29 +37,65 @@ impl HW {
 
 pub struct Scheduler {
     pub cycle: usize,
-    event_queue: PriorityQueue<EventWrapper, Reverse<usize>>,
+    // Sorted by cycle with the latest first, so the next event is popped off the end.
+    // Events scheduled for the same cycle run in the order they were scheduled.
+    queue: Vec<QueuedEvent>,
+    // Cycle each event is pending at, indexed by Event::slot, so it can be found without comparing every event
+    pending: [Option<usize>; Event::SLOTS],
+    stats: [EventStats; EventKind::COUNT],
+    pub profiling: bool,
 }
 
 impl Scheduler {
     pub fn new() -> Scheduler {
-        let queue = PriorityQueue::new();
         Scheduler {
             cycle: 0,
-            event_queue: queue,
+            queue: Vec::new(),
+            pending: [None; Event::SLOTS],
+            stats: [EventStats::default(); EventKind::COUNT],
+            profiling: false,
         }
     }
 
-    fn get_next_event(&mut self) -> Option<EventWrapper> {
-        // There should always be at least one event in the queue
-        let (_event_type, Reverse(cycle)) = self.event_queue.peek().unwrap();
-        if self.cycle >= *cycle {
-            Some(self.event_queue.pop().unwrap().0)
-        } else { None }
+    pub fn pending_events(&self) -> Vec<PendingEvent> {
+        self.queue.iter().rev().map(|queued| PendingEvent {
+            kind: queued.event.kind(),
+            description: format!("{:?}", queued.event),
+            cycle: queued.cycle,
+            delta: queued.cycle as isize - self.cycle as isize,
+        }).collect()
+    }
+
+    pub fn event_stats(&self) -> Vec<(EventKind, EventStats)> {
+        EventKind::ALL.iter().map(|kind| (*kind, self.stats[*kind as usize])).collect()
+    }
+
+    pub fn reset_event_stats(&mut self) {
+        self.stats = [EventStats::default(); EventKind::COUNT];
+    }
+
+    // There should always be at least one event in the queue
+    fn next_cycle(&self) -> usize {
+        self.queue.last().unwrap().cycle
+    }
+
+    fn pop(&mut self) -> QueuedEvent {
+        let queued = self.queue.pop().unwrap();
+        self.pending[queued.slot] = None;
+        queued
+    }
+
+    fn get_next_event(&mut self) -> Option<QueuedEvent> {
+        if self.cycle >= self.next_cycle() { Some(self.pop()) } else { None }
     }
 
     pub fn schedule(&mut self, event: Event, handler: EventHandler, delay: usize) {
-        let wrapper = EventWrapper::new(event, handler);
-        self.event_queue.push(wrapper, Reverse(self.cycle + delay));
+        // Scheduling an event that's already pending moves it instead of adding another one
+        let replaced = self.cancel(event.slot());
+        let stats = &mut self.stats[event.kind() as usize];
+        stats.scheduled += 1;
+        if replaced { stats.replaced += 1 }
+        self.push(QueuedEvent::new(event, handler, self.cycle + delay));
     }
 
     pub fn run_now(&mut self, event: Event, handler: EventHandler) {
@@ -63,14 +103,59 @@ impl Scheduler {
     }
 
     pub fn remove(&mut self, event: Event) {
-        let wrapper = EventWrapper::new(event, HW::dummy_handler);
-        self.event_queue.remove(&wrapper);
+        if self.cancel(event.slot()) { self.stats[event.kind() as usize].removed += 1 }
+    }
+
+    fn push(&mut self, queued: QueuedEvent) {
+        // Before any events at the same cycle so those are popped first
+        let i = self.queue.partition_point(|other| other.cycle > queued.cycle);
+        self.pending[queued.slot] = Some(queued.cycle);
+        self.queue.insert(i, queued);
+    }
+
+    fn cancel(&mut self, slot: usize) -> bool {
+        let cycle = match self.pending[slot].take() {
+            Some(cycle) => cycle,
+            None => return false,
+        };
+        let start = self.queue.partition_point(|queued| queued.cycle > cycle);
+        let i = start + self.queue[start..].iter().position(|queued| queued.slot == slot).unwrap();
+        self.queue.remove(i);
+        true
+    }
+}
+
+// Handlers aren't stored, they're looked up again from the event when loading
+impl Savestate for Scheduler {
+    fn save(&self, state: &mut StateWriter) {
+        self.cycle.save(state);
+        self.queue.len().save(state);
+        for queued in self.queue.iter().rev() {
+            queued.event.save(state);
+            queued.cycle.save(state);
+        }
+    }
+
+    fn load(&mut self, state: &mut StateReader) {
+        self.cycle.load(state);
+        let len = state.read_len();
+        self.queue.clear();
+        self.pending = [None; Event::SLOTS];
+        for _ in 0..len {
+            let mut event = Event::StartNextLine;
+            let mut cycle = 0;
+            event.load(state);
+            cycle.load(state);
+            self.cancel(event.slot());
+            self.push(QueuedEvent::new(event, event.handler(), cycle));
+        }
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Event {
     DMA(bool, usize),
+    NDMA(bool, usize),
     StartNextLine,
     HBlank,
     VBlank,
@@ -80,33 +165,216 @@ pub enum Event {
     ROMBlockEnded(bool),
     GenerateAudioSample,
     StepAudioChannel(spu::ChannelSpec),
-    ResetAudioChannel(spu::ChannelSpec),
+    SPITransferFinished,
+    AUXSPITransferFinished,
+    RTCTick,
+    WiFiPoll,
+    WiFiUSCompare,
+    WiFiPreBeacon,
+    WiFiTransferFinished,
 }
 
-struct EventWrapper {
-    event: Event,
-    handler: EventHandler,
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventKind {
+    DMA,
+    NDMA,
+    StartNextLine,
+    HBlank,
+    VBlank,
+    CheckGeometryCommandFIFO,
+    TimerOverflow,
+    ROMWordTransfered,
+    ROMBlockEnded,
+    GenerateAudioSample,
+    StepAudioChannel,
+    SPITransferFinished,
+    AUXSPITransferFinished,
+    RTCTick,
+    WiFiPoll,
+    WiFiUSCompare,
+    WiFiPreBeacon,
+    WiFiTransferFinished,
+    // Not an event, it just follows the last one so the number of kinds can't go out of date
+    Count,
 }
 
-impl EventWrapper {
-    pub fn new(event: Event, handler: EventHandler) -> Self {
-        EventWrapper {
-            event,
-            handler,
+impl EventKind {
+    const COUNT: usize = EventKind::Count as usize;
+    pub const ALL: [EventKind; EventKind::COUNT] = [
+        EventKind::DMA, EventKind::NDMA, EventKind::StartNextLine, EventKind::HBlank, EventKind::VBlank,
+        EventKind::CheckGeometryCommandFIFO, EventKind::TimerOverflow, EventKind::ROMWordTransfered,
+        EventKind::ROMBlockEnded, EventKind::GenerateAudioSample, EventKind::StepAudioChannel,
+        EventKind::SPITransferFinished, EventKind::AUXSPITransferFinished, EventKind::RTCTick, EventKind::WiFiPoll,
+        EventKind::WiFiUSCompare, EventKind::WiFiPreBeacon, EventKind::WiFiTransferFinished,
+    ];
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct EventStats {
+    pub scheduled: u64,
+    // Scheduled again while still pending
+    pub replaced: u64,
+    pub removed: u64,
+    pub handled: u64,
+    // Time spent in the handler, only measured while profiling
+    pub nanos: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct PendingEvent {
+    pub kind: EventKind,
+    pub description: String,
+    pub cycle: usize, // In ARM7 cycles
+    pub delta: isize, // Negative if overdue
+}
+
+impl Event {
+    const SLOTS: usize = 55;
+
+    // Unique index for each event that can be pending
+    fn slot(&self) -> usize {
+        match *self {
+            Event::DMA(is_nds9, num) => (is_nds9 as usize) * 4 + num,
+            Event::StartNextLine => 8,
+            Event::HBlank => 9,
+            Event::VBlank => 10,
+            Event::CheckGeometryCommandFIFO => 11,
+            Event::TimerOverflow(is_nds9, num) => 12 + (is_nds9 as usize) * 4 + num,
+            Event::ROMWordTransfered => 20,
+            Event::ROMBlockEnded(is_arm9) => 21 + is_arm9 as usize,
+            Event::GenerateAudioSample => 23,
+            Event::StepAudioChannel(spu::ChannelSpec::Base(num)) => 24 + num,
+            Event::StepAudioChannel(spu::ChannelSpec::PSG(num)) => 32 + num,
+            Event::StepAudioChannel(spu::ChannelSpec::Noise(num)) => 38 + num,
+            Event::SPITransferFinished => 40,
+            Event::AUXSPITransferFinished => 41,
+            Event::RTCTick => 42,
+            Event::WiFiPoll => 43,
+            Event::WiFiUSCompare => 44,
+            Event::WiFiPreBeacon => 45,
+            Event::WiFiTransferFinished => 46,
+            Event::NDMA(is_arm9, num) => 47 + (is_arm9 as usize) * 4 + num,
+        }
+    }
+
+    fn kind(&self) -> EventKind {
+        match self {
+            Event::DMA(_, _) => EventKind::DMA,
+            Event::NDMA(_, _) => EventKind::NDMA,
+            Event::StartNextLine => EventKind::StartNextLine,
+            Event::HBlank => EventKind::HBlank,
+            Event::VBlank => EventKind::VBlank,
+            Event::CheckGeometryCommandFIFO => EventKind::CheckGeometryCommandFIFO,
+            Event::TimerOverflow(_, _) => EventKind::TimerOverflow,
+            Event::ROMWordTransfered => EventKind::ROMWordTransfered,
+            Event::ROMBlockEnded(_) => EventKind::ROMBlockEnded,
+            Event::GenerateAudioSample => EventKind::GenerateAudioSample,
+            Event::StepAudioChannel(_) => EventKind::StepAudioChannel,
+            Event::SPITransferFinished => EventKind::SPITransferFinished,
+            Event::AUXSPITransferFinished => EventKind::AUXSPITransferFinished,
+            Event::RTCTick => EventKind::RTCTick,
+            Event::WiFiPoll => EventKind::WiFiPoll,
+            Event::WiFiUSCompare => EventKind::WiFiUSCompare,
+            Event::WiFiPreBeacon => EventKind::WiFiPreBeacon,
+            Event::WiFiTransferFinished => EventKind::WiFiTransferFinished,
+        }
+    }
+
+    fn handler(&self) -> EventHandler {
+        match self {
+            Event::DMA(_, _) => HW::on_dma,
+            Event::NDMA(_, _) => HW::on_ndma,
+            Event::StartNextLine => HW::start_next_line,
+            Event::HBlank => HW::on_hblank,
+            Event::VBlank => HW::dummy_handler,
+            Event::CheckGeometryCommandFIFO => HW::check_geometry_command_fifo_handler,
+            Event::TimerOverflow(_, _) => HW::on_timer_overflow,
+            Event::ROMWordTransfered => HW::on_rom_word_transfered,
+            Event::ROMBlockEnded(_) => HW::on_rom_block_ended,
+            Event::GenerateAudioSample => HW::generate_audio_sample,
+            Event::StepAudioChannel(_) => HW::on_audio_channel_event,
+            Event::SPITransferFinished => HW::on_spi_transfer_finished,
+            Event::AUXSPITransferFinished => HW::on_aux_spi_transfer_finished,
+            Event::RTCTick => HW::on_rtc_tick,
+            Event::WiFiPoll => HW::on_wifi_poll,
+            Event::WiFiUSCompare => HW::on_wifi_us_compare,
+            Event::WiFiPreBeacon => HW::on_wifi_pre_beacon,
+            Event::WiFiTransferFinished => HW::on_wifi_transfer_finished,
         }
     }
 }
 
-impl PartialEq for EventWrapper {
-    fn eq(&self, other: &Self) -> bool {
-        self.event.eq(&other.event)
+impl Savestate for Event {
+    fn save(&self, state: &mut StateWriter) {
+        match *self {
+            Event::DMA(is_nds9, num) => { 0u8.save(state); is_nds9.save(state); num.save(state) },
+            Event::StartNextLine => 1u8.save(state),
+            Event::HBlank => 2u8.save(state),
+            Event::VBlank => 3u8.save(state),
+            Event::CheckGeometryCommandFIFO => 4u8.save(state),
+            Event::TimerOverflow(is_nds9, num) => { 5u8.save(state); is_nds9.save(state); num.save(state) },
+            Event::ROMWordTransfered => 6u8.save(state),
+            Event::ROMBlockEnded(is_arm9) => { 7u8.save(state); is_arm9.save(state) },
+            Event::GenerateAudioSample => 8u8.save(state),
+            Event::StepAudioChannel(spec) => { 9u8.save(state); spec.save(state) },
+            Event::SPITransferFinished => 10u8.save(state),
+            Event::AUXSPITransferFinished => 11u8.save(state),
+            Event::RTCTick => 12u8.save(state),
+            Event::WiFiPoll => 13u8.save(state),
+            Event::WiFiUSCompare => 14u8.save(state),
+            Event::WiFiPreBeacon => 15u8.save(state),
+            Event::WiFiTransferFinished => 16u8.save(state),
+            Event::NDMA(is_arm9, num) => { 17u8.save(state); is_arm9.save(state); num.save(state) },
+        }
+    }
+
+    fn load(&mut self, state: &mut StateReader) {
+        let mut tag = 0u8;
+        let mut flag = false;
+        let mut num = 0usize;
+        tag.load(state);
+        *self = match tag {
+            0 => { flag.load(state); num.load(state); Event::DMA(flag, num) },
+            1 => Event::StartNextLine,
+            2 => Event::HBlank,
+            3 => Event::VBlank,
+            4 => Event::CheckGeometryCommandFIFO,
+            5 => { flag.load(state); num.load(state); Event::TimerOverflow(flag, num) },
+            6 => Event::ROMWordTransfered,
+            7 => { flag.load(state); Event::ROMBlockEnded(flag) },
+            8 => Event::GenerateAudioSample,
+            9 => {
+                let mut spec = spu::ChannelSpec::Base(0);
+                spec.load(state);
+                Event::StepAudioChannel(spec)
+            },
+            10 => Event::SPITransferFinished,
+            11 => Event::AUXSPITransferFinished,
+            12 => Event::RTCTick,
+            13 => Event::WiFiPoll,
+            14 => Event::WiFiUSCompare,
+            15 => Event::WiFiPreBeacon,
+            16 => Event::WiFiTransferFinished,
+            17 => { flag.load(state); num.load(state); Event::NDMA(flag, num) },
+            _ => { state.invalidate(); Event::StartNextLine },
+        };
     }
 }
 
-impl Eq for EventWrapper {}
+struct QueuedEvent {
+    cycle: usize,
+    slot: usize,
+    event: Event,
+    handler: EventHandler,
+}
 
-impl Hash for EventWrapper {
-    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        self.event.hash(state);
+impl QueuedEvent {
+    pub fn new(event: Event, handler: EventHandler, cycle: usize) -> Self {
+        QueuedEvent {
+            cycle,
+            slot: event.slot(),
+            event,
+            handler,
+        }
     }
 }
@@ -0,0 +1,240 @@
+use crate::notifications::Notifier;
+use crate::savestate::{Savestate, StateReader, StateWriter};
+use super::{SaveStorage, Slot2Device};
+
+// GBA cartridge in Slot-2, used by DS games that read GBA saves
+pub struct GBACartridge {
+    rom: Vec<u8>,
+    save: GBASave,
+    storage: Box<dyn SaveStorage>,
+    dirty: bool,
+    frames_until_flush: Option<usize>,
+    notifier: Notifier,
+}
+
+savestate!(GBACartridge { save });
+
+impl GBACartridge {
+    const FLUSH_DELAY_FRAMES: usize = 60;
+
+    pub fn new(rom: Vec<u8>, mut storage: Box<dyn SaveStorage>, notifier: Notifier) -> Self {
+        let save = GBASave::detect(&rom, storage.load());
+        GBACartridge {
+            rom,
+            save,
+            storage,
+            dirty: false,
+            frames_until_flush: None,
+            notifier,
+        }
+    }
+}
+
+impl Slot2Device for GBACartridge {
+    // ROM is on a 16-bit bus that returns the address when nothing is driving it
+    fn read_rom(&self, addr: u32) -> u16 {
+        let offset = (addr & 0x1FF_FFFE) as usize;
+        match self.rom.get(offset..offset + 2) {
+            Some(bytes) => u16::from_le_bytes([bytes[0], bytes[1]]),
+            None => (addr / 2) as u16,
+        }
+    }
+
+    // Save memory is on an 8-bit bus
+    fn read_ram(&self, addr: u32) -> u8 {
+        self.save.read(addr as usize & 0xFFFF)
+    }
+
+    fn write_ram(&mut self, addr: u32, value: u8) {
+        if self.save.write(addr as usize & 0xFFFF, value) { self.dirty = true }
+    }
+
+    fn update_save(&mut self) -> bool {
+        if self.dirty {
+            self.dirty = false;
+            self.frames_until_flush = Some(GBACartridge::FLUSH_DELAY_FRAMES);
+            return true
+        } else if let Some(frames) = self.frames_until_flush {
+            if frames == 0 { self.flush_save() } else { self.frames_until_flush = Some(frames - 1) }
+        }
+        false
+    }
+
+    fn flush_save(&mut self) {
+        if self.dirty || self.frames_until_flush.is_some() {
+            self.dirty = false;
+            self.frames_until_flush = None;
+            let storage = &mut self.storage;
+            if let Some(Err(err)) = self.save.mem().map(|mem| storage.flush(mem)) {
+                notify!(self.notifier, "Unable to Save to File: {}!", err);
+            }
+        }
+    }
+}
+
+impl Drop for GBACartridge {
+    fn drop(&mut self) {
+        self.flush_save();
+    }
+}
+
+enum GBASave {
+    None,
+    SRAM(Vec<u8>),
+    Flash(GBAFlash),
+}
+
+impl GBASave {
+    fn detect(rom: &[u8], saved: Option<Vec<u8>>) -> Self {
+        // Games built with the official SDK contain the name of their save library
+        let contains = |id: &[u8]| rom.windows(id.len()).any(|window| window == id);
+        let initial_mem = |size: usize| match saved {
+            Some(mem) if mem.len() == size => mem,
+            _ => vec![0xFF; size],
+        };
+        if contains(b"FLASH1M_V") {
+            GBASave::Flash(GBAFlash::new(initial_mem(0x2_0000), GBAFlash::MACRONIX_128K))
+        } else if contains(b"FLASH_V") || contains(b"FLASH512_V") {
+            GBASave::Flash(GBAFlash::new(initial_mem(0x1_0000), GBAFlash::PANASONIC_64K))
+        } else if contains(b"SRAM_V") || contains(b"SRAM_F_V") {
+            GBASave::SRAM(initial_mem(0x8000))
+        } else { GBASave::None }
+    }
+
+    fn read(&self, addr: usize) -> u8 {
+        match self {
+            GBASave::None => 0xFF,
+            GBASave::SRAM(mem) => mem[addr % mem.len()],
+            GBASave::Flash(flash) => flash.read(addr),
+        }
+    }
+
+    fn write(&mut self, addr: usize, value: u8) -> bool {
+        match self {
+            GBASave::None => false,
+            GBASave::SRAM(mem) => { let len = mem.len(); mem[addr % len] = value; true },
+            GBASave::Flash(flash) => flash.write(addr, value),
+        }
+    }
+
+    fn mem(&self) -> Option<&[u8]> {
+        match self {
+            GBASave::None => None,
+            GBASave::SRAM(mem) => Some(mem),
+            GBASave::Flash(flash) => Some(&flash.mem),
+        }
+    }
+}
+
+// The save type is detected from the ROM, so only its contents are loaded
+impl Savestate for GBASave {
+    fn save(&self, state: &mut StateWriter) {
+        match self {
+            GBASave::None => 0u8.save(state),
+            GBASave::SRAM(mem) => { 1u8.save(state); mem.save(state) },
+            GBASave::Flash(flash) => { 2u8.save(state); flash.save(state) },
+        }
+    }
+
+    fn load(&mut self, state: &mut StateReader) {
+        let mut save_type = 0u8;
+        save_type.load(state);
+        match (save_type, self) {
+            (0, GBASave::None) => (),
+            (1, GBASave::SRAM(mem)) => mem.load(state),
+            (2, GBASave::Flash(flash)) => flash.load(state),
+            _ => state.invalidate(),
+        }
+    }
+}
+
+struct GBAFlash {
+    mem: Vec<u8>,
+    id: [u8; 2],
+    mode: FlashMode,
+    unlock_step: usize,
+    id_mode: bool,
+    erase_prepared: bool,
+    bank: usize,
+}
+
+savestate!(GBAFlash { mem, mode, unlock_step, id_mode, erase_prepared, bank });
+
+#[derive(Clone, Copy, PartialEq)]
+enum FlashMode {
+    Command,
+    Write,
+    SetBank,
+}
+
+savestate_enum!(FlashMode { Command, Write, SetBank });
+
+impl GBAFlash {
+    const MACRONIX_128K: [u8; 2] = [0xC2, 0x09];
+    const PANASONIC_64K: [u8; 2] = [0x32, 0x1B];
+    const BANK_LEN: usize = 0x1_0000;
+    const SECTOR_LEN: usize = 0x1000;
+
+    fn new(mem: Vec<u8>, id: [u8; 2]) -> Self {
+        GBAFlash {
+            mem,
+            id,
+            mode: FlashMode::Command,
+            unlock_step: 0,
+            id_mode: false,
+            erase_prepared: false,
+            bank: 0,
+        }
+    }
+
+    fn read(&self, addr: usize) -> u8 {
+        if self.id_mode && addr < 2 { self.id[addr] } else { self.mem[self.bank * GBAFlash::BANK_LEN + addr] }
+    }
+
+    // Returns whether the contents changed
+    fn write(&mut self, addr: usize, value: u8) -> bool {
+        match self.mode {
+            FlashMode::Write => {
+                self.mode = FlashMode::Command;
+                // Programming can only clear bits
+                self.mem[self.bank * GBAFlash::BANK_LEN + addr] &= value;
+                return true
+            },
+            FlashMode::SetBank => {
+                self.mode = FlashMode::Command;
+                if addr == 0 { self.bank = (value & 0x1) as usize % (self.mem.len() / GBAFlash::BANK_LEN) }
+                return false
+            },
+            FlashMode::Command => (),
+        }
+        match (self.unlock_step, addr, value) {
+            (0, 0x5555, 0xAA) => self.unlock_step = 1,
+            (1, 0x2AAA, 0x55) => self.unlock_step = 2,
+            (2, 0x5555, _) => {
+                self.unlock_step = 0;
+                match value {
+                    0x90 => self.id_mode = true,
+                    0xF0 => self.id_mode = false,
+                    0x80 => self.erase_prepared = true,
+                    0x10 if self.erase_prepared => {
+                        self.erase_prepared = false;
+                        self.mem.iter_mut().for_each(|byte| *byte = 0xFF);
+                        return true
+                    },
+                    0xA0 => self.mode = FlashMode::Write,
+                    0xB0 => self.mode = FlashMode::SetBank,
+                    _ => warn!("Unknown GBA Flash Command: 0x{:X}", value),
+                }
+            },
+            (2, _, 0x30) if self.erase_prepared => {
+                self.unlock_step = 0;
+                self.erase_prepared = false;
+                let start = self.bank * GBAFlash::BANK_LEN + (addr & !(GBAFlash::SECTOR_LEN - 1));
+                self.mem[start..start + GBAFlash::SECTOR_LEN].iter_mut().for_each(|byte| *byte = 0xFF);
+                return true
+            },
+            _ => self.unlock_step = 0,
+        }
+        false
+    }
+}
@@ -0,0 +1,31 @@
+use super::{GuitarKey, Slot2Device};
+
+// Guitar Hero: On Tour controller, which exposes its keys on the RAM bus
+pub struct GuitarGrip {
+    keys_pressed: u8,
+}
+
+// Held keys come from the host
+savestate!(GuitarGrip {});
+
+impl GuitarGrip {
+    pub fn new() -> Self {
+        GuitarGrip {
+            keys_pressed: 0,
+        }
+    }
+}
+
+impl Slot2Device for GuitarGrip {
+    // Bits 9 and 10 are pulled low so games can detect the grip
+    fn read_rom(&self, _addr: u32) -> u16 { 0xF9FF }
+    // Pressed keys read as 0
+    fn read_ram(&self, _addr: u32) -> u8 { !self.keys_pressed }
+
+    fn set_guitar_key(&mut self, key: GuitarKey, pressed: bool) {
+        if pressed { self.keys_pressed |= 1 << key as usize } else { self.keys_pressed &= !(1 << key as usize) }
+    }
+
+    fn guitar_keys(&self) -> u8 { self.keys_pressed }
+    fn set_guitar_keys(&mut self, keys: u8) { self.keys_pressed = keys }
+}
@@ -0,0 +1,55 @@
+use super::Slot2Device;
+
+// 8MB of RAM used by the Opera browser, mapped at 0x09000000 once unlocked
+pub struct MemoryExpansionPak {
+    ram: Vec<u8>,
+    ram_enable: bool,
+}
+
+savestate!(MemoryExpansionPak { ram, ram_enable });
+
+impl MemoryExpansionPak {
+    const RAM_SIZE: usize = 0x80_0000;
+    const RAM_ADDR: u32 = 0x100_0000;
+    const CNT_ADDR: u32 = 0x24_0000;
+
+    pub fn new() -> Self {
+        MemoryExpansionPak {
+            ram: vec![0; MemoryExpansionPak::RAM_SIZE],
+            ram_enable: false,
+        }
+    }
+}
+
+impl Slot2Device for MemoryExpansionPak {
+    fn read_rom(&self, addr: u32) -> u16 {
+        let addr = addr & 0x1FF_FFFE;
+        if addr < MemoryExpansionPak::RAM_ADDR {
+            match addr {
+                // ID checked by the browser
+                0xB0 => 0xFFFF,
+                0xB2 => 0x0000,
+                0xB4 => 0x2400,
+                0xB6 => 0x2424,
+                0xB8 ..= 0xBC => 0xFFFF,
+                0xBE | 0x1_FFFC | 0x1_FFFE => 0x7FFF,
+                MemoryExpansionPak::CNT_ADDR => self.ram_enable as u16,
+                _ => 0xFFFF,
+            }
+        } else if addr < MemoryExpansionPak::RAM_ADDR + MemoryExpansionPak::RAM_SIZE as u32 && self.ram_enable {
+            let addr = (addr - MemoryExpansionPak::RAM_ADDR) as usize;
+            u16::from_le_bytes([self.ram[addr], self.ram[addr + 1]])
+        } else { 0xFFFF }
+    }
+
+    fn write_rom(&mut self, addr: u32, value: u16) {
+        let addr = addr & 0x1FF_FFFE;
+        if addr == MemoryExpansionPak::CNT_ADDR {
+            self.ram_enable = value & 0x1 != 0;
+        } else if addr >= MemoryExpansionPak::RAM_ADDR &&
+            addr < MemoryExpansionPak::RAM_ADDR + MemoryExpansionPak::RAM_SIZE as u32 && self.ram_enable {
+            let addr = (addr - MemoryExpansionPak::RAM_ADDR) as usize;
+            self.ram[addr..addr + 2].copy_from_slice(&value.to_le_bytes());
+        }
+    }
+}
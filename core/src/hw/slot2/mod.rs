@@ -0,0 +1,85 @@
+mod gba_cart;
+mod rumble_pak;
+mod memory_expansion;
+mod guitar_grip;
+
+use crate::notifications::Notifier;
+use crate::savestate::{Savestate, StateReader, StateWriter};
+use super::SaveStorage;
+
+use gba_cart::GBACartridge;
+use rumble_pak::RumblePak;
+use memory_expansion::MemoryExpansionPak;
+use guitar_grip::GuitarGrip;
+
+// Devices on the 16-bit ROM bus at 0x08000000 and the 8-bit RAM bus at 0x0A000000
+pub trait Slot2Device: Savestate + Send {
+    fn read_rom(&self, addr: u32) -> u16;
+    fn write_rom(&mut self, _addr: u32, _value: u16) {}
+    fn read_ram(&self, _addr: u32) -> u8 { 0xFF }
+    fn write_ram(&mut self, _addr: u32, _value: u8) {}
+
+    fn set_guitar_key(&mut self, _key: GuitarKey, _pressed: bool) {}
+    // Held guitar keys as a bitmask of GuitarKey bits
+    fn guitar_keys(&self) -> u8 { 0 }
+    fn set_guitar_keys(&mut self, _keys: u8) {}
+    // Whether the game wrote to the save since the last update
+    fn update_save(&mut self) -> bool { false }
+    fn flush_save(&mut self) {}
+}
+
+impl dyn Slot2Device {
+    pub fn new(slot2: Slot2, notifier: Notifier) -> Option<Box<dyn Slot2Device>> {
+        Some(match slot2 {
+            Slot2::Empty => return None,
+            Slot2::GBACartridge(rom, save_storage) => Box::new(GBACartridge::new(rom, save_storage, notifier)),
+            Slot2::RumblePak(rumble) => Box::new(RumblePak::new(rumble)),
+            Slot2::MemoryExpansionPak => Box::new(MemoryExpansionPak::new()),
+            Slot2::GuitarGrip => Box::new(GuitarGrip::new()),
+        })
+    }
+}
+
+// Loading requires the same kind of device to be inserted
+impl Savestate for Option<Box<dyn Slot2Device>> {
+    fn save(&self, state: &mut StateWriter) {
+        self.is_some().save(state);
+        if let Some(slot2) = self { slot2.save(state) }
+    }
+
+    fn load(&mut self, state: &mut StateReader) {
+        let mut inserted = false;
+        inserted.load(state);
+        match self {
+            Some(slot2) if inserted => slot2.load(state),
+            None if !inserted => (),
+            _ => state.invalidate(),
+        }
+    }
+}
+
+pub enum Slot2 {
+    Empty,
+    GBACartridge(Vec<u8>, Box<dyn SaveStorage>),
+    // Called whenever the motor turns on or off
+    RumblePak(Box<dyn FnMut(bool) + Send>),
+    MemoryExpansionPak,
+    GuitarGrip,
+}
+
+// What reads from an empty slot return. Some games check for the console's pattern to detect flash cartridges.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum OpenBus {
+    // Like a DS, where the ROM bus returns the halfword address and the RAM bus reads as 0xFF
+    Pattern,
+    Zeros,
+    Ones,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GuitarKey {
+    Green = 6,
+    Red = 5,
+    Yellow = 4,
+    Blue = 3,
+}
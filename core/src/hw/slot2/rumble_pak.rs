@@ -0,0 +1,30 @@
+use super::Slot2Device;
+
+pub struct RumblePak {
+    rumble: Box<dyn FnMut(bool) + Send>,
+    rumbling: bool,
+}
+
+savestate!(RumblePak { rumbling });
+
+impl RumblePak {
+    pub fn new(rumble: Box<dyn FnMut(bool) + Send>) -> Self {
+        RumblePak {
+            rumble,
+            rumbling: false,
+        }
+    }
+}
+
+impl Slot2Device for RumblePak {
+    // Bit 1 is pulled low so games can detect the pak
+    fn read_rom(&self, _addr: u32) -> u16 { 0xFFFD }
+
+    fn write_rom(&mut self, _addr: u32, value: u16) {
+        let rumbling = value & 0x2 != 0;
+        if rumbling != self.rumbling {
+            self.rumbling = rumbling;
+            (self.rumble)(rumbling);
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use super::super::{HW, GPU};
+
+const SIZE: usize = 0x4_0000;
+const USER_SETTINGS_ADDR: usize = 0x3_FE00;
+const USER_SETTINGS_LEN: usize = 0x70;
+
+const NICKNAME: &str = "NDS";
+const LANGUAGE_ENGLISH: u16 = 1;
+const MAX_BACKLIGHT: u16 = 3;
+
+// Used when no firmware image is supplied
+fn generate() -> Vec<u8> {
+    let mut firmware = vec![0xFF; SIZE];
+    firmware[..0x200].iter_mut().for_each(|byte| *byte = 0);
+    firmware[0x08..0x0C].copy_from_slice(b"MACP");
+    firmware[0x1D] = 0xFF; // Original DS
+    HW::write_mem(&mut firmware, 0x20, (USER_SETTINGS_ADDR / 8) as u16);
+
+    for (count, addr) in [USER_SETTINGS_ADDR, USER_SETTINGS_ADDR + 0x100].iter().enumerate() {
+        let settings = &mut firmware[*addr..*addr + 0x100];
+        settings.iter_mut().for_each(|byte| *byte = 0);
+        settings[0x00] = 5; // Version
+        settings[0x02] = 0; // Favorite Color
+        settings[0x03] = 1; // Birthday Month
+        settings[0x04] = 1; // Birthday Day
+        for (i, c) in NICKNAME.encode_utf16().enumerate() { HW::write_mem(settings, 0x06 + 2 * i as u32, c) }
+        settings[0x1A] = NICKNAME.encode_utf16().count() as u8;
+        // Language, backlight and user info set flags
+        HW::write_mem(settings, 0x64, 0xFC00 | MAX_BACKLIGHT << 4 | LANGUAGE_ENGLISH);
+        HW::write_mem(settings, 0x70, count as u16);
+    }
+    firmware
+}
+
+pub fn init(firmware: Option<Vec<u8>>) -> Vec<u8> {
+    let mut firmware = match firmware {
+        Some(firmware) if user_settings_addrs(&firmware).is_none() => {
+            warn!("Ignoring Firmware Too Small to Hold User Settings: {} Bytes", firmware.len());
+            generate()
+        },
+        firmware => firmware.unwrap_or_else(generate),
+    };
+    let max_x = GPU::WIDTH - 1;
+    let max_y = GPU::HEIGHT - 1;
+    for addr in user_settings_addrs(&firmware).unwrap().iter() {
+        let addr = *addr as u32;
+        // Set Touch Screen Calibration
+        // Top Left Corner
+        HW::write_mem(&mut firmware, addr + 0x58, 0u16);
+        HW::write_mem(&mut firmware, addr + 0x5A, 0u16);
+        firmware[addr as usize + 0x5C] = 0;
+        firmware[addr as usize + 0x5D] = 0;
+        // Bottom Right Corner
+        HW::write_mem(&mut firmware, addr + 0x5E, (max_x as u16) << 4);
+        HW::write_mem(&mut firmware, addr + 0x60, (max_y as u16) << 4);
+        firmware[addr as usize + 0x62] = max_x as u8;
+        firmware[addr as usize + 0x63] = max_y as u8;
+        let crc = crc16(&firmware[addr as usize..addr as usize + USER_SETTINGS_LEN]);
+        HW::write_mem(&mut firmware, addr + 0x72, crc);
+    }
+    firmware
+}
+
+// Firmware too small for both copies of the user settings has none. init replaces it with the default firmware.
+pub fn user_settings_addrs(firmware: &[u8]) -> Option<[usize; 2]> {
+    let last_addr = firmware.len().checked_sub(0x200)?;
+    let addr = u16::from_le_bytes([firmware[0x20], firmware[0x21]]) as usize * 8;
+    let addr = if addr == 0 || addr > last_addr { last_addr } else { addr };
+    Some([addr, addr + 0x100])
+}
+
+// The copy with the newer update count is in use
+pub fn user_settings(firmware: &[u8]) -> &[u8] {
+    let [addr0, addr1] = user_settings_addrs(firmware).unwrap();
+    let count = |addr: usize| firmware[addr + 0x70] & 0x7F;
+    let addr = if count(addr0) == (count(addr1) + 1) & 0x7F { addr0 } else { addr1 };
+    &firmware[addr..addr + USER_SETTINGS_LEN]
+}
+
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0xFFFF;
+    let vals = [0xC0C1, 0xC181, 0xC301, 0xC601, 0xCC01, 0xD801, 0xF001, 0xA001];
+    for byte in data.iter() {
+        crc ^= *byte as u32;
+        for (i, val) in vals.iter().enumerate() {
+            let new_crc = crc >> 1;
+            crc = if crc & 0x1 != 0 { // Carry Occurred
+                new_crc ^ (val << (7 - i))
+            } else { new_crc };
+        }
+    }
+    crc as u16
+}
@@ -0,0 +1,69 @@
+use std::collections::VecDeque;
+
+use crate::nds::NDS;
+
+pub struct Microphone {
+    samples: VecDeque<i16>,
+    sample_rate: usize,
+    // Fraction of a sample in units of input sample rate / clock rate
+    sample_progress: usize,
+    prev_cycle: usize,
+    blowing: bool,
+    noise: u16,
+}
+
+// Buffered samples come from the host
+savestate!(Microphone { prev_cycle, noise });
+
+impl Microphone {
+    // Avoid latency building up if the frontend feeds samples faster than they're read
+    const MAX_BUFFERED_SECS: usize = 1;
+
+    pub fn new() -> Self {
+        Microphone {
+            samples: VecDeque::new(),
+            sample_rate: 0,
+            sample_progress: 0,
+            prev_cycle: 0,
+            blowing: false,
+            noise: 0xACE1,
+        }
+    }
+
+    pub fn feed_samples(&mut self, samples: &[i16], sample_rate: usize) {
+        if sample_rate != self.sample_rate {
+            self.samples.clear();
+            self.sample_rate = sample_rate;
+            self.sample_progress = 0;
+        }
+        self.samples.extend(samples.iter());
+        let max_len = sample_rate * Microphone::MAX_BUFFERED_SECS;
+        if self.samples.len() > max_len { self.samples.drain(..self.samples.len() - max_len); }
+    }
+
+    pub fn set_blowing(&mut self, blowing: bool) { self.blowing = blowing }
+    pub fn blowing(&self) -> bool { self.blowing }
+
+    pub fn advance(&mut self, cycle: usize) {
+        let cycles_passed = cycle - self.prev_cycle;
+        self.prev_cycle = cycle;
+        if self.samples.is_empty() { self.sample_progress = 0; return }
+        self.sample_progress += cycles_passed * self.sample_rate;
+        let samples_passed = self.sample_progress / NDS::CLOCK_RATE;
+        self.sample_progress %= NDS::CLOCK_RATE;
+        // Keep the last sample until more data arrives
+        let samples_passed = samples_passed.min(self.samples.len() - 1);
+        self.samples.drain(..samples_passed);
+    }
+
+    // 12 bit unsigned sample
+    pub fn sample(&mut self) -> u16 {
+        let sample = if self.blowing {
+            // Blowing into the microphone is loud noise
+            let bit = (self.noise ^ self.noise >> 2 ^ self.noise >> 3 ^ self.noise >> 5) & 0x1;
+            self.noise = self.noise >> 1 | bit << 15;
+            self.noise as i16
+        } else { self.samples.front().copied().unwrap_or(0) };
+        (0x800 + (sample >> 4)) as u16
+    }
+}
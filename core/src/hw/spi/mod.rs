@@ -1,92 +1,130 @@
+mod firmware;
+mod mic;
+mod powerman;
 mod tsc;
 
-use super::{HW, GPU, mem::IORegister, Scheduler};
+use super::{
+    HW,
+    mem::IORegister,
+    interrupt_controller::InterruptRequest,
+    scheduler::{Event, Scheduler},
+};
+use crate::fault::{Fault, Faults};
 use crate::hw::cartridge::{Backup, Flash};
+use powerman::PowerManager;
 use tsc::TSC;
 
+pub trait SPIDevice {
+    // Exchanges a byte with the device
+    fn transfer(&mut self, value: u8) -> u8;
+    // Chip select was released
+    fn deselect(&mut self);
+}
+
 pub struct SPI {
     cnt: CNT,
+    data: u8,
+    pending_value: u8,
+    powerman: PowerManager,
     firmware: Flash,
     tsc: TSC,
+    faults: Faults,
 }
 
+savestate!(SPI { cnt, data, pending_value, powerman, firmware, tsc });
+
 impl SPI {
-    pub fn new(firmware: Vec<u8>) -> Self {
+    // Cycles per bit for each baudrate: 4MHz, 2MHz, 1MHz, 512KHz
+    const CYCLES_PER_BIT: [usize; 4] = [8, 16, 32, 64];
+
+    pub fn new(firmware: Option<Vec<u8>>, faults: Faults) -> Self {
         SPI {
             cnt: CNT::new(),
-            firmware: Flash::new_firmware(SPI::init_firmware(firmware)),
+            data: 0,
+            pending_value: 0,
+            powerman: PowerManager::new(),
+            firmware: Flash::new_firmware(firmware::init(firmware)),
             tsc: TSC::new(),
+            faults,
         }
     }
 
     pub fn read_cnt(&self, byte: usize) -> u8 { if self.cnt.enable { self.cnt.read(byte) } else { 0 } }
-    pub fn read_data(&self) -> u8 {
-        match self.cnt.device {
-            Device::Firmware => self.firmware.read(),
-            Device::Touchscreen => self.tsc.read(),
-            _ => 0,
-        }
-    }
+    pub fn read_data(&self) -> u8 { if self.cnt.enable { self.data } else { 0 } }
     
     pub fn write_cnt(&mut self, scheduler: &mut Scheduler, byte: usize, value: u8) {
         let prev_enable = self.cnt.enable;
         let prev_device = self.cnt.device;
+        if byte == 1 && value & 0x3 == 3 {
+            self.faults.raise(Fault::InvalidValue("SPI Device 3".to_string()));
+            return
+        }
         self.cnt.write(scheduler, byte, value);
         if prev_enable && !self.cnt.enable {
             // Disabling requires device to be reset for libnds to work
-            match prev_device {
-                Device::Firmware => self.firmware.deselect(),
-                Device::Touchscreen => self.tsc.deselect(),
-                _ => (),
-            }
+            self.device(prev_device).deselect();
         }
     }
 
-    pub fn write_data(&mut self, value: u8) {
-        if !self.cnt.enable { return }
-        match self.cnt.device {
-            Device::Firmware => self.firmware.write(self.cnt.hold, value),
-            Device::Touchscreen => self.tsc.write(value),
-            _ => (),
+    pub fn write_data(&mut self, scheduler: &mut Scheduler, value: u8) {
+        if !self.cnt.enable || self.cnt.busy { return }
+        self.cnt.busy = true;
+        self.pending_value = value;
+        scheduler.schedule(Event::SPITransferFinished, HW::on_spi_transfer_finished,
+            8 * SPI::CYCLES_PER_BIT[self.cnt.baudrate as usize]);
+    }
+
+    fn finish_transfer(&mut self, cycle: usize) -> bool {
+        self.tsc.mic.advance(cycle);
+        self.cnt.busy = false;
+        let (value, hold) = (self.pending_value, self.cnt.hold);
+        let device = self.device(self.cnt.device);
+        let data = device.transfer(value);
+        if !hold { device.deselect() }
+        self.data = data;
+        self.cnt.irq
+    }
+
+    fn device(&mut self, device: Device) -> &mut dyn SPIDevice {
+        match device {
+            Device::Powerman => &mut self.powerman,
+            Device::Firmware => &mut self.firmware,
+            Device::Touchscreen => &mut self.tsc,
         }
     }
 
     pub fn press_screen(&mut self, x: usize, y: usize) { self.tsc.press_screen(x, y) }
     pub fn release_screen(&mut self) { self.tsc.release_screen() }
-    pub fn init_firmware(firmware: Vec<u8>) -> Vec<u8> {
-        let mut firmware = firmware;
-        let user_settings_addr = 0x3FE00;
-
-        // Set Touch Screen Calibration
-        let max_x = GPU::WIDTH - 1;
-        let max_y = GPU::HEIGHT - 1;
-        // Top Left Corner
-        HW::write_mem(&mut firmware, user_settings_addr + 0x58, 0u16);
-        HW::write_mem(&mut firmware, user_settings_addr + 0x5A, 0u16);
-        firmware[user_settings_addr as usize + 0x5C] = 0;
-        firmware[user_settings_addr as usize + 0x5D] = 0;
-        // Bottom Right Corner
-        HW::write_mem(&mut firmware, user_settings_addr + 0x5E, (max_x as u16) << 4);
-        HW::write_mem(&mut firmware, user_settings_addr + 0x60, (max_y as u16) << 4);
-        firmware[user_settings_addr as usize + 0x62] = max_x as u8;
-        firmware[user_settings_addr as usize + 0x63] = max_y as u8;
-        let crc16 = {
-            let mut crc = 0xFFFF;
-            let vals = [0xC0C1, 0xC181, 0xC301, 0xC601, 0xCC01, 0xD801, 0xF001, 0xA001];
-            for byte in firmware[user_settings_addr as usize..user_settings_addr as usize + 0x70].iter() {
-                crc ^= *byte as u32;
-                for (i, val) in vals.iter().enumerate() {
-                    let new_crc = crc >> 1;
-                    crc = if crc & 0x1 != 0 { // Carry Occurred
-                        new_crc ^ (val << (7 - i)) 
-                    } else { new_crc };
-                }
-            }
-            crc as u16
-        };
-        HW::write_mem(&mut firmware, user_settings_addr + 0x72, crc16);
-        firmware
+    pub fn touch_position(&self) -> (usize, usize) { self.tsc.touch_position() }
+    pub fn feed_mic_samples(&mut self, samples: &[i16], sample_rate: usize) {
+        self.tsc.mic.feed_samples(samples, sample_rate)
     }
+    pub fn set_mic_blowing(&mut self, blowing: bool) { self.tsc.mic.set_blowing(blowing) }
+    pub fn mic_blowing(&self) -> bool { self.tsc.mic.blowing() }
+    pub fn powered_off(&self) -> bool { self.powerman.powered_off() }
+    pub fn set_battery_low(&mut self, low: bool) { self.powerman.set_battery_low(low) }
+    pub fn set_external_power(&mut self, connected: bool) { self.powerman.set_external_power(connected) }
+    pub fn battery_low(&self) -> bool { self.powerman.battery_low() }
+    pub fn external_power(&self) -> bool { self.powerman.external_power() }
+    pub fn set_backlight_level(&mut self, level: u8) { self.powerman.set_backlight_level(level) }
+    pub fn backlight(&self) -> [Option<u8>; 2] { self.powerman.backlight() }
+    pub fn user_settings(&self) -> &[u8] { firmware::user_settings(self.firmware.data()) }
+    pub fn user_settings_addr(&self) -> usize { firmware::user_settings_addrs(self.firmware.data()).unwrap()[0] }
+}
+
+impl HW {
+    pub(super) fn on_spi_transfer_finished(&mut self, _event: Event) {
+        if self.spi.finish_transfer(self.scheduler.cycle) { self.interrupts[0].request |= InterruptRequest::SPI }
+    }
+}
+
+impl SPIDevice for Flash {
+    fn transfer(&mut self, value: u8) -> u8 {
+        Backup::write(self, true, value);
+        Backup::read(self)
+    }
+
+    fn deselect(&mut self) { Flash::deselect(self) }
 }
 
 pub struct CNT {
@@ -99,6 +137,8 @@ pub struct CNT {
     enable: bool,
 }
 
+savestate!(CNT { baudrate, busy, device, transfer16, hold, irq, enable });
+
 impl CNT {
     pub fn new() -> Self {
         CNT {
@@ -125,17 +165,14 @@ impl IORegister for CNT {
 
     fn write(&mut self, _scheduler: &mut Scheduler, byte: usize, value: u8) {
         match byte {
-            0 => {
-                // TODO: Set busy flag properly
-                self.baudrate = value & 0x3;
-            },
+            0 => self.baudrate = value & 0x3,
             1 => {
                 self.enable = value >> 7 & 0x1 != 0;
                 self.irq = value >> 6 & 0x1 != 0;
-                assert!(!self.irq);
                 self.hold = value >> 3 & 0x1 != 0;
                 self.transfer16 = value >> 2 & 0x1 != 0;
-                assert!(!self.transfer16);
+                // Only the lower 8 bits are transferred in 16 bit mode
+                if self.transfer16 { warn!("16 bit SPI transfers are bugged") }
                 self.device = Device::from_bits(value & 0x3);
             },
             _ => unreachable!(),
@@ -151,13 +188,14 @@ enum Device {
     Touchscreen = 2,
 }
 
+savestate_enum!(Device { Powerman, Firmware, Touchscreen });
+
 impl Device {
     pub fn from_bits(value: u8) -> Self {
         match value {
             0 => Self::Powerman,
             1 => Self::Firmware,
             2 => Self::Touchscreen,
-            3 => panic!("Reserved SPI Device!"),
             _ => unreachable!(),
         }
     }
@@ -0,0 +1,109 @@
+use bitflags::*;
+
+use super::SPIDevice;
+
+pub struct PowerManager {
+    // Index byte is sent first, followed by data
+    index: Option<u8>,
+    read: bool,
+
+    control: Control,
+    battery_low: bool,
+    // Whether the DS is plugged in, which the frontend sets like the microphone input
+    external_power: bool,
+    mic_amp_enable: bool,
+    mic_amp_gain: u8,
+    backlight_level: u8,
+}
+
+savestate!(PowerManager { index, read, control, battery_low, mic_amp_enable, mic_amp_gain, backlight_level });
+
+impl PowerManager {
+    pub fn new() -> Self {
+        PowerManager {
+            index: None,
+            read: false,
+
+            control: Control::SOUND_AMP_ENABLE | Control::LOWER_BACKLIGHT | Control::UPPER_BACKLIGHT,
+            battery_low: false,
+            external_power: false,
+            mic_amp_enable: false,
+            mic_amp_gain: 0,
+            backlight_level: 3,
+        }
+    }
+
+    pub fn powered_off(&self) -> bool { self.control.contains(Control::POWER_OFF) }
+    pub fn set_battery_low(&mut self, low: bool) { self.battery_low = low }
+    pub fn set_external_power(&mut self, connected: bool) { self.external_power = connected }
+    pub fn battery_low(&self) -> bool { self.battery_low }
+    pub fn external_power(&self) -> bool { self.external_power }
+    pub fn set_backlight_level(&mut self, level: u8) {
+        self.backlight_level = self.backlight_level & !0x3 | level & 0x3;
+    }
+
+    // Top then bottom, from 0 to 3 or None when that screen's backlight is off.
+    // A DS Lite can be told to use the brightest level while it's plugged in.
+    pub fn backlight(&self) -> [Option<u8>; 2] {
+        let level = if self.backlight_level & 0x4 != 0 && self.external_power { 3 } else { self.backlight_level & 0x3 };
+        [Control::UPPER_BACKLIGHT, Control::LOWER_BACKLIGHT]
+            .map(|screen| if self.control.contains(screen) { Some(level) } else { None })
+    }
+
+    fn read_register(&self, index: u8) -> u8 {
+        match index {
+            0 => self.control.bits,
+            1 => self.battery_low as u8,
+            2 => self.mic_amp_enable as u8,
+            3 => self.mic_amp_gain,
+            4 => self.backlight_level | (self.external_power as u8) << 3, // DS Lite
+            _ => { warn!("Reading from Unknown Power Management Register {}", index); 0 },
+        }
+    }
+
+    fn write_register(&mut self, index: u8, value: u8) {
+        match index {
+            0 => {
+                self.control = Control::from_bits_truncate(value);
+                if self.powered_off() { info!("Powering Off") }
+            },
+            1 => (), // Read Only
+            2 => self.mic_amp_enable = value & 0x1 != 0,
+            3 => self.mic_amp_gain = value & 0x3,
+            4 => self.backlight_level = value & 0x7,
+            _ => warn!("Writing to Unknown Power Management Register {} = 0x{:X}", index, value),
+        }
+    }
+}
+
+impl SPIDevice for PowerManager {
+    fn transfer(&mut self, value: u8) -> u8 {
+        match self.index {
+            None => {
+                self.read = value & 0x80 != 0;
+                self.index = Some(value & 0x7F);
+                0
+            },
+            Some(index) if self.read => self.read_register(index),
+            Some(index) => { self.write_register(index, value); 0 },
+        }
+    }
+
+    fn deselect(&mut self) {
+        self.index = None;
+    }
+}
+
+bitflags! {
+    struct Control: u8 {
+        const SOUND_AMP_ENABLE = 1 << 0;
+        const SOUND_AMP_MUTE = 1 << 1;
+        const LOWER_BACKLIGHT = 1 << 2;
+        const UPPER_BACKLIGHT = 1 << 3;
+        const POWER_LED_BLINK = 1 << 4;
+        const POWER_LED_BLINK_FAST = 1 << 5;
+        const POWER_OFF = 1 << 6;
+    }
+}
+
+savestate_bitflags!(Control);
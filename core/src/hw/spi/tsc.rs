@@ -1,58 +1,78 @@
+use super::{mic::Microphone, SPIDevice};
+
 pub struct TSC {
     x: u16,
     y: u16,
+    pub mic: Microphone,
 
     pos: usize,
     value: u16,
-    return_byte: u8,
+    mode8: bool,
 }
 
+// The touch position comes from the host
+savestate!(TSC { mic, pos, value, mode8 });
+
 impl TSC {
     pub fn new() -> Self {
         TSC {
             x: 0,
             y: 0,
+            mic: Microphone::new(),
 
             pos: 0,
             value: 0,
-            return_byte: 0,
+            mode8: false,
         }
     }
 
-    pub fn read(&self) -> u8 {
-        self.return_byte
+    pub fn press_screen(&mut self, x: usize, y: usize) {
+        self.x = (x as u16) << 4;
+        self.y = (y as u16) << 4;
+    }
+
+    pub fn touch_position(&self) -> (usize, usize) {
+        ((self.x >> 4) as usize, (self.y >> 4) as usize)
     }
 
-    pub fn write(&mut self, value: u8) {
-        self.return_byte = match self.pos {
-            0 => self.value >> 5,
-            1 => self.value << 3,
-            _ => 0,
+    pub fn release_screen(&mut self) {
+        self.x = 0;
+        self.y = 0xFFF;
+    }
+}
+
+impl SPIDevice for TSC {
+    fn transfer(&mut self, value: u8) -> u8 {
+        let return_byte = if self.mode8 {
+            let value = self.value >> 4;
+            match self.pos {
+                0 => value >> 1,
+                1 => value << 7,
+                _ => 0,
+            }
+        } else {
+            match self.pos {
+                0 => self.value >> 5,
+                1 => self.value << 3,
+                _ => 0,
+            }
         } as u8;
 
         if value & 0x80 != 0 {
             let channel = value >> 4 & 0x7;
             self.pos = 0;
+            self.mode8 = value >> 3 & 0x1 != 0;
             self.value = match channel {
                 1 => self.y,
                 5 => self.x,
-                6 => 0, // TODO: Microphone,
+                6 => self.mic.sample(),
                 _ => 0xFFF,
             };
         } else { self.pos += 1 }
+        return_byte
     }
 
-    pub fn deselect(&mut self) {
+    fn deselect(&mut self) {
         self.pos = 0;
     }
-
-    pub fn press_screen(&mut self, x: usize, y: usize) {
-        self.x = (x as u16) << 4;
-        self.y = (y as u16) << 4;
-    }
-
-    pub fn release_screen(&mut self) {
-        self.x = 0;
-        self.y = 0xFFF;
-    }
 }
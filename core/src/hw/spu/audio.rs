@@ -1,83 +1,77 @@
-use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use ringbuf::RingBuffer;
+use std::sync::{Arc, Mutex};
 
-pub struct Audio {
-    config: cpal::StreamConfig,
-    _stream: cpal::Stream,
-    prod: ringbuf::Producer<[f32; 2]>,
-}
+use super::resampler::Resampler;
 
-impl Audio {
-    const BUFFER_LEN: usize = 2048;
+pub trait AudioSink: Send {
+    // Stereo samples in the range [-1.0, 1.0] at sample_rate()
+    fn push_samples(&mut self, samples: &[[f32; 2]]);
+    fn sample_rate(&self) -> usize;
+    // Fraction of the sink's buffer that is full, used for dynamic rate control if available
+    fn buffer_fill(&self) -> Option<f64> { None }
+}
 
-    pub fn new() -> Self {
-        let host = cpal::default_host();
-        let device = host.default_output_device().expect("No audio output device available!");
-        let config = device.default_output_config().expect("No audio output config available!");
+// Collects samples for frontends that pull audio after each frame. Clones share the same queue,
+// so one can be handed to the NDS and the other kept to take samples from.
+#[derive(Clone)]
+pub struct SampleQueue {
+    sample_rate: usize,
+    samples: Arc<Mutex<Vec<[f32; 2]>>>,
+}
 
-        match config.sample_format() {
-            cpal::SampleFormat::F32 => Audio::init::<f32>(device, config.into()),
-            cpal::SampleFormat::I16 => Audio::init::<i16>(device, config.into()),
-            cpal::SampleFormat::U16 => Audio::init::<u16>(device, config.into()),
+impl SampleQueue {
+    pub fn new(sample_rate: usize) -> Self {
+        SampleQueue {
+            sample_rate,
+            samples: Arc::new(Mutex::new(Vec::new())),
         }
     }
 
-    fn init<T: cpal::Sample>(device: cpal::Device, config: cpal::StreamConfig) -> Self {
-        let buffer = RingBuffer::<[f32; 2]>::new(Audio::BUFFER_LEN);
-        let (prod, mut cons) = buffer.split();
-
-        let output_config = OutputConfig::from(config.channels);
-        let stream = device.build_output_stream(
-            &config,
-            move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
-                for frame in data.chunks_mut(output_config as usize) {
-                    let samples = cons.pop().unwrap_or_else(|| [0.0, 0.0]);
-                    match output_config {
-                        OutputConfig::Mono => {
-                            let sample = samples.iter().sum::<f32>() / 2.0;
-                            frame[0] = cpal::Sample::from::<f32>(&sample);
-                        },
-                        OutputConfig::Stereo => {
-                            frame[0] = cpal::Sample::from::<f32>(&(samples[0]));
-                            frame[1] = cpal::Sample::from::<f32>(&(samples[1]));
-                        },
-                    }
-                }
-            },
-            |err| error!("Audio Stream Error: {}", err),
-        ).unwrap();
-        stream.play().unwrap();
-
-        Audio {
-            config,
-            _stream: stream,
-            prod,
-        }
+    pub fn take(&self) -> Vec<[f32; 2]> {
+        std::mem::take(&mut self.samples.lock().unwrap())
     }
+}
 
-    pub fn push_sample(&mut self, left_sample: f32, right_sample: f32) {
-        while self.prod.is_full() {} // TODO: Block thread instead of using CPU
-        self.prod.push([left_sample, right_sample]).unwrap();
+impl AudioSink for SampleQueue {
+    fn push_samples(&mut self, samples: &[[f32; 2]]) {
+        self.samples.lock().unwrap().extend_from_slice(samples);
     }
 
-    pub fn sample_rate(&self) -> usize {
-        self.config.sample_rate.0 as usize
+    fn sample_rate(&self) -> usize {
+        self.sample_rate
     }
 }
 
-#[derive(Clone, Copy)]
-enum OutputConfig {
-    Mono = 1,
-    Stereo = 2,
+pub struct Audio {
+    sink: Box<dyn AudioSink>,
+    resampler: Resampler,
+    base_ratio: f64,
+    samples: Vec<[f32; 2]>,
 }
 
-impl From<u16> for OutputConfig {
-    fn from(value: u16) -> Self {
-        use OutputConfig::*;
-        match value {
-            1 => Mono,
-            2 => Stereo,
-            _ => panic!("Only Mono and Stereo audio devices supported!"),
+impl Audio {
+    // Maximum amount the resampling ratio can be adjusted by to keep the buffer half full
+    const MAX_RATE_DELTA: f64 = 0.005;
+
+    pub fn new(sink: Box<dyn AudioSink>, input_sample_rate: f64) -> Self {
+        let resampler = Resampler::new(input_sample_rate, sink.sample_rate() as f64);
+        Audio {
+            sink,
+            base_ratio: resampler.ratio(),
+            resampler,
+            samples: Vec::new(),
+        }
+    }
+
+    pub fn push_sample(&mut self, left_sample: f32, right_sample: f32) {
+        if let Some(fill) = self.sink.buffer_fill() {
+            // Dynamic rate control: produce fewer samples when buffer is filling up and more when it's draining
+            self.resampler.set_ratio(self.base_ratio * (1.0 + (2.0 * fill - 1.0) * Audio::MAX_RATE_DELTA));
+        }
+        let samples = &mut self.samples;
+        self.resampler.push_sample([left_sample, right_sample], |sample| samples.push(sample));
+        if !self.samples.is_empty() {
+            self.sink.push_samples(&self.samples);
+            self.samples.clear();
         }
     }
 }
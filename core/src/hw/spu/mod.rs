@@ -1,6 +1,19 @@
 mod registers;
 mod audio;
-
+mod resampler;
+#[cfg(feature = "host")]
+mod recorder;
+
+use std::collections::VecDeque;
+#[cfg(feature = "host")]
+use std::io;
+#[cfg(feature = "simd")]
+use std::simd::{num::SimdInt, i32x16};
+#[cfg(feature = "host")]
+use std::path::Path;
+
+use crate::fault::{Fault, Faults};
+use crate::savestate::{Savestate, StateReader, StateWriter};
 use super::{
     HW,
     mem::IORegister,
@@ -9,6 +22,10 @@ use super::{
 
 use registers::*;
 use audio::Audio;
+pub use audio::{AudioSink, SampleQueue};
+pub use registers::Format as ChannelFormat;
+#[cfg(feature = "host")]
+use recorder::Recorder;
 
 pub struct SPU {
     cnt: SoundControl,
@@ -16,13 +33,25 @@ pub struct SPU {
     captures: [Capture; 2],
     // Sound Generation
     audio: Audio,
-    clocks_per_sample: usize,
+    // Frames replayed by netplay have already been heard
+    output_muted: bool,
+    #[cfg(feature = "host")]
+    recorder: Option<Recorder>,
+    #[cfg(feature = "host")]
+    captured_samples: Option<Vec<(i16, i16)>>,
+    // Debugging
+    muted_channels: [bool; SPU::NUM_CHANNELS],
+    soloed_channels: [bool; SPU::NUM_CHANNELS],
     // Channels
     pub base_channels: [Channel<BaseChannel>; 8],
     pub psg_channels: [Channel<PSGChannel>; 6],
     pub noise_channels: [Channel<NoiseChannel>; 2],
+    faults: Faults,
 }
 
+// Audio output, recording and the debug mute state belong to the host
+savestate!(SPU { cnt, sound_bias, captures, base_channels, psg_channels, noise_channels });
+
 macro_rules! create_channels {
     ($type:ident, $spec:ident, $( $num:expr ), *) => {
         [
@@ -46,60 +75,179 @@ impl SPU {
         0x5771, 0x602F, 0x69CE, 0x7462, 0x7FFF
     ];
 
-    pub fn new(scheduler: &mut Scheduler) -> Self {
-        let audio = Audio::new();
-        // TODO: Sample at 32.768 kHz and resample to device sample rate
-        let clocks_per_sample = crate::nds::NDS::CLOCK_RATE / audio.sample_rate();
-        scheduler.schedule(Event::GenerateAudioSample, HW::generate_audio_sample, clocks_per_sample);
+    pub const CLOCKS_PER_SAMPLE: usize = 1024;
+    pub const NUM_CHANNELS: usize = 16;
+
+    pub fn new(scheduler: &mut Scheduler, audio_sink: Box<dyn AudioSink>, faults: Faults) -> Self {
+        // Mixer runs at ~32.768 kHz and is resampled to the device sample rate
+        let audio = Audio::new(audio_sink, crate::nds::NDS::CLOCK_RATE as f64 / SPU::CLOCKS_PER_SAMPLE as f64);
+        scheduler.schedule(Event::GenerateAudioSample, HW::generate_audio_sample, SPU::CLOCKS_PER_SAMPLE);
         SPU {
             cnt: SoundControl::new(),
-            sound_bias: 0,
+            sound_bias: 0x200, // Set by firmware during boot
             captures: [Capture::new(), Capture::new()],
             // Sound Generation
             audio,
-            clocks_per_sample,
+            output_muted: false,
+            #[cfg(feature = "host")]
+            recorder: None,
+            #[cfg(feature = "host")]
+            captured_samples: None,
+            // Debugging
+            muted_channels: [false; SPU::NUM_CHANNELS],
+            soloed_channels: [false; SPU::NUM_CHANNELS],
             // Channels
             base_channels: create_channels!(BaseChannel, Base, 0, 1, 2, 3, 4, 5, 6, 7),
             psg_channels: create_channels!(PSGChannel, PSG, 0, 1, 2, 3, 4, 5),
             noise_channels: create_channels!(NoiseChannel, Noise, 0, 1),
+            faults,
         }
     }
 
-    fn generate_mixer(&self) -> ((i32, i32), (i32, i32), (i32, i32)) {
-        let mut mixer = (0, 0);
-        for i in (0..1).chain(2..3).chain(4..self.base_channels.len()) { self.base_channels[i].generate_sample(&mut mixer) }
-        for channel in self.psg_channels.iter() { channel.generate_sample(&mut mixer) }
-        for channel in self.noise_channels.iter() { channel.generate_sample(&mut mixer) }
+    // Muting is only applied to audio output so captured data isn't affected
+    fn generate_mixer(&self, apply_mute: bool) -> ((i32, i32), (i32, i32), (i32, i32)) {
+        let audible = |num: usize| !apply_mute || self.channel_audible(num);
+        let mut samples = [0; SPU::NUM_CHANNELS];
+        let mut left_factors = [0; SPU::NUM_CHANNELS];
+        let mut right_factors = [0; SPU::NUM_CHANNELS];
+        for (i, channel) in self.base_channels.iter().enumerate() {
+            // Channels 1 and 3 are mixed separately since they can be routed around the mixer
+            if i != 1 && i != 3 && audible(i) {
+                (samples[i], left_factors[i], right_factors[i]) = channel.weighted_sample();
+            }
+        }
+        for (i, channel) in self.psg_channels.iter().enumerate() { if audible(8 + i) {
+            (samples[8 + i], left_factors[8 + i], right_factors[8 + i]) = channel.weighted_sample();
+        } }
+        for (i, channel) in self.noise_channels.iter().enumerate() { if audible(14 + i) {
+            (samples[14 + i], left_factors[14 + i], right_factors[14 + i]) = channel.weighted_sample();
+        } }
+        let mut mixer = mix(&samples, &left_factors, &right_factors);
         let (mut ch1, mut ch3) = ((0, 0), (0, 0));
-        self.base_channels[1].generate_sample(&mut ch1);
-        self.base_channels[3].generate_sample(&mut ch3);
+        if audible(1) { self.base_channels[1].generate_sample(&mut ch1) }
+        if audible(3) { self.base_channels[3].generate_sample(&mut ch3) }
         if self.cnt.output_1 { mixer.0 += ch1.0; mixer.1 += ch1.1 }
         if self.cnt.output_3 { mixer.0 += ch3.0; mixer.1 += ch3.1 }
         (mixer, ch1, ch3)
     }
 
     pub fn generate_sample(&mut self) {
-        let (mixer, ch1, ch3) = self.generate_mixer();
+        let (mixer, ch1, ch3) = self.generate_mixer(true);
         let left_sample = match self.cnt.left_output {
             ChannelOutput::Mixer => mixer.0,
             ChannelOutput::Ch1 => ch1.0,
             ChannelOutput::Ch3 => ch3.0,
-            ChannelOutput::Ch1Ch3 => todo!(),
+            ChannelOutput::Ch1Ch3 => ch1.0 + ch3.0,
         } >> 16;
         let right_sample = match self.cnt.right_output {
             ChannelOutput::Mixer => mixer.1,
             ChannelOutput::Ch1 => ch1.1,
             ChannelOutput::Ch3 => ch3.1,
-            ChannelOutput::Ch1Ch3 => todo!(),
+            ChannelOutput::Ch1Ch3 => ch1.1 + ch3.1,
         } >> 16;
         let final_sample = (
-            ((left_sample * self.cnt.master_volume()) >> 7) as i16,
-            ((right_sample * self.cnt.master_volume()) >> 7) as i16,
-        );
-        self.audio.push_sample(
-            cpal::Sample::from::<i16>(&final_sample.0),
-            cpal::Sample::from::<i16>(&final_sample.1),
+            self.apply_bias((left_sample * self.cnt.master_volume()) >> 7),
+            self.apply_bias((right_sample * self.cnt.master_volume()) >> 7),
         );
+        self.push_sample(final_sample);
+    }
+
+    pub fn push_sample(&mut self, final_sample: (i16, i16)) {
+        if !self.output_muted {
+            self.audio.push_sample(
+                final_sample.0 as f32 / 0x8000 as f32,
+                final_sample.1 as f32 / 0x8000 as f32,
+            );
+        }
+        #[cfg(feature = "host")]
+        if let Some(mut recorder) = self.recorder.take() {
+            recorder.write_mixer(final_sample);
+            if recorder.records_channels() { recorder.write_channels(&self.channel_samples()) }
+            self.recorder = Some(recorder);
+        }
+        #[cfg(feature = "host")]
+        if let Some(captured_samples) = self.captured_samples.as_mut() { captured_samples.push(final_sample) }
+    }
+
+    #[cfg(feature = "host")]
+    fn channel_samples(&self) -> [(i16, i16); SPU::NUM_CHANNELS] {
+        let mut samples = [(0, 0); SPU::NUM_CHANNELS];
+        let mut channel_samples = [(0, 0); SPU::NUM_CHANNELS];
+        for (i, channel) in self.base_channels.iter().enumerate() { channel.generate_sample(&mut channel_samples[i]) }
+        for (i, channel) in self.psg_channels.iter().enumerate() { channel.generate_sample(&mut channel_samples[8 + i]) }
+        for (i, channel) in self.noise_channels.iter().enumerate() { channel.generate_sample(&mut channel_samples[14 + i]) }
+        for (sample, channel_sample) in samples.iter_mut().zip(channel_samples.iter()) {
+            // Channel output is a 16 bit sample multiplied by 7 bit volume and pan factors
+            *sample = (
+                (channel_sample.0 >> 14).clamp(i16::MIN as i32, i16::MAX as i32) as i16,
+                (channel_sample.1 >> 14).clamp(i16::MIN as i32, i16::MAX as i32) as i16,
+            );
+        }
+        samples
+    }
+
+    #[cfg(feature = "host")]
+    pub fn start_recording(&mut self, path: &Path, record_channels: bool) -> io::Result<()> {
+        let sample_rate = (crate::nds::NDS::CLOCK_RATE / SPU::CLOCKS_PER_SAMPLE) as u32;
+        self.recorder = Some(Recorder::new(path, sample_rate, record_channels)?);
+        Ok(())
+    }
+
+    #[cfg(feature = "host")]
+    pub fn stop_recording(&mut self) {
+        // WAV headers are finalized when the recorder is dropped
+        self.recorder = None;
+    }
+
+    #[cfg(feature = "host")]
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    #[cfg(feature = "host")]
+    pub fn set_capturing_samples(&mut self, capturing: bool) {
+        self.captured_samples = if capturing { Some(Vec::new()) } else { None };
+    }
+
+    #[cfg(feature = "host")]
+    pub fn take_captured_samples(&mut self) -> Vec<(i16, i16)> {
+        self.captured_samples.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    pub fn set_channel_muted(&mut self, num: usize, muted: bool) {
+        if let Some(channel_muted) = self.muted_channels.get_mut(num) { *channel_muted = muted }
+    }
+
+    pub fn set_output_muted(&mut self, muted: bool) {
+        self.output_muted = muted;
+    }
+
+    pub fn set_channel_soloed(&mut self, num: usize, soloed: bool) {
+        if let Some(channel_soloed) = self.soloed_channels.get_mut(num) { *channel_soloed = soloed }
+    }
+
+    fn channel_audible(&self, num: usize) -> bool {
+        if self.soloed_channels.iter().any(|soloed| *soloed) { self.soloed_channels[num] }
+        else { !self.muted_channels[num] }
+    }
+
+    pub fn channel_state(&self, num: usize) -> Option<ChannelState> {
+        let mut state = match num {
+            0x0 ..= 0x7 => self.base_channels[num].state(),
+            0x8 ..= 0xD => self.psg_channels[num - 0x8].state(),
+            0xE ..= 0xF => self.noise_channels[num - 0xE].state(),
+            _ => return None,
+        };
+        state.muted = self.muted_channels[num];
+        state.soloed = self.soloed_channels[num];
+        state.audible = self.channel_audible(num);
+        Some(state)
+    }
+
+    fn apply_bias(&self, sample: i32) -> i16 {
+        // Hardware outputs 10 bit unsigned samples with the bias added and clipped
+        let output = ((sample >> 6) + self.sound_bias as i32).clamp(0, 0x3FF);
+        ((output - 0x200) << 6) as i16
     }
 
     pub fn capture_addr(&mut self, num: usize) -> Option<(u32, usize, bool)> {
@@ -118,24 +266,17 @@ impl SPU {
     }
 
     pub fn capture_data<T: super::MemoryValue>(&self, capture_i: usize) -> T {
-        let capture_value = if self.captures[capture_i].cnt.use_channel {
-            // TODO: Implement bugged behavior
-            todo!()
-        } else {
-            let (mixer, _, _) = self.generate_mixer();
-            let mixer_value = (if capture_i == 0 { mixer.0 } else { mixer.1 } >> 16) as u16;
-            if std::mem::size_of::<T>() == 1 {
-                mixer_value >> 8
-            } else {
-                mixer_value
-            }
-        };
-        if self.captures[capture_i].cnt.add {
-            // TODO: Implement adding channel
-            todo!()
-        } else {
-            num_traits::cast(capture_value).unwrap()
+        // TODO: Implement bugged behavior of capturing a channel and adding a channel
+        let cnt = &self.captures[capture_i].cnt;
+        if cnt.use_channel || cnt.add {
+            let source = if cnt.use_channel { "a Channel" } else { "the Sum of Two Channels" };
+            self.faults.raise(Fault::Unimplemented(format!("Capturing {}", source)));
+            return num_traits::zero()
         }
+        let (mixer, _, _) = self.generate_mixer(false);
+        let mixer_value = (if capture_i == 0 { mixer.0 } else { mixer.1 } >> 16) as u16;
+        let capture_value = if std::mem::size_of::<T>() == 1 { mixer_value >> 8 } else { mixer_value };
+        num_traits::cast(capture_value).unwrap()
     }
 
     pub fn read_channels(&self, addr: usize) -> u8 {
@@ -168,7 +309,8 @@ impl IORegister for SPU {
         match addr {
             0x400 ..= 0x4FF => self.read_channels(addr),
             0x500 ..= 0x503 => self.cnt.read(addr & 0x3),
-            0x504 ..= 0x507 => HW::read_byte_from_value(&self.sound_bias, addr & 0x3),
+            0x504 ..= 0x505 => HW::read_byte_from_value(&self.sound_bias, addr & 0x1),
+            0x506 ..= 0x507 => 0,
             0x508 ..= 0x509 => self.captures[addr & 0x1].cnt.read(),
             0x510 ..= 0x51F => self.captures[addr >> 3 & 0x1].read(addr & 0xF),
             _ => { warn!("Ignoring SPU Register Read at 0x04000{:03X}", addr); 0 }
@@ -179,10 +321,11 @@ impl IORegister for SPU {
         match addr {
             0x400 ..= 0x4FF => self.write_channels(scheduler, addr & 0xFF, value),
             0x500 ..= 0x503 => self.cnt.write(scheduler, addr & 0x3, value),
-            0x504 ..= 0x507 => {
-                HW::write_byte_to_value(&mut self.sound_bias, addr & 0x3, value);
+            0x504 ..= 0x505 => {
+                HW::write_byte_to_value(&mut self.sound_bias, addr & 0x1, value);
                 self.sound_bias &= 0x3FF;
             },
+            0x506 ..= 0x507 => (),
             0x508 ..= 0x509 => self.captures[addr & 0x1].write_cnt(value),
             0x510 ..= 0x51F => self.captures[addr >> 3 & 0x1].write(addr & 0x7, value),
             _ => warn!("Ignoring SPU Register Write at 0x04000{:03X}", addr)
@@ -190,49 +333,131 @@ impl IORegister for SPU {
     }
 }
 
+#[cfg(not(feature = "simd"))]
+fn mix(samples: &[i32; SPU::NUM_CHANNELS], left_factors: &[i32; SPU::NUM_CHANNELS],
+    right_factors: &[i32; SPU::NUM_CHANNELS]) -> (i32, i32) {
+    samples.iter().zip(left_factors.iter().zip(right_factors.iter()))
+        .fold((0, 0), |(left, right), (sample, (left_factor, right_factor))|
+            (left + sample * left_factor, right + sample * right_factor)
+        )
+}
+
+#[cfg(feature = "simd")]
+fn mix(samples: &[i32; SPU::NUM_CHANNELS], left_factors: &[i32; SPU::NUM_CHANNELS],
+    right_factors: &[i32; SPU::NUM_CHANNELS]) -> (i32, i32) {
+    let samples = i32x16::from_array(*samples);
+    (
+        (samples * i32x16::from_array(*left_factors)).reduce_sum(),
+        (samples * i32x16::from_array(*right_factors)).reduce_sum(),
+    )
+}
+
 impl HW {
-    fn generate_audio_sample(&mut self, _event: Event) {
-        self.scheduler.schedule(Event::GenerateAudioSample, HW::generate_audio_sample, self.spu.clocks_per_sample);
+    #[cfg(feature = "host")]
+    pub fn start_audio_recording(&mut self, path: &Path, record_channels: bool) -> io::Result<()> {
+        self.spu.start_recording(path, record_channels)
+    }
+
+    #[cfg(feature = "host")]
+    pub fn stop_audio_recording(&mut self) {
+        self.spu.stop_recording();
+    }
+
+    #[cfg(feature = "host")]
+    pub fn is_recording_audio(&self) -> bool {
+        self.spu.is_recording()
+    }
+
+    // Mixer output is kept for the video recorder until taken, so it lines up with the frames it was produced during
+    #[cfg(feature = "host")]
+    pub fn set_capturing_samples(&mut self, capturing: bool) {
+        self.spu.set_capturing_samples(capturing);
+    }
+
+    #[cfg(feature = "host")]
+    pub fn take_captured_samples(&mut self) -> Vec<(i16, i16)> {
+        self.spu.take_captured_samples()
+    }
+
+    pub fn set_audio_channel_muted(&mut self, num: usize, muted: bool) {
+        self.spu.set_channel_muted(num, muted);
+    }
+
+    pub fn set_audio_output_muted(&mut self, muted: bool) {
+        self.spu.set_output_muted(muted);
+    }
+
+    pub fn set_audio_channel_soloed(&mut self, num: usize, soloed: bool) {
+        self.spu.set_channel_soloed(num, soloed);
+    }
+
+    pub fn audio_channel_state(&self, num: usize) -> Option<ChannelState> {
+        self.spu.channel_state(num)
+    }
+
+    pub(super) fn generate_audio_sample(&mut self, _event: Event) {
+        self.scheduler.schedule(Event::GenerateAudioSample, HW::generate_audio_sample, SPU::CLOCKS_PER_SAMPLE);
+        if self.gba_mode() { return self.generate_gba_audio_sample() }
+        self.run_audio_channels();
         self.spu.generate_sample();
     }
 
-    fn step_audio_channel(&mut self, event: Event) {
+    // Channels are only stepped when their output is needed, when their registers are written, and when they stop
+    pub fn run_audio_channels(&mut self) {
+        for num in 0..self.spu.base_channels.len() { self.run_audio_channel(ChannelSpec::Base(num)) }
+        for num in 0..self.spu.psg_channels.len() { self.run_audio_channel(ChannelSpec::PSG(num)) }
+        for num in 0..self.spu.noise_channels.len() { self.run_audio_channel(ChannelSpec::Noise(num)) }
+    }
+
+    fn run_audio_channel(&mut self, channel_spec: ChannelSpec) {
+        let cycle = self.scheduler.cycle;
+        while match channel_spec {
+            ChannelSpec::Base(num) => self.spu.base_channels[num].step_due(cycle),
+            ChannelSpec::PSG(num) => self.spu.psg_channels[num].step_due(cycle),
+            ChannelSpec::Noise(num) => self.spu.noise_channels[num].step_due(cycle),
+        } { self.step_audio_channel(channel_spec) }
+    }
+
+    pub(super) fn on_audio_channel_event(&mut self, event: Event) {
         let channel_spec = match event {
             Event::StepAudioChannel(channel_spec) => channel_spec,
             _ => unreachable!(),
         };
+        self.run_audio_channel(channel_spec);
+        match channel_spec {
+            ChannelSpec::Base(num) => self.spu.base_channels[num].schedule(&mut self.scheduler),
+            ChannelSpec::PSG(num) => self.spu.psg_channels[num].schedule(&mut self.scheduler),
+            ChannelSpec::Noise(num) => self.spu.noise_channels[num].schedule(&mut self.scheduler),
+        }
+    }
+
+    fn step_audio_channel(&mut self, channel_spec: ChannelSpec) {
         match channel_spec {
             // TODO: Figure out how to avoid code duplication
-            // TODO: Use SPU FIFO
             ChannelSpec::Base(num) => {
+                self.fill_audio_fifo(channel_spec);
                 let format = self.spu.base_channels[num].format();
                 match format {
                     Format::PCM8 => {
-                        let (addr, reset) = self.spu.base_channels[num].next_addr_pcm::<u8>();
-                        self.spu.base_channels[num].schedule(&mut self.scheduler, reset);
-                        let sample = self.arm7_read::<u8>(addr);
+                        self.spu.base_channels[num].advance_pcm::<u8>();
+                        let sample = self.spu.base_channels[num].read_fifo::<u8>();
                         self.spu.base_channels[num].set_sample(sample);
                     },
                     Format::PCM16 => {
-                        let (addr, reset) = self.spu.base_channels[num].next_addr_pcm::<u16>();
-                        self.spu.base_channels[num].schedule(&mut self.scheduler, reset);
-                        let sample = self.arm7_read::<u16>(addr);
+                        self.spu.base_channels[num].advance_pcm::<u16>();
+                        let sample = self.spu.base_channels[num].read_fifo::<u16>();
                         self.spu.base_channels[num].set_sample(sample);
                     },
                     Format::ADPCM => {
-                        let reset = if let Some(addr) = self.spu.base_channels[num].initial_adpcm_addr() {
-                            let value = self.arm7_read::<u32>(addr);
+                        if self.spu.base_channels[num].in_adpcm_header() {
+                            let value = self.spu.base_channels[num].read_fifo::<u32>();
                             self.spu.base_channels[num].set_initial_adpcm(value);
-                            false
                         } else {
-                            let (addr, reset) = self.spu.base_channels[num].next_addr_adpcm();
-                            let value = self.arm7_read(addr);
+                            let value = self.spu.base_channels[num].next_adpcm_data();
                             self.spu.base_channels[num].set_adpcm_data(value);
-                            reset
-                        };
-                        self.spu.base_channels[num].schedule(&mut self.scheduler, reset);
+                        }
                     },
-                    _ => todo!(),
+                    Format::Special => self.faults.raise(Fault::InvalidValue(format!("Audio Channel {} Format 3", num))),
                 }
                 if let Some((addr, capture_i, use_pcm8)) = self.spu.capture_addr(num) {
                     if use_pcm8 {
@@ -245,79 +470,74 @@ impl HW {
                 }
             },
             ChannelSpec::PSG(num) => {
+                self.fill_audio_fifo(channel_spec);
                 let format = self.spu.psg_channels[num].format();
                 match format {
                     Format::PCM8 => {
-                        let (addr, reset) = self.spu.psg_channels[num].next_addr_pcm::<u8>();
-                        self.spu.psg_channels[num].schedule(&mut self.scheduler, reset);
-                        let sample = self.arm7_read::<u8>(addr);
+                        self.spu.psg_channels[num].advance_pcm::<u8>();
+                        let sample = self.spu.psg_channels[num].read_fifo::<u8>();
                         self.spu.psg_channels[num].set_sample(sample);
                     },
                     Format::PCM16 => {
-                        let (addr, reset) = self.spu.psg_channels[num].next_addr_pcm::<u16>();
-                        self.spu.psg_channels[num].schedule(&mut self.scheduler, reset);
-                        let sample = self.arm7_read::<u16>(addr);
+                        self.spu.psg_channels[num].advance_pcm::<u16>();
+                        let sample = self.spu.psg_channels[num].read_fifo::<u16>();
                         self.spu.psg_channels[num].set_sample(sample);
                     },
                     Format::ADPCM => {
-                        let reset = if let Some(addr) = self.spu.psg_channels[num].initial_adpcm_addr() {
-                            let value = self.arm7_read::<u32>(addr);
+                        if self.spu.psg_channels[num].in_adpcm_header() {
+                            let value = self.spu.psg_channels[num].read_fifo::<u32>();
                             self.spu.psg_channels[num].set_initial_adpcm(value);
-                            false
                         } else {
-                            let (addr, reset) = self.spu.psg_channels[num].next_addr_adpcm();
-                            let value = self.arm7_read(addr);
+                            let value = self.spu.psg_channels[num].next_adpcm_data();
                             self.spu.psg_channels[num].set_adpcm_data(value);
-                            reset
-                        };
-                        self.spu.psg_channels[num].schedule(&mut self.scheduler, reset);
+                        }
                     },
-                    _ => todo!(),
+                    Format::Special => self.faults.raise(Fault::Unimplemented("PSG Audio Channels".to_string())),
                 }
             },
             ChannelSpec::Noise(num) => {
+                self.fill_audio_fifo(channel_spec);
                 let format = self.spu.noise_channels[num].format();
                 match format {
                     Format::PCM8 => {
-                        let (addr, reset) = self.spu.noise_channels[num].next_addr_pcm::<u8>();
-                        self.spu.noise_channels[num].schedule(&mut self.scheduler, reset);
-                        let sample = self.arm7_read::<u8>(addr);
+                        self.spu.noise_channels[num].advance_pcm::<u8>();
+                        let sample = self.spu.noise_channels[num].read_fifo::<u8>();
                         self.spu.noise_channels[num].set_sample(sample);
                     },
                     Format::PCM16 => {
-                        let (addr, reset) = self.spu.noise_channels[num].next_addr_pcm::<u16>();
-                        self.spu.noise_channels[num].schedule(&mut self.scheduler, reset);
-                        let sample = self.arm7_read::<u16>(addr);
+                        self.spu.noise_channels[num].advance_pcm::<u16>();
+                        let sample = self.spu.noise_channels[num].read_fifo::<u16>();
                         self.spu.noise_channels[num].set_sample(sample);
                     },
                     Format::ADPCM => {
-                        let reset = if let Some(addr) = self.spu.noise_channels[num].initial_adpcm_addr() {
-                            let value = self.arm7_read::<u32>(addr);
+                        if self.spu.noise_channels[num].in_adpcm_header() {
+                            let value = self.spu.noise_channels[num].read_fifo::<u32>();
                             self.spu.noise_channels[num].set_initial_adpcm(value);
-                            false
                         } else {
-                            let (addr, reset) = self.spu.noise_channels[num].next_addr_adpcm();
-                            let value = self.arm7_read(addr);
+                            let value = self.spu.noise_channels[num].next_adpcm_data();
                             self.spu.noise_channels[num].set_adpcm_data(value);
-                            reset
-                        };
-                        self.spu.noise_channels[num].schedule(&mut self.scheduler, reset);
+                        }
                     },
-                    _ => todo!(),
+                    Format::Special => self.faults.raise(Fault::Unimplemented("Noise Audio Channels".to_string())),
                 }
             },
         }
     }
 
-    fn reset_audio_channel(&mut self, event: Event) {
-        let channel_spec = match event {
-            Event::ResetAudioChannel(channel_spec) => channel_spec,
-            _ => unreachable!(),
-        };
+    fn fill_audio_fifo(&mut self, channel_spec: ChannelSpec) {
         match channel_spec {
-            ChannelSpec::Base(num) => self.spu.base_channels[num].reset_sample(),
-            ChannelSpec::PSG(num) => self.spu.psg_channels[num].reset_sample(),
-            ChannelSpec::Noise(num) => self.spu.noise_channels[num].reset_sample(),
+            ChannelSpec::Base(num) => while let Some(addr) = self.spu.base_channels[num].next_fetch_addr() {
+                let value = self.arm7_read::<u32>(addr);
+                self.spu.base_channels[num].push_fifo(value);
+            },
+            ChannelSpec::PSG(num) => while let Some(addr) = self.spu.psg_channels[num].next_fetch_addr() {
+                let value = self.arm7_read::<u32>(addr);
+                self.spu.psg_channels[num].push_fifo(value);
+            },
+            ChannelSpec::Noise(num) => while let Some(addr) = self.spu.noise_channels[num].next_fetch_addr() {
+                let value = self.arm7_read::<u32>(addr);
+                self.spu.noise_channels[num].push_fifo(value);
+            },
         }
     }
 }
@@ -334,6 +554,13 @@ pub struct Channel<T: ChannelType> {
     addr: u32,
     num_bytes_left: usize,
     sample: i16,
+    next_step_cycle: usize,
+    pending_reset: bool,
+    // FIFO
+    fifo: VecDeque<u8>,
+    fifo_refilling: bool,
+    fetch_addr: u32,
+    fetch_bytes_left: usize,
     // ADPCM
     adpcm_in_header: bool,
     adpcm_low_nibble: bool,
@@ -343,6 +570,10 @@ pub struct Channel<T: ChannelType> {
     initial_adpcm_value: i16,
 }
 
+savestate!([T: ChannelType] Channel<T> { cnt, src_addr, timer_val, loop_start, len, addr, num_bytes_left, sample, next_step_cycle,
+    pending_reset, fifo, fifo_refilling, fetch_addr, fetch_bytes_left, adpcm_in_header, adpcm_low_nibble, adpcm_index, adpcm_value,
+    initial_adpcm_index, initial_adpcm_value });
+
 impl<T: ChannelType> IORegister for Channel<T> {
     fn read(&self, byte: usize) -> u8 {
         match byte {
@@ -371,8 +602,11 @@ impl<T: ChannelType> IORegister for Channel<T> {
                 if !prev_busy && self.cnt.busy {
                     self.adpcm_in_header = true;
                     self.adpcm_low_nibble = true;
-                    self.schedule(scheduler, false);
+                    self.restart();
+                    self.next_step_cycle = scheduler.cycle + self.timer_period();
+                    self.schedule(scheduler);
                 } else if !self.cnt.busy {
+                    self.pending_reset = false;
                     scheduler.remove(Event::StepAudioChannel(self.spec));
                 }
             }
@@ -383,17 +617,20 @@ impl<T: ChannelType> IORegister for Channel<T> {
             },
             0x8 ..= 0x9 => {
                 self.timer_val = self.timer_val & !mask16 | value16;
-                if self.cnt.busy { self.schedule(scheduler, false) }
+                if self.cnt.busy {
+                    self.next_step_cycle = scheduler.cycle + self.timer_period();
+                    self.schedule(scheduler);
+                }
             },
             0xA ..= 0xB => {
                 self.loop_start = self.loop_start & !mask16 | value16;
                 self.num_bytes_left = (self.loop_start as usize + self.len as usize) * 4;
-                if self.cnt.busy { self.schedule(scheduler, false) }
+                if self.cnt.busy { self.schedule(scheduler) }
             },
             0xC ..= 0xF => {
                 self.len = (self.len & !mask32 | value32) & 0x3F_FFFF;
                 self.num_bytes_left = (self.loop_start as usize + self.len as usize) * 4;
-                if self.cnt.busy { self.schedule(scheduler, false) }
+                if self.cnt.busy { self.schedule(scheduler) }
             },
             _ => unreachable!(),
         }
@@ -401,6 +638,8 @@ impl<T: ChannelType> IORegister for Channel<T> {
 }
 
 impl<T: ChannelType> Channel<T> {
+    const FIFO_LEN: usize = 16;
+
     pub fn new(spec: ChannelSpec) -> Self {
         Channel {
             // Registers
@@ -414,6 +653,13 @@ impl<T: ChannelType> Channel<T> {
             addr: 0,
             num_bytes_left: 0,
             sample: 0,
+            next_step_cycle: 0,
+            pending_reset: false,
+            // FIFO
+            fifo: VecDeque::with_capacity(Channel::<T>::FIFO_LEN),
+            fifo_refilling: false,
+            fetch_addr: 0,
+            fetch_bytes_left: 0,
             // ADPCM
             adpcm_in_header: true,
             adpcm_low_nibble: true,
@@ -425,25 +671,67 @@ impl<T: ChannelType> Channel<T> {
     }
 
     fn generate_sample(&self, sample: &mut (i32, i32)) {
-        // TODO: Use volume and panning
-        sample.0 += ((self.sample as i32) >> self.cnt.volume_shift()) *
-            self.cnt.volume_factor() *
-            (128 - self.cnt.pan_factor());
-        sample.1 += ((self.sample as i32) >> self.cnt.volume_shift()) *
-            self.cnt.volume_factor() *
-            (self.cnt.pan_factor());
+        let (value, left_factor, right_factor) = self.weighted_sample();
+        sample.0 += value * left_factor;
+        sample.1 += value * right_factor;
+    }
+
+    // TODO: Use volume and panning
+    fn weighted_sample(&self) -> (i32, i32, i32) {
+        let factor = self.cnt.volume_factor();
+        (
+            (self.sample as i32) >> self.cnt.volume_shift(),
+            factor * (128 - self.cnt.pan_factor()),
+            factor * self.cnt.pan_factor(),
+        )
+    }
+
+    fn restart(&mut self) {
+        self.addr = self.src_addr;
+        self.num_bytes_left = (self.loop_start as usize + self.len as usize) * 4;
+        self.fifo.clear();
+        self.fifo_refilling = true;
+        self.fetch_addr = self.src_addr & !0x3;
+        self.fetch_bytes_left = self.num_bytes_left;
+    }
+
+    // Sample data is read from memory a word at a time into the FIFO once it's half empty
+    pub fn next_fetch_addr(&mut self) -> Option<u32> {
+        if self.fifo.len() <= Channel::<T>::FIFO_LEN / 2 { self.fifo_refilling = true }
+        if !self.fifo_refilling || self.fetch_bytes_left == 0 || self.fifo.len() + 4 > Channel::<T>::FIFO_LEN {
+            self.fifo_refilling = false;
+            return None
+        }
+        let return_addr = self.fetch_addr;
+        self.fetch_addr += 4;
+        self.fetch_bytes_left -= 4;
+        if self.fetch_bytes_left == 0 && self.cnt.repeat_mode == RepeatMode::Loop {
+            self.fetch_addr = (self.src_addr & !0x3) + self.loop_start as u32 * 4;
+            self.fetch_bytes_left = self.len as usize * 4;
+        }
+        Some(return_addr)
     }
 
-    pub fn next_addr_pcm<M: super::MemoryValue>(&mut self) -> (u32, bool) {
+    pub fn push_fifo(&mut self, value: u32) {
+        self.fifo.extend(value.to_le_bytes().iter());
+    }
+
+    pub fn read_fifo<M: super::MemoryValue>(&mut self) -> M {
+        let mut value = 0u32;
+        for i in 0..std::mem::size_of::<M>() {
+            value |= (self.fifo.pop_front().unwrap_or(0) as u32) << (8 * i);
+        }
+        num_traits::cast::<u32, M>(value).unwrap()
+    }
+
+    pub fn advance_pcm<M: super::MemoryValue>(&mut self) {
         assert!(self.num_bytes_left > 0);
-        let return_addr = self.addr;
         self.addr += std::mem::size_of::<M>() as u32;
         self.num_bytes_left -= std::mem::size_of::<M>();
-        let reset = if self.num_bytes_left == 0 { self.handle_end() } else { false };
-        (return_addr, reset)
+        if self.num_bytes_left == 0 { self.handle_end() }
     }
 
-    fn handle_end(&mut self) -> bool {
+    fn handle_end(&mut self) {
         // TODO: Verify out timing of busy bit for other modes
         let (reset, new_busy) = match self.cnt.repeat_mode {
             RepeatMode::Manual => (true, true),
@@ -456,7 +744,8 @@ impl<T: ChannelType> Channel<T> {
             RepeatMode::OneShot => (true, false),
         };
         self.cnt.busy = new_busy;
-        reset
+        // Sample is cleared one step after the end
+        self.pending_reset = reset;
     }
 
     pub fn reset_sample(&mut self) {
@@ -469,26 +758,27 @@ impl<T: ChannelType> Channel<T> {
         self.sample = if std::mem::size_of::<M>() == 1 { sample << 8 } else { sample } as i16;
     }
 
-    pub fn initial_adpcm_addr(&mut self) -> Option<u32> {
+    pub fn in_adpcm_header(&mut self) -> bool {
         if self.adpcm_in_header {
             assert_eq!(self.src_addr, self.addr);
             self.adpcm_in_header = false;
-            let return_addr = self.addr;
             self.addr += std::mem::size_of::<u32>() as u32;
             self.num_bytes_left -= std::mem::size_of::<u32>();
-            Some(return_addr)
-        } else { None }
+            true
+        } else { false }
     }
 
-    pub fn next_addr_adpcm(&mut self) -> (u32, bool) {
+    pub fn next_adpcm_data(&mut self) -> u8 {
         assert!(self.num_bytes_left > 0);
-        let return_addr = self.addr;
-        let reset = if self.adpcm_low_nibble { false } else {
+        // Both nibbles come from the same byte, so only pop it once the high nibble is used
+        let value = self.fifo.front().copied().unwrap_or(0);
+        if !self.adpcm_low_nibble {
+            self.fifo.pop_front();
             self.addr += 1;
             self.num_bytes_left -= 1;
-            if self.num_bytes_left == 0 { self.handle_end() } else { false }
-        };
-        (return_addr, reset)
+            if self.num_bytes_left == 0 { self.handle_end() }
+        }
+        value
     }
 
     pub fn set_adpcm_data(&mut self, value: u8) {
@@ -525,23 +815,80 @@ impl<T: ChannelType> Channel<T> {
         self.cnt.format
     }
 
-    pub fn schedule(&mut self, scheduler: &mut Scheduler, reset: bool) {
-        if self.timer_val != 0 && self.len + self.loop_start as u32 != 0 {
-            if reset {
-                scheduler.schedule(
-                    Event::ResetAudioChannel(self.spec),
-                    HW::reset_audio_channel,
-                    (-(self.timer_val as i16) as u16) as usize);
-            } else {
-                scheduler.schedule(
-                    Event::StepAudioChannel(self.spec),
-                    HW::step_audio_channel,
-                    (-(self.timer_val as i16) as u16) as usize);
-            }
+    fn timer_period(&self) -> usize {
+        (-(self.timer_val as i16) as u16) as usize
+    }
+
+    fn can_step(&self) -> bool {
+        self.timer_val != 0 && self.len + self.loop_start as u32 != 0
+    }
+
+    // Returns whether a step was due by the given cycle, in which case the step is consumed
+    pub fn step_due(&mut self, cycle: usize) -> bool {
+        if !self.can_step() || self.next_step_cycle > cycle { return false }
+        if self.pending_reset {
+            self.pending_reset = false;
+            self.reset_sample();
+            return false
+        }
+        if !self.cnt.busy { return false }
+        self.next_step_cycle += self.timer_period();
+        true
+    }
+
+    fn steps_until_stop(&self) -> Option<usize> {
+        if self.pending_reset { return Some(0) }
+        if !self.cnt.busy || self.cnt.repeat_mode == RepeatMode::Loop { return None }
+        // Includes the step that clears the sample after the end
+        Some(match self.cnt.format {
+            Format::PCM8 => self.num_bytes_left,
+            Format::PCM16 => self.num_bytes_left / 2,
+            Format::ADPCM => if self.adpcm_in_header {
+                1 + 2 * self.num_bytes_left.saturating_sub(4)
+            } else { 2 * self.num_bytes_left - !self.adpcm_low_nibble as usize },
+            Format::Special => return None,
+        })
+    }
+
+    // Only schedules an event for when the channel stops, since it's otherwise stepped when needed
+    pub fn schedule(&mut self, scheduler: &mut Scheduler) {
+        scheduler.remove(Event::StepAudioChannel(self.spec));
+        if !self.can_step() { return }
+        if let Some(steps) = self.steps_until_stop() {
+            let stop_cycle = self.next_step_cycle + steps * self.timer_period();
+            scheduler.schedule(Event::StepAudioChannel(self.spec), HW::on_audio_channel_event,
+                stop_cycle.saturating_sub(scheduler.cycle));
+        }
+    }
+
+    pub fn state(&self) -> ChannelState {
+        ChannelState {
+            busy: self.cnt.busy,
+            format: self.cnt.format,
+            frequency: if self.timer_val == 0 { 0 } else { crate::nds::NDS::CLOCK_RATE / self.timer_period() },
+            sample: self.sample,
+            volume: self.cnt.volume_factor() as u8 >> self.cnt.volume_shift(),
+            pan: self.cnt.pan_factor() as u8,
+            muted: false,
+            soloed: false,
+            audible: true,
         }
     }
 }
 
+#[derive(Clone, Copy)]
+pub struct ChannelState {
+    pub busy: bool,
+    pub format: Format,
+    pub frequency: usize, // Hz
+    pub sample: i16,
+    pub volume: u8, // Effective volume after divider: 0 - 128
+    pub pan: u8, // 0 (left) - 128 (right)
+    pub muted: bool,
+    pub soloed: bool,
+    pub audible: bool,
+}
+
 struct Capture {
     // Registers
     cnt: CaptureControl,
@@ -552,6 +899,8 @@ struct Capture {
     num_bytes_left: usize,
 }
 
+savestate!(Capture { cnt, dest_addr, len, addr, num_bytes_left });
+
 impl Capture {
     pub fn new() -> Self {
         Capture {
@@ -617,6 +966,31 @@ pub enum ChannelSpec {
     Noise(usize),
 }
 
+impl Savestate for ChannelSpec {
+    fn save(&self, state: &mut StateWriter) {
+        let (tag, num) = match *self {
+            ChannelSpec::Base(num) => (0u8, num),
+            ChannelSpec::PSG(num) => (1, num),
+            ChannelSpec::Noise(num) => (2, num),
+        };
+        tag.save(state);
+        num.save(state);
+    }
+
+    fn load(&mut self, state: &mut StateReader) {
+        let mut tag = 0u8;
+        let mut num = 0usize;
+        tag.load(state);
+        num.load(state);
+        *self = match tag {
+            0 => ChannelSpec::Base(num),
+            1 => ChannelSpec::PSG(num),
+            2 => ChannelSpec::Noise(num),
+            _ => { state.invalidate(); ChannelSpec::Base(0) },
+        };
+    }
+}
+
 pub trait ChannelType {
     fn supports_psg() -> bool;
     fn supports_noise() -> bool;
@@ -0,0 +1,112 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use super::SPU;
+
+pub struct Recorder {
+    mixer: WavWriter,
+    channels: Option<Vec<WavWriter>>,
+}
+
+impl Recorder {
+    pub fn new(path: &Path, sample_rate: u32, record_channels: bool) -> io::Result<Self> {
+        let channels = if record_channels {
+            let mut channels = Vec::with_capacity(SPU::NUM_CHANNELS);
+            for num in 0..SPU::NUM_CHANNELS {
+                channels.push(WavWriter::new(&Recorder::channel_path(path, num), sample_rate)?);
+            }
+            Some(channels)
+        } else { None };
+        Ok(Recorder {
+            mixer: WavWriter::new(path, sample_rate)?,
+            channels,
+        })
+    }
+
+    fn channel_path(path: &Path, num: usize) -> PathBuf {
+        let stem = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("audio");
+        path.with_file_name(format!("{}_ch{}.wav", stem, num))
+    }
+
+    pub fn records_channels(&self) -> bool {
+        self.channels.is_some()
+    }
+
+    pub fn write_mixer(&mut self, sample: (i16, i16)) {
+        self.mixer.write_sample(sample);
+    }
+
+    pub fn write_channels(&mut self, samples: &[(i16, i16); SPU::NUM_CHANNELS]) {
+        if let Some(channels) = self.channels.as_mut() {
+            for (channel, sample) in channels.iter_mut().zip(samples.iter()) {
+                channel.write_sample(*sample);
+            }
+        }
+    }
+}
+
+// 16 bit stereo PCM WAV file
+struct WavWriter {
+    writer: BufWriter<File>,
+    num_samples: u32,
+    failed: bool,
+}
+
+impl WavWriter {
+    const HEADER_SIZE: u32 = 44;
+    const BYTES_PER_SAMPLE: u32 = 4;
+
+    pub fn new(path: &Path, sample_rate: u32) -> io::Result<Self> {
+        let mut wav_writer = WavWriter {
+            writer: BufWriter::new(File::create(path)?),
+            num_samples: 0,
+            failed: false,
+        };
+        wav_writer.write_header(sample_rate)?;
+        Ok(wav_writer)
+    }
+
+    fn write_header(&mut self, sample_rate: u32) -> io::Result<()> {
+        let writer = &mut self.writer;
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&(WavWriter::HEADER_SIZE - 8).to_le_bytes())?; // Patched in finish
+        writer.write_all(b"WAVE")?;
+        writer.write_all(b"fmt ")?;
+        writer.write_all(&16u32.to_le_bytes())?;
+        writer.write_all(&1u16.to_le_bytes())?; // PCM
+        writer.write_all(&2u16.to_le_bytes())?; // Stereo
+        writer.write_all(&sample_rate.to_le_bytes())?;
+        writer.write_all(&(sample_rate * WavWriter::BYTES_PER_SAMPLE).to_le_bytes())?;
+        writer.write_all(&(WavWriter::BYTES_PER_SAMPLE as u16).to_le_bytes())?;
+        writer.write_all(&16u16.to_le_bytes())?;
+        writer.write_all(b"data")?;
+        writer.write_all(&0u32.to_le_bytes())?; // Patched in finish
+        Ok(())
+    }
+
+    pub fn write_sample(&mut self, sample: (i16, i16)) {
+        if self.failed { return }
+        let result = self.writer.write_all(&sample.0.to_le_bytes())
+            .and_then(|_| self.writer.write_all(&sample.1.to_le_bytes()));
+        if let Err(err) = result {
+            warn!("Unable to Write Audio Recording: {}!", err);
+            self.failed = true;
+        } else { self.num_samples += 1 }
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        let data_size = self.num_samples * WavWriter::BYTES_PER_SAMPLE;
+        self.writer.seek(SeekFrom::Start(4))?;
+        self.writer.write_all(&(WavWriter::HEADER_SIZE - 8 + data_size).to_le_bytes())?;
+        self.writer.seek(SeekFrom::Start(40))?;
+        self.writer.write_all(&data_size.to_le_bytes())?;
+        self.writer.flush()
+    }
+}
+
+impl Drop for WavWriter {
+    fn drop(&mut self) {
+        self.finish().unwrap_or_else(|err| warn!("Unable to Finish Audio Recording: {}!", err));
+    }
+}
@@ -11,6 +11,8 @@ pub struct SoundControl {
     pub enable: bool,
 }
 
+savestate!(SoundControl { master_volume, left_output, right_output, output_1, output_3, enable });
+
 impl IORegister for SoundControl {
     fn read(&self, byte: usize) -> u8 {
         match byte {
@@ -63,6 +65,8 @@ pub enum ChannelOutput {
     Ch1Ch3 = 3,
 }
 
+savestate_enum!(ChannelOutput { Mixer, Ch1, Ch3, Ch1Ch3 });
+
 impl From<u8> for ChannelOutput {
     fn from(value: u8) -> Self {
         use ChannelOutput::*;
@@ -88,6 +92,8 @@ pub struct ChannelControl<T: ChannelType> {
     channel_type: PhantomData<T>,
 }
 
+savestate!([T: ChannelType] ChannelControl<T> { volume_mul, volume_div, hold, panning, wave_duty, repeat_mode, format, busy });
+
 impl<T: ChannelType> IORegister for ChannelControl<T> {
     fn read(&self, byte: usize) -> u8 {
         match byte {
@@ -146,13 +152,15 @@ impl<T: ChannelType> ChannelControl<T> {
     }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq)]
 pub enum RepeatMode {
     Manual = 0,
     Loop = 1,
     OneShot = 2,
 }
 
+savestate_enum!(RepeatMode { Manual, Loop, OneShot });
+
 impl From<u8> for RepeatMode {
     fn from(value: u8) -> Self {
         use RepeatMode::*;
@@ -165,7 +173,7 @@ impl From<u8> for RepeatMode {
     }
 }
 
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Format {
     PCM8 = 0,
     PCM16 = 1,
@@ -173,6 +181,8 @@ pub enum Format {
     Special = 3,
 }
 
+savestate_enum!(Format { PCM8, PCM16, ADPCM, Special });
+
 impl From<u8> for Format {
     fn from(value: u8) -> Self {
         use Format::*;
@@ -194,6 +204,8 @@ pub struct CaptureControl {
     pub busy: bool,
 }
 
+savestate!(CaptureControl { add, use_channel, no_repeat, use_pcm8, busy });
+
 impl CaptureControl {
     pub fn new() -> Self {
         CaptureControl {
@@ -0,0 +1,86 @@
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+
+// Polyphase windowed-sinc resampler
+pub struct Resampler {
+    ratio: f64, // Input samples per output sample
+    pos: f64, // Position of the next output sample relative to the start of the history
+    history: VecDeque<[f32; 2]>,
+    filter: Vec<f32>,
+}
+
+impl Resampler {
+    const TAPS: usize = 32;
+    const PHASES: usize = 256;
+
+    pub fn new(input_rate: f64, output_rate: f64) -> Self {
+        let mut history = VecDeque::with_capacity(2 * Resampler::TAPS);
+        // Prime history so first samples are not delayed by filter length
+        for _ in 0..Resampler::TAPS / 2 - 1 { history.push_back([0.0, 0.0]) }
+        let ratio = input_rate / output_rate;
+        Resampler {
+            ratio,
+            pos: 0.0,
+            history,
+            filter: Resampler::gen_filter(ratio),
+        }
+    }
+
+    fn gen_filter(ratio: f64) -> Vec<f32> {
+        // Lower cutoff when downsampling to avoid aliasing, and leave some room for the transition band
+        let cutoff = 0.95 * if ratio > 1.0 { 1.0 / ratio } else { 1.0 };
+        let half_taps = (Resampler::TAPS / 2) as f64;
+        let mut filter = vec![0.0; (Resampler::PHASES + 1) * Resampler::TAPS];
+        for phase in 0..=Resampler::PHASES {
+            let frac = phase as f64 / Resampler::PHASES as f64;
+            let coeffs = &mut filter[phase * Resampler::TAPS..(phase + 1) * Resampler::TAPS];
+            let mut sum = 0.0;
+            for (tap, coeff) in coeffs.iter_mut().enumerate() {
+                // Distance from the output point to this input sample
+                let x = tap as f64 - (half_taps - 1.0) - frac;
+                let sinc = if x == 0.0 { 1.0 } else { (PI * cutoff * x).sin() / (PI * cutoff * x) };
+                // Blackman window
+                let n = (x + half_taps) / (2.0 * half_taps);
+                let window = 0.42 - 0.5 * (2.0 * PI * n).cos() + 0.08 * (4.0 * PI * n).cos();
+                let value = cutoff * sinc * window;
+                *coeff = value as f32;
+                sum += value;
+            }
+            // Normalize for unity DC gain
+            for coeff in coeffs.iter_mut() { *coeff /= sum as f32 }
+        }
+        filter
+    }
+
+    pub fn ratio(&self) -> f64 { self.ratio }
+
+    pub fn set_ratio(&mut self, ratio: f64) {
+        // Filter cutoff is left as is since adjustments are small
+        self.ratio = ratio;
+    }
+
+    pub fn push_sample<F: FnMut([f32; 2])>(&mut self, sample: [f32; 2], mut output: F) {
+        self.history.push_back(sample);
+        while self.pos as usize + Resampler::TAPS <= self.history.len() {
+            let start = self.pos as usize;
+            let frac = self.pos - start as f64;
+            let phase = frac * Resampler::PHASES as f64;
+            let phase_i = phase as usize;
+            let phase_frac = (phase - phase_i as f64) as f32;
+            let coeffs0 = &self.filter[phase_i * Resampler::TAPS..(phase_i + 1) * Resampler::TAPS];
+            let coeffs1 = &self.filter[(phase_i + 1) * Resampler::TAPS..(phase_i + 2) * Resampler::TAPS];
+            let mut value = [0.0, 0.0];
+            for (i, sample) in self.history.range(start..start + Resampler::TAPS).enumerate() {
+                // Linearly interpolate between adjacent phases
+                let coeff = coeffs0[i] + (coeffs1[i] - coeffs0[i]) * phase_frac;
+                value[0] += sample[0] * coeff;
+                value[1] += sample[1] * coeff;
+            }
+            output(value);
+            self.pos += self.ratio;
+        }
+        let consumed = (self.pos as usize).min(self.history.len());
+        self.history.drain(..consumed);
+        self.pos -= consumed as f64;
+    }
+}
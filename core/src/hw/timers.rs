@@ -9,6 +9,8 @@ pub struct Timers {
     timers: [Timer; Timers::NUM_TIMERS],
 }
 
+savestate!(Timers { timers });
+
 impl Timers {
     const NUM_TIMERS: usize = 4;
     const PRESCALERS: [usize; Self::NUM_TIMERS] = [1, 64, 256, 1024];
@@ -23,6 +25,10 @@ impl Timers {
             ],
         }
     }
+
+    pub fn enter_gba_mode(&mut self) {
+        for timer in self.timers.iter_mut() { timer.gba_mode = true }
+    }
 }
 
 impl std::ops::Index<usize> for Timers {
@@ -42,6 +48,7 @@ impl std::ops::IndexMut<usize> for Timers {
 #[derive(Clone, Copy)]
 pub struct Timer {
     is_nds9: bool,
+    gba_mode: bool,
     pub reload: u16,
     pub cnt: TMCNT,
     pub index: usize,
@@ -49,16 +56,19 @@ pub struct Timer {
     // Counter Calcuation
     // Count-Up Timing
     counter: u16,
-    // Regular Timing
+    // Regular Timing - counter is only stored when the timer is (re)started and derived from the cycle otherwise
     start_cycle: usize,
     time_till_first_clock: usize,
-    timer_len: usize,
+    overflow_cycle: usize,
 }
 
+savestate!(Timer { gba_mode, reload, cnt, counter, start_cycle, time_till_first_clock, overflow_cycle });
+
 impl Timer {
     pub fn new(is_nds9: bool, index: usize, interrupt: InterruptRequest) -> Timer {
         Timer {
             is_nds9,
+            gba_mode: false,
             reload: 0,
             cnt: TMCNT::new(),
             index,
@@ -69,7 +79,7 @@ impl Timer {
             // Regular Timing
             start_cycle: 0,
             time_till_first_clock: 0,
-            timer_len: 0,
+            overflow_cycle: 0,
         }
     }
 
@@ -86,31 +96,37 @@ impl Timer {
     }
 
     fn calc_counter(&self, global_cycle: usize) -> u16 {
-        let cycles_passed = global_cycle - self.start_cycle;
-        // Counter stores the reload value
-        if cycles_passed >= self.time_till_first_clock {
-            let cycles_passed = cycles_passed - self.time_till_first_clock;
-            let counter_change = cycles_passed / Timers::PRESCALERS[self.cnt.prescaler as usize];
-            assert!(counter_change < 0x1_0000);
-            self.counter + 1 + counter_change as u16
-        } else { self.counter }
+        if global_cycle < self.start_cycle + self.time_till_first_clock { return self.counter }
+        let prescaler = self.prescaler();
+        let clocks = 1 + (global_cycle - self.start_cycle - self.time_till_first_clock) / prescaler;
+        let counter = self.counter as usize + clocks;
+        if counter < 0x1_0000 { return counter as u16 }
+        // Overflowed but the overflow event hasn't been handled yet
+        let period = 0x1_0000 - self.reload as usize;
+        (self.reload as usize + (counter - 0x1_0000) % period) as u16
     }
 
     pub fn reload(&mut self) { self.counter = self.reload }
 
-    pub fn create_event(&mut self, scheduler: &mut Scheduler, delay: usize) {
-        self.start_cycle = scheduler.cycle + delay;
+    // Timers are clocked by the 16.78 MHz bus in GBA mode
+    fn prescaler(&self) -> usize {
+        Timers::PRESCALERS[self.cnt.prescaler as usize] << self.gba_mode as usize
+    }
+
+    pub fn create_event(&mut self, scheduler: &mut Scheduler, start_cycle: usize) {
+        self.start_cycle = start_cycle;
         // Syncs prescaler to global cycle
-        let prescaler = Timers::PRESCALERS[self.cnt.prescaler as usize];
+        let prescaler = self.prescaler();
         trace!("Starting NDS{} {} Timer{}: {} * 0x{:X}", if self.is_nds9 { 9 } else { 7 },
-        if self.is_count_up() { "Count-Up" } else { "Regular" }, self.index, prescaler, self.reload);
+        if self.is_count_up() { "Count-Up" } else { "Regular" }, self.index, prescaler, self.counter);
         // Add 1 for 1 cycle delay in timer start
         self.time_till_first_clock = prescaler - (self.start_cycle + 1) % prescaler;
-        self.timer_len = prescaler * (0x10000 - self.reload as usize - 1);
+        self.overflow_cycle = self.start_cycle + self.time_till_first_clock +
+            prescaler * (0xFFFF - self.counter as usize);
         scheduler.schedule(
             Event::TimerOverflow(self.is_nds9, self.index),
             HW::on_timer_overflow,
-            delay + self.time_till_first_clock + self.timer_len
+            self.overflow_cycle.saturating_sub(scheduler.cycle)
         );
     }
 
@@ -140,12 +156,14 @@ impl Timer {
                     self.counter = self.calc_counter(global_cycle);
                 }
                 self.cnt.write(scheduler, 0, value);
+                // Timer 0 has no previous timer to count up from
+                if self.index == 0 { self.cnt.count_up = false }
                 if !self.is_count_up() {
                     if !prev_start && self.cnt.start {
                         self.reload();
-                        self.create_event(scheduler, 1);
+                        self.create_event(scheduler, global_cycle + 1);
                     } else if self.cnt.start {
-                        self.create_event(scheduler, 0);
+                        self.create_event(scheduler, global_cycle);
                     }
                 } else {
                     if !prev_start && self.cnt.start {
@@ -160,7 +178,7 @@ impl Timer {
 }
 
 impl HW {
-    fn on_timer_overflow(&mut self, event: Event) {
+    pub(super) fn on_timer_overflow(&mut self, event: Event) {
         let (is_nds9, num) = match event {
             Event::TimerOverflow(is_nds9, num) => (is_nds9, num),
             _ => unreachable!(),
@@ -169,14 +187,16 @@ impl HW {
         if self.timers[i][num].cnt.irq {
             self.interrupts[i].request |= self.timers[i].timers[num].interrupt
         }
+        if self.timers[i][num].gba_mode && num < 2 { self.on_gba_timer_overflow(num) }
         // Cascade Timers
         if num + 1 < Timers::NUM_TIMERS && self.timers[i][num + 1].is_count_up() {
             if self.timers[i][num + 1].clock() { self.on_timer_overflow(Event::TimerOverflow(is_nds9, num + 1)) }
         }
-        // TODO: Can I move this up to avoid recreating timers
+        // Restart from the exact overflow cycle so events handled late don't drift
         if !self.timers[i][num].is_count_up() {
+            let overflow_cycle = self.timers[i][num].overflow_cycle;
             self.timers[i][num].reload();
-            self.timers[i][num].create_event(&mut self.scheduler, 0);
+            self.timers[i][num].create_event(&mut self.scheduler, overflow_cycle);
         }
     }
 }
@@ -190,6 +210,8 @@ pub struct TMCNT {
     pub start: bool,
 }
 
+savestate!(TMCNT { prescaler, count_up, irq, start });
+
 impl IORegister for TMCNT {
     fn read(&self, byte: usize) -> u8 {
         match byte {
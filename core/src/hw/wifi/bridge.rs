@@ -0,0 +1,162 @@
+use std::collections::VecDeque;
+use std::io;
+use std::time::{Duration, Instant};
+
+use pnet_datalink::{self, Channel, Config, DataLinkReceiver, DataLinkSender};
+
+use super::{WiFiFrame, WiFiLink};
+
+// Emulates an open access point and bridges its data frames onto a host network interface.
+// Frames are sent with the console's MAC address, so this needs a wired interface and raw socket permissions.
+pub struct BridgeLink {
+    tx: Box<dyn DataLinkSender>,
+    rx: Box<dyn DataLinkReceiver>,
+    ssid: Vec<u8>,
+    start: Instant,
+    next_beacon: Instant,
+    seq_no: u16,
+    client: Option<[u8; 6]>,
+    pending: VecDeque<WiFiFrame>,
+}
+
+impl BridgeLink {
+    const AP_MAC: [u8; 6] = [0x00, 0xF0, 0x77, 0x77, 0x77, 0x77];
+    const BROADCAST: [u8; 6] = [0xFF; 6];
+    const CHANNEL: u8 = 6;
+    const BEACON_INTERVAL_TU: u16 = 100;
+    const LLC_SNAP: [u8; 6] = [0xAA, 0xAA, 0x03, 0x00, 0x00, 0x00];
+    const RATE_2MBPS: u8 = 0x14;
+
+    pub fn new(interface: &str, ssid: &str) -> io::Result<Self> {
+        let interface = pnet_datalink::interfaces().into_iter().find(|iface| iface.name == interface)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Network interface not found"))?;
+        let config = Config { read_timeout: Some(Duration::from_millis(0)), ..Config::default() };
+        let (tx, rx) = match pnet_datalink::channel(&interface, config)? {
+            Channel::Ethernet(tx, rx) => (tx, rx),
+            _ => return Err(io::Error::other("Unsupported channel type")),
+        };
+        let now = Instant::now();
+        Ok(BridgeLink {
+            tx,
+            rx,
+            ssid: ssid.as_bytes().to_vec(),
+            start: now,
+            next_beacon: now,
+            seq_no: 0,
+            client: None,
+            pending: VecDeque::new(),
+        })
+    }
+
+    pub fn interfaces() -> Vec<String> {
+        pnet_datalink::interfaces().into_iter()
+            .filter(|iface| iface.is_up() && !iface.is_loopback() && iface.mac.is_some())
+            .map(|iface| iface.name).collect()
+    }
+
+    fn queue_frame(&mut self, frame_control: u16, dest: [u8; 6], source: [u8; 6], body: &[u8]) {
+        let mut data = Vec::with_capacity(24 + body.len());
+        data.extend_from_slice(&frame_control.to_le_bytes());
+        data.extend_from_slice(&[0, 0]);
+        data.extend_from_slice(&dest);
+        // Frames from the distribution system have the BSSID second and the source third
+        if frame_control & 0x0200 != 0 {
+            data.extend_from_slice(&BridgeLink::AP_MAC);
+            data.extend_from_slice(&source);
+        } else {
+            data.extend_from_slice(&source);
+            data.extend_from_slice(&BridgeLink::AP_MAC);
+        }
+        data.extend_from_slice(&(self.seq_no << 4).to_le_bytes());
+        self.seq_no = (self.seq_no + 1) & 0xFFF;
+        data.extend_from_slice(body);
+        self.pending.push_back(WiFiFrame { rate: BridgeLink::RATE_2MBPS, data });
+    }
+
+    // Shared by beacons and probe responses
+    fn network_info(&self, ssid: &[u8]) -> Vec<u8> {
+        let mut body = Vec::new();
+        body.extend_from_slice(&(self.start.elapsed().as_micros() as u64).to_le_bytes());
+        body.extend_from_slice(&BridgeLink::BEACON_INTERVAL_TU.to_le_bytes());
+        body.extend_from_slice(&0x0021u16.to_le_bytes()); // ESS and short preamble
+        body.extend_from_slice(&[0x00, ssid.len() as u8]);
+        body.extend_from_slice(ssid);
+        body.extend_from_slice(&[0x01, 0x02, 0x82, 0x84]); // 1 and 2 Mbit/s
+        body.extend_from_slice(&[0x03, 0x01, BridgeLink::CHANNEL]);
+        body
+    }
+
+    fn handle_management(&mut self, subtype: u16, source: [u8; 6], body: &[u8]) {
+        match subtype {
+            // Association request
+            0x0 => {
+                self.client = Some(source);
+                let mut response = vec![0x21, 0x00, 0x00, 0x00, 0x01, 0xC0];
+                response.extend_from_slice(&[0x01, 0x02, 0x82, 0x84]);
+                self.queue_frame(0x0010, source, BridgeLink::AP_MAC, &response);
+            },
+            // Probe request
+            0x4 => {
+                let ssid = match body {
+                    [0x00, len, ssid @ ..] if *len != 0 && ssid.len() >= *len as usize => ssid[..*len as usize].to_vec(),
+                    _ => self.ssid.clone(),
+                };
+                let response = self.network_info(&ssid);
+                self.queue_frame(0x0050, source, BridgeLink::AP_MAC, &response);
+            },
+            // Disassociation and deauthentication
+            0xA | 0xC => self.client = None,
+            // Open system authentication
+            0xB => self.queue_frame(0x00B0, source, BridgeLink::AP_MAC, &[0x00, 0x00, 0x02, 0x00, 0x00, 0x00]),
+            _ => (),
+        }
+    }
+}
+
+impl WiFiLink for BridgeLink {
+    fn send(&mut self, frame: &WiFiFrame) {
+        let data = &frame.data;
+        if data.len() < 24 || data[4..10] != BridgeLink::AP_MAC && data[4..10] != BridgeLink::BROADCAST { return }
+        let frame_control = u16::from_le_bytes([data[0], data[1]]);
+        let mut source = [0; 6];
+        source.copy_from_slice(&data[10..16]);
+        match frame_control >> 2 & 0x3 {
+            0 => self.handle_management(frame_control >> 4 & 0xF, source, &data[24..]),
+            2 if Some(source) == self.client && data.len() >= 32 && data[24..30] == BridgeLink::LLC_SNAP => {
+                // The LLC header is replaced by an Ethernet header
+                let mut packet = Vec::with_capacity(data.len() - 10);
+                packet.extend_from_slice(&data[16..22]);
+                packet.extend_from_slice(&source);
+                packet.extend_from_slice(&data[30..]);
+                if let Some(Err(err)) = self.tx.send_to(&packet, None) { warn!("Unable to Send Bridged Packet: {}", err) }
+            },
+            _ => (),
+        }
+    }
+
+    fn recv(&mut self) -> Option<WiFiFrame> {
+        let now = Instant::now();
+        if now >= self.next_beacon {
+            self.next_beacon = now + Duration::from_micros(BridgeLink::BEACON_INTERVAL_TU as u64 * 1024);
+            let body = self.network_info(&self.ssid.clone());
+            self.queue_frame(0x0080, BridgeLink::BROADCAST, BridgeLink::AP_MAC, &body);
+        }
+
+        if let Some(client) = self.client {
+            while let Ok(packet) = self.rx.next().map(<[u8]>::to_vec) {
+                if packet.len() < 14 { continue }
+                // Group addresses have bit 0 set
+                if packet[0..6] != client && packet[0] & 0x1 == 0 { continue }
+                let mut dest = [0; 6];
+                let mut source = [0; 6];
+                dest.copy_from_slice(&packet[0..6]);
+                source.copy_from_slice(&packet[6..12]);
+                if source == client { continue }
+                let mut body = BridgeLink::LLC_SNAP.to_vec();
+                body.extend_from_slice(&packet[12..]);
+                self.queue_frame(0x0208, dest, source, &body);
+            }
+        }
+        self.pending.pop_front()
+    }
+}
@@ -0,0 +1,47 @@
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+#[derive(Clone)]
+pub struct WiFiFrame {
+    // In units of 100Kbit/s
+    pub rate: u8,
+    // IEEE 802.11 header and body without the FCS
+    pub data: Vec<u8>,
+}
+
+// Carries frames between consoles
+pub trait WiFiLink: Send {
+    fn send(&mut self, frame: &WiFiFrame);
+    fn recv(&mut self) -> Option<WiFiFrame>;
+}
+
+pub struct NoLink;
+
+impl WiFiLink for NoLink {
+    fn send(&mut self, _frame: &WiFiFrame) {}
+    fn recv(&mut self) -> Option<WiFiFrame> { None }
+}
+
+// Connects two consoles running in the same process
+pub struct LocalLink {
+    tx: Sender<WiFiFrame>,
+    rx: Receiver<WiFiFrame>,
+}
+
+impl LocalLink {
+    pub fn pair() -> (LocalLink, LocalLink) {
+        let (tx_a, rx_b) = channel();
+        let (tx_b, rx_a) = channel();
+        (LocalLink { tx: tx_a, rx: rx_a }, LocalLink { tx: tx_b, rx: rx_b })
+    }
+}
+
+impl WiFiLink for LocalLink {
+    fn send(&mut self, frame: &WiFiFrame) {
+        // The other console may have been dropped
+        self.tx.send(WiFiFrame { rate: frame.rate, data: frame.data.clone() }).ok();
+    }
+
+    fn recv(&mut self) -> Option<WiFiFrame> {
+        self.rx.try_recv().ok()
+    }
+}
@@ -0,0 +1,609 @@
+#[cfg(feature = "bridge")]
+mod bridge;
+mod link;
+#[cfg(feature = "host")]
+mod udp;
+
+use super::{
+    HW,
+    interrupt_controller::InterruptRequest,
+    scheduler::{Event, Scheduler},
+};
+use crate::nds::NDS;
+pub use link::{LocalLink, NoLink, WiFiFrame, WiFiLink};
+#[cfg(feature = "host")]
+pub use udp::UdpLink;
+#[cfg(feature = "bridge")]
+pub use bridge::BridgeLink;
+
+pub struct WiFi {
+    regs: Vec<u16>,
+    ram: Vec<u8>,
+    bb_regs: [u8; 0x100],
+    rf_regs: [u32; 0x20],
+    random: u16,
+    link: Box<dyn WiFiLink>,
+    interrupt: bool,
+    // Microsecond counter
+    us_count: u64,
+    us_count_cycle: usize,
+    us_compare: u64,
+    tx_slot: Option<TXSlot>,
+}
+
+// The link is the host side of the connection and stays attached
+savestate!(WiFi { regs, ram, bb_regs, rf_regs, random, interrupt, us_count, us_count_cycle, us_compare, tx_slot });
+
+impl WiFi {
+    const REGS_SIZE: usize = 0x1000;
+    const RAM_SIZE: usize = 0x2000;
+    const TX_HEADER_LEN: usize = 0xC;
+    const FCS_LEN: usize = 4;
+    const POLL_INTERVAL_US: usize = 64;
+
+    // Registers
+    const W_ID: usize = 0x000;
+    const W_MODE_RST: usize = 0x004;
+    const W_IF: usize = 0x010;
+    const W_IE: usize = 0x012;
+    const W_MACADDR: usize = 0x018;
+    const W_BSSID: usize = 0x020;
+    const W_AID_LOW: usize = 0x028;
+    const W_RXCNT: usize = 0x030;
+    const W_POWERSTATE: usize = 0x03C;
+    const W_POWERFORCE: usize = 0x040;
+    const W_RANDOM: usize = 0x044;
+    const W_RXBUF_BEGIN: usize = 0x050;
+    const W_RXBUF_END: usize = 0x052;
+    const W_RXBUF_WRCSR: usize = 0x054;
+    const W_RXBUF_WR_ADDR: usize = 0x056;
+    const W_RXBUF_RD_ADDR: usize = 0x058;
+    const W_RXBUF_COUNT: usize = 0x05C;
+    const W_RXBUF_RD_DATA: usize = 0x060;
+    const W_RXBUF_GAP: usize = 0x062;
+    const W_RXBUF_GAPDISP: usize = 0x064;
+    const W_TXBUF_WR_ADDR: usize = 0x068;
+    const W_TXBUF_COUNT: usize = 0x06C;
+    const W_TXBUF_WR_DATA: usize = 0x070;
+    const W_TXBUF_GAP: usize = 0x074;
+    const W_TXBUF_GAPDISP: usize = 0x076;
+    const W_TXBUF_BEACON: usize = 0x080;
+    const W_BEACONINT: usize = 0x08C;
+    const W_TXBUF_CMD: usize = 0x090;
+    const W_TXBUF_REPLY1: usize = 0x094;
+    const W_TXBUF_REPLY2: usize = 0x098;
+    const W_TXBUF_LOC1: usize = 0x0A0;
+    const W_TXBUF_LOC2: usize = 0x0A4;
+    const W_TXBUF_LOC3: usize = 0x0A8;
+    const W_TXREQ_RESET: usize = 0x0AC;
+    const W_TXREQ_SET: usize = 0x0AE;
+    const W_TXREQ_READ: usize = 0x0B0;
+    const W_TXBUSY: usize = 0x0B6;
+    const W_TXSTAT: usize = 0x0B8;
+    const W_PREAMBLE: usize = 0x0BC;
+    const W_CMD_REPLYTIME: usize = 0x0C4;
+    const W_US_COUNTCNT: usize = 0x0E8;
+    const W_US_COMPARECNT: usize = 0x0EA;
+    const W_US_COMPARE0: usize = 0x0F0;
+    const W_US_COMPARE3: usize = 0x0F6;
+    const W_US_COUNT0: usize = 0x0F8;
+    const W_US_COUNT3: usize = 0x0FE;
+    const W_PRE_BEACON: usize = 0x110;
+    const W_BB_CNT: usize = 0x158;
+    const W_BB_WRITE: usize = 0x15A;
+    const W_BB_READ: usize = 0x15C;
+    const W_BB_BUSY: usize = 0x15E;
+    const W_RF_DATA2: usize = 0x17C;
+    const W_RF_DATA1: usize = 0x17E;
+    const W_RF_BUSY: usize = 0x180;
+    const W_RF_PINS: usize = 0x19C;
+    const W_TX_SEQNO: usize = 0x210;
+    const W_RF_STATUS: usize = 0x214;
+    const W_IF_SET: usize = 0x21C;
+
+    // Interrupts
+    const IRQ_RX_COMPLETE: u16 = 1 << 0;
+    const IRQ_TX_COMPLETE: u16 = 1 << 1;
+    const IRQ_RX_START: u16 = 1 << 6;
+    const IRQ_TX_START: u16 = 1 << 7;
+    const IRQ_TXBUF_COUNT: u16 = 1 << 8;
+    const IRQ_RXBUF_COUNT: u16 = 1 << 9;
+    const IRQ_RF_WAKEUP: u16 = 1 << 11;
+    const IRQ_MP_END: u16 = 1 << 12;
+    const IRQ_BEACON: u16 = 1 << 14;
+    const IRQ_PRE_BEACON: u16 = 1 << 15;
+
+    const CHIP_ID: u16 = 0x1440;
+    const BB_CHIP_ID: u8 = 0x6D;
+    // Bit 9 is set while asleep, and powering down through W_POWERFORCE sets bit 8 as well
+    const POWERSTATE_ASLEEP: u16 = 0x0200;
+    const RF_STATUS_RX: u16 = 1;
+    const RF_STATUS_TX: u16 = 3;
+    const RF_STATUS_IDLE: u16 = 9;
+    const MP_ACK_ADDR: [u8; 6] = [0x03, 0x09, 0xBF, 0x00, 0x00, 0x03];
+
+    pub fn new() -> Self {
+        let mut regs = vec![0; WiFi::REGS_SIZE / 2];
+        regs[WiFi::W_ID / 2] = WiFi::CHIP_ID;
+        regs[WiFi::W_POWERSTATE / 2] = WiFi::POWERSTATE_ASLEEP;
+        let mut bb_regs = [0; 0x100];
+        bb_regs[0x00] = WiFi::BB_CHIP_ID;
+        WiFi {
+            regs,
+            ram: vec![0; WiFi::RAM_SIZE],
+            bb_regs,
+            rf_regs: [0; 0x20],
+            random: 0x7FF,
+            link: Box::new(NoLink),
+            interrupt: false,
+            us_count: 0,
+            us_count_cycle: 0,
+            us_compare: 0,
+            tx_slot: None,
+        }
+    }
+
+    pub fn set_link(&mut self, link: Box<dyn WiFiLink>) {
+        self.link = link;
+    }
+
+    pub fn take_interrupt(&mut self) -> bool {
+        let interrupt = self.interrupt;
+        self.interrupt = false;
+        interrupt
+    }
+
+    pub fn read(&mut self, scheduler: &Scheduler, addr: u32) -> u16 {
+        let addr = addr as usize & 0x7FFE;
+        match addr {
+            0x0000 ..= 0x1FFF => self.read_reg(scheduler, addr & (WiFi::REGS_SIZE - 1)),
+            0x4000 ..= 0x5FFF => self.read_ram(addr),
+            _ => 0xFFFF,
+        }
+    }
+
+    pub fn write(&mut self, scheduler: &mut Scheduler, addr: u32, value: u16) {
+        let addr = addr as usize & 0x7FFE;
+        match addr {
+            0x0000 ..= 0x1FFF => self.write_reg(scheduler, addr & (WiFi::REGS_SIZE - 1), value),
+            0x4000 ..= 0x5FFF => self.write_ram(addr, value),
+            _ => (),
+        }
+    }
+
+    fn read_reg(&mut self, scheduler: &Scheduler, addr: usize) -> u16 {
+        match addr {
+            WiFi::W_RANDOM => {
+                let value = self.random;
+                self.random = (self.random & 0x1) ^ (((self.random & 0x3FF) << 1) | (self.random >> 10));
+                value
+            },
+            WiFi::W_RXBUF_RD_DATA => self.read_rx_data(),
+            WiFi::W_US_COUNT0 ..= WiFi::W_US_COUNT3 =>
+                (self.read_us_count(scheduler.cycle) >> (8 * (addr - WiFi::W_US_COUNT0))) as u16,
+            _ => self.regs[addr / 2],
+        }
+    }
+
+    fn write_reg(&mut self, scheduler: &mut Scheduler, addr: usize, value: u16) {
+        match addr {
+            WiFi::W_ID | WiFi::W_RANDOM | WiFi::W_RXBUF_WRCSR | WiFi::W_RXBUF_RD_DATA | WiFi::W_TXREQ_READ |
+            WiFi::W_TXBUSY | WiFi::W_TXSTAT | WiFi::W_BB_READ | WiFi::W_BB_BUSY | WiFi::W_RF_BUSY |
+            WiFi::W_RF_PINS | WiFi::W_TX_SEQNO | WiFi::W_RF_STATUS => (),
+            WiFi::W_MODE_RST => {
+                let was_enabled = self.enabled();
+                self.regs[addr / 2] = value;
+                if self.enabled() && !was_enabled {
+                    self.set_rf_status(WiFi::RF_STATUS_IDLE);
+                    scheduler.schedule(Event::WiFiPoll, HW::on_wifi_poll, WiFi::us_to_cycles(WiFi::POLL_INTERVAL_US));
+                } else if !self.enabled() && was_enabled {
+                    self.regs[WiFi::W_RF_STATUS / 2] = 0;
+                    scheduler.remove(Event::WiFiPoll);
+                }
+            },
+            WiFi::W_IF => self.regs[addr / 2] &= !value,
+            WiFi::W_IE => {
+                self.regs[addr / 2] = value;
+                if self.regs[WiFi::W_IF / 2] & value != 0 { self.interrupt = true }
+            },
+            WiFi::W_IF_SET => self.request_interrupt(value),
+            WiFi::W_POWERSTATE => if value & 0x2 != 0 { self.wake_up() },
+            WiFi::W_POWERFORCE => {
+                self.regs[addr / 2] = value & 0x8001;
+                if value & 0x8000 != 0 {
+                    if value & 0x1 != 0 { self.regs[WiFi::W_POWERSTATE / 2] = 0x0300 } else { self.wake_up() }
+                }
+            },
+            WiFi::W_RXCNT => {
+                // Bits 0 and 7 only trigger actions
+                self.regs[addr / 2] = value & 0xFF0E;
+                if value & 0x0001 != 0 {
+                    self.regs[WiFi::W_RXBUF_WRCSR / 2] = self.regs[WiFi::W_RXBUF_WR_ADDR / 2] & 0xFFF;
+                }
+                if value & 0x0080 != 0 {
+                    self.regs[WiFi::W_TXBUF_REPLY1 / 2] = self.regs[WiFi::W_TXBUF_REPLY2 / 2];
+                    self.regs[WiFi::W_TXBUF_REPLY2 / 2] &= !0x8000;
+                }
+                if self.tx_slot.is_none() { self.set_rf_status(WiFi::RF_STATUS_IDLE) }
+            },
+            WiFi::W_TXBUF_WR_DATA => self.write_tx_data(value),
+            WiFi::W_TXREQ_RESET => self.regs[WiFi::W_TXREQ_READ / 2] &= !value,
+            WiFi::W_TXREQ_SET => {
+                self.regs[WiFi::W_TXREQ_READ / 2] |= value & 0xF;
+                self.start_next_tx(scheduler);
+            },
+            WiFi::W_US_COUNTCNT => {
+                self.latch_us_count(scheduler.cycle);
+                self.regs[addr / 2] = value & 0x1;
+                self.schedule_compare(scheduler);
+            },
+            WiFi::W_US_COMPARECNT => {
+                self.regs[addr / 2] = value & 0x1;
+                self.schedule_compare(scheduler);
+            },
+            WiFi::W_US_COUNT0 ..= WiFi::W_US_COUNT3 => {
+                self.latch_us_count(scheduler.cycle);
+                let shift = 8 * (addr - WiFi::W_US_COUNT0);
+                self.us_count = self.us_count & !(0xFFFF << shift) | (value as u64) << shift;
+                self.schedule_compare(scheduler);
+            },
+            WiFi::W_US_COMPARE0 ..= WiFi::W_US_COMPARE3 => {
+                // Bit 0 of W_US_COMPARE0 forces a beacon timeslot instead of being part of the compare value
+                self.regs[addr / 2] = if addr == WiFi::W_US_COMPARE0 { value & !0x3FF } else { value };
+                self.us_compare = (0..4).fold(0, |compare, i|
+                    compare | (self.regs[WiFi::W_US_COMPARE0 / 2 + i] as u64) << (16 * i));
+                self.schedule_compare(scheduler);
+                if addr == WiFi::W_US_COMPARE0 && value & 0x1 != 0 {
+                    scheduler.remove(Event::WiFiUSCompare);
+                    scheduler.run_now(Event::WiFiUSCompare, HW::on_wifi_us_compare);
+                }
+            },
+            WiFi::W_BB_CNT => {
+                let index = value as usize & 0xFF;
+                match value >> 12 {
+                    0x5 => self.bb_regs[index] = self.regs[WiFi::W_BB_WRITE / 2] as u8,
+                    0x6 => self.regs[WiFi::W_BB_READ / 2] = self.bb_regs[index] as u16,
+                    _ => warn!("Unknown Wi-Fi Baseband Direction: 0x{:X}", value >> 12),
+                }
+            },
+            WiFi::W_RF_DATA1 => {
+                self.regs[addr / 2] = value;
+                let data = (self.regs[WiFi::W_RF_DATA2 / 2] as u32) << 16 | value as u32;
+                let index = (data >> 18) as usize & 0x1F;
+                if data & (1 << 23) != 0 {
+                    let data = (data & !0x3_FFFF) | self.rf_regs[index];
+                    self.regs[WiFi::W_RF_DATA1 / 2] = data as u16;
+                    self.regs[WiFi::W_RF_DATA2 / 2] = (data >> 16) as u16;
+                } else { self.rf_regs[index] = data & 0x3_FFFF }
+            },
+            _ => self.regs[addr / 2] = value,
+        }
+    }
+
+    fn read_ram(&self, addr: usize) -> u16 {
+        let addr = addr & (WiFi::RAM_SIZE - 2);
+        u16::from_le_bytes([self.ram[addr], self.ram[addr + 1]])
+    }
+
+    fn write_ram(&mut self, addr: usize, value: u16) {
+        let addr = addr & (WiFi::RAM_SIZE - 2);
+        self.ram[addr..addr + 2].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn request_interrupt(&mut self, bits: u16) {
+        self.regs[WiFi::W_IF / 2] |= bits;
+        if self.regs[WiFi::W_IE / 2] & bits != 0 { self.interrupt = true }
+    }
+
+    fn enabled(&self) -> bool {
+        self.regs[WiFi::W_MODE_RST / 2] & 0x1 != 0
+    }
+
+    fn awake(&self) -> bool {
+        self.enabled() && self.regs[WiFi::W_POWERSTATE / 2] & 0x0300 == 0
+    }
+
+    fn wake_up(&mut self) {
+        if self.regs[WiFi::W_POWERSTATE / 2] != 0 {
+            self.regs[WiFi::W_POWERSTATE / 2] = 0;
+            self.request_interrupt(WiFi::IRQ_RF_WAKEUP);
+        }
+    }
+
+    fn set_rf_status(&mut self, status: u16) {
+        let status = if status == WiFi::RF_STATUS_IDLE && self.regs[WiFi::W_RXCNT / 2] & 0x8000 != 0 {
+            WiFi::RF_STATUS_RX
+        } else { status };
+        self.regs[WiFi::W_RF_STATUS / 2] = status;
+        self.regs[WiFi::W_RF_PINS / 2] = if status == WiFi::RF_STATUS_RX { 0x0084 } else { 0x0046 };
+    }
+
+    // Buffers
+
+    fn rx_wrap(&self, addr: usize) -> usize {
+        let begin = self.regs[WiFi::W_RXBUF_BEGIN / 2] as usize & 0x1FFE;
+        let end = self.regs[WiFi::W_RXBUF_END / 2] as usize & 0x1FFE;
+        if end > begin && addr >= end { begin + (addr - end) % (end - begin) } else { addr & 0x1FFE }
+    }
+
+    fn read_rx_data(&mut self) -> u16 {
+        let addr = self.regs[WiFi::W_RXBUF_RD_ADDR / 2] as usize & 0x1FFE;
+        let value = self.read_ram(addr);
+        let mut next_addr = self.rx_wrap(addr + 2);
+        if next_addr == self.regs[WiFi::W_RXBUF_GAP / 2] as usize & 0x1FFE {
+            next_addr = self.rx_wrap(next_addr + 2 * (self.regs[WiFi::W_RXBUF_GAPDISP / 2] as usize & 0xFFF));
+        }
+        self.regs[WiFi::W_RXBUF_RD_ADDR / 2] = next_addr as u16;
+        self.regs[WiFi::W_RXBUF_RD_DATA / 2] = value;
+
+        let count = &mut self.regs[WiFi::W_RXBUF_COUNT / 2];
+        if *count != 0 {
+            *count -= 1;
+            if *count == 0 { self.request_interrupt(WiFi::IRQ_RXBUF_COUNT) }
+        }
+        value
+    }
+
+    fn write_tx_data(&mut self, value: u16) {
+        let addr = self.regs[WiFi::W_TXBUF_WR_ADDR / 2] as usize & 0x1FFE;
+        self.write_ram(addr, value);
+        let mut next_addr = addr + 2;
+        if next_addr == self.regs[WiFi::W_TXBUF_GAP / 2] as usize & 0x1FFE {
+            next_addr += 2 * (self.regs[WiFi::W_TXBUF_GAPDISP / 2] as usize & 0xFFF);
+        }
+        self.regs[WiFi::W_TXBUF_WR_ADDR / 2] = (next_addr & 0x1FFE) as u16;
+
+        let count = &mut self.regs[WiFi::W_TXBUF_COUNT / 2];
+        if *count != 0 {
+            *count -= 1;
+            if *count == 0 { self.request_interrupt(WiFi::IRQ_TXBUF_COUNT) }
+        }
+    }
+
+    // Timers
+
+    fn us_to_cycles(us: usize) -> usize {
+        (us as u64 * NDS::CLOCK_RATE as u64 / 1_000_000) as usize
+    }
+
+    fn read_us_count(&self, cycle: usize) -> u64 {
+        if self.regs[WiFi::W_US_COUNTCNT / 2] & 0x1 != 0 {
+            self.us_count + cycle.saturating_sub(self.us_count_cycle) as u64 * 1_000_000 / NDS::CLOCK_RATE as u64
+        } else { self.us_count }
+    }
+
+    fn latch_us_count(&mut self, cycle: usize) {
+        self.us_count = self.read_us_count(cycle);
+        self.us_count_cycle = cycle;
+    }
+
+    fn schedule_compare(&mut self, scheduler: &mut Scheduler) {
+        scheduler.remove(Event::WiFiUSCompare);
+        scheduler.remove(Event::WiFiPreBeacon);
+        if self.regs[WiFi::W_US_COUNTCNT / 2] & 0x1 == 0 || self.regs[WiFi::W_US_COMPARECNT / 2] & 0x1 == 0 { return }
+        let now = self.read_us_count(scheduler.cycle);
+        let cycles_until = |us: u64| WiFi::us_to_cycles(us.saturating_sub(now) as usize);
+        scheduler.schedule(Event::WiFiUSCompare, HW::on_wifi_us_compare, cycles_until(self.us_compare));
+        let pre_beacon = self.regs[WiFi::W_PRE_BEACON / 2] as u64;
+        if pre_beacon != 0 && self.us_compare.saturating_sub(pre_beacon) > now {
+            scheduler.schedule(Event::WiFiPreBeacon, HW::on_wifi_pre_beacon, cycles_until(self.us_compare - pre_beacon));
+        }
+    }
+
+    fn beacon_timeslot(&mut self, scheduler: &mut Scheduler) {
+        self.request_interrupt(WiFi::IRQ_BEACON);
+        if self.regs[WiFi::W_TXBUF_BEACON / 2] & 0x8000 != 0 && self.awake() && self.tx_slot.is_none() {
+            self.start_tx(scheduler, TXSlot::Beacon);
+        }
+
+        // Beacon intervals are in units of 1024us
+        let interval = (self.regs[WiFi::W_BEACONINT / 2] as u64 & 0x3FF) * 1024;
+        if interval == 0 { return }
+        let now = self.read_us_count(scheduler.cycle);
+        if self.us_compare <= now { self.us_compare += ((now - self.us_compare) / interval + 1) * interval }
+        for i in 0..4 { self.regs[WiFi::W_US_COMPARE0 / 2 + i] = (self.us_compare >> (16 * i)) as u16 }
+        self.schedule_compare(scheduler);
+    }
+
+    // Transmission
+
+    fn start_next_tx(&mut self, scheduler: &mut Scheduler) {
+        if self.tx_slot.is_some() || !self.awake() { return }
+        let requested = self.regs[WiFi::W_TXREQ_READ / 2];
+        let slot = [TXSlot::Cmd, TXSlot::Loc3, TXSlot::Loc2, TXSlot::Loc1].iter().copied().find(|slot|
+            requested & slot.bit() != 0 && self.regs[slot.loc_reg() / 2] & 0x8000 != 0
+        );
+        if let Some(slot) = slot { self.start_tx(scheduler, slot) }
+    }
+
+    fn start_tx(&mut self, scheduler: &mut Scheduler, slot: TXSlot) {
+        let loc = self.regs[slot.loc_reg() / 2];
+        let addr = (loc as usize & 0xFFF) * 2;
+        let rate = self.ram[(addr + 0x8) & (WiFi::RAM_SIZE - 1)];
+        let len = self.read_ram(addr + 0xA) as usize;
+        let start = addr + WiFi::TX_HEADER_LEN;
+        let mut data: Vec<u8> = (0..len.saturating_sub(WiFi::FCS_LEN))
+            .map(|i| self.ram[(start + i) & (WiFi::RAM_SIZE - 1)]).collect();
+        if data.len() >= 24 && loc & 0x2000 == 0 {
+            let seq_no = self.regs[WiFi::W_TX_SEQNO / 2];
+            data[22..24].copy_from_slice(&(seq_no << 4).to_le_bytes());
+            self.regs[WiFi::W_TX_SEQNO / 2] = (seq_no + 1) & 0xFFF;
+        }
+        if slot == TXSlot::Beacon && data.len() >= 32 {
+            data[24..32].copy_from_slice(&self.read_us_count(scheduler.cycle).to_le_bytes());
+        }
+        self.link.send(&WiFiFrame { rate, data });
+
+        self.tx_slot = Some(slot);
+        self.regs[WiFi::W_TXBUSY / 2] |= slot.bit();
+        self.set_rf_status(WiFi::RF_STATUS_TX);
+        self.request_interrupt(WiFi::IRQ_TX_START);
+
+        let preamble_us = if self.regs[WiFi::W_PREAMBLE / 2] & 0x4 != 0 { 96 } else { 192 };
+        let us_per_byte = if rate == 0x14 { 4 } else { 8 };
+        let mut duration = preamble_us + len * us_per_byte;
+        if slot == TXSlot::Cmd && len >= 28 {
+            // Wait for every polled client to reply
+            let clients = self.read_ram(start + 26).count_ones() as usize;
+            duration += clients * self.regs[WiFi::W_CMD_REPLYTIME / 2] as usize;
+        }
+        scheduler.schedule(Event::WiFiTransferFinished, HW::on_wifi_transfer_finished, WiFi::us_to_cycles(duration));
+    }
+
+    fn finish_tx(&mut self, scheduler: &mut Scheduler) {
+        let slot = match self.tx_slot.take() {
+            Some(slot) => slot,
+            None => return,
+        };
+        let loc_reg = slot.loc_reg() / 2;
+        self.write_ram((self.regs[loc_reg] as usize & 0xFFF) * 2, 0x0001);
+        self.regs[WiFi::W_TXBUSY / 2] &= !slot.bit();
+        match slot {
+            TXSlot::Beacon => (),
+            TXSlot::Cmd => {
+                self.regs[loc_reg] &= !0x8000;
+                self.regs[WiFi::W_TXREQ_READ / 2] &= !slot.bit();
+                self.send_mp_ack();
+                self.request_interrupt(WiFi::IRQ_MP_END);
+            },
+            _ => self.regs[loc_reg] &= !0x8000,
+        }
+        self.regs[WiFi::W_TXSTAT / 2] = (slot as u16) << 12 | 0x1;
+        self.set_rf_status(WiFi::RF_STATUS_IDLE);
+        self.request_interrupt(WiFi::IRQ_TX_COMPLETE);
+        self.start_next_tx(scheduler);
+    }
+
+    // Ends a multiplayer exchange started with W_TXBUF_CMD
+    fn send_mp_ack(&mut self) {
+        let mut data = vec![0; 28];
+        data[0..2].copy_from_slice(&0x0218u16.to_le_bytes());
+        data[4..10].copy_from_slice(&WiFi::MP_ACK_ADDR);
+        for i in 0..3 {
+            data[10 + 2 * i..12 + 2 * i].copy_from_slice(&self.regs[WiFi::W_MACADDR / 2 + i].to_le_bytes());
+            data[16 + 2 * i..18 + 2 * i].copy_from_slice(&self.regs[WiFi::W_BSSID / 2 + i].to_le_bytes());
+        }
+        self.link.send(&WiFiFrame { rate: 0x14, data });
+    }
+
+    // Reception
+
+    fn poll(&mut self, scheduler: &mut Scheduler) {
+        while let Some(frame) = self.link.recv() { self.receive(scheduler, frame) }
+    }
+
+    fn receive(&mut self, scheduler: &mut Scheduler, frame: WiFiFrame) {
+        let data = &frame.data;
+        if !self.awake() || self.regs[WiFi::W_RXCNT / 2] & 0x8000 == 0 || data.len() < 24 { return }
+        let reg_bytes = |reg: usize| {
+            let mut bytes = [0; 6];
+            for i in 0..3 { bytes[2 * i..2 * i + 2].copy_from_slice(&self.regs[reg / 2 + i].to_le_bytes()) }
+            bytes
+        };
+        let mac = reg_bytes(WiFi::W_MACADDR);
+        // Group addresses have bit 0 set
+        if data[4] & 0x1 == 0 && data[4..10] != mac { return }
+
+        let frame_control = u16::from_le_bytes([data[0], data[1]]);
+        let mut flags = 0x0010 | match (frame_control >> 2 & 0x3, frame_control >> 4 & 0xF) {
+            (0, 0x8) => 0x1, // Beacon
+            (1, _) => 0x5,
+            (2, 0x0) => 0x8,
+            (2, 0x1) => 0xE, // Multiplayer reply
+            (2, 0x2) => 0xC, // Multiplayer command
+            (2, _) => 0xD,
+            _ => 0x0,
+        };
+        if frame_control & 0x0400 != 0 { flags |= 0x0100 }
+        if data[16..22] == reg_bytes(WiFi::W_BSSID) { flags |= 0x8000 }
+        let header = [flags, 0x0040, 0x0000, frame.rate as u16, data.len() as u16, 0x1010];
+
+        let mut addr = (self.regs[WiFi::W_RXBUF_WRCSR / 2] as usize & 0xFFF) * 2;
+        let halfwords = data.chunks(2).map(|bytes| bytes[0] as u16 | (*bytes.get(1).unwrap_or(&0) as u16) << 8);
+        for value in header.iter().copied().chain(halfwords).collect::<Vec<_>>() {
+            self.write_ram(addr, value);
+            addr = self.rx_wrap(addr + 2);
+        }
+        // Frames start on word boundaries
+        if addr & 0x2 != 0 { addr = self.rx_wrap(addr + 2) }
+        self.regs[WiFi::W_RXBUF_WRCSR / 2] = (addr / 2) as u16;
+        self.request_interrupt(WiFi::IRQ_RX_START | WiFi::IRQ_RX_COMPLETE);
+
+        // Clients reply to multiplayer commands that poll them
+        let aid = self.regs[WiFi::W_AID_LOW / 2] & 0xF;
+        let polled = data.len() >= 28 && u16::from_le_bytes([data[26], data[27]]) & (1 << aid) != 0;
+        if frame_control == 0x0228 && polled && self.regs[WiFi::W_TXBUF_REPLY1 / 2] & 0x8000 != 0
+            && self.tx_slot.is_none() {
+            self.start_tx(scheduler, TXSlot::Reply);
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Default)]
+enum TXSlot {
+    #[default]
+    Loc1,
+    Cmd,
+    Loc2,
+    Loc3,
+    Beacon,
+    Reply,
+}
+
+savestate_enum!(TXSlot { Loc1, Cmd, Loc2, Loc3, Beacon, Reply });
+
+impl TXSlot {
+    fn loc_reg(self) -> usize {
+        match self {
+            TXSlot::Loc1 => WiFi::W_TXBUF_LOC1,
+            TXSlot::Cmd => WiFi::W_TXBUF_CMD,
+            TXSlot::Loc2 => WiFi::W_TXBUF_LOC2,
+            TXSlot::Loc3 => WiFi::W_TXBUF_LOC3,
+            TXSlot::Beacon => WiFi::W_TXBUF_BEACON,
+            TXSlot::Reply => WiFi::W_TXBUF_REPLY1,
+        }
+    }
+
+    // Bit in W_TXREQ and W_TXBUSY
+    fn bit(self) -> u16 {
+        match self {
+            TXSlot::Loc1 => 1 << 0,
+            TXSlot::Cmd => 1 << 1,
+            TXSlot::Loc2 => 1 << 2,
+            TXSlot::Loc3 => 1 << 3,
+            TXSlot::Beacon => 1 << 4,
+            TXSlot::Reply => 1 << 7,
+        }
+    }
+}
+
+impl HW {
+    pub fn set_wifi_link(&mut self, link: Box<dyn WiFiLink>) {
+        self.wifi.set_link(link);
+    }
+
+    pub(super) fn check_wifi_interrupt(&mut self) {
+        if self.wifi.take_interrupt() { self.interrupts[0].request |= InterruptRequest::WIFI }
+    }
+
+    pub(super) fn on_wifi_poll(&mut self, _event: Event) {
+        self.scheduler.schedule(Event::WiFiPoll, HW::on_wifi_poll, WiFi::us_to_cycles(WiFi::POLL_INTERVAL_US));
+        self.wifi.poll(&mut self.scheduler);
+        self.check_wifi_interrupt();
+    }
+
+    pub(super) fn on_wifi_us_compare(&mut self, _event: Event) {
+        self.wifi.beacon_timeslot(&mut self.scheduler);
+        self.check_wifi_interrupt();
+    }
+
+    pub(super) fn on_wifi_pre_beacon(&mut self, _event: Event) {
+        self.wifi.request_interrupt(WiFi::IRQ_PRE_BEACON);
+        self.check_wifi_interrupt();
+    }
+
+    pub(super) fn on_wifi_transfer_finished(&mut self, _event: Event) {
+        self.wifi.finish_tx(&mut self.scheduler);
+        self.check_wifi_interrupt();
+    }
+}
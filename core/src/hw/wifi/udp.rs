@@ -0,0 +1,100 @@
+use std::collections::VecDeque;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+use super::{WiFiFrame, WiFiLink};
+
+// Ni-Fi: carries raw frames between emulator instances on the same LAN
+pub struct UdpLink {
+    socket: UdpSocket,
+    peers: Vec<SocketAddr>,
+    id: u32,
+    // Received frames are held back to smooth out jitter
+    latency: Duration,
+    pending: VecDeque<(Instant, WiFiFrame)>,
+    buffer: Vec<u8>,
+}
+
+impl UdpLink {
+    pub const BASE_PORT: u16 = 7064;
+    pub const MAX_INSTANCES: u16 = 8;
+    const MAGIC: [u8; 4] = *b"NIFI";
+    const HEADER_LEN: usize = 9;
+    const MAX_PACKET_LEN: usize = 0x1000;
+
+    // Binds the first free port and broadcasts to the ports of every other instance
+    pub fn new(latency: Duration) -> io::Result<Self> {
+        let ports = UdpLink::BASE_PORT..UdpLink::BASE_PORT + UdpLink::MAX_INSTANCES;
+        let mut last_err = None;
+        for port in ports.clone() {
+            match UdpSocket::bind((Ipv4Addr::UNSPECIFIED, port)) {
+                Ok(socket) => {
+                    socket.set_broadcast(true)?;
+                    let peers = ports.filter(|peer| *peer != port)
+                        .map(|peer| SocketAddr::from((Ipv4Addr::BROADCAST, peer))).collect();
+                    return UdpLink::from_socket(socket, peers, latency)
+                },
+                Err(err) => last_err = Some(err),
+            }
+        }
+        Err(last_err.unwrap())
+    }
+
+    pub fn with_peers(addr: SocketAddr, peers: Vec<SocketAddr>, latency: Duration) -> io::Result<Self> {
+        UdpLink::from_socket(UdpSocket::bind(addr)?, peers, latency)
+    }
+
+    fn from_socket(socket: UdpSocket, peers: Vec<SocketAddr>, latency: Duration) -> io::Result<Self> {
+        socket.set_nonblocking(true)?;
+        let id = std::process::id() << 16 | socket.local_addr()?.port() as u32;
+        Ok(UdpLink {
+            socket,
+            peers,
+            id,
+            latency,
+            pending: VecDeque::new(),
+            buffer: vec![0; UdpLink::MAX_PACKET_LEN],
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+}
+
+impl WiFiLink for UdpLink {
+    fn send(&mut self, frame: &WiFiFrame) {
+        let mut packet = Vec::with_capacity(UdpLink::HEADER_LEN + frame.data.len());
+        packet.extend_from_slice(&UdpLink::MAGIC);
+        packet.extend_from_slice(&self.id.to_le_bytes());
+        packet.push(frame.rate);
+        packet.extend_from_slice(&frame.data);
+        for peer in self.peers.iter() {
+            if let Err(err) = self.socket.send_to(&packet, peer) {
+                warn!("Unable to Send Ni-Fi Packet to {}: {}", peer, err);
+            }
+        }
+    }
+
+    fn recv(&mut self) -> Option<WiFiFrame> {
+        loop {
+            let len = match self.socket.recv_from(&mut self.buffer) {
+                Ok((len, _)) => len,
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) => { warn!("Unable to Receive Ni-Fi Packet: {}", err); break },
+            };
+            let packet = &self.buffer[..len];
+            if len < UdpLink::HEADER_LEN || packet[..4] != UdpLink::MAGIC { continue }
+            // Broadcasts are also received by the sender
+            if packet[4..8] == self.id.to_le_bytes() { continue }
+            let frame = WiFiFrame { rate: packet[8], data: packet[UdpLink::HEADER_LEN..].to_vec() };
+            self.pending.push_back((Instant::now() + self.latency, frame));
+        }
+
+        match self.pending.front() {
+            Some((ready, _)) if *ready <= Instant::now() => self.pending.pop_front().map(|(_, frame)| frame),
+            _ => None,
+        }
+    }
+}
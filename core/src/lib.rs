@@ -1,11 +1,30 @@
+#![cfg_attr(feature = "simd", feature(portable_simd))]
+
 #[macro_use] pub extern crate log;
 use num_traits as num;
-pub use simplelog;
 
+#[macro_use] mod notifications;
+#[macro_use] mod savestate;
 mod arm7;
 mod arm9;
+mod call_stack;
 mod hw;
+#[cfg(feature = "host")]
+mod video;
 
+pub mod achievements;
+pub mod cheats;
+pub mod events;
+pub mod fault;
+#[cfg(feature = "host")]
+pub mod logging;
 pub mod nds;
+#[cfg(feature = "host")]
+pub mod netplay;
+pub mod rewind;
+pub mod rom;
+pub mod screenshot;
+pub mod symbols;
+pub mod trace;
 
 pub use nds::NDS;
@@ -0,0 +1,137 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+
+use log::{Level, LevelFilter, Log, Metadata, Record, SetLoggerError};
+
+// Handles the log macros used throughout the core. Levels can be set per module while running, so
+// e.g. only SPU register accesses can be logged with set_module_level("nds_core::hw::spu", LevelFilter::Trace).
+struct Logger {
+    filter: RwLock<Filter>,
+    output: Mutex<Output>,
+}
+
+struct Filter {
+    level: LevelFilter,
+    // Module path prefixes, longest first so the most specific one is used
+    module_levels: Vec<(String, LevelFilter)>,
+}
+
+struct Output {
+    file: Option<(PathBuf, BufWriter<File>)>,
+    // Warnings from the same line after this many are dropped, 0 keeps all of them
+    repeat_limit: usize,
+    repeats: BTreeMap<(String, u32), usize>,
+}
+
+static LOGGER: Logger = Logger {
+    filter: RwLock::new(Filter {
+        level: LevelFilter::Warn,
+        module_levels: Vec::new(),
+    }),
+    output: Mutex::new(Output {
+        file: None,
+        repeat_limit: 0,
+        repeats: BTreeMap::new(),
+    }),
+};
+
+impl Filter {
+    fn level(&self, target: &str) -> LevelFilter {
+        self.module_levels.iter().find(|(module, _)| target.starts_with(module.as_str()))
+            .map_or(self.level, |(_, level)| *level)
+    }
+
+    fn max_level(&self) -> LevelFilter {
+        self.module_levels.iter().map(|(_, level)| *level).fold(self.level, std::cmp::max)
+    }
+}
+
+impl Output {
+    fn write(&mut self, line: &str) {
+        eprintln!("{}", line);
+        if let Some((path, file)) = self.file.as_mut() {
+            if let Err(err) = writeln!(file, "{}", line) {
+                eprintln!("Unable to Write to {}: {}!", path.display(), err);
+                self.file = None;
+            }
+        }
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.filter.read().unwrap().level(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) { return }
+        let mut output = self.output.lock().unwrap();
+        if record.level() == Level::Warn && output.repeat_limit != 0 {
+            let limit = output.repeat_limit;
+            let count = output.repeats.entry((record.target().to_string(), record.line().unwrap_or(0))).or_insert(0);
+            *count += 1;
+            if *count > limit { return }
+            if *count == limit {
+                output.write(&format!("[{}] {}: {}", record.level(), record.target(), record.args()));
+                output.write(&format!("[{}] {}: Suppressing Further Warnings from Line {}", record.level(),
+                    record.target(), record.line().unwrap_or(0)));
+                return
+            }
+        }
+        output.write(&format!("[{}] {}: {}", record.level(), record.target(), record.args()));
+    }
+
+    fn flush(&self) {
+        if let Some((_, file)) = self.output.lock().unwrap().file.as_mut() { file.flush().ok(); }
+    }
+}
+
+fn update_max_level() {
+    log::set_max_level(LOGGER.filter.read().unwrap().max_level());
+}
+
+pub fn init() -> Result<(), SetLoggerError> {
+    log::set_logger(&LOGGER)?;
+    update_max_level();
+    Ok(())
+}
+
+pub fn set_level(level: LevelFilter) {
+    LOGGER.filter.write().unwrap().level = level;
+    update_max_level();
+}
+
+pub fn set_module_level(module: &str, level: LevelFilter) {
+    let mut filter = LOGGER.filter.write().unwrap();
+    filter.module_levels.retain(|(prefix, _)| prefix != module);
+    filter.module_levels.push((module.to_string(), level));
+    filter.module_levels.sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+    drop(filter);
+    update_max_level();
+}
+
+pub fn clear_module_levels() {
+    LOGGER.filter.write().unwrap().module_levels.clear();
+    update_max_level();
+}
+
+// Messages are also appended to the file until it's set to None. Setting the same file again keeps it open.
+pub fn set_file(path: Option<&Path>) -> io::Result<()> {
+    let mut output = LOGGER.output.lock().unwrap();
+    if output.file.as_ref().map(|(cur_path, _)| cur_path.as_path()) == path { return Ok(()) }
+    if let Some((_, file)) = output.file.as_mut() { file.flush()? }
+    output.file = match path {
+        Some(path) => Some((path.to_path_buf(), BufWriter::new(File::create(path)?))),
+        None => None,
+    };
+    Ok(())
+}
+
+pub fn set_repeat_limit(limit: usize) {
+    let mut output = LOGGER.output.lock().unwrap();
+    output.repeat_limit = limit;
+    output.repeats.clear();
+}
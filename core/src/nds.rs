@@ -1,60 +1,702 @@
-use std::path::PathBuf;
+use std::collections::HashSet;
+use std::io::{self, Write};
+#[cfg(feature = "host")]
+use std::path::Path;
 
 use crate::arm7::ARM7;
+use crate::achievements;
 use crate::arm9::ARM9;
-use crate::hw::HW;
+pub use crate::call_stack::StackFrame;
+use crate::events::{Event, Hook, HookId, Hooks};
+use crate::fault::Fault;
+use crate::hw::{HW, Header};
+use crate::rom::Banner;
+use crate::savestate::{Savestate, Section, SectionLoader, StateReader, StateWriter};
+use crate::screenshot::{Layout, Screenshot};
+use crate::trace::{OutputHashes, TraceLevel, Tracer};
+#[cfg(feature = "host")]
+use crate::video::VideoRecorder;
 
 pub use crate::hw::{
+    AudioSink,
+    CapturedPolygon,
+    CapturedVertex,
+    ChannelFormat,
+    ChannelState,
+    ConsoleModel,
     Engine,
+    EventKind,
+    EventStats,
+    Frame,
     GraphicsType,
-    Key
+    GuitarKey,
+    Key,
+    LocalLink,
+    MemoryStorage,
+    NoLink,
+    OAMEntry,
+    OBJMode,
+    OpenBus,
+    PendingEvent,
+    PolygonMode,
+    Register,
+    RegisterField,
+    RtcMode,
+    SampleQueue,
+    SaveStorage,
+    SdImage,
+    Slot2,
+    SystemFiles,
+    Texture,
+    TextureFormat,
+    TexturePack,
+    WiFiFrame,
+    WiFiLink,
 };
+#[cfg(feature = "host")]
+pub use crate::hw::{FileStorage, TextureDir, UdpLink};
+#[cfg(feature = "bridge")]
+pub use crate::hw::BridgeLink;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Cpu {
+    ARM7,
+    ARM9,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AddressSpace {
+    // Memory as seen by the CPU, following the current mappings
+    CPU(Cpu),
+    // Raw contents of VRAM banks A - I as 0 - 8, wherever they're mapped
+    VRAMBank(usize),
+}
+
+// What a save state contains, for checking states without loading them
+#[derive(Debug)]
+pub struct StateInfo {
+    pub version: u32,
+    pub game_code: String,
+    // Empty for unsupported versions
+    pub sections: Vec<SectionInfo>,
+    // Sections this build needs that aren't in the state
+    pub missing: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct SectionInfo {
+    pub name: String,
+    pub version: u32,
+    pub len: usize,
+    // None for sections this build doesn't know about, which are skipped when loading
+    pub current_version: Option<u32>,
+    pub loadable: bool,
+}
 
 pub struct NDS {
     arm9_cycles_ahead: i32, // Measured in 66 MHz ARM9 cycles
     arm7: ARM7,
     arm9: ARM9,
     hw: HW,
+    #[cfg(feature = "host")]
+    video_recorder: Option<VideoRecorder>,
+    hooks: Hooks,
+    breakpoints: HashSet<(Cpu, u32)>,
+    // Set when stopped at a breakpoint so the instruction runs when emulation continues
+    stopped_at: Option<(Cpu, u32)>,
+    tracer: Option<Tracer>,
+    deterministic: bool,
+    hardcore: bool,
 }
 
+savestate_sections!(NDS {
+    b"CPU " 1 => { arm9_cycles_ahead, arm7, arm9 },
+});
+
 impl NDS {
     pub const CLOCK_RATE: usize = 33513982;
+    const STATE_MAGIC: [u8; 4] = *b"NDSS";
+    // Version 1 states weren't split into sections, and can't be loaded since the layouts of their fields have changed
+    const STATE_VERSION: u32 = 2;
+    // 2000-01-01 00:00:00 UTC
+    const DETERMINISTIC_TIMESTAMP: u64 = 946_684_800;
 
-    pub fn new(bios7: Vec<u8>, bios9: Vec<u8>, firmware: Vec<u8>, rom: Vec<u8>, save_file: PathBuf) -> Self {
-        let direct_boot = true;
-        let mut hw = HW::new(bios7, bios9, firmware, rom, save_file, direct_boot);
-        NDS {
+    // Direct boot skips the BIOS and firmware boot sequence and starts the game immediately.
+    // A DSi still boots with the DS BIOSes, so it needs direct boot to start DSi titles in DSi mode.
+    pub fn new(files: SystemFiles, rom: Vec<u8>, save_storage: Box<dyn SaveStorage>, audio_sink: Box<dyn AudioSink>,
+        direct_boot: bool, model: ConsoleModel) -> Result<Self, Fault> {
+        let mut hw = HW::new(files, rom, save_storage, audio_sink, direct_boot, model)?;
+        Ok(NDS {
             arm9_cycles_ahead: 0,
             arm7: ARM7::new(&mut hw, direct_boot),
             arm9: ARM9::new(&mut hw, direct_boot),
             hw,
+            #[cfg(feature = "host")]
+            video_recorder: None,
+            hooks: Hooks::new(),
+            breakpoints: HashSet::new(),
+            stopped_at: None,
+            tracer: None,
+            deterministic: false,
+            hardcore: false,
+        })
+    }
+
+    // Returns false if a breakpoint stopped emulation before the frame finished.
+    // Once a fault is returned, every later frame returns it too until a save state is loaded.
+    pub fn run_frame(&mut self) -> Result<bool, Fault> {
+        let finished = if self.hw.gba_mode() { self.emulate_gba_frame() } else { self.emulate_nds_frame() };
+        if let Some(fault) = self.hw.faults.get() { return Err(fault) }
+        if !finished { return Ok(false) }
+        self.hooks.emit(Event::VBlank);
+        if self.hw.update_backup() { self.hooks.emit(Event::BackupModified) }
+        #[cfg(feature = "host")]
+        if let Some(video_recorder) = self.video_recorder.as_mut() {
+            let samples = self.hw.take_captured_samples();
+            if let Err(err) = video_recorder.write_frame(&self.hw.gpu.frame(), &samples) {
+                notify!(self.hw.notifier, "Unable to Write Video Recording: {}!", err);
+            }
         }
+        self.trace_frame();
+        self.hooks.emit(Event::FrameCompleted);
+        Ok(true)
     }
 
-    pub fn emulate_frame(&mut self) {
-        while !self.hw.rendered_frame() {
+    // Returns false if the frame didn't finish because of a breakpoint or switching to GBA mode
+    fn emulate_nds_frame(&mut self) -> bool {
+        // An ARM7 breakpoint stops before the ARM7 catches up to the ARM9, so that's where emulation continues
+        let mut resume_arm7 = matches!(self.stopped_at, Some((Cpu::ARM7, _)));
+        while !self.hw.rendered_frame() && !self.hw.powered_off() && !self.hw.faults.raised() {
             if !self.hw.gpu.bus_stalled() {
-                self.arm9.handle_irq(&mut self.hw);
-                self.arm9_cycles_ahead += if self.hw.cp15.arm9_halted {
-                    self.hw.cycles_until_event()
-                } else {
-                    self.arm9.emulate_instr(&mut self.hw)
-                } as i32;
-
-                while self.arm9_cycles_ahead >= 0 {
+                if !std::mem::take(&mut resume_arm7) {
+                    self.arm9.handle_irq(&mut self.hw);
+                    let arm9_stopped = self.hw.arm9_halted() || self.hw.dma_active(true);
+                    // Only an event can wake a CPU or finish a DMA, so while both CPUs wait the scheduler skips
+                    // straight from one event to the next
+                    if arm9_stopped && self.hw.arm7_halted() && !self.hw.dma_active(false) {
+                        self.hw.clock(self.hw.cycles_until_event());
+                        continue
+                    }
+                    // DMAs steal the bus from their CPU until they finish
+                    self.arm9_cycles_ahead += if arm9_stopped {
+                        self.hw.cycles_until_event()
+                    } else {
+                        if self.hit_breakpoint(Cpu::ARM9, self.arm9.pc()) { return false }
+                        if self.tracing_instrs(Cpu::ARM9) { self.trace_instr(Cpu::ARM9) }
+                        self.arm9.emulate_instr(&mut self.hw)
+                    } as i32;
+                }
+
+                while self.arm9_cycles_ahead >= 0 && !self.hw.faults.raised() {
                     self.arm7.handle_irq(&mut self.hw);
-                    let arm7_cycles_ran = if self.hw.haltcnt.halted() { 1 }
-                    else { self.arm7.emulate_instr(&mut self.hw) };
+                    // Skips ahead to the next event, without getting ahead of the ARM9
+                    let arm7_cycles_ran = if self.hw.arm7_halted() || self.hw.dma_active(false) {
+                        self.hw.cycles_until_event().clamp(1, self.arm9_cycles_ahead as usize / 2 + 1)
+                    } else {
+                        if self.hit_breakpoint(Cpu::ARM7, self.arm7.pc()) { return false }
+                        if self.tracing_instrs(Cpu::ARM7) { self.trace_instr(Cpu::ARM7) }
+                        self.arm7.emulate_instr(&mut self.hw)
+                    };
                     self.hw.clock(arm7_cycles_ran);
-                    self.arm9_cycles_ahead -= 2 * arm7_cycles_ran as i32
+                    self.arm9_cycles_ahead -= 2 * arm7_cycles_ran as i32;
+                    if self.hw.gba_mode() {
+                        self.arm7 = ARM7::new_gba(&mut self.hw);
+                        return false
+                    }
                 }
             } else { self.hw.clock_until_event() }
         }
-        self.hw.save_backup();
+        true
+    }
+
+    // The ARM9 is off and the ARM7 runs at the GBA's 16.78 MHz
+    fn emulate_gba_frame(&mut self) -> bool {
+        while !self.hw.rendered_frame() && !self.hw.faults.raised() {
+            self.arm7.handle_irq(&mut self.hw);
+            let cycles_ran = if self.hw.arm7_halted() || self.hw.dma_active(false) {
+                (self.hw.cycles_until_event() / 2).max(1)
+            } else {
+                if self.hit_breakpoint(Cpu::ARM7, self.arm7.pc()) { return false }
+                if self.tracing_instrs(Cpu::ARM7) { self.trace_instr(Cpu::ARM7) }
+                self.arm7.emulate_instr(&mut self.hw)
+            };
+            self.hw.clock(2 * cycles_ran);
+        }
+        true
+    }
+
+    fn hit_breakpoint(&mut self, cpu: Cpu, addr: u32) -> bool {
+        // Taken first so it's cleared even if the breakpoint was removed while stopped
+        if self.stopped_at.take() == Some((cpu, addr)) { return false }
+        if self.breakpoints.is_empty() || !self.breakpoints.contains(&(cpu, addr)) { return false }
+        self.stopped_at = Some((cpu, addr));
+        self.hooks.emit(Event::Breakpoint(cpu, addr));
+        true
+    }
+
+    // Replaces any trace in progress. Frame records are written after every frame, and with TraceLevel::Instruction
+    // the registers of each CPU are also written before every instruction. TraceLevel::Cpu only writes instructions.
+    pub fn start_trace(&mut self, writer: Box<dyn Write + Send>, level: TraceLevel) {
+        self.tracer = Some(Tracer::new(writer, level));
+    }
+
+    pub fn stop_trace(&mut self) -> io::Result<()> {
+        match self.tracer.take() {
+            Some(mut tracer) => tracer.flush(),
+            None => Ok(()),
+        }
+    }
+
+    pub fn tracing(&self) -> bool {
+        self.tracer.is_some()
+    }
+
+    fn tracing_instrs(&self, cpu: Cpu) -> bool {
+        match self.tracer {
+            Some(Tracer { level: TraceLevel::Instruction, .. }) => true,
+            Some(Tracer { level: TraceLevel::Cpu(traced_cpu), .. }) => traced_cpu == cpu,
+            _ => false,
+        }
+    }
+
+    fn pc(&self, cpu: Cpu) -> u32 {
+        match cpu {
+            Cpu::ARM7 => self.arm7.pc(),
+            Cpu::ARM9 => self.arm9.pc(),
+        }
+    }
+
+    fn trace_instr(&mut self, cpu: Cpu) {
+        let pc = self.pc(cpu);
+        let mut regs = [0; 17];
+        for (i, reg) in regs.iter_mut().enumerate() { *reg = self.reg(cpu, i as u32) }
+        let instr_size = if regs[16] & 0x20 != 0 { 2 } else { 4 };
+        let opcode = (0..instr_size).rev()
+            .fold(0, |opcode, i| opcode << 8 | self.peek(cpu, pc.wrapping_add(i)).unwrap_or(0) as u32);
+        let result = self.tracer.as_mut().unwrap().instruction(cpu, pc, opcode, &regs);
+        self.handle_trace_result(result);
+    }
+
+    fn trace_frame(&mut self) {
+        if matches!(self.tracer, None | Some(Tracer { level: TraceLevel::Cpu(_), .. })) { return }
+        let mut fields = Vec::new();
+        for (cpu, prefix) in [(Cpu::ARM9, "ARM9"), (Cpu::ARM7, "ARM7")] {
+            if cpu == Cpu::ARM9 && self.hw.gba_mode() { continue }
+            fields.push((format!("{}.PC", prefix), self.pc(cpu) as u64));
+            fields.extend(self.io_registers(cpu).into_iter()
+                .filter(|register| Tracer::FRAME_REGISTERS.contains(&register.name.as_str()))
+                .map(|register| (format!("{}.{}", prefix, register.name), register.value)));
+        }
+        let frame = self.hw.gpu.frame();
+        let (count, frame_hash) = (frame.count, Tracer::hash(&[frame.top, frame.bottom]));
+        let result = self.tracer.as_mut().unwrap().frame(count, &fields, frame_hash);
+        self.handle_trace_result(result);
+    }
+
+    fn handle_trace_result(&mut self, result: io::Result<()>) {
+        if let Err(err) = result {
+            notify!(self.hw.notifier, "Unable to Write Trace: {}!", err);
+            self.tracer = None;
+        }
+    }
+
+    // Warnings for the user since the last call
+    pub fn take_notifications(&mut self) -> Vec<String> {
+        self.hw.notifier.take()
+    }
+
+    pub fn add_hook(&mut self, hook: Hook) -> HookId {
+        self.hooks.add(hook)
+    }
+
+    pub fn remove_hook(&mut self, id: HookId) {
+        self.hooks.remove(id)
+    }
+
+    pub fn add_breakpoint(&mut self, cpu: Cpu, addr: u32) {
+        self.breakpoints.insert((cpu, addr));
+    }
+
+    pub fn remove_breakpoint(&mut self, cpu: Cpu, addr: u32) {
+        self.breakpoints.remove(&(cpu, addr));
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    pub fn set_gba_bios(&mut self, bios: Vec<u8>) {
+        self.hw.set_gba_bios(bios);
+    }
+
+    pub fn gba_mode(&self) -> bool {
+        self.hw.gba_mode()
+    }
+
+    pub fn enter_gba_mode(&mut self) {
+        self.hw.enter_gba_mode();
+        self.arm7 = ARM7::new_gba(&mut self.hw);
+    }
+
+    // States can only be loaded into the game they were saved from
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = StateWriter::new();
+        state.write_bytes(&NDS::STATE_MAGIC);
+        NDS::STATE_VERSION.save(&mut state);
+        self.game_id().save(&mut state);
+        self.save_machine(&mut state);
+        self.hooks.emit(Event::StateSaved);
+        state.finish()
+    }
+
+    pub fn load_state(&mut self, data: &[u8]) -> io::Result<()> {
+        if self.hardcore {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, "Save States are Disabled in Hardcore Mode"))
+        }
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+        let mut state = StateReader::new(data);
+        let (version, game_id) = NDS::read_state_header(&mut state)?;
+        if version != NDS::STATE_VERSION {
+            return Err(invalid(format!("Unsupported Save State Version {}", version)))
+        }
+        if game_id != self.game_id() { return Err(invalid("Save State is From a Different Game".to_string())) }
+
+        // Go back to the current state if the save state turns out to be truncated or corrupt
+        let prev_state = self.snapshot();
+        if let Err(err) = self.load_machine(&mut state) {
+            self.restore_snapshot(&prev_state);
+            return Err(invalid(err))
+        }
+        self.hw.faults.clear();
+        Ok(())
+    }
+
+    pub fn inspect_state(data: &[u8]) -> io::Result<StateInfo> {
+        let mut state = StateReader::new(data);
+        let (version, (game_code, _)) = NDS::read_state_header(&mut state)?;
+        let mut info = StateInfo { version, game_code: String::from_utf8_lossy(&game_code).to_string(),
+            sections: Vec::new(), missing: Vec::new() };
+        if version != NDS::STATE_VERSION { return Ok(info) }
+        let sections = state.read_sections()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Corrupt Save State"))?;
+        let known_sections = NDS::STATE_SECTIONS.iter().chain(HW::STATE_SECTIONS);
+        for section in sections.iter() {
+            let current_version = known_sections.clone().find(|(tag, _)| *tag == section.tag).map(|(_, version)| *version);
+            let loadable = match current_version {
+                Some(current) => section.version == current ||
+                    section.version < current && SectionLoader::can_migrate(section.tag, section.version, current),
+                None => true,
+            };
+            info.sections.push(SectionInfo {
+                name: Section::name(section.tag),
+                version: section.version,
+                len: section.data.len(),
+                current_version,
+                loadable,
+            });
+        }
+        info.missing = known_sections.filter(|(tag, _)| !sections.iter().any(|section| section.tag == *tag))
+            .map(|(tag, _)| Section::name(*tag)).collect();
+        Ok(info)
+    }
+
+    fn read_state_header(state: &mut StateReader) -> io::Result<(u32, ([u8; 4], u16))> {
+        let mut magic = [0; 4];
+        let mut version = 0u32;
+        let mut game_id = ([0; 4], 0);
+        magic.load(state);
+        version.load(state);
+        game_id.load(state);
+        if magic != NDS::STATE_MAGIC { return Err(io::Error::new(io::ErrorKind::InvalidData, "Not a Save State")) }
+        Ok((version, game_id))
+    }
+
+    fn game_id(&self) -> ([u8; 4], u16) {
+        (self.hw.header().game_code, self.hw.header().header_checksum)
+    }
+
+    fn save_machine(&self, state: &mut StateWriter) {
+        self.save_sections(state);
+        self.hw.save_sections(state);
+    }
+
+    fn load_machine(&mut self, state: &mut StateReader) -> Result<(), String> {
+        // The battery and power supply are set by the host like the keys, so states don't change them
+        let (battery_low, external_power) = (self.hw.battery_low(), self.hw.external_power());
+        let mut sections = SectionLoader::new(state).ok_or_else(|| "Corrupt Save State".to_string())?;
+        self.load_sections(&mut sections);
+        self.hw.load_sections(&mut sections);
+        sections.finish()?;
+        self.hw.set_battery_low(battery_low);
+        self.hw.set_external_power(external_power);
+        self.hw.restore_interrupt_sources();
+        self.hw.remap_pages();
+        self.hw.gpu.invalidate_lines();
+        self.arm7.call_stack.clear();
+        self.arm9.call_stack.clear();
+        Ok(())
+    }
+
+    // Only memory without side effects can be accessed, so IO registers and cartridges are never touched.
+    // The BIOS can be read but not written.
+    pub fn peek(&mut self, cpu: Cpu, addr: u32) -> Option<u8> {
+        self.read_byte(AddressSpace::CPU(cpu), addr)
+    }
+
+    pub fn poke(&mut self, cpu: Cpu, addr: u32, value: u8) -> bool {
+        !self.hardcore && self.write_byte(AddressSpace::CPU(cpu), addr, value)
+    }
+
+    // Bytes that can't be accessed are None, so they can be told apart from zeros
+    pub fn read_memory(&mut self, space: AddressSpace, addr: u32, len: usize) -> Vec<Option<u8>> {
+        (0..len as u32).map(|i| self.read_byte(space, addr.wrapping_add(i))).collect()
+    }
+
+    // Returns the number of bytes written, skipping the ones that can't be
+    pub fn write_memory(&mut self, space: AddressSpace, addr: u32, data: &[u8]) -> usize {
+        if self.hardcore { return 0 }
+        let mut written = 0;
+        for (i, value) in data.iter().enumerate() {
+            if self.write_byte(space, addr.wrapping_add(i as u32), *value) { written += 1 }
+        }
+        written
+    }
+
+    fn read_byte(&mut self, space: AddressSpace, addr: u32) -> Option<u8> {
+        match space {
+            AddressSpace::CPU(Cpu::ARM7) => self.hw.arm7_debug_read(addr),
+            AddressSpace::CPU(Cpu::ARM9) if self.hw.gba_mode() => None,
+            AddressSpace::CPU(Cpu::ARM9) => self.hw.arm9_debug_read(addr),
+            AddressSpace::VRAMBank(bank) => self.hw.gpu.vram.debug_bank_mem(bank, addr as usize).map(|byte| *byte),
+        }
+    }
+
+    fn write_byte(&mut self, space: AddressSpace, addr: u32, value: u8) -> bool {
+        match space {
+            AddressSpace::CPU(Cpu::ARM7) => self.hw.arm7_debug_mem(addr).map(|byte| *byte = value).is_some(),
+            AddressSpace::CPU(Cpu::ARM9) if self.hw.gba_mode() => false,
+            AddressSpace::CPU(Cpu::ARM9) => self.hw.arm9_debug_write(addr, value).is_some(),
+            AddressSpace::VRAMBank(bank) =>
+                self.hw.gpu.vram.debug_bank_mem(bank, addr as usize).map(|byte| *byte = value).is_some(),
+        }
+    }
+
+    // Little endian value of num_bytes at a RetroAchievements address, where unmapped bytes read as 0 like rcheevos
+    pub fn peek_achievement_memory(&self, addr: u32, num_bytes: u32) -> u32 {
+        (0..num_bytes.min(4)).rev().fold(0, |value, i| value << 8 | self.achievement_byte(addr.wrapping_add(i)) as u32)
+    }
+
+    fn achievement_byte(&self, addr: u32) -> u8 {
+        let mems = [self.hw.main_mem(), self.hw.dtcm()];
+        achievements::MEMORY_REGIONS.iter().zip(mems).find(|(region, _)| (region.start..=region.end).contains(&addr))
+        .map_or(0, |(region, mem)| mem[(addr - region.start) as usize])
+    }
+
+    // Registers are read without side effects, so they can be shown every frame
+    pub fn io_registers(&self, cpu: Cpu) -> Vec<Register> {
+        if self.hw.gba_mode() { return Vec::new() }
+        self.hw.io_registers(cpu == Cpu::ARM9)
+    }
+
+    // Registers 0 - 15 of the current mode and CPSR as 16. PC can't be written since the pipeline would have to be refilled.
+    pub fn reg(&self, cpu: Cpu, reg: u32) -> u32 {
+        assert!(reg <= 16);
+        match cpu {
+            Cpu::ARM7 => self.arm7.reg(reg),
+            Cpu::ARM9 => self.arm9.reg(reg),
+        }
+    }
+
+    // Calls are only tracked while enabled since it slows down every branch
+    pub fn set_call_stack_tracking(&mut self, enabled: bool) {
+        self.arm7.call_stack.set_enabled(enabled);
+        self.arm9.call_stack.set_enabled(enabled);
+    }
+
+    // Innermost call first
+    pub fn call_stack(&self, cpu: Cpu) -> Vec<StackFrame> {
+        match cpu {
+            Cpu::ARM7 => self.arm7.call_stack.frames(),
+            Cpu::ARM9 => self.arm9.call_stack.frames(),
+        }
+    }
+
+    // Ignored in hardcore mode
+    pub fn set_reg(&mut self, cpu: Cpu, reg: u32, value: u32) {
+        if self.hardcore { return }
+        match cpu {
+            Cpu::ARM7 => self.arm7.set_reg(reg, value),
+            Cpu::ARM9 => self.arm9.set_reg(reg, value),
+        }
+    }
+
+    pub fn flush_backup(&mut self) {
+        self.hw.flush_backup();
+    }
+
+    pub fn set_slot2(&mut self, slot2: Slot2) {
+        self.hw.set_slot2(slot2);
+    }
+
+    pub fn set_slot2_open_bus(&mut self, open_bus: OpenBus) {
+        self.hw.set_slot2_open_bus(open_bus);
+    }
+
+    // Ignored while deterministic
+    pub fn set_wifi_link(&mut self, link: Box<dyn WiFiLink>) {
+        if !self.deterministic { self.hw.set_wifi_link(link) }
+    }
+
+    // Ignored while deterministic
+    pub fn feed_mic_samples(&mut self, samples: &[i16], sample_rate: usize) {
+        if !self.deterministic { self.hw.feed_mic_samples(samples, sample_rate) }
+    }
+
+    pub fn set_mic_blowing(&mut self, blowing: bool) {
+        self.hw.set_mic_blowing(blowing);
+    }
+
+    pub fn mic_blowing(&self) -> bool {
+        self.hw.mic_blowing()
+    }
+
+    pub fn header(&self) -> &Header {
+        self.hw.header()
+    }
+
+    pub fn rom(&self) -> &[u8] {
+        self.hw.rom()
+    }
+
+    pub fn main_mem(&self) -> &[u8] {
+        self.hw.main_mem()
+    }
+
+    pub fn banner(&self) -> Option<Banner> {
+        Banner::parse(self.hw.rom(), self.hw.header())
+    }
+
+    // Ignored while deterministic
+    pub fn set_rtc_mode(&mut self, mode: RtcMode) {
+        if !self.deterministic { self.hw.set_rtc_mode(mode) }
+    }
+
+    // Cuts emulation off from the host clock, network and microphone, so the same ROM and inputs always reach the same
+    // state. The RTC starts from 2000-01-01 and there's no Wi-Fi link. Those stay until they're set again after turning
+    // it off.
+    pub fn set_deterministic(&mut self, deterministic: bool) {
+        if deterministic {
+            self.hw.set_rtc_mode(RtcMode::Fixed(NDS::DETERMINISTIC_TIMESTAMP));
+            self.hw.set_wifi_link(Box::new(NoLink));
+        }
+        self.deterministic = deterministic;
     }
 
-    pub fn get_screens(&self) -> [&Vec<u16>; 2] {
-        self.hw.gpu.get_screens()
+    // Hardcore mode is for RetroAchievements, so nothing can change the game's state except playing it: save states
+    // can't be loaded and memory and registers can't be written, which also keeps cheats out
+    pub fn set_hardcore(&mut self, hardcore: bool) {
+        self.hardcore = hardcore;
+    }
+
+    pub fn hardcore(&self) -> bool {
+        self.hardcore
+    }
+
+    // Netplay's link is stepped along with the consoles, so it's kept while deterministic
+    pub(crate) fn set_lockstep_wifi_link(&mut self, link: Box<dyn WiFiLink>) {
+        self.hw.set_wifi_link(link);
+    }
+
+    // Netplay rolls back to these, so they skip the checks and hooks of save states
+    pub(crate) fn snapshot(&self) -> Vec<u8> {
+        let mut state = StateWriter::new();
+        self.save_machine(&mut state);
+        state.finish()
+    }
+
+    pub(crate) fn restore_snapshot(&mut self, snapshot: &[u8]) {
+        self.load_machine(&mut StateReader::new(snapshot)).expect("Unable to Restore Snapshot");
+    }
+
+    pub(crate) fn set_audio_output_muted(&mut self, muted: bool) {
+        self.hw.set_audio_output_muted(muted);
+    }
+
+    pub(crate) fn held_keys(&self) -> u16 {
+        self.hw.held_keys()
+    }
+
+    pub(crate) fn set_held_keys(&mut self, keys: u16) {
+        self.hw.set_held_keys(keys);
+    }
+
+    pub(crate) fn touch(&self) -> Option<(usize, usize)> {
+        self.hw.touch()
+    }
+
+    pub(crate) fn memory_hash(&self) -> u32 {
+        Tracer::hash(&[self.hw.main_mem()])
+    }
+
+    pub fn deterministic(&self) -> bool {
+        self.deterministic
+    }
+
+    // FAT image used as the SD card by homebrew with a DLDI driver, or in a DSi's SD card slot
+    pub fn set_sd_image(&mut self, image: Option<Box<dyn SdImage>>) {
+        self.hw.set_sd_image(image);
+    }
+
+    // Raw dump of a DSi's eMMC, which isn't decrypted
+    pub fn set_nand_image(&mut self, image: Option<Box<dyn SdImage>>) {
+        self.hw.set_nand_image(image);
+    }
+
+    pub fn powered_off(&self) -> bool {
+        self.hw.powered_off()
+    }
+
+    // Reported by the power management device. A DSi's battery level would come from its MCU, which isn't emulated.
+    pub fn set_battery_low(&mut self, low: bool) {
+        self.hw.set_battery_low(low);
+    }
+
+    // Only a DS Lite reports whether it's plugged in
+    pub fn set_external_power(&mut self, connected: bool) {
+        self.hw.set_external_power(connected);
+    }
+
+    // From 0 to 3, like a DS Lite's brightness setting
+    pub fn set_backlight_level(&mut self, level: u8) {
+        self.hw.set_backlight_level(level);
+    }
+
+    // Top then bottom screen, None when a game has turned the backlight off
+    pub fn backlight(&self) -> [Option<u8>; 2] {
+        self.hw.backlight()
+    }
+
+    // The last frame finished at VBlank, which is the last one drawn while frames are being skipped
+    pub fn frame(&self) -> Frame<'_> {
+        self.hw.gpu.frame()
+    }
+
+    // Frames that aren't drawn after each one that is. Lowering it also ends the current run of skipped frames early.
+    pub fn set_frameskip(&mut self, frames: usize) {
+        self.hw.gpu.set_frameskip(frames);
+    }
+
+    pub fn frameskip(&self) -> usize {
+        self.hw.gpu.frameskip
+    }
+
+    pub fn screenshot(&self, layout: Layout) -> Screenshot {
+        Screenshot::new(&self.frame(), layout)
+    }
+
+    #[cfg(feature = "host")]
+    pub fn save_screenshot(&self, path: &Path, layout: Layout) -> io::Result<()> {
+        self.screenshot(layout).save(path)
     }
 
     pub fn press_key(&mut self, key: Key) {
@@ -65,6 +707,19 @@ impl NDS {
         self.hw.release_key(key);
     }
 
+    pub fn set_guitar_key(&mut self, key: GuitarKey, pressed: bool) {
+        self.hw.set_guitar_key(key, pressed);
+    }
+
+    // All of the Guitar Grip's held keys at once, as bits numbered by GuitarKey
+    pub fn guitar_keys(&self) -> u8 {
+        self.hw.guitar_keys()
+    }
+
+    pub fn set_guitar_keys(&mut self, keys: u8) {
+        self.hw.set_guitar_keys(keys);
+    }
+
     pub fn press_screen(&mut self, x: usize, y: usize) {
         self.hw.press_screen(x, y);
     }
@@ -73,6 +728,77 @@ impl NDS {
         self.hw.release_screen();
     }
 
+    #[cfg(feature = "host")]
+    // Records the final mixer output to a WAV file, and each channel to <name>_ch<n>.wav if record_channels is set
+    pub fn start_audio_recording(&mut self, path: &Path, record_channels: bool) -> io::Result<()> {
+        self.hw.start_audio_recording(path, record_channels)
+    }
+
+    #[cfg(feature = "host")]
+    pub fn stop_audio_recording(&mut self) {
+        self.hw.stop_audio_recording();
+    }
+
+    #[cfg(feature = "host")]
+    pub fn is_recording_audio(&self) -> bool {
+        self.hw.is_recording_audio()
+    }
+
+    #[cfg(feature = "host")]
+    // Records the screens stacked vertically along with the mixer output to an AVI file
+    pub fn start_video_recording(&mut self, path: &Path) -> io::Result<()> {
+        self.video_recorder = Some(VideoRecorder::new(path, self.hw.gpu.cycles_per_frame())?);
+        self.hw.set_capturing_samples(true);
+        Ok(())
+    }
+
+    #[cfg(feature = "host")]
+    pub fn stop_video_recording(&mut self) {
+        self.video_recorder = None;
+        self.hw.set_capturing_samples(false);
+    }
+
+    #[cfg(feature = "host")]
+    pub fn is_recording_video(&self) -> bool {
+        self.video_recorder.is_some()
+    }
+
+    #[cfg(feature = "host")]
+    // Every frame is drawn during the run so the hashes don't depend on the frameskip. Stops at the first fault
+    pub fn run_hashed(&mut self, frames: u64) -> Result<OutputHashes, Fault> {
+        let frameskip = self.frameskip();
+        self.set_frameskip(0);
+        self.hw.set_capturing_samples(true);
+        let mut hashes = Ok(OutputHashes { video: Tracer::hash(&[]), audio: Tracer::hash(&[]) });
+        for _ in 0..frames {
+            if self.powered_off() { break }
+            if let Err(fault) = self.run_frame() { hashes = Err(fault); break }
+            let hashes = hashes.as_mut().unwrap();
+            let frame = self.hw.gpu.frame();
+            hashes.video = Tracer::continue_hash(hashes.video, &[frame.top, frame.bottom]);
+            let samples = self.hw.take_captured_samples().iter()
+                .flat_map(|(left, right)| [left.to_le_bytes(), right.to_le_bytes()].concat()).collect::<Vec<_>>();
+            hashes.audio = Tracer::continue_hash(hashes.audio, &[&samples]);
+        }
+        self.hw.set_capturing_samples(self.video_recorder.is_some());
+        self.set_frameskip(frameskip);
+        hashes
+    }
+
+    // Channels are numbered 0 - 15 like on hardware. Soloing any channel silences all non-soloed channels
+    pub fn set_audio_channel_muted(&mut self, num: usize, muted: bool) {
+        self.hw.set_audio_channel_muted(num, muted);
+    }
+
+    pub fn set_audio_channel_soloed(&mut self, num: usize, soloed: bool) {
+        self.hw.set_audio_channel_soloed(num, soloed);
+    }
+
+    // None for channels past AUDIO_CHANNELS
+    pub fn audio_channel_state(&self, num: usize) -> Option<ChannelState> {
+        self.hw.audio_channel_state(num)
+    }
+
     pub fn render_palettes(&self, extended: bool, slot: usize, palette: usize,
         engine: Engine, graphics_type: GraphicsType) -> (Vec<u16>, usize, usize) {
         self.hw.render_palettes(extended, slot, palette, engine, graphics_type)
@@ -83,8 +809,47 @@ impl NDS {
     }
 
     pub fn render_tiles(&self, engine: Engine, graphics_type: GraphicsType, extended: bool, bitmap: bool, bpp8: bool,
-        slot: usize, palette: usize, offset: usize) -> (Vec<u16>, usize, usize) {
-        self.hw.render_tiles(engine, graphics_type, extended, bitmap, bpp8, slot, palette, offset)
+        slot: usize, palette: usize, base: usize) -> (Vec<u16>, usize, usize) {
+        self.hw.render_tiles(engine, graphics_type, extended, bitmap, bpp8, slot, palette, base)
+    }
+
+    pub fn oam_entries(&self, engine: Engine) -> Vec<OAMEntry> {
+        self.hw.oam_entries(engine)
+    }
+
+    pub fn capture_3d_polygons(&mut self) {
+        self.hw.gpu.engine3d.request_capture()
+    }
+
+    pub fn take_3d_polygons(&mut self) -> Option<Vec<CapturedPolygon>> {
+        self.hw.gpu.engine3d.take_capture()
+    }
+
+    pub fn set_3d_wireframe(&mut self, enabled: bool) {
+        self.hw.gpu.engine3d.wireframe = enabled;
+    }
+
+    // 3D textures are dumped to the pack and swapped for its replacements, which only changes what's drawn
+    pub fn set_texture_pack(&mut self, pack: Option<Box<dyn TexturePack>>) {
+        self.hw.gpu.engine3d.set_texture_pack(pack);
+    }
+
+    pub fn pending_events(&self) -> Vec<PendingEvent> {
+        self.hw.pending_events()
+    }
+
+    // Counts since power on or the last reset
+    pub fn event_stats(&self) -> Vec<(EventKind, EventStats)> {
+        self.hw.event_stats()
+    }
+
+    pub fn reset_event_stats(&mut self) {
+        self.hw.reset_event_stats()
+    }
+
+    // Times event handlers in the event stats, which slows emulation down a little
+    pub fn set_profiling(&mut self, profiling: bool) {
+        self.hw.set_profiling(profiling)
     }
 
     pub fn render_bank(&self, bank: usize, ignore_alpha: bool) -> (Vec<u16>, usize, usize) {
@@ -94,3 +859,11 @@ impl NDS {
 
 pub const WIDTH: usize = crate::hw::GPU::WIDTH;
 pub const HEIGHT: usize = crate::hw::GPU::HEIGHT;
+pub const AUDIO_CHANNELS: usize = HW::AUDIO_CHANNELS;
+pub const FRAME_RATE: f64 = NDS::CLOCK_RATE as f64 / crate::hw::GPU::CYCLES_PER_FRAME as f64;
+
+// Each instance owns all of its state, so instances can be created on and moved between threads
+const _: fn() = || {
+    fn assert_send<T: Send>() {}
+    assert_send::<NDS>();
+};
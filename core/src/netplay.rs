@@ -0,0 +1,543 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::fault::Fault;
+use crate::hw::{WiFiFrame, WiFiLink};
+use crate::nds::NDS;
+use crate::trace::Tracer;
+
+// Both players run both consoles in lockstep, linked to each other like local multiplayer, so only inputs are sent
+// over the network. Each player controls one of the consoles, which sees the other as if it were in the same room.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NetplayMode {
+    // Waits for the other player's input for each frame, which arrives in time as long as the delay covers the latency
+    Delay,
+    // Guesses that the other player's input hasn't changed, and rolls back to replay the frames since when it has
+    Rollback,
+}
+
+#[derive(Debug)]
+pub enum NetplayError {
+    Fault(Fault),
+    Io(io::Error),
+    DifferentVersion,
+    DifferentStart,
+    Disconnected,
+    Desync(u64),
+}
+
+impl fmt::Display for NetplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NetplayError::Fault(fault) => write!(f, "{}", fault),
+            NetplayError::Io(err) => write!(f, "{}", err),
+            NetplayError::DifferentVersion => write!(f, "Other Player is Running a Different Version"),
+            NetplayError::DifferentStart => write!(f, "Other Player Has a Different ROM, Firmware, Save or State"),
+            NetplayError::Disconnected => write!(f, "Other Player Disconnected"),
+            NetplayError::Desync(frame) => write!(f, "Consoles Desynced Before Frame {}", frame),
+        }
+    }
+}
+
+impl std::error::Error for NetplayError {}
+
+impl From<Fault> for NetplayError {
+    fn from(fault: Fault) -> Self {
+        NetplayError::Fault(fault)
+    }
+}
+
+impl From<io::Error> for NetplayError {
+    fn from(err: io::Error) -> Self {
+        NetplayError::Io(err)
+    }
+}
+
+pub struct Netplay {
+    socket: UdpSocket,
+    peer: Option<SocketAddr>,
+    player: usize,
+    connected: bool,
+    mode: NetplayMode,
+    delay: u64,
+    start_hash: u32,
+    queues: Queues,
+    frame: u64,
+    local_inputs: Vec<Input>,
+    remote_inputs: Vec<Input>,
+    // Local inputs the other player has received
+    acked: usize,
+    // States from before each frame that was run with a guess of the other player's input
+    snapshots: VecDeque<Snapshot>,
+    rollback_to: Option<u64>,
+    // Memory hashes from the start of every HASH_INTERVAL frames, which are compared once both players confirm them
+    hashes: VecDeque<(u64, u32)>,
+    last_received: Instant,
+    buffer: Vec<u8>,
+}
+
+impl Netplay {
+    pub const DEFAULT_PORT: u16 = 7080;
+    const MAGIC: [u8; 4] = *b"NDSN";
+    const PROTOCOL_VERSION: u8 = 2;
+    const HELLO: u8 = 0;
+    const INPUTS: u8 = 1;
+    const HELLO_LEN: usize = 12;
+    const INPUTS_HEADER_LEN: usize = 22;
+    const MAX_INPUTS_PER_PACKET: usize = 0xFF;
+    const MAX_PACKET_LEN: usize = 0x800;
+    const NO_HASH: u32 = u32::MAX;
+    const MAX_ROLLBACK: u64 = 8;
+    const HASH_INTERVAL: u64 = 300;
+    const MAX_HASHES: usize = 8;
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    // Consoles are given in player order. The host is player 1 and picks the mode and delay for both players.
+    // Both players need the same ROM, BIOS and firmware and have to start from the same state, so each console needs
+    // the same save on both sides.
+    pub fn host(port: u16, mode: NetplayMode, delay: u8, consoles: [&mut NDS; 2]) -> io::Result<Self> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, port))?;
+        Netplay::new(socket, None, 0, mode, delay as u64, consoles)
+    }
+
+    pub fn connect(addr: SocketAddr, consoles: [&mut NDS; 2]) -> io::Result<Self> {
+        let socket = if addr.is_ipv4() { UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))? }
+            else { UdpSocket::bind((Ipv6Addr::UNSPECIFIED, 0))? };
+        Netplay::new(socket, Some(addr), 1, NetplayMode::Delay, 0, consoles)
+    }
+
+    fn new(socket: UdpSocket, peer: Option<SocketAddr>, player: usize, mode: NetplayMode, delay: u64,
+        mut consoles: [&mut NDS; 2]) -> io::Result<Self> {
+        socket.set_nonblocking(true)?;
+        let queues = Queues::default();
+        for (console, nds) in consoles.iter_mut().enumerate() {
+            nds.set_deterministic(true);
+            nds.set_lockstep_wifi_link(Box::new(LockstepLink { queues: Arc::clone(&queues), console }));
+        }
+        Ok(Netplay {
+            socket,
+            peer,
+            player,
+            connected: false,
+            mode,
+            delay,
+            start_hash: Tracer::hash(&[&consoles[0].snapshot(), &consoles[1].snapshot()]),
+            queues,
+            frame: 0,
+            local_inputs: Vec::new(),
+            remote_inputs: Vec::new(),
+            acked: 0,
+            snapshots: VecDeque::new(),
+            rollback_to: None,
+            hashes: VecDeque::new(),
+            last_received: Instant::now(),
+            buffer: vec![0; Netplay::MAX_PACKET_LEN],
+        })
+    }
+
+    // Index of the local player's console
+    pub fn player(&self) -> usize {
+        self.player
+    }
+
+    pub fn connected(&self) -> bool {
+        self.connected
+    }
+
+    pub fn mode(&self) -> NetplayMode {
+        self.mode
+    }
+
+    pub fn delay(&self) -> u64 {
+        self.delay
+    }
+
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    // Returns whether a frame was run, which it isn't while waiting for the other player.
+    // Input for the local player is taken from their console, which keeps it between frames.
+    pub fn run_frame(&mut self, mut consoles: [&mut NDS; 2]) -> Result<bool, NetplayError> {
+        self.exchange()?;
+        if !self.connected { return Ok(false) }
+        let live_input = Input::capture(consoles[self.player]);
+        while self.local_inputs.len() as u64 <= self.frame + self.delay {
+            let input = if (self.local_inputs.len() as u64) < self.delay { Input::default() } else { live_input };
+            self.local_inputs.push(input);
+        }
+        self.send_inputs()?;
+        let ran = self.advance(&mut consoles);
+        live_input.apply(consoles[self.player]);
+        ran
+    }
+
+    // Keeps the connection alive without running, e.g. while paused
+    pub fn poll(&mut self) -> Result<(), NetplayError> {
+        self.exchange()?;
+        if self.connected { self.send_inputs()? }
+        Ok(())
+    }
+
+    fn exchange(&mut self) -> Result<(), NetplayError> {
+        self.receive()?;
+        if !self.connected {
+            if self.player == 1 { self.send_hello()? }
+            return Ok(())
+        }
+        if self.last_received.elapsed() > Netplay::TIMEOUT { return Err(NetplayError::Disconnected) }
+        Ok(())
+    }
+
+    fn advance(&mut self, consoles: &mut [&mut NDS; 2]) -> Result<bool, NetplayError> {
+        if let Some(frame) = self.rollback_to.take() { self.rollback(consoles, frame)? }
+        let max_guesses = match self.mode {
+            NetplayMode::Delay => 0,
+            NetplayMode::Rollback => Netplay::MAX_ROLLBACK,
+        };
+        if self.frame >= self.remote_inputs.len() as u64 + max_guesses { return Ok(false) }
+        self.step(consoles)?;
+        Ok(true)
+    }
+
+    // Replayed frames have already been shown and heard
+    fn rollback(&mut self, consoles: &mut [&mut NDS; 2], frame: u64) -> Result<(), NetplayError> {
+        let snapshot = self.snapshots.pop_front().unwrap();
+        self.snapshots.clear();
+        for (nds, state) in consoles.iter_mut().zip(snapshot.consoles.iter()) { nds.restore_snapshot(state) }
+        *self.queues.lock().unwrap() = snapshot.queues;
+        let target = self.frame;
+        self.frame = frame;
+        let frameskips = [consoles[0].frameskip(), consoles[1].frameskip()];
+        for nds in consoles.iter_mut() {
+            nds.set_frameskip(usize::MAX);
+            nds.set_audio_output_muted(true);
+        }
+        let replayed = (frame..target).try_for_each(|_| self.step(consoles));
+        for (nds, frameskip) in consoles.iter_mut().zip(frameskips.iter()) {
+            nds.set_frameskip(*frameskip);
+            nds.set_audio_output_muted(false);
+        }
+        replayed
+    }
+
+    fn step(&mut self, consoles: &mut [&mut NDS; 2]) -> Result<(), NetplayError> {
+        let frame = self.frame;
+        let remote_input = self.remote_inputs.get(frame as usize).copied();
+        let guess = remote_input.unwrap_or_else(|| self.remote_inputs.last().copied().unwrap_or_default());
+        if remote_input.is_none() {
+            self.snapshots.push_back(Snapshot {
+                consoles: [consoles[0].snapshot(), consoles[1].snapshot()],
+                queues: self.queues.lock().unwrap().clone(),
+                guess,
+            });
+        }
+        if frame.is_multiple_of(Netplay::HASH_INTERVAL) {
+            let hashes = [consoles[0].memory_hash().to_le_bytes(), consoles[1].memory_hash().to_le_bytes()];
+            self.hashes.retain(|(hashed, _)| *hashed < frame);
+            self.hashes.push_back((frame, Tracer::hash(&[&hashes[0], &hashes[1]])));
+            if self.hashes.len() > Netplay::MAX_HASHES { self.hashes.pop_front(); }
+        }
+        self.local_inputs[frame as usize].apply(consoles[self.player]);
+        guess.apply(consoles[1 - self.player]);
+        for nds in consoles.iter_mut() { nds.run_frame()?; }
+        self.frame += 1;
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Result<(), NetplayError> {
+        loop {
+            let (len, addr) = match self.socket.recv_from(&mut self.buffer) {
+                Ok(received) => received,
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                // Hellos sent before the host was listening bounce back
+                Err(err) if err.kind() == io::ErrorKind::ConnectionRefused ||
+                    err.kind() == io::ErrorKind::ConnectionReset => continue,
+                Err(err) => return Err(err.into()),
+            };
+            if len < 5 || self.buffer[..4] != Netplay::MAGIC || self.peer.is_some_and(|peer| peer != addr) { continue }
+            let packet = self.buffer[..len].to_vec();
+            match packet[4] {
+                Netplay::HELLO => self.receive_hello(&packet, addr)?,
+                Netplay::INPUTS if self.connected => self.receive_inputs(&packet)?,
+                _ => continue,
+            }
+            self.last_received = Instant::now();
+        }
+    }
+
+    // The host replies to every hello, in case its reply was lost
+    fn receive_hello(&mut self, packet: &[u8], addr: SocketAddr) -> Result<(), NetplayError> {
+        if packet.len() < Netplay::HELLO_LEN { return Ok(()) }
+        if self.player == 0 {
+            self.peer = Some(addr);
+            self.send_hello()?;
+        }
+        if packet[5] != Netplay::PROTOCOL_VERSION { return Err(NetplayError::DifferentVersion) }
+        if read_u32(packet, 6) != self.start_hash { return Err(NetplayError::DifferentStart) }
+        if self.player == 1 {
+            self.mode = if packet[10] != 0 { NetplayMode::Rollback } else { NetplayMode::Delay };
+            self.delay = packet[11] as u64;
+        }
+        self.connected = true;
+        Ok(())
+    }
+
+    fn receive_inputs(&mut self, packet: &[u8]) -> Result<(), NetplayError> {
+        if packet.len() < Netplay::INPUTS_HEADER_LEN { return Ok(()) }
+        let acked = read_u32(packet, 5) as usize;
+        let (hash_frame, hash) = (read_u32(packet, 9), read_u32(packet, 13));
+        let start = read_u32(packet, 17) as usize;
+        let count = packet[21] as usize;
+        if packet.len() < Netplay::INPUTS_HEADER_LEN + count * Input::LEN { return Ok(()) }
+        self.acked = self.acked.max(acked);
+        for i in 0..count {
+            if start + i != self.remote_inputs.len() { continue }
+            let input = Input::read(&packet[Netplay::INPUTS_HEADER_LEN + i * Input::LEN..]);
+            let frame = self.remote_inputs.len() as u64;
+            self.remote_inputs.push(input);
+            // Snapshots are kept for every frame since the last confirmed input until a guess turns out wrong
+            if frame < self.frame && self.rollback_to.is_none() {
+                let snapshot = self.snapshots.pop_front().unwrap();
+                if snapshot.guess != input {
+                    self.snapshots.push_front(snapshot);
+                    self.rollback_to = Some(frame);
+                }
+            }
+        }
+        if hash_frame != Netplay::NO_HASH {
+            let hash_frame = hash_frame as u64;
+            if self.confirmed_hashes().any(|(frame, local_hash)| *frame == hash_frame && *local_hash != hash) {
+                return Err(NetplayError::Desync(hash_frame))
+            }
+        }
+        Ok(())
+    }
+
+    // States are confirmed once all inputs before them are, unless a guess before them turned out wrong
+    fn confirmed_hashes(&self) -> impl Iterator<Item = &(u64, u32)> {
+        let confirmed = self.remote_inputs.len() as u64;
+        let rollback_to = self.rollback_to;
+        self.hashes.iter()
+            .filter(move |(frame, _)| *frame <= confirmed && rollback_to.is_none_or(|rollback_to| *frame <= rollback_to))
+    }
+
+    fn send_hello(&self) -> io::Result<()> {
+        let mut packet = Vec::with_capacity(Netplay::HELLO_LEN);
+        packet.extend_from_slice(&Netplay::MAGIC);
+        packet.push(Netplay::HELLO);
+        packet.push(Netplay::PROTOCOL_VERSION);
+        packet.extend_from_slice(&self.start_hash.to_le_bytes());
+        packet.push((self.mode == NetplayMode::Rollback) as u8);
+        packet.push(self.delay as u8);
+        self.send(&packet)
+    }
+
+    // Every input the other player hasn't acknowledged is resent, so lost packets don't need to be detected
+    fn send_inputs(&self) -> io::Result<()> {
+        let (hash_frame, hash) = self.confirmed_hashes().last()
+            .map_or((Netplay::NO_HASH, 0), |(frame, hash)| (*frame as u32, *hash));
+        let start = self.acked.min(self.local_inputs.len());
+        let inputs = &self.local_inputs[start..self.local_inputs.len().min(start + Netplay::MAX_INPUTS_PER_PACKET)];
+        let mut packet = Vec::with_capacity(Netplay::INPUTS_HEADER_LEN + inputs.len() * Input::LEN);
+        packet.extend_from_slice(&Netplay::MAGIC);
+        packet.push(Netplay::INPUTS);
+        packet.extend_from_slice(&(self.remote_inputs.len() as u32).to_le_bytes());
+        packet.extend_from_slice(&hash_frame.to_le_bytes());
+        packet.extend_from_slice(&hash.to_le_bytes());
+        packet.extend_from_slice(&(start as u32).to_le_bytes());
+        packet.push(inputs.len() as u8);
+        for input in inputs.iter() { input.write(&mut packet) }
+        self.send(&packet)
+    }
+
+    fn send(&self, packet: &[u8]) -> io::Result<()> {
+        let peer = match self.peer {
+            Some(peer) => peer,
+            None => return Ok(()),
+        };
+        match self.socket.send_to(packet, peer) {
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock || err.kind() == io::ErrorKind::ConnectionRefused => Ok(()),
+            result => result.map(|_| ()),
+        }
+    }
+}
+
+fn read_u32(bytes: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes([bytes[pos], bytes[pos + 1], bytes[pos + 2], bytes[pos + 3]])
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+struct Input {
+    keys: u16,
+    touch: Option<(u8, u8)>,
+    mic_blowing: bool,
+    guitar_keys: u8,
+}
+
+impl Input {
+    const LEN: usize = 5;
+    const TOUCHING: u16 = 1 << 15;
+    // Guitar keys use bits 3 - 6, which leaves bit 0 for the microphone
+    const MIC_BLOWING: u8 = 1 << 0;
+
+    fn capture(nds: &NDS) -> Self {
+        Input {
+            keys: nds.held_keys(),
+            touch: nds.touch().map(|(x, y)| (x as u8, y as u8)),
+            mic_blowing: nds.mic_blowing(),
+            guitar_keys: nds.guitar_keys(),
+        }
+    }
+
+    fn apply(&self, nds: &mut NDS) {
+        nds.set_held_keys(self.keys);
+        match self.touch {
+            Some((x, y)) => nds.press_screen(x as usize, y as usize),
+            None => nds.release_screen(),
+        }
+        nds.set_mic_blowing(self.mic_blowing);
+        nds.set_guitar_keys(self.guitar_keys);
+    }
+
+    fn write(&self, packet: &mut Vec<u8>) {
+        let (x, y) = self.touch.unwrap_or((0, 0));
+        let keys = if self.touch.is_some() { self.keys | Input::TOUCHING } else { self.keys };
+        packet.extend_from_slice(&keys.to_le_bytes());
+        packet.extend_from_slice(&[x, y, self.guitar_keys & !Input::MIC_BLOWING | self.mic_blowing as u8]);
+    }
+
+    fn read(bytes: &[u8]) -> Self {
+        let keys = u16::from_le_bytes([bytes[0], bytes[1]]);
+        Input {
+            keys: keys & !Input::TOUCHING,
+            touch: if keys & Input::TOUCHING != 0 { Some((bytes[2], bytes[3])) } else { None },
+            mic_blowing: bytes[4] & Input::MIC_BLOWING != 0,
+            guitar_keys: bytes[4] & !Input::MIC_BLOWING,
+        }
+    }
+}
+
+struct Snapshot {
+    consoles: [Vec<u8>; 2],
+    queues: [VecDeque<WiFiFrame>; 2],
+    guess: Input,
+}
+
+// Frames in flight between the consoles are part of their state, so they're kept here to be rolled back with them
+type Queues = Arc<Mutex<[VecDeque<WiFiFrame>; 2]>>;
+
+struct LockstepLink {
+    queues: Queues,
+    console: usize,
+}
+
+impl WiFiLink for LockstepLink {
+    fn send(&mut self, frame: &WiFiFrame) {
+        self.queues.lock().unwrap()[1 - self.console].push_back(frame.clone());
+    }
+
+    fn recv(&mut self) -> Option<WiFiFrame> {
+        self.queues.lock().unwrap()[self.console].pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nds::{ConsoleModel, MemoryStorage, SampleQueue, SystemFiles};
+
+    // Keeps mixing KEYINPUT into a word of main memory, so the input of every frame changes the state
+    const ARM9_KEY_MIXER: [u32; 9] = [
+        0xE3A0_0621, // mov r0, #0x02100000
+        0xE3A0_2301, // mov r2, #0x04000000
+        0xE282_2E13, // add r2, r2, #0x130
+        0xE3A0_1000, // mov r1, #0
+        0xE1D2_30B0, // ldrh r3, [r2]
+        0xE081_1003, // add r1, r1, r3
+        0xE1A0_1FE1, // ror r1, r1, #31
+        0xE580_1000, // str r1, [r0]
+        0xEAFF_FFFA, // b 0x02000010
+    ];
+    const ARM7_IDLE: [u32; 1] = [0xEAFF_FFFE]; // b 0x02380000
+
+    fn nds() -> NDS {
+        let mut rom = vec![0; 0x8000];
+        let words = |rom: &mut [u8], addr: usize, words: &[u32]| for (i, word) in words.iter().enumerate() {
+            rom[addr + 4 * i..addr + 4 * i + 4].copy_from_slice(&word.to_le_bytes());
+        };
+        // ARM9 and ARM7 ROM offsets, entry points, RAM addresses and sizes
+        words(&mut rom, 0x20, &[0x4000, 0x0200_0000, 0x0200_0000, 0x1000, 0x5000, 0x0238_0000, 0x0238_0000, 0x100]);
+        words(&mut rom, 0x4000, &ARM9_KEY_MIXER);
+        words(&mut rom, 0x5000, &ARM7_IDLE);
+        let files = SystemFiles { bios7: vec![0; 0x4000], bios9: vec![0; 0x1000], firmware: None };
+        NDS::new(files, rom, Box::new(MemoryStorage::new(None)), Box::new(SampleQueue::new(48000)), true,
+            ConsoleModel::DS).unwrap()
+    }
+
+    // A host that's already connected, with no peer to send to
+    fn netplay(consoles: [&mut NDS; 2]) -> Netplay {
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let mut netplay = Netplay::new(socket, None, 0, NetplayMode::Rollback, 0, consoles).unwrap();
+        netplay.connected = true;
+        netplay
+    }
+
+    fn receive(netplay: &mut Netplay, start: usize, inputs: &[Input]) {
+        let mut packet = Netplay::MAGIC.to_vec();
+        packet.push(Netplay::INPUTS);
+        for value in [0, Netplay::NO_HASH, 0, start as u32] { packet.extend_from_slice(&value.to_le_bytes()) }
+        packet.push(inputs.len() as u8);
+        for input in inputs.iter() { input.write(&mut packet) }
+        netplay.receive_inputs(&packet).unwrap();
+    }
+
+    #[test]
+    fn input_encoding() {
+        let inputs = [
+            Input::default(),
+            Input { keys: 0x3FF, touch: Some((255, 191)), mic_blowing: true, guitar_keys: 0x78 },
+            Input { keys: 0x2A, touch: Some((0, 0)), mic_blowing: false, guitar_keys: 0x08 },
+            Input { keys: 0x001, touch: None, mic_blowing: true, guitar_keys: 0 },
+        ];
+        for input in inputs.iter() {
+            let mut packet = Vec::new();
+            input.write(&mut packet);
+            assert_eq!(packet.len(), Input::LEN);
+            assert!(Input::read(&packet) == *input);
+        }
+    }
+
+    #[test]
+    fn wrong_guess_rollback() {
+        let remote_inputs = [0x001, 0x002, 0x004].map(|keys| Input { keys, ..Input::default() });
+
+        // The states to end up in, with every input of the other player known in time
+        let (mut expected_nds0, mut expected_nds1) = (nds(), nds());
+        let mut expected = netplay([&mut expected_nds0, &mut expected_nds1]);
+        receive(&mut expected, 0, &remote_inputs);
+        for _ in 0..4 { assert!(expected.run_frame([&mut expected_nds0, &mut expected_nds1]).unwrap()) }
+
+        let (mut nds0, mut nds1) = (nds(), nds());
+        let mut netplay = netplay([&mut nds0, &mut nds1]);
+        receive(&mut netplay, 0, &remote_inputs[..1]);
+        for _ in 0..3 { assert!(netplay.run_frame([&mut nds0, &mut nds1]).unwrap()) }
+        assert_eq!(netplay.snapshots.len(), 2);
+        // Sent while running with the wrong guess, so it has to be taken back along with those frames
+        let mut link = LockstepLink { queues: Arc::clone(&netplay.queues), console: 0 };
+        link.send(&WiFiFrame { rate: 20, data: vec![0; 24] });
+
+        receive(&mut netplay, 1, &remote_inputs[1..]);
+        assert_eq!(netplay.rollback_to, Some(1));
+        assert!(netplay.run_frame([&mut nds0, &mut nds1]).unwrap());
+        assert_eq!(netplay.frame(), 4);
+        assert!(netplay.queues.lock().unwrap().iter().all(VecDeque::is_empty));
+        assert!(nds0.snapshot() == expected_nds0.snapshot());
+        assert!(nds1.snapshot() == expected_nds1.snapshot());
+        assert_ne!(nds1.memory_hash(), nds0.memory_hash());
+    }
+}
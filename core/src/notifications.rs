@@ -0,0 +1,23 @@
+use std::sync::{Arc, Mutex};
+
+// Warnings meant for the user rather than only the log, which frontends can take and show on screen.
+// Clones share the same queue, so each part of the system can keep a handle without any global state.
+#[derive(Clone, Default)]
+pub(crate) struct Notifier {
+    pending: Arc<Mutex<Vec<String>>>,
+}
+
+impl Notifier {
+    pub fn notify(&self, message: String) {
+        warn!("{}", message);
+        self.pending.lock().unwrap().push(message);
+    }
+
+    pub fn take(&self) -> Vec<String> {
+        std::mem::take(&mut self.pending.lock().unwrap())
+    }
+}
+
+macro_rules! notify {
+    ($notifier:expr, $($arg:tt)*) => { $notifier.notify(format!($($arg)*)) };
+}
@@ -0,0 +1,117 @@
+use std::collections::VecDeque;
+
+use crate::nds::NDS;
+
+// Only the newest snapshot is kept whole. Older snapshots are stored as the compressed XOR with the snapshot
+// after them, which is mostly zeros since little changes between a few frames.
+pub struct Rewinder {
+    interval: usize,
+    capacity: usize,
+    frames_until_snapshot: usize,
+    newest: Vec<u8>,
+    deltas: VecDeque<Delta>,
+}
+
+impl Rewinder {
+    pub fn new(interval: usize, capacity: usize) -> Self {
+        assert!(interval > 0);
+        Rewinder {
+            interval,
+            capacity,
+            frames_until_snapshot: 0,
+            newest: Vec::new(),
+            deltas: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub fn frame_completed(&mut self, nds: &NDS) {
+        if self.frames_until_snapshot > 0 { self.frames_until_snapshot -= 1; return }
+        self.frames_until_snapshot = self.interval - 1;
+        let state = nds.save_state();
+        if !self.newest.is_empty() {
+            if self.deltas.len() == self.capacity { self.deltas.pop_front(); }
+            self.deltas.push_back(Delta::new(&state, &self.newest));
+        }
+        self.newest = state;
+    }
+
+    // Steps back one snapshot, returning false once the buffer is exhausted
+    pub fn rewind(&mut self, nds: &mut NDS) -> bool {
+        let delta = match self.deltas.pop_back() {
+            Some(delta) => delta,
+            None => return false,
+        };
+        delta.apply(&mut self.newest);
+        self.frames_until_snapshot = self.interval - 1;
+        match nds.load_state(&self.newest) {
+            Ok(()) => true,
+            Err(err) => { warn!("Unable to Rewind: {}", err); self.clear(); false },
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.frames_until_snapshot = 0;
+        self.newest.clear();
+        self.deltas.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    pub fn memory_used(&self) -> usize {
+        self.newest.len() + self.deltas.iter().map(|delta| delta.data.len()).sum::<usize>()
+    }
+}
+
+// Runs of (zero byte count, literal byte count, literal bytes) with u32 counts
+struct Delta {
+    prev_len: usize,
+    data: Vec<u8>,
+}
+
+impl Delta {
+    fn new(cur: &[u8], prev: &[u8]) -> Self {
+        let len = cur.len().max(prev.len());
+        let xor = |i: usize| cur.get(i).copied().unwrap_or(0) ^ prev.get(i).copied().unwrap_or(0);
+        let mut data = Vec::new();
+        let mut i = 0;
+        while i < len {
+            let zeros_start = i;
+            while i < len && xor(i) == 0 { i += 1 }
+            let literal_start = i;
+            // Short zero runs are cheaper to keep in the literal than to start a new run
+            while i < len && (xor(i) != 0 || (i + 8 < len && (i..i + 8).any(|j| xor(j) != 0))) { i += 1 }
+            data.extend_from_slice(&((literal_start - zeros_start) as u32).to_le_bytes());
+            data.extend_from_slice(&((i - literal_start) as u32).to_le_bytes());
+            data.extend((literal_start..i).map(xor));
+        }
+        Delta {
+            prev_len: prev.len(),
+            data,
+        }
+    }
+
+    fn apply(&self, state: &mut Vec<u8>) {
+        let read_u32 = |pos: usize| u32::from_le_bytes([
+            self.data[pos], self.data[pos + 1], self.data[pos + 2], self.data[pos + 3],
+        ]) as usize;
+        state.resize(state.len().max(self.prev_len), 0);
+        let (mut pos, mut i) = (0, 0);
+        while pos < self.data.len() {
+            i += read_u32(pos);
+            let literal_len = read_u32(pos + 4);
+            pos += 8;
+            for (byte, xor) in state[i..i + literal_len].iter_mut().zip(self.data[pos..pos + literal_len].iter()) {
+                *byte ^= xor;
+            }
+            i += literal_len;
+            pos += literal_len;
+        }
+        state.truncate(self.prev_len);
+    }
+}
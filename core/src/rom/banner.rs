@@ -0,0 +1,79 @@
+use std::convert::TryInto;
+
+use super::Header;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum BannerLanguage {
+    Japanese = 0,
+    English = 1,
+    French = 2,
+    German = 3,
+    Italian = 4,
+    Spanish = 5,
+    Chinese = 6,
+    Korean = 7,
+}
+
+// Icon and titles shown in the DS menu
+pub struct Banner {
+    pub version: u16,
+    // 32x32 RGBA8 pixels
+    pub icon: Vec<u8>,
+    titles: Vec<String>,
+}
+
+impl Banner {
+    pub const ICON_SIZE: usize = 32;
+    const ICON_ADDR: usize = 0x20;
+    const PALETTE_ADDR: usize = 0x220;
+    const TITLES_ADDR: usize = 0x240;
+    const TITLE_LEN: usize = 0x100;
+
+    pub fn parse(rom: &[u8], header: &Header) -> Option<Banner> {
+        let start = header.icon_offset as usize;
+        if start == 0 { return None }
+        let banner = rom.get(start..start + Banner::TITLES_ADDR + 6 * Banner::TITLE_LEN)?;
+        let version = u16::from_le_bytes(banner[0..2].try_into().unwrap());
+        let palette: Vec<u16> = banner[Banner::PALETTE_ADDR..Banner::PALETTE_ADDR + 0x20].chunks_exact(2)
+            .map(|color| u16::from_le_bytes(color.try_into().unwrap())).collect();
+
+        // 4x4 tiles of 8x8 pixels at 4 bits per pixel
+        let mut icon = vec![0; Banner::ICON_SIZE * Banner::ICON_SIZE * 4];
+        for y in 0..Banner::ICON_SIZE {
+            for x in 0..Banner::ICON_SIZE {
+                let tile = y / 8 * 4 + x / 8;
+                let byte = banner[Banner::ICON_ADDR + tile * 0x20 + y % 8 * 4 + x % 8 / 2];
+                let palette_i = if x % 2 == 0 { byte & 0xF } else { byte >> 4 } as usize;
+                let color = palette[palette_i];
+                let to_8bit = |value: u16| ((value & 0x1F) << 3 | (value & 0x1F) >> 2) as u8;
+                let pixel = (y * Banner::ICON_SIZE + x) * 4;
+                icon[pixel..pixel + 4].copy_from_slice(&[
+                    to_8bit(color), to_8bit(color >> 5), to_8bit(color >> 10),
+                    // Color 0 is transparent
+                    if palette_i == 0 { 0 } else { 0xFF },
+                ]);
+            }
+        }
+
+        // Chinese and Korean titles were added in versions 2 and 3
+        let title_count = match version & 0xFF { 1 => 6, 2 => 7, _ => 8 };
+        let titles = (0..title_count).filter_map(|i| {
+            let addr = start + Banner::TITLES_ADDR + i * Banner::TITLE_LEN;
+            let title = rom.get(addr..addr + Banner::TITLE_LEN)?;
+            let chars: Vec<u16> = title.chunks_exact(2).map(|c| u16::from_le_bytes(c.try_into().unwrap()))
+                .take_while(|c| *c != 0).collect();
+            Some(String::from_utf16_lossy(&chars))
+        }).collect();
+
+        Some(Banner {
+            version,
+            icon,
+            titles,
+        })
+    }
+
+    // Lines are separated by newlines, usually the game's name followed by the publisher
+    pub fn title(&self, language: BannerLanguage) -> Option<&str> {
+        self.titles.get(language as usize).map(String::as_str)
+    }
+}
@@ -0,0 +1,84 @@
+mod patch;
+mod banner;
+
+#[cfg(feature = "host")]
+use std::ffi::OsStr;
+#[cfg(feature = "host")]
+use std::fs::{self, File};
+#[cfg(feature = "host")]
+use std::io::{self, Read};
+#[cfg(feature = "host")]
+use std::path::Path;
+
+#[cfg(feature = "host")]
+use flate2::read::GzDecoder;
+#[cfg(feature = "host")]
+use sevenz_rust::{Password, SevenZReader};
+#[cfg(feature = "host")]
+use zip::ZipArchive;
+
+pub use patch::apply_patch;
+pub use banner::{Banner, BannerLanguage};
+pub use crate::hw::{Header, Region, UnitCode};
+
+#[cfg(feature = "host")]
+// Applies the given patch or else an IPS, UPS or BPS patch with the same name as the ROM.
+// The ROM file itself is left unmodified.
+pub fn read_patched_rom(path: &Path, rom_extension: &str, entry_name: Option<&str>,
+    patch_path: Option<&Path>) -> io::Result<Vec<u8>> {
+    let rom = read_rom(path, rom_extension, entry_name)?;
+    let patch_path = match patch_path {
+        Some(patch_path) => patch_path.to_path_buf(),
+        None => match ["ips", "ups", "bps"].iter().map(|extension| path.with_extension(extension))
+            .find(|patch_path| patch_path.is_file()) {
+            Some(patch_path) => patch_path,
+            None => return Ok(rom),
+        },
+    };
+    info!("Applying Patch {}", patch_path.display());
+    apply_patch(rom, &fs::read(patch_path)?)
+}
+
+#[cfg(feature = "host")]
+// Reads a ROM that may be compressed with zip, 7z or gzip. From archives, the entry with the given name is
+// used or else the first one with the ROM's extension (nds or gba).
+pub fn read_rom(path: &Path, rom_extension: &str, entry_name: Option<&str>) -> io::Result<Vec<u8>> {
+    let is_rom = |name: &str| match entry_name {
+        Some(entry_name) => name == entry_name,
+        None => Path::new(name).extension().and_then(OsStr::to_str)
+            .is_some_and(|extension| extension.eq_ignore_ascii_case(rom_extension)),
+    };
+    let not_found = || io::Error::new(io::ErrorKind::NotFound, format!("No .{} File in Archive", rom_extension));
+    let extension = path.extension().and_then(OsStr::to_str).map(str::to_lowercase);
+    match extension.as_deref() {
+        Some("zip") => {
+            let mut archive = ZipArchive::new(File::open(path)?)?;
+            let index = (0..archive.len()).find(|i| archive.by_index(*i).is_ok_and(|file| is_rom(file.name())))
+                .ok_or_else(not_found)?;
+            let mut file = archive.by_index(index)?;
+            let mut rom = Vec::with_capacity(file.size() as usize);
+            file.read_to_end(&mut rom)?;
+            Ok(rom)
+        },
+        Some("7z") => {
+            let to_io_error = |err: sevenz_rust::Error| io::Error::new(io::ErrorKind::InvalidData, err.to_string());
+            let mut archive = SevenZReader::open(path, Password::empty()).map_err(to_io_error)?;
+            let mut rom = None;
+            archive.for_each_entries(|entry, reader| {
+                if rom.is_some() { return Ok(false) }
+                if entry.is_directory() || !is_rom(entry.name()) { return Ok(true) }
+                let mut data = Vec::with_capacity(entry.size() as usize);
+                reader.read_to_end(&mut data)?;
+                rom = Some(data);
+                Ok(false)
+            }).map_err(to_io_error)?;
+            rom.ok_or_else(not_found)
+        },
+        Some("gz") => {
+            let mut rom = Vec::new();
+            GzDecoder::new(File::open(path)?).read_to_end(&mut rom)?;
+            Ok(rom)
+        },
+        _ => fs::read(path),
+    }
+}
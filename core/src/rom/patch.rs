@@ -0,0 +1,172 @@
+use std::convert::TryInto;
+use std::io;
+
+use flate2::Crc;
+
+// Applies an IPS, UPS or BPS patch, detected from its header
+pub fn apply_patch(rom: Vec<u8>, patch: &[u8]) -> io::Result<Vec<u8>> {
+    let mut reader = PatchReader::new(patch);
+    if patch.starts_with(b"PATCH") {
+        reader.pos = 5;
+        apply_ips(rom, reader)
+    } else if patch.starts_with(b"UPS1") {
+        reader.pos = 4;
+        apply_ups(rom, reader)
+    } else if patch.starts_with(b"BPS1") {
+        reader.pos = 4;
+        apply_bps(rom, reader)
+    } else { Err(invalid_patch()) }
+}
+
+fn invalid_patch() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "Invalid Patch")
+}
+
+fn apply_ips(mut rom: Vec<u8>, mut reader: PatchReader) -> io::Result<Vec<u8>> {
+    loop {
+        let offset = reader.read_bytes(3)?;
+        if offset == b"EOF" { break }
+        let offset = (offset[0] as usize) << 16 | (offset[1] as usize) << 8 | offset[2] as usize;
+        let len = reader.read_u16_be()? as usize;
+        // Zero length records are run-length encoded
+        let (len, data) = if len == 0 {
+            let len = reader.read_u16_be()? as usize;
+            (len, vec![reader.read_u8()?; len])
+        } else { (len, reader.read_bytes(len)?.to_vec()) };
+        if rom.len() < offset + len { rom.resize(offset + len, 0) }
+        rom[offset..offset + len].copy_from_slice(&data);
+    }
+    // Some patches truncate the ROM
+    if let Ok(len) = reader.read_bytes(3) {
+        rom.truncate((len[0] as usize) << 16 | (len[1] as usize) << 8 | len[2] as usize);
+    }
+    Ok(rom)
+}
+
+fn apply_ups(mut rom: Vec<u8>, mut reader: PatchReader) -> io::Result<Vec<u8>> {
+    let (source_crc, target_crc) = reader.verify_footer()?;
+    let source_len = reader.read_varint()?;
+    let target_len = reader.read_varint()?;
+    check_crc("Source", &rom, source_crc);
+    if rom.len() != source_len { warn!("ROM Size Does Not Match Patch Source") }
+    rom.resize(target_len, 0);
+    let mut pos = 0;
+    while reader.pos < reader.data.len() - 12 {
+        pos += reader.read_varint()?;
+        // Bytes are XORed with the ROM until and including a 0
+        loop {
+            let value = reader.read_u8()?;
+            if let Some(byte) = rom.get_mut(pos) { *byte ^= value }
+            pos += 1;
+            if value == 0 { break }
+        }
+    }
+    check_crc("Target", &rom, target_crc);
+    Ok(rom)
+}
+
+fn apply_bps(rom: Vec<u8>, mut reader: PatchReader) -> io::Result<Vec<u8>> {
+    let (source_crc, target_crc) = reader.verify_footer()?;
+    let source_len = reader.read_varint()?;
+    let target_len = reader.read_varint()?;
+    let metadata_len = reader.read_varint()?;
+    reader.read_bytes(metadata_len)?;
+    check_crc("Source", &rom, source_crc);
+    if rom.len() != source_len { warn!("ROM Size Does Not Match Patch Source") }
+
+    let mut target = Vec::with_capacity(target_len);
+    let mut source_offset = 0usize;
+    let mut target_offset = 0usize;
+    let read_offset = |reader: &mut PatchReader, offset: usize| -> io::Result<usize> {
+        let value = reader.read_varint()?;
+        let delta = value >> 1;
+        if value & 0x1 != 0 { offset.checked_sub(delta) } else { Some(offset + delta) }.ok_or_else(invalid_patch)
+    };
+    while reader.pos < reader.data.len() - 12 {
+        let value = reader.read_varint()?;
+        let len = (value >> 2) + 1;
+        match value & 0x3 {
+            // Source Read
+            0 => {
+                let start = target.len();
+                target.extend_from_slice(rom.get(start..start + len).ok_or_else(invalid_patch)?);
+            },
+            // Target Read
+            1 => target.extend_from_slice(reader.read_bytes(len)?),
+            // Source Copy
+            2 => {
+                source_offset = read_offset(&mut reader, source_offset)?;
+                target.extend_from_slice(rom.get(source_offset..source_offset + len).ok_or_else(invalid_patch)?);
+                source_offset += len;
+            },
+            // Target Copy, which can overlap with the bytes being written
+            3 => {
+                target_offset = read_offset(&mut reader, target_offset)?;
+                if target_offset >= target.len() { return Err(invalid_patch()) }
+                for _ in 0..len {
+                    target.push(target[target_offset]);
+                    target_offset += 1;
+                }
+            },
+            _ => unreachable!(),
+        }
+    }
+    if target.len() != target_len { return Err(invalid_patch()) }
+    check_crc("Target", &target, target_crc);
+    Ok(target)
+}
+
+fn check_crc(label: &str, data: &[u8], expected: u32) {
+    let mut crc = Crc::new();
+    crc.update(data);
+    if crc.sum() != expected { warn!("{} CRC32 Does Not Match Patch", label) }
+}
+
+struct PatchReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> PatchReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        PatchReader {
+            data,
+            pos: 0,
+        }
+    }
+
+    fn read_bytes(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        let bytes = self.data.get(self.pos..self.pos + len).ok_or_else(invalid_patch)?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn read_u8(&mut self) -> io::Result<u8> { Ok(self.read_bytes(1)?[0]) }
+
+    fn read_u16_be(&mut self) -> io::Result<u16> { Ok(u16::from_be_bytes(self.read_bytes(2)?.try_into().unwrap())) }
+
+    // Variable length encoding shared by UPS and BPS
+    fn read_varint(&mut self) -> io::Result<usize> {
+        let mut value = 0usize;
+        let mut shift = 1usize;
+        loop {
+            let byte = self.read_u8()?;
+            value = shift.checked_mul((byte & 0x7F) as usize).and_then(|add| value.checked_add(add))
+                .ok_or_else(invalid_patch)?;
+            if byte & 0x80 != 0 { return Ok(value) }
+            shift = shift.checked_shl(7).filter(|shift| *shift != 0).ok_or_else(invalid_patch)?;
+            value = value.checked_add(shift).ok_or_else(invalid_patch)?;
+        }
+    }
+
+    // Returns the source and target CRC32s after checking the patch's own
+    fn verify_footer(&self) -> io::Result<(u32, u32)> {
+        let len = self.data.len();
+        if len < self.pos + 12 { return Err(invalid_patch()) }
+        let read_crc = |offset: usize| u32::from_le_bytes(self.data[offset..offset + 4].try_into().unwrap());
+        let mut crc = Crc::new();
+        crc.update(&self.data[..len - 4]);
+        if crc.sum() != read_crc(len - 4) { return Err(invalid_patch()) }
+        Ok((read_crc(len - 12), read_crc(len - 8)))
+    }
+}
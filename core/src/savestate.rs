@@ -0,0 +1,386 @@
+use std::collections::VecDeque;
+use std::marker::PhantomData;
+
+// Machine state is written field by field and loaded back in place, so host resources like the ROM,
+// save files and the audio sink stay attached to the running instance
+pub trait Savestate {
+    fn save(&self, state: &mut StateWriter);
+    fn load(&mut self, state: &mut StateReader);
+
+    fn save_slice(items: &[Self], state: &mut StateWriter) where Self: Sized {
+        for item in items.iter() { item.save(state) }
+    }
+
+    fn load_slice(items: &mut [Self], state: &mut StateReader) where Self: Sized {
+        for item in items.iter_mut() { item.load(state) }
+    }
+}
+
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        StateWriter {
+            data: Vec::new(),
+        }
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    // Sections start with their tag, version and length, so they can be migrated or skipped on their own
+    pub fn write_section(&mut self, tag: [u8; 4], version: u32, save: impl FnOnce(&mut StateWriter)) {
+        self.write_bytes(&tag);
+        version.save(self);
+        let len_pos = self.data.len();
+        0u64.save(self);
+        save(self);
+        let len = (self.data.len() - len_pos - 8) as u64;
+        self.data[len_pos..len_pos + 8].copy_from_slice(&len.to_le_bytes());
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    valid: bool,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        StateReader {
+            data,
+            pos: 0,
+            valid: true,
+        }
+    }
+
+    // Reads past the end fill with zeros and invalidate the state instead of panicking
+    pub fn read_bytes(&mut self, bytes: &mut [u8]) {
+        match self.data.get(self.pos..self.pos + bytes.len()) {
+            Some(data) => bytes.copy_from_slice(data),
+            None => { bytes.iter_mut().for_each(|byte| *byte = 0); self.valid = false },
+        }
+        self.pos += bytes.len();
+    }
+
+    pub fn read_len(&mut self) -> usize {
+        let mut len = 0u64;
+        len.load(self);
+        // Lengths are bounded by the remaining data so corrupt states can't allocate huge buffers
+        if len as usize > self.data.len().saturating_sub(self.pos) { self.valid = false; 0 } else { len as usize }
+    }
+
+    pub fn invalidate(&mut self) {
+        self.valid = false;
+    }
+
+    pub fn finished(&self) -> bool {
+        self.valid && self.pos == self.data.len()
+    }
+
+    // Returns None if the rest of the data isn't a list of whole sections
+    pub fn read_sections(&mut self) -> Option<Vec<Section<'a>>> {
+        let mut sections = Vec::new();
+        while self.pos < self.data.len() {
+            let mut tag = [0; 4];
+            let mut version = 0u32;
+            tag.load(self);
+            version.load(self);
+            let len = self.read_len();
+            if !self.valid { return None }
+            sections.push(Section { tag, version, data: &self.data[self.pos..self.pos + len] });
+            self.pos += len;
+        }
+        Some(sections)
+    }
+}
+
+pub struct Section<'a> {
+    pub tag: [u8; 4],
+    pub version: u32,
+    pub data: &'a [u8],
+}
+
+impl Section<'_> {
+    pub fn name(tag: [u8; 4]) -> String {
+        String::from_utf8_lossy(&tag).trim_end().to_string()
+    }
+}
+
+// Upgrades a section's data from one version to the next. Whenever a section's layout changes, its version is bumped
+// and a migration from the previous version is added here, e.g. one that appends a new field's default value.
+type Migration = ([u8; 4], u32, fn(&[u8]) -> Vec<u8>);
+
+const MIGRATIONS: &[Migration] = &[
+    // BIOSPROT, which the firmware will have set by the time a game is running
+    (*b"IO  ", 1, |data| [data, &0x1204u16.to_le_bytes()].concat()),
+    // Each CPU's interrupt sources and whether its IRQs are delayed
+    (*b"IO  ", 2, crate::hw::migrate_io_v2),
+    // The console model, SCFG and DSi WRAM
+    (*b"MEM ", 1, crate::hw::migrate_mem_v1),
+    // DSi interrupts and the SD/MMC controller
+    (*b"MEM ", 2, crate::hw::migrate_mem_v2),
+    // The DSi's NDMA channels
+    (*b"MEM ", 3, crate::hw::migrate_mem_v3),
+    // A pending VCOUNT write, which older states never had
+    (*b"GPU ", 1, |data| {
+        let mut state = StateWriter::new();
+        None::<u16>.save(&mut state);
+        [data, &state.finish()].concat()
+    }),
+];
+
+// Loads sections by tag, so their order doesn't matter and sections from newer builds that aren't known are skipped
+pub struct SectionLoader<'a> {
+    sections: Vec<Section<'a>>,
+    error: Option<String>,
+}
+
+impl<'a> SectionLoader<'a> {
+    pub fn new(state: &mut StateReader<'a>) -> Option<Self> {
+        Some(SectionLoader {
+            sections: state.read_sections()?,
+            error: None,
+        })
+    }
+
+    pub fn can_migrate(tag: [u8; 4], from: u32, to: u32) -> bool {
+        (from..to).all(|version| MIGRATIONS.iter().any(|(migration_tag, from, _)| *migration_tag == tag && *from == version))
+    }
+
+    fn migrate(tag: [u8; 4], from: u32, to: u32, data: &[u8]) -> Vec<u8> {
+        (from..to).fold(data.to_vec(), |data, version| {
+            let (_, _, migrate) = MIGRATIONS.iter().find(|(migration_tag, from, _)| *migration_tag == tag && *from == version)
+                .unwrap();
+            migrate(&data)
+        })
+    }
+
+    pub fn load(&mut self, tag: [u8; 4], version: u32, load: impl FnOnce(&mut StateReader)) {
+        if self.error.is_some() { return }
+        let section = match self.sections.iter().find(|section| section.tag == tag) {
+            Some(section) => section,
+            None => { self.error = Some(format!("Missing {} Section", Section::name(tag))); return },
+        };
+        let migrated;
+        let data = if section.version == version { section.data } else {
+            if section.version > version || !SectionLoader::can_migrate(tag, section.version, version) {
+                self.error = Some(format!("Unsupported {} Section Version {}", Section::name(tag), section.version));
+                return
+            }
+            migrated = SectionLoader::migrate(tag, section.version, version, section.data);
+            &migrated
+        };
+        let mut state = StateReader::new(data);
+        load(&mut state);
+        if !state.finished() { self.error = Some(format!("Corrupt {} Section", Section::name(tag))) }
+    }
+
+    pub fn finish(self) -> Result<(), String> {
+        match self.error {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+}
+
+macro_rules! savestate_primitive {
+    ($($ty:ty),*) => {
+        $(impl Savestate for $ty {
+            fn save(&self, state: &mut StateWriter) {
+                state.write_bytes(&self.to_le_bytes());
+            }
+
+            fn load(&mut self, state: &mut StateReader) {
+                let mut bytes = self.to_le_bytes();
+                state.read_bytes(&mut bytes);
+                *self = <$ty>::from_le_bytes(bytes);
+            }
+        })*
+    };
+}
+
+savestate_primitive!(u16, u32, u64, i8, i16, i32, i64, f32, f64);
+
+// Bytes are copied in bulk since memories make up most of a state
+impl Savestate for u8 {
+    fn save(&self, state: &mut StateWriter) { state.write_bytes(&[*self]) }
+    fn load(&mut self, state: &mut StateReader) { state.read_bytes(std::slice::from_mut(self)) }
+    fn save_slice(items: &[Self], state: &mut StateWriter) { state.write_bytes(items) }
+    fn load_slice(items: &mut [Self], state: &mut StateReader) { state.read_bytes(items) }
+}
+
+impl Savestate for usize {
+    fn save(&self, state: &mut StateWriter) { (*self as u64).save(state) }
+    fn load(&mut self, state: &mut StateReader) {
+        let mut value = 0u64;
+        value.load(state);
+        *self = value as usize;
+    }
+}
+
+impl Savestate for bool {
+    fn save(&self, state: &mut StateWriter) { (*self as u8).save(state) }
+    fn load(&mut self, state: &mut StateReader) {
+        let mut value = 0u8;
+        value.load(state);
+        *self = value != 0;
+    }
+}
+
+impl<T: Savestate, const N: usize> Savestate for [T; N] {
+    fn save(&self, state: &mut StateWriter) { T::save_slice(self, state) }
+    fn load(&mut self, state: &mut StateReader) { T::load_slice(self, state) }
+}
+
+impl<T: Savestate + Default> Savestate for Vec<T> {
+    fn save(&self, state: &mut StateWriter) {
+        self.len().save(state);
+        T::save_slice(self, state);
+    }
+
+    fn load(&mut self, state: &mut StateReader) {
+        let len = state.read_len();
+        self.clear();
+        self.resize_with(len, T::default);
+        T::load_slice(self, state);
+    }
+}
+
+impl<T: Savestate + Default> Savestate for VecDeque<T> {
+    fn save(&self, state: &mut StateWriter) {
+        self.len().save(state);
+        for item in self.iter() { item.save(state) }
+    }
+
+    fn load(&mut self, state: &mut StateReader) {
+        let len = state.read_len();
+        self.clear();
+        for _ in 0..len {
+            let mut item = T::default();
+            item.load(state);
+            self.push_back(item);
+        }
+    }
+}
+
+impl<T: Savestate + Default> Savestate for Option<T> {
+    fn save(&self, state: &mut StateWriter) {
+        self.is_some().save(state);
+        if let Some(value) = self { value.save(state) }
+    }
+
+    fn load(&mut self, state: &mut StateReader) {
+        let mut is_some = false;
+        is_some.load(state);
+        *self = if is_some {
+            let mut value = T::default();
+            value.load(state);
+            Some(value)
+        } else { None };
+    }
+}
+
+impl<A: Savestate, B: Savestate> Savestate for (A, B) {
+    fn save(&self, state: &mut StateWriter) { self.0.save(state); self.1.save(state) }
+    fn load(&mut self, state: &mut StateReader) { self.0.load(state); self.1.load(state) }
+}
+
+impl<T: Savestate + ?Sized> Savestate for Box<T> {
+    fn save(&self, state: &mut StateWriter) { (**self).save(state) }
+    fn load(&mut self, state: &mut StateReader) { (**self).load(state) }
+}
+
+impl<T> Savestate for PhantomData<T> {
+    fn save(&self, _state: &mut StateWriter) {}
+    fn load(&mut self, _state: &mut StateReader) {}
+}
+
+// Lists the fields of a struct that make up its state
+macro_rules! savestate {
+    ([$($generics:tt)*] $ty:ty { $($field:tt),* $(,)? }) => {
+        impl<$($generics)*> $crate::savestate::Savestate for $ty {
+            fn save(&self, state: &mut $crate::savestate::StateWriter) {
+                $($crate::savestate::Savestate::save(&self.$field, state);)*
+            }
+
+            fn load(&mut self, state: &mut $crate::savestate::StateReader) {
+                $($crate::savestate::Savestate::load(&mut self.$field, state);)*
+            }
+        }
+    };
+    ($ty:ty {}) => {
+        impl $crate::savestate::Savestate for $ty {
+            fn save(&self, _state: &mut $crate::savestate::StateWriter) {}
+            fn load(&mut self, _state: &mut $crate::savestate::StateReader) {}
+        }
+    };
+    ($ty:ty { $($field:tt),* $(,)? }) => { savestate!([] $ty { $($field),* }); };
+}
+
+// Groups fields into versioned sections, and lists the current version of each section.
+// Fields of an array element can be listed too, e.g. interrupts[0].delayed.
+macro_rules! savestate_sections {
+    ($ty:ty { $($tag:literal $version:literal => {
+        $($field:ident $([$index:literal])? $(.$member:ident)?),* $(,)?
+    }),* $(,)? }) => {
+        impl $ty {
+            pub(crate) const STATE_SECTIONS: &'static [([u8; 4], u32)] = &[$((*$tag, $version)),*];
+
+            pub(crate) fn save_sections(&self, state: &mut $crate::savestate::StateWriter) {
+                $(state.write_section(*$tag, $version, |state| {
+                    $($crate::savestate::Savestate::save(&self.$field$([$index])?$(.$member)?, state);)*
+                });)*
+            }
+
+            pub(crate) fn load_sections(&mut self, sections: &mut $crate::savestate::SectionLoader) {
+                $(sections.load(*$tag, $version, |state| {
+                    $($crate::savestate::Savestate::load(&mut self.$field$([$index])?$(.$member)?, state);)*
+                });)*
+            }
+        }
+    };
+}
+
+// Enums without fields are stored as their discriminant
+macro_rules! savestate_enum {
+    ($ty:ident { $($variant:ident),* $(,)? }) => {
+        impl $crate::savestate::Savestate for $ty {
+            fn save(&self, state: &mut $crate::savestate::StateWriter) {
+                $crate::savestate::Savestate::save(&(*self as u8), state);
+            }
+
+            fn load(&mut self, state: &mut $crate::savestate::StateReader) {
+                let mut value = 0u8;
+                $crate::savestate::Savestate::load(&mut value, state);
+                match [$($ty::$variant),*].iter().find(|variant| **variant as u8 == value) {
+                    Some(variant) => *self = *variant,
+                    None => state.invalidate(),
+                }
+            }
+        }
+    };
+}
+
+// Has to be used in the module that declares the flags, since the raw bits are kept as is
+macro_rules! savestate_bitflags {
+    ($($ty:ty),* $(,)?) => {
+        $(impl $crate::savestate::Savestate for $ty {
+            fn save(&self, state: &mut $crate::savestate::StateWriter) {
+                $crate::savestate::Savestate::save(&self.bits, state);
+            }
+
+            fn load(&mut self, state: &mut $crate::savestate::StateReader) {
+                $crate::savestate::Savestate::load(&mut self.bits, state);
+            }
+        })*
+    };
+}
@@ -0,0 +1,69 @@
+#[cfg(feature = "host")]
+use std::fs::File;
+#[cfg(feature = "host")]
+use std::io::{self, BufWriter, Write};
+#[cfg(feature = "host")]
+use std::path::Path;
+
+use crate::nds::Frame;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Layout {
+    Top,
+    Bottom,
+    Vertical,
+    Horizontal,
+}
+
+impl Layout {
+    pub const ALL: [Layout; 4] = [Layout::Top, Layout::Bottom, Layout::Vertical, Layout::Horizontal];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Layout::Top => "Top Screen",
+            Layout::Bottom => "Bottom Screen",
+            Layout::Vertical => "Both Screens (Vertical)",
+            Layout::Horizontal => "Both Screens (Horizontal)",
+        }
+    }
+}
+
+// RGBA8 pixels, row by row
+pub struct Screenshot {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+impl Screenshot {
+    pub fn new(frame: &Frame, layout: Layout) -> Self {
+        let (width, height) = (frame.width, frame.height);
+        match layout {
+            Layout::Top => Screenshot { width, height, pixels: frame.top.to_vec() },
+            Layout::Bottom => Screenshot { width, height, pixels: frame.bottom.to_vec() },
+            Layout::Vertical => Screenshot { width, height: 2 * height, pixels: [frame.top, frame.bottom].concat() },
+            Layout::Horizontal => {
+                let mut pixels = Vec::with_capacity(frame.top.len() + frame.bottom.len());
+                for (top_row, bottom_row) in frame.top.chunks_exact(4 * width).zip(frame.bottom.chunks_exact(4 * width)) {
+                    pixels.extend_from_slice(top_row);
+                    pixels.extend_from_slice(bottom_row);
+                }
+                Screenshot { width: 2 * width, height, pixels }
+            },
+        }
+    }
+
+    #[cfg(feature = "host")]
+    pub fn write_png<W: Write>(&self, writer: W) -> io::Result<()> {
+        let mut encoder = png::Encoder::new(writer, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header()?.write_image_data(&self.pixels)?;
+        Ok(())
+    }
+
+    #[cfg(feature = "host")]
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        self.write_png(BufWriter::new(File::create(path)?))
+    }
+}
@@ -0,0 +1,80 @@
+use std::io;
+
+// Function symbols from an ELF's symbol table, used to name addresses in the debugger
+pub struct Symbols {
+    // Sorted by address
+    functions: Vec<Symbol>,
+}
+
+struct Symbol {
+    name: String,
+    addr: u32,
+    size: u32,
+}
+
+impl Symbols {
+    const SHT_SYMTAB: u32 = 2;
+    const STT_FUNC: u8 = 2;
+
+    // Only 32 bit little endian ELFs are supported, like the ones produced by devkitARM
+    pub fn from_elf(elf: &[u8]) -> io::Result<Self> {
+        let invalid = |msg| io::Error::new(io::ErrorKind::InvalidData, msg);
+        let read_u16 = |addr: usize| elf.get(addr..addr + 2).map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]) as usize);
+        let read_u32 = |addr: usize| elf.get(addr..addr + 4)
+            .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]));
+        if elf.get(..6) != Some(&[0x7F, b'E', b'L', b'F', 1, 1]) { return Err(invalid("Not a 32 Bit Little Endian ELF")) }
+
+        let section_offset = read_u32(0x20).ok_or_else(|| invalid("Truncated ELF"))? as usize;
+        let section_size = read_u16(0x2E).ok_or_else(|| invalid("Truncated ELF"))?;
+        let section_count = read_u16(0x30).ok_or_else(|| invalid("Truncated ELF"))?;
+        // Type, offset, size and link of each section
+        let section = |i: usize| {
+            let addr = section_offset + i * section_size;
+            Some((read_u32(addr + 4)?, read_u32(addr + 16)? as usize, read_u32(addr + 20)? as usize,
+                read_u32(addr + 24)? as usize))
+        };
+
+        let mut functions = Vec::new();
+        for i in 0..section_count {
+            let (section_type, offset, size, link) = section(i).ok_or_else(|| invalid("Truncated Section Header"))?;
+            if section_type != Symbols::SHT_SYMTAB { continue }
+            let (_, strings_offset, strings_size, _) = section(link).ok_or_else(|| invalid("Invalid String Table"))?;
+            let strings = elf.get(strings_offset..strings_offset + strings_size)
+                .ok_or_else(|| invalid("Truncated String Table"))?;
+            let symbols = elf.get(offset..offset + size).ok_or_else(|| invalid("Truncated Symbol Table"))?;
+            for symbol in symbols.chunks_exact(16) {
+                if symbol[12] & 0xF != Symbols::STT_FUNC { continue }
+                let field = |i: usize| u32::from_le_bytes([symbol[i], symbol[i + 1], symbol[i + 2], symbol[i + 3]]);
+                let name = strings.get(field(0) as usize..).unwrap_or_default();
+                let name = &name[..name.iter().position(|byte| *byte == 0).unwrap_or(name.len())];
+                if name.is_empty() { continue }
+                functions.push(Symbol {
+                    name: String::from_utf8_lossy(name).into_owned(),
+                    // Bit 0 marks Thumb functions
+                    addr: field(4) & !0x1,
+                    size: field(8),
+                });
+            }
+        }
+        if functions.is_empty() { return Err(invalid("ELF has no Function Symbols")) }
+        functions.sort_by_key(|symbol| symbol.addr);
+        Ok(Symbols { functions })
+    }
+
+    // Name of the function containing the address and the offset into it
+    pub fn lookup(&self, addr: u32) -> Option<(&str, u32)> {
+        let i = self.functions.partition_point(|symbol| symbol.addr <= addr).checked_sub(1)?;
+        let symbol = &self.functions[i];
+        let offset = addr - symbol.addr;
+        if symbol.size != 0 && offset >= symbol.size { return None }
+        Some((&symbol.name, offset))
+    }
+
+    pub fn describe(&self, addr: u32) -> String {
+        match self.lookup(addr) {
+            Some((name, 0)) => name.to_string(),
+            Some((name, offset)) => format!("{}+0x{:X}", name, offset),
+            None => format!("{:08X}", addr),
+        }
+    }
+}
@@ -0,0 +1,242 @@
+use std::io::{self, BufRead, Write};
+
+use crate::nds::Cpu;
+
+// Each line is a record of space separated NAME:VALUE fields, with registers in the usual R0 - R15 then CPSR order.
+// Traces from other emulators are read through a TraceFormat instead of having to be converted.
+//   CPU:ARM9 PC:02000000 OP:E3A00000 R0:00000000 ... R15:02000008 CPSR:0000001F
+//   FRAME:1 ARM9.PC:02000000 ARM9.IME:00000001 ... ARM7.PC:03800000 ... FB:1A2B3C4D
+// Traces of one CPU are instead written like the instruction traces of other emulators, so they can be diffed as is:
+//   02000000: E3A00000 00000000 ... 02000008 0000001F
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TraceLevel {
+    // Key IO registers, the PCs and a hash of the screens after every frame
+    Frame,
+    // Registers before every instruction, along with the frame records
+    Instruction,
+    // Only the PC, opcode and registers before each of this CPU's instructions
+    Cpu(Cpu),
+}
+
+pub(crate) struct Tracer {
+    writer: Box<dyn Write + Send>,
+    pub level: TraceLevel,
+}
+
+impl Tracer {
+    pub const FRAME_REGISTERS: [&'static str; 6] = ["IME", "IE", "IF", "DISPSTAT", "VCOUNT", "KEYINPUT"];
+
+    pub fn new(writer: Box<dyn Write + Send>, level: TraceLevel) -> Self {
+        Tracer {
+            writer,
+            level,
+        }
+    }
+
+    // Registers are R0 - R15 and CPSR
+    pub fn instruction(&mut self, cpu: Cpu, pc: u32, opcode: u32, regs: &[u32; 17]) -> io::Result<()> {
+        if let TraceLevel::Cpu(_) = self.level {
+            write!(self.writer, "{:08X}: {:08X}", pc, opcode)?;
+            for value in regs.iter() { write!(self.writer, " {:08X}", value)? }
+            return writeln!(self.writer)
+        }
+        write!(self.writer, "CPU:{:?} PC:{:08X} OP:{:08X}", cpu, pc, opcode)?;
+        for (i, value) in regs[..16].iter().enumerate() {
+            write!(self.writer, " R{}:{:08X}", i, value)?;
+        }
+        writeln!(self.writer, " CPSR:{:08X}", regs[16])
+    }
+
+    pub fn frame(&mut self, frame: u64, fields: &[(String, u64)], frame_hash: u32) -> io::Result<()> {
+        write!(self.writer, "FRAME:{}", frame)?;
+        for (name, value) in fields.iter() {
+            write!(self.writer, " {}:{:08X}", name, value)?;
+        }
+        writeln!(self.writer, " FB:{:08X}", frame_hash)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    // FNV-1a, which is simple to reproduce when converting other traces
+    pub fn hash(data: &[&[u8]]) -> u32 {
+        Tracer::continue_hash(0x811C_9DC5, data)
+    }
+
+    pub fn continue_hash(hash: u32, data: &[&[u8]]) -> u32 {
+        data.iter().copied().flatten().fold(hash, |hash, byte| (hash ^ *byte as u32).wrapping_mul(0x0100_0193))
+    }
+}
+
+// Hashes of the screens after every frame of a run and all of its audio output, for comparing runs against
+// hashes stored from a known good build
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OutputHashes {
+    pub video: u32,
+    pub audio: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct Divergence {
+    // Starting at 1, like a text editor
+    pub line: usize,
+    // Empty if that trace ended first
+    pub expected: String,
+    pub actual: String,
+    // Names of the fields that differ
+    pub fields: Vec<String>,
+}
+
+enum FormatPart {
+    Text(String),
+    Field(String),
+}
+
+// Describes the lines of another emulator's trace with literal text and %NAME% fields named like the fields here,
+// such as "%PC%: %OP%  r0=%R0% r1=%R1% ... cpsr=%CPSR%". Values are compared as hex numbers, with or without 0x.
+// Lines that don't fit the format are skipped, and so are records here without every field it names.
+// Traces of only one CPU without a %CPU% field are compared against that CPU's records.
+pub struct TraceFormat {
+    parts: Vec<FormatPart>,
+    cpu: Option<Cpu>,
+}
+
+impl TraceFormat {
+    pub fn new(template: &str, cpu: Option<Cpu>) -> Result<Self, String> {
+        let pieces: Vec<&str> = template.split('%').collect();
+        if pieces.len().is_multiple_of(2) { return Err("Unclosed Field".to_string()) }
+        let mut parts = Vec::new();
+        for (i, piece) in pieces.iter().enumerate() {
+            if i.is_multiple_of(2) {
+                if !piece.is_empty() { parts.push(FormatPart::Text(piece.to_string())) }
+            } else {
+                if piece.is_empty() { return Err("Empty Field Name".to_string()) }
+                // Nothing would separate the values
+                if let Some(FormatPart::Field(prev)) = parts.last() {
+                    return Err(format!("No Text Between {} and {}", prev, piece))
+                }
+                parts.push(FormatPart::Field(piece.to_ascii_uppercase()));
+            }
+        }
+        Ok(TraceFormat { parts, cpu })
+    }
+
+    fn parse(&self, line: &str) -> Option<Vec<(String, String)>> {
+        let mut rest = line;
+        let mut fields = Vec::new();
+        for (i, part) in self.parts.iter().enumerate() {
+            match part {
+                FormatPart::Text(text) => rest = rest.strip_prefix(text.as_str())?,
+                FormatPart::Field(name) => {
+                    let len = match self.parts.get(i + 1) {
+                        Some(FormatPart::Text(text)) => rest.find(text.as_str())?,
+                        _ => rest.find(char::is_whitespace).unwrap_or(rest.len()),
+                    };
+                    fields.push((name.clone(), rest[..len].to_string()));
+                    rest = &rest[len..];
+                },
+            }
+        }
+        Some(fields)
+    }
+
+    // Whether a record here has a line to compare in the other trace
+    fn matches(&self, fields: &[(String, String)]) -> bool {
+        let has = |name: &str| fields.iter().any(|(other_name, _)| other_name == name);
+        let cpu_matches = match self.cpu {
+            Some(cpu) => fields.iter().any(|(name, value)| name == "CPU" && *value == format!("{:?}", cpu)),
+            None => true,
+        };
+        cpu_matches && self.parts.iter().all(|part| match part {
+            FormatPart::Field(name) => has(name),
+            FormatPart::Text(_) => true,
+        })
+    }
+}
+
+struct Record {
+    line: usize,
+    text: String,
+    fields: Vec<(String, String)>,
+}
+
+// Finds the first record that differs between the traces
+pub fn compare<E: BufRead, A: BufRead>(expected: E, actual: A) -> io::Result<Option<Divergence>> {
+    compare_records(records(expected, |line| Some(parse(line))), records(actual, |line| Some(parse(line))), true)
+}
+
+// Compares another emulator's trace, with lines in the given format, to one from here
+pub fn compare_with_format<E: BufRead, A: BufRead>(expected: E, format: &TraceFormat, actual: A)
+    -> io::Result<Option<Divergence>> {
+    let actual = records(actual, |line| Some(parse(line)).filter(|fields| format.matches(fields)));
+    compare_records(records(expected, |line| format.parse(line)), actual, false)
+}
+
+fn records<'a, R: BufRead + 'a>(reader: R, parse: impl Fn(&str) -> Option<Vec<(String, String)>> + 'a)
+    -> impl Iterator<Item = io::Result<Record>> + 'a {
+    reader.lines().enumerate().filter_map(move |(i, text)| match text {
+        Ok(text) => parse(&text).map(|fields| Ok(Record { line: i + 1, text, fields })),
+        Err(err) => Some(Err(err)),
+    })
+}
+
+// Extra fields in the actual trace only count when both traces are from here
+fn compare_records(mut expected: impl Iterator<Item = io::Result<Record>>,
+    mut actual: impl Iterator<Item = io::Result<Record>>, extra_fields_differ: bool) -> io::Result<Option<Divergence>> {
+    loop {
+        let (expected_record, actual_record) = match (expected.next().transpose()?, actual.next().transpose()?) {
+            (None, None) => return Ok(None),
+            records => records,
+        };
+        let line = expected_record.as_ref().or(actual_record.as_ref()).map_or(0, |record| record.line);
+        let (expected_record, actual_record) = (
+            expected_record.unwrap_or(Record { line, text: String::new(), fields: Vec::new() }),
+            actual_record.unwrap_or(Record { line, text: String::new(), fields: Vec::new() }),
+        );
+        let fields = diff_fields(&expected_record.fields, &actual_record.fields, extra_fields_differ);
+        if !fields.is_empty() {
+            return Ok(Some(Divergence {
+                line: expected_record.line,
+                expected: expected_record.text,
+                actual: actual_record.text,
+                fields,
+            }))
+        }
+    }
+}
+
+// Fields without a name are named after their position
+fn parse(line: &str) -> Vec<(String, String)> {
+    line.split_whitespace().enumerate().map(|(i, field)| match field.split_once(':') {
+        Some((name, value)) => (name.to_string(), value.to_string()),
+        None => (format!("#{}", i), field.to_string()),
+    }).collect()
+}
+
+fn same_value(expected: &str, actual: &str) -> bool {
+    let hex = |value: &str| {
+        let value = value.trim();
+        u64::from_str_radix(value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")).unwrap_or(value), 16).ok()
+    };
+    match (hex(expected), hex(actual)) {
+        (Some(expected), Some(actual)) => expected == actual,
+        _ => expected.trim().eq_ignore_ascii_case(actual.trim()),
+    }
+}
+
+fn diff_fields(expected: &[(String, String)], actual: &[(String, String)], extra_fields_differ: bool) -> Vec<String> {
+    let mut fields = Vec::new();
+    for (name, value) in expected.iter() {
+        match actual.iter().find(|(other_name, _)| other_name == name) {
+            Some((_, other_value)) if same_value(value, other_value) => (),
+            _ => fields.push(name.clone()),
+        }
+    }
+    if extra_fields_differ {
+        for (name, _) in actual.iter() {
+            if !expected.iter().any(|(other_name, _)| other_name == name) { fields.push(name.clone()) }
+        }
+    }
+    fields
+}
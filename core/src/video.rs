@@ -0,0 +1,202 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::nds::{Frame, NDS};
+
+// Frames and audio are written as they're emulated, so the recording stays in sync regardless of how fast the
+// emulator runs. Files are split before reaching the AVI 1.0 size limit.
+pub struct VideoRecorder {
+    path: PathBuf,
+    num_segments: usize,
+    cycles_per_frame: usize,
+    last_frame_count: Option<u64>,
+    avi: Option<AviWriter>,
+}
+
+impl VideoRecorder {
+    const MAX_SEGMENT_SIZE: u64 = 0x7800_0000;
+
+    pub fn new(path: &Path, cycles_per_frame: usize) -> io::Result<Self> {
+        Ok(VideoRecorder {
+            path: path.to_path_buf(),
+            num_segments: 1,
+            cycles_per_frame,
+            last_frame_count: None,
+            avi: Some(AviWriter::new(path, cycles_per_frame)?),
+        })
+    }
+
+    // Recording stops after the first error
+    pub fn write_frame(&mut self, frame: &Frame, samples: &[(i16, i16)]) -> io::Result<()> {
+        // No frame is finished when switching to GBA mode or powering off
+        if self.last_frame_count == Some(frame.count) { return Ok(()) }
+        self.last_frame_count = Some(frame.count);
+        let avi = match self.avi.as_mut() {
+            Some(avi) => avi,
+            None => return Ok(()),
+        };
+        let result = if avi.size() > VideoRecorder::MAX_SEGMENT_SIZE {
+            self.start_segment()
+        } else { Ok(()) }.and_then(|_| self.avi.as_mut().unwrap().write_frame(frame, samples));
+        if result.is_err() { self.avi = None }
+        result
+    }
+
+    fn start_segment(&mut self) -> io::Result<()> {
+        let stem = self.path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("video");
+        let path = self.path.with_file_name(format!("{}_{}.avi", stem, self.num_segments));
+        self.avi = None;
+        self.avi = Some(AviWriter::new(&path, self.cycles_per_frame)?);
+        self.num_segments += 1;
+        Ok(())
+    }
+}
+
+// Uncompressed RGB555 video, which holds DS colors exactly, interleaved with 16 bit stereo PCM audio
+struct AviWriter {
+    writer: BufWriter<File>,
+    index: Vec<([u8; 4], u32, u32)>,
+    movi_size: u32,
+    num_frames: u32,
+    num_samples: u32,
+}
+
+impl AviWriter {
+    const WIDTH: u32 = 256;
+    const HEIGHT: u32 = 2 * 192;
+    const FRAME_SIZE: u32 = AviWriter::WIDTH * AviWriter::HEIGHT * 2;
+    const SAMPLE_RATE: u32 = (NDS::CLOCK_RATE / 1024) as u32;
+    const BYTES_PER_SAMPLE: u32 = 4;
+    // Offsets of values patched in finish
+    const RIFF_SIZE_POS: u64 = 4;
+    const TOTAL_FRAMES_POS: u64 = 48;
+    const VIDEO_LENGTH_POS: u64 = 140;
+    const AUDIO_LENGTH_POS: u64 = 264;
+    const MOVI_SIZE_POS: u64 = 316;
+    const MOVI_START: u32 = 320;
+    const KEYFRAME: u32 = 0x10;
+
+    fn new(path: &Path, cycles_per_frame: usize) -> io::Result<Self> {
+        let mut avi_writer = AviWriter {
+            writer: BufWriter::new(File::create(path)?),
+            index: Vec::new(),
+            movi_size: 4,
+            num_frames: 0,
+            num_samples: 0,
+        };
+        avi_writer.write_header(cycles_per_frame as u32)?;
+        Ok(avi_writer)
+    }
+
+    fn write_header(&mut self, cycles_per_frame: u32) -> io::Result<()> {
+        let clock_rate = NDS::CLOCK_RATE as u32;
+        let bytes_per_sec = AviWriter::SAMPLE_RATE * AviWriter::BYTES_PER_SAMPLE;
+        let w = &mut self.writer;
+        fn write_u32s(w: &mut BufWriter<File>, values: &[u32]) -> io::Result<()> {
+            for value in values.iter() { w.write_all(&value.to_le_bytes())? }
+            Ok(())
+        }
+        w.write_all(b"RIFF")?;
+        write_u32s(w, &[0])?; // Patched in finish
+        w.write_all(b"AVI LIST")?;
+        write_u32s(w, &[4 + (8 + 56) + (8 + 116) + (8 + 92)])?;
+        w.write_all(b"hdrlavih")?;
+        write_u32s(w, &[56, (1_000_000 * cycles_per_frame as u64 / clock_rate as u64) as u32,
+            AviWriter::FRAME_SIZE * 60 + bytes_per_sec, 0, 0x110, 0, 0, 2, AviWriter::FRAME_SIZE,
+            AviWriter::WIDTH, AviWriter::HEIGHT, 0, 0, 0, 0])?;
+
+        // Frame rate is exactly the clock rate over the cycles per frame
+        w.write_all(b"LIST")?;
+        write_u32s(w, &[116])?;
+        w.write_all(b"strlstrh")?;
+        write_u32s(w, &[56])?;
+        w.write_all(b"vidsDIB ")?;
+        write_u32s(w, &[0, 0, 0, cycles_per_frame, clock_rate, 0, 0, AviWriter::FRAME_SIZE, u32::MAX, 0,
+            0, AviWriter::WIDTH | AviWriter::HEIGHT << 16])?;
+        w.write_all(b"strf")?;
+        write_u32s(w, &[40, 40, AviWriter::WIDTH, AviWriter::HEIGHT, 1 | 16 << 16, 0, AviWriter::FRAME_SIZE,
+            0, 0, 0, 0])?;
+
+        w.write_all(b"LIST")?;
+        write_u32s(w, &[92])?;
+        w.write_all(b"strlstrh")?;
+        write_u32s(w, &[56])?;
+        w.write_all(b"auds")?;
+        write_u32s(w, &[0, 0, 0, 0, AviWriter::BYTES_PER_SAMPLE, bytes_per_sec, 0, 0, bytes_per_sec, u32::MAX,
+            AviWriter::BYTES_PER_SAMPLE, 0, 0])?;
+        w.write_all(b"strf")?;
+        write_u32s(w, &[16, 1 | 2 << 16, AviWriter::SAMPLE_RATE, bytes_per_sec,
+            AviWriter::BYTES_PER_SAMPLE | 16 << 16])?;
+
+        w.write_all(b"LIST")?;
+        write_u32s(w, &[0])?; // Patched in finish
+        w.write_all(b"movi")
+    }
+
+    fn size(&self) -> u64 {
+        AviWriter::MOVI_START as u64 + self.movi_size as u64 + 8 + 16 * self.index.len() as u64
+    }
+
+    fn write_frame(&mut self, frame: &Frame, samples: &[(i16, i16)]) -> io::Result<()> {
+        // Rows are stored bottom to top
+        let mut data = Vec::with_capacity(AviWriter::FRAME_SIZE as usize);
+        for screen in [frame.bottom, frame.top].iter() {
+            for row in screen.chunks_exact(4 * frame.width).rev() {
+                for rgba in row.chunks_exact(4) {
+                    let color = (rgba[0] as u16 >> 3) << 10 | (rgba[1] as u16 >> 3) << 5 | rgba[2] as u16 >> 3;
+                    data.extend_from_slice(&color.to_le_bytes());
+                }
+            }
+        }
+        self.write_chunk(*b"00db", &data)?;
+        self.num_frames += 1;
+
+        if !samples.is_empty() {
+            let mut data = Vec::with_capacity(samples.len() * AviWriter::BYTES_PER_SAMPLE as usize);
+            for sample in samples.iter() {
+                data.extend_from_slice(&sample.0.to_le_bytes());
+                data.extend_from_slice(&sample.1.to_le_bytes());
+            }
+            self.write_chunk(*b"01wb", &data)?;
+            self.num_samples += samples.len() as u32;
+        }
+        Ok(())
+    }
+
+    fn write_chunk(&mut self, id: [u8; 4], data: &[u8]) -> io::Result<()> {
+        self.writer.write_all(&id)?;
+        self.writer.write_all(&(data.len() as u32).to_le_bytes())?;
+        self.writer.write_all(data)?;
+        self.index.push((id, self.movi_size, data.len() as u32));
+        self.movi_size += 8 + data.len() as u32;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.writer.write_all(b"idx1")?;
+        self.writer.write_all(&(16 * self.index.len() as u32).to_le_bytes())?;
+        for (id, offset, size) in self.index.iter() {
+            self.writer.write_all(id)?;
+            for value in [AviWriter::KEYFRAME, *offset, *size].iter() { self.writer.write_all(&value.to_le_bytes())? }
+        }
+        let patches = [
+            (AviWriter::RIFF_SIZE_POS, self.size() as u32 - 8),
+            (AviWriter::TOTAL_FRAMES_POS, self.num_frames),
+            (AviWriter::VIDEO_LENGTH_POS, self.num_frames),
+            (AviWriter::AUDIO_LENGTH_POS, self.num_samples),
+            (AviWriter::MOVI_SIZE_POS, self.movi_size),
+        ];
+        for (pos, value) in patches.iter() {
+            self.writer.seek(SeekFrom::Start(*pos))?;
+            self.writer.write_all(&value.to_le_bytes())?;
+        }
+        self.writer.flush()
+    }
+}
+
+impl Drop for AviWriter {
+    fn drop(&mut self) {
+        self.finish().unwrap_or_else(|err| warn!("Unable to Finish Video Recording: {}!", err));
+    }
+}
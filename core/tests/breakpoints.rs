@@ -0,0 +1,28 @@
+use nds_core::nds::{Cpu, NDS};
+
+mod common;
+
+// The ARM9 counts up into the backdrop color while the ARM7 counts in a register
+fn nds() -> NDS {
+    common::nds(common::rom(&common::ARM9_COUNTER, &[
+        0xE281_1001, // add r1, r1, #1
+        0xEAFF_FFFD, // b 0x02380000
+    ]))
+}
+
+// Stopping at a breakpoint and continuing has to run the CPUs in the same order as not stopping
+#[test]
+fn resumes_in_order() {
+    let mut expected = nds();
+    for _ in 0..2 { assert!(expected.run_frame().unwrap()) }
+
+    let mut nds = nds();
+    nds.add_breakpoint(Cpu::ARM7, 0x0238_0000);
+    let mut stops = 0;
+    for _ in 0..2 {
+        while !nds.run_frame().unwrap() { stops += 1 }
+    }
+    assert!(stops > 0);
+    assert!(nds.reg(Cpu::ARM9, 1) == expected.reg(Cpu::ARM9, 1));
+    assert!(nds.save_state() == expected.save_state());
+}
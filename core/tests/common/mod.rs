@@ -0,0 +1,32 @@
+use nds_core::nds::{ConsoleModel, MemoryStorage, NDS, SampleQueue, SystemFiles};
+
+// Counts up into the backdrop color
+pub const ARM9_COUNTER: [u32; 5] = [
+    0xE3A0_0405, // mov r0, #0x05000000
+    0xE3A0_1000, // mov r1, #0
+    0xE281_1001, // add r1, r1, #1
+    0xE1C0_10B0, // strh r1, [r0]
+    0xEAFF_FFFC, // b 0x02000008
+];
+
+// A direct-booted ROM that runs the ARM9 code at 0x02000000 and the ARM7 code at 0x02380000
+pub fn rom(arm9: &[u32], arm7: &[u32]) -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    let words = |rom: &mut [u8], addr: usize, words: &[u32]| for (i, word) in words.iter().enumerate() {
+        rom[addr + 4 * i..addr + 4 * i + 4].copy_from_slice(&word.to_le_bytes());
+    };
+    // ARM9 and ARM7 ROM offsets, entry points, RAM addresses and sizes
+    words(&mut rom, 0x20, &[0x4000, 0x0200_0000, 0x0200_0000, 0x1000, 0x5000, 0x0238_0000, 0x0238_0000, 0x100]);
+    words(&mut rom, 0x4000, arm9);
+    words(&mut rom, 0x5000, arm7);
+    rom
+}
+
+// A deterministic DS with zeroed BIOSes and no firmware
+pub fn nds(rom: Vec<u8>) -> NDS {
+    let files = SystemFiles { bios7: vec![0; 0x4000], bios9: vec![0; 0x1000], firmware: None };
+    let mut nds = NDS::new(files, rom, Box::new(MemoryStorage::new(None)), Box::new(SampleQueue::new(48000)), true,
+        ConsoleModel::DS).unwrap();
+    nds.set_deterministic(true);
+    nds
+}
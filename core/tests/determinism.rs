@@ -0,0 +1,13 @@
+mod common;
+
+// The ARM9 counts up into the backdrop color while the ARM7 waits
+fn run(frames: u64) -> (u32, u32, Vec<u8>) {
+    let mut nds = common::nds(common::rom(&common::ARM9_COUNTER, &[0xEAFF_FFFE])); // b 0x02380000
+    let hashes = nds.run_hashed(frames).unwrap();
+    (hashes.video, hashes.audio, nds.save_state())
+}
+
+#[test]
+fn replays_identically() {
+    assert!(run(20) == run(20));
+}
@@ -0,0 +1,186 @@
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use ringbuf::RingBuffer;
+
+use nds_core::log::error;
+use nds_core::nds::AudioSink;
+use serde::Deserialize;
+
+pub struct Audio {
+    config: cpal::StreamConfig,
+    // Streams can't be sent between threads, so each one plays on its own thread until this is dropped
+    _stream: Sender<()>,
+    prod: ringbuf::Producer<[f32; 2]>,
+    settings: Arc<AudioSettings>,
+    segment: Vec<[f32; 2]>,
+    overlap: Vec<[f32; 2]>,
+}
+
+impl Audio {
+    const MIN_BUFFER_LEN: usize = 1024;
+    const SEGMENT_LEN: usize = 512;
+    const OVERLAP_LEN: usize = 64;
+    // Per sample decay when the buffer runs dry, e.g. while paused, so the output doesn't click
+    const FADE_OUT: f32 = 0.995;
+
+    pub fn new(settings: Arc<AudioSettings>, buffer_len: usize) -> Self {
+        let host = cpal::default_host();
+        let device = host.default_output_device().expect("No audio output device available!");
+        let config = device.default_output_config().expect("No audio output config available!");
+
+        match config.sample_format() {
+            cpal::SampleFormat::F32 => Audio::init::<f32>(device, config.into(), settings, buffer_len),
+            cpal::SampleFormat::I16 => Audio::init::<i16>(device, config.into(), settings, buffer_len),
+            cpal::SampleFormat::U16 => Audio::init::<u16>(device, config.into(), settings, buffer_len),
+        }
+    }
+
+    fn init<T: cpal::Sample + Send + 'static>(device: cpal::Device, config: cpal::StreamConfig,
+        settings: Arc<AudioSettings>, buffer_len: usize) -> Self {
+        let buffer = RingBuffer::<[f32; 2]>::new(buffer_len.max(Audio::MIN_BUFFER_LEN));
+        let (prod, mut cons) = buffer.split();
+
+        let output_config = OutputConfig::from(config.channels);
+        let stream_config = config.clone();
+        let (stop, stopped) = mpsc::channel::<()>();
+        thread::spawn(move || {
+            let mut last_samples = [0.0, 0.0];
+            let stream = device.build_output_stream(
+                &stream_config,
+                move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+                    for frame in data.chunks_mut(output_config as usize) {
+                        let samples = cons.pop()
+                            .unwrap_or_else(|| [last_samples[0] * Audio::FADE_OUT, last_samples[1] * Audio::FADE_OUT]);
+                        last_samples = samples;
+                        match output_config {
+                            OutputConfig::Mono => {
+                                let sample = samples.iter().sum::<f32>() / 2.0;
+                                frame[0] = cpal::Sample::from::<f32>(&sample);
+                            },
+                            OutputConfig::Stereo => {
+                                frame[0] = cpal::Sample::from::<f32>(&(samples[0]));
+                                frame[1] = cpal::Sample::from::<f32>(&(samples[1]));
+                            },
+                        }
+                    }
+                },
+                |err| error!("Audio Stream Error: {}", err),
+            ).unwrap();
+            stream.play().unwrap();
+            // Only returns once the sender is dropped
+            stopped.recv().ok();
+        });
+
+        Audio {
+            config,
+            _stream: stop,
+            prod,
+            settings,
+            segment: Vec::with_capacity(Audio::SEGMENT_LEN + Audio::OVERLAP_LEN),
+            overlap: Vec::new(),
+        }
+    }
+
+    // Audio is cut into segments and only as many as the device keeps up with are played, crossfading
+    // between them so the skipped audio doesn't change the pitch
+    fn push_turbo_sample(&mut self, sample: [f32; 2]) {
+        self.segment.push(sample);
+        if self.segment.len() < Audio::SEGMENT_LEN + Audio::OVERLAP_LEN { return }
+        if self.prod.len() < self.prod.capacity() / 2 {
+            for (i, sample) in self.segment[..Audio::SEGMENT_LEN].iter().enumerate() {
+                let sample = match self.overlap.get(i) {
+                    Some(prev_sample) => {
+                        let t = i as f32 / Audio::OVERLAP_LEN as f32;
+                        [prev_sample[0] * (1.0 - t) + sample[0] * t, prev_sample[1] * (1.0 - t) + sample[1] * t]
+                    },
+                    None => *sample,
+                };
+                self.prod.push(sample).ok();
+            }
+            self.overlap.clear();
+            self.overlap.extend_from_slice(&self.segment[Audio::SEGMENT_LEN..]);
+        }
+        self.segment.clear();
+    }
+}
+
+impl AudioSink for Audio {
+    fn push_samples(&mut self, samples: &[[f32; 2]]) {
+        let volume = *self.settings.volume.lock().unwrap();
+        let samples = samples.iter().map(|sample| [sample[0] * volume, sample[1] * volume]);
+        let turbo = *self.settings.turbo.lock().unwrap();
+        match turbo {
+            // The frame limiter keeps emulation in time, so anything that doesn't fit is dropped instead of waited on
+            None => {
+                self.segment.clear();
+                for sample in samples {
+                    if self.prod.push(sample).is_err() { break }
+                }
+            },
+            Some(TurboAudio::Mute) => (),
+            Some(TurboAudio::PitchPreserving) => for sample in samples { self.push_turbo_sample(sample) },
+        }
+    }
+
+    fn sample_rate(&self) -> usize {
+        self.config.sample_rate.0 as usize
+    }
+
+    fn buffer_fill(&self) -> Option<f64> {
+        Some(self.prod.len() as f64 / self.prod.capacity() as f64)
+    }
+}
+
+// Discards all audio, for consoles that run alongside the one being heard
+pub struct Muted;
+
+impl AudioSink for Muted {
+    fn push_samples(&mut self, _samples: &[[f32; 2]]) {}
+
+    fn sample_rate(&self) -> usize {
+        48000
+    }
+}
+
+// Shared with the frontend so changes apply while the stream is running
+pub struct AudioSettings {
+    pub volume: Mutex<f32>,
+    // Set while emulation runs faster than real time
+    pub turbo: Mutex<Option<TurboAudio>>,
+}
+
+impl AudioSettings {
+    pub fn new() -> Self {
+        AudioSettings {
+            volume: Mutex::new(1.0),
+            turbo: Mutex::new(None),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TurboAudio {
+    Mute,
+    PitchPreserving,
+}
+
+#[derive(Clone, Copy)]
+enum OutputConfig {
+    Mono = 1,
+    Stereo = 2,
+}
+
+impl From<u16> for OutputConfig {
+    fn from(value: u16) -> Self {
+        use OutputConfig::*;
+        match value {
+            1 => Mono,
+            2 => Stereo,
+            _ => panic!("Only Mono and Stereo audio devices supported!"),
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use std::collections::BTreeMap;
+use std::time::Instant;
+
+use nds_core::log::error;
+use nds_core::nds::{NDS, EventKind};
+use serde::Serialize;
+
+// Printed as a single line of JSON so runs can be appended to a file and compared over time
+#[derive(Serialize)]
+pub struct Report {
+    rom: String,
+    frames: u64,
+    seconds: f64,
+    fps: f64,
+    // Seconds spent in each subsystem's events, with everything outside of them counted as CPU
+    subsystems: BTreeMap<&'static str, f64>,
+}
+
+pub fn run(nds: &mut NDS, rom: String, frames: u64) -> Report {
+    nds.set_profiling(true);
+    nds.reset_event_stats();
+    let start = Instant::now();
+    let mut frames_ran = 0;
+    while frames_ran < frames && !nds.powered_off() {
+        if let Err(fault) = nds.run_frame() { error!("Emulation Stopped: {}!", fault); break }
+        frames_ran += 1;
+    }
+    let seconds = start.elapsed().as_secs_f64();
+    nds.set_profiling(false);
+
+    let mut subsystems = BTreeMap::new();
+    for (kind, stats) in nds.event_stats() {
+        *subsystems.entry(subsystem(kind)).or_insert(0.0) += stats.nanos as f64 / 1e9;
+    }
+    let events_seconds: f64 = subsystems.values().sum();
+    subsystems.insert("CPU", seconds - events_seconds);
+    Report { rom, frames: frames_ran, seconds, fps: frames_ran as f64 / seconds, subsystems }
+}
+
+fn subsystem(kind: EventKind) -> &'static str {
+    match kind {
+        EventKind::StartNextLine | EventKind::HBlank | EventKind::VBlank | EventKind::CheckGeometryCommandFIFO => "GPU",
+        EventKind::GenerateAudioSample | EventKind::StepAudioChannel => "SPU",
+        EventKind::DMA | EventKind::NDMA => "DMA",
+        EventKind::TimerOverflow => "Timers",
+        EventKind::ROMWordTransfered | EventKind::ROMBlockEnded | EventKind::AUXSPITransferFinished => "Cartridge",
+        EventKind::SPITransferFinished | EventKind::RTCTick => "SPI",
+        EventKind::WiFiPoll | EventKind::WiFiUSCompare | EventKind::WiFiPreBeacon |
+            EventKind::WiFiTransferFinished => "WiFi",
+        EventKind::Count => unreachable!(),
+    }
+}
@@ -0,0 +1,167 @@
+use std::path::PathBuf;
+
+use clap::{Parser, ValueEnum};
+use nds_core::netplay::NetplayMode;
+use nds_core::nds::Cpu;
+use nds_core::trace::TraceLevel;
+
+use crate::config::Config;
+
+// Options given here take precedence over the config file
+#[derive(Parser)]
+#[command(about = "A NDS Emulator in Rust")]
+pub struct Args {
+    /// ROM to load instead of the one in the config file
+    pub rom: Option<PathBuf>,
+    #[arg(long)]
+    pub bios7: Option<PathBuf>,
+    #[arg(long)]
+    pub bios9: Option<PathBuf>,
+    #[arg(long)]
+    pub firmware: Option<PathBuf>,
+    /// Skip the firmware boot sequence, pass false to boot through the firmware
+    #[arg(long, value_name = "BOOL", num_args = 0..=1, default_missing_value = "true")]
+    pub direct_boot: Option<bool>,
+    #[arg(long)]
+    pub fullscreen: bool,
+    /// RetroAchievements hardcore mode, which disables loading save states, rewind, frame advance, slowdown and cheats
+    #[arg(long)]
+    pub hardcore: bool,
+    /// Initial window size as a multiple of the screen size
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..=8))]
+    pub scale: u32,
+    /// Save state to load once the ROM has booted
+    #[arg(long, value_name = "FILE")]
+    pub savestate: Option<PathBuf>,
+    /// Record video and audio to an AVI file from the first frame
+    #[arg(long, visible_alias = "record-movie", value_name = "FILE")]
+    pub record_video: Option<PathBuf>,
+    /// Run this many frames without a window or audio output and exit
+    #[arg(long, value_name = "N")]
+    pub headless_frames: Option<u64>,
+    /// Run this many frames as fast as possible without a window or audio output and print timings as JSON
+    #[arg(long, value_name = "N")]
+    pub bench: Option<u64>,
+    /// Run the test ROMs listed in a manifest without a window and report which pass, exiting with an error if any fail
+    #[arg(long, value_name = "MANIFEST")]
+    pub test_roms: Option<PathBuf>,
+    /// Compare the screens and audio of the runs in a corpus against their stored hashes, exiting with an error on a mismatch
+    #[arg(long, value_name = "CORPUS")]
+    pub golden: Option<PathBuf>,
+    /// Store the hashes from this build in the golden corpus instead of comparing them
+    #[arg(long, requires = "golden")]
+    pub update_golden: bool,
+    /// Run the ROM twice for this many frames in deterministic mode and check that both runs match
+    #[arg(long, value_name = "N")]
+    pub check_determinism: Option<u64>,
+    /// List the sections of a save state and check that it loads into the ROM, exiting with an error if it doesn't
+    #[arg(long, value_name = "STATE")]
+    pub check_state: Option<PathBuf>,
+    /// Host a netplay session on this port, where each player controls one of two consoles linked by local wireless.
+    /// Both players need the same ROM, BIOS, firmware and saves, with the other player's save as <game>.2.sav
+    #[arg(long, value_name = "PORT", num_args = 0..=1, default_missing_value = "7080", conflicts_with = "netplay_connect")]
+    pub netplay_host: Option<u16>,
+    /// Join the netplay session hosted at this address, which uses the host's mode and delay
+    #[arg(long, value_name = "HOST:PORT")]
+    pub netplay_connect: Option<String>,
+    #[arg(long, value_enum, default_value_t = NetplayArg::Rollback)]
+    pub netplay_mode: NetplayArg,
+    /// Frames of input delay, which rollback needs less of
+    #[arg(long, value_name = "FRAMES", default_value_t = 1)]
+    pub netplay_delay: u8,
+    /// ELF with function names to show in the call stack
+    #[arg(long, value_name = "ELF")]
+    pub symbols: Option<PathBuf>,
+    /// Write a trace of the CPU state and key IO registers to compare with other emulators
+    #[arg(long, value_name = "FILE")]
+    pub trace: Option<PathBuf>,
+    /// arm9 and arm7 only write that CPU's instructions, as "PC: opcode r0 ... r15 cpsr" lines like other emulators
+    #[arg(long, value_enum, default_value_t = TraceArg::Frame)]
+    pub trace_level: TraceArg,
+    /// Print the first line where two traces differ and exit
+    #[arg(long, num_args = 2, value_names = ["EXPECTED", "ACTUAL"])]
+    pub compare_traces: Option<Vec<PathBuf>>,
+    /// Lines of an expected trace from another emulator, with fields like %PC%, %OP%, %R0% and %CPSR%
+    #[arg(long, value_name = "TEMPLATE", requires = "compare_traces")]
+    pub trace_format: Option<String>,
+    /// CPU the other emulator's trace is of, if its format has no %CPU% field
+    #[arg(long, value_enum, requires = "trace_format")]
+    pub trace_cpu: Option<CpuArg>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum CpuArg {
+    Arm7,
+    Arm9,
+}
+
+impl From<CpuArg> for Cpu {
+    fn from(arg: CpuArg) -> Self {
+        match arg {
+            CpuArg::Arm7 => Cpu::ARM7,
+            CpuArg::Arm9 => Cpu::ARM9,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum TraceArg {
+    Frame,
+    Instruction,
+    Arm9,
+    Arm7,
+}
+
+impl From<TraceArg> for TraceLevel {
+    fn from(arg: TraceArg) -> Self {
+        match arg {
+            TraceArg::Frame => TraceLevel::Frame,
+            TraceArg::Instruction => TraceLevel::Instruction,
+            TraceArg::Arm9 => TraceLevel::Cpu(Cpu::ARM9),
+            TraceArg::Arm7 => TraceLevel::Cpu(Cpu::ARM7),
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum NetplayArg {
+    Delay,
+    Rollback,
+}
+
+impl From<NetplayArg> for NetplayMode {
+    fn from(arg: NetplayArg) -> Self {
+        match arg {
+            NetplayArg::Delay => NetplayMode::Delay,
+            NetplayArg::Rollback => NetplayMode::Rollback,
+        }
+    }
+}
+
+impl Args {
+    // Paths are made absolute since the working directory changes before they're used
+    pub fn parse_args() -> Self {
+        let mut args = Args::parse();
+        let cwd = std::env::current_dir().unwrap_or_default();
+        for path in [&mut args.rom, &mut args.bios7, &mut args.bios9, &mut args.firmware, &mut args.savestate,
+            &mut args.record_video, &mut args.trace, &mut args.test_roms, &mut args.golden,
+            &mut args.check_state, &mut args.symbols].iter_mut() {
+            if let Some(path) = path.as_mut() { *path = cwd.join(path.as_path()) }
+        }
+        for path in args.compare_traces.iter_mut().flatten() { *path = cwd.join(path.as_path()) }
+        args
+    }
+
+    pub fn netplay(&self) -> bool {
+        self.netplay_host.is_some() || self.netplay_connect.is_some()
+    }
+
+    pub fn apply(&self, config: &mut Config) {
+        if let Some(rom) = &self.rom { config.paths.rom = rom.clone() }
+        if let Some(bios7) = &self.bios7 { config.paths.bios7 = bios7.clone() }
+        if let Some(bios9) = &self.bios9 { config.paths.bios9 = bios9.clone() }
+        if let Some(firmware) = &self.firmware { config.paths.firmware = firmware.clone() }
+        if let Some(direct_boot) = self.direct_boot { config.emulation.direct_boot = direct_boot }
+        if self.hardcore { config.emulation.hardcore = true }
+    }
+}
@@ -0,0 +1,322 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use nds_core::log::*;
+use nds_core::logging;
+use nds_core::nds::{ConsoleModel, OpenBus};
+use nds_core::screenshot::Layout;
+use serde::Deserialize;
+
+use crate::audio::TurboAudio;
+use crate::filter::Filter;
+use crate::input::BindingsConfig;
+use crate::layout::{Arrangement, Rotation, ScreenLayout};
+use crate::limiter::Speed;
+
+// Every field has a default, so the file only needs the settings that differ
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub paths: PathsConfig,
+    pub video: VideoConfig,
+    pub audio: AudioConfig,
+    pub emulation: EmulationConfig,
+    pub input: BindingsConfig,
+    pub logging: LoggingConfig,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Self {
+        if !path.exists() { return Config::default() }
+        match fs::read_to_string(path).map_err(|err| err.to_string())
+            .and_then(|contents| toml::from_str(&contents).map_err(|err| err.to_string())) {
+            Ok(config) => config,
+            Err(err) => { error!("Unable to Load {}: {}!", path.display(), err); Config::default() },
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct PathsConfig {
+    pub bios7: PathBuf,
+    pub bios9: PathBuf,
+    pub firmware: PathBuf,
+    pub gba_bios: PathBuf,
+    // Only used by the DSi model
+    pub nand: PathBuf,
+    pub rom: PathBuf,
+    // Directories for battery saves, save states, recordings and texture packs. Empty paths keep them next to the ROM.
+    pub saves: PathBuf,
+    pub states: PathBuf,
+    pub recordings: PathBuf,
+    pub textures: PathBuf,
+}
+
+impl PathsConfig {
+    // Path of a file belonging to the ROM in dir, which still needs its extension set
+    pub fn in_dir(dir: &Path, rom_path: &Path) -> PathBuf {
+        if dir.as_os_str().is_empty() { return rom_path.to_path_buf() }
+        if !dir.exists() {
+            fs::create_dir_all(dir).unwrap_or_else(|err| error!("Unable to Create {}: {}!", dir.display(), err));
+        }
+        dir.join(rom_path.file_name().unwrap_or_default())
+    }
+}
+
+impl Default for PathsConfig {
+    fn default() -> Self {
+        PathsConfig {
+            bios7: PathBuf::from("bios7.bin"),
+            bios9: PathBuf::from("bios9.bin"),
+            firmware: PathBuf::from("firmware.bin"),
+            gba_bios: PathBuf::from("gba_bios.bin"),
+            nand: PathBuf::from("nand.bin"),
+            rom: PathBuf::from("examples/3D/BoxTest.nds"),
+            saves: PathBuf::new(),
+            states: PathBuf::new(),
+            recordings: PathBuf::new(),
+            textures: PathBuf::new(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct VideoConfig {
+    // nearest, bilinear or xbr
+    pub filter: String,
+    pub integer_scaling: bool,
+    pub color_correction: bool,
+    pub show_fps: bool,
+    // vertical, horizontal or single
+    pub layout: String,
+    // Clockwise degrees: 0, 90 or 270
+    pub rotation: u32,
+    pub screen_gap: usize,
+    pub swap_screens: bool,
+    // top, bottom, vertical or horizontal
+    pub screenshot_layout: String,
+    // Frames skipped after each drawn frame, or the most skipped when auto_frameskip is on
+    pub frameskip: usize,
+    // Only skips frames while the emulator can't keep up or is fast-forwarding
+    pub auto_frameskip: bool,
+    // 3D textures are dumped to <textures>/<game>/dump and replaced from <textures>/<game>/replace as PNGs named after
+    // their hash. Replacements can be any multiple of the original size.
+    pub dump_textures: bool,
+    pub replace_textures: bool,
+}
+
+impl VideoConfig {
+    pub fn filter(&self) -> Filter {
+        match self.filter.as_str() {
+            "nearest" => Filter::Nearest,
+            "bilinear" => Filter::Bilinear,
+            "xbr" => Filter::Xbr,
+            filter => { warn!("Unknown Filter: {}", filter); Filter::Nearest },
+        }
+    }
+
+    pub fn screen_layout(&self) -> ScreenLayout {
+        ScreenLayout {
+            arrangement: match self.layout.as_str() {
+                "vertical" => Arrangement::Vertical,
+                "horizontal" => Arrangement::Horizontal,
+                "single" => Arrangement::Single,
+                layout => { warn!("Unknown Screen Layout: {}", layout); Arrangement::Vertical },
+            },
+            rotation: match self.rotation {
+                0 => Rotation::None,
+                90 => Rotation::Deg90,
+                270 => Rotation::Deg270,
+                rotation => { warn!("Unsupported Rotation: {}", rotation); Rotation::None },
+            },
+            gap: self.screen_gap.min(ScreenLayout::MAX_GAP),
+            swapped: self.swap_screens,
+        }
+    }
+
+    pub fn screenshot_layout(&self) -> Layout {
+        match self.screenshot_layout.as_str() {
+            "top" => Layout::Top,
+            "bottom" => Layout::Bottom,
+            "vertical" => Layout::Vertical,
+            "horizontal" => Layout::Horizontal,
+            layout => { warn!("Unknown Screenshot Layout: {}", layout); Layout::Vertical },
+        }
+    }
+}
+
+impl Default for VideoConfig {
+    fn default() -> Self {
+        VideoConfig {
+            filter: "nearest".to_string(),
+            integer_scaling: false,
+            color_correction: false,
+            show_fps: false,
+            layout: "vertical".to_string(),
+            rotation: 0,
+            screen_gap: 0,
+            swap_screens: false,
+            screenshot_layout: "vertical".to_string(),
+            frameskip: 0,
+            auto_frameskip: false,
+            dump_textures: false,
+            replace_textures: false,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct AudioConfig {
+    pub volume: f32,
+    // Samples buffered for the output device, only applied when a ROM is loaded
+    pub buffer_len: usize,
+    pub fast_forward: TurboAudio,
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        AudioConfig {
+            volume: 1.0,
+            buffer_len: 2048,
+            fast_forward: TurboAudio::PitchPreserving,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct EmulationConfig {
+    // Percentages of full speed, where 0 is unlimited
+    pub speed: u32,
+    pub fast_forward_speed: u32,
+    pub fixed_rtc: bool,
+    // Ignores the host clock, network and microphone so runs can be reproduced exactly
+    pub deterministic: bool,
+    // Only applied when a ROM is loaded
+    pub direct_boot: bool,
+    // RetroAchievements hardcore mode, which turns off save state loading, rewind, frame advance, slowdown and memory
+    // writes. Turning it on only takes effect when a ROM is loaded.
+    pub hardcore: bool,
+    // What reads from an empty GBA slot return: pattern, zeros or ones
+    pub slot2_open_bus: String,
+    // ds or dsi, only applied when a ROM is loaded
+    pub console_model: String,
+}
+
+impl EmulationConfig {
+    pub fn speed(&self) -> Speed { EmulationConfig::to_speed(self.speed) }
+
+    pub fn fast_forward_speed(&self) -> Speed { EmulationConfig::to_speed(self.fast_forward_speed) }
+
+    fn to_speed(percent: u32) -> Speed {
+        if percent == 0 { Speed::Unlimited } else { Speed::Percent(percent) }
+    }
+
+    pub fn slot2_open_bus(&self) -> OpenBus {
+        match self.slot2_open_bus.as_str() {
+            "pattern" => OpenBus::Pattern,
+            "zeros" => OpenBus::Zeros,
+            "ones" => OpenBus::Ones,
+            open_bus => { warn!("Unknown Slot-2 Open Bus: {}", open_bus); OpenBus::Pattern },
+        }
+    }
+
+    pub fn console_model(&self) -> ConsoleModel {
+        match self.console_model.as_str() {
+            "ds" => ConsoleModel::DS,
+            "dsi" => ConsoleModel::DSi,
+            model => { warn!("Unknown Console Model: {}", model); ConsoleModel::DS },
+        }
+    }
+}
+
+impl Default for EmulationConfig {
+    fn default() -> Self {
+        EmulationConfig {
+            speed: 100,
+            fast_forward_speed: 0,
+            fixed_rtc: false,
+            deterministic: false,
+            direct_boot: true,
+            hardcore: false,
+            slot2_open_bus: "pattern".to_string(),
+            console_model: "ds".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct LoggingConfig {
+    // One of off, error, warn, info, debug or trace
+    pub level: String,
+    // Levels for module path prefixes, e.g. "nds_core::hw::spu" = "trace"
+    pub modules: BTreeMap<String, String>,
+    // Empty to only log to the terminal
+    pub file: PathBuf,
+    // Repeated warnings from the same line are dropped after this many, 0 keeps all of them
+    pub repeat_limit: usize,
+}
+
+impl LoggingConfig {
+    pub fn apply(&self) {
+        let parse = |level: &str| level.parse::<LevelFilter>().map_err(|_| warn!("Invalid Log Level: {}!", level)).ok();
+        if let Some(level) = parse(&self.level) { logging::set_level(level) }
+        logging::clear_module_levels();
+        for (module, level) in self.modules.iter() {
+            if let Some(level) = parse(level) { logging::set_module_level(module, level) }
+        }
+        let file = if self.file.as_os_str().is_empty() { None } else { Some(self.file.as_path()) };
+        if let Err(err) = logging::set_file(file) { error!("Unable to Open {}: {}!", self.file.display(), err) }
+        logging::set_repeat_limit(self.repeat_limit);
+    }
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        LoggingConfig {
+            level: "warn".to_string(),
+            modules: BTreeMap::new(),
+            file: PathBuf::new(),
+            repeat_limit: 10,
+        }
+    }
+}
+
+// Reloads the config whenever the file is modified
+pub struct ConfigWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    last_check: Instant,
+}
+
+impl ConfigWatcher {
+    const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+    pub fn new(path: &Path) -> Self {
+        ConfigWatcher {
+            path: path.to_path_buf(),
+            modified: ConfigWatcher::modified(path),
+            last_check: Instant::now(),
+        }
+    }
+
+    fn modified(path: &Path) -> Option<SystemTime> {
+        fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+    }
+
+    pub fn poll(&mut self) -> Option<Config> {
+        if self.last_check.elapsed() < ConfigWatcher::CHECK_INTERVAL { return None }
+        self.last_check = Instant::now();
+        let modified = ConfigWatcher::modified(&self.path);
+        if modified == self.modified { return None }
+        self.modified = modified;
+        info!("Reloading {}", self.path.display());
+        Some(Config::load(&self.path))
+    }
+}
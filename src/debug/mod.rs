@@ -6,7 +6,7 @@ use imgui::*;
 use glfw::Key;
 
 pub use windows::*;
-use super::{Engine, GraphicsType, NDS};
+use super::{CapturedPolygon, Engine, GraphicsType, NDS};
 
 pub struct DebugWindow<S> where S: DebugWindowState {
     title: ImString,
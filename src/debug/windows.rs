@@ -1,10 +1,16 @@
 use std::borrow::Cow;
 use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
 use std::time::Instant;
 
 use imgui::*;
 
-use super::{DebugWindowState, Engine, GraphicsType, NDS};
+use nds_core::nds::{self, Cpu};
+use nds_core::symbols::Symbols;
+
+use super::{CapturedPolygon, DebugWindowState, Engine, GraphicsType, NDS, Texture};
 
 pub struct PalettesWindowState {
     palettes_extended: bool,
@@ -93,11 +99,12 @@ pub struct TilesWindowState {
     tiles_bpp8: bool,
     tiles_slot: u32,
     tiles_palette: u32,
-    tiles_offset: u32,
+    tiles_base: u32,
 }
 
 impl TilesWindowState {
-    const TILES_RANGES: [std::ops::RangeInclusive<u32>; 2] = [0 as u32..=3, 0 as u32..=1];
+    // In 16 KB blocks
+    const BASE_RANGES: [[std::ops::RangeInclusive<u32>; 2]; 2] = [[0 as u32..=31, 0 as u32..=15], [0 as u32..=7, 0 as u32..=7]];
 }
 
 impl DebugWindowState for TilesWindowState {
@@ -110,7 +117,7 @@ impl DebugWindowState for TilesWindowState {
             tiles_bpp8: false,
             tiles_slot: 0,
             tiles_palette: 0,
-            tiles_offset: 0,
+            tiles_base: 0,
         }
     }
 
@@ -147,16 +154,18 @@ impl DebugWindowState for TilesWindowState {
             }
 
         }
-        if Self::ENGINES[self.tiles_engine] == Engine::A {
-            Slider::new(im_str!("Offset")).range(Self::TILES_RANGES[self.tiles_graphics_type].clone())
-            .build(ui, &mut self.tiles_offset);
-        }
+        let range = Self::BASE_RANGES[self.tiles_engine][self.tiles_graphics_type].clone();
+        self.tiles_base = self.tiles_base.min(*range.end());
+        Slider::new(im_str!("Base")).range(range)
+        .build(ui, &mut self.tiles_base);
+        ui.same_line(0.0);
+        ui.text(format!("0x{:05X}", self.tiles_base * 0x4000));
     }
 
     fn get_pixels(&self, nds: &mut NDS) -> (Vec<u16>, usize, usize) {
         nds.render_tiles(Self::ENGINES[self.tiles_engine], Self::GRAPHICS_TYPES[self.tiles_graphics_type],
             self.tiles_extended, self.tiles_bitmap, self.tiles_bpp8, self.tiles_slot as usize,
-            self.tiles_palette as usize, self.tiles_offset as usize)
+            self.tiles_palette as usize, self.tiles_base as usize)
     }
 }
 
@@ -233,3 +242,253 @@ impl StatsWindow {
         if clicked { self.opened = !self.opened }
     }
 }
+
+pub struct AudioChannelsWindow {
+    opened: bool,
+}
+
+impl AudioChannelsWindow {
+    pub fn new() -> Self {
+        AudioChannelsWindow {
+            opened: false,
+        }
+    }
+
+    pub fn render(&mut self, nds: &mut NDS, ui: &Ui) {
+        if !self.opened { return }
+        let mut opened = self.opened;
+        Window::new(im_str!("Audio Channels"))
+        .always_auto_resize(true)
+        .opened(&mut opened)
+        .build(ui, || {
+            for num in 0..nds::AUDIO_CHANNELS {
+                let state = match nds.audio_channel_state(num) {
+                    Some(state) => state,
+                    None => continue,
+                };
+                let (mut muted, mut soloed) = (state.muted, state.soloed);
+                if ui.checkbox(&im_str!("Mute##{}", num), &mut muted) { nds.set_audio_channel_muted(num, muted) }
+                ui.same_line(0.0);
+                if ui.checkbox(&im_str!("Solo##{}", num), &mut soloed) { nds.set_audio_channel_soloed(num, soloed) }
+                ui.same_line(0.0);
+                ui.text(format!("Ch {:2}: {} {:?} {:6} Hz Vol {:3} Pan {:3} Sample {:6}",
+                    num, if state.busy { "On " } else { "Off" }, state.format, state.frequency,
+                    state.volume, state.pan, state.sample));
+            }
+        });
+        self.opened = opened;
+    }
+
+    pub fn menu_item(&mut self, ui: &Ui) {
+        let clicked = MenuItem::new(im_str!("Audio Channels")).selected(self.opened).build(ui);
+        if clicked { self.opened = !self.opened }
+    }
+}
+
+pub struct OAMWindow {
+    opened: bool,
+    engine: usize,
+    obj: u32,
+    texture: Texture,
+}
+
+impl OAMWindow {
+    const ENGINES: [Engine; 2] = [Engine::A, Engine::B];
+    const SCALE: f32 = 2.0;
+
+    pub fn new() -> Self {
+        OAMWindow {
+            opened: false,
+            engine: 0,
+            obj: 0,
+            texture: Texture::new(),
+        }
+    }
+
+    pub fn render(&mut self, nds: &mut NDS, ui: &Ui) {
+        if !self.opened { return }
+        let mut entries = nds.oam_entries(Self::ENGINES[self.engine]);
+        let entry = &mut entries[self.obj as usize];
+        let visible = entry.width != 0;
+        if visible { self.texture.update_pixels(std::mem::take(&mut entry.pixels), entry.width, entry.height) }
+        let mut opened = self.opened;
+        Window::new(im_str!("OAM"))
+        .always_auto_resize(true)
+        .opened(&mut opened)
+        .build(ui, || {
+            ui.set_next_item_width(ui.window_size()[0] * 0.3);
+            ComboBox::new(im_str!("Engine"))
+            .build_simple(ui, &mut self.engine,
+            &Self::ENGINES, &(|i| Cow::from(ImString::new(i.label()))));
+            Slider::new(im_str!("OBJ")).range(0 as u32..=127)
+            .build(ui, &mut self.obj);
+
+            ui.text(format!("Position: ({}, {}) Size: {}x{}", entry.x, entry.y, entry.width, entry.height));
+            ui.text(format!("Tile: 0x{:03X} Palette: {} Priority: {}", entry.tile, entry.palette, entry.priority));
+            ui.text(format!("Mode: {:?} {} Mosaic: {}", entry.mode, if entry.bpp8 { "256 Colors" } else { "16 Colors" },
+                entry.mosaic));
+            if entry.affine {
+                ui.text(format!("Affine Param: {} Double Size: {}", entry.affine_param, entry.double_size));
+            } else {
+                ui.text(format!("Flip: {} {} Disabled: {}", if entry.flip_x { "X" } else { "-" },
+                    if entry.flip_y { "Y" } else { "-" }, entry.disabled));
+            }
+            if visible { self.texture.render(Self::SCALE).build(ui) }
+        });
+        self.opened = opened;
+    }
+
+    pub fn menu_item(&mut self, ui: &Ui) {
+        let clicked = MenuItem::new(im_str!("OAM")).selected(self.opened).build(ui);
+        if clicked { self.opened = !self.opened }
+    }
+}
+
+pub struct PolygonsWindow {
+    opened: bool,
+    wireframe: bool,
+    polygons: Vec<CapturedPolygon>,
+    polygon: u32,
+}
+
+impl PolygonsWindow {
+    pub fn new() -> Self {
+        PolygonsWindow {
+            opened: false,
+            wireframe: false,
+            polygons: Vec::new(),
+            polygon: 0,
+        }
+    }
+
+    // Returns whether the captured polygons should be exported
+    pub fn render(&mut self, nds: &mut NDS, ui: &Ui) -> bool {
+        if let Some(polygons) = nds.take_3d_polygons() {
+            self.polygons = polygons;
+            self.polygon = 0;
+        }
+        if !self.opened { return false }
+        let mut opened = self.opened;
+        let mut export = false;
+        Window::new(im_str!("3D Polygons"))
+        .always_auto_resize(true)
+        .opened(&mut opened)
+        .build(ui, || {
+            if ui.checkbox(im_str!("Wireframe"), &mut self.wireframe) { nds.set_3d_wireframe(self.wireframe) }
+            if ui.button(im_str!("Capture Frame"), [0.0, 0.0]) { nds.capture_3d_polygons() }
+            if self.polygons.is_empty() { return }
+            ui.same_line(0.0);
+            export = ui.button(im_str!("Export"), [0.0, 0.0]);
+
+            ui.text(format!("{} Polygons", self.polygons.len()));
+            Slider::new(im_str!("Polygon")).range(0 as u32..=self.polygons.len() as u32 - 1)
+            .build(ui, &mut self.polygon);
+            let polygon = &self.polygons[self.polygon as usize];
+            ui.text(format!("Mode: {:?} Alpha: {} ID: {} {}", polygon.mode, polygon.alpha, polygon.polygon_id,
+                if polygon.is_front { "Front" } else { "Back" }));
+            ui.text(format!("Texture: {:?} at 0x{:05X} {}x{} Palette: 0x{:05X}", polygon.tex_format, polygon.tex_addr,
+                polygon.tex_size[0], polygon.tex_size[1], polygon.palette_base));
+            for (i, vert) in polygon.vertices.iter().enumerate() {
+                ui.text(format!("V{}: ({:3}, {:3}) Depth: 0x{:06X} Color: {:?} Tex: ({}, {})", i,
+                    vert.screen_coords[0], vert.screen_coords[1], vert.depth, vert.color,
+                    vert.tex_coord[0] >> 4, vert.tex_coord[1] >> 4));
+            }
+        });
+        self.opened = opened;
+        export
+    }
+
+    pub fn export(&self, path: &Path) -> io::Result<()> {
+        CapturedPolygon::write_obj(&self.polygons, BufWriter::new(File::create(path)?))
+    }
+
+    pub fn menu_item(&mut self, ui: &Ui) {
+        let clicked = MenuItem::new(im_str!("3D Polygons")).selected(self.opened).build(ui);
+        if clicked { self.opened = !self.opened }
+    }
+}
+
+pub struct SchedulerWindow {
+    opened: bool,
+}
+
+impl SchedulerWindow {
+    pub fn new() -> Self {
+        SchedulerWindow {
+            opened: false,
+        }
+    }
+
+    pub fn render(&mut self, nds: &mut NDS, ui: &Ui) {
+        if !self.opened { return }
+        let mut opened = self.opened;
+        Window::new(im_str!("Scheduler"))
+        .always_auto_resize(true)
+        .opened(&mut opened)
+        .build(ui, || {
+            ui.text("Pending Events");
+            for event in nds.pending_events() {
+                ui.text(format!("{:10} ({:+8}) {}", event.cycle, event.delta, event.description));
+            }
+            ui.separator();
+            ui.text("Statistics");
+            ui.same_line(0.0);
+            if ui.small_button(im_str!("Reset")) { nds.reset_event_stats() }
+            ui.text(format!("{:24} {:>10} {:>10} {:>10} {:>10}", "Event", "Scheduled", "Replaced", "Removed", "Handled"));
+            for (kind, stats) in nds.event_stats() {
+                ui.text(format!("{:24} {:10} {:10} {:10} {:10}", format!("{:?}", kind), stats.scheduled, stats.replaced,
+                    stats.removed, stats.handled));
+            }
+        });
+        self.opened = opened;
+    }
+
+    pub fn menu_item(&mut self, ui: &Ui) {
+        let clicked = MenuItem::new(im_str!("Scheduler")).selected(self.opened).build(ui);
+        if clicked { self.opened = !self.opened }
+    }
+}
+
+pub struct CallStackWindow {
+    opened: bool,
+    symbols: Option<Symbols>,
+}
+
+impl CallStackWindow {
+    pub fn new(symbols: Option<Symbols>) -> Self {
+        CallStackWindow {
+            opened: false,
+            symbols,
+        }
+    }
+
+    // Calls are only tracked while the window is open
+    pub fn render(&mut self, nds: &mut NDS, ui: &Ui) {
+        nds.set_call_stack_tracking(self.opened);
+        if !self.opened { return }
+        let mut opened = self.opened;
+        let describe = |addr| match &self.symbols {
+            Some(symbols) => symbols.describe(addr),
+            None => format!("{:08X}", addr),
+        };
+        Window::new(im_str!("Call Stack"))
+        .always_auto_resize(true)
+        .opened(&mut opened)
+        .build(ui, || {
+            for (cpu, name) in [(Cpu::ARM9, "ARM9"), (Cpu::ARM7, "ARM7")].iter() {
+                ui.text(name);
+                for frame in nds.call_stack(*cpu) {
+                    ui.text(format!("{:08X} {:40} from {}", frame.target, describe(frame.target),
+                        describe(frame.call_addr)));
+                }
+                ui.separator();
+            }
+        });
+        self.opened = opened;
+    }
+
+    pub fn menu_item(&mut self, ui: &Ui) {
+        let clicked = MenuItem::new(im_str!("Call Stack")).selected(self.opened).build(ui);
+        if clicked { self.opened = !self.opened }
+    }
+}
@@ -3,10 +3,15 @@ extern crate imgui_opengl_renderer;
 
 use glfw::{Action, Context, Glfw, Window};
 
-use std::{path::PathBuf, time::Instant};
+use std::{borrow::Cow, path::PathBuf, time::Instant};
 use std::collections::HashSet;
 
-use nds_core::nds::{self, NDS};
+use nds_core::nds::NDS;
+
+use crate::filter::{self, ColorCorrection, Filter};
+use crate::input::Input;
+use crate::layout::ScreenLayout;
+use crate::scripting::Overlay;
 
 pub struct Display {
     window: Window,
@@ -19,22 +24,40 @@ pub struct Display {
     prev_frame_time: Instant,
     prev_fps_update_time: Instant,
     frames_passed: u32,
+    fps: f64,
+    game_title: String,
+    pub filter: Filter,
+    pub integer_scaling: bool,
+    pub color_correction: bool,
+    pub layout: ScreenLayout,
+    pixels: Vec<u8>,
+    console_pixels: Vec<u8>,
+    filtered: Vec<u8>,
+    touched_console: Option<usize>,
+    color_table: ColorCorrection,
 }
 
 impl Display {
-    const WIDTH: usize = nds::WIDTH;
-    const HEIGHT: usize = 2 * nds::HEIGHT;
-    const SCALE: usize = 1;
+    // Consoles are drawn side by side, each with the full screen layout
+    pub const MAX_CONSOLES: usize = 2;
 
-    pub fn new(imgui: &mut imgui::Context) -> Display {
+    pub fn new(imgui: &mut imgui::Context, layout: ScreenLayout, scale: usize, fullscreen: bool) -> Display {
         let mut glfw = glfw::init(glfw::FAIL_ON_ERRORS).unwrap();
         glfw.set_error_callback(glfw::FAIL_ON_ERRORS);
 
-        let width = (Display::WIDTH * Display::SCALE) as u32;
-        let height = 19 + (Display::HEIGHT * Display::SCALE) as u32; // TODO: Don't hardcode main menu bar height
-        let (mut window, events) = glfw.create_window(width, height,
-            "GBA Emulator", glfw::WindowMode::Windowed).expect("Failed to create GLFW window!");
+        let (layout_width, layout_height) = layout.size();
+        let width = (layout_width * scale) as u32;
+        let height = 19 + (layout_height * scale) as u32; // TODO: Don't hardcode main menu bar height
+        let (mut window, events) = glfw.with_primary_monitor(|glfw, monitor| {
+            let mode = match monitor {
+                Some(monitor) if fullscreen => glfw::WindowMode::FullScreen(monitor),
+                _ => glfw::WindowMode::Windowed,
+            };
+            glfw.create_window(width, height, "GBA Emulator", mode)
+        }).expect("Failed to create GLFW window!");
         window.make_current();
+        // Frame pacing is left to the frame limiter
+        glfw.set_swap_interval(glfw::SwapInterval::None);
         window.set_all_polling(true);
         gl::load_with(|name| window.get_proc_address(name));
 
@@ -61,7 +84,8 @@ impl Display {
             gl::TexParameterfv(gl::TEXTURE_2D, gl::TEXTURE_BORDER_COLOR, &color_black as *const f32);
             gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
             gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
-            gl::TexStorage2D(gl::TEXTURE_2D, 1, gl::RGBA8, Display::WIDTH as i32, Display::HEIGHT as i32);
+            let tex_size = (Filter::MAX_SCALE * ScreenLayout::MAX_SIZE) as i32;
+            gl::TexStorage2D(gl::TEXTURE_2D, 1, gl::RGBA8, Display::MAX_CONSOLES as i32 * tex_size, tex_size);
             
             gl::GenFramebuffers(1, &mut fbo as *mut u32);
             gl::BindFramebuffer(gl::READ_FRAMEBUFFER, fbo);
@@ -79,6 +103,17 @@ impl Display {
             prev_frame_time: Instant::now(),
             prev_fps_update_time: Instant::now(),
             frames_passed: 0,
+            fps: 0.0,
+            game_title: String::new(),
+            filter: Filter::Nearest,
+            integer_scaling: false,
+            color_correction: false,
+            layout,
+            pixels: Vec::new(),
+            console_pixels: Vec::new(),
+            filtered: Vec::new(),
+            touched_console: None,
+            color_table: ColorCorrection::new(),
         }
     }
 
@@ -114,8 +149,20 @@ impl Display {
         io[Key::Z] = glfw::Key::Z as _;
     }
 
+    // Screens that games have dimmed or turned the backlight off for are darkened, though they stay faintly visible
+    fn dim(pixels: &mut [u8], level: Option<u8>) {
+        let brightness = match level { Some(level) => 7 + level as u32 * 3, None => 2 };
+        for pixel in pixels.chunks_exact_mut(4) {
+            for channel in pixel[..3].iter_mut() { *channel = (*channel as u32 * brightness / 16) as u8 }
+        }
+    }
+
     pub fn should_close(&self) -> bool { self.window.should_close() }
 
+    pub fn set_game_title(&mut self, game_title: String) { self.game_title = game_title }
+
+    pub fn fps(&self) -> f64 { self.fps }
+
     fn prepare_frame(&mut self, io: &mut imgui::Io) {
         if io.want_set_mouse_pos {
             self.window.set_cursor_pos(io.mouse_pos[0] as f64, io.mouse_pos[1] as f64);
@@ -153,36 +200,61 @@ impl Display {
         }
     }
 
-    pub fn render_main(&mut self, nds: &mut NDS, imgui: &mut imgui::Context, main_menu_height: f32) ->
-        (HashSet<glfw::Key>, Vec<PathBuf>) {
-        let screens = nds.get_screens();
+    // Input goes to the active console, except for the stylus, which goes to the console under the cursor
+    pub fn render_main(&mut self, consoles: &mut [&mut NDS], active: usize, input: &mut Input,
+        imgui: &mut imgui::Context, main_menu_height: f32, overlay: Option<&Overlay>) -> (HashSet<glfw::Key>, Vec<PathBuf>) {
         let (width, height) = self.window.get_size();
         let height = height - main_menu_height as i32;
-
-        let (tex_x, tex_y) = if width * Display::HEIGHT as i32 > height * Display::WIDTH as i32 {
-            let scaled_width = (Display::WIDTH as f32 / Display::HEIGHT as f32 * height as f32) as i32;
-            ((width - scaled_width) / 2, 0)
-        } else if width * (Display::HEIGHT as i32) < height * Display::WIDTH as i32 {
-            let scaled_height = (Display::HEIGHT as f32 / Display::WIDTH as f32 * width as f32) as i32;
-            (0, (height - scaled_height) / 2)
-        } else { (0, 0) };
-
-        let x_start = tex_x;
-        let y_start = tex_y;
-        let x_end = width - tex_x;
-        let y_end = height - tex_y;
+        let (console_width, layout_height) = self.layout.size();
+        let (layout_width, layout_height) = ((consoles.len() * console_width) as i32, layout_height as i32);
+
+        let (scaled_width, scaled_height) = if self.integer_scaling {
+            let scale = (width / layout_width).min(height / layout_height).max(1);
+            (scale * layout_width, scale * layout_height)
+        } else if width * layout_height > height * layout_width {
+            ((layout_width as f32 / layout_height as f32 * height as f32) as i32, height)
+        } else if width * layout_height < height * layout_width {
+            (width, (layout_height as f32 / layout_width as f32 * width as f32) as i32)
+        } else { (width, height) };
+
+        let x_start = (width - scaled_width) / 2;
+        let y_start = (height - scaled_height) / 2;
+        let x_end = x_start + scaled_width;
+        let y_end = y_start + scaled_height;
 
         unsafe {
             gl::ClearColor(0.0, 0.0, 0.0, 1.0);
             gl::BindTexture(gl::TEXTURE_2D, self.screen_tex);
             gl::Clear(gl::COLOR_BUFFER_BIT);
-            gl::TexSubImage2D(gl::TEXTURE_2D, 0, 0, 0, nds::WIDTH as i32, nds::HEIGHT as i32,
-                gl::RGBA, gl::UNSIGNED_SHORT_1_5_5_5_REV, screens[0].as_ptr() as *const std::ffi::c_void);
-            gl::TexSubImage2D(gl::TEXTURE_2D, 0, 0, nds::HEIGHT as i32, nds::WIDTH as i32, nds::HEIGHT as i32,
-                gl::RGBA, gl::UNSIGNED_SHORT_1_5_5_5_REV, screens[1].as_ptr() as *const std::ffi::c_void);
+            self.pixels.clear();
+            self.pixels.resize((layout_width * layout_height * 4) as usize, 0);
+            for (i, nds) in consoles.iter().enumerate() {
+                let frame = nds.frame();
+                let mut screens = [Cow::Borrowed(frame.top), Cow::Borrowed(frame.bottom)];
+                if let (0, Some(overlay)) = (i, overlay) {
+                    for (i, screen) in screens.iter_mut().enumerate() { overlay.blend(i, screen.to_mut()) }
+                }
+                for (screen, level) in screens.iter_mut().zip(nds.backlight().iter()) {
+                    if *level != Some(3) { Display::dim(screen.to_mut(), *level) }
+                }
+                self.layout.compose([&screens[0], &screens[1]], &mut self.console_pixels);
+                for (y, row) in self.console_pixels.chunks_exact(console_width * 4).enumerate() {
+                    let start = (y * layout_width as usize + i * console_width) * 4;
+                    self.pixels[start..start + row.len()].copy_from_slice(row);
+                }
+            }
+            if self.color_correction { self.color_table.apply(&mut self.pixels) }
+            if self.filter == Filter::Xbr {
+                filter::xbr2x(&self.pixels, layout_width as usize, layout_height as usize, &mut self.filtered);
+            }
+            let pixels = if self.filter.scale() == 1 { &self.pixels } else { &self.filtered };
+            let scale = self.filter.scale() as i32;
+            gl::TexSubImage2D(gl::TEXTURE_2D, 0, 0, 0, scale * layout_width, scale * layout_height,
+                gl::RGBA, gl::UNSIGNED_BYTE, pixels.as_ptr() as *const std::ffi::c_void);
             // Flip src0 and src1 because OpenGL wants the texture flipped vertically
-            gl::BlitFramebuffer(0, Display::HEIGHT as i32, Display::WIDTH as i32, 0,
-                x_start, y_start, x_end, y_end, gl::COLOR_BUFFER_BIT, gl::NEAREST);
+            gl::BlitFramebuffer(0, scale * layout_height, scale * layout_width, 0,
+                x_start, y_start, x_end, y_end, gl::COLOR_BUFFER_BIT,
+                if self.filter == Filter::Bilinear { gl::LINEAR } else { gl::NEAREST });
         }
 
         let io = imgui.io_mut();
@@ -193,39 +265,21 @@ impl Display {
         let mut modifiers = HashSet::new();
         let mut files_dropped = Vec::new();
         let old_mouse_pressed = self.window.get_mouse_button(glfw::MouseButtonLeft) == Action::Press;
-        for (_, event) in glfw::flush_messages(&self.events) {
+        let events = glfw::flush_messages(&self.events).collect::<Vec<_>>();
+        for (_, event) in events {
             Display::handle_event(io, &event);
             match event {
                 glfw::WindowEvent::Key(key, _, action, new_modifiers)
                 if !io.want_capture_keyboard => {
                     if action != Action::Release { keys_pressed.insert(key); modifiers.insert(new_modifiers); }
-                    let nds_key = match key {
-                        glfw::Key::A => nds::Key::A,
-                        glfw::Key::B => nds::Key::B,
-                        glfw::Key::X => nds::Key::X,
-                        glfw::Key::Y => nds::Key::Y,
-                        glfw::Key::E => nds::Key::Select,
-                        glfw::Key::T => nds::Key::Start,
-                        glfw::Key::Right => nds::Key::Right,
-                        glfw::Key::Left => nds::Key::Left,
-                        glfw::Key::Up => nds::Key::Up,
-                        glfw::Key::Down => nds::Key::Down,
-                        glfw::Key::R => nds::Key::R,
-                        glfw::Key::L => nds::Key::L,
-                        _ => continue,
-                    };
-                    match action {
-                        Action::Press => nds.press_key(nds_key),
-                        Action::Release => nds.release_key(nds_key),
-                        _ => continue,
-                    };
+                    input.key_event(consoles[active], key, action);
                 },
                 glfw::WindowEvent::MouseButton(glfw::MouseButtonLeft, Action::Press, _) |
                 glfw::WindowEvent::MouseButton(glfw::MouseButtonLeft, Action::Release, _) if !io.want_capture_mouse =>
-                    self.check_stylus(nds, main_menu_height as f64, x_start, y_start,
+                    self.check_stylus(consoles, main_menu_height as f64, x_start, y_start,
                     x_end - x_start, y_end - y_start),
                 glfw::WindowEvent::CursorPos(_, _) if old_mouse_pressed && !io.want_capture_mouse =>
-                    self.check_stylus(nds, main_menu_height as f64, x_start, y_start,
+                    self.check_stylus(consoles, main_menu_height as f64, x_start, y_start,
                     x_end - x_start, y_end - y_start),
                 glfw::WindowEvent::FileDrop(paths) => files_dropped = paths,
                 _ => (),
@@ -252,8 +306,8 @@ impl Display {
 
         let time_passed = self.prev_fps_update_time.elapsed().as_secs_f64();
         if time_passed >= 1.0 {
-            let fps = self.frames_passed as f64 / time_passed;
-            self.window.set_title(&format!("NDS Emulator - {:.2} FPS", fps));
+            self.fps = self.frames_passed as f64 / time_passed;
+            self.window.set_title(&format!("NDS Emulator - {} - {:.2} FPS", self.game_title, self.fps));
             self.frames_passed = 0;
             self.prev_fps_update_time = Instant::now();
         }
@@ -290,22 +344,33 @@ impl Display {
         }
     }
 
-    fn check_stylus(&self, nds: &mut NDS, main_menu_height: f64, tex_x: i32, tex_y: i32, tex_width: i32, tex_height: i32) {
+    fn check_stylus(&mut self, consoles: &mut [&mut NDS], main_menu_height: f64, tex_x: i32, tex_y: i32,
+        tex_width: i32, tex_height: i32) {
         let pressed = self.window.get_mouse_button(glfw::MouseButtonLeft) == Action::Press;
-        if !pressed { nds.release_screen(); return }
         let (cursor_x, cursor_y) = self.window.get_cursor_pos();
         let cursor_y = cursor_y - main_menu_height;
 
+        let (console_width, layout_height) = self.layout.size();
         let (width_factor, height_factor) = (
-            tex_width as f64 / Display::WIDTH as f64,
-            tex_height as f64 / Display::HEIGHT as f64
+            tex_width as f64 / (consoles.len() * console_width) as f64,
+            tex_height as f64 / layout_height as f64
         );
-        let clamp = |val, max|
-            if val < 0.0 { 0.0 } else if val > max as f64 { max as f64 } else { val };
+        let x = (cursor_x - tex_x as f64) / width_factor;
+        let console = ((x / console_width as f64).max(0.0) as usize).min(consoles.len() - 1);
+        if let Some(prev_console) = self.touched_console.filter(|&prev_console| !pressed || prev_console != console) {
+            consoles[prev_console].release_screen();
+            self.touched_console = None;
+        }
+        if !pressed { return }
 
-        let touch_x = clamp((cursor_x - tex_x as f64) / width_factor, nds::WIDTH);
-        let touch_y = clamp((cursor_y - tex_y as f64 - (tex_height / 2) as f64) / height_factor, nds::HEIGHT);
-        nds.press_screen(touch_x as usize, touch_y as usize);
+        let x = x - (console * console_width) as f64;
+        match self.layout.touch_pos(x, (cursor_y - tex_y as f64) / height_factor) {
+            Some((touch_x, touch_y)) => {
+                consoles[console].press_screen(touch_x, touch_y);
+                self.touched_console = Some(console);
+            },
+            None => consoles[console].release_screen(),
+        }
     }
 }
 
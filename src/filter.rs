@@ -0,0 +1,116 @@
+#[derive(Clone, Copy, PartialEq)]
+pub enum Filter {
+    Nearest,
+    Bilinear,
+    Xbr,
+}
+
+impl Filter {
+    pub const ALL: [Filter; 3] = [Filter::Nearest, Filter::Bilinear, Filter::Xbr];
+    pub const MAX_SCALE: usize = 2;
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Filter::Nearest => "Nearest Neighbor",
+            Filter::Bilinear => "Bilinear",
+            Filter::Xbr => "2xBR",
+        }
+    }
+
+    // Factor the filter upscales by before the image is stretched to the window
+    pub fn scale(&self) -> usize {
+        match self {
+            Filter::Nearest | Filter::Bilinear => 1,
+            Filter::Xbr => 2,
+        }
+    }
+}
+
+fn to_yuv(pixel: &[u8]) -> [i32; 3] {
+    let (r, g, b) = (pixel[0] as i32, pixel[1] as i32, pixel[2] as i32);
+    [
+        (299 * r + 587 * g + 114 * b) / 1000,
+        (-169 * r - 331 * g + 500 * b) / 1000,
+        (500 * r - 419 * g - 81 * b) / 1000,
+    ]
+}
+
+// Differences in brightness are weighted the most, like in the original xBR
+fn distance(a: [i32; 3], b: [i32; 3]) -> i32 {
+    48 * (a[0] - b[0]).abs() + 7 * (a[1] - b[1]).abs() + 6 * (a[2] - b[2]).abs()
+}
+
+// 2xBR: Each pixel is doubled and the quarters on a detected edge are blended with the pixel across it
+pub fn xbr2x(src: &[u8], width: usize, height: usize, dst: &mut Vec<u8>) {
+    let yuv = src.chunks_exact(4).map(to_yuv).collect::<Vec<_>>();
+    dst.clear();
+    dst.resize(src.len() * 4, 0);
+    for y in 0..height {
+        for x in 0..width {
+            let e = y * width + x;
+            for &(corner_x, corner_y) in [(1, 1), (-1, 1), (1, -1), (-1, -1)].iter() {
+                // Offsets are written for the bottom right corner and mirrored for the others
+                let at = |dx: i32, dy: i32| {
+                    let x = (x as i32 + dx * corner_x).max(0).min(width as i32 - 1) as usize;
+                    let y = (y as i32 + dy * corner_y).max(0).min(height as i32 - 1) as usize;
+                    y * width + x
+                };
+                let (f, h, i) = (at(1, 0), at(0, 1), at(1, 1));
+                let pixel = |i: usize| &src[4 * i..4 * i + 4];
+                let d = |a: usize, b: usize| distance(yuv[a], yuv[b]);
+
+                let (sub_x, sub_y) = (2 * x + (corner_x + 1) as usize / 2, 2 * y + (corner_y + 1) as usize / 2);
+                let out = 4 * (sub_y * 2 * width + sub_x);
+                dst[out..out + 4].copy_from_slice(pixel(e));
+                if pixel(e) == pixel(f) || pixel(e) == pixel(h) { continue }
+
+                let edge = d(e, at(1, -1)) + d(e, at(-1, 1)) + d(i, at(2, 0)) + d(i, at(0, 2)) + 4 * d(h, f);
+                let across = d(h, at(-1, 0)) + d(h, at(1, 2)) + d(f, at(2, 1)) + d(f, at(0, -1)) + 4 * d(e, i);
+                if edge < across {
+                    let blended = if d(e, f) <= d(e, h) { f } else { h };
+                    for (out, blended) in dst[out..out + 4].iter_mut().zip(pixel(blended)) {
+                        *out = ((*out as u16 + *blended as u16) / 2) as u8;
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Approximates the DS Lite's LCD, which is less saturated and brighter in the midtones than the raw RGB555 output
+pub struct ColorCorrection {
+    to_linear: [f32; 256],
+    to_display: Vec<u8>,
+}
+
+impl ColorCorrection {
+    const LCD_GAMMA: f32 = 2.0;
+    const DISPLAY_GAMMA: f32 = 2.2;
+    const SATURATION: f32 = 0.85;
+    const DISPLAY_STEPS: usize = 4096;
+
+    pub fn new() -> Self {
+        let mut to_linear = [0.0; 256];
+        for (value, linear) in to_linear.iter_mut().enumerate() {
+            *linear = (value as f32 / 255.0).powf(ColorCorrection::LCD_GAMMA);
+        }
+        let steps = ColorCorrection::DISPLAY_STEPS;
+        ColorCorrection {
+            to_linear,
+            to_display: (0..steps).map(|step|
+                ((step as f32 / (steps - 1) as f32).powf(1.0 / ColorCorrection::DISPLAY_GAMMA) * 255.0).round() as u8
+            ).collect(),
+        }
+    }
+
+    pub fn apply(&self, pixels: &mut [u8]) {
+        for pixel in pixels.chunks_exact_mut(4) {
+            let [r, g, b] = [pixel[0], pixel[1], pixel[2]].map(|value| self.to_linear[value as usize]);
+            let luminance = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+            for (out, linear) in pixel.iter_mut().zip([r, g, b].iter()) {
+                let corrected = (luminance + ColorCorrection::SATURATION * (linear - luminance)).clamp(0.0, 1.0);
+                *out = self.to_display[(corrected * (ColorCorrection::DISPLAY_STEPS - 1) as f32) as usize];
+            }
+        }
+    }
+}
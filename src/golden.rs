@@ -0,0 +1,90 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use nds_core::fault::Fault;
+use nds_core::nds::NDS;
+use nds_core::trace::OutputHashes;
+use serde::{Deserialize, Serialize};
+
+// Each run boots a ROM, optionally loads a save state and hashes its output for a number of frames, e.g.
+//
+// [[run]]
+// rom = "game.nds"
+// savestate = "game_title.state"
+// frames = 600
+// video = "1A2B3C4D"
+// audio = "5E6F7A8B"
+//
+// Hashes are filled in by running with --update-golden on a known good build.
+#[derive(Deserialize, Serialize)]
+struct Corpus {
+    #[serde(default)]
+    run: Vec<GoldenRun>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct GoldenRun {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    // Paths are relative to the corpus
+    rom: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    savestate: Option<PathBuf>,
+    frames: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    video: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    audio: Option<String>,
+}
+
+// Returns whether every run matched its stored hashes, or with update, stores the new hashes instead
+pub fn run<F: Fn(&Path) -> Result<NDS, Fault>>(corpus_path: &Path, update: bool, load_rom: F) -> bool {
+    let mut corpus: Corpus = match fs::read_to_string(corpus_path).map_err(|err| err.to_string())
+        .and_then(|contents| toml::from_str(&contents).map_err(|err| err.to_string())) {
+        Ok(corpus) => corpus,
+        Err(err) => { println!("Unable to Load {}: {}!", corpus_path.display(), err); return false },
+    };
+    let dir = corpus_path.parent().unwrap_or_else(|| Path::new(""));
+    let mut passed = 0;
+    for run in corpus.run.iter_mut() {
+        let name = run.name.clone().unwrap_or_else(|| run.rom.display().to_string());
+        let hashes = match run_hashed(run, dir, &load_rom) {
+            Ok(hashes) => (format!("{:08X}", hashes.video), format!("{:08X}", hashes.audio)),
+            Err(reason) => { println!("FAIL {}: {}", name, reason); continue },
+        };
+        let stored = |hash: &Option<String>| hash.clone().unwrap_or_else(|| "None".to_string());
+        let expected = (stored(&run.video), stored(&run.audio));
+        if hashes == expected { println!("PASS {}", name); passed += 1; continue }
+        if update {
+            println!("UPDATED {}", name);
+            run.video = Some(hashes.0);
+            run.audio = Some(hashes.1);
+            passed += 1;
+            continue
+        }
+        for (label, actual, expected) in [("Video", &hashes.0, &expected.0), ("Audio", &hashes.1, &expected.1)] {
+            if actual != expected { println!("FAIL {}: {} is {} instead of {}", name, label, actual, expected) }
+        }
+    }
+    println!("{}/{} Passed", passed, corpus.run.len());
+    if update {
+        if let Err(err) = toml::to_string(&corpus).map_err(|err| err.to_string())
+            .and_then(|contents| fs::write(corpus_path, contents).map_err(|err| err.to_string())) {
+            println!("Unable to Save {}: {}!", corpus_path.display(), err);
+            return false
+        }
+    }
+    passed == corpus.run.len()
+}
+
+fn run_hashed<F: Fn(&Path) -> Result<NDS, Fault>>(run: &GoldenRun, dir: &Path, load_rom: &F) -> Result<OutputHashes, String> {
+    let rom_path = dir.join(&run.rom);
+    if !rom_path.exists() { return Err(format!("{} not found", rom_path.display())) }
+    let mut nds = load_rom(&rom_path).map_err(|fault| fault.to_string())?;
+    if let Some(path) = &run.savestate {
+        let path = dir.join(path);
+        fs::read(&path).and_then(|data| nds.load_state(&data))
+            .map_err(|err| format!("Unable to Load State from {}: {}", path.display(), err))?;
+    }
+    nds.run_hashed(run.frames).map_err(|fault| fault.to_string())
+}
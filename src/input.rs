@@ -0,0 +1,342 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use gilrs::{Axis, Button, EventType, Gilrs};
+use nds_core::log::*;
+use nds_core::nds::{self, GuitarKey, Key, NDS};
+use serde::Deserialize;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Control {
+    Key(Key),
+    // Repeatedly presses and releases the key while held
+    Turbo(Key),
+    Guitar(GuitarKey),
+    Mic,
+    FastForward,
+    Rewind,
+    Screenshot,
+    SwapScreens,
+    Pause,
+    FrameAdvance,
+    SwitchConsole,
+}
+
+impl Control {
+    const NAMES: [(Control, &'static str); 30] = [
+        (Control::Key(Key::A), "a"), (Control::Key(Key::B), "b"), (Control::Key(Key::X), "x"),
+        (Control::Key(Key::Y), "y"), (Control::Key(Key::L), "l"), (Control::Key(Key::R), "r"),
+        (Control::Key(Key::Start), "start"), (Control::Key(Key::Select), "select"),
+        (Control::Key(Key::Up), "up"), (Control::Key(Key::Down), "down"),
+        (Control::Key(Key::Left), "left"), (Control::Key(Key::Right), "right"),
+        (Control::Turbo(Key::A), "turbo_a"), (Control::Turbo(Key::B), "turbo_b"),
+        (Control::Turbo(Key::X), "turbo_x"), (Control::Turbo(Key::Y), "turbo_y"),
+        (Control::Turbo(Key::L), "turbo_l"), (Control::Turbo(Key::R), "turbo_r"),
+        (Control::Guitar(GuitarKey::Green), "guitar_green"), (Control::Guitar(GuitarKey::Red), "guitar_red"),
+        (Control::Guitar(GuitarKey::Yellow), "guitar_yellow"), (Control::Guitar(GuitarKey::Blue), "guitar_blue"),
+        (Control::Mic, "mic"), (Control::FastForward, "fast_forward"), (Control::Rewind, "rewind"),
+        (Control::Screenshot, "screenshot"), (Control::SwapScreens, "swap_screens"),
+        (Control::Pause, "pause"), (Control::FrameAdvance, "frame_advance"),
+        (Control::SwitchConsole, "switch_console"),
+    ];
+
+    fn from_name(name: &str) -> Option<Control> {
+        Control::NAMES.iter().find(|(_, control_name)| *control_name == name).map(|(control, _)| *control)
+    }
+
+    fn name(&self) -> &'static str {
+        Control::NAMES.iter().find(|(control, _)| control == self).unwrap().1
+    }
+}
+
+// Keys go by the same names as in the bindings config
+pub fn key_from_name(name: &str) -> Option<Key> {
+    match Control::from_name(name) {
+        Some(Control::Key(key)) => Some(key),
+        _ => None,
+    }
+}
+
+macro_rules! names {
+    ($name:ident, $type:ty, $( $variant:ident ),*) => {
+        const $name: &[($type, &str)] = &[$( (<$type>::$variant, stringify!($variant)), )*];
+    };
+}
+
+names!(KEY_NAMES, glfw::Key,
+    A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+    Num0, Num1, Num2, Num3, Num4, Num5, Num6, Num7, Num8, Num9,
+    F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+    Kp0, Kp1, Kp2, Kp3, Kp4, Kp5, Kp6, Kp7, Kp8, Kp9, KpDecimal, KpDivide, KpMultiply, KpSubtract, KpAdd, KpEnter,
+    Space, Apostrophe, Comma, Minus, Period, Slash, Semicolon, Equal, LeftBracket, Backslash, RightBracket,
+    GraveAccent, Escape, Enter, Tab, Backspace, Insert, Delete, Right, Left, Down, Up, PageUp, PageDown, Home, End,
+    LeftShift, LeftControl, LeftAlt, RightShift, RightControl, RightAlt
+);
+
+names!(BUTTON_NAMES, Button,
+    South, East, North, West, C, Z, LeftTrigger, LeftTrigger2, RightTrigger, RightTrigger2, Select, Start, Mode,
+    LeftThumb, RightThumb, DPadUp, DPadDown, DPadLeft, DPadRight
+);
+
+fn from_name<T: Copy>(names: &[(T, &str)], name: &str) -> Option<T> {
+    names.iter().find(|(_, value_name)| *value_name == name).map(|(value, _)| *value)
+}
+
+fn to_name<T: PartialEq>(names: &[(T, &'static str)], value: T) -> &'static str {
+    names.iter().find(|(named_value, _)| *named_value == value).unwrap().1
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum TouchStick {
+    None,
+    Left,
+    Right,
+}
+
+// Controls are bound by name so the file stays readable, e.g. fast_forward = "Tab". Controls missing from the file
+// keep their default binding and "" leaves a control unbound.
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct BindingsConfig {
+    keyboard: BTreeMap<String, String>,
+    gamepad: BTreeMap<String, String>,
+    touch_stick: String,
+    // Frames turbo keys stay pressed and then released for
+    turbo_frames: u32,
+}
+
+impl Default for BindingsConfig {
+    fn default() -> Self {
+        Bindings::default().to_config()
+    }
+}
+
+pub struct Bindings {
+    keyboard: HashMap<glfw::Key, Control>,
+    gamepad: HashMap<Button, Control>,
+    touch_stick: TouchStick,
+    turbo_frames: u32,
+}
+
+impl Bindings {
+    // User bindings are applied over the defaults in a fixed order. Rebinding a control drops its default input
+    // and takes the input from any default bound to it.
+    pub fn new(config: &BindingsConfig) -> Self {
+        fn apply<T: Copy + Eq + std::hash::Hash>(parsed: &mut HashMap<T, Control>, bindings: &BTreeMap<String, String>,
+            names: &[(T, &str)]) {
+            for (control_name, input_name) in bindings.iter() {
+                let control = match Control::from_name(control_name) {
+                    Some(control) => control,
+                    None => { warn!("Unknown Control: {}", control_name); continue },
+                };
+                // Empty inputs unbind the control
+                let input = if input_name.is_empty() { None } else {
+                    match from_name(names, input_name) {
+                        Some(input) => Some(input),
+                        None => { warn!("Unknown Input for {}: {}", control_name, input_name); continue },
+                    }
+                };
+                parsed.retain(|_, bound| *bound != control);
+                if let Some(input) = input { parsed.insert(input, control); }
+            }
+        }
+        let mut bindings = Bindings::default();
+        apply(&mut bindings.keyboard, &config.keyboard, KEY_NAMES);
+        apply(&mut bindings.gamepad, &config.gamepad, BUTTON_NAMES);
+        bindings.touch_stick = match config.touch_stick.as_str() {
+            "left" => TouchStick::Left,
+            "right" => TouchStick::Right,
+            "none" => TouchStick::None,
+            stick => { warn!("Unknown Touch Stick: {}", stick); TouchStick::None },
+        };
+        bindings.turbo_frames = config.turbo_frames.max(1);
+        bindings
+    }
+
+    fn to_config(&self) -> BindingsConfig {
+        BindingsConfig {
+            keyboard: self.keyboard.iter()
+                .map(|(key, control)| (control.name().to_string(), to_name(KEY_NAMES, *key).to_string())).collect(),
+            gamepad: self.gamepad.iter()
+                .map(|(button, control)| (control.name().to_string(), to_name(BUTTON_NAMES, *button).to_string()))
+                .collect(),
+            touch_stick: match self.touch_stick {
+                TouchStick::None => "none",
+                TouchStick::Left => "left",
+                TouchStick::Right => "right",
+            }.to_string(),
+            turbo_frames: self.turbo_frames,
+        }
+    }
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        let keyboard = [
+            (glfw::Key::A, Control::Key(Key::A)), (glfw::Key::B, Control::Key(Key::B)),
+            (glfw::Key::X, Control::Key(Key::X)), (glfw::Key::Y, Control::Key(Key::Y)),
+            (glfw::Key::L, Control::Key(Key::L)), (glfw::Key::R, Control::Key(Key::R)),
+            (glfw::Key::T, Control::Key(Key::Start)), (glfw::Key::E, Control::Key(Key::Select)),
+            (glfw::Key::Up, Control::Key(Key::Up)), (glfw::Key::Down, Control::Key(Key::Down)),
+            (glfw::Key::Left, Control::Key(Key::Left)), (glfw::Key::Right, Control::Key(Key::Right)),
+            (glfw::Key::Num1, Control::Guitar(GuitarKey::Green)), (glfw::Key::Num2, Control::Guitar(GuitarKey::Red)),
+            (glfw::Key::Num3, Control::Guitar(GuitarKey::Yellow)), (glfw::Key::Num4, Control::Guitar(GuitarKey::Blue)),
+            (glfw::Key::M, Control::Mic), (glfw::Key::Tab, Control::FastForward),
+            (glfw::Key::Backspace, Control::Rewind), (glfw::Key::F12, Control::Screenshot),
+            (glfw::Key::S, Control::SwapScreens), (glfw::Key::P, Control::Pause),
+            (glfw::Key::N, Control::FrameAdvance), (glfw::Key::C, Control::SwitchConsole),
+        ];
+        // Face buttons are bound by position, matching the DS layout
+        let gamepad = [
+            (Button::East, Control::Key(Key::A)), (Button::South, Control::Key(Key::B)),
+            (Button::North, Control::Key(Key::X)), (Button::West, Control::Key(Key::Y)),
+            (Button::LeftTrigger, Control::Key(Key::L)), (Button::RightTrigger, Control::Key(Key::R)),
+            (Button::Start, Control::Key(Key::Start)), (Button::Select, Control::Key(Key::Select)),
+            (Button::DPadUp, Control::Key(Key::Up)), (Button::DPadDown, Control::Key(Key::Down)),
+            (Button::DPadLeft, Control::Key(Key::Left)), (Button::DPadRight, Control::Key(Key::Right)),
+            (Button::LeftTrigger2, Control::Rewind), (Button::RightTrigger2, Control::FastForward),
+        ];
+        Bindings {
+            keyboard: keyboard.iter().copied().collect(),
+            gamepad: gamepad.iter().copied().collect(),
+            touch_stick: TouchStick::Right,
+            turbo_frames: 2,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Source {
+    Keyboard,
+    Gamepad,
+}
+
+pub struct Input {
+    bindings: Bindings,
+    gilrs: Option<Gilrs>,
+    held: HashSet<(Source, Control)>,
+    pressed: HashSet<Control>,
+    stick_touching: bool,
+    // Frames each held turbo key has been held for
+    turbo: HashMap<Key, u32>,
+}
+
+impl Input {
+    const STICK_DEADZONE: f32 = 0.2;
+
+    pub fn new(bindings: Bindings) -> Self {
+        let gilrs = Gilrs::new().map_err(|err| warn!("Unable to Initialize Gamepads: {}", err)).ok();
+        Input {
+            bindings,
+            gilrs,
+            held: HashSet::new(),
+            pressed: HashSet::new(),
+            stick_touching: false,
+            turbo: HashMap::new(),
+        }
+    }
+
+    // Held controls are released first since their inputs might not be bound anymore
+    pub fn set_bindings(&mut self, nds: &mut NDS, bindings: Bindings) {
+        self.release_all(nds);
+        self.bindings = bindings;
+    }
+
+    pub fn release_all(&mut self, nds: &mut NDS) {
+        for (source, control) in self.held.clone() { self.set_held(nds, source, control, false) }
+        if self.stick_touching { nds.release_screen(); self.stick_touching = false }
+    }
+
+    pub fn key_event(&mut self, nds: &mut NDS, key: glfw::Key, action: glfw::Action) {
+        if action == glfw::Action::Repeat { return }
+        if let Some(control) = self.bindings.keyboard.get(&key).copied() {
+            self.set_held(nds, Source::Keyboard, control, action == glfw::Action::Press);
+        }
+    }
+
+    pub fn update(&mut self, nds: &mut NDS) {
+        self.pressed.clear();
+        self.update_turbo(nds);
+        let mut gilrs = match self.gilrs.take() {
+            Some(gilrs) => gilrs,
+            None => return,
+        };
+        while let Some(event) = gilrs.next_event() {
+            let (button, pressed) = match event.event {
+                EventType::ButtonPressed(button, _) => (button, true),
+                EventType::ButtonReleased(button, _) => (button, false),
+                _ => continue,
+            };
+            if let Some(control) = self.bindings.gamepad.get(&button).copied() {
+                self.set_held(nds, Source::Gamepad, control, pressed);
+            }
+        }
+        self.update_touch_stick(nds, &gilrs);
+        self.gilrs = Some(gilrs);
+    }
+
+    // Turbo is timed in emulated frames rather than real time, so it behaves the same at any speed and in recordings
+    fn update_turbo(&mut self, nds: &mut NDS) {
+        for (key, frames) in self.turbo.iter() {
+            if (*frames / self.bindings.turbo_frames).is_multiple_of(2) {
+                nds.press_key(*key);
+            } else if !self.held.contains(&(Source::Keyboard, Control::Key(*key))) &&
+                !self.held.contains(&(Source::Gamepad, Control::Key(*key))) {
+                nds.release_key(*key);
+            }
+        }
+    }
+
+    // Only frames that were emulated count, so turbo doesn't keep toggling while paused or rewinding
+    pub fn turbo_frame_completed(&mut self) {
+        for frames in self.turbo.values_mut() { *frames += 1 }
+    }
+
+    // The stick's position maps directly to a point on the touch screen while it's pushed past the deadzone
+    fn update_touch_stick(&mut self, nds: &mut NDS, gilrs: &Gilrs) {
+        let (x_axis, y_axis) = match self.bindings.touch_stick {
+            TouchStick::None => return,
+            TouchStick::Left => (Axis::LeftStickX, Axis::LeftStickY),
+            TouchStick::Right => (Axis::RightStickX, Axis::RightStickY),
+        };
+        let position = gilrs.gamepads().map(|(_, gamepad)| (gamepad.value(x_axis), gamepad.value(y_axis)))
+            .find(|(x, y)| x.hypot(*y) > Input::STICK_DEADZONE);
+        if let Some((x, y)) = position {
+            let to_screen = |value: f32, size: usize| ((value.clamp(-1.0, 1.0) + 1.0) / 2.0 * (size - 1) as f32) as usize;
+            nds.press_screen(to_screen(x, nds::WIDTH), to_screen(-y, nds::HEIGHT));
+            self.stick_touching = true;
+        } else if self.stick_touching {
+            nds.release_screen();
+            self.stick_touching = false;
+        }
+    }
+
+    fn set_held(&mut self, nds: &mut NDS, source: Source, control: Control, pressed: bool) {
+        let was_held = self.held(control);
+        if pressed { self.held.insert((source, control)); } else { self.held.remove(&(source, control)); }
+        let held = self.held(control);
+        if held == was_held { return }
+        if held { self.pressed.insert(control); }
+        match control {
+            Control::Key(key) => if held { nds.press_key(key) } else { nds.release_key(key) },
+            Control::Turbo(key) => if held {
+                self.turbo.insert(key, 0);
+            } else {
+                self.turbo.remove(&key);
+                if !self.held(Control::Key(key)) { nds.release_key(key) }
+            },
+            Control::Guitar(key) => nds.set_guitar_key(key, held),
+            Control::Mic => nds.set_mic_blowing(held),
+            Control::FastForward | Control::Rewind | Control::Screenshot | Control::SwapScreens | Control::Pause |
+            Control::FrameAdvance | Control::SwitchConsole => (),
+        }
+    }
+
+    pub fn held(&self, control: Control) -> bool {
+        self.held.contains(&(Source::Keyboard, control)) || self.held.contains(&(Source::Gamepad, control))
+    }
+
+    // Whether the control was pressed since the last update
+    pub fn pressed(&self, control: Control) -> bool {
+        self.pressed.contains(&control)
+    }
+}
@@ -0,0 +1,130 @@
+use nds_core::nds;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Arrangement {
+    Vertical,
+    Horizontal,
+    Single,
+}
+
+impl Arrangement {
+    pub const ALL: [Arrangement; 3] = [Arrangement::Vertical, Arrangement::Horizontal, Arrangement::Single];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Arrangement::Vertical => "Vertical",
+            Arrangement::Horizontal => "Horizontal",
+            Arrangement::Single => "Single Screen",
+        }
+    }
+}
+
+// Clockwise rotation of the whole layout, for games that are held sideways
+#[derive(Clone, Copy, PartialEq)]
+pub enum Rotation {
+    None,
+    Deg90,
+    Deg270,
+}
+
+impl Rotation {
+    pub const ALL: [Rotation; 3] = [Rotation::None, Rotation::Deg90, Rotation::Deg270];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Rotation::None => "No Rotation",
+            Rotation::Deg90 => "90°",
+            Rotation::Deg270 => "270°",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct ScreenLayout {
+    pub arrangement: Arrangement,
+    pub rotation: Rotation,
+    pub gap: usize,
+    // Puts the bottom screen first, or shows it instead of the top screen in single screen mode
+    pub swapped: bool,
+}
+
+impl ScreenLayout {
+    pub const MAX_GAP: usize = nds::HEIGHT;
+    // Large enough for any layout, rotated or not
+    pub const MAX_SIZE: usize = 2 * nds::WIDTH + ScreenLayout::MAX_GAP;
+
+    fn unrotated_size(&self) -> (usize, usize) {
+        match self.arrangement {
+            Arrangement::Vertical => (nds::WIDTH, 2 * nds::HEIGHT + self.gap),
+            Arrangement::Horizontal => (2 * nds::WIDTH + self.gap, nds::HEIGHT),
+            Arrangement::Single => (nds::WIDTH, nds::HEIGHT),
+        }
+    }
+
+    pub fn size(&self) -> (usize, usize) {
+        let (width, height) = self.unrotated_size();
+        if self.rotation == Rotation::None { (width, height) } else { (height, width) }
+    }
+
+    // Position of the top (0) or bottom (1) screen before rotation, if it's shown
+    fn screen_pos(&self, screen: usize) -> Option<(usize, usize)> {
+        let first = if self.swapped { 1 } else { 0 };
+        if screen == first { return Some((0, 0)) }
+        match self.arrangement {
+            Arrangement::Vertical => Some((0, nds::HEIGHT + self.gap)),
+            Arrangement::Horizontal => Some((nds::WIDTH + self.gap, 0)),
+            Arrangement::Single => None,
+        }
+    }
+
+    // Draws both RGBA8 screens into pixels, which ends up with size() pixels
+    pub fn compose(&self, screens: [&[u8]; 2], pixels: &mut Vec<u8>) {
+        let (width, height) = self.unrotated_size();
+        pixels.clear();
+        pixels.resize(width * height * 4, 0);
+        for (screen, screen_pixels) in screens.iter().enumerate() {
+            let (screen_x, screen_y) = match self.screen_pos(screen) { Some(pos) => pos, None => continue };
+            for (y, row) in screen_pixels.chunks_exact(nds::WIDTH * 4).enumerate() {
+                let y = screen_y + y;
+                if self.rotation == Rotation::None {
+                    let start = (y * width + screen_x) * 4;
+                    pixels[start..start + row.len()].copy_from_slice(row);
+                    continue
+                }
+                for (x, pixel) in row.chunks_exact(4).enumerate() {
+                    let x = screen_x + x;
+                    let i = match self.rotation {
+                        Rotation::None => unreachable!(),
+                        Rotation::Deg90 => x * height + (height - 1 - y),
+                        Rotation::Deg270 => (width - 1 - x) * height + y,
+                    } * 4;
+                    pixels[i..i + 4].copy_from_slice(pixel);
+                }
+            }
+        }
+    }
+
+    // Maps a position in the layout to bottom screen coordinates, clamping it to the screen
+    pub fn touch_pos(&self, x: f64, y: f64) -> Option<(usize, usize)> {
+        let (width, height) = self.unrotated_size();
+        let (x, y) = match self.rotation {
+            Rotation::None => (x, y),
+            Rotation::Deg90 => (y, height as f64 - x),
+            Rotation::Deg270 => (width as f64 - y, x),
+        };
+        let (screen_x, screen_y) = self.screen_pos(1)?;
+        let clamp = |val: f64, max: usize| val.max(0.0).min((max - 1) as f64) as usize;
+        Some((clamp(x - screen_x as f64, nds::WIDTH), clamp(y - screen_y as f64, nds::HEIGHT)))
+    }
+}
+
+impl Default for ScreenLayout {
+    fn default() -> Self {
+        ScreenLayout {
+            arrangement: Arrangement::Vertical,
+            rotation: Rotation::None,
+            gap: 0,
+            swapped: false,
+        }
+    }
+}
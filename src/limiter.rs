@@ -0,0 +1,99 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use nds_core::nds;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Speed {
+    Percent(u32),
+    Unlimited,
+}
+
+impl Speed {
+    pub fn label(&self) -> String {
+        match self {
+            Speed::Percent(percent) => format!("{}%", percent),
+            Speed::Unlimited => "Unlimited".to_string(),
+        }
+    }
+
+    pub fn is_slowdown(&self) -> bool {
+        matches!(self, Speed::Percent(percent) if *percent < 100)
+    }
+}
+
+pub struct FrameLimiter {
+    pub speed: Speed,
+    pub fast_forward_speed: Speed,
+    pub fast_forward: bool,
+    pub fast_forward_held: bool,
+    // The display keeps refreshing at full speed while paused
+    pub paused: bool,
+    // Frames skipped after each drawn frame, or the most that are skipped with auto_frameskip
+    pub frameskip: usize,
+    pub auto_frameskip: bool,
+    // Hardcore mode runs at full speed when slower speeds are chosen
+    pub no_slowdown: bool,
+    next_frame: Instant,
+    behind: bool,
+}
+
+impl FrameLimiter {
+    pub const SPEEDS: [Speed; 7] = [
+        Speed::Percent(50), Speed::Percent(75), Speed::Percent(100), Speed::Percent(150),
+        Speed::Percent(200), Speed::Percent(300), Speed::Unlimited,
+    ];
+    pub const MAX_FRAMESKIP: usize = 9;
+
+    pub fn new() -> Self {
+        FrameLimiter {
+            speed: Speed::Percent(100),
+            fast_forward_speed: Speed::Unlimited,
+            fast_forward: false,
+            fast_forward_held: false,
+            paused: false,
+            frameskip: 0,
+            auto_frameskip: false,
+            no_slowdown: false,
+            next_frame: Instant::now(),
+            behind: false,
+        }
+    }
+
+    pub fn cur_speed(&self) -> Speed {
+        let speed = if self.paused { Speed::Percent(100) }
+            else if self.fast_forward || self.fast_forward_held { self.fast_forward_speed } else { self.speed };
+        if self.no_slowdown && speed.is_slowdown() { Speed::Percent(100) } else { speed }
+    }
+
+    pub fn is_turbo(&self) -> bool {
+        match self.cur_speed() {
+            Speed::Percent(percent) => percent > 100,
+            Speed::Unlimited => true,
+        }
+    }
+
+    // Auto frameskip skips one more frame each time the host falls behind or fast-forwards, and one less when it keeps up
+    pub fn frames_to_skip(&self, skipping: usize) -> usize {
+        if self.paused { 0 }
+        else if !self.auto_frameskip { self.frameskip }
+        else if self.behind || self.is_turbo() { (skipping + 1).min(self.frameskip) }
+        else { skipping.saturating_sub(1) }
+    }
+
+    pub fn wait(&mut self) {
+        let frame_period = match self.cur_speed() {
+            Speed::Percent(percent) => Duration::from_secs_f64(100.0 / (percent as f64 * nds::FRAME_RATE)),
+            Speed::Unlimited => { self.next_frame = Instant::now(); self.behind = true; return },
+        };
+        self.next_frame += frame_period;
+        let now = Instant::now();
+        self.behind = self.next_frame <= now;
+        if self.next_frame > now {
+            thread::sleep(self.next_frame - now);
+        } else if now - self.next_frame > frame_period {
+            // Don't try to catch up after falling behind by more than a frame, e.g. while loading a ROM
+            self.next_frame = now;
+        }
+    }
+}
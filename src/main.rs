@@ -1,66 +1,139 @@
+mod audio;
+mod bench;
+mod cli;
+mod config;
 mod display;
 mod debug;
+mod filter;
+mod golden;
+mod input;
+mod layout;
+mod limiter;
+mod osd;
+mod savestates;
+mod scripting;
+mod test_roms;
 
-use std::fs;
-use std::path::PathBuf;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Cursor};
+use std::net::ToSocketAddrs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 
-use nds_core::simplelog::*;
+use nds_core::achievements;
 use nds_core::log::*;
-use nds_core::nds::{NDS, Engine, GraphicsType};
+use nds_core::fault::Fault;
+use nds_core::logging;
+use nds_core::netplay::Netplay;
+use nds_core::nds::{NDS, AudioSink, CapturedPolygon, ConsoleModel, Cpu, Engine, FileStorage, GraphicsType, LocalLink,
+    MemoryStorage, NoLink, RtcMode, SampleQueue, SaveStorage, SdImage, Slot2, SystemFiles, TextureDir, TexturePack,
+    UdpLink};
+use nds_core::rewind::Rewinder;
+use nds_core::rom::{self, BannerLanguage};
+use nds_core::screenshot::Layout;
+use nds_core::symbols::Symbols;
+use nds_core::trace::{self, TraceFormat};
 
+#[cfg(feature = "bridge")]
+use nds_core::nds::BridgeLink;
+
+use audio::{Audio, AudioSettings, Muted, TurboAudio};
+use cli::Args;
+use config::{Config, ConfigWatcher, PathsConfig};
 use display::Display;
+use filter::Filter;
+use input::{Bindings, Control, Input};
+use layout::{Arrangement, Rotation, ScreenLayout};
+use limiter::FrameLimiter;
+use osd::Osd;
 use debug::*;
+use savestates::{SaveStates, Slot};
+use scripting::Script;
 use imgui::*;
 
+// Snapshot every 6 frames and keep 60 seconds worth
+const REWIND_INTERVAL: usize = 6;
+const REWIND_CAPACITY: usize = 600;
+
+// Access point the firmware's connection settings should point to
+#[cfg(feature = "bridge")]
+const WIFI_SSID: &str = "NDS-Emulator";
+
 fn main() {
-    let rom_path = PathBuf::from("examples/3D/BoxTest.nds");
-    let bios7_path = PathBuf::from("bios7.bin");
-    let bios9_path = PathBuf::from("bios9.bin");
-    let firmware_path = PathBuf::from("firmware.bin");
+    // 2000-01-01 00:00:00 UTC
+    let fixed_rtc_mode = RtcMode::Fixed(946_684_800);
+    let mut rtc_mode = RtcMode::Host;
 
+    let args = Args::parse_args();
+    logging::init().unwrap();
+    if let Some(paths) = &args.compare_traces { return compare_traces(&paths[0], &paths[1], &args) }
     std::env::set_current_dir("ROMs").unwrap();
-    let arm7_file_name = "arm7.log";
-    let arm9_file_name = "arm9.log";
-    let instructions7_filter = LevelFilter::Off;
-    let instructions9_filter = LevelFilter::Off;
-
-    let arm7_file = fs::File::create(arm7_file_name);
-    let arm9_file = fs::File::create(arm9_file_name);
-    let mut loggers: Vec<Box<dyn SharedLogger>> = vec![
-        TermLogger::new(LevelFilter::Warn, Config::default(), TerminalMode::Mixed),
-    ];
-    if let Ok(file) = arm7_file {
-        loggers.push(WriteLogger::new(instructions7_filter,
-            ConfigBuilder::new()
-            .set_time_level(LevelFilter::Off)
-            .set_thread_level(LevelFilter::Off)
-            .set_target_level(LevelFilter::Off)
-            .set_location_level(LevelFilter::Off)
-            .set_time_level(LevelFilter::Off)
-            .set_max_level(LevelFilter::Off)
-            .add_filter_allow_str("nds_core::arm7")
-            .build(),
-        file));
-    }
-    if let Ok(file) = arm9_file {
-        loggers.push(WriteLogger::new(instructions9_filter,
-            ConfigBuilder::new()
-            .set_time_level(LevelFilter::Off)
-            .set_thread_level(LevelFilter::Off)
-            .set_target_level(LevelFilter::Off)
-            .set_location_level(LevelFilter::Off)
-            .set_time_level(LevelFilter::Off)
-            .set_max_level(LevelFilter::Off)
-            .add_filter_allow_str("nds_core::arm9")
-            .build(),
-        file))
-    }
-    CombinedLogger::init(loggers).unwrap();
+
+    let config_path = PathBuf::from("config.toml");
+    let mut config = Config::load(&config_path);
+    args.apply(&mut config);
+    config.logging.apply();
+    if let Some(frames) = args.headless_frames { return run_headless(&config, &args, frames) }
+    if let Some(frames) = args.bench { return run_bench(&config, &args, frames) }
+    if let Some(manifest) = &args.test_roms { return run_test_roms(&config, manifest) }
+    if let Some(corpus) = &args.golden { return run_golden(&config, corpus, args.update_golden) }
+    if let Some(frames) = args.check_determinism { return check_determinism(&config, &args, frames) }
+    if let Some(path) = &args.check_state { return check_state(&config, path) }
 
     let mut imgui = Context::create();
-    let mut display = Display::new(&mut imgui);
+    let mut display = Display::new(&mut imgui, config.video.screen_layout(), args.scale as usize, args.fullscreen);
+    let mut config_watcher = ConfigWatcher::new(&config_path);
+    let mut config_changed = true;
+    // Applying the logging config starts repeated warnings over, so it's only done when it changes
+    let mut logging_config = config.logging.clone();
+    let mut input = Input::new(Bindings::default());
     
-    let mut nds = load_rom(&bios7_path, &bios9_path, &firmware_path, &rom_path);
+    let audio_settings = Arc::new(AudioSettings::new());
+    let mut turbo_audio = TurboAudio::PitchPreserving;
+    let mut limiter = FrameLimiter::new();
+    let mut osd = Osd::new();
+    let mut turbo = false;
+    let mut paused = false;
+    let mut frame_advance = false;
+    let mut rom_path = config.paths.rom.clone();
+    let mut nds = expect_rom(load_rom(&config, &rom_path, file_storage(&config, &rom_path, 0),
+        !args.netplay() && !config.emulation.deterministic, audio_output(&config, &audio_settings)));
+    display.set_game_title(game_title(&nds));
+    start_achievements(&mut nds, &config);
+    let mut save_states = SaveStates::new(&PathsConfig::in_dir(&config.paths.states, &rom_path));
+    // Both players have to start from the same state
+    if !args.netplay() && !nds.hardcore() { save_states.recover(&mut nds) }
+    start_from_args(&mut nds, &args);
+    let mut rewinder = Rewinder::new(REWIND_INTERVAL, REWIND_CAPACITY);
+    let mut script: Option<Script> = None;
+    let mut screenshot_layout = Layout::Vertical;
+    let mut gba_rom_path: Option<PathBuf> = None;
+    let mut sd_image_path: Option<PathBuf> = None;
+    let mut slot2 = Slot2Selection::None;
+    let (mut battery_low, mut external_power) = (false, false);
+    let rumbling = Arc::new(AtomicBool::new(false));
+    let mut wifi_mode = WiFiMode::Offline;
+    let mut wifi_changed = false;
+    let mut nifi_latency_ms = 0;
+    // Second console for local multiplayer, which runs the same ROM and only receives input while active
+    let mut other_nds: Option<NDS> = None;
+    let mut active_console = 0;
+    // The local player's console stays the first console while the other player's is the second
+    let mut netplay: Option<Netplay> = None;
+    if args.netplay() {
+        let mut remote_nds = expect_rom(load_rom(&config, &rom_path, file_storage(&config, &rom_path, 1), false, Box::new(Muted)));
+        match start_netplay(&args, &mut nds, &mut remote_nds) {
+            Ok(session) => {
+                osd.show(if session.player() == 0 { "Waiting for Player 2" } else { "Connecting to Player 1" }.to_string());
+                netplay = Some(session);
+                other_nds = Some(remote_nds);
+                wifi_mode = WiFiMode::Local;
+            },
+            Err(err) => error!("Unable to Start Netplay: {}!", err),
+        }
+    }
 
     let mut main_menu_height = 0.0;
     let mut palettes_window = DebugWindow::<PalettesWindowState>::new("Palettes");
@@ -68,12 +141,150 @@ fn main() {
     let mut tiles_window = DebugWindow::<TilesWindowState>::new("Tiles");
     let mut vram_window = DebugWindow::<VRAMWindowState>::new("VRAM");
     let mut stats_window = StatsWindow::new();
+    let mut audio_channels_window = AudioChannelsWindow::new();
+    let mut oam_window = OAMWindow::new();
+    let mut polygons_window = PolygonsWindow::new();
+    let mut scheduler_window = SchedulerWindow::new();
+    let mut call_stack_window = CallStackWindow::new(args.symbols.as_ref().and_then(|path|
+        fs::read(path).and_then(|elf| Symbols::from_elf(&elf))
+        .map_err(|err| error!("Unable to Load Symbols from {}: {}!", path.display(), err)).ok()
+    ));
 
-    while !display.should_close() {
-        nds.emulate_frame();
-        stats_window.frame_completed();
+    while !display.should_close() && !nds.powered_off() {
+        if let Some(new_config) = config_watcher.poll() { config = new_config; args.apply(&mut config); config_changed = true }
+        if config_changed {
+            // Paths and the audio buffer length take effect when the next ROM is loaded
+            config_changed = false;
+            if config.logging != logging_config {
+                config.logging.apply();
+                logging_config = config.logging.clone();
+            }
+            input.set_bindings(console(&mut nds, &mut other_nds, active_console), Bindings::new(&config.input));
+            display.filter = config.video.filter();
+            display.integer_scaling = config.video.integer_scaling;
+            display.color_correction = config.video.color_correction;
+            osd.show_fps = config.video.show_fps;
+            display.layout = config.video.screen_layout();
+            screenshot_layout = config.video.screenshot_layout();
+            *audio_settings.volume.lock().unwrap() = config.audio.volume.clamp(0.0, 1.0);
+            turbo_audio = config.audio.fast_forward;
+            limiter.speed = config.emulation.speed();
+            limiter.fast_forward_speed = config.emulation.fast_forward_speed();
+            limiter.frameskip = config.video.frameskip.min(FrameLimiter::MAX_FRAMESKIP);
+            limiter.auto_frameskip = config.video.auto_frameskip;
+            // Setting the pack again picks up replacements that changed on disk
+            nds.set_texture_pack(texture_pack(&config, &rom_path));
+            // Hardcore mode can be left at any time but only entered by loading a ROM
+            if nds.hardcore() && !config.emulation.hardcore {
+                nds.set_hardcore(false);
+                osd.show("Hardcore Mode Off".to_string());
+            }
+            limiter.no_slowdown = nds.hardcore();
+            rtc_mode = if config.emulation.fixed_rtc { fixed_rtc_mode } else { RtcMode::Host };
+            // Netplay keeps both consoles deterministic
+            if netplay.is_none() {
+                nds.set_deterministic(config.emulation.deterministic);
+                if let Some(other_nds) = other_nds.as_mut() { other_nds.set_deterministic(config.emulation.deterministic) }
+                nds.set_slot2_open_bus(config.emulation.slot2_open_bus());
+                if let Some(other_nds) = other_nds.as_mut() { other_nds.set_slot2_open_bus(config.emulation.slot2_open_bus()) }
+                nds.set_rtc_mode(rtc_mode);
+                if let Some(other_nds) = other_nds.as_mut() { other_nds.set_rtc_mode(rtc_mode) }
+            }
+        }
+        if wifi_changed {
+            wifi_changed = false;
+            if netplay.take().is_some() { osd.show("Left Netplay".to_string()); config_changed = true }
+            input.release_all(console(&mut nds, &mut other_nds, active_console));
+            active_console = 0;
+            set_wifi_link(&mut nds, &wifi_mode, nifi_latency_ms);
+            other_nds = if wifi_mode == WiFiMode::Local {
+                Some(start_local_multiplayer(&config, &rom_path, &mut nds, rtc_mode))
+            } else { None };
+        }
+        input.update(console(&mut nds, &mut other_nds, active_console));
+        if input.pressed(Control::SwitchConsole) && other_nds.is_some() && netplay.is_none() {
+            input.release_all(console(&mut nds, &mut other_nds, active_console));
+            active_console = 1 - active_console;
+            osd.show(format!("Controlling Console {}", active_console + 1));
+        }
+        if input.pressed(Control::Pause) {
+            paused = !paused;
+            osd.show(if paused { "Paused" } else { "Resumed" }.to_string());
+        }
+        // Advancing a frame pauses, so each press runs exactly one frame
+        let advance = (input.pressed(Control::FrameAdvance) || std::mem::take(&mut frame_advance)) && !nds.hardcore();
+        if advance { paused = true }
+        limiter.paused = paused;
+        limiter.fast_forward_held = input.held(Control::FastForward);
+        if limiter.is_turbo() != turbo {
+            turbo = limiter.is_turbo();
+            osd.show(format!("Fast-Forward {}", if turbo { "On" } else { "Off" }));
+        }
+        *audio_settings.turbo.lock().unwrap() = if limiter.is_turbo() { Some(turbo_audio) } else { None };
+        let running = !paused || advance;
+        // Recordings and traces need every frame drawn
+        let frameskip = if nds.is_recording_video() || nds.tracing() { 0 } else { limiter.frames_to_skip(nds.frameskip()) };
+        nds.set_frameskip(frameskip);
+        if let Some(other_nds) = other_nds.as_mut() { other_nds.set_frameskip(frameskip) }
+        if let Some(session) = netplay.as_mut() {
+            let was_connected = session.connected();
+            let remote_nds = other_nds.as_mut().unwrap();
+            let consoles = if session.player() == 0 { [&mut nds, remote_nds] } else { [remote_nds, &mut nds] };
+            let result = if running { session.run_frame(consoles) } else { session.poll().map(|_| false) };
+            if !was_connected && session.connected() {
+                osd.show(format!("Connected as Player {}", session.player() + 1));
+            }
+            match result {
+                Ok(ran) => if ran { input.turbo_frame_completed() },
+                Err(err) => {
+                    error!("Netplay Stopped: {}!", err);
+                    osd.show(format!("Netplay Stopped: {}", err));
+                    paused = true;
+                    netplay = None;
+                    config_changed = true;
+                },
+            }
+        } else {
+            if running && !(input.held(Control::Rewind) && !nds.hardcore() && rewinder.rewind(&mut nds)) {
+                if let Err(fault) = nds.run_frame() {
+                    error!("Emulation Stopped: {}!", fault);
+                    osd.show(format!("Emulation Stopped: {}", fault));
+                    paused = true;
+                }
+                input.turbo_frame_completed();
+                if !nds.hardcore() { rewinder.frame_completed(&nds) }
+                if let Some(err) = script.as_mut().and_then(|script| script.frame_completed(&mut nds).err()) {
+                    error!("Script Error: {}", err);
+                    osd.show(format!("Script Error: {}", err));
+                    script = None;
+                }
+            }
+            if let Some(other_nds) = other_nds.as_mut().filter(|_| running) {
+                if let Err(fault) = other_nds.run_frame() {
+                    error!("Console 2 Stopped: {}!", fault);
+                    osd.show(format!("Console 2 Stopped: {}", fault));
+                    paused = true;
+                }
+                if other_nds.powered_off() { wifi_mode = WiFiMode::Offline; wifi_changed = true }
+            }
+        }
+        for message in nds.take_notifications() { osd.show(message) }
+        for message in other_nds.iter_mut().flat_map(NDS::take_notifications) {
+            osd.show(format!("Console 2: {}", message));
+        }
+        if running { stats_window.frame_completed() }
+        save_states.update(&nds);
         
-        let (keys_pressed, files_dropped) = display.render_main(&mut nds, &mut imgui, main_menu_height);
+        let mut consoles = std::iter::once(&mut nds).chain(other_nds.as_mut()).collect::<Vec<_>>();
+        let (keys_pressed, files_dropped) = display.render_main(&mut consoles, active_console, &mut input,
+            &mut imgui, main_menu_height, script.as_ref().map(|script| script.overlay()));
+        let mut take_screenshot = input.pressed(Control::Screenshot);
+        let mut export_polygons = false;
+        if input.pressed(Control::SwapScreens) { display.layout.swapped = !display.layout.swapped }
+        let mut screen_layout = display.layout;
+        let (mut filter, mut integer_scaling) = (display.filter, display.integer_scaling);
+        let (mut color_correction, mut show_fps) = (display.color_correction, osd.show_fps);
+        let fps = display.fps();
         display.render_imgui(&mut imgui, keys_pressed, |ui, keys_pressed| {
             ui.main_menu_bar(|| {
                 ui.menu(im_str!("Debug Windows"), true, || {
@@ -81,36 +292,653 @@ fn main() {
                     maps_window.menu_item(ui);
                     tiles_window.menu_item(ui);
                     vram_window.menu_item(ui);
+                    oam_window.menu_item(ui);
+                    polygons_window.menu_item(ui);
+                    scheduler_window.menu_item(ui);
+                    call_stack_window.menu_item(ui);
                     stats_window.menu_item(ui);
+                    audio_channels_window.menu_item(ui);
+                });
+                ui.menu(im_str!("Audio"), true, || {
+                    if nds.is_recording_audio() {
+                        if MenuItem::new(im_str!("Stop Recording")).build(ui) { nds.stop_audio_recording() }
+                    } else {
+                        let record_mixer = MenuItem::new(im_str!("Record")).build(ui);
+                        let record_channels = MenuItem::new(im_str!("Record with Channels")).build(ui);
+                        if record_mixer || record_channels {
+                            let path = PathsConfig::in_dir(&config.paths.recordings, &rom_path).with_extension("wav");
+                            nds.start_audio_recording(&path, record_channels)
+                            .unwrap_or_else(|err| error!("Unable to Start Audio Recording: {}!", err));
+                        }
+                    }
+                });
+                ui.menu(im_str!("Video"), true, || {
+                    if nds.is_recording_video() {
+                        if MenuItem::new(im_str!("Stop Recording")).build(ui) {
+                            nds.stop_video_recording();
+                            osd.show("Stopped Video Recording".to_string());
+                        }
+                    } else if MenuItem::new(im_str!("Record")).build(ui) {
+                        let path = PathsConfig::in_dir(&config.paths.recordings, &rom_path).with_extension("avi");
+                        match nds.start_video_recording(&path) {
+                            Ok(()) => osd.show(format!("Recording Video to {}", path.display())),
+                            Err(err) => error!("Unable to Start Video Recording: {}!", err),
+                        }
+                    }
+                    ui.separator();
+                    ui.menu(im_str!("Screen Layout"), true, || {
+                        for arrangement in Arrangement::ALL.iter() {
+                            let label = ImString::new(arrangement.label());
+                            if MenuItem::new(&label).selected(screen_layout.arrangement == *arrangement).build(ui) {
+                                screen_layout.arrangement = *arrangement;
+                            }
+                        }
+                        ui.separator();
+                        for rotation in Rotation::ALL.iter() {
+                            let label = ImString::new(rotation.label());
+                            if MenuItem::new(&label).selected(screen_layout.rotation == *rotation).build(ui) {
+                                screen_layout.rotation = *rotation;
+                            }
+                        }
+                        ui.separator();
+                        if MenuItem::new(im_str!("Swap Screens")).selected(screen_layout.swapped).build(ui) {
+                            screen_layout.swapped = !screen_layout.swapped;
+                        }
+                        let mut gap = screen_layout.gap as u32;
+                        if Slider::new(im_str!("Gap")).range(0 as u32..=ScreenLayout::MAX_GAP as u32).build(ui, &mut gap) {
+                            screen_layout.gap = gap as usize;
+                        }
+                    });
+                    ui.menu(im_str!("Filter"), true, || {
+                        for new_filter in Filter::ALL.iter() {
+                            let label = ImString::new(new_filter.label());
+                            if MenuItem::new(&label).selected(filter == *new_filter).build(ui) { filter = *new_filter }
+                        }
+                    });
+                    if MenuItem::new(im_str!("Integer Scaling")).selected(integer_scaling).build(ui) {
+                        integer_scaling = !integer_scaling;
+                    }
+                    if MenuItem::new(im_str!("Color Correction")).selected(color_correction).build(ui) {
+                        color_correction = !color_correction;
+                    }
+                    if MenuItem::new(im_str!("Show FPS")).selected(show_fps).build(ui) { show_fps = !show_fps }
+                    ui.menu(im_str!("Frameskip"), true, || {
+                        if MenuItem::new(im_str!("Auto")).selected(limiter.auto_frameskip).build(ui) {
+                            limiter.auto_frameskip = !limiter.auto_frameskip;
+                        }
+                        ui.separator();
+                        for frames in 0..=FrameLimiter::MAX_FRAMESKIP {
+                            let label = ImString::new(if frames == 0 { "Off".to_string() } else { frames.to_string() });
+                            if MenuItem::new(&label).selected(limiter.frameskip == frames).build(ui) {
+                                limiter.frameskip = frames;
+                            }
+                        }
+                    });
+                    ui.separator();
+                    if MenuItem::new(im_str!("Screenshot")).build(ui) { take_screenshot = true }
+                    ui.menu(im_str!("Screenshot Layout"), true, || {
+                        for layout in Layout::ALL.iter() {
+                            let label = ImString::new(layout.label());
+                            if MenuItem::new(&label).selected(screenshot_layout == *layout).build(ui) {
+                                screenshot_layout = *layout;
+                            }
+                        }
+                    });
                 });
+                ui.menu(im_str!("Emulation"), true, || {
+                    ui.menu(im_str!("Save State"), true, || {
+                        for num in 1..=SaveStates::NUM_SLOTS {
+                            if MenuItem::new(&im_str!("Slot {}", num)).build(ui) {
+                                match save_states.save(&nds, Slot::Numbered(num)) {
+                                    Ok(()) => osd.show(format!("Saved State to Slot {}", num)),
+                                    Err(err) => error!("Unable to Save State: {}!", err),
+                                }
+                            }
+                        }
+                    });
+                    // Loading a state would desync netplay
+                    ui.menu(im_str!("Load State"), netplay.is_none() && !nds.hardcore(), || {
+                        let newest = save_states.newest();
+                        if MenuItem::new(im_str!("Newest")).enabled(newest.is_some()).build(ui) {
+                            save_states.load_newest(&mut nds);
+                        }
+                        ui.separator();
+                        for slot in SaveStates::slots() {
+                            let label = match slot {
+                                Slot::Auto => ImString::new("Auto-Save"),
+                                Slot::Numbered(num) => im_str!("Slot {}", num),
+                            };
+                            let exists = save_states.modified(slot).is_some();
+                            if MenuItem::new(&label).selected(newest == Some(slot)).enabled(exists).build(ui) {
+                                match save_states.load(&mut nds, slot) {
+                                    Ok(()) => osd.show(format!("Loaded State from {}", label)),
+                                    Err(err) => error!("Unable to Load State: {}!", err),
+                                }
+                            }
+                        }
+                    });
+                    ui.separator();
+                    if MenuItem::new(im_str!("Pause")).selected(paused).build(ui) { paused = !paused }
+                    if MenuItem::new(im_str!("Frame Advance")).enabled(!nds.hardcore()).build(ui) { frame_advance = true }
+                    ui.separator();
+                    if MenuItem::new(im_str!("Fast-Forward")).selected(limiter.fast_forward).build(ui) {
+                        limiter.fast_forward = !limiter.fast_forward;
+                    }
+                    ui.menu(im_str!("Speed"), true, || {
+                        for speed in FrameLimiter::SPEEDS.iter() {
+                            let enabled = !(limiter.no_slowdown && speed.is_slowdown());
+                            let label = ImString::new(speed.label());
+                            if MenuItem::new(&label).selected(limiter.speed == *speed).enabled(enabled).build(ui) {
+                                limiter.speed = *speed;
+                            }
+                        }
+                    });
+                    ui.menu(im_str!("Fast-Forward Speed"), true, || {
+                        for speed in FrameLimiter::SPEEDS.iter() {
+                            let selected = limiter.fast_forward_speed == *speed;
+                            if MenuItem::new(&ImString::new(speed.label())).selected(selected).build(ui) {
+                                limiter.fast_forward_speed = *speed;
+                            }
+                        }
+                    });
+                    ui.menu(im_str!("Fast-Forward Audio"), true, || {
+                        let modes = [
+                            (im_str!("Mute"), TurboAudio::Mute),
+                            (im_str!("Pitch-Preserving"), TurboAudio::PitchPreserving),
+                        ];
+                        for (label, mode) in modes.iter() {
+                            if MenuItem::new(label).selected(turbo_audio == *mode).build(ui) { turbo_audio = *mode }
+                        }
+                    });
+                    ui.separator();
+                    let fixed_rtc = rtc_mode == fixed_rtc_mode;
+                    if MenuItem::new(im_str!("Fixed RTC Date")).selected(fixed_rtc).build(ui) {
+                        rtc_mode = if fixed_rtc { RtcMode::Host } else { fixed_rtc_mode };
+                        nds.set_rtc_mode(rtc_mode);
+                    }
+                    // Both players' consoles have to see the same power state
+                    ui.menu(im_str!("Power"), netplay.is_none(), || {
+                        if MenuItem::new(im_str!("Low Battery")).selected(battery_low).build(ui) {
+                            battery_low = !battery_low;
+                            nds.set_battery_low(battery_low);
+                        }
+                        if MenuItem::new(im_str!("Plugged In")).selected(external_power).build(ui) {
+                            external_power = !external_power;
+                            nds.set_external_power(external_power);
+                        }
+                        ui.separator();
+                        let backlight = nds.backlight().iter().flatten().copied().max();
+                        for level in 0..4 {
+                            let label = ImString::new(format!("Backlight Level {}", level + 1));
+                            if MenuItem::new(&label).selected(backlight == Some(level)).build(ui) {
+                                nds.set_backlight_level(level);
+                            }
+                        }
+                    });
+                    let can_boot_gba = slot2 == Slot2Selection::GBACartridge && !nds.gba_mode() && netplay.is_none();
+                    if MenuItem::new(im_str!("Boot GBA Cartridge")).enabled(can_boot_gba).build(ui) {
+                        nds.enter_gba_mode();
+                    }
+                });
+                // Only the local console would change, which would desync netplay
+                ui.menu(im_str!("Slot-2"), netplay.is_none(), || {
+                    let devices = [
+                        (im_str!("None"), Slot2Selection::None),
+                        (im_str!("GBA Cartridge"), Slot2Selection::GBACartridge),
+                        (im_str!("Rumble Pak"), Slot2Selection::RumblePak),
+                        (im_str!("Memory Expansion Pak"), Slot2Selection::MemoryExpansionPak),
+                        (im_str!("Guitar Grip"), Slot2Selection::GuitarGrip),
+                    ];
+                    for (label, device) in devices.iter() {
+                        let enabled = *device != Slot2Selection::GBACartridge || gba_rom_path.is_some();
+                        if MenuItem::new(label).selected(slot2 == *device).enabled(enabled).build(ui) {
+                            slot2 = *device;
+                            set_slot2(&mut nds, slot2, &gba_rom_path, &config.paths.saves, &rumbling);
+                        }
+                    }
+                });
+                ui.menu(im_str!("Scripting"), true, || {
+                    if MenuItem::new(im_str!("Stop Script")).enabled(script.is_some()).build(ui) { script = None }
+                });
+                ui.menu(im_str!("Wi-Fi"), true, || {
+                    let modes = [
+                        (im_str!("Offline"), WiFiMode::Offline),
+                        (im_str!("Ni-Fi (LAN)"), WiFiMode::NiFi),
+                        (im_str!("Local Multiplayer"), WiFiMode::Local),
+                    ];
+                    for (label, mode) in modes.iter() {
+                        if MenuItem::new(label).selected(wifi_mode == *mode).build(ui) {
+                            wifi_mode = mode.clone();
+                            wifi_changed = true;
+                        }
+                    }
+                    #[cfg(feature = "bridge")]
+                    ui.menu(im_str!("Internet (Bridge)"), true, || {
+                        for interface in BridgeLink::interfaces() {
+                            let selected = wifi_mode == WiFiMode::Bridge(interface.clone());
+                            if MenuItem::new(&ImString::new(&interface)).selected(selected).build(ui) {
+                                wifi_mode = WiFiMode::Bridge(interface);
+                                wifi_changed = true;
+                            }
+                        }
+                    });
+                    ui.menu(im_str!("Ni-Fi Latency Padding"), true, || {
+                        for latency_ms in [0, 8, 16, 33].iter() {
+                            let label = im_str!("{} ms", latency_ms);
+                            if MenuItem::new(&label).selected(nifi_latency_ms == *latency_ms).build(ui) {
+                                nifi_latency_ms = *latency_ms;
+                                // Restarting local multiplayer would reset the second console
+                                wifi_changed = wifi_mode == WiFiMode::NiFi;
+                            }
+                        }
+                    });
+                });
+                if rumbling.load(Ordering::Relaxed) { ui.text(im_str!("Rumble")) }
                 main_menu_height = ui.window_size()[1];
             });
+            osd.draw(ui, main_menu_height, fps);
 
             palettes_window.render(&mut nds, ui, &keys_pressed);
             maps_window.render(&mut nds, ui, &keys_pressed);
             tiles_window.render(&mut nds, ui, &keys_pressed);
             vram_window.render(&mut nds, ui, &keys_pressed);
+            oam_window.render(&mut nds, ui);
+            export_polygons = polygons_window.render(&mut nds, ui);
+            scheduler_window.render(&mut nds, ui);
+            call_stack_window.render(&mut nds, ui);
             stats_window.render(ui);
+            audio_channels_window.render(&mut nds, ui);
         });
 
+        display.layout = screen_layout;
+        display.filter = filter;
+        display.integer_scaling = integer_scaling;
+        display.color_correction = color_correction;
+        osd.show_fps = show_fps;
+        if export_polygons {
+            let path = PathsConfig::in_dir(&config.paths.recordings, &rom_path).with_extension("obj");
+            match polygons_window.export(&path) {
+                Ok(()) => osd.show(format!("Exported 3D Polygons to {}", path.display())),
+                Err(err) => error!("Unable to Export 3D Polygons: {}!", err),
+            }
+        }
+
+        if take_screenshot {
+            let path = screenshot_path(&PathsConfig::in_dir(&config.paths.recordings, &rom_path));
+            match nds.save_screenshot(&path, screenshot_layout) {
+                Ok(()) => osd.show(format!("Saved Screenshot to {}", path.display())),
+                Err(err) => error!("Unable to Save Screenshot: {}!", err),
+            }
+        }
+
         if files_dropped.len() == 1 {
             if let Some(ext) = files_dropped[0].extension() {
                 if let Some(str) = ext.to_str() {
-                    if str.to_lowercase() == "nds" {
-                        nds = load_rom(&bios7_path, &bios9_path, &firmware_path, &files_dropped[0]);
-                    } else { error!("File is not a .nds file!") }
+                    match str.to_lowercase().as_str() {
+                        // Archives are assumed to contain a DS ROM
+                        "nds" | "zip" | "7z" | "gz" => match load_rom(&config, &files_dropped[0],
+                            file_storage(&config, &files_dropped[0], 0), netplay.is_none() && !config.emulation.deterministic,
+                            audio_output(&config, &audio_settings)) {
+                            Ok(new_nds) => {
+                                save_states.exit(&nds);
+                                rom_path = files_dropped[0].clone();
+                                other_nds = None;
+                                nds = new_nds;
+                                display.set_game_title(game_title(&nds));
+                                start_achievements(&mut nds, &config);
+                                limiter.no_slowdown = nds.hardcore();
+                                nds.set_texture_pack(texture_pack(&config, &rom_path));
+                                nds.set_rtc_mode(rtc_mode);
+                                nds.set_battery_low(battery_low);
+                                nds.set_external_power(external_power);
+                                set_slot2(&mut nds, slot2, &gba_rom_path, &config.paths.saves, &rumbling);
+                                set_sd_image(&mut nds, &sd_image_path);
+                                wifi_changed = true;
+                                save_states = SaveStates::new(&PathsConfig::in_dir(&config.paths.states, &rom_path));
+                                if !nds.hardcore() { save_states.recover(&mut nds) }
+                                rewinder.clear();
+                                script = None;
+                            },
+                            Err(fault) => {
+                                error!("Unable to Load ROM: {}!", fault);
+                                osd.show(format!("Unable to Load ROM: {}", fault));
+                            },
+                        },
+                        "gba" | "img" if netplay.is_some() => warn!("Ignoring {} During Netplay", files_dropped[0].display()),
+                        "gba" => {
+                            gba_rom_path = Some(files_dropped[0].clone());
+                            slot2 = Slot2Selection::GBACartridge;
+                            set_slot2(&mut nds, slot2, &gba_rom_path, &config.paths.saves, &rumbling);
+                        },
+                        "img" => {
+                            sd_image_path = Some(files_dropped[0].clone());
+                            set_sd_image(&mut nds, &sd_image_path);
+                        },
+                        "lua" => {
+                            script = None;
+                            match Script::load(&files_dropped[0], &mut nds) {
+                                Ok(new_script) => {
+                                    script = Some(new_script);
+                                    osd.show(format!("Loaded {}", files_dropped[0].display()));
+                                },
+                                Err(err) => { error!("Script Error: {}", err); osd.show(format!("Script Error: {}", err)) },
+                            }
+                        },
+                        _ => error!("File is not a .nds, .gba, .img or .lua file!"),
+                    }
                 }
             } else { error!("File does not have an extension!") }
         } else if files_dropped.len() > 1 { error!("More than 1 file dropped!") }
+        limiter.wait();
+    }
+    save_states.exit(&nds);
+    stop_trace(&mut nds);
+
+    fn start_from_args(nds: &mut NDS, args: &Args) {
+        if let Some(path) = &args.savestate {
+            fs::read(path).and_then(|data| nds.load_state(&data))
+            .unwrap_or_else(|err| error!("Unable to Load State from {}: {}!", path.display(), err));
+        }
+        if let Some(path) = &args.record_video {
+            nds.start_video_recording(path).unwrap_or_else(|err| error!("Unable to Start Video Recording: {}!", err));
+        }
+        if let Some(path) = &args.trace {
+            match File::create(path) {
+                Ok(file) => nds.start_trace(Box::new(BufWriter::new(file)), args.trace_level.into()),
+                Err(err) => error!("Unable to Start Trace: {}!", err),
+            }
+        }
     }
 
-    fn load_rom(bios7_path: &PathBuf, bios9_path: &PathBuf, firmware_path: &PathBuf, rom_path: &PathBuf) -> NDS {
-        NDS::new(
-            fs::read(bios7_path).unwrap(),
-            fs::read(bios9_path).unwrap(),
-            fs::read(firmware_path).unwrap(),
-            fs::read(rom_path).unwrap(),
-            rom_path.with_extension("sav")
-        )
+    fn stop_trace(nds: &mut NDS) {
+        nds.stop_trace().unwrap_or_else(|err| error!("Unable to Write Trace: {}!", err));
     }
+
+    fn compare_traces(expected: &Path, actual: &Path, args: &Args) {
+        let open = |path: &Path| File::open(path).map(BufReader::new)
+            .unwrap_or_else(|err| panic!("Unable to Open {}: {}!", path.display(), err));
+        let result = match &args.trace_format {
+            Some(template) => match TraceFormat::new(template, args.trace_cpu.map(Cpu::from)) {
+                Ok(format) => trace::compare_with_format(open(expected), &format, open(actual)),
+                Err(err) => { error!("Unable to Read Trace Format: {}!", err); std::process::exit(2) },
+            },
+            None => trace::compare(open(expected), open(actual)),
+        };
+        match result {
+            Ok(None) => println!("Traces Match"),
+            Ok(Some(divergence)) => {
+                println!("Traces Diverge at Line {} in {}", divergence.line, divergence.fields.join(", "));
+                println!("Expected: {}", divergence.expected);
+                println!("Actual:   {}", divergence.actual);
+                std::process::exit(1);
+            },
+            Err(err) => { error!("Unable to Compare Traces: {}!", err); std::process::exit(2) },
+        }
+    }
+
+    // Runs without a window or audio device, which is useful for scripted recordings and testing
+    fn run_headless(config: &Config, args: &Args, frames: u64) {
+        let samples = SampleQueue::new(48000);
+        let mut nds = expect_rom(load_rom(config, &config.paths.rom, memory_storage(config, &config.paths.rom), false,
+            Box::new(samples.clone())));
+        start_from_args(&mut nds, args);
+        for _ in 0..frames {
+            if nds.powered_off() { break }
+            if let Err(fault) = nds.run_frame() { error!("Emulation Stopped: {}!", fault); break }
+            samples.take();
+        }
+        nds.stop_video_recording();
+        stop_trace(&mut nds);
+    }
+
+    fn run_bench(config: &Config, args: &Args, frames: u64) {
+        let mut nds = expect_rom(load_rom(config, &config.paths.rom, memory_storage(config, &config.paths.rom), false,
+            Box::new(Muted)));
+        start_from_args(&mut nds, args);
+        let report = bench::run(&mut nds, config.paths.rom.display().to_string(), frames);
+        stop_trace(&mut nds);
+        println!("{}", serde_json::to_string(&report).unwrap());
+    }
+
+    fn run_test_roms(config: &Config, manifest: &Path) {
+        let passed = test_roms::run(manifest, false, |rom_path| load_deterministic(config, rom_path));
+        if !passed { std::process::exit(1) }
+    }
+
+    fn run_golden(config: &Config, corpus: &Path, update: bool) {
+        let passed = golden::run(corpus, update, |rom_path| load_deterministic(config, rom_path));
+        if !passed { std::process::exit(1) }
+    }
+
+    // Runs the ROM twice from the same start and checks that the output and final state match
+    fn check_determinism(config: &Config, args: &Args, frames: u64) {
+        let run = || {
+            let mut nds = expect_rom(load_deterministic(config, &config.paths.rom));
+            if let Some(path) = &args.savestate {
+                fs::read(path).and_then(|data| nds.load_state(&data))
+                .unwrap_or_else(|err| error!("Unable to Load State from {}: {}!", path.display(), err));
+            }
+            let hashes = nds.run_hashed(frames)
+                .unwrap_or_else(|fault| { error!("Emulation Stopped: {}!", fault); std::process::exit(2) });
+            (hashes, nds.save_state())
+        };
+        let (first, second) = (run(), run());
+        if first.0.video != second.0.video { println!("Video Differs: {:08X} and {:08X}", first.0.video, second.0.video) }
+        if first.0.audio != second.0.audio { println!("Audio Differs: {:08X} and {:08X}", first.0.audio, second.0.audio) }
+        if first.1 != second.1 { println!("Save States Differ") }
+        if first != second { std::process::exit(1) }
+        println!("Deterministic");
+    }
+
+    // Sections that are missing or too new for this build are reported before the ROM is loaded to try the state
+    fn check_state(config: &Config, path: &Path) {
+        let data = fs::read(path).unwrap_or_else(|err| { println!("Unable to Load {}: {}!", path.display(), err); std::process::exit(1) });
+        let info = NDS::inspect_state(&data)
+            .unwrap_or_else(|err| { println!("Unable to Load {}: {}!", path.display(), err); std::process::exit(1) });
+        println!("Version {} State for {}", info.version, info.game_code);
+        for section in info.sections.iter() {
+            let status = match section.current_version {
+                None => "Unknown, Skipped".to_string(),
+                Some(current) if section.version == current => "OK".to_string(),
+                Some(current) if section.loadable => format!("Migrated to Version {}", current),
+                Some(current) => format!("Unsupported, Version {} is Current", current),
+            };
+            println!("{:4} Version {} {:>9} Bytes: {}", section.name, section.version, section.len, status);
+        }
+        for name in info.missing.iter() { println!("{:4} Missing", name) }
+        let mut nds = expect_rom(load_deterministic(config, &config.paths.rom));
+        if let Err(err) = nds.load_state(&data) { println!("Unable to Load State: {}!", err); std::process::exit(1) }
+        println!("Loaded");
+    }
+
+    fn start_netplay(args: &Args, nds: &mut NDS, remote_nds: &mut NDS) -> io::Result<Netplay> {
+        match &args.netplay_connect {
+            Some(addr) => {
+                let addr = addr.to_socket_addrs()?.next()
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No Address Found"))?;
+                Netplay::connect(addr, [remote_nds, nds])
+            },
+            None => Netplay::host(args.netplay_host.unwrap_or(Netplay::DEFAULT_PORT), args.netplay_mode.into(),
+                args.netplay_delay, [nds, remote_nds]),
+        }
+    }
+
+    fn load_deterministic(config: &Config, rom_path: &Path) -> Result<NDS, Fault> {
+        let mut nds = load_rom(config, rom_path, memory_storage(config, rom_path), false, Box::new(Muted))?;
+        nds.set_deterministic(true);
+        Ok(nds)
+    }
+
+    fn screenshot_path(base_path: &Path) -> PathBuf {
+        let stem = base_path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("screenshot");
+        (1..).map(|num| base_path.with_file_name(format!("{}_{}.png", stem, num)))
+        .find(|path| !path.exists()).unwrap()
+    }
+
+    fn audio_output(config: &Config, audio_settings: &Arc<AudioSettings>) -> Box<dyn AudioSink> {
+        Box::new(Audio::new(Arc::clone(audio_settings), config.audio.buffer_len))
+    }
+
+    // Every console after the first gets its own save file so they don't overwrite each other
+    fn save_path(config: &Config, rom_path: &Path, console: usize) -> PathBuf {
+        let extension = if console == 0 { "sav".to_string() } else { format!("{}.sav", console + 1) };
+        PathsConfig::in_dir(&config.paths.saves, rom_path).with_extension(extension)
+    }
+
+    fn file_storage(config: &Config, rom_path: &Path, console: usize) -> Box<dyn SaveStorage> {
+        Box::new(FileStorage::new(save_path(config, rom_path, console)))
+    }
+
+    // Headless runs start from the ROM's save but never write to it, so runs can be repeated and the save is left alone
+    fn memory_storage(config: &Config, rom_path: &Path) -> Box<dyn SaveStorage> {
+        Box::new(MemoryStorage::new(fs::read(save_path(config, rom_path, 0)).ok()))
+    }
+
+    // Only one console may write to the NAND, and only outside of deterministic runs and netplay
+    fn load_rom(config: &Config, rom_path: &Path, save_storage: Box<dyn SaveStorage>, nand_writable: bool,
+        audio_sink: Box<dyn AudioSink>) -> Result<NDS, Fault> {
+        let mut nds = NDS::new(
+            SystemFiles {
+                bios7: fs::read(&config.paths.bios7).unwrap(),
+                bios9: fs::read(&config.paths.bios9).unwrap(),
+                firmware: fs::read(&config.paths.firmware).ok(),
+            },
+            rom::read_patched_rom(rom_path, "nds", None, None).unwrap(),
+            save_storage,
+            audio_sink,
+            config.emulation.direct_boot,
+            config.emulation.console_model(),
+        )?;
+        // Optional, GBA cartridges are booted directly without it
+        if let Ok(gba_bios) = fs::read(&config.paths.gba_bios) { nds.set_gba_bios(gba_bios) }
+        nds.set_deterministic(config.emulation.deterministic);
+        nds.set_slot2_open_bus(config.emulation.slot2_open_bus());
+        if config.emulation.console_model() == ConsoleModel::DSi {
+            match nand_image(config, nand_writable) {
+                Ok(nand) => nds.set_nand_image(Some(nand)),
+                Err(err) => warn!("Unable to Open NAND Image: {}!", err),
+            }
+        }
+        Ok(nds)
+    }
+
+    // Read-only images are copied into memory, so writes only last until the console is closed
+    fn nand_image(config: &Config, writable: bool) -> io::Result<Box<dyn SdImage>> {
+        if writable {
+            Ok(Box::new(fs::OpenOptions::new().read(true).write(true).open(&config.paths.nand)?))
+        } else {
+            Ok(Box::new(Cursor::new(fs::read(&config.paths.nand)?)))
+        }
+    }
+
+    fn texture_pack(config: &Config, rom_path: &Path) -> Option<Box<dyn TexturePack>> {
+        if !config.video.dump_textures && !config.video.replace_textures { return None }
+        let dir = PathsConfig::in_dir(&config.paths.textures, rom_path).with_extension("");
+        Some(Box::new(TextureDir::new(
+            Some(dir.join("dump")).filter(|_| config.video.dump_textures),
+            Some(dir.join("replace")).filter(|_| config.video.replace_textures),
+        )))
+    }
+
+    // Hardcore mode only starts along with a ROM, so nothing could have changed the game's state before it
+    fn start_achievements(nds: &mut NDS, config: &Config) {
+        nds.set_hardcore(config.emulation.hardcore);
+        match achievements::game_hash(nds.rom()) {
+            Some(hash) => info!("RetroAchievements Hash: {}", hash),
+            None => warn!("Unable to Hash ROM for RetroAchievements!"),
+        }
+    }
+
+    fn expect_rom(result: Result<NDS, Fault>) -> NDS {
+        result.unwrap_or_else(|fault| { error!("Unable to Load ROM: {}!", fault); std::process::exit(2) })
+    }
+
+    // First line of the English banner title, which is usually the game's full name
+    fn game_title(nds: &NDS) -> String {
+        nds.banner().and_then(|banner| banner.title(BannerLanguage::English)?.lines().next().map(str::to_string))
+        .unwrap_or_else(|| nds.header().title())
+    }
+
+    fn set_sd_image(nds: &mut NDS, sd_image_path: &Option<PathBuf>) {
+        nds.set_sd_image(sd_image_path.as_ref().and_then(|path|
+            match fs::OpenOptions::new().read(true).write(true).open(path) {
+                Ok(image) => Some(Box::new(image) as Box<dyn SdImage>),
+                Err(err) => { error!("Unable to Open SD Card Image: {}!", err); None },
+            }
+        ));
+    }
+
+    fn console<'a>(nds: &'a mut NDS, other_nds: &'a mut Option<NDS>, index: usize) -> &'a mut NDS {
+        match other_nds {
+            Some(other_nds) if index == 1 => other_nds,
+            _ => nds,
+        }
+    }
+
+    // The second console is muted and linked directly to the first, without going through the network
+    fn start_local_multiplayer(config: &Config, rom_path: &Path, nds: &mut NDS, rtc_mode: RtcMode) -> NDS {
+        let mut other_nds = expect_rom(load_rom(config, rom_path, file_storage(config, rom_path, 1), false, Box::new(Muted)));
+        other_nds.set_rtc_mode(rtc_mode);
+        let (link, other_link) = LocalLink::pair();
+        nds.set_wifi_link(Box::new(link));
+        other_nds.set_wifi_link(Box::new(other_link));
+        other_nds
+    }
+
+    fn set_wifi_link(nds: &mut NDS, mode: &WiFiMode, nifi_latency_ms: u64) {
+        // Free the port before binding it again
+        nds.set_wifi_link(Box::new(NoLink));
+        match mode {
+            WiFiMode::Offline | WiFiMode::Local => (),
+            WiFiMode::NiFi => match UdpLink::new(Duration::from_millis(nifi_latency_ms)) {
+                Ok(link) => nds.set_wifi_link(Box::new(link)),
+                Err(err) => error!("Unable to Start Ni-Fi: {}!", err),
+            },
+            #[cfg(feature = "bridge")]
+            WiFiMode::Bridge(interface) => match BridgeLink::new(interface, WIFI_SSID) {
+                Ok(link) => nds.set_wifi_link(Box::new(link)),
+                Err(err) => error!("Unable to Bridge to {}: {}!", interface, err),
+            },
+        }
+    }
+
+    fn set_slot2(nds: &mut NDS, selection: Slot2Selection, gba_rom_path: &Option<PathBuf>, saves_dir: &Path,
+        rumbling: &Arc<AtomicBool>) {
+        rumbling.store(false, Ordering::Relaxed);
+        nds.set_slot2(match (selection, gba_rom_path) {
+            (Slot2Selection::GBACartridge, Some(gba_rom_path)) =>
+            match rom::read_patched_rom(gba_rom_path, "gba", None, None) {
+                Ok(rom) => {
+                    let save_path = PathsConfig::in_dir(saves_dir, gba_rom_path).with_extension("sav");
+                    Slot2::GBACartridge(rom, Box::new(FileStorage::new(save_path)))
+                },
+                Err(err) => { error!("Unable to Load GBA ROM: {}!", err); Slot2::Empty },
+            },
+            (Slot2Selection::RumblePak, _) => {
+                let rumbling = Arc::clone(rumbling);
+                Slot2::RumblePak(Box::new(move |value| rumbling.store(value, Ordering::Relaxed)))
+            },
+            (Slot2Selection::MemoryExpansionPak, _) => Slot2::MemoryExpansionPak,
+            (Slot2Selection::GuitarGrip, _) => Slot2::GuitarGrip,
+            _ => Slot2::Empty,
+        });
+    }
+}
+
+#[derive(Clone, PartialEq)]
+enum WiFiMode {
+    Offline,
+    NiFi,
+    Local,
+    #[cfg(feature = "bridge")]
+    Bridge(String),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Slot2Selection {
+    None,
+    GBACartridge,
+    RumblePak,
+    MemoryExpansionPak,
+    GuitarGrip,
 }
@@ -0,0 +1,43 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use imgui::{ImString, Ui};
+
+// Transient messages and the FPS counter, drawn over the screens but under any windows
+pub struct Osd {
+    messages: VecDeque<(String, Instant)>,
+    pub show_fps: bool,
+}
+
+impl Osd {
+    const DURATION: Duration = Duration::from_secs(3);
+    const MAX_MESSAGES: usize = 6;
+    const PADDING: f32 = 4.0;
+
+    pub fn new() -> Self {
+        Osd {
+            messages: VecDeque::new(),
+            show_fps: false,
+        }
+    }
+
+    pub fn show(&mut self, message: String) {
+        if self.messages.len() == Osd::MAX_MESSAGES { self.messages.pop_front(); }
+        self.messages.push_back((message, Instant::now()));
+    }
+
+    pub fn draw(&mut self, ui: &Ui, top: f32, fps: f64) {
+        self.messages.retain(|(_, shown)| shown.elapsed() < Osd::DURATION);
+        let fps = if self.show_fps { Some(format!("{:.1} FPS", fps)) } else { None };
+        let draw_list = ui.get_background_draw_list();
+        let mut y = top + Osd::PADDING;
+        for line in fps.iter().chain(self.messages.iter().map(|(message, _)| message)) {
+            let text = ImString::new(line.as_str());
+            let [width, height] = ui.calc_text_size(&text, false, -1.0);
+            let (x_end, y_end) = (width + 3.0 * Osd::PADDING, y + height + 2.0 * Osd::PADDING);
+            draw_list.add_rect([Osd::PADDING, y], [x_end, y_end], [0.0, 0.0, 0.0, 0.6]).filled(true).build();
+            draw_list.add_text([2.0 * Osd::PADDING, y + Osd::PADDING], [1.0, 1.0, 1.0, 1.0], &text);
+            y = y_end + Osd::PADDING;
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use nds_core::log::{error, warn};
+use nds_core::nds::NDS;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Slot {
+    Auto,
+    Numbered(usize),
+}
+
+// States are kept next to the ROM as <game>.<slot>.state
+pub struct SaveStates {
+    rom_path: PathBuf,
+    last_autosave: Instant,
+    crashed: bool,
+}
+
+impl SaveStates {
+    pub const NUM_SLOTS: usize = 9;
+    const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+    pub fn new(rom_path: &Path) -> Self {
+        let save_states = SaveStates {
+            rom_path: rom_path.to_path_buf(),
+            last_autosave: Instant::now(),
+            crashed: false,
+        };
+        // The marker is only removed on a clean exit, so finding it means the last session crashed
+        let crashed = save_states.running_path().exists();
+        fs::write(save_states.running_path(), [])
+        .unwrap_or_else(|err| error!("Unable to Create {}: {}!", save_states.running_path().display(), err));
+        SaveStates { crashed, ..save_states }
+    }
+
+    pub fn slots() -> impl Iterator<Item = Slot> {
+        std::iter::once(Slot::Auto).chain((1..=SaveStates::NUM_SLOTS).map(Slot::Numbered))
+    }
+
+    pub fn path(&self, slot: Slot) -> PathBuf {
+        match slot {
+            Slot::Auto => self.rom_path.with_extension("auto.state"),
+            Slot::Numbered(num) => self.rom_path.with_extension(format!("{}.state", num)),
+        }
+    }
+
+    fn running_path(&self) -> PathBuf {
+        self.rom_path.with_extension("running")
+    }
+
+    pub fn modified(&self, slot: Slot) -> Option<SystemTime> {
+        fs::metadata(self.path(slot)).and_then(|metadata| metadata.modified()).ok()
+    }
+
+    pub fn newest(&self) -> Option<Slot> {
+        SaveStates::slots().filter_map(|slot| Some((self.modified(slot)?, slot))).max_by_key(|(time, _)| *time)
+        .map(|(_, slot)| slot)
+    }
+
+    pub fn save(&mut self, nds: &NDS, slot: Slot) -> io::Result<()> {
+        if slot == Slot::Auto { self.last_autosave = Instant::now() }
+        // Written to a temporary file first so a crash mid-write can't clobber the previous state
+        let path = self.path(slot);
+        let temp_path = path.with_extension("tmp");
+        fs::write(&temp_path, nds.save_state()).and_then(|_| fs::rename(&temp_path, &path))
+    }
+
+    fn autosave(&mut self, nds: &NDS) {
+        self.save(nds, Slot::Auto)
+        .unwrap_or_else(|err| error!("Unable to Save State to {}: {}!", self.path(Slot::Auto).display(), err));
+    }
+
+    pub fn load(&self, nds: &mut NDS, slot: Slot) -> io::Result<()> {
+        nds.load_state(&fs::read(self.path(slot))?)
+    }
+
+    pub fn load_newest(&self, nds: &mut NDS) {
+        if let Some(slot) = self.newest() {
+            self.load(nds, slot)
+            .unwrap_or_else(|err| error!("Unable to Load State from {}: {}!", self.path(slot).display(), err));
+        }
+    }
+
+    pub fn recover(&mut self, nds: &mut NDS) {
+        if !self.crashed { return }
+        self.crashed = false;
+        warn!("Previous Session Didn't Exit Cleanly, Loading Newest State");
+        self.load_newest(nds);
+    }
+
+    pub fn update(&mut self, nds: &NDS) {
+        if self.last_autosave.elapsed() >= SaveStates::AUTOSAVE_INTERVAL { self.autosave(nds) }
+    }
+
+    pub fn exit(mut self, nds: &NDS) {
+        // A powered off system would shut down again as soon as its state is loaded
+        if !nds.powered_off() { self.autosave(nds) }
+        fs::remove_file(self.running_path())
+        .unwrap_or_else(|err| error!("Unable to Remove {}: {}!", self.running_path().display(), err));
+    }
+}
@@ -0,0 +1,196 @@
+use std::cell::RefCell;
+use std::fs;
+use std::path::Path;
+
+use mlua::{Function, Lua, Table};
+use nds_core::nds::{self, Cpu, Key, NDS};
+
+use crate::input::key_from_name;
+
+// Scripts only have access to the emulator while their top level or a callback runs, so the bindings
+// can't be stored and called later
+pub struct Script {
+    lua: Lua,
+    overlay: Overlay,
+}
+
+impl Script {
+    const FRAME_CALLBACKS: &'static str = "frame_callbacks";
+
+    pub fn load(path: &Path, nds: &mut NDS) -> mlua::Result<Self> {
+        let source = fs::read_to_string(path).map_err(mlua::Error::external)?;
+        let lua = Lua::new();
+        lua.set_named_registry_value(Script::FRAME_CALLBACKS, lua.create_table()?)?;
+        let mut script = Script {
+            lua,
+            overlay: Overlay::new(),
+        };
+        let Script { lua, overlay } = &mut script;
+        with_bindings(lua, nds, overlay, |lua| lua.load(&source).set_name(path.to_string_lossy()).exec())?;
+        Ok(script)
+    }
+
+    pub fn frame_completed(&mut self, nds: &mut NDS) -> mlua::Result<()> {
+        self.overlay.clear();
+        let Script { lua, overlay } = self;
+        with_bindings(lua, nds, overlay, |lua| {
+            let callbacks: Table = lua.named_registry_value(Script::FRAME_CALLBACKS)?;
+            for callback in callbacks.sequence_values::<Function>() { callback?.call::<_, ()>(())? }
+            Ok(())
+        })
+    }
+
+    pub fn overlay(&self) -> &Overlay {
+        &self.overlay
+    }
+}
+
+fn with_bindings<R>(lua: &Lua, nds: &mut NDS, overlay: &mut Overlay, f: impl FnOnce(&Lua) -> mlua::Result<R>)
+    -> mlua::Result<R> {
+    let nds = &RefCell::new(nds);
+    let overlay = &RefCell::new(overlay);
+    lua.scope(|scope| {
+        let memory = lua.create_table()?;
+        for (len, read_name, write_name) in [(1, "read_u8", "write_u8"), (2, "read_u16", "write_u16"),
+            (4, "read_u32", "write_u32")].iter().copied() {
+            // Values are little endian and reads of unmapped memory return nil
+            memory.set(read_name, scope.create_function(move |_, (addr, cpu): (u32, Option<String>)| {
+                let cpu = parse_cpu(cpu)?;
+                let mut value = 0;
+                for i in 0..len {
+                    match nds.borrow_mut().peek(cpu, addr.wrapping_add(i)) {
+                        Some(byte) => value |= (byte as u32) << (8 * i),
+                        None => return Ok(None),
+                    }
+                }
+                Ok(Some(value))
+            })?)?;
+            memory.set(write_name, scope.create_function(move |_, (addr, value, cpu): (u32, u32, Option<String>)| {
+                let cpu = parse_cpu(cpu)?;
+                for i in 0..len { nds.borrow_mut().poke(cpu, addr.wrapping_add(i), (value >> (8 * i)) as u8); }
+                Ok(())
+            })?)?;
+        }
+        lua.globals().set("memory", memory)?;
+
+        let cpu = lua.create_table()?;
+        cpu.set("reg", scope.create_function(move |_, (reg, cpu): (u32, Option<String>)| {
+            if reg > 16 { return Err(mlua::Error::RuntimeError(format!("Invalid Register {}", reg))) }
+            Ok(nds.borrow().reg(parse_cpu(cpu)?, reg))
+        })?)?;
+        cpu.set("set_reg", scope.create_function(move |_, (reg, value, cpu): (u32, u32, Option<String>)| {
+            if reg >= 15 { return Err(mlua::Error::RuntimeError(format!("Register {} Can't be Written", reg))) }
+            nds.borrow_mut().set_reg(parse_cpu(cpu)?, reg, value);
+            Ok(())
+        })?)?;
+        lua.globals().set("cpu", cpu)?;
+
+        let input = lua.create_table()?;
+        input.set("press", scope.create_function(move |_, key: String| {
+            nds.borrow_mut().press_key(parse_key(&key)?);
+            Ok(())
+        })?)?;
+        input.set("release", scope.create_function(move |_, key: String| {
+            nds.borrow_mut().release_key(parse_key(&key)?);
+            Ok(())
+        })?)?;
+        input.set("touch", scope.create_function(move |_, (x, y): (usize, usize)| {
+            nds.borrow_mut().press_screen(x.min(nds::WIDTH - 1), y.min(nds::HEIGHT - 1));
+            Ok(())
+        })?)?;
+        input.set("release_touch", scope.create_function(move |_, ()| {
+            nds.borrow_mut().release_screen();
+            Ok(())
+        })?)?;
+        lua.globals().set("input", input)?;
+
+        let savestate = lua.create_table()?;
+        savestate.set("save", scope.create_function(move |lua, ()| lua.create_string(nds.borrow().save_state()))?)?;
+        savestate.set("load", scope.create_function(move |_, state: mlua::String| {
+            nds.borrow_mut().load_state(state.as_bytes()).map_err(mlua::Error::external)
+        })?)?;
+        lua.globals().set("savestate", savestate)?;
+
+        // Colors are 0xRRGGBBAA and the overlay is cleared before the frame callbacks run
+        let gui = lua.create_table()?;
+        gui.set("pixel", scope.create_function(move |_, (x, y, color, screen): (i64, i64, u32, Option<String>)| {
+            overlay.borrow_mut().fill(parse_screen(screen)?, x, y, 1, 1, color);
+            Ok(())
+        })?)?;
+        gui.set("rect", scope.create_function(move |_,
+            (x, y, width, height, color, screen): (i64, i64, i64, i64, u32, Option<String>)| {
+            overlay.borrow_mut().fill(parse_screen(screen)?, x, y, width, height, color);
+            Ok(())
+        })?)?;
+        gui.set("clear", scope.create_function(move |_, ()| {
+            overlay.borrow_mut().clear();
+            Ok(())
+        })?)?;
+        lua.globals().set("gui", gui)?;
+
+        let emu = lua.create_table()?;
+        emu.set("frame_count", scope.create_function(move |_, ()| Ok(nds.borrow().frame().count))?)?;
+        emu.set("on_frame", lua.create_function(|lua, callback: Function| {
+            lua.named_registry_value::<Table>(Script::FRAME_CALLBACKS)?.raw_push(callback)
+        })?)?;
+        lua.globals().set("emu", emu)?;
+
+        f(lua)
+    })
+}
+
+fn parse_cpu(cpu: Option<String>) -> mlua::Result<Cpu> {
+    match cpu.as_deref() {
+        None | Some("arm9") => Ok(Cpu::ARM9),
+        Some("arm7") => Ok(Cpu::ARM7),
+        Some(cpu) => Err(mlua::Error::RuntimeError(format!("Unknown CPU {}", cpu))),
+    }
+}
+
+fn parse_screen(screen: Option<String>) -> mlua::Result<usize> {
+    match screen.as_deref() {
+        None | Some("top") => Ok(0),
+        Some("bottom") => Ok(1),
+        Some(screen) => Err(mlua::Error::RuntimeError(format!("Unknown Screen {}", screen))),
+    }
+}
+
+fn parse_key(key: &str) -> mlua::Result<Key> {
+    key_from_name(key).ok_or_else(|| mlua::Error::RuntimeError(format!("Unknown Key {}", key)))
+}
+
+// Drawn over the screens in RGBA8, the same format as the frame
+pub struct Overlay {
+    screens: [Vec<u8>; 2],
+}
+
+impl Overlay {
+    fn new() -> Self {
+        Overlay {
+            screens: [vec![0; 4 * nds::WIDTH * nds::HEIGHT], vec![0; 4 * nds::WIDTH * nds::HEIGHT]],
+        }
+    }
+
+    fn clear(&mut self) {
+        for screen in self.screens.iter_mut() { screen.iter_mut().for_each(|byte| *byte = 0) }
+    }
+
+    fn fill(&mut self, screen: usize, x: i64, y: i64, width: i64, height: i64, color: u32) {
+        for y in y.max(0)..(y + height).min(nds::HEIGHT as i64) {
+            for x in x.max(0)..(x + width).min(nds::WIDTH as i64) {
+                let i = 4 * (y as usize * nds::WIDTH + x as usize);
+                self.screens[screen][i..i + 4].copy_from_slice(&color.to_be_bytes());
+            }
+        }
+    }
+
+    pub fn blend(&self, screen: usize, pixels: &mut [u8]) {
+        for (pixel, overlay_pixel) in pixels.chunks_exact_mut(4).zip(self.screens[screen].chunks_exact(4)) {
+            let alpha = overlay_pixel[3] as u32;
+            if alpha == 0 { continue }
+            for (channel, overlay_channel) in pixel[..3].iter_mut().zip(overlay_pixel[..3].iter()) {
+                *channel = ((*overlay_channel as u32 * alpha + *channel as u32 * (255 - alpha)) / 255) as u8;
+            }
+        }
+    }
+}
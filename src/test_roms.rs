@@ -0,0 +1,191 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use nds_core::fault::Fault;
+use nds_core::nds::{Cpu, NDS};
+use serde::Deserialize;
+
+use crate::input::key_from_name;
+
+// Each test in the manifest boots a ROM, runs it for a number of frames and then checks its results, e.g.
+//
+// [[test]]
+// rom = "armwrestler.nds"
+// frames = 300
+// inputs = [{ frame = 120, key = "start" }]
+// checks = [{ type = "no_color", screen = "top", color = 0xFF0000 }]
+#[derive(Deserialize)]
+struct Manifest {
+    #[serde(default)]
+    test: Vec<TestRom>,
+}
+
+#[derive(Deserialize)]
+struct TestRom {
+    name: Option<String>,
+    // Relative to the manifest
+    rom: PathBuf,
+    frames: u64,
+    #[serde(default)]
+    inputs: Vec<Input>,
+    checks: Vec<Check>,
+}
+
+#[derive(Deserialize)]
+struct Input {
+    frame: u64,
+    key: String,
+    #[serde(default = "Input::default_hold")]
+    hold: u64,
+}
+
+impl Input {
+    fn default_hold() -> u64 { 2 }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Check {
+    // Little endian value of `size` bytes in the CPU's address space
+    Memory {
+        #[serde(default)]
+        cpu: TestCpu,
+        addr: u32,
+        #[serde(default = "Check::default_size")]
+        size: u32,
+        value: u32,
+    },
+    // Colors are 0xRRGGBB
+    Pixel { #[serde(default)] screen: Screen, x: usize, y: usize, color: u32 },
+    Color { #[serde(default)] screen: Screen, color: u32 },
+    NoColor { #[serde(default)] screen: Screen, color: u32 },
+}
+
+impl Check {
+    fn default_size() -> u32 { 4 }
+}
+
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum TestCpu {
+    #[default]
+    Arm9,
+    Arm7,
+}
+
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Screen {
+    #[default]
+    Top,
+    Bottom,
+}
+
+// Returns whether every test passed and at least one ran. With skip_missing, tests whose ROM isn't there are skipped
+// instead of failing.
+pub fn run<F: Fn(&Path) -> Result<NDS, Fault>>(manifest_path: &Path, skip_missing: bool, load_rom: F) -> bool {
+    let manifest: Manifest = match fs::read_to_string(manifest_path).map_err(|err| err.to_string())
+        .and_then(|contents| toml::from_str(&contents).map_err(|err| err.to_string())) {
+        Ok(manifest) => manifest,
+        Err(err) => { println!("Unable to Load {}: {}!", manifest_path.display(), err); return false },
+    };
+    let dir = manifest_path.parent().unwrap_or_else(|| Path::new(""));
+    let (mut passed, mut skipped) = (0, 0);
+    for test in manifest.test.iter() {
+        let name = test.name.clone().unwrap_or_else(|| test.rom.display().to_string());
+        let rom_path = dir.join(&test.rom);
+        if skip_missing && !rom_path.exists() {
+            println!("SKIP {}: {} not found", name, rom_path.display());
+            skipped += 1;
+            continue
+        }
+        match run_test(test, &rom_path, &load_rom) {
+            Ok(()) => { println!("PASS {}", name); passed += 1 },
+            Err(reason) => println!("FAIL {}: {}", name, reason),
+        }
+    }
+    println!("{}/{} Passed, {} Skipped", passed, manifest.test.len() - skipped, skipped);
+    if skipped == manifest.test.len() { println!("No Test ROMs Ran"); return false }
+    passed + skipped == manifest.test.len()
+}
+
+fn run_test<F: Fn(&Path) -> Result<NDS, Fault>>(test: &TestRom, rom_path: &Path, load_rom: &F) -> Result<(), String> {
+    if !rom_path.exists() { return Err(format!("{} not found", rom_path.display())) }
+    let inputs = test.inputs.iter().map(|input| key_from_name(&input.key).map(|key| (input, key))
+        .ok_or_else(|| format!("Unknown Key {}", input.key))).collect::<Result<Vec<_>, _>>()?;
+    let mut nds = load_rom(rom_path).map_err(|fault| fault.to_string())?;
+    for frame in 0..test.frames {
+        if nds.powered_off() { return Err(format!("Powered off after {} frames", frame)) }
+        for (input, key) in inputs.iter() {
+            if frame == input.frame { nds.press_key(*key) }
+            if frame == input.frame + input.hold { nds.release_key(*key) }
+        }
+        nds.run_frame().map_err(|fault| format!("Stopped after {} frames: {}", frame, fault))?;
+    }
+    test.checks.iter().try_for_each(|check| run_check(&mut nds, check))
+}
+
+fn run_check(nds: &mut NDS, check: &Check) -> Result<(), String> {
+    match *check {
+        Check::Memory { cpu, addr, size, value } => {
+            let cpu = match cpu { TestCpu::Arm9 => Cpu::ARM9, TestCpu::Arm7 => Cpu::ARM7 };
+            if size == 0 || size > 4 { return Err(format!("Size {} isn't 1 to 4 bytes", size)) }
+            let mut actual = 0;
+            for i in (0..size).rev() {
+                let byte_addr = addr.checked_add(i).ok_or_else(|| format!("0x{:08X} + {} is out of range", addr, i))?;
+                let byte = nds.peek(cpu, byte_addr).ok_or_else(|| format!("0x{:08X} can't be read", byte_addr))?;
+                actual = actual << 8 | byte as u32;
+            }
+            if actual == value { Ok(()) }
+            else { Err(format!("0x{:08X} is 0x{:X} instead of 0x{:X}", addr, actual, value)) }
+        },
+        Check::Pixel { screen, x, y, color } => {
+            let (width, height) = (nds.frame().width, nds.frame().height);
+            if x >= width || y >= height { return Err(format!("Pixel ({}, {}) is off screen", x, y)) }
+            let actual = pixels(nds, screen).nth(y * width + x).unwrap();
+            if actual == color { Ok(()) }
+            else { Err(format!("Pixel ({}, {}) is 0x{:06X} instead of 0x{:06X}", x, y, actual, color)) }
+        },
+        Check::Color { screen, color } => if pixels(nds, screen).any(|pixel| pixel == color) { Ok(()) }
+            else { Err(format!("No pixels are 0x{:06X}", color)) },
+        Check::NoColor { screen, color } => match pixels(nds, screen).position(|pixel| pixel == color) {
+            Some(i) => Err(format!("Pixel ({}, {}) is 0x{:06X}", i % nds.frame().width, i / nds.frame().width, color)),
+            None => Ok(()),
+        },
+    }
+}
+
+fn pixels(nds: &NDS, screen: Screen) -> impl Iterator<Item = u32> + '_ {
+    let frame = nds.frame();
+    let screen = match screen { Screen::Top => frame.top, Screen::Bottom => frame.bottom };
+    screen.chunks_exact(4).map(|rgba| (rgba[0] as u32) << 16 | (rgba[1] as u32) << 8 | rgba[2] as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use nds_core::nds::{ConsoleModel, MemoryStorage, NDS, SystemFiles};
+    use nds_core::rom;
+
+    use crate::audio::Muted;
+
+    // ROMs are direct booted, with zeroed BIOSes unless dumps are next to the manifest
+    #[test]
+    fn test_roms() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/roms");
+        let bios = |name: &str, len: usize| fs::read(dir.join(name)).unwrap_or_else(|_| vec![0; len]);
+        assert!(super::run(&dir.join("manifest.toml"), true, |rom_path| {
+            let files = SystemFiles {
+                bios7: bios("bios7.bin", 0x4000),
+                bios9: bios("bios9.bin", 0x1000),
+                firmware: fs::read(dir.join("firmware.bin")).ok(),
+            };
+            let mut nds = NDS::new(files, rom::read_patched_rom(rom_path, "nds", None, None).unwrap(),
+                Box::new(MemoryStorage::new(None)), Box::new(Muted), true, ConsoleModel::DS)?;
+            nds.set_deterministic(true);
+            Ok(nds)
+        }));
+    }
+}
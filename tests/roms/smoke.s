@@ -0,0 +1,156 @@
+@ Small test ROM that cargo test always runs, so the suite checks something without any downloaded ROMs.
+@ The ARM9 runs a few CPU and divider checks, stores how many passed and failed at 0x02100000 and 0x02100004,
+@ then shows a green backdrop if they all passed or a red one otherwise.
+@ Only PC-relative addressing is used so it can be built without a linker:
+@   llvm-mc --triple=armv5te-none-eabi -filetype=obj smoke.s -o smoke.o
+@   llvm-objcopy -O binary --only-section=.text smoke.o smoke.nds
+    .arm
+    .text
+    .global _start
+_start:
+    .ascii "SMOKE TEST\0\0"     @ Title
+    .ascii "SMKE"               @ Game code
+    .ascii "00"                 @ Maker code
+    .byte 0                     @ DS only
+    .space 0x20 - 0x13
+    .word arm9_start - _start   @ ARM9 ROM offset
+    .word 0x02000000            @ ARM9 entry point
+    .word 0x02000000            @ ARM9 RAM address
+    .word arm9_end - arm9_start
+    .word arm7_start - _start   @ ARM7 ROM offset
+    .word 0x02380000            @ ARM7 entry point
+    .word 0x02380000            @ ARM7 RAM address
+    .word arm7_end - arm7_start
+    .space 0x80 - 0x40
+    .word rom_end - _start      @ Used ROM size
+    .word 0x200                 @ Header size
+    .space 0x200 - 0x88
+
+@ Counts a check as passed if Z is set
+.macro check
+    addeq r4, r4, #1
+    addne r5, r5, #1
+.endm
+
+arm9_start:
+    mov r4, #0
+    mov r5, #0
+
+    @ Carry out of an add
+    mvn r0, #0
+    adds r0, r0, #1
+    adc r0, r0, #0
+    cmp r0, #1
+    check
+
+    mov r0, #123
+    mov r1, #45
+    mul r2, r0, r1
+    ldr r3, =5535
+    cmp r2, r3
+    check
+
+    @ 0xFFFFFFFF * 2 = 0x1_FFFFFFFE
+    mvn r0, #0
+    mov r1, #2
+    umull r2, r3, r0, r1
+    mvn r6, #1
+    cmp r2, r6
+    cmpeq r3, #1
+    check
+
+    mov r0, #0x10000
+    clz r1, r0
+    cmp r1, #15
+    check
+
+    @ Saturates instead of overflowing
+    mvn r0, #0x80000000
+    mov r1, #1
+    qadd r2, r0, r1
+    cmp r2, r0
+    check
+
+    ldr r10, =0x02100100
+    mov r0, #1
+    mov r1, #2
+    mov r2, #3
+    mov r3, #4
+    stmia r10!, {r0-r3}
+    ldmdb r10, {r6-r9}
+    add r6, r6, r7
+    add r6, r6, r8
+    add r6, r6, r9
+    cmp r6, #10
+    check
+
+    ldr r10, =0x02100200
+    mov r0, #7
+    mov r1, #9
+    strd r0, r1, [r10]
+    ldrd r2, r3, [r10]
+    add r2, r2, r3
+    cmp r2, #16
+    check
+
+    @ Switch to THUMB and back
+    adr lr, thumb_done
+    adr r0, thumb_code + 1
+    bx r0
+thumb_done:
+    cmp r0, #20
+    check
+
+    @ 32 bit division, 100 / 7 = 14 remainder 2
+    ldr r10, =0x04000280
+    mov r0, #0
+    str r0, [r10]               @ DIVCNT
+    mov r0, #100
+    str r0, [r10, #0x10]        @ DIV_NUMER
+    mov r0, #7
+    str r0, [r10, #0x18]        @ DIV_DENOM
+    mov r0, #0
+    str r0, [r10, #0x1C]
+wait_div:
+    ldr r0, [r10]
+    tst r0, #0x8000             @ Busy
+    bne wait_div
+    ldr r0, [r10, #0x20]        @ DIV_RESULT
+    ldr r1, [r10, #0x28]        @ DIVREM_RESULT
+    cmp r0, #14
+    cmpeq r1, #2
+    check
+
+    ldr r0, =0x02100000
+    str r4, [r0]
+    str r5, [r0, #4]
+    ldr r0, =0x04000304
+    ldr r1, =0x8003             @ Engine A on the top screen
+    str r1, [r0]                @ POWCNT1
+    ldr r0, =0x04000000
+    mov r1, #0x10000            @ Graphics display mode
+    str r1, [r0]                @ DISPCNT
+    ldr r0, =0x05000000
+    cmp r5, #0
+    ldreq r1, =0x03E0           @ Green
+    movne r1, #0x001F           @ Red
+    strh r1, [r0]               @ Backdrop
+halt9:
+    b halt9
+
+    .thumb
+thumb_code:
+    movs r0, #5
+    lsls r0, r0, #2
+    bx lr
+
+    .arm
+    .ltorg
+arm9_end:
+
+arm7_start:
+    b arm7_start
+arm7_end:
+
+    .balign 0x200
+rom_end: